@@ -0,0 +1,338 @@
+//! Keyframed animations of the joints of a [`Skeleton`](crate::skeleton::Skeleton), as loaded
+//! from glTF.
+
+use std::ops::{Add, Mul};
+
+use glam::{Quat, Vec3};
+
+use crate::skeleton::Pose;
+
+/// How values are found between two keyframes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Interpolation {
+    /// The earlier keyframe's value, until the next.
+    Step,
+    /// Straight from one value to the next, or along the shortest arc for rotations.
+    #[default]
+    Linear,
+    /// A cubic Hermite spline, with an in and an out tangent around each value.
+    CubicSpline,
+}
+
+/// The values a channel animates, one per keyframe, or three for cubic splines: the in tangent,
+/// the value and the out tangent.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Keyframes {
+    Translation(Vec<Vec3>),
+    Rotation(Vec<Quat>),
+    Scale(Vec<Vec3>),
+    /// The weight of every morph target of the mesh, one after the other, for each of the
+    /// values above.
+    Weights(Vec<f32>),
+}
+
+/// One property of one joint over time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Channel {
+    /// An index into the joints of the skeleton, unused for morph target weights.
+    pub joint: usize,
+    /// The time of each keyframe in seconds, increasing.
+    pub times: Vec<f32>,
+    pub keyframes: Keyframes,
+    pub interpolation: Interpolation,
+}
+
+/// A named animation, e.g. a walk cycle, moving the joints of a skeleton.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AnimationClip {
+    pub name: String,
+    /// The seconds until the last keyframe.
+    pub duration: f32,
+    pub channels: Vec<Channel>,
+}
+
+impl AnimationClip {
+    /// Builds a clip lasting until the last keyframe of `channels`.
+    pub fn new(name: impl Into<String>, channels: Vec<Channel>) -> Self {
+        let duration = channels
+            .iter()
+            .filter_map(|channel| channel.times.last().copied())
+            .fold(0.0, f32::max);
+        Self {
+            name: name.into(),
+            duration,
+            channels,
+        }
+    }
+
+    /// Moves the joints and morph target weights the clip animates in `pose` to where they are
+    /// `time` seconds in, holding the first and last keyframes outside of the clip. Other
+    /// joints and weights are left alone.
+    pub fn sample(&self, time: f32, pose: &mut Pose) {
+        for channel in &self.channels {
+            let times = &channel.times;
+            let interpolation = channel.interpolation;
+            if let Keyframes::Weights(values) = &channel.keyframes {
+                let count = pose.weights.len();
+                for (target, weight) in pose.weights.iter_mut().enumerate() {
+                    // Every `count`th value from the target's own is one of its keyframes.
+                    let values: Vec<f32> =
+                        values.iter().skip(target).step_by(count).copied().collect();
+                    if let Some(value) = sample(times, &values, interpolation, time, |a, b, t| {
+                        a + (b - a) * t
+                    }) {
+                        *weight = value;
+                    }
+                }
+                continue;
+            }
+            let Some(joint) = pose.joints.get_mut(channel.joint) else {
+                continue;
+            };
+            match &channel.keyframes {
+                Keyframes::Translation(values) => {
+                    if let Some(value) =
+                        sample(times, values, interpolation, time, |a, b, t| a.lerp(b, t))
+                    {
+                        joint.translation = value;
+                    }
+                }
+                Keyframes::Rotation(values) => {
+                    if let Some(value) =
+                        sample(times, values, interpolation, time, |a, b, t| a.slerp(b, t))
+                    {
+                        joint.rotation = value.normalize();
+                    }
+                }
+                Keyframes::Scale(values) => {
+                    if let Some(value) =
+                        sample(times, values, interpolation, time, |a, b, t| a.lerp(b, t))
+                    {
+                        joint.scale = value;
+                    }
+                }
+                Keyframes::Weights(_) => (),
+            }
+        }
+    }
+}
+
+/// A clip playing in an [`AnimationPlayer`], blended with the other layers by its weight.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AnimationLayer {
+    /// An index into the clips the player samples.
+    pub clip: usize,
+    /// The seconds into the clip.
+    pub time: f32,
+    /// Scales how fast the clip plays, negative to play it backwards.
+    pub speed: f32,
+    /// Whether the clip starts over past its end, or holds its last keyframe.
+    pub looping: bool,
+    /// How much the layer counts relative to the others, from 0 to 1.
+    pub weight: f32,
+    /// The weight the layer fades towards, and how much it changes by per second.
+    target_weight: f32,
+    fade_rate: f32,
+}
+
+impl AnimationLayer {
+    fn new(clip: usize, weight: f32) -> Self {
+        Self {
+            clip,
+            time: 0.0,
+            speed: 1.0,
+            looping: true,
+            weight,
+            target_weight: weight,
+            fade_rate: 0.0,
+        }
+    }
+
+    /// Fades the weight to `weight` over `duration` seconds, or right away without one.
+    pub fn fade_to(&mut self, weight: f32, duration: f32) {
+        self.target_weight = weight.clamp(0.0, 1.0);
+        if duration > 0.0 {
+            self.fade_rate = (self.target_weight - self.weight).abs() / duration;
+        } else {
+            self.weight = self.target_weight;
+        }
+    }
+
+    /// Whether the layer is fading out and will be removed once it has.
+    pub fn is_fading_out(&self) -> bool {
+        self.target_weight == 0.0
+    }
+
+    /// Whether a clip that doesn't loop has played to its end.
+    pub fn is_finished(&self, clip: &AnimationClip) -> bool {
+        !self.looping
+            && if self.speed < 0.0 {
+                self.time <= 0.0
+            } else {
+                self.time >= clip.duration
+            }
+    }
+}
+
+/// Plays clips on a skeleton, blending layers of them by weight and crossfading from one to
+/// the next, e.g. from idle to walking.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnimationPlayer {
+    /// Scales the speed of every layer, to slow down or pause the whole skeleton.
+    pub speed: f32,
+    layers: Vec<AnimationLayer>,
+    /// The pose of a single layer, reused across frames.
+    scratch: Pose,
+}
+
+impl Default for AnimationPlayer {
+    fn default() -> Self {
+        Self {
+            speed: 1.0,
+            layers: Vec::new(),
+            scratch: Pose::default(),
+        }
+    }
+}
+
+impl AnimationPlayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The clips playing, blended in order.
+    pub fn layers(&self) -> &[AnimationLayer] {
+        &self.layers
+    }
+
+    /// The layer playing `clip`, to change its speed, looping or time.
+    pub fn layer_mut(&mut self, clip: usize) -> Option<&mut AnimationLayer> {
+        self.layers.iter_mut().find(|layer| layer.clip == clip)
+    }
+
+    /// Plays `clip` alone from its start.
+    pub fn play(&mut self, clip: usize) -> &mut AnimationLayer {
+        self.layers.clear();
+        self.layers.push(AnimationLayer::new(clip, 1.0));
+        &mut self.layers[0]
+    }
+
+    /// Fades `clip` in over `duration` seconds while every other layer fades out, continuing
+    /// `clip` where it is if it's already playing.
+    pub fn crossfade(&mut self, clip: usize, duration: f32) -> &mut AnimationLayer {
+        for layer in &mut self.layers {
+            layer.fade_to(0.0, duration);
+        }
+        self.blend(clip, 1.0, duration)
+    }
+
+    /// Fades the layer playing `clip` to `weight` over `duration` seconds, adding it if it isn't
+    /// playing yet, and leaves the other layers as they are.
+    pub fn blend(&mut self, clip: usize, weight: f32, duration: f32) -> &mut AnimationLayer {
+        let index = match self.layers.iter().position(|layer| layer.clip == clip) {
+            Some(index) => index,
+            None => {
+                self.layers.push(AnimationLayer::new(clip, 0.0));
+                self.layers.len() - 1
+            }
+        };
+        let layer = &mut self.layers[index];
+        layer.fade_to(weight, duration);
+        layer
+    }
+
+    /// Stops every clip, leaving the skeleton in its rest pose.
+    pub fn stop(&mut self) {
+        self.layers.clear();
+    }
+
+    /// Advances every layer and its fade by `delta_time` seconds, dropping the layers that have
+    /// faded out and those playing clips missing from `clips`.
+    pub fn update(&mut self, delta_time: f32, clips: &[AnimationClip]) {
+        let delta_time = delta_time * self.speed;
+        self.layers.retain_mut(|layer| {
+            let Some(clip) = clips.get(layer.clip) else {
+                return false;
+            };
+            layer.time += delta_time * layer.speed;
+            layer.time = if layer.looping && clip.duration > 0.0 {
+                layer.time.rem_euclid(clip.duration)
+            } else {
+                layer.time.clamp(0.0, clip.duration)
+            };
+            let step = layer.fade_rate * delta_time.abs();
+            layer.weight = if layer.weight < layer.target_weight {
+                (layer.weight + step).min(layer.target_weight)
+            } else {
+                (layer.weight - step).max(layer.target_weight)
+            };
+            !(layer.is_fading_out() && layer.weight <= 0.0)
+        });
+    }
+
+    /// Replaces `pose` with the weighted blend of every layer, each sampled on top of `rest`,
+    /// or with `rest` when nothing plays.
+    pub fn sample(&mut self, clips: &[AnimationClip], rest: &Pose, pose: &mut Pose) {
+        pose.clone_from(rest);
+        let mut total = 0.0;
+        for layer in &self.layers {
+            let Some(clip) = clips.get(layer.clip).filter(|_| layer.weight > 0.0) else {
+                continue;
+            };
+            self.scratch.clone_from(rest);
+            clip.sample(layer.time, &mut self.scratch);
+            // Blending each layer by its share of the weights so far averages them all.
+            total += layer.weight;
+            pose.blend(&self.scratch, layer.weight / total);
+        }
+    }
+}
+
+/// The value of the keyframes `values` at `times` at `time`, interpolated linearly with
+/// `lerp`, or `None` without keyframes.
+fn sample<T: Copy + Add<Output = T> + Mul<f32, Output = T>>(
+    times: &[f32],
+    values: &[T],
+    interpolation: Interpolation,
+    time: f32,
+    lerp: impl Fn(T, T, f32) -> T,
+) -> Option<T> {
+    let stride = if interpolation == Interpolation::CubicSpline {
+        3
+    } else {
+        1
+    };
+    let count = times.len().min(values.len() / stride);
+    if count == 0 {
+        return None;
+    }
+    // The value of keyframe `key`, the middle of the three values of cubic splines.
+    let value = |key: usize| values[key * stride + stride / 2];
+    let next = times[..count].partition_point(|&key_time| key_time <= time);
+    if next == 0 {
+        return Some(value(0));
+    }
+    if next == count {
+        return Some(value(count - 1));
+    }
+    let previous = next - 1;
+    let span = times[next] - times[previous];
+    let t = if span > 0.0 {
+        (time - times[previous]) / span
+    } else {
+        0.0
+    };
+    Some(match interpolation {
+        Interpolation::Step => value(previous),
+        Interpolation::Linear => lerp(value(previous), value(next), t),
+        Interpolation::CubicSpline => {
+            let out_tangent = values[previous * 3 + 2];
+            let in_tangent = values[next * 3];
+            let (t2, t3) = (t * t, t * t * t);
+            value(previous) * (2.0 * t3 - 3.0 * t2 + 1.0)
+                + out_tangent * ((t3 - 2.0 * t2 + t) * span)
+                + value(next) * (-2.0 * t3 + 3.0 * t2)
+                + in_tangent * ((t3 - t2) * span)
+        }
+    })
+}
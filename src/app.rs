@@ -0,0 +1,758 @@
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
+use std::sync::Arc;
+
+#[cfg(not(target_arch = "wasm32"))]
+use pollster::FutureExt;
+use web_time::Instant;
+use winit::application::ApplicationHandler;
+use winit::event::WindowEvent;
+use winit::event_loop::{EventLoop, EventLoopProxy};
+
+use crate::assets;
+use crate::camera::{Camera, CameraController, OrbitController};
+use crate::capture;
+use crate::config::{Config, WindowConfig};
+use crate::debug_ui::DebugUi;
+use crate::fullscreen::{Fullscreen, WindowMode};
+use crate::gamepad::Gamepads;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::hot_reload::FileWatcher;
+use crate::input::{ActionMap, Input};
+use crate::instance::Instance;
+use crate::light::Light;
+use crate::math::Aabb;
+use crate::mesh::MeshData;
+use crate::recorder::Recorder;
+use crate::renderer::{Renderer, RendererOptions};
+use crate::scene::{Entity, MeshRenderer, Parent, Scene, Transform};
+use crate::skybox::CubeMapData;
+use crate::stats::FrameStats;
+use crate::timestep::FixedTimestep;
+use crate::tonemap::Tonemap;
+
+/// The framerate videos are recorded at.
+const RECORDING_FPS: u32 = 60;
+
+/// How far the cursor can move between pressing and releasing the select button for it to count
+/// as a click, in physical pixels.
+const CLICK_DISTANCE: f64 = 4.0;
+
+/// The number of fixed updates per second.
+const UPDATE_RATE: u32 = 120;
+
+struct Application<'a> {
+    window: Arc<winit::window::Window>,
+    renderer: Renderer<'a>,
+    scene: Scene,
+    /// The entity of each instance drawn, which picks are resolved to.
+    instance_entities: Vec<Entity>,
+    /// Where the select button went down.
+    select_start: Option<(f64, f64)>,
+    controller: CameraController,
+    debug_ui: DebugUi,
+    stats: FrameStats,
+    recorder: Option<Recorder>,
+    screenshot_requested: bool,
+    fullscreen: Fullscreen,
+    input: Input,
+    /// Missing on platforms without gamepad support.
+    gamepads: Option<Gamepads>,
+    timestep: FixedTimestep,
+    /// The camera as of the latest update, and the one before it. The renderer's camera is
+    /// interpolated between the two.
+    camera: Camera,
+    previous_camera: Camera,
+    /// The configuration currently applied.
+    config: Config,
+    #[cfg(not(target_arch = "wasm32"))]
+    config_watcher: Option<(PathBuf, FileWatcher)>,
+}
+
+impl<'a> Application<'a> {
+    /// Runs the updates due since the previous call and schedules a redraw.
+    fn tick(&mut self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        self.reload_config();
+
+        if let Some(gamepads) = &mut self.gamepads {
+            gamepads.poll(&mut self.input);
+        }
+        self.handle_actions();
+        self.handle_selection();
+        self.controller.process_input(&self.input);
+        self.input.end_frame();
+
+        let dt = self.timestep.step().as_secs_f32();
+        for _ in 0..self.timestep.advance() {
+            self.update(dt);
+        }
+        self.window.request_redraw();
+    }
+
+    /// Advances everything that depends on time by one fixed step of `dt` seconds.
+    fn update(&mut self, dt: f32) {
+        self.previous_camera.clone_from(&self.camera);
+        self.controller.update_camera(&mut self.camera, dt);
+    }
+
+    fn set_cursor_grab(&mut self, grab: bool) {
+        use winit::window::CursorGrabMode;
+
+        let result = if grab {
+            self.window
+                .set_cursor_grab(CursorGrabMode::Locked)
+                .or_else(|_| self.window.set_cursor_grab(CursorGrabMode::Confined))
+        } else {
+            self.window.set_cursor_grab(CursorGrabMode::None)
+        };
+        if let Err(err) = result {
+            eprintln!("failed to change cursor grab: {err}");
+        }
+        self.window.set_cursor_visible(!grab);
+        if let CameraController::Fly(controller) = &mut self.controller {
+            controller.mouse_look = grab;
+        }
+    }
+
+    /// Applies the settings of `config` that differ from the current configuration, so that
+    /// changes made at runtime, e.g. through the debug UI, are kept when others are reloaded.
+    fn apply_config(&mut self, config: Config) {
+        let (old, new) = (&self.config.window, &config.window);
+        if new.title != old.title {
+            self.window.set_title(&new.title);
+        }
+        if new.resizable != old.resizable {
+            self.window.set_resizable(new.resizable);
+        }
+        if new.maximized != old.maximized {
+            self.window.set_maximized(new.maximized);
+        }
+        if new.size() != old.size() {
+            if let Some(size) = new.size() {
+                // The surface is resized once the window reports its new size.
+                let _ = self.window.request_inner_size(size);
+            }
+        }
+        if new.monitor != old.monitor {
+            self.fullscreen.set_monitor(&self.window, new.monitor);
+        }
+        if new.mode != old.mode {
+            self.fullscreen.set_mode(&self.window, new.mode);
+        }
+
+        let (old, new) = (&self.config.renderer, &config.renderer);
+        if new.clear_color != old.clear_color {
+            let [r, g, b] = new.clear_color;
+            self.renderer
+                .set_clear_color(wgpu::Color { r, g, b, a: 1.0 });
+        }
+        if new.vsync != old.vsync {
+            self.renderer.set_vsync(new.vsync);
+        }
+        if new.wireframe != old.wireframe {
+            self.renderer.set_wireframe(new.wireframe);
+        }
+        if new.shadows != old.shadows {
+            self.renderer.set_shadows(new.shadows);
+        }
+        if new.skybox != old.skybox {
+            let skybox = match &new.skybox {
+                Some(path) => assets::environment::load(path),
+                None => Ok(CubeMapData::gradient()),
+            };
+            match skybox {
+                Ok(skybox) => self.renderer.set_skybox(&skybox),
+                Err(err) => eprintln!("failed to load the skybox: {err:#}"),
+            }
+        }
+        if new.lut != old.lut {
+            match new.lut.as_ref().map(assets::lut::load).transpose() {
+                Ok(lut) => self.renderer.set_lut(lut.as_ref()),
+                Err(err) => eprintln!("failed to load the LUT: {err:#}"),
+            }
+        }
+        if new.tonemapper != old.tonemapper || new.exposure != old.exposure {
+            if let Some(tonemap) = self.renderer.post_process_mut().effect_mut::<Tonemap>() {
+                tonemap.tonemapper = new.tonemapper;
+                tonemap.exposure = new.exposure;
+            }
+        }
+        if new.msaa != old.msaa {
+            if self.renderer.supported_sample_counts().contains(&new.msaa) {
+                self.renderer.set_sample_count(new.msaa);
+            } else {
+                eprintln!(
+                    "MSAA {}x isn't supported, the supported sample counts are {:?}",
+                    new.msaa,
+                    self.renderer.supported_sample_counts()
+                );
+            }
+        }
+        if new.fxaa != old.fxaa {
+            self.renderer.set_fxaa(new.fxaa);
+        }
+        if new.taa != old.taa {
+            self.renderer.set_taa(new.taa);
+        }
+        if new.ssao != old.ssao {
+            self.renderer.ssao_mut().set_enabled(new.ssao);
+        }
+        if new.fov != old.fov {
+            self.renderer.camera_mut().fovy = new.fov.to_radians();
+        }
+
+        if config.controls != self.config.controls {
+            let mut actions = ActionMap::default();
+            for (action, buttons) in &config.controls {
+                if actions.contains(action) {
+                    actions.bind(action.clone(), buttons.iter().copied());
+                } else {
+                    eprintln!("ignoring controls for unknown action {action:?}");
+                }
+            }
+            self.input.actions = actions;
+        }
+        if let Some(gamepads) = &mut self.gamepads {
+            gamepads.deadzone = config.gamepad.deadzone;
+        }
+
+        self.config = config;
+    }
+
+    /// Reloads the configuration if its file changed.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn reload_config(&mut self) {
+        let Some((path, watcher)) = &self.config_watcher else {
+            return;
+        };
+        if !watcher.changed() {
+            return;
+        }
+        let path = path.clone();
+        match Config::load(&path) {
+            Ok(config) => {
+                println!("reloaded {}", path.display());
+                self.apply_config(config);
+            }
+            Err(err) => eprintln!("{err:#}"),
+        }
+    }
+
+    /// Runs the actions triggered since the previous tick.
+    fn handle_actions(&mut self) {
+        const ACTIONS: [&str; 11] = [
+            "toggle_camera",
+            "toggle_ui",
+            "toggle_stats",
+            "record",
+            "screenshot",
+            "toggle_wireframe",
+            "cycle_msaa",
+            "grab_cursor",
+            "cycle_fullscreen",
+            "cycle_present_mode",
+            "next_monitor",
+        ];
+        for action in ACTIONS {
+            if self.input.action_just_pressed(action) {
+                self.run_action(action);
+            }
+        }
+    }
+
+    /// Picks the instance under the cursor when the select button is clicked, and selects its
+    /// entity for the gizmo once the pick has been read back.
+    fn handle_selection(&mut self) {
+        if self.input.action_just_pressed("select") {
+            self.select_start = self.input.cursor_position();
+        }
+        if self.input.action_released("select") {
+            if let (Some(start), Some(end)) =
+                (self.select_start.take(), self.input.cursor_position())
+            {
+                if (end.0 - start.0).hypot(end.1 - start.1) <= CLICK_DISTANCE {
+                    self.renderer.request_pick((end.0 as u32, end.1 as u32));
+                }
+            }
+        }
+        if let Some(pick) = self.renderer.take_pick() {
+            let entity = pick
+                .instance
+                .and_then(|instance| self.instance_entities.get(instance as usize))
+                .copied();
+            self.debug_ui.gizmo_mut().select(entity);
+        }
+    }
+
+    fn run_action(&mut self, action: &str) {
+        match action {
+            "toggle_camera" => {
+                self.set_cursor_grab(false);
+                self.controller.toggle(&self.camera);
+            }
+            "toggle_ui" => {
+                let visible = !self.debug_ui.visible();
+                self.debug_ui.set_visible(visible);
+            }
+            "toggle_stats" => {
+                let stats_visible = !self.debug_ui.stats_visible();
+                self.debug_ui.set_stats_visible(stats_visible);
+            }
+            "record" => self.toggle_recording(),
+            "screenshot" => {
+                self.screenshot_requested = true;
+                self.renderer.request_capture();
+            }
+            "toggle_wireframe" => {
+                let wireframe = !self.renderer.wireframe();
+                self.renderer.set_wireframe(wireframe);
+            }
+            "cycle_msaa" => {
+                let counts = self.renderer.supported_sample_counts();
+                let next = counts
+                    .iter()
+                    .copied()
+                    .find(|&count| count > self.renderer.sample_count())
+                    .unwrap_or(1);
+                self.renderer.set_sample_count(next);
+                println!("MSAA: {next}x");
+            }
+            "grab_cursor" => {
+                if let CameraController::Fly(controller) = &self.controller {
+                    let grab = !controller.mouse_look;
+                    self.set_cursor_grab(grab);
+                }
+            }
+            "cycle_fullscreen" => {
+                self.fullscreen.cycle(&self.window);
+                println!("window mode: {:?}", self.fullscreen.mode());
+            }
+            "cycle_present_mode" => {
+                let modes = self.renderer.present_modes();
+                let Some(&first) = modes.first() else {
+                    return;
+                };
+                let next = modes
+                    .iter()
+                    .position(|&mode| mode == self.renderer.present_mode())
+                    .and_then(|index| modes.get(index + 1))
+                    .copied()
+                    .unwrap_or(first);
+                self.renderer.set_present_mode(next);
+                println!("present mode: {next:?}");
+            }
+            "next_monitor" => {
+                self.fullscreen.next_monitor(&self.window);
+                if let Some(monitor) = self.fullscreen.monitor() {
+                    println!("monitor: {monitor}");
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn toggle_recording(&mut self) {
+        match self.recorder.take() {
+            Some(recorder) => match recorder.finish() {
+                Ok(path) => println!("saved recording to {}", path.display()),
+                Err(err) => eprintln!("failed to finish recording: {err:#}"),
+            },
+            None => {
+                let config = self.renderer.surface_config();
+                let result = capture::timestamped_path("recording", "mp4").and_then(|path| {
+                    Recorder::start(path, config.width, config.height, RECORDING_FPS)
+                });
+                match result {
+                    Ok(recorder) => {
+                        println!("recording to {}", recorder.path().display());
+                        self.recorder = Some(recorder);
+                    }
+                    Err(err) => eprintln!("failed to start recording: {err:#}"),
+                }
+            }
+        }
+        self.debug_ui.set_recording(self.recorder.is_some());
+    }
+
+    /// Hands a captured frame to the recording and to the requested screenshot.
+    fn handle_capture(&mut self, capture: anyhow::Result<image::RgbaImage>) {
+        let screenshot_requested = std::mem::take(&mut self.screenshot_requested);
+        let image = match capture {
+            Ok(image) => image,
+            Err(err) => {
+                eprintln!("failed to capture frame: {err:#}");
+                return;
+            }
+        };
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(err) = recorder.push_frame(&image) {
+                eprintln!("stopping recording: {err:#}");
+                self.toggle_recording();
+            }
+        }
+        if screenshot_requested {
+            save_screenshot(image);
+        }
+    }
+
+    fn redraw(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        // Redrawing resumes once the surface is recreated.
+        if self.renderer.is_suspended() {
+            return;
+        }
+        let frame_start = Instant::now();
+        self.interpolate_camera();
+        self.instance_entities = self.scene.update(&mut self.renderer).entities;
+        if self.recorder.as_ref().is_some_and(Recorder::wants_frame) {
+            self.renderer.request_capture();
+        }
+        self.debug_ui.run(
+            &self.window,
+            &mut self.renderer,
+            &mut self.scene,
+            &self.stats,
+        );
+        let debug_ui = &mut self.debug_ui;
+        match self.renderer.render_with(|context| debug_ui.paint(context)) {
+            Ok(()) => {
+                // Waiting for the swapchain is neither CPU nor GPU work.
+                let cpu_time = frame_start
+                    .elapsed()
+                    .saturating_sub(self.renderer.acquire_time());
+                self.stats.record(
+                    cpu_time,
+                    self.renderer.gpu_time(),
+                    self.renderer.pass_times(),
+                );
+                if let Some(capture) = self.renderer.take_capture() {
+                    self.handle_capture(capture);
+                }
+            }
+            // The swapchain no longer matches the window, rebuild it and try again
+            // on the next frame.
+            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                self.renderer.reconfigure()
+            }
+            Err(wgpu::SurfaceError::Timeout) => eprintln!("surface timeout, skipping frame"),
+            Err(wgpu::SurfaceError::OutOfMemory) => {
+                eprintln!("out of GPU memory, exiting");
+                event_loop.exit();
+            }
+        }
+    }
+
+    /// Places the scene's camera between the two latest updates, by how far the time is
+    /// between them. Only the view is interpolated, the projection belongs to the renderer.
+    fn interpolate_camera(&mut self) {
+        let alpha = self.timestep.alpha();
+        let (previous, current) = (&self.previous_camera, &self.camera);
+        let Some(camera) = self.scene.camera_mut() else {
+            return;
+        };
+        camera.eye = previous.eye.lerp(current.eye, alpha);
+        camera.target = previous.target.lerp(current.target, alpha);
+        camera.up = current.up;
+    }
+}
+
+/// Shows `mesh` framed by the camera, or a grid of cubes without one, as the entities of the
+/// returned scene, which has already been submitted to `renderer`.
+pub fn setup_scene(renderer: &mut Renderer, mesh: Option<&MeshData>) -> Scene {
+    let mut scene = Scene::new();
+    let mut camera = renderer.camera().clone();
+    match mesh {
+        Some(mesh) => {
+            renderer.set_mesh(mesh);
+            let Aabb { min, max } = mesh.bounds().aabb;
+            camera.target = (min + max) * 0.5;
+            camera.eye = camera.target + glam::Vec3::new(0.6, 0.6, 1.0) * (max - min).length();
+            scene
+                .world
+                .spawn((Transform::default(), MeshRenderer::default()));
+        }
+        None => {
+            camera.eye = glam::Vec3::new(0.0, 12.0, 24.0);
+            let grid = scene.world.spawn((Transform::default(),));
+            scene
+                .world
+                .spawn_batch(Instance::grid(20, 20, 1.5).into_iter().map(|instance| {
+                    (
+                        Transform::from_matrix(instance.transform),
+                        Parent(grid),
+                        MeshRenderer {
+                            color: instance.color,
+                        },
+                    )
+                }));
+            let point = |position, color| {
+                (
+                    Transform::from_translation(position),
+                    Light::point(glam::Vec3::ZERO, color, 60.0, 12.0).with_shadows(),
+                )
+            };
+            scene.world.spawn_batch([
+                point(glam::Vec3::new(-8.0, 2.0, -8.0), [1.0, 0.3, 0.2]),
+                point(glam::Vec3::new(8.0, 2.0, -8.0), [0.2, 1.0, 0.3]),
+                point(glam::Vec3::new(0.0, 2.0, 8.0), [0.3, 0.4, 1.0]),
+            ]);
+            scene.world.spawn((
+                Transform::from_translation(glam::Vec3::new(0.0, 10.0, 0.0)),
+                Light::spot(
+                    glam::Vec3::ZERO,
+                    glam::Vec3::NEG_Y,
+                    [1.0, 0.9, 0.7],
+                    250.0,
+                    20.0,
+                    25.0_f32.to_radians(),
+                )
+                .with_shadows(),
+            ));
+        }
+    }
+    scene.world.spawn((Transform::default(), camera));
+    scene.update(renderer);
+    scene
+}
+
+/// Writes a captured frame to a timestamped PNG next to the executable, encoding it on a
+/// separate thread.
+fn save_screenshot(image: image::RgbaImage) {
+    let path = match capture::timestamped_path("screenshot", "png") {
+        Ok(path) => path,
+        Err(err) => {
+            eprintln!("failed to take screenshot: {err:#}");
+            return;
+        }
+    };
+    std::thread::spawn(move || match image.save(&path) {
+        Ok(()) => println!("saved screenshot to {}", path.display()),
+        Err(err) => eprintln!("failed to save screenshot to {}: {err}", path.display()),
+    });
+}
+
+/// Sent to the event loop once the renderer for `window` has been created, which happens
+/// asynchronously in the browser.
+pub struct RendererReady {
+    window: Arc<winit::window::Window>,
+    renderer: anyhow::Result<Renderer<'static>>,
+}
+
+/// The winit [`ApplicationHandler`] driving the demo: it creates the window on resume and
+/// forwards window events to the [`Renderer`].
+pub struct State<'a> {
+    app: Option<Application<'a>>,
+    mesh: Option<MeshData>,
+    options: RendererOptions,
+    config: Config,
+    #[cfg(not(target_arch = "wasm32"))]
+    config_path: Option<PathBuf>,
+    /// The initial inner size of the window, overriding the configuration's.
+    window_size: Option<winit::dpi::PhysicalSize<u32>>,
+    proxy: EventLoopProxy<RendererReady>,
+}
+
+impl<'a> State<'a> {
+    /// Displays `mesh`, framing the camera around it, or a grid of cubes without one.
+    pub fn new(
+        event_loop: &EventLoop<RendererReady>,
+        mesh: Option<MeshData>,
+        options: RendererOptions,
+    ) -> Self {
+        Self {
+            app: None,
+            mesh,
+            options,
+            config: Config::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            config_path: None,
+            window_size: None,
+            proxy: event_loop.create_proxy(),
+        }
+    }
+
+    pub fn with_config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Reloads the configuration whenever the file at `path` changes.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn watch_config(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config_path = Some(path.into());
+        self
+    }
+
+    pub fn with_window_size(mut self, width: u32, height: u32) -> Self {
+        self.window_size = Some(winit::dpi::PhysicalSize::new(width, height));
+        self
+    }
+}
+
+impl<'a> ApplicationHandler<RendererReady> for State<'a> {
+    fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        // Android destroys the native window when the application is suspended, only the surface
+        // has to be recreated when it comes back.
+        if let Some(app) = &mut self.app {
+            if let Err(err) = app.renderer.resume(app.window.clone()) {
+                eprintln!("failed to recreate the surface: {err:#}");
+                event_loop.exit();
+            }
+            app.window.request_redraw();
+            return;
+        }
+
+        let window_config = &self.config.window;
+        let mut attributes = winit::window::Window::default_attributes()
+            .with_title(&window_config.title)
+            .with_resizable(window_config.resizable)
+            .with_maximized(window_config.maximized);
+        if let Some(size) = self.window_size.or(window_config.size()) {
+            attributes = attributes.with_inner_size(size);
+        }
+        #[cfg(target_arch = "wasm32")]
+        let attributes = {
+            use winit::platform::web::WindowAttributesExtWebSys;
+
+            // Adds the canvas to the page, it is sized by the page's CSS.
+            attributes.with_append(true)
+        };
+        let window = Arc::new(event_loop.create_window(attributes).unwrap());
+
+        let proxy = self.proxy.clone();
+        let options = self.options.clone();
+        let create_renderer = async move {
+            let renderer = Renderer::new(window.clone(), &options).await;
+            // The event loop outlives the application.
+            let _ = proxy.send_event(RendererReady { window, renderer });
+        };
+        #[cfg(target_arch = "wasm32")]
+        wasm_bindgen_futures::spawn_local(create_renderer);
+        #[cfg(not(target_arch = "wasm32"))]
+        create_renderer.block_on();
+    }
+
+    fn suspended(&mut self, _event_loop: &winit::event_loop::ActiveEventLoop) {
+        if let Some(app) = &mut self.app {
+            app.renderer.suspend();
+        }
+    }
+
+    fn user_event(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+        RendererReady { window, renderer }: RendererReady,
+    ) {
+        match renderer {
+            Ok(mut renderer) => {
+                let scene = setup_scene(&mut renderer, self.mesh.as_ref());
+                let controller =
+                    CameraController::Orbit(OrbitController::from_camera(renderer.camera()));
+                let debug_ui = DebugUi::new(&window, &renderer);
+                renderer.debug_draw_mut().pixels_per_point = window.scale_factor() as f32;
+                let camera = renderer.camera().clone();
+                #[cfg(not(target_arch = "wasm32"))]
+                let config_watcher = self.config_path.clone().and_then(|path| {
+                    let watcher = FileWatcher::new(&path)
+                        .inspect_err(|err| eprintln!("configuration reload disabled: {err}"))
+                        .ok()?;
+                    Some((path, watcher))
+                });
+                window.request_redraw();
+                let mut app = Application {
+                    window,
+                    renderer,
+                    scene,
+                    instance_entities: Vec::new(),
+                    select_start: None,
+                    controller,
+                    debug_ui,
+                    stats: FrameStats::default(),
+                    recorder: None,
+                    screenshot_requested: false,
+                    fullscreen: Fullscreen::default(),
+                    input: Input::default(),
+                    gamepads: Gamepads::new(self.config.gamepad.deadzone)
+                        .inspect_err(|err| eprintln!("gamepads disabled: {err:#}"))
+                        .ok(),
+                    timestep: FixedTimestep::new(UPDATE_RATE),
+                    camera: camera.clone(),
+                    previous_camera: camera,
+                    // The window was created with the configured attributes apart from the
+                    // fullscreen ones, which need its monitors, and the renderer still has its
+                    // defaults.
+                    config: Config {
+                        window: WindowConfig {
+                            mode: WindowMode::Windowed,
+                            monitor: None,
+                            ..self.config.window.clone()
+                        },
+                        ..Config::default()
+                    },
+                    #[cfg(not(target_arch = "wasm32"))]
+                    config_watcher,
+                };
+                app.apply_config(self.config.clone());
+                self.app = Some(app);
+            }
+            Err(err) => {
+                eprintln!("failed to initialise renderer: {err:#}");
+                event_loop.exit();
+            }
+        }
+    }
+
+    fn window_event(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+        window_id: winit::window::WindowId,
+        event: WindowEvent,
+    ) {
+        let Some(app) = &mut self.app else {
+            return;
+        };
+        if app.window.id() != window_id {
+            return;
+        }
+        // Input over the overlay is meant for egui, not the camera or the key bindings.
+        if app.debug_ui.on_window_event(&app.window, &event) {
+            return;
+        }
+        app.input.process_window_event(&event);
+        match event {
+            WindowEvent::CloseRequested => event_loop.exit(),
+            WindowEvent::Resized(new_size) => app.renderer.resize(new_size),
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                app.renderer.debug_draw_mut().pixels_per_point = scale_factor as f32;
+            }
+            WindowEvent::CursorMoved { position, .. } => app.renderer.set_cursor_position(position),
+            WindowEvent::Focused(false) => {
+                if let CameraController::Fly(controller) = &mut app.controller {
+                    controller.reset();
+                }
+                app.set_cursor_grab(false);
+            }
+            WindowEvent::RedrawRequested => app.redraw(event_loop),
+            _ => (),
+        }
+    }
+
+    fn about_to_wait(&mut self, _event_loop: &winit::event_loop::ActiveEventLoop) {
+        if let Some(app) = &mut self.app {
+            if !app.renderer.is_suspended() {
+                app.tick();
+            }
+        }
+    }
+
+    fn device_event(
+        &mut self,
+        _event_loop: &winit::event_loop::ActiveEventLoop,
+        _device_id: winit::event::DeviceId,
+        event: winit::event::DeviceEvent,
+    ) {
+        if let Some(app) = &mut self.app {
+            app.input.process_device_event(&event);
+        }
+    }
+}
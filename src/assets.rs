@@ -0,0 +1,38 @@
+//! Loaders turning asset files into [`MeshData`], environment cube maps, color grading LUTs,
+//! MSDF font atlases, sprite sheets, tile maps and vector graphics.
+
+use std::path::Path;
+
+use crate::mesh::MeshData;
+
+pub mod aseprite;
+pub mod environment;
+pub mod gltf;
+pub mod lut;
+pub mod msdf;
+pub mod obj;
+pub mod svg;
+pub mod tiled;
+
+/// Loads a mesh from an `.obj`, `.gltf` or `.glb` file, picking the loader by extension.
+pub fn load_mesh(path: impl AsRef<Path>) -> anyhow::Result<MeshData> {
+    let path = path.as_ref();
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("obj") => obj::load(path),
+        Some("gltf" | "glb") => gltf::load(path),
+        _ => anyhow::bail!("unsupported mesh format: {}", path.display()),
+    }
+}
+
+/// Decodes an sRGB channel from 0 to 1 into linear.
+pub(crate) fn srgb_to_linear(srgb: f32) -> f32 {
+    if srgb <= 0.04045 {
+        srgb / 12.92
+    } else {
+        ((srgb + 0.055) / 1.055).powf(2.4)
+    }
+}
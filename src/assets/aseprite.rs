@@ -0,0 +1,150 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use crate::sprite_animation::{LoopMode, SpriteClip, SpriteFrame, SpriteSheet};
+
+/// The JSON Aseprite exports sprite sheets with, in either its array or hash form.
+#[derive(Deserialize)]
+struct SheetJson {
+    frames: Frames,
+    meta: Meta,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Frames {
+    Array(Vec<Frame>),
+    /// By file name, in the order Aseprite wrote them.
+    Hash(serde_json::Map<String, serde_json::Value>),
+}
+
+#[derive(Deserialize)]
+struct Frame {
+    frame: Rect,
+    /// In milliseconds.
+    duration: f32,
+    #[serde(default)]
+    rotated: bool,
+}
+
+#[derive(Deserialize)]
+struct Rect {
+    x: f32,
+    y: f32,
+    w: f32,
+    h: f32,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Meta {
+    image: Option<String>,
+    size: Size,
+    #[serde(default)]
+    frame_tags: Vec<Tag>,
+}
+
+#[derive(Deserialize)]
+struct Size {
+    w: f32,
+    h: f32,
+}
+
+#[derive(Deserialize)]
+struct Tag {
+    name: String,
+    from: usize,
+    to: usize,
+    #[serde(default)]
+    direction: Direction,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Direction {
+    #[default]
+    Forward,
+    Reverse,
+    Pingpong,
+    PingpongReverse,
+}
+
+/// A sprite sheet exported by Aseprite.
+#[derive(Clone, Debug)]
+pub struct AsepriteSheet {
+    /// The frames, with a clip per tag.
+    pub sheet: SpriteSheet,
+    /// The image the frames are in, relative to the JSON file's directory.
+    pub image: Option<PathBuf>,
+}
+
+/// Loads the JSON data of a sprite sheet exported by Aseprite, with `--data` or "Output File"
+/// in the export dialog. Rotated frames aren't supported.
+pub fn load(path: impl AsRef<Path>) -> anyhow::Result<AsepriteSheet> {
+    let path = path.as_ref();
+    let json = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let mut sheet =
+        parse(&json).with_context(|| format!("invalid sprite sheet in {}", path.display()))?;
+    if let (Some(image), Some(directory)) = (&mut sheet.image, path.parent()) {
+        *image = directory.join(&*image);
+    }
+    Ok(sheet)
+}
+
+/// Parses the JSON data of a sprite sheet exported by Aseprite.
+pub fn parse(json: &str) -> anyhow::Result<AsepriteSheet> {
+    let data: SheetJson = serde_json::from_str(json)?;
+    let frames = match data.frames {
+        Frames::Array(frames) => frames,
+        Frames::Hash(frames) => frames
+            .into_iter()
+            .map(|(name, frame)| {
+                serde_json::from_value(frame).with_context(|| format!("frame {name}"))
+            })
+            .collect::<anyhow::Result<_>>()?,
+    };
+    let (width, height) = (data.meta.size.w, data.meta.size.h);
+    let frames = frames
+        .into_iter()
+        .enumerate()
+        .map(|(index, frame)| {
+            if frame.rotated {
+                anyhow::bail!("frame {index} is rotated");
+            }
+            let Rect { x, y, w, h } = frame.frame;
+            Ok(SpriteFrame {
+                uv: [x / width, y / height, (x + w) / width, (y + h) / height],
+                duration: frame.duration / 1000.0,
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let mut clips = std::collections::HashMap::new();
+    for tag in data.meta.frame_tags {
+        if tag.from > tag.to || tag.to >= frames.len() {
+            anyhow::bail!("tag {} spans missing frames", tag.name);
+        }
+        let (mode, reverse) = match tag.direction {
+            Direction::Forward => (LoopMode::Loop, false),
+            Direction::Reverse => (LoopMode::Loop, true),
+            Direction::Pingpong => (LoopMode::PingPong, false),
+            Direction::PingpongReverse => (LoopMode::PingPong, true),
+        };
+        clips.insert(
+            tag.name,
+            SpriteClip {
+                frames: tag.from..=tag.to,
+                mode,
+                reverse,
+            },
+        );
+    }
+
+    Ok(AsepriteSheet {
+        sheet: SpriteSheet { frames, clips },
+        image: data.meta.image.map(PathBuf::from),
+    })
+}
@@ -0,0 +1,68 @@
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::assets::srgb_to_linear;
+use crate::skybox::CubeMapData;
+
+/// The file names of the faces of a cube map directory, in cube face order.
+const FACE_NAMES: [&str; 6] = ["px", "nx", "py", "ny", "pz", "nz"];
+
+/// Loads an environment cube map from an equirectangular panorama, or from a directory of six
+/// faces named `px`, `nx`, `py`, `ny`, `pz` and `nz`, in any supported image format. HDR images
+/// are kept as they are, others are taken to be sRGB.
+pub fn load(path: impl AsRef<Path>) -> anyhow::Result<CubeMapData> {
+    let path = path.as_ref();
+    if !path.is_dir() {
+        let image = load_linear(path)?;
+        // About a quarter of the panorama's width covers each face at the same resolution.
+        let size = (image.width() / 4).next_power_of_two().clamp(16, 2048);
+        return Ok(CubeMapData::from_equirectangular(&image, size));
+    }
+
+    let faces = FACE_NAMES
+        .iter()
+        .map(|name| {
+            let face = std::fs::read_dir(path)?
+                .filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .find(|face| face.file_stem().is_some_and(|stem| stem == *name))
+                .with_context(|| format!("{} has no {name} face", path.display()))?;
+            load_linear(&face)
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let size = faces[0].width();
+    if let Some(face) = faces
+        .iter()
+        .find(|face| face.width() != size || face.height() != size)
+    {
+        anyhow::bail!(
+            "the faces in {} aren't all {size}x{size}, found {}x{}",
+            path.display(),
+            face.width(),
+            face.height()
+        );
+    }
+    Ok(CubeMapData {
+        size,
+        faces: faces.try_into().expect("six faces"),
+    })
+}
+
+/// Loads an image as linear RGBA, decoding sRGB unless it is already floating point.
+fn load_linear(path: &Path) -> anyhow::Result<image::Rgba32FImage> {
+    let image = image::open(path).with_context(|| format!("failed to load {}", path.display()))?;
+    let hdr = matches!(
+        image,
+        image::DynamicImage::ImageRgb32F(_) | image::DynamicImage::ImageRgba32F(_)
+    );
+    let mut image = image.into_rgba32f();
+    if !hdr {
+        for pixel in image.pixels_mut() {
+            for channel in &mut pixel.0[..3] {
+                *channel = srgb_to_linear(*channel);
+            }
+        }
+    }
+    Ok(image)
+}
@@ -0,0 +1,494 @@
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+
+use ::gltf::animation::Property;
+use anyhow::Context;
+use glam::{Mat3, Mat4, Quat, Vec3};
+
+use crate::animation::{AnimationClip, Channel, Interpolation, Keyframes};
+use crate::material::{AlphaMode, MaterialData, MaterialFactors};
+use crate::mesh::{generate_tangents, MeshData, SubMeshData};
+use crate::morph::{MorphDelta, MorphTargets};
+use crate::skeleton::{Joint, JointTransform, Skeleton};
+use crate::skinning::{SkinVertex, SkinnedMeshData};
+use crate::vertex::Vertex;
+
+/// Loads the default scene of a `.gltf` or `.glb` file, flattened into a single mesh: every
+/// triangle primitive is transformed into world space by its node hierarchy and gets its own
+/// submesh, referencing the file's materials.
+pub fn load(path: impl AsRef<Path>) -> anyhow::Result<MeshData> {
+    let path = path.as_ref();
+    let (document, buffers, images) =
+        ::gltf::import(path).with_context(|| format!("failed to load {}", path.display()))?;
+
+    let scene = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+        .with_context(|| format!("{} contains no scene", path.display()))?;
+
+    let mut mesh = MeshData::default();
+    let mut stack: Vec<_> = scene.nodes().map(|node| (node, Mat4::IDENTITY)).collect();
+    while let Some((node, parent_transform)) = stack.pop() {
+        let transform = parent_transform * Mat4::from_cols_array_2d(&node.transform().matrix());
+        if let Some(node_mesh) = node.mesh() {
+            for primitive in node_mesh.primitives() {
+                if primitive.mode() != ::gltf::mesh::Mode::Triangles {
+                    eprintln!(
+                        "skipping non-triangle primitive in mesh {:?}",
+                        node_mesh.name()
+                    );
+                    continue;
+                }
+                append_primitive(&mut mesh, &primitive, &buffers, transform, node_mesh.name());
+            }
+        }
+        stack.extend(node.children().map(|child| (child, transform)));
+    }
+
+    mesh.materials = load_materials(&document, &images);
+
+    Ok(mesh)
+}
+
+/// Loads the first skinned or morphed mesh of the default scene of a `.gltf` or `.glb` file with
+/// its skeleton, its morph targets and every animation moving the skeleton's joints or the
+/// targets' weights. Unlike [`load`], the vertices are kept in the mesh's own space, which the
+/// skin's inverse bind matrices start from, and other meshes are left out. Meshes without a
+/// skin get an empty skeleton.
+pub fn load_skinned(path: impl AsRef<Path>) -> anyhow::Result<SkinnedMeshData> {
+    let path = path.as_ref();
+    let (document, buffers, images) =
+        ::gltf::import(path).with_context(|| format!("failed to load {}", path.display()))?;
+
+    let scene = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+        .with_context(|| format!("{} contains no scene", path.display()))?;
+    let node_count = document.nodes().len();
+    let mut parents = vec![None; node_count];
+    let mut global_transforms = vec![Mat4::IDENTITY; node_count];
+    let mut stack: Vec<_> = scene.nodes().map(|node| (node, Mat4::IDENTITY)).collect();
+    while let Some((node, parent_transform)) = stack.pop() {
+        let transform = parent_transform * Mat4::from_cols_array_2d(&node.transform().matrix());
+        global_transforms[node.index()] = transform;
+        for child in node.children() {
+            parents[child.index()] = Some(node.index());
+            stack.push((child, transform));
+        }
+    }
+    let morphed = |node: &::gltf::Node| {
+        node.mesh().is_some_and(|mesh| {
+            mesh.primitives()
+                .any(|primitive| primitive.morph_targets().len() > 0)
+        })
+    };
+    let mesh_node = document
+        .nodes()
+        .find(|node| node.mesh().is_some() && node.skin().is_some())
+        .or_else(|| document.nodes().find(morphed))
+        .with_context(|| format!("{} contains no skinned or morphed mesh", path.display()))?;
+    let skin = mesh_node.skin();
+
+    // Each joint's nearest ancestor among the joints, ordered so that parents come first.
+    let joint_nodes: Vec<usize> = skin
+        .iter()
+        .flat_map(|skin| skin.joints())
+        .map(|node| node.index())
+        .collect();
+    let joint_parent = |node: usize| {
+        std::iter::successors(parents[node], |&ancestor| parents[ancestor])
+            .find(|ancestor| joint_nodes.contains(ancestor))
+    };
+    let depth = |node: usize| {
+        std::iter::successors(joint_parent(node), |&ancestor| joint_parent(ancestor)).count()
+    };
+    let mut order: Vec<usize> = (0..joint_nodes.len()).collect();
+    order.sort_by_key(|&joint| depth(joint_nodes[joint]));
+    let mut remap = vec![0; joint_nodes.len()];
+    for (new, &old) in order.iter().enumerate() {
+        remap[old] = new;
+    }
+    let node_joint = |node: usize| {
+        joint_nodes
+            .iter()
+            .position(|&joint| joint == node)
+            .map(|joint| remap[joint])
+    };
+
+    let inverse_binds: Vec<Mat4> = skin
+        .as_ref()
+        .and_then(|skin| {
+            skin.reader(|buffer| Some(&buffers[buffer.index()]))
+                .read_inverse_bind_matrices()
+        })
+        .map(|matrices| {
+            matrices
+                .map(|matrix| Mat4::from_cols_array_2d(&matrix))
+                .collect()
+        })
+        .unwrap_or_default();
+    let nodes: Vec<_> = document.nodes().collect();
+    let skeleton = Skeleton {
+        joints: order
+            .iter()
+            .map(|&old| {
+                let node = &nodes[joint_nodes[old]];
+                let parent = joint_parent(node.index());
+                Joint {
+                    name: node.name().unwrap_or_default().to_owned(),
+                    parent: parent.and_then(node_joint),
+                    rest: JointTransform::from_matrix(Mat4::from_cols_array_2d(
+                        &node.transform().matrix(),
+                    )),
+                    inverse_bind: inverse_binds.get(old).copied().unwrap_or(Mat4::IDENTITY),
+                    base: match (parent, parents[node.index()]) {
+                        (None, Some(ancestor)) => global_transforms[ancestor],
+                        _ => Mat4::IDENTITY,
+                    },
+                }
+            })
+            .collect(),
+    };
+
+    let mut data = SkinnedMeshData {
+        skeleton,
+        ..Default::default()
+    };
+    // Every node sharing the skin is drawn with it, but only the first's targets are loaded.
+    let skinned_nodes = document.nodes().filter(|node| match (&skin, node.skin()) {
+        (Some(skin), Some(other)) => other.index() == skin.index(),
+        _ => node.index() == mesh_node.index(),
+    });
+    let mut targets: Vec<Vec<MorphDelta>> = Vec::new();
+    for node in skinned_nodes {
+        let Some(node_mesh) = node.mesh() else {
+            continue;
+        };
+        for primitive in node_mesh.primitives() {
+            if primitive.mode() != ::gltf::mesh::Mode::Triangles {
+                eprintln!(
+                    "skipping non-triangle primitive in mesh {:?}",
+                    node_mesh.name()
+                );
+                continue;
+            }
+            let first_vertex = data.mesh.vertices.len();
+            // Skinned vertices ignore the transform of their node.
+            append_primitive(
+                &mut data.mesh,
+                &primitive,
+                &buffers,
+                Mat4::IDENTITY,
+                node_mesh.name(),
+            );
+            append_skin(&mut data, &primitive, &buffers, &remap);
+            if node.index() == mesh_node.index() {
+                let vertices = first_vertex..data.mesh.vertices.len();
+                append_morph_targets(&mut targets, vertices, &primitive, &buffers);
+            }
+        }
+    }
+    // Vertices of primitives without a target aren't displaced by it.
+    let vertex_count = data.mesh.vertices.len();
+    for target in &mut targets {
+        target.resize(vertex_count, MorphDelta::default());
+    }
+    let mesh_weights = mesh_node.mesh().and_then(|mesh| mesh.weights());
+    data.morph_targets = MorphTargets {
+        vertex_count,
+        deltas: targets.concat(),
+        weights: mesh_node
+            .weights()
+            .or(mesh_weights)
+            .unwrap_or_default()
+            .to_vec(),
+    };
+    data.mesh.materials = load_materials(&document, &images);
+    data.animations = document
+        .animations()
+        .map(|animation| {
+            let channels = animation
+                .channels()
+                .filter_map(|channel| {
+                    let target = channel.target();
+                    let joint = match target.property() {
+                        Property::MorphTargetWeights => {
+                            (target.node().index() == mesh_node.index()).then_some(0)?
+                        }
+                        _ => node_joint(target.node().index())?,
+                    };
+                    load_channel(&channel, &buffers, joint)
+                })
+                .collect();
+            AnimationClip::new(animation.name().unwrap_or_default(), channels)
+        })
+        .filter(|clip| !clip.channels.is_empty())
+        .collect();
+
+    Ok(data)
+}
+
+/// Appends the joints and weights of the vertices `primitive` appended to `data.mesh`, with
+/// the joints remapped into the order of the skeleton. Vertices without a skin follow no joint.
+fn append_skin(
+    data: &mut SkinnedMeshData,
+    primitive: &::gltf::Primitive,
+    buffers: &[::gltf::buffer::Data],
+    remap: &[usize],
+) {
+    let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+    let count = data.mesh.vertices.len() - data.skin.len();
+    let mut joints = reader.read_joints(0).map(|joints| joints.into_u16());
+    let mut weights = reader.read_weights(0).map(|weights| weights.into_f32());
+    for _ in 0..count {
+        let joints = joints.as_mut().and_then(Iterator::next).unwrap_or([0; 4]);
+        let weights = weights
+            .as_mut()
+            .and_then(Iterator::next)
+            .unwrap_or([0.0; 4]);
+        data.skin.push(SkinVertex {
+            joints: joints.map(|joint| remap.get(joint as usize).copied().unwrap_or(0) as u32),
+            weights,
+        });
+    }
+}
+
+/// Adds the displacements of the `vertices` of a mesh appended from `primitive` to `targets`,
+/// one list per morph target, padding any vertices before them with zeroes.
+fn append_morph_targets(
+    targets: &mut Vec<Vec<MorphDelta>>,
+    vertices: Range<usize>,
+    primitive: &::gltf::Primitive,
+    buffers: &[::gltf::buffer::Data],
+) {
+    let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+    for (index, (positions, normals, _)) in reader.read_morph_targets().enumerate() {
+        if targets.len() <= index {
+            targets.push(Vec::new());
+        }
+        let target = &mut targets[index];
+        target.resize(vertices.start, MorphDelta::default());
+        let mut positions = positions.into_iter().flatten();
+        let mut normals = normals.into_iter().flatten();
+        target.extend(vertices.clone().map(|_| {
+            MorphDelta::new(
+                positions.next().unwrap_or_default(),
+                normals.next().unwrap_or_default(),
+            )
+        }));
+    }
+}
+
+/// The keyframes of an animation channel moving `joint`, or the weights of the mesh's morph
+/// targets.
+fn load_channel(
+    channel: &::gltf::animation::Channel,
+    buffers: &[::gltf::buffer::Data],
+    joint: usize,
+) -> Option<Channel> {
+    use ::gltf::animation::util::ReadOutputs;
+
+    let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
+    let times = reader.read_inputs()?.collect();
+    let keyframes = match reader.read_outputs()? {
+        ReadOutputs::Translations(values) => {
+            Keyframes::Translation(values.map(Vec3::from).collect())
+        }
+        ReadOutputs::Rotations(values) => {
+            Keyframes::Rotation(values.into_f32().map(Quat::from_array).collect())
+        }
+        ReadOutputs::Scales(values) => Keyframes::Scale(values.map(Vec3::from).collect()),
+        ReadOutputs::MorphTargetWeights(values) => Keyframes::Weights(values.into_f32().collect()),
+    };
+    Some(Channel {
+        joint,
+        times,
+        keyframes,
+        interpolation: match channel.sampler().interpolation() {
+            ::gltf::animation::Interpolation::Step => Interpolation::Step,
+            ::gltf::animation::Interpolation::Linear => Interpolation::Linear,
+            ::gltf::animation::Interpolation::CubicSpline => Interpolation::CubicSpline,
+        },
+    })
+}
+
+/// The file's materials, in the order primitives reference them by.
+fn load_materials(
+    document: &::gltf::Document,
+    images: &[::gltf::image::Data],
+) -> Vec<MaterialData> {
+    // Images are converted once, however many materials use them.
+    let mut rgba_images: Vec<Option<Option<Arc<image::RgbaImage>>>> = vec![None; images.len()];
+    let mut texture_image = |texture: ::gltf::Texture, tex_coord: u32| {
+        if tex_coord != 0 {
+            eprintln!("texture {} uses an unsupported UV set", texture.index());
+            return None;
+        }
+        let index = texture.source().index();
+        rgba_images[index]
+            .get_or_insert_with(|| {
+                let rgba = to_rgba8(&images[index]);
+                if rgba.is_none() {
+                    eprintln!(
+                        "image {index} has an unsupported format {:?}",
+                        images[index].format
+                    );
+                }
+                rgba.map(Arc::new)
+            })
+            .clone()
+    };
+    document
+        .materials()
+        .map(|material| {
+            let pbr = material.pbr_metallic_roughness();
+            MaterialData {
+                name: material.name().map(str::to_owned),
+                factors: MaterialFactors {
+                    base_color: pbr.base_color_factor(),
+                    metallic: pbr.metallic_factor(),
+                    roughness: pbr.roughness_factor(),
+                    emissive: material.emissive_factor(),
+                    normal_scale: material.normal_texture().map_or(1.0, |info| info.scale()),
+                    occlusion_strength: material
+                        .occlusion_texture()
+                        .map_or(1.0, |info| info.strength()),
+                },
+                base_color_texture: pbr
+                    .base_color_texture()
+                    .and_then(|info| texture_image(info.texture(), info.tex_coord())),
+                metallic_roughness_texture: pbr
+                    .metallic_roughness_texture()
+                    .and_then(|info| texture_image(info.texture(), info.tex_coord())),
+                normal_texture: material
+                    .normal_texture()
+                    .and_then(|info| texture_image(info.texture(), info.tex_coord())),
+                occlusion_texture: material
+                    .occlusion_texture()
+                    .and_then(|info| texture_image(info.texture(), info.tex_coord())),
+                emissive_texture: material
+                    .emissive_texture()
+                    .and_then(|info| texture_image(info.texture(), info.tex_coord())),
+                double_sided: material.double_sided(),
+                // Masked materials are drawn opaque, without alpha testing.
+                alpha_mode: match material.alpha_mode() {
+                    ::gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+                    _ => AlphaMode::Opaque,
+                },
+            }
+        })
+        .collect()
+}
+
+/// Expands a decoded glTF image to 8-bit RGBA, keeping the high byte of 16-bit channels.
+fn to_rgba8(data: &::gltf::image::Data) -> Option<image::RgbaImage> {
+    use ::gltf::image::Format;
+
+    let (channels, bytes_per_channel) = match data.format {
+        Format::R8 => (1, 1),
+        Format::R8G8 => (2, 1),
+        Format::R8G8B8 => (3, 1),
+        Format::R8G8B8A8 => (4, 1),
+        Format::R16 => (1, 2),
+        Format::R16G16 => (2, 2),
+        Format::R16G16B16 => (3, 2),
+        Format::R16G16B16A16 => (4, 2),
+        _ => return None,
+    };
+    let pixels = data
+        .pixels
+        .chunks_exact(channels * bytes_per_channel)
+        .flat_map(|pixel| {
+            // Little-endian, so the high byte comes last.
+            let channel = |index: usize| pixel[index * bytes_per_channel + bytes_per_channel - 1];
+            match channels {
+                // Single-channel images are grayscale.
+                1 => [channel(0), channel(0), channel(0), 255],
+                2 => [channel(0), channel(1), 0, 255],
+                3 => [channel(0), channel(1), channel(2), 255],
+                _ => [channel(0), channel(1), channel(2), channel(3)],
+            }
+        })
+        .collect();
+    image::RgbaImage::from_raw(data.width, data.height, pixels)
+}
+
+fn append_primitive(
+    mesh: &mut MeshData,
+    primitive: &::gltf::Primitive,
+    buffers: &[::gltf::buffer::Data],
+    transform: Mat4,
+    name: Option<&str>,
+) {
+    let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+    let Some(positions) = reader.read_positions() else {
+        return;
+    };
+
+    let tangent_matrix = Mat3::from_mat4(transform);
+    let normal_matrix = tangent_matrix.inverse().transpose();
+    // Mirroring flips the bitangent.
+    let bitangent_sign = transform.determinant().signum();
+
+    let base = mesh.vertices.len() as u32;
+    let mut normals = reader.read_normals();
+    let mut tangents = reader.read_tangents();
+    let has_tangents = tangents.is_some();
+    let mut colors = reader.read_colors(0).map(|colors| colors.into_rgb_f32());
+    let mut uvs = reader.read_tex_coords(0).map(|uvs| uvs.into_f32());
+    for position in positions {
+        let normal = normals
+            .as_mut()
+            .and_then(Iterator::next)
+            .unwrap_or([0.0, 1.0, 0.0]);
+        // Multiplied with the material's base color, like in glTF.
+        let color = colors.as_mut().and_then(Iterator::next).unwrap_or([1.0; 3]);
+        let uv = uvs.as_mut().and_then(Iterator::next).unwrap_or([0.0, 0.0]);
+        let tangent = tangents
+            .as_mut()
+            .and_then(Iterator::next)
+            .unwrap_or([0.0; 4]);
+        mesh.vertices.push(Vertex {
+            position: transform.transform_point3(Vec3::from(position)).to_array(),
+            normal: (normal_matrix * Vec3::from(normal))
+                .normalize_or_zero()
+                .to_array(),
+            color,
+            uv,
+            tangent: (tangent_matrix * Vec3::from_slice(&tangent[..3]))
+                .normalize_or_zero()
+                .extend(tangent[3] * bitangent_sign)
+                .to_array(),
+        });
+    }
+
+    let first_index = mesh.indices.len() as u32;
+    match reader.read_indices() {
+        Some(indices) => mesh
+            .indices
+            .extend(indices.into_u32().map(|index| base + index)),
+        None => mesh.indices.extend(base..mesh.vertices.len() as u32),
+    }
+    // A negative determinant mirrors the geometry, which flips the winding order.
+    if transform.determinant() < 0.0 {
+        for triangle in mesh.indices[first_index as usize..].chunks_exact_mut(3) {
+            triangle.swap(1, 2);
+        }
+    }
+
+    if !has_tangents {
+        let vertices = &mut mesh.vertices[base as usize..];
+        let indices: Vec<u32> = mesh.indices[first_index as usize..]
+            .iter()
+            .map(|index| index - base)
+            .collect();
+        generate_tangents(vertices, &indices);
+    }
+
+    mesh.submeshes.push(SubMeshData {
+        name: name.unwrap_or_default().to_owned(),
+        material: primitive.material().index(),
+        indices: first_index..mesh.indices.len() as u32,
+    });
+}
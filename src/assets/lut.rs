@@ -0,0 +1,79 @@
+use std::fmt::Write as _;
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::color_grading::LutData;
+
+/// Loads a 3D LUT from an Adobe/Resolve `.cube` file.
+pub fn load(path: impl AsRef<Path>) -> anyhow::Result<LutData> {
+    let path = path.as_ref();
+    let source = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    parse(&source).with_context(|| format!("invalid LUT in {}", path.display()))
+}
+
+/// Parses the contents of a `.cube` file. Only 3D LUTs are supported.
+pub fn parse(source: &str) -> anyhow::Result<LutData> {
+    let mut size = None;
+    let mut domain_min = [0.0; 3];
+    let mut domain_max = [1.0; 3];
+    let mut texels = Vec::new();
+    for (number, line) in source.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut words = line.split_whitespace();
+        let keyword = words.next().unwrap_or_default();
+        let result = match keyword {
+            "TITLE" => Ok(()),
+            "LUT_1D_SIZE" => Err(anyhow::anyhow!("1D LUTs aren't supported")),
+            "LUT_3D_SIZE" => words
+                .next()
+                .context("missing size")
+                .and_then(|value| Ok(value.parse()?))
+                .map(|value| size = Some(value)),
+            "DOMAIN_MIN" => parse_color(words).map(|color| domain_min = color),
+            "DOMAIN_MAX" => parse_color(words).map(|color| domain_max = color),
+            _ => parse_color(line.split_whitespace()).map(|color| texels.push(color)),
+        };
+        result.with_context(|| format!("line {}", number + 1))?;
+    }
+
+    let size = size.context("missing LUT_3D_SIZE")?;
+    if !(2..=256).contains(&size) {
+        anyhow::bail!("unsupported LUT_3D_SIZE {size}");
+    }
+    let expected = (size * size * size) as usize;
+    if texels.len() != expected {
+        anyhow::bail!("expected {expected} entries, found {}", texels.len());
+    }
+    Ok(LutData {
+        size,
+        domain_min,
+        domain_max,
+        texels,
+    })
+}
+
+fn parse_color<'a>(words: impl Iterator<Item = &'a str>) -> anyhow::Result<[f32; 3]> {
+    let values = words.map(str::parse).collect::<Result<Vec<f32>, _>>()?;
+    values
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("expected three numbers"))
+}
+
+/// Writes `lut` as a `.cube` file, e.g. a [`LutData::neutral`] one to grade in other tools.
+pub fn save(lut: &LutData, path: impl AsRef<Path>) -> anyhow::Result<()> {
+    let path = path.as_ref();
+    let mut source = format!("LUT_3D_SIZE {}\n", lut.size);
+    let [r, g, b] = lut.domain_min;
+    writeln!(source, "DOMAIN_MIN {r} {g} {b}")?;
+    let [r, g, b] = lut.domain_max;
+    writeln!(source, "DOMAIN_MAX {r} {g} {b}")?;
+    for [r, g, b] in &lut.texels {
+        writeln!(source, "{r:.6} {g:.6} {b:.6}")?;
+    }
+    std::fs::write(path, source).with_context(|| format!("failed to write {}", path.display()))
+}
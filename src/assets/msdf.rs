@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use crate::msdf::{MsdfFontData, MsdfGlyph};
+
+/// The JSON layout msdf-atlas-gen writes with `-json`.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AtlasJson {
+    atlas: AtlasInfo,
+    metrics: Metrics,
+    glyphs: Vec<Glyph>,
+    #[serde(default)]
+    kerning: Vec<Kerning>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AtlasInfo {
+    #[serde(rename = "type")]
+    kind: String,
+    distance_range: f32,
+    width: u32,
+    height: u32,
+    #[serde(default)]
+    y_origin: YOrigin,
+}
+
+#[derive(Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum YOrigin {
+    #[default]
+    Bottom,
+    Top,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Metrics {
+    line_height: f32,
+    ascender: f32,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Glyph {
+    unicode: u32,
+    advance: f32,
+    plane_bounds: Option<Bounds>,
+    atlas_bounds: Option<Bounds>,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+struct Bounds {
+    left: f32,
+    bottom: f32,
+    right: f32,
+    top: f32,
+}
+
+#[derive(Deserialize)]
+struct Kerning {
+    unicode1: u32,
+    unicode2: u32,
+    advance: f32,
+}
+
+/// Loads an MSDF font atlas generated by msdf-atlas-gen, from its JSON layout at `path` and the
+/// PNG image next to it with the same name, e.g. from
+/// `msdf-atlas-gen -font font.ttf -type msdf -emsize 1 -json font.json -imageout font.png`.
+pub fn load(path: impl AsRef<Path>) -> anyhow::Result<MsdfFontData> {
+    let path = path.as_ref();
+    let json = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let image_path = path.with_extension("png");
+    let image = image::open(&image_path)
+        .with_context(|| format!("failed to load {}", image_path.display()))?
+        .to_rgba8();
+    parse(&json, image).with_context(|| format!("invalid MSDF atlas in {}", path.display()))
+}
+
+/// Parses msdf-atlas-gen's JSON layout of the glyphs in `image`. The metrics have to be in ems,
+/// as with `-emsize 1`.
+pub fn parse(json: &str, image: image::RgbaImage) -> anyhow::Result<MsdfFontData> {
+    let atlas: AtlasJson = serde_json::from_str(json)?;
+    if !matches!(atlas.atlas.kind.as_str(), "msdf" | "mtsdf") {
+        anyhow::bail!(
+            "expected an msdf or mtsdf atlas, found {}",
+            atlas.atlas.kind
+        );
+    }
+    if (image.width(), image.height()) != (atlas.atlas.width, atlas.atlas.height) {
+        anyhow::bail!(
+            "the atlas is {}x{} but its image {}x{}",
+            atlas.atlas.width,
+            atlas.atlas.height,
+            image.width(),
+            image.height()
+        );
+    }
+
+    // Plane bounds become Y up and atlas bounds UVs with V down, whichever origin was used.
+    let flipped = atlas.atlas.y_origin == YOrigin::Top;
+    let size = [atlas.atlas.width as f32, atlas.atlas.height as f32];
+    let mut glyphs = HashMap::new();
+    for glyph in atlas.glyphs {
+        let Some(character) = char::from_u32(glyph.unicode) else {
+            continue;
+        };
+        let quad = glyph
+            .plane_bounds
+            .zip(glyph.atlas_bounds)
+            .map(|(plane, uv)| {
+                let plane = if flipped {
+                    [plane.left, -plane.bottom, plane.right, -plane.top]
+                } else {
+                    [plane.left, plane.bottom, plane.right, plane.top]
+                };
+                let (top, bottom) = if flipped {
+                    (uv.top / size[1], uv.bottom / size[1])
+                } else {
+                    (1.0 - uv.top / size[1], 1.0 - uv.bottom / size[1])
+                };
+                (plane, [uv.left / size[0], top, uv.right / size[0], bottom])
+            });
+        glyphs.insert(
+            character,
+            MsdfGlyph {
+                advance: glyph.advance,
+                plane: quad.map(|(plane, _)| plane),
+                uv: quad.map_or([0.0; 4], |(_, uv)| uv),
+            },
+        );
+    }
+    let kerning = atlas
+        .kerning
+        .iter()
+        .filter_map(|pair| {
+            let first = char::from_u32(pair.unicode1)?;
+            let second = char::from_u32(pair.unicode2)?;
+            Some(((first, second), pair.advance))
+        })
+        .collect();
+
+    Ok(MsdfFontData {
+        distance_range: atlas.atlas.distance_range,
+        line_height: atlas.metrics.line_height,
+        ascender: atlas.metrics.ascender,
+        glyphs,
+        kerning,
+        image,
+    })
+}
@@ -0,0 +1,89 @@
+use std::path::Path;
+
+use anyhow::Context;
+use glam::Vec3;
+
+use crate::mesh::{generate_tangents, MeshData, SubMeshData};
+use crate::vertex::Vertex;
+
+/// Loads a Wavefront OBJ file (and its MTL library, if any) into a single mesh with one
+/// submesh per OBJ model. The diffuse color of each model's material is baked into its
+/// vertices.
+pub fn load(path: impl AsRef<Path>) -> anyhow::Result<MeshData> {
+    let path = path.as_ref();
+    let (models, materials) = tobj::load_obj(
+        path,
+        &tobj::LoadOptions {
+            single_index: true,
+            triangulate: true,
+            ..Default::default()
+        },
+    )
+    .with_context(|| format!("failed to load {}", path.display()))?;
+    let materials = materials.unwrap_or_else(|err| {
+        eprintln!("failed to load materials for {}: {err}", path.display());
+        Vec::new()
+    });
+
+    let mut mesh = MeshData::default();
+    for model in models {
+        let obj = model.mesh;
+        let base = mesh.vertices.len() as u32;
+        let first_index = mesh.indices.len() as u32;
+        let material_color = obj
+            .material_id
+            .and_then(|id| materials.get(id))
+            .and_then(|material| material.diffuse)
+            .unwrap_or([1.0, 1.0, 1.0]);
+
+        for i in 0..obj.positions.len() / 3 {
+            let attribute = |data: &[f32]| {
+                data.get(i * 3..i * 3 + 3)
+                    .map(|value| [value[0], value[1], value[2]])
+            };
+            mesh.vertices.push(Vertex {
+                position: attribute(&obj.positions).unwrap(),
+                normal: attribute(&obj.normals).unwrap_or_default(),
+                color: attribute(&obj.vertex_color).unwrap_or(material_color),
+                // OBJ places the UV origin at the bottom left, wgpu at the top left.
+                uv: obj
+                    .texcoords
+                    .get(i * 2..i * 2 + 2)
+                    .map_or([0.0, 0.0], |uv| [uv[0], 1.0 - uv[1]]),
+                tangent: [0.0; 4],
+            });
+        }
+        mesh.indices
+            .extend(obj.indices.iter().map(|index| base + index));
+
+        if obj.normals.is_empty() {
+            compute_normals(&mut mesh.vertices[base as usize..], &obj.indices);
+        }
+        // OBJ has no tangents.
+        generate_tangents(&mut mesh.vertices[base as usize..], &obj.indices);
+
+        mesh.submeshes.push(SubMeshData {
+            name: model.name,
+            material: obj.material_id,
+            indices: first_index..mesh.indices.len() as u32,
+        });
+    }
+
+    Ok(mesh)
+}
+
+/// Computes smooth vertex normals by averaging the area-weighted normals of adjacent faces.
+fn compute_normals(vertices: &mut [Vertex], indices: &[u32]) {
+    let mut normals = vec![Vec3::ZERO; vertices.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| triangle[i] as usize);
+        let [pa, pb, pc] = [a, b, c].map(|i| Vec3::from(vertices[i].position));
+        let normal = (pb - pa).cross(pc - pa);
+        for i in [a, b, c] {
+            normals[i] += normal;
+        }
+    }
+    for (vertex, normal) in vertices.iter_mut().zip(normals) {
+        vertex.normal = normal.normalize_or_zero().to_array();
+    }
+}
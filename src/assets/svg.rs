@@ -0,0 +1,214 @@
+use std::path::Path;
+
+use anyhow::Context;
+use glam::Vec2;
+use lyon::math::point;
+use lyon::tessellation::{
+    BuffersBuilder, FillOptions, FillTessellator, FillVertex, StrokeOptions, StrokeTessellator,
+    StrokeVertex, VertexBuffers,
+};
+use usvg::tiny_skia_path::PathSegment;
+
+use crate::assets::srgb_to_linear;
+use crate::sprite::SpriteVertex;
+use crate::svg::{SvgData, CURVE_TOLERANCE};
+
+/// Loads an SVG file and tessellates it for drawing at up to `scale` pixels per SVG unit,
+/// see [`tessellate`].
+pub fn load(path: impl AsRef<Path>, scale: f32) -> anyhow::Result<SvgData> {
+    let path = path.as_ref();
+    let data = std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let tree = parse(&data).with_context(|| format!("invalid SVG in {}", path.display()))?;
+    tessellate(&tree, scale)
+}
+
+/// Parses an SVG document, resolving its styles, `use` references and transforms.
+pub fn parse(data: &[u8]) -> anyhow::Result<usvg::Tree> {
+    Ok(usvg::Tree::from_data(data, &usvg::Options::default())?)
+}
+
+/// Tessellates the filled and stroked paths of `tree` into triangles in SVG units, flattening
+/// curves finely enough to be drawn at up to `scale` pixels per unit. Gradients and patterns
+/// are painted with the average color of their stops, and images and text are skipped.
+pub fn tessellate(tree: &usvg::Tree, scale: f32) -> anyhow::Result<SvgData> {
+    let mut buffers = VertexBuffers::new();
+    tessellate_group(tree.root(), scale, 1.0, &mut buffers)?;
+    let size = tree.size();
+    Ok(SvgData {
+        size: Vec2::new(size.width(), size.height()),
+        vertices: buffers.vertices,
+        indices: buffers.indices,
+    })
+}
+
+fn tessellate_group(
+    group: &usvg::Group,
+    scale: f32,
+    opacity: f32,
+    buffers: &mut VertexBuffers<SpriteVertex, u32>,
+) -> anyhow::Result<()> {
+    let opacity = opacity * group.opacity().get();
+    for node in group.children() {
+        match node {
+            usvg::Node::Group(group) => tessellate_group(group, scale, opacity, buffers)?,
+            usvg::Node::Path(path) if path.is_visible() => {
+                tessellate_path(path, scale, opacity, buffers)?
+            }
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+fn tessellate_path(
+    path: &usvg::Path,
+    scale: f32,
+    opacity: f32,
+    buffers: &mut VertexBuffers<SpriteVertex, u32>,
+) -> anyhow::Result<()> {
+    let transform = path.abs_transform();
+    // Tessellated before the transform, so the tolerance shrinks by however much it scales.
+    let transform_scale = (transform.sx * transform.sy - transform.kx * transform.ky)
+        .abs()
+        .sqrt();
+    let tolerance = CURVE_TOLERANCE / (scale * transform_scale).max(1e-6);
+    let to_vertex = |position: lyon::math::Point, color: [f32; 4]| {
+        let x = transform.sx * position.x + transform.kx * position.y + transform.tx;
+        let y = transform.ky * position.x + transform.sy * position.y + transform.ty;
+        SpriteVertex {
+            position: [x, y],
+            uv: [0.5, 0.5],
+            color,
+        }
+    };
+    let lyon_path = build_path(path.data());
+
+    let mut fill_and_stroke = [
+        path.fill().map(|fill| {
+            let color = paint_color(fill.paint(), fill.opacity().get() * opacity);
+            Paint::Fill(fill.rule(), color)
+        }),
+        path.stroke().map(|stroke| {
+            let color = paint_color(stroke.paint(), stroke.opacity().get() * opacity);
+            Paint::Stroke(stroke, color)
+        }),
+    ];
+    if path.paint_order() == usvg::PaintOrder::StrokeAndFill {
+        fill_and_stroke.reverse();
+    }
+    for paint in fill_and_stroke.into_iter().flatten() {
+        match paint {
+            Paint::Fill(rule, color) => {
+                let options = FillOptions::tolerance(tolerance).with_fill_rule(match rule {
+                    usvg::FillRule::NonZero => lyon::tessellation::FillRule::NonZero,
+                    usvg::FillRule::EvenOdd => lyon::tessellation::FillRule::EvenOdd,
+                });
+                FillTessellator::new().tessellate_path(
+                    &lyon_path,
+                    &options,
+                    &mut BuffersBuilder::new(buffers, |vertex: FillVertex| {
+                        to_vertex(vertex.position(), color)
+                    }),
+                )?;
+            }
+            Paint::Stroke(stroke, color) => {
+                let options = StrokeOptions::tolerance(tolerance)
+                    .with_line_width(stroke.width().get())
+                    .with_miter_limit(stroke.miterlimit().get())
+                    .with_line_cap(match stroke.linecap() {
+                        usvg::LineCap::Butt => lyon::tessellation::LineCap::Butt,
+                        usvg::LineCap::Round => lyon::tessellation::LineCap::Round,
+                        usvg::LineCap::Square => lyon::tessellation::LineCap::Square,
+                    })
+                    .with_line_join(match stroke.linejoin() {
+                        usvg::LineJoin::Miter => lyon::tessellation::LineJoin::Miter,
+                        usvg::LineJoin::MiterClip => lyon::tessellation::LineJoin::MiterClip,
+                        usvg::LineJoin::Round => lyon::tessellation::LineJoin::Round,
+                        usvg::LineJoin::Bevel => lyon::tessellation::LineJoin::Bevel,
+                    });
+                StrokeTessellator::new().tessellate_path(
+                    &lyon_path,
+                    &options,
+                    &mut BuffersBuilder::new(buffers, |vertex: StrokeVertex| {
+                        to_vertex(vertex.position(), color)
+                    }),
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// How a path is painted, in the order it is painted in.
+enum Paint<'a> {
+    Fill(usvg::FillRule, [f32; 4]),
+    Stroke(&'a usvg::Stroke, [f32; 4]),
+}
+
+/// The outline of a path in the SVG's own coordinates.
+fn build_path(data: &usvg::tiny_skia_path::Path) -> lyon::path::Path {
+    let mut builder = lyon::path::Path::builder();
+    let mut open = false;
+    for segment in data.segments() {
+        match segment {
+            PathSegment::MoveTo(to) => {
+                if open {
+                    builder.end(false);
+                }
+                builder.begin(point(to.x, to.y));
+                open = true;
+            }
+            PathSegment::LineTo(to) => {
+                builder.line_to(point(to.x, to.y));
+            }
+            PathSegment::QuadTo(control, to) => {
+                builder.quadratic_bezier_to(point(control.x, control.y), point(to.x, to.y));
+            }
+            PathSegment::CubicTo(first, second, to) => {
+                builder.cubic_bezier_to(
+                    point(first.x, first.y),
+                    point(second.x, second.y),
+                    point(to.x, to.y),
+                );
+            }
+            PathSegment::Close => {
+                if open {
+                    builder.end(true);
+                    open = false;
+                }
+            }
+        }
+    }
+    if open {
+        builder.end(false);
+    }
+    builder.build()
+}
+
+/// The linear RGBA color `paint` is drawn with.
+fn paint_color(paint: &usvg::Paint, opacity: f32) -> [f32; 4] {
+    let color = |color: usvg::Color| {
+        [color.red, color.green, color.blue].map(|channel| srgb_to_linear(channel as f32 / 255.0))
+    };
+    let stops = match paint {
+        usvg::Paint::Color(solid) => {
+            let [r, g, b] = color(*solid);
+            return [r, g, b, opacity];
+        }
+        usvg::Paint::LinearGradient(gradient) => gradient.stops(),
+        usvg::Paint::RadialGradient(gradient) => gradient.stops(),
+        usvg::Paint::Pattern(_) => return [0.5, 0.5, 0.5, opacity],
+    };
+    if stops.is_empty() {
+        return [0.0; 4];
+    }
+    let mut sum = [0.0; 4];
+    for stop in stops {
+        let [r, g, b] = color(stop.color());
+        for (total, channel) in sum.iter_mut().zip([r, g, b, stop.opacity().get()]) {
+            *total += channel;
+        }
+    }
+    let [r, g, b, a] = sum.map(|total| total / stops.len() as f32);
+    [r, g, b, a * opacity]
+}
@@ -0,0 +1,488 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::Context;
+use base64::Engine as _;
+use glam::Vec2;
+use serde::Deserialize;
+
+use crate::tilemap::{TileAnimationFrame, TileLayerData, TileMapData, TilesetData};
+
+/// Loads an orthogonal map saved by Tiled, as `.tmx` XML or `.tmj` JSON, along with its
+/// external tilesets. Tile layers are kept, flattened out of their groups, while object and
+/// image layers are skipped. Layer data has to be CSV or uncompressed base64, and infinite
+/// maps aren't supported.
+pub fn load(path: impl AsRef<Path>) -> anyhow::Result<TileMapData> {
+    let path = path.as_ref();
+    let source = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let directory = path.parent().unwrap_or(Path::new(""));
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+    let map = match extension.as_deref() {
+        Some("tmx") => parse_tmx(&source, directory),
+        Some("tmj" | "json") => parse_tmj(&source, directory),
+        _ => anyhow::bail!("unsupported map format: {}", path.display()),
+    };
+    map.with_context(|| format!("invalid map in {}", path.display()))
+}
+
+/// Where a layer ends up after the groups around it.
+#[derive(Clone, Copy)]
+struct Inherited {
+    offset: Vec2,
+    parallax: Vec2,
+    opacity: f32,
+    visible: bool,
+}
+
+impl Inherited {
+    const ROOT: Self = Self {
+        offset: Vec2::ZERO,
+        parallax: Vec2::ONE,
+        opacity: 1.0,
+        visible: true,
+    };
+
+    fn nest(self, offset: Vec2, parallax: Vec2, opacity: f32, visible: bool) -> Self {
+        Self {
+            offset: self.offset + offset,
+            parallax: self.parallax * parallax,
+            opacity: self.opacity * opacity,
+            visible: self.visible && visible,
+        }
+    }
+}
+
+/// Decodes layer data in `encoding` into global tile IDs.
+fn decode_tiles(
+    data: &str,
+    encoding: Option<&str>,
+    compression: Option<&str>,
+) -> anyhow::Result<Vec<u32>> {
+    if let Some(compression) = compression.filter(|compression| !compression.is_empty()) {
+        anyhow::bail!("{compression} compressed layers aren't supported");
+    }
+    match encoding {
+        Some("csv") => data
+            .split(',')
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+            .map(|id| Ok(id.parse()?))
+            .collect(),
+        Some("base64") => {
+            let bytes = base64::engine::general_purpose::STANDARD.decode(data.trim())?;
+            Ok(bytes
+                .chunks_exact(4)
+                .map(|id| u32::from_le_bytes([id[0], id[1], id[2], id[3]]))
+                .collect())
+        }
+        encoding => anyhow::bail!("unsupported layer encoding {encoding:?}"),
+    }
+}
+
+fn sort_tilesets(mut tilesets: Vec<TilesetData>) -> Vec<TilesetData> {
+    tilesets.sort_by_key(|tileset| tileset.first_gid);
+    tilesets
+}
+
+#[derive(Deserialize)]
+struct MapJson {
+    width: u32,
+    height: u32,
+    tilewidth: u32,
+    tileheight: u32,
+    #[serde(default)]
+    infinite: bool,
+    #[serde(default = "default_orientation")]
+    orientation: String,
+    #[serde(default)]
+    parallaxoriginx: f32,
+    #[serde(default)]
+    parallaxoriginy: f32,
+    layers: Vec<LayerJson>,
+    tilesets: Vec<TilesetRefJson>,
+}
+
+fn default_orientation() -> String {
+    "orthogonal".to_owned()
+}
+
+fn one() -> f32 {
+    1.0
+}
+
+fn yes() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+struct LayerJson {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    width: u32,
+    #[serde(default)]
+    height: u32,
+    data: Option<serde_json::Value>,
+    encoding: Option<String>,
+    compression: Option<String>,
+    #[serde(default)]
+    layers: Vec<LayerJson>,
+    #[serde(default)]
+    offsetx: f32,
+    #[serde(default)]
+    offsety: f32,
+    #[serde(default = "one")]
+    parallaxx: f32,
+    #[serde(default = "one")]
+    parallaxy: f32,
+    #[serde(default = "one")]
+    opacity: f32,
+    #[serde(default = "yes")]
+    visible: bool,
+}
+
+#[derive(Deserialize)]
+struct TilesetRefJson {
+    firstgid: u32,
+    source: Option<String>,
+    #[serde(flatten)]
+    tileset: Option<TilesetJson>,
+}
+
+#[derive(Deserialize)]
+struct TilesetJson {
+    tilewidth: u32,
+    tileheight: u32,
+    columns: u32,
+    tilecount: u32,
+    #[serde(default)]
+    margin: u32,
+    #[serde(default)]
+    spacing: u32,
+    image: String,
+    imagewidth: u32,
+    imageheight: u32,
+    #[serde(default)]
+    tiles: Vec<TileJson>,
+}
+
+#[derive(Deserialize)]
+struct TileJson {
+    id: u32,
+    #[serde(default)]
+    animation: Vec<FrameJson>,
+}
+
+#[derive(Deserialize)]
+struct FrameJson {
+    tileid: u32,
+    /// In milliseconds.
+    duration: f32,
+}
+
+impl TilesetJson {
+    fn into_data(self, first_gid: u32, directory: &Path) -> TilesetData {
+        let animations = self
+            .tiles
+            .into_iter()
+            .filter(|tile| !tile.animation.is_empty())
+            .map(|tile| {
+                let frames = tile
+                    .animation
+                    .iter()
+                    .map(|frame| TileAnimationFrame {
+                        tile: frame.tileid,
+                        duration: frame.duration / 1000.0,
+                    })
+                    .collect();
+                (tile.id, frames)
+            })
+            .collect();
+        TilesetData {
+            first_gid,
+            tile_width: self.tilewidth,
+            tile_height: self.tileheight,
+            columns: self.columns,
+            tile_count: self.tilecount,
+            margin: self.margin,
+            spacing: self.spacing,
+            image: directory.join(self.image),
+            image_width: self.imagewidth,
+            image_height: self.imageheight,
+            animations,
+        }
+    }
+}
+
+/// Parses a `.tmj` map, loading external tilesets relative to `directory`.
+pub fn parse_tmj(source: &str, directory: &Path) -> anyhow::Result<TileMapData> {
+    let map: MapJson = serde_json::from_str(source)?;
+    if map.infinite {
+        anyhow::bail!("infinite maps aren't supported");
+    }
+    if map.orientation != "orthogonal" {
+        anyhow::bail!("{} maps aren't supported", map.orientation);
+    }
+
+    let tilesets = map
+        .tilesets
+        .into_iter()
+        .map(|reference| match (reference.source, reference.tileset) {
+            (Some(source), _) => load_tileset(&directory.join(source), reference.firstgid),
+            (None, Some(tileset)) => Ok(tileset.into_data(reference.firstgid, directory)),
+            (None, None) => anyhow::bail!("tileset {} is empty", reference.firstgid),
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    fn flatten(
+        layers: Vec<LayerJson>,
+        inherited: Inherited,
+        out: &mut Vec<TileLayerData>,
+    ) -> anyhow::Result<()> {
+        for layer in layers {
+            let inherited = inherited.nest(
+                Vec2::new(layer.offsetx, layer.offsety),
+                Vec2::new(layer.parallaxx, layer.parallaxy),
+                layer.opacity,
+                layer.visible,
+            );
+            match layer.kind.as_str() {
+                "group" => flatten(layer.layers, inherited, out)?,
+                "tilelayer" => {
+                    let tiles = match layer.data {
+                        Some(serde_json::Value::String(data)) => decode_tiles(
+                            &data,
+                            layer.encoding.as_deref(),
+                            layer.compression.as_deref(),
+                        )?,
+                        Some(data) => serde_json::from_value(data)?,
+                        None => anyhow::bail!("layer {} has no data", layer.name),
+                    };
+                    out.push(tile_layer(
+                        layer.name,
+                        layer.width,
+                        layer.height,
+                        tiles,
+                        inherited,
+                    )?);
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+    let mut layers = Vec::new();
+    flatten(map.layers, Inherited::ROOT, &mut layers)?;
+
+    Ok(TileMapData {
+        width: map.width,
+        height: map.height,
+        tile_width: map.tilewidth,
+        tile_height: map.tileheight,
+        parallax_origin: Vec2::new(map.parallaxoriginx, map.parallaxoriginy),
+        tilesets: sort_tilesets(tilesets),
+        layers,
+    })
+}
+
+fn tile_layer(
+    name: String,
+    width: u32,
+    height: u32,
+    tiles: Vec<u32>,
+    inherited: Inherited,
+) -> anyhow::Result<TileLayerData> {
+    if tiles.len() != (width * height) as usize {
+        anyhow::bail!(
+            "layer {name} has {} tiles instead of {width}x{height}",
+            tiles.len()
+        );
+    }
+    Ok(TileLayerData {
+        name,
+        width,
+        height,
+        tiles,
+        offset: inherited.offset,
+        parallax: inherited.parallax,
+        opacity: inherited.opacity,
+        visible: inherited.visible,
+    })
+}
+
+/// Loads an external `.tsx` or `.tsj` tileset whose tiles start at `first_gid`.
+fn load_tileset(path: &Path, first_gid: u32) -> anyhow::Result<TilesetData> {
+    let source = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let directory = path.parent().unwrap_or(Path::new(""));
+    let is_xml = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("tsx"));
+    let tileset = if is_xml {
+        roxmltree::Document::parse(&source)
+            .map_err(anyhow::Error::from)
+            .and_then(|document| tsx_tileset(document.root_element(), first_gid, directory))
+    } else {
+        serde_json::from_str::<TilesetJson>(&source)
+            .map(|tileset| tileset.into_data(first_gid, directory))
+            .map_err(anyhow::Error::from)
+    };
+    tileset.with_context(|| format!("invalid tileset in {}", path.display()))
+}
+
+/// Reads the attribute `name` of `node`, or `default` without it.
+fn attribute<T: std::str::FromStr>(
+    node: roxmltree::Node,
+    name: &str,
+    default: Option<T>,
+) -> anyhow::Result<T>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    match node.attribute(name) {
+        Some(value) => value
+            .parse()
+            .with_context(|| format!("invalid {name} {value:?}")),
+        None => default.with_context(|| format!("missing {name} on <{}>", node.tag_name().name())),
+    }
+}
+
+fn child<'a, 'input>(
+    node: roxmltree::Node<'a, 'input>,
+    name: &str,
+) -> Option<roxmltree::Node<'a, 'input>> {
+    node.children().find(|child| child.has_tag_name(name))
+}
+
+/// Reads a `<tileset>` element with its image.
+fn tsx_tileset(
+    node: roxmltree::Node,
+    first_gid: u32,
+    directory: &Path,
+) -> anyhow::Result<TilesetData> {
+    let image = child(node, "image").context("tilesets of separate images aren't supported")?;
+    let mut animations = HashMap::new();
+    for tile in node.children().filter(|child| child.has_tag_name("tile")) {
+        let Some(animation) = child(tile, "animation") else {
+            continue;
+        };
+        let frames = animation
+            .children()
+            .filter(|child| child.has_tag_name("frame"))
+            .map(|frame| {
+                Ok(TileAnimationFrame {
+                    tile: attribute(frame, "tileid", None)?,
+                    duration: attribute::<f32>(frame, "duration", None)? / 1000.0,
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        animations.insert(attribute(tile, "id", None)?, frames);
+    }
+
+    Ok(TilesetData {
+        first_gid,
+        tile_width: attribute(node, "tilewidth", None)?,
+        tile_height: attribute(node, "tileheight", None)?,
+        columns: attribute(node, "columns", None)?,
+        tile_count: attribute(node, "tilecount", None)?,
+        margin: attribute(node, "margin", Some(0))?,
+        spacing: attribute(node, "spacing", Some(0))?,
+        image: directory.join(attribute::<String>(image, "source", None)?),
+        image_width: attribute(image, "width", None)?,
+        image_height: attribute(image, "height", None)?,
+        animations,
+    })
+}
+
+/// Parses a `.tmx` map, loading external tilesets relative to `directory`.
+pub fn parse_tmx(source: &str, directory: &Path) -> anyhow::Result<TileMapData> {
+    let document = roxmltree::Document::parse(source)?;
+    let map = document.root_element();
+    if attribute(map, "infinite", Some(0u8))? != 0 {
+        anyhow::bail!("infinite maps aren't supported");
+    }
+    let orientation: String = attribute(map, "orientation", Some("orthogonal".to_owned()))?;
+    if orientation != "orthogonal" {
+        anyhow::bail!("{orientation} maps aren't supported");
+    }
+
+    let tilesets = map
+        .children()
+        .filter(|child| child.has_tag_name("tileset"))
+        .map(|tileset| {
+            let first_gid = attribute(tileset, "firstgid", None)?;
+            match tileset.attribute("source") {
+                Some(source) => load_tileset(&directory.join(source), first_gid),
+                None => tsx_tileset(tileset, first_gid, directory),
+            }
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    fn flatten(
+        parent: roxmltree::Node,
+        inherited: Inherited,
+        out: &mut Vec<TileLayerData>,
+    ) -> anyhow::Result<()> {
+        for node in parent.children().filter(roxmltree::Node::is_element) {
+            let tag = node.tag_name().name();
+            if tag != "group" && tag != "layer" {
+                continue;
+            }
+            let inherited = inherited.nest(
+                Vec2::new(
+                    attribute(node, "offsetx", Some(0.0))?,
+                    attribute(node, "offsety", Some(0.0))?,
+                ),
+                Vec2::new(
+                    attribute(node, "parallaxx", Some(1.0))?,
+                    attribute(node, "parallaxy", Some(1.0))?,
+                ),
+                attribute(node, "opacity", Some(1.0))?,
+                attribute(node, "visible", Some(1u8))? != 0,
+            );
+            if tag == "group" {
+                flatten(node, inherited, out)?;
+                continue;
+            }
+            let name: String = attribute(node, "name", Some(String::new()))?;
+            let data = child(node, "data").with_context(|| format!("layer {name} has no data"))?;
+            if child(data, "tile").is_some() {
+                anyhow::bail!("layer {name} is saved as XML, which isn't supported");
+            }
+            let tiles = decode_tiles(
+                data.text().unwrap_or_default(),
+                data.attribute("encoding"),
+                data.attribute("compression"),
+            )?;
+            out.push(tile_layer(
+                name,
+                attribute(node, "width", None)?,
+                attribute(node, "height", None)?,
+                tiles,
+                inherited,
+            )?);
+        }
+        Ok(())
+    }
+    let mut layers = Vec::new();
+    flatten(map, Inherited::ROOT, &mut layers)?;
+
+    Ok(TileMapData {
+        width: attribute(map, "width", None)?,
+        height: attribute(map, "height", None)?,
+        tile_width: attribute(map, "tilewidth", None)?,
+        tile_height: attribute(map, "tileheight", None)?,
+        parallax_origin: Vec2::new(
+            attribute(map, "parallaxoriginx", Some(0.0))?,
+            attribute(map, "parallaxoriginy", Some(0.0))?,
+        ),
+        tilesets: sort_tilesets(tilesets),
+        layers,
+    })
+}
@@ -0,0 +1,393 @@
+//! Packs many small images into a few atlas pages, so that sprites drawn from them share
+//! textures and batch into fewer draws.
+
+use glam::Vec2;
+
+use crate::nine_patch::NinePatch;
+use crate::sprite::{self, Sprite, SpriteBatch, SpriteTexture};
+use crate::texture::{SamplerDesc, Texture};
+
+/// Places rectangles in a fixed-size area with the skyline bottom-left heuristic: the top edge
+/// of everything placed so far is kept as a list of segments, and each rectangle goes where it
+/// ends lowest, leftmost on ties. Space under overhangs is lost, which for images of similar
+/// heights, like glyphs and icons, is little.
+#[derive(Clone, Debug)]
+pub struct SkylinePacker {
+    width: u32,
+    height: u32,
+    /// The `(x, y, width)` segments of the skyline, left to right, covering the whole width.
+    skyline: Vec<(u32, u32, u32)>,
+}
+
+impl SkylinePacker {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            skyline: vec![(0, 0, width)],
+        }
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Finds room for a `width` by `height` rectangle and returns its top left corner, or
+    /// `None` if it doesn't fit anywhere. Empty rectangles take no room and go in the corner.
+    pub fn pack(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
+        if width == 0 || height == 0 {
+            return (width <= self.width && height <= self.height).then_some((0, 0));
+        }
+        let (index, x, y) = (0..self.skyline.len())
+            .filter_map(|index| {
+                let y = self.fits(index, width, height)?;
+                Some((index, self.skyline[index].0, y))
+            })
+            .min_by_key(|&(_, x, y)| (y + height, x))?;
+
+        // The new segment replaces those it covers, cutting the last one short.
+        let right = x + width;
+        let mut end = index;
+        while end < self.skyline.len() && self.skyline[end].0 < right {
+            end += 1;
+        }
+        let (last_x, last_y, last_width) = self.skyline[end - 1];
+        let last_right = last_x + last_width;
+        let mut replacement = vec![(x, y + height, width)];
+        if last_right > right {
+            replacement.push((right, last_y, last_right - right));
+        }
+        self.skyline.splice(index..end, replacement);
+        self.merge();
+        Some((x, y))
+    }
+
+    /// The height a rectangle starting at segment `index` would rest at, if it fits there.
+    fn fits(&self, index: usize, width: u32, height: u32) -> Option<u32> {
+        let x = self.skyline[index].0;
+        if x + width > self.width {
+            return None;
+        }
+        let mut y = 0;
+        let mut covered = 0;
+        for &(_, segment_y, segment_width) in &self.skyline[index..] {
+            y = y.max(segment_y);
+            covered += segment_width;
+            if covered >= width {
+                break;
+            }
+        }
+        (y + height <= self.height).then_some(y)
+    }
+
+    /// Joins neighbouring segments at the same height.
+    fn merge(&mut self) {
+        self.skyline.dedup_by(|next, previous| {
+            if previous.1 == next.1 {
+                previous.2 += next.2;
+                true
+            } else {
+                false
+            }
+        });
+    }
+}
+
+/// An image added to an [`AtlasBuilder`], by the order it was added in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AtlasImage(pub usize);
+
+/// Where an image ended up in an atlas.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AtlasRegion {
+    pub page: usize,
+    /// The left, top, right and bottom of the image in the page, in UVs.
+    pub uv: [f32; 4],
+    /// The size of the image in pixels.
+    pub size: (u32, u32),
+}
+
+impl AtlasRegion {
+    /// Maps `uv`, relative to the image, to the page.
+    pub fn map_uv(&self, uv: [f32; 4]) -> [f32; 4] {
+        sprite::map_uv(self.uv, uv)
+    }
+}
+
+/// Packed atlas pages and the regions of the images in them, indexed by [`AtlasImage`].
+#[derive(Clone, Debug)]
+pub struct AtlasData {
+    pub pages: Vec<image::RgbaImage>,
+    pub regions: Vec<AtlasRegion>,
+}
+
+/// Collects images and packs them into as few pages as fit them, see [`SkylinePacker`].
+#[derive(Clone, Debug)]
+pub struct AtlasBuilder {
+    page_size: u32,
+    padding: u32,
+    images: Vec<image::RgbaImage>,
+}
+
+impl AtlasBuilder {
+    /// Packs into square pages `page_size` pixels wide.
+    pub fn new(page_size: u32) -> Self {
+        Self {
+            page_size,
+            padding: 1,
+            images: Vec::new(),
+        }
+    }
+
+    /// Surrounds every image with `padding` pixels repeating its edges, keeping filtering from
+    /// bleeding neighbours in. One pixel by default.
+    pub fn with_padding(mut self, padding: u32) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    pub fn add(&mut self, image: image::RgbaImage) -> AtlasImage {
+        self.images.push(image);
+        AtlasImage(self.images.len() - 1)
+    }
+
+    pub fn len(&self) -> usize {
+        self.images.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.images.is_empty()
+    }
+
+    /// Packs the images, tallest first, starting a new page whenever one doesn't fit in those
+    /// so far. Fails if an image doesn't fit in a page on its own.
+    pub fn build(self) -> anyhow::Result<AtlasData> {
+        let padding = self.padding;
+        let mut order: Vec<usize> = (0..self.images.len()).collect();
+        order.sort_by_key(|&index| {
+            let image = &self.images[index];
+            std::cmp::Reverse((image.height(), image.width()))
+        });
+
+        let mut packers: Vec<SkylinePacker> = Vec::new();
+        let mut pages: Vec<image::RgbaImage> = Vec::new();
+        let mut regions = vec![None; self.images.len()];
+        for index in order {
+            let image = &self.images[index];
+            let (width, height) = (image.width() + 2 * padding, image.height() + 2 * padding);
+            if width > self.page_size || height > self.page_size {
+                anyhow::bail!(
+                    "a {}x{} image doesn't fit in {size}x{size} atlas pages",
+                    image.width(),
+                    image.height(),
+                    size = self.page_size
+                );
+            }
+            let placed = packers
+                .iter_mut()
+                .enumerate()
+                .find_map(|(page, packer)| Some((page, packer.pack(width, height)?)));
+            let (page, (x, y)) = match placed {
+                Some(placed) => placed,
+                None => {
+                    let mut packer = SkylinePacker::new(self.page_size, self.page_size);
+                    let position = packer
+                        .pack(width, height)
+                        .expect("an image fits in an empty page");
+                    packers.push(packer);
+                    pages.push(image::RgbaImage::new(self.page_size, self.page_size));
+                    (packers.len() - 1, position)
+                }
+            };
+            blit_extruded(&mut pages[page], image, x, y, padding);
+
+            let size = self.page_size as f32;
+            let (left, top) = (x + padding, y + padding);
+            regions[index] = Some(AtlasRegion {
+                page,
+                uv: [
+                    left as f32 / size,
+                    top as f32 / size,
+                    (left + image.width()) as f32 / size,
+                    (top + image.height()) as f32 / size,
+                ],
+                size: image.dimensions(),
+            });
+        }
+
+        Ok(AtlasData {
+            pages,
+            regions: regions.into_iter().flatten().collect(),
+        })
+    }
+}
+
+/// Copies `image` into `page` at `(x, y)` after `padding` pixels, and fills the padding by
+/// clamping to its edges.
+fn blit_extruded(
+    page: &mut image::RgbaImage,
+    image: &image::RgbaImage,
+    x: u32,
+    y: u32,
+    padding: u32,
+) {
+    let (width, height) = image.dimensions();
+    if width == 0 || height == 0 {
+        return;
+    }
+    for row in 0..height + 2 * padding {
+        for column in 0..width + 2 * padding {
+            let source_x = column.saturating_sub(padding).min(width - 1);
+            let source_y = row.saturating_sub(padding).min(height - 1);
+            page.put_pixel(x + column, y + row, *image.get_pixel(source_x, source_y));
+        }
+    }
+}
+
+/// Atlas pages uploaded for a [`SpriteBatch`].
+#[derive(Clone, Debug)]
+pub struct SpriteAtlas {
+    pages: Vec<SpriteTexture>,
+    regions: Vec<AtlasRegion>,
+}
+
+impl SpriteAtlas {
+    /// Uploads the pages of `atlas` as sRGB textures and adds them to `batch`.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        atlas: &AtlasData,
+        batch: &mut SpriteBatch,
+    ) -> Self {
+        let pages = atlas
+            .pages
+            .iter()
+            .enumerate()
+            .map(|(index, page)| {
+                let texture = Texture::from_rgba(
+                    device,
+                    queue,
+                    page,
+                    wgpu::TextureFormat::Rgba8UnormSrgb,
+                    &format!("atlas page {index}"),
+                    SamplerDesc::Linear(wgpu::AddressMode::ClampToEdge),
+                    None,
+                );
+                batch.add_texture(device, &texture)
+            })
+            .collect();
+
+        Self {
+            pages,
+            regions: atlas.regions.clone(),
+        }
+    }
+
+    pub fn region(&self, image: AtlasImage) -> &AtlasRegion {
+        &self.regions[image.0]
+    }
+
+    /// The page texture of `image` and `sprite` with its UVs mapped into the region of `image`,
+    /// for [`SpriteBatch::draw`].
+    pub fn sprite(&self, image: AtlasImage, sprite: Sprite) -> (SpriteTexture, Sprite) {
+        let region = self.region(image);
+        let sprite = Sprite {
+            uv: region.map_uv(sprite.uv),
+            ..sprite
+        };
+        (self.pages[region.page], sprite)
+    }
+
+    /// A sprite of `image` at its size in pixels, centered on `position`.
+    pub fn sprite_at(&self, image: AtlasImage, position: Vec2) -> (SpriteTexture, Sprite) {
+        let (width, height) = self.region(image).size;
+        self.sprite(
+            image,
+            Sprite::new(position, Vec2::new(width as f32, height as f32)),
+        )
+    }
+
+    /// `image` cut into nine by the border `insets` in pixels, as left, top, right and bottom.
+    pub fn nine_patch(&self, image: AtlasImage, insets: [f32; 4]) -> NinePatch {
+        let region = self.region(image);
+        let (width, height) = region.size;
+        NinePatch::new(self.pages[region.page], Vec2::ZERO, insets)
+            .with_region(region.uv, Vec2::new(width as f32, height as f32))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packs_bottom_left() {
+        let mut packer = SkylinePacker::new(16, 16);
+        assert_eq!(packer.pack(8, 4), Some((0, 0)));
+        assert_eq!(packer.pack(8, 2), Some((8, 0)));
+        assert_eq!(packer.pack(8, 4), Some((8, 2)));
+        assert_eq!(packer.pack(16, 10), Some((0, 6)));
+        assert_eq!(packer.pack(1, 1), None);
+    }
+
+    #[test]
+    fn fills_the_whole_area() {
+        let mut packer = SkylinePacker::new(8, 8);
+        for y in (0..8).step_by(2) {
+            for x in (0..8).step_by(4) {
+                assert_eq!(packer.pack(4, 2), Some((x, y)));
+            }
+        }
+        assert_eq!(packer.pack(1, 1), None);
+    }
+
+    #[test]
+    fn rejects_oversized_rectangles() {
+        let mut packer = SkylinePacker::new(8, 8);
+        assert_eq!(packer.pack(9, 1), None);
+        assert_eq!(packer.pack(1, 9), None);
+        assert_eq!(packer.pack(0, 9), None);
+        assert_eq!(packer.pack(8, 8), Some((0, 0)));
+    }
+
+    #[test]
+    fn empty_rectangles_take_no_room() {
+        let mut packer = SkylinePacker::new(8, 8);
+        assert_eq!(packer.pack(0, 0), Some((0, 0)));
+        assert_eq!(packer.pack(0, 4), Some((0, 0)));
+        assert_eq!(packer.pack(4, 0), Some((0, 0)));
+        assert_eq!(packer.pack(8, 8), Some((0, 0)));
+        assert_eq!(packer.pack(0, 0), Some((0, 0)));
+    }
+
+    #[test]
+    fn builds_new_pages_when_full() {
+        let mut builder = AtlasBuilder::new(8).with_padding(0);
+        let images: Vec<_> = (0..5)
+            .map(|_| builder.add(image::RgbaImage::new(4, 4)))
+            .collect();
+        let atlas = builder.build().unwrap();
+        assert_eq!(atlas.pages.len(), 2);
+        assert_eq!(atlas.regions.len(), images.len());
+        assert_eq!(atlas.regions[4].page, 1);
+        assert_eq!(atlas.regions[4].uv, [0.0, 0.0, 0.5, 0.5]);
+    }
+
+    #[test]
+    fn builds_empty_images() {
+        let mut builder = AtlasBuilder::new(8).with_padding(0);
+        let empty = builder.add(image::RgbaImage::new(0, 0));
+        let image = builder.add(image::RgbaImage::new(8, 8));
+        let atlas = builder.build().unwrap();
+        assert_eq!(atlas.pages.len(), 1);
+        assert_eq!(atlas.regions[empty.0].size, (0, 0));
+        assert_eq!(atlas.regions[image.0].uv, [0.0, 0.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn rejects_images_larger_than_a_page() {
+        let mut builder = AtlasBuilder::new(8);
+        builder.add(image::RgbaImage::new(7, 7));
+        assert!(builder.build().is_err());
+    }
+}
@@ -0,0 +1,149 @@
+//! Material textures in one bind group of texture and sampler arrays, indexed in the shader by
+//! the material being drawn, so that draws switch materials with a dynamic offset alone instead
+//! of a bind group per material.
+
+use std::num::NonZeroU32;
+
+use crate::material::Material;
+use crate::shader::Preprocessor;
+
+/// The most materials the arrays have room for, at five textures each.
+const MAX_MATERIALS: u32 = 256;
+/// Sampled textures and samplers left to the other bind groups of the scene shader.
+const RESERVED_BINDINGS: u32 = 16;
+/// The textures of each material, in the order of [`Material::textures`].
+const TEXTURES_PER_MATERIAL: u32 = 5;
+
+/// The textures of every material at `@group(2)`: a `binding_array` of texture views at binding 0
+/// and one of their samplers at binding 1. Material slot 0 belongs to the default material,
+/// which materials past the capacity fall back to.
+pub struct BindlessTextures {
+    capacity: u32,
+    layout: wgpu::BindGroupLayout,
+    bind_group: Option<wgpu::BindGroup>,
+    /// The views the bind group was created with, to tell when materials change.
+    views: Vec<wgpu::Id<wgpu::TextureView>>,
+    warned: bool,
+}
+
+impl BindlessTextures {
+    /// `None` without [`wgpu::Features::TEXTURE_BINDING_ARRAY`] or room in the limits for two
+    /// materials, in which case each material binds its own bind group.
+    pub fn new(device: &wgpu::Device) -> Option<Self> {
+        if !device
+            .features()
+            .contains(wgpu::Features::TEXTURE_BINDING_ARRAY)
+        {
+            return None;
+        }
+        let limits = device.limits();
+        let bindings = limits
+            .max_sampled_textures_per_shader_stage
+            .min(limits.max_samplers_per_shader_stage)
+            .saturating_sub(RESERVED_BINDINGS);
+        let capacity = (bindings / TEXTURES_PER_MATERIAL).min(MAX_MATERIALS);
+        if capacity < 2 {
+            return None;
+        }
+        let count = NonZeroU32::new(capacity * TEXTURES_PER_MATERIAL);
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("bindless material textures"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count,
+                },
+            ],
+        });
+        Some(Self {
+            capacity,
+            layout,
+            bind_group: None,
+            views: Vec::new(),
+            warned: false,
+        })
+    }
+
+    /// Defines `BINDLESS` for `shader.wgsl`, which then samples the arrays.
+    pub fn configure_preprocessor(&self, preprocessor: Preprocessor) -> Preprocessor {
+        preprocessor.define("BINDLESS", "")
+    }
+
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.layout
+    }
+
+    /// The bind group created by the last [`BindlessTextures::update`].
+    pub fn bind_group(&self) -> Option<&wgpu::BindGroup> {
+        self.bind_group.as_ref()
+    }
+
+    /// How many materials fit, the default one included.
+    pub fn capacity(&self) -> usize {
+        self.capacity as usize
+    }
+
+    /// The index of the first texture of the material in `slot`, as passed to
+    /// [`BindlessTextures::update`].
+    pub fn texture_index(&self, slot: usize) -> u32 {
+        let slot = if slot < self.capacity() { slot } else { 0 };
+        slot as u32 * TEXTURES_PER_MATERIAL
+    }
+
+    /// Puts the textures of `materials` in consecutive slots, the default material first, and
+    /// creates the bind group again if any of them changed. Unused slots repeat the default
+    /// material's textures, since every element of the arrays has to be bound.
+    pub fn update(&mut self, device: &wgpu::Device, materials: &[&Material]) {
+        let Some(default) = materials.first() else {
+            return;
+        };
+        if materials.len() > self.capacity() && !self.warned {
+            eprintln!(
+                "{} materials don't fit in {} bindless slots, the rest use the default material's \
+                 textures",
+                materials.len(),
+                self.capacity
+            );
+            self.warned = true;
+        }
+        let textures: Vec<_> = (0..self.capacity())
+            .flat_map(|slot| materials.get(slot).unwrap_or(default).textures())
+            .collect();
+        let views: Vec<_> = textures
+            .iter()
+            .map(|texture| texture.view.global_id())
+            .collect();
+        if self.bind_group.is_some() && views == self.views {
+            return;
+        }
+        let texture_views: Vec<_> = textures.iter().map(|texture| &texture.view).collect();
+        let samplers: Vec<_> = textures.iter().map(|texture| &texture.sampler).collect();
+        self.bind_group = Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("bindless material textures"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureViewArray(&texture_views),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::SamplerArray(&samplers),
+                },
+            ],
+        }));
+        self.views = views;
+    }
+}
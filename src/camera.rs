@@ -0,0 +1,121 @@
+use glam::{Mat4, Vec3};
+
+mod fly;
+mod orbit;
+
+pub use fly::FlyController;
+pub use orbit::OrbitController;
+
+/// The controller currently driving the [`Camera`].
+#[derive(Clone, Debug)]
+pub enum CameraController {
+    Orbit(OrbitController),
+    Fly(FlyController),
+}
+
+impl CameraController {
+    pub fn process_input(&mut self, input: &crate::input::Input) {
+        match self {
+            CameraController::Orbit(controller) => controller.process_input(input),
+            CameraController::Fly(controller) => controller.process_input(input),
+        }
+    }
+
+    pub fn update_camera(&mut self, camera: &mut Camera, dt: f32) {
+        match self {
+            CameraController::Orbit(controller) => controller.update_camera(camera, dt),
+            CameraController::Fly(controller) => controller.update_camera(camera, dt),
+        }
+    }
+
+    /// Switches between orbit and fly mode, keeping the current view.
+    pub fn toggle(&mut self, camera: &Camera) {
+        *self = match self {
+            CameraController::Orbit(_) => CameraController::Fly(FlyController::from_camera(camera)),
+            CameraController::Fly(_) => {
+                CameraController::Orbit(OrbitController::from_camera(camera))
+            }
+        };
+    }
+}
+
+/// A perspective camera looking from `eye` towards `target`.
+#[derive(Clone, Debug)]
+pub struct Camera {
+    pub eye: Vec3,
+    pub target: Vec3,
+    pub up: Vec3,
+    pub aspect: f32,
+    /// Vertical field of view in radians.
+    pub fovy: f32,
+    pub znear: f32,
+    pub zfar: f32,
+}
+
+impl Camera {
+    pub fn new(aspect: f32) -> Self {
+        Self {
+            eye: Vec3::new(1.5, 1.5, 2.5),
+            target: Vec3::ZERO,
+            up: Vec3::Y,
+            aspect,
+            fovy: 45.0_f32.to_radians(),
+            znear: 0.1,
+            zfar: 100.0,
+        }
+    }
+
+    pub fn view_matrix(&self) -> Mat4 {
+        Mat4::look_at_rh(self.eye, self.target, self.up)
+    }
+
+    /// Right-handed projection mapping depth to wgpu's `0..1` range.
+    pub fn projection_matrix(&self) -> Mat4 {
+        Mat4::perspective_rh(self.fovy, self.aspect, self.znear, self.zfar)
+    }
+
+    pub fn view_projection_matrix(&self) -> Mat4 {
+        self.projection_matrix() * self.view_matrix()
+    }
+}
+
+/// The camera data uploaded to shaders, bound at `@group(1) @binding(0)`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct CameraUniform {
+    pub view_proj: [[f32; 4]; 4],
+    /// The view projection of the previous frame, which motion vectors are measured from.
+    pub previous_view_proj: [[f32; 4]; 4],
+    /// World-space camera position, `w` is unused.
+    pub position: [f32; 4],
+    /// A subpixel offset in normalized device coordinates added to the projected vertices,
+    /// without affecting the motion vectors.
+    pub jitter: [f32; 2],
+    pub _padding: [f32; 2],
+}
+
+impl Default for CameraUniform {
+    fn default() -> Self {
+        Self {
+            view_proj: Mat4::IDENTITY.to_cols_array_2d(),
+            previous_view_proj: Mat4::IDENTITY.to_cols_array_2d(),
+            position: [0.0; 4],
+            jitter: [0.0; 2],
+            _padding: [0.0; 2],
+        }
+    }
+}
+
+/// A still, unjittered camera.
+impl From<&Camera> for CameraUniform {
+    fn from(camera: &Camera) -> Self {
+        let view_proj = camera.view_projection_matrix().to_cols_array_2d();
+        Self {
+            view_proj,
+            previous_view_proj: view_proj,
+            position: camera.eye.extend(1.0).to_array(),
+            jitter: [0.0; 2],
+            _padding: [0.0; 2],
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use glam::Vec3;
+
+use super::Camera;
+use crate::input::Input;
+
+/// A first-person camera moved with the `move_*` actions, WASD plus Q/E for down/up or the left
+/// stick and triggers by default, and rotated with raw mouse motion while the cursor is grabbed
+/// or with the `look_*` actions, bound to the right stick.
+#[derive(Clone, Debug)]
+pub struct FlyController {
+    pub position: Vec3,
+    /// Rotation around the world Y axis in radians, zero looks down `-Z`.
+    pub yaw: f32,
+    /// Rotation above the horizon in radians.
+    pub pitch: f32,
+    /// Movement speed in units per second.
+    pub speed: f32,
+    /// Radians of rotation per unit of mouse motion.
+    pub sensitivity: f32,
+    /// Radians of rotation per second with the `look_*` actions fully triggered.
+    pub look_speed: f32,
+    /// Whether mouse motion rotates the camera, i.e. the cursor is grabbed.
+    pub mouse_look: bool,
+    movement: Movement,
+    look_delta: (f32, f32),
+    /// How strongly the `look_*` actions are triggered, towards the right and up.
+    look_rate: (f32, f32),
+}
+
+/// How strongly each movement direction is triggered, from -1 to 1.
+#[derive(Clone, Copy, Debug, Default)]
+struct Movement {
+    forward: f32,
+    right: f32,
+    up: f32,
+}
+
+impl FlyController {
+    /// Creates a controller matching the current position and orientation of `camera`.
+    pub fn from_camera(camera: &Camera) -> Self {
+        let direction = (camera.target - camera.eye).normalize_or_zero();
+        Self {
+            position: camera.eye,
+            yaw: direction.x.atan2(-direction.z),
+            pitch: direction.y.clamp(-1.0, 1.0).asin(),
+            speed: 2.0,
+            sensitivity: 0.002,
+            look_speed: 2.0,
+            mouse_look: false,
+            movement: Movement::default(),
+            look_delta: (0.0, 0.0),
+            look_rate: (0.0, 0.0),
+        }
+    }
+
+    pub fn forward(&self) -> Vec3 {
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
+        Vec3::new(sin_yaw * cos_pitch, sin_pitch, -cos_yaw * cos_pitch)
+    }
+
+    /// Follows the movement actions and, while `mouse_look` is set, raw mouse motion, applied on
+    /// the next [`FlyController::update_camera`].
+    pub fn process_input(&mut self, input: &Input) {
+        let axis = |positive: &str, negative: &str| {
+            input.action_value(positive) - input.action_value(negative)
+        };
+        self.movement = Movement {
+            forward: axis("move_forward", "move_backward"),
+            right: axis("move_right", "move_left"),
+            up: axis("move_up", "move_down"),
+        };
+        self.look_rate = (
+            axis("look_right", "look_left"),
+            axis("look_up", "look_down"),
+        );
+        if self.mouse_look {
+            let (dx, dy) = input.mouse_motion();
+            self.look_delta.0 += dx as f32;
+            self.look_delta.1 += dy as f32;
+        }
+    }
+
+    /// Releases all movement keys, e.g. when the window loses focus.
+    pub fn reset(&mut self) {
+        self.movement = Movement::default();
+        self.look_delta = (0.0, 0.0);
+        self.look_rate = (0.0, 0.0);
+    }
+
+    /// Advances the controller by `dt` seconds and writes the result into `camera`.
+    pub fn update_camera(&mut self, camera: &mut Camera, dt: f32) {
+        let limit = std::f32::consts::FRAC_PI_2 - 0.01;
+        let (dx, dy) = std::mem::take(&mut self.look_delta);
+        let (rate_x, rate_y) = self.look_rate;
+        self.yaw += dx * self.sensitivity + rate_x * self.look_speed * dt;
+        self.pitch = (self.pitch - dy * self.sensitivity + rate_y * self.look_speed * dt)
+            .clamp(-limit, limit);
+
+        let forward = self.forward();
+        let right = forward.cross(Vec3::Y).normalize_or_zero();
+        let movement = forward * self.movement.forward
+            + right * self.movement.right
+            + Vec3::Y * self.movement.up;
+        // Diagonal movement is as fast as straight movement, partial stick tilts are slower.
+        self.position += movement.clamp_length_max(1.0) * self.speed * dt;
+
+        camera.eye = self.position;
+        camera.target = self.position + forward;
+        camera.up = Vec3::Y;
+    }
+}
@@ -0,0 +1,96 @@
+use super::Camera;
+use crate::input::Input;
+use glam::Vec3;
+
+/// Rotates a [`Camera`] around a target point by dragging with the left mouse button, or
+/// whatever the `orbit` action is bound to, or with the `look_*` actions, and zooms with the
+/// scroll wheel.
+#[derive(Clone, Debug)]
+pub struct OrbitController {
+    pub target: Vec3,
+    pub distance: f32,
+    /// Rotation around the world Y axis in radians.
+    pub yaw: f32,
+    /// Elevation above the XZ plane in radians.
+    pub pitch: f32,
+    /// Radians of rotation per pixel of cursor movement.
+    pub rotate_speed: f32,
+    /// Radians of rotation per second with the `look_*` actions fully triggered.
+    pub look_speed: f32,
+    /// Fraction of the distance zoomed per scroll line.
+    pub zoom_speed: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    /// How strongly the `look_*` actions are triggered, towards the right and up.
+    look_rate: (f32, f32),
+}
+
+impl OrbitController {
+    /// Creates a controller orbiting `camera.target` from the current camera position.
+    pub fn from_camera(camera: &Camera) -> Self {
+        let offset = camera.eye - camera.target;
+        let distance = offset.length().max(0.1);
+        let mut controller = Self::new(camera.target, distance);
+        controller.yaw = offset.x.atan2(offset.z);
+        controller.pitch = (offset.y / distance).clamp(-1.0, 1.0).asin();
+        controller
+    }
+
+    pub fn new(target: Vec3, distance: f32) -> Self {
+        Self {
+            target,
+            distance,
+            yaw: 0.0,
+            pitch: 0.0,
+            rotate_speed: 0.005,
+            look_speed: 2.0,
+            zoom_speed: 0.1,
+            min_distance: 0.1,
+            max_distance: 100.0,
+            look_rate: (0.0, 0.0),
+        }
+    }
+
+    /// Rotates while the `orbit` action is held and zooms with the scroll wheel.
+    pub fn process_input(&mut self, input: &Input) {
+        if input.action_pressed("orbit") {
+            let (dx, dy) = input.cursor_delta();
+            self.rotate(dx as f32, dy as f32);
+        }
+        self.zoom(input.scroll_lines());
+        self.look_rate = (
+            input.action_value("look_right") - input.action_value("look_left"),
+            input.action_value("look_up") - input.action_value("look_down"),
+        );
+    }
+
+    /// Rotates by a cursor movement in pixels.
+    pub fn rotate(&mut self, dx: f32, dy: f32) {
+        let limit = std::f32::consts::FRAC_PI_2 - 0.01;
+        self.yaw -= dx * self.rotate_speed;
+        self.pitch = (self.pitch + dy * self.rotate_speed).clamp(-limit, limit);
+    }
+
+    /// Zooms in for positive `lines` and out for negative ones.
+    pub fn zoom(&mut self, lines: f32) {
+        self.distance = (self.distance * (1.0 - lines * self.zoom_speed))
+            .clamp(self.min_distance, self.max_distance);
+    }
+
+    /// Advances the rotation from the `look_*` actions by `dt` seconds and writes the result into
+    /// `camera`.
+    pub fn update_camera(&mut self, camera: &mut Camera, dt: f32) {
+        // Tilting the stick turns the view like dragging the cursor in that direction.
+        let (rate_x, rate_y) = self.look_rate;
+        let pixels = self.look_speed * dt / self.rotate_speed;
+        self.rotate(rate_x * pixels, -rate_y * pixels);
+
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
+        let offset = Vec3::new(sin_yaw * cos_pitch, sin_pitch, cos_yaw * cos_pitch);
+
+        camera.target = self.target;
+        camera.eye = self.target + offset * self.distance;
+        camera.up = Vec3::Y;
+    }
+}
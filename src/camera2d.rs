@@ -0,0 +1,163 @@
+//! An orthographic camera for sprites and tile maps, measured in world pixels with Y down.
+
+use glam::{Mat4, Vec2};
+
+use crate::input::Input;
+
+/// Shows the world pixels from `position` at the top left of the screen, `zoom` screen pixels
+/// per world pixel. The default shows the world one to one, the same as
+/// [`crate::sprite::pixel_projection`].
+#[derive(Clone, Debug)]
+pub struct Camera2D {
+    /// The world point at the top left of the screen.
+    pub position: Vec2,
+    /// Screen pixels per world pixel.
+    pub zoom: f32,
+    /// The zoom [`Camera2D::update`] eases towards.
+    pub target_zoom: f32,
+    /// The screen point kept still while easing the zoom.
+    zoom_anchor: Vec2,
+    /// How quickly the zoom approaches its target, 0 jumping there at once.
+    pub smoothing: f32,
+    /// Factor the zoom changes by per scroll line.
+    pub zoom_speed: f32,
+    pub min_zoom: f32,
+    pub max_zoom: f32,
+    /// Scales by whole numbers only and snaps to world pixels, so that low resolution art stays
+    /// crisp with every texel covering the same number of screen pixels.
+    pub pixel_perfect: bool,
+}
+
+impl Default for Camera2D {
+    fn default() -> Self {
+        Self {
+            position: Vec2::ZERO,
+            zoom: 1.0,
+            target_zoom: 1.0,
+            zoom_anchor: Vec2::ZERO,
+            smoothing: 12.0,
+            zoom_speed: 1.1,
+            min_zoom: 0.1,
+            max_zoom: 32.0,
+            pixel_perfect: false,
+        }
+    }
+}
+
+impl Camera2D {
+    pub fn new(position: Vec2, zoom: f32) -> Self {
+        Self {
+            position,
+            zoom,
+            target_zoom: zoom,
+            ..Default::default()
+        }
+    }
+
+    /// The zoom drawn with, a whole number of at least 1 in pixel perfect mode.
+    pub fn effective_zoom(&self) -> f32 {
+        if self.pixel_perfect {
+            self.zoom.round().max(1.0)
+        } else {
+            self.zoom
+        }
+    }
+
+    /// The world point at the top left of the screen, snapped to world pixels in pixel perfect
+    /// mode.
+    pub fn effective_position(&self) -> Vec2 {
+        if self.pixel_perfect {
+            self.position.round()
+        } else {
+            self.position
+        }
+    }
+
+    /// Maps world pixels to clip space on a `width` by `height` target.
+    pub fn view_projection(&self, width: f32, height: f32) -> Mat4 {
+        let zoom = self.effective_zoom();
+        let Vec2 { x: left, y: top } = self.effective_position();
+        Mat4::orthographic_rh(
+            left,
+            left + width / zoom,
+            top + height / zoom,
+            top,
+            -1.0,
+            1.0,
+        )
+    }
+
+    /// The world point under the screen point `screen`, in physical pixels.
+    pub fn screen_to_world(&self, screen: Vec2) -> Vec2 {
+        self.effective_position() + screen / self.effective_zoom()
+    }
+
+    pub fn world_to_screen(&self, world: Vec2) -> Vec2 {
+        (world - self.effective_position()) * self.effective_zoom()
+    }
+
+    /// Moves the view by a cursor movement in screen pixels, so that what's under the cursor
+    /// follows it.
+    pub fn pan(&mut self, screen_delta: Vec2) {
+        self.position -= screen_delta / self.effective_zoom();
+    }
+
+    /// Eases the zoom towards its target times `factor`, keeping the world point under the screen
+    /// point `anchor` in place.
+    pub fn zoom_at(&mut self, anchor: Vec2, factor: f32) {
+        self.target_zoom = if self.pixel_perfect {
+            // Steps through the whole numbers, which rounding a small factor would never leave.
+            let zoom = self.target_zoom.round() + factor.ln().signum();
+            zoom.clamp(
+                self.min_zoom.ceil().max(1.0),
+                self.max_zoom.floor().max(1.0),
+            )
+        } else {
+            (self.target_zoom * factor).clamp(self.min_zoom, self.max_zoom)
+        };
+        self.zoom_anchor = anchor;
+        if self.smoothing <= 0.0 {
+            self.set_zoom_at(anchor, self.target_zoom);
+        }
+    }
+
+    /// Sets the zoom at once, keeping the world point under the screen point `anchor` in place.
+    pub fn set_zoom_at(&mut self, anchor: Vec2, zoom: f32) {
+        let world = self.position + anchor / self.zoom;
+        self.zoom = zoom;
+        self.position = world - anchor / zoom;
+    }
+
+    /// Pans while the `pan` action is held and zooms towards the cursor with the scroll wheel.
+    pub fn process_input(&mut self, input: &Input) {
+        if input.action_pressed("pan") {
+            let (dx, dy) = input.cursor_delta();
+            self.pan(Vec2::new(dx as f32, dy as f32));
+        }
+        let lines = input.scroll_lines();
+        if lines != 0.0 {
+            let cursor = input
+                .cursor_position()
+                .map_or(self.zoom_anchor, |(x, y)| Vec2::new(x as f32, y as f32));
+            self.zoom_at(cursor, self.zoom_speed.powf(lines));
+        }
+    }
+
+    /// Eases the zoom towards its target by `dt` seconds.
+    pub fn update(&mut self, dt: f32) {
+        if self.zoom == self.target_zoom {
+            return;
+        }
+        // In log space, so that zooming in and out take as long.
+        let t = if self.smoothing > 0.0 {
+            1.0 - (-self.smoothing * dt).exp()
+        } else {
+            1.0
+        };
+        let mut zoom = (self.zoom.ln() + (self.target_zoom.ln() - self.zoom.ln()) * t).exp();
+        if (zoom / self.target_zoom - 1.0).abs() < 1e-3 {
+            zoom = self.target_zoom;
+        }
+        self.set_zoom_at(self.zoom_anchor, zoom);
+    }
+}
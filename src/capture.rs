@@ -0,0 +1,116 @@
+use anyhow::Context;
+
+/// Copies a texture into a mappable buffer so that its contents can be read back on the CPU.
+pub struct TextureReadback {
+    buffer: wgpu::Buffer,
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+    /// Rows in the buffer are padded to [`wgpu::COPY_BYTES_PER_ROW_ALIGNMENT`].
+    padded_bytes_per_row: u32,
+}
+
+impl TextureReadback {
+    /// Records a copy of the first mip level of `texture`, which needs
+    /// [`wgpu::TextureUsages::COPY_SRC`] and an 8-bit RGBA or BGRA format.
+    pub fn copy(
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+    ) -> anyhow::Result<Self> {
+        use wgpu::TextureFormat as F;
+
+        let format = texture.format();
+        anyhow::ensure!(
+            matches!(
+                format,
+                F::Rgba8Unorm | F::Rgba8UnormSrgb | F::Bgra8Unorm | F::Bgra8UnormSrgb
+            ),
+            "reading back {format:?} textures is not supported"
+        );
+        anyhow::ensure!(
+            texture.usage().contains(wgpu::TextureUsages::COPY_SRC),
+            "the texture can't be copied from"
+        );
+
+        let (width, height) = (texture.width(), texture.height());
+        let padded_bytes_per_row = (width * 4).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("readback"),
+            size: u64::from(padded_bytes_per_row) * u64::from(height),
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: None,
+                },
+            },
+            texture.size(),
+        );
+
+        Ok(Self {
+            buffer,
+            format,
+            width,
+            height,
+            padded_bytes_per_row,
+        })
+    }
+
+    /// Waits for the copy to finish and returns the texture as an RGBA image. The encoder the
+    /// copy was recorded into must have been submitted.
+    pub fn read(self, device: &wgpu::Device) -> anyhow::Result<image::RgbaImage> {
+        let slice = self.buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        // Waiting only works on native, in the browser the mapping completes asynchronously.
+        device.poll(wgpu::Maintain::Wait);
+        receiver
+            .try_recv()
+            .context("mapping the readback buffer didn't complete")?
+            .context("failed to map the readback buffer")?;
+
+        let row_bytes = self.width as usize * 4;
+        let mut pixels = Vec::with_capacity(row_bytes * self.height as usize);
+        for row in slice
+            .get_mapped_range()
+            .chunks_exact(self.padded_bytes_per_row as usize)
+        {
+            pixels.extend_from_slice(&row[..row_bytes]);
+        }
+        self.buffer.unmap();
+
+        if matches!(
+            self.format,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+        ) {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        image::RgbaImage::from_raw(self.width, self.height, pixels)
+            .context("readback has the wrong size")
+    }
+}
+
+/// A path next to the executable named after the current time, e.g. `screenshot-1700000000123.png`.
+pub fn timestamped_path(prefix: &str, extension: &str) -> anyhow::Result<std::path::PathBuf> {
+    let executable = std::env::current_exe().context("failed to locate the executable")?;
+    let directory = executable
+        .parent()
+        .context("the executable has no parent directory")?;
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    Ok(directory.join(format!("{prefix}-{timestamp}.{extension}")))
+}
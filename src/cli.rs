@@ -0,0 +1,97 @@
+use std::path::PathBuf;
+
+use crate::config;
+use crate::headless;
+use crate::renderer::RendererOptions;
+
+/// Renders a grid of cubes, or the given model, with wgpu.
+#[derive(Debug, clap::Parser)]
+#[command(version)]
+pub struct Args {
+    /// An OBJ or glTF model to display instead of the default cubes.
+    pub mesh: Option<PathBuf>,
+
+    /// The graphics API to render with.
+    #[arg(long, value_enum)]
+    pub backend: Option<Backend>,
+
+    /// The adapter to render with, as its index among the compatible adapters or part of its
+    /// name.
+    #[arg(long)]
+    pub adapter: Option<String>,
+
+    /// How frames are presented, the default is vsync.
+    #[arg(long, value_enum)]
+    pub present_mode: Option<PresentMode>,
+
+    /// The configuration file, reloaded when it changes.
+    #[arg(long, default_value = config::DEFAULT_PATH)]
+    pub config: PathBuf,
+
+    /// The size of the window, overriding the configuration, or of the frames when rendering
+    /// headless.
+    #[arg(long, value_name = "WIDTHxHEIGHT", value_parser = headless::parse_size)]
+    pub size: Option<(u32, u32)>,
+
+    /// Renders frames to PNGs without opening a window.
+    #[arg(long)]
+    pub headless: bool,
+
+    /// The number of frames to render headless.
+    #[arg(long, default_value_t = 1, requires = "headless")]
+    pub frames: u32,
+
+    /// The directory headless frames are written to.
+    #[arg(long, default_value = "frames", requires = "headless")]
+    pub out: PathBuf,
+
+    /// Writes a neutral `.cube` LUT to grade screenshots against, and exits.
+    #[arg(long, value_name = "PATH")]
+    pub neutral_lut: Option<PathBuf>,
+}
+
+impl Args {
+    pub fn renderer_options(&self) -> RendererOptions {
+        RendererOptions {
+            backends: self.backend.map(Backend::to_wgpu),
+            adapter: self.adapter.clone(),
+            present_mode: self.present_mode.map(PresentMode::to_wgpu),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum Backend {
+    Vulkan,
+    Dx12,
+    Metal,
+    Gl,
+}
+
+impl Backend {
+    pub fn to_wgpu(self) -> wgpu::Backends {
+        match self {
+            Self::Vulkan => wgpu::Backends::VULKAN,
+            Self::Dx12 => wgpu::Backends::DX12,
+            Self::Metal => wgpu::Backends::METAL,
+            Self::Gl => wgpu::Backends::GL,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum PresentMode {
+    Fifo,
+    Mailbox,
+    Immediate,
+}
+
+impl PresentMode {
+    pub fn to_wgpu(self) -> wgpu::PresentMode {
+        match self {
+            Self::Fifo => wgpu::PresentMode::Fifo,
+            Self::Mailbox => wgpu::PresentMode::Mailbox,
+            Self::Immediate => wgpu::PresentMode::Immediate,
+        }
+    }
+}
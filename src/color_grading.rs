@@ -0,0 +1,259 @@
+use std::sync::Arc;
+
+use wgpu::util::DeviceExt;
+
+use crate::post_process::{self, Effect, EffectContext, EffectPipelineKey};
+use crate::shader::{self, Preprocessor};
+use crate::texture::Texture;
+
+/// The format of LUT textures, filterable everywhere and precise enough for subtle grades.
+pub const LUT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// The size of the LUTs written for artists to grade, the most common one in grading tools.
+pub const NEUTRAL_LUT_SIZE: u32 = 33;
+
+/// A 3D lookup table on the CPU, mapping sRGB-encoded colors to sRGB-encoded colors. See
+/// [`crate::assets::lut`] to read and write it as a `.cube` file.
+#[derive(Clone, Debug)]
+pub struct LutData {
+    /// The number of entries along each axis.
+    pub size: u32,
+    /// The input colors mapped to the first and last entries.
+    pub domain_min: [f32; 3],
+    pub domain_max: [f32; 3],
+    /// `size³` output colors, red varying fastest and blue slowest.
+    pub texels: Vec<[f32; 3]>,
+}
+
+impl LutData {
+    /// A LUT mapping every color to itself, the starting point to grade screenshots against.
+    pub fn neutral(size: u32) -> Self {
+        let scale = 1.0 / (size - 1) as f32;
+        let texels = (0..size)
+            .flat_map(|b| (0..size).flat_map(move |g| (0..size).map(move |r| [r, g, b])))
+            .map(|texel| texel.map(|channel| channel as f32 * scale))
+            .collect();
+        Self {
+            size,
+            domain_min: [0.0; 3],
+            domain_max: [1.0; 3],
+            texels,
+        }
+    }
+}
+
+/// `ColorGrading` in `color_grading.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ColorGradingUniform {
+    domain_min: [f32; 3],
+    strength: f32,
+    domain_max: [f32; 3],
+    _padding: f32,
+}
+
+/// The [`Effect`] grading the tonemapped image through a 3D LUT, meant to run right after
+/// [`Tonemap`](crate::tonemap::Tonemap).
+pub struct ColorGrading {
+    /// How much of the graded color replaces the original one, from 0 to 1.
+    pub strength: f32,
+    domain_min: [f32; 3],
+    domain_max: [f32; 3],
+    lut: wgpu::TextureView,
+    lut_sampler: wgpu::Sampler,
+    buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline_layout: Arc<wgpu::PipelineLayout>,
+    shader_module: Arc<wgpu::ShaderModule>,
+}
+
+impl ColorGrading {
+    /// Compiles `color_grading.wgsl` with `preprocessor`, grading through a neutral LUT until
+    /// [`set_lut`](Self::set_lut) is called.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        preprocessor: &Preprocessor,
+    ) -> anyhow::Result<Self> {
+        let lut = LutData::neutral(2);
+        let strength = 1.0;
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("color grading"),
+            contents: bytemuck::bytes_of(&uniform(&lut, strength)),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let lut_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("color grading lut"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let [input, input_sampler] = post_process::input_layout_entries();
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("color grading"),
+            entries: &[
+                input,
+                input_sampler,
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D3,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = Arc::new(device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("color grading"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            },
+        ));
+        let shader_module = Arc::new(shader::create_module(
+            device,
+            preprocessor,
+            "color_grading.wgsl",
+        )?);
+
+        Ok(Self {
+            strength,
+            domain_min: lut.domain_min,
+            domain_max: lut.domain_max,
+            lut: upload_lut(device, queue, &lut),
+            lut_sampler,
+            buffer,
+            bind_group_layout,
+            pipeline_layout,
+            shader_module,
+        })
+    }
+
+    /// Grades through `lut` from now on.
+    pub fn set_lut(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, lut: &LutData) {
+        self.domain_min = lut.domain_min;
+        self.domain_max = lut.domain_max;
+        self.lut = upload_lut(device, queue, lut);
+    }
+}
+
+impl Effect for ColorGrading {
+    fn name(&self) -> &'static str {
+        "color grading"
+    }
+
+    fn update(&mut self, queue: &wgpu::Queue) {
+        let uniform = ColorGradingUniform {
+            domain_min: self.domain_min,
+            strength: self.strength.clamp(0.0, 1.0),
+            domain_max: self.domain_max,
+            _padding: 0.0,
+        };
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&uniform));
+    }
+
+    fn render(
+        &mut self,
+        context: &mut EffectContext,
+        input: &Texture,
+        output: &wgpu::TextureView,
+        format: wgpu::TextureFormat,
+    ) {
+        let key = EffectPipelineKey {
+            effect: self.name(),
+            format,
+        };
+        let pipeline = context.pipelines.get(
+            &key,
+            &*post_process::pipeline_builder(
+                "color grading",
+                self.pipeline_layout.clone(),
+                self.shader_module.clone(),
+                format,
+            ),
+        );
+        let [input, input_sampler] = post_process::input_bind_group_entries(input);
+        let bind_group = context
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("color grading"),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    input,
+                    input_sampler,
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(&self.lut),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: wgpu::BindingResource::Sampler(&self.lut_sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: self.buffer.as_entire_binding(),
+                    },
+                ],
+            });
+        context.draw(self.name(), output, &pipeline, &bind_group);
+    }
+}
+
+fn uniform(lut: &LutData, strength: f32) -> ColorGradingUniform {
+    ColorGradingUniform {
+        domain_min: lut.domain_min,
+        strength,
+        domain_max: lut.domain_max,
+        _padding: 0.0,
+    }
+}
+
+/// Uploads `lut` as a half-float 3D texture, red along X, green along Y and blue along Z.
+fn upload_lut(device: &wgpu::Device, queue: &wgpu::Queue, lut: &LutData) -> wgpu::TextureView {
+    let texels: Vec<u16> = lut
+        .texels
+        .iter()
+        .flat_map(|&[r, g, b]| [r, g, b, 1.0])
+        .map(|channel| half::f16::from_f32(channel).to_bits())
+        .collect();
+    let size = wgpu::Extent3d {
+        width: lut.size,
+        height: lut.size,
+        depth_or_array_layers: lut.size,
+    };
+    let texture = device.create_texture_with_data(
+        queue,
+        &wgpu::TextureDescriptor {
+            label: Some("color grading lut"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D3,
+            format: LUT_FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        },
+        wgpu::util::TextureDataOrder::LayerMajor,
+        bytemuck::cast_slice(&texels),
+    );
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
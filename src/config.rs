@@ -0,0 +1,167 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::fullscreen::WindowMode;
+use crate::input::Button;
+use crate::tonemap::Tonemapper;
+use anyhow::Context;
+use serde::Deserialize;
+
+/// The configuration file looked for in the working directory.
+pub const DEFAULT_PATH: &str = "hello-wgpu.toml";
+
+/// Settings read from a TOML file, every one of them optional:
+///
+/// ```toml
+/// [window]
+/// title = "Hello, wgpu!"
+/// width = 1280
+/// height = 720
+/// mode = "borderless"
+/// monitor = 1
+///
+/// [renderer]
+/// clear_color = [0.1, 0.2, 0.3]
+/// vsync = false
+/// msaa = 8
+/// fxaa = true
+/// taa = true
+/// ssao = true
+/// skybox = "sky.hdr"
+/// lut = "grade.cube"
+/// tonemapper = "agx"
+/// exposure = 0.5
+///
+/// [controls]
+/// screenshot = ["KeyP"]
+/// orbit = ["Left", "Right"]
+/// move_up = ["Space", "GamepadSouth"]
+///
+/// [gamepad]
+/// deadzone = 0.2
+/// ```
+///
+/// The controls rebind the actions of [`ActionMap`](crate::input::ActionMap), see [`Button`]
+/// for how keys and mouse buttons are named.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub window: WindowConfig,
+    pub renderer: RendererConfig,
+    pub controls: BTreeMap<String, Vec<Button>>,
+    pub gamepad: GamepadConfig,
+}
+
+impl Config {
+    /// Reads the configuration at `path`, or returns the default one if there is no such file.
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let source = match std::fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => {
+                return Err(err).with_context(|| format!("failed to read {}", path.display()))
+            }
+        };
+        toml::from_str(&source)
+            .with_context(|| format!("invalid configuration in {}", path.display()))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowConfig {
+    pub title: String,
+    /// The inner size of the window, used when both `width` and `height` are set.
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub resizable: bool,
+    pub maximized: bool,
+    /// Windowed, `"borderless"` or `"exclusive"` fullscreen.
+    pub mode: WindowMode,
+    /// The index of the monitor to go fullscreen on, the window's current one without it.
+    pub monitor: Option<usize>,
+}
+
+impl WindowConfig {
+    pub fn size(&self) -> Option<winit::dpi::PhysicalSize<u32>> {
+        Some(winit::dpi::PhysicalSize::new(self.width?, self.height?))
+    }
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        Self {
+            title: "Hello, wgpu!".to_owned(),
+            width: None,
+            height: None,
+            resizable: true,
+            maximized: false,
+            mode: WindowMode::Windowed,
+            monitor: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RendererConfig {
+    /// Linear RGB.
+    pub clear_color: [f64; 3],
+    pub vsync: bool,
+    /// The MSAA sample count, ignored if the surface doesn't support it.
+    pub msaa: u32,
+    /// Whether FXAA smooths the edges of the tonemapped image.
+    pub fxaa: bool,
+    /// Whether TAA accumulates jittered frames before tonemapping.
+    pub taa: bool,
+    /// Whether screen-space ambient occlusion darkens the ambient light in creases.
+    pub ssao: bool,
+    pub wireframe: bool,
+    /// Whether the directional light casts shadows.
+    pub shadows: bool,
+    /// An equirectangular panorama, or a directory of six cube faces, drawn behind the scene
+    /// instead of the default sky. See [`crate::assets::environment::load`].
+    pub skybox: Option<PathBuf>,
+    /// A `.cube` LUT grading the tonemapped image, see `--neutral-lut` for one to start from.
+    pub lut: Option<PathBuf>,
+    /// `"aces"`, `"reinhard"` or `"agx"`.
+    pub tonemapper: Tonemapper,
+    /// In stops, brightening the scene before tonemapping when positive.
+    pub exposure: f32,
+    /// The vertical field of view in degrees.
+    pub fov: f32,
+}
+
+impl Default for RendererConfig {
+    fn default() -> Self {
+        Self {
+            clear_color: [0.0; 3],
+            vsync: true,
+            msaa: 4,
+            fxaa: false,
+            taa: false,
+            ssao: false,
+            wireframe: false,
+            shadows: true,
+            skybox: None,
+            lut: None,
+            tonemapper: Tonemapper::default(),
+            exposure: 0.0,
+            fov: 45.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GamepadConfig {
+    /// How far from the center stick positions read as centered, from 0 to 1.
+    pub deadzone: f32,
+}
+
+impl Default for GamepadConfig {
+    fn default() -> Self {
+        Self { deadzone: 0.15 }
+    }
+}
@@ -0,0 +1,399 @@
+use std::sync::{Arc, OnceLock};
+
+use glam::Vec3;
+
+use crate::camera::Camera;
+use crate::particles::{ParticleBlend, ParticleEmitter};
+use crate::pipeline_cache::PipelineBuilder;
+use crate::shader::{self, Preprocessor};
+use crate::taa::MOTION_FORMAT;
+use crate::texture::Texture;
+use crate::upload::Upload;
+use crate::vertex::{VertexLayout, VertexType};
+
+/// The corners of the two triangles of a quad.
+const CORNERS: [[f32; 2]; 6] = [
+    [-1.0, -1.0],
+    [1.0, -1.0],
+    [1.0, 1.0],
+    [-1.0, -1.0],
+    [1.0, 1.0],
+    [-1.0, 1.0],
+];
+
+/// Values that can be blended linearly, for [`Curve`].
+pub trait Lerp: Copy {
+    fn lerp(self, other: Self, t: f32) -> Self;
+}
+
+impl Lerp for f32 {
+    fn lerp(self, other: Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl<const N: usize> Lerp for [f32; N] {
+    fn lerp(self, other: Self, t: f32) -> Self {
+        std::array::from_fn(|index| self[index].lerp(other[index], t))
+    }
+}
+
+/// A value over a particle's life, linearly interpolated between keys at ages from 0 (just
+/// emitted) to 1 (about to die).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Curve<T> {
+    /// Sorted by age.
+    keys: Vec<(f32, T)>,
+}
+
+impl<T: Lerp> Curve<T> {
+    /// `value` at every age.
+    pub fn constant(value: T) -> Self {
+        Self {
+            keys: vec![(0.0, value)],
+        }
+    }
+
+    /// From `start` at birth to `end` at death.
+    pub fn linear(start: T, end: T) -> Self {
+        Self {
+            keys: vec![(0.0, start), (1.0, end)],
+        }
+    }
+
+    /// Adds a key at `age`, replacing any already there.
+    pub fn with_key(mut self, age: f32, value: T) -> Self {
+        let age = age.clamp(0.0, 1.0);
+        let index = self.keys.partition_point(|&(key, _)| key < age);
+        match self.keys.get_mut(index) {
+            Some(key) if key.0 == age => key.1 = value,
+            _ => self.keys.insert(index, (age, value)),
+        }
+        self
+    }
+
+    pub fn keys(&self) -> &[(f32, T)] {
+        &self.keys
+    }
+
+    pub fn keys_mut(&mut self) -> &mut [(f32, T)] {
+        &mut self.keys
+    }
+
+    /// The value at `age`, holding the first and last keys before and after them.
+    pub fn sample(&self, age: f32) -> T {
+        let index = self.keys.partition_point(|&(key, _)| key <= age);
+        match (
+            index.checked_sub(1).map(|index| self.keys[index]),
+            self.keys.get(index),
+        ) {
+            (Some((start, a)), Some(&(end, b))) => a.lerp(b, (age - start) / (end - start)),
+            (Some((_, value)), None) | (None, Some(&(_, value))) => value,
+            (None, None) => panic!("a curve always has a key"),
+        }
+    }
+}
+
+/// A corner of a particle's quad.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ParticleVertex {
+    pub position: [f32; 3],
+    /// From -1 to 1 across the quad.
+    pub corner: [f32; 2],
+    /// Linear RGBA.
+    pub color: [f32; 4],
+}
+
+impl VertexType for ParticleVertex {
+    fn vertex_layout() -> &'static VertexLayout {
+        static LAYOUT: OnceLock<VertexLayout> = OnceLock::new();
+        LAYOUT.get_or_init(|| {
+            VertexLayout::new(wgpu::VertexStepMode::Vertex, 0)
+                .with("position", wgpu::VertexFormat::Float32x3)
+                .with("corner", wgpu::VertexFormat::Float32x2)
+                .with("color", wgpu::VertexFormat::Float32x4)
+        })
+    }
+}
+
+/// Everything that distinguishes one CPU particle pipeline from another.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CpuParticlePipelineKey {
+    pub format: wgpu::TextureFormat,
+    pub sample_count: u32,
+    pub blend: ParticleBlend,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Particle {
+    position: Vec3,
+    velocity: Vec3,
+    age: f32,
+    lifetime: f32,
+}
+
+/// Particles simulated on the CPU and expanded into camera-facing quads in a vertex buffer
+/// every frame. Simpler than [`GpuParticles`] and available everywhere, WebGL included, but
+/// meant for hundreds or thousands of particles rather than tens of thousands. Alpha-blended
+/// particles are sorted back to front.
+///
+/// [`GpuParticles`]: crate::particles::GpuParticles
+pub struct CpuParticles {
+    enabled: bool,
+    /// Where and how particles are emitted. Its colors are ignored in favor of
+    /// `color_over_life`.
+    pub emitter: ParticleEmitter,
+    /// Linear RGBA over each particle's life.
+    pub color_over_life: Curve<[f32; 4]>,
+    /// Scales the emitter's size over each particle's life.
+    pub size_over_life: Curve<f32>,
+    pub blend: ParticleBlend,
+    /// Particles beyond it aren't emitted until others die.
+    pub max_particles: usize,
+    particles: Vec<Particle>,
+    emit_remainder: f32,
+    random: u32,
+    vertices: Vec<ParticleVertex>,
+    buffer: Option<wgpu::Buffer>,
+    /// The vertices uploaded for the frame being rendered.
+    vertex_count: u32,
+    pipeline_layout: Arc<wgpu::PipelineLayout>,
+    shader_module: Arc<wgpu::ShaderModule>,
+}
+
+impl CpuParticles {
+    /// Compiles `cpu_particles.wgsl` with `preprocessor`, reading the camera from a bind group
+    /// with `camera_layout`.
+    pub fn new(
+        device: &wgpu::Device,
+        preprocessor: &Preprocessor,
+        camera_layout: &wgpu::BindGroupLayout,
+    ) -> anyhow::Result<Self> {
+        let pipeline_layout = Arc::new(device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("cpu particles"),
+                bind_group_layouts: &[camera_layout],
+                push_constant_ranges: &[],
+            },
+        ));
+        let shader_module = Arc::new(shader::create_module(
+            device,
+            preprocessor,
+            "cpu_particles.wgsl",
+        )?);
+
+        let emitter = ParticleEmitter {
+            rate: 200.0,
+            size: 0.15,
+            ..Default::default()
+        };
+        Ok(Self {
+            enabled: false,
+            color_over_life: Curve::linear(emitter.start_color, emitter.end_color),
+            size_over_life: Curve::linear(1.0, 0.5),
+            emitter,
+            blend: ParticleBlend::default(),
+            max_particles: 4096,
+            particles: Vec::new(),
+            emit_remainder: 0.0,
+            random: 0x9e37_79b9,
+            vertices: Vec::new(),
+            buffer: None,
+            vertex_count: 0,
+            pipeline_layout,
+            shader_module,
+        })
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Disabling kills every particle.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.particles.clear();
+            self.emit_remainder = 0.0;
+        }
+    }
+
+    /// The particles alive.
+    pub fn len(&self) -> usize {
+        self.particles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.particles.is_empty()
+    }
+
+    /// Emits and moves the particles over `delta_time` seconds, and expands them into quads
+    /// facing `camera` for [`CpuParticles::upload`].
+    pub fn update(&mut self, delta_time: f32, camera: &Camera) {
+        self.particles.retain_mut(|particle| {
+            particle.velocity += self.emitter.gravity * delta_time;
+            particle.position += particle.velocity * delta_time;
+            particle.age += delta_time;
+            particle.age < particle.lifetime
+        });
+
+        let emitted = self.emitter.rate.max(0.0) * delta_time + self.emit_remainder;
+        self.emit_remainder = emitted.fract();
+        let room = self.max_particles.saturating_sub(self.particles.len());
+        for _ in 0..(emitted as usize).min(room) {
+            let particle = self.emit();
+            self.particles.push(particle);
+        }
+
+        let view = camera.view_matrix();
+        if self.blend == ParticleBlend::Alpha {
+            // Furthest first, the view looking down negative Z.
+            self.particles.sort_by(|a, b| {
+                let depth = |particle: &Particle| view.transform_point3(particle.position).z;
+                depth(a).total_cmp(&depth(b))
+            });
+        }
+        let right = view.row(0).truncate();
+        let up = view.row(1).truncate();
+        self.vertices.clear();
+        for particle in &self.particles {
+            let life = particle.age / particle.lifetime;
+            let color = self.color_over_life.sample(life);
+            let half_size = self.emitter.size * self.size_over_life.sample(life) * 0.5;
+            self.vertices.extend(CORNERS.map(|corner| {
+                ParticleVertex {
+                    position: (particle.position
+                        + (right * corner[0] + up * corner[1]) * half_size)
+                        .to_array(),
+                    corner,
+                    color,
+                }
+            }));
+        }
+    }
+
+    /// A particle at the emitter, like those `particles.wgsl` emits.
+    fn emit(&mut self) -> Particle {
+        let emitter = self.emitter;
+        let cos_theta = 1.0f32.lerp(emitter.spread.cos(), self.random());
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = self.random() * std::f32::consts::TAU;
+        let axis = emitter.direction.try_normalize().unwrap_or(Vec3::Y);
+        let (tangent, bitangent) = axis.any_orthonormal_pair();
+        let direction =
+            axis * cos_theta + (tangent * phi.cos() + bitangent * phi.sin()) * sin_theta;
+        Particle {
+            position: emitter.position,
+            velocity: direction * emitter.speed * 0.8f32.lerp(1.0, self.random()),
+            age: 0.0,
+            lifetime: emitter.lifetime * 0.75f32.lerp(1.0, self.random()),
+        }
+    }
+
+    /// A uniform random number in `0..1` from a xorshift generator.
+    fn random(&mut self) -> f32 {
+        self.random ^= self.random << 13;
+        self.random ^= self.random >> 17;
+        self.random ^= self.random << 5;
+        (self.random >> 8) as f32 / (1 << 24) as f32
+    }
+
+    /// Uploads the quads of the last [`CpuParticles::update`] for [`CpuParticles::draw`],
+    /// growing the vertex buffer if it is too small.
+    pub fn upload(&mut self, upload: &mut Upload) {
+        self.vertex_count = self.vertices.len() as u32;
+        if self.vertices.is_empty() {
+            return;
+        }
+        let size = std::mem::size_of_val(self.vertices.as_slice()) as wgpu::BufferAddress;
+        if self
+            .buffer
+            .as_ref()
+            .is_none_or(|buffer| buffer.size() < size)
+        {
+            self.buffer = Some(upload.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("cpu particles"),
+                size: size.next_power_of_two(),
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }));
+        }
+        if let Some(buffer) = &self.buffer {
+            upload.write(buffer, 0, bytemuck::cast_slice(&self.vertices));
+        }
+    }
+
+    pub fn pipeline_builder(&self, key: CpuParticlePipelineKey) -> Arc<PipelineBuilder> {
+        let layout = self.pipeline_layout.clone();
+        let shader_module = self.shader_module.clone();
+        Arc::new(move |device, cache| create_pipeline(device, &layout, &shader_module, key, cache))
+    }
+
+    /// Draws the quads uploaded last, with the camera's bind group at group 0.
+    pub fn draw(
+        &self,
+        render_pass: &mut wgpu::RenderPass,
+        pipeline: &wgpu::RenderPipeline,
+        camera_bind_group: &wgpu::BindGroup,
+    ) {
+        let Some(buffer) = self.buffer.as_ref().filter(|_| self.vertex_count > 0) else {
+            return;
+        };
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, buffer.slice(..));
+        render_pass.draw(0..self.vertex_count, 0..1);
+    }
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader_module: &wgpu::ShaderModule,
+    key: CpuParticlePipelineKey,
+    cache: Option<&wgpu::PipelineCache>,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("cpu particles"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader_module,
+            entry_point: "vs_main",
+            buffers: &[ParticleVertex::vertex_layout().buffer_layout()],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        primitive: wgpu::PrimitiveState::default(),
+        // Tested against the scene, but not hiding each other.
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: Texture::DEPTH_FORMAT,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::LessEqual,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: key.sample_count,
+            ..Default::default()
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader_module,
+            entry_point: "fs_main",
+            targets: &[
+                Some(wgpu::ColorTargetState {
+                    format: key.format,
+                    blend: Some(key.blend.state()),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }),
+                Some(wgpu::ColorTargetState {
+                    format: MOTION_FORMAT,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::empty(),
+                }),
+            ],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        }),
+        multiview: None,
+        cache,
+    })
+}
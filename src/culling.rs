@@ -0,0 +1,400 @@
+use glam::Mat4;
+
+use crate::instance::InstanceRaw;
+use crate::math::{Bounds, Frustum};
+use crate::mesh::Mesh;
+use crate::profiler::GpuProfiler;
+use crate::shader::{self, Preprocessor};
+use crate::uniform::UniformBuffer;
+use crate::upload::Upload;
+
+/// The invocations per workgroup of `culling.wgsl`.
+const WORKGROUP_SIZE: u32 = 64;
+
+/// How many instances were drawn and skipped in the last frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CullingStats {
+    pub drawn: u32,
+    /// Outside the frustum.
+    pub culled: u32,
+    /// Inside the frustum but hidden behind other instances, see [`OcclusionCulling`].
+    ///
+    /// [`OcclusionCulling`]: crate::occlusion::OcclusionCulling
+    pub occluded: u32,
+}
+
+/// Skips the instances whose bounds lie outside the camera's frustum, by copying the others
+/// into a buffer of their own every frame, which the camera's passes draw instead of every
+/// instance.
+#[derive(Debug)]
+pub struct FrustumCulling {
+    enabled: bool,
+    /// The index of each instance inside the frustum this frame.
+    in_frustum: Vec<u32>,
+    /// The index of each instance drawn this frame.
+    drawn: Vec<u32>,
+    visible: Vec<InstanceRaw>,
+    buffer: Option<wgpu::Buffer>,
+    stats: CullingStats,
+}
+
+impl Default for FrustumCulling {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            in_frustum: Vec::new(),
+            drawn: Vec::new(),
+            visible: Vec::new(),
+            buffer: None,
+            stats: CullingStats::default(),
+        }
+    }
+}
+
+impl FrustumCulling {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Draws every instance while disabled.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn stats(&self) -> CullingStats {
+        self.stats
+    }
+
+    /// The indices of the instances inside the frustum as of the last update, whether occluded
+    /// or not. Empty while disabled.
+    pub fn in_frustum(&self) -> &[u32] {
+        &self.in_frustum
+    }
+
+    /// The indices of the instances drawn as of the last update, all of them while disabled.
+    pub fn drawn(&self) -> &[u32] {
+        &self.drawn
+    }
+
+    /// Uploads the instances whose mesh, with `bounds`, may be seen through `view_projection`.
+    /// The instances `occluded` marks are skipped too. Instances are tested against the frustum
+    /// with their bounding sphere first and their bounding box only if that passes.
+    pub fn update(
+        &mut self,
+        upload: &mut Upload,
+        view_projection: Mat4,
+        bounds: &Bounds,
+        instances: &[InstanceRaw],
+        occluded: &[bool],
+    ) {
+        let total = instances.len() as u32;
+        self.in_frustum.clear();
+        self.drawn.clear();
+        if !self.enabled {
+            self.drawn.extend(0..total);
+            self.stats = CullingStats {
+                drawn: total,
+                ..CullingStats::default()
+            };
+            return;
+        }
+
+        let frustum = Frustum::from_view_projection(view_projection);
+        if !bounds.aabb.is_empty() {
+            self.in_frustum.extend(
+                instances
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, instance)| {
+                        let transform = Mat4::from_cols_array_2d(&instance.model);
+                        frustum.intersects_sphere(&bounds.sphere.transformed(transform))
+                            && frustum.intersects_aabb(&bounds.aabb.transformed(transform))
+                    })
+                    .map(|(index, _)| index as u32),
+            );
+        }
+        self.drawn.extend(
+            self.in_frustum
+                .iter()
+                .copied()
+                .filter(|&index| !occluded.get(index as usize).copied().unwrap_or(false)),
+        );
+        self.visible.clear();
+        self.visible
+            .extend(self.drawn.iter().map(|&index| instances[index as usize]));
+        let (drawn, in_frustum) = (self.visible.len() as u32, self.in_frustum.len() as u32);
+        self.stats = CullingStats {
+            drawn,
+            culled: total - in_frustum,
+            occluded: in_frustum - drawn,
+        };
+        if self.visible.is_empty() {
+            return;
+        }
+
+        let size = std::mem::size_of_val(self.visible.as_slice()) as wgpu::BufferAddress;
+        if self
+            .buffer
+            .as_ref()
+            .is_none_or(|buffer| buffer.size() < size)
+        {
+            self.buffer = Some(upload.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("visible instances"),
+                size: size.next_power_of_two(),
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }));
+        }
+        if let Some(buffer) = &self.buffer {
+            upload.write(buffer, 0, bytemuck::cast_slice(&self.visible));
+        }
+    }
+
+    /// The buffer and number of instances to draw: the visible ones, or `all` with its count
+    /// while disabled.
+    pub fn instances<'a>(&'a self, all: &'a wgpu::Buffer, count: u32) -> (&'a wgpu::Buffer, u32) {
+        match (&self.buffer, self.enabled) {
+            (Some(buffer), true) => (buffer, self.stats.drawn),
+            (None, true) => (all, 0),
+            (_, false) => (all, count),
+        }
+    }
+}
+
+/// The frustum and bounds instances are culled with by `culling.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct CullingUniform {
+    planes: [[f32; 4]; 6],
+    sphere: [f32; 4],
+    aabb_min: [f32; 4],
+    aabb_max: [f32; 4],
+    instance_count: u32,
+    draw_count: u32,
+    args_stride: u32,
+    _padding: u32,
+}
+
+/// Culls instances in a compute shader, which compacts the visible ones into a buffer and counts
+/// them into the indirect arguments of the mesh's draws, so that the CPU never touches them.
+/// Needs compute shaders and indirect draws, which WebGL lacks.
+pub struct GpuCulling {
+    enabled: bool,
+    /// Whether the culled draws are submitted with multi-draws, where supported.
+    multi_draw: bool,
+    multi_draw_supported: bool,
+    uniform: UniformBuffer<CullingUniform>,
+    buffers_layout: wgpu::BindGroupLayout,
+    cull_pipeline: wgpu::ComputePipeline,
+    write_args_pipeline: wgpu::ComputePipeline,
+    visible: Option<wgpu::Buffer>,
+    args: Option<wgpu::Buffer>,
+    bind_group: Option<wgpu::BindGroup>,
+}
+
+impl GpuCulling {
+    /// Compiles `culling.wgsl` with `preprocessor`, or returns `None` where the downlevel
+    /// capabilities rule it out.
+    pub fn new(
+        device: &wgpu::Device,
+        preprocessor: &Preprocessor,
+        downlevel: &wgpu::DownlevelCapabilities,
+    ) -> anyhow::Result<Option<Self>> {
+        let required =
+            wgpu::DownlevelFlags::COMPUTE_SHADERS | wgpu::DownlevelFlags::INDIRECT_EXECUTION;
+        if !downlevel.flags.contains(required) {
+            return Ok(None);
+        }
+
+        let uniform = UniformBuffer::new(
+            device,
+            "culling",
+            CullingUniform::default(),
+            wgpu::ShaderStages::COMPUTE,
+        );
+        let storage = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let buffers_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("culling buffers"),
+            entries: &[storage(0, true), storage(1, false), storage(2, false)],
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("culling"),
+            bind_group_layouts: &[uniform.bind_group_layout(), &buffers_layout],
+            push_constant_ranges: &[],
+        });
+        let shader_module = shader::create_module(device, preprocessor, "culling.wgsl")?;
+        let pipeline = |entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&layout),
+                module: &shader_module,
+                entry_point,
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            })
+        };
+
+        let multi_draw_supported = device
+            .features()
+            .contains(wgpu::Features::MULTI_DRAW_INDIRECT);
+        Ok(Some(Self {
+            enabled: true,
+            multi_draw: multi_draw_supported,
+            multi_draw_supported,
+            cull_pipeline: pipeline("cull"),
+            write_args_pipeline: pipeline("write_args"),
+            uniform,
+            buffers_layout,
+            visible: None,
+            args: None,
+            bind_group: None,
+        }))
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Leaves culling to [`FrustumCulling`] while disabled.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Whether [`Mesh::draw_indirect`] submits the culled draws with multi-draws, batching the
+    /// draws of each material into one call.
+    pub fn multi_draw(&self) -> bool {
+        self.multi_draw
+    }
+
+    pub fn multi_draw_supported(&self) -> bool {
+        self.multi_draw_supported
+    }
+
+    /// Only takes effect with [`wgpu::Features::MULTI_DRAW_INDIRECT`].
+    pub fn set_multi_draw(&mut self, multi_draw: bool) {
+        self.multi_draw = multi_draw && self.multi_draw_supported;
+    }
+
+    /// Uploads the frustum of `view_projection` and the bounds of `mesh`, and resets the
+    /// indirect arguments, for [`GpuCulling::dispatch`] to cull the `count` instances in
+    /// `instance_buffer`, which must have been created with [`wgpu::BufferUsages::STORAGE`].
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        view_projection: Mat4,
+        mesh: &Mesh,
+        instance_buffer: &wgpu::Buffer,
+        count: u32,
+    ) {
+        let bounds = mesh.bounds();
+        let frustum = Frustum::from_view_projection(view_projection);
+        self.uniform.value = CullingUniform {
+            planes: frustum.planes.map(|plane| plane.to_array()),
+            sphere: bounds.sphere.center.extend(bounds.sphere.radius).to_array(),
+            aabb_min: bounds.aabb.min.extend(0.0).to_array(),
+            aabb_max: bounds.aabb.max.extend(0.0).to_array(),
+            instance_count: if bounds.aabb.is_empty() { 0 } else { count },
+            draw_count: mesh.draw_count() as u32,
+            args_stride: mesh.indirect_stride(),
+            _padding: 0,
+        };
+        self.uniform.update(queue);
+
+        let visible_size =
+            (count.max(1) as usize * std::mem::size_of::<InstanceRaw>()) as wgpu::BufferAddress;
+        if self
+            .visible
+            .as_ref()
+            .is_none_or(|buffer| buffer.size() < visible_size)
+        {
+            self.visible = Some(device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("culled instances"),
+                size: visible_size.next_power_of_two(),
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::VERTEX,
+                mapped_at_creation: false,
+            }));
+        }
+        let args = mesh.indirect_args();
+        if self
+            .args
+            .as_ref()
+            .is_none_or(|buffer| buffer.size() != args.len() as wgpu::BufferAddress)
+        {
+            self.args = Some(device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("culled draw arguments"),
+                size: args.len() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::STORAGE
+                    | wgpu::BufferUsages::INDIRECT
+                    | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }));
+        }
+        let (Some(visible), Some(args_buffer)) = (&self.visible, &self.args) else {
+            return;
+        };
+        queue.write_buffer(args_buffer, 0, &args);
+        // The instance buffer is replaced whenever the number of instances changes.
+        self.bind_group = Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("culling buffers"),
+            layout: &self.buffers_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: instance_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: visible.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: args_buffer.as_entire_binding(),
+                },
+            ],
+        }));
+    }
+
+    /// Records the compute pass culling the instances given to [`GpuCulling::prepare`].
+    pub fn dispatch(&self, encoder: &mut wgpu::CommandEncoder, profiler: Option<&mut GpuProfiler>) {
+        let Some(bind_group) = &self.bind_group else {
+            return;
+        };
+        let CullingUniform {
+            instance_count,
+            draw_count,
+            ..
+        } = self.uniform.value;
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("culling"),
+            timestamp_writes: profiler
+                .and_then(|profiler| profiler.compute_timestamp_writes("culling")),
+        });
+        compute_pass.set_bind_group(0, self.uniform.bind_group(), &[]);
+        compute_pass.set_bind_group(1, bind_group, &[]);
+        compute_pass.set_pipeline(&self.cull_pipeline);
+        compute_pass.dispatch_workgroups(instance_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+        if draw_count > 1 {
+            compute_pass.set_pipeline(&self.write_args_pipeline);
+            compute_pass.dispatch_workgroups((draw_count - 1).div_ceil(WORKGROUP_SIZE), 1, 1);
+        }
+    }
+
+    /// The instance buffer and indirect arguments to draw the mesh with, once culled.
+    pub fn buffers(&self) -> Option<(&wgpu::Buffer, &wgpu::Buffer)> {
+        self.visible.as_ref().zip(self.args.as_ref())
+    }
+}
@@ -0,0 +1,294 @@
+//! Bezier curves and Catmull-Rom splines in 2D or 3D: evaluated at a parameter, e.g. to move a
+//! camera along a path, or flattened into lines for drawing.
+
+use std::ops::{Add, Mul, Sub};
+
+use glam::{Vec2, Vec3};
+
+/// How deep flattening subdivides a curve at most, 2^16 lines per curve or spline segment.
+const MAX_DEPTH: u32 = 16;
+
+/// A point curves are made of, [`Vec2`] or [`Vec3`].
+pub trait CurvePoint:
+    Copy + Add<Output = Self> + Sub<Output = Self> + Mul<f32, Output = Self>
+{
+    const ZERO: Self;
+
+    fn distance(self, other: Self) -> f32;
+
+    fn dot(self, other: Self) -> f32;
+}
+
+impl CurvePoint for Vec2 {
+    const ZERO: Self = Vec2::ZERO;
+
+    fn distance(self, other: Self) -> f32 {
+        Vec2::distance(self, other)
+    }
+
+    fn dot(self, other: Self) -> f32 {
+        Vec2::dot(self, other)
+    }
+}
+
+impl CurvePoint for Vec3 {
+    const ZERO: Self = Vec3::ZERO;
+
+    fn distance(self, other: Self) -> f32 {
+        Vec3::distance(self, other)
+    }
+
+    fn dot(self, other: Self) -> f32 {
+        Vec3::dot(self, other)
+    }
+}
+
+/// A Bezier curve from its first control point to its last, pulled towards the others.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Bezier<P> {
+    Quadratic([P; 3]),
+    Cubic([P; 4]),
+}
+
+impl<P: CurvePoint> Bezier<P> {
+    /// The point at `t` from 0 at the start to 1 at the end.
+    pub fn position(&self, t: f32) -> P {
+        let s = 1.0 - t;
+        match *self {
+            Bezier::Quadratic([a, b, c]) => a * (s * s) + b * (2.0 * s * t) + c * (t * t),
+            Bezier::Cubic([a, b, c, d]) => {
+                a * (s * s * s) + b * (3.0 * s * s * t) + c * (3.0 * s * t * t) + d * (t * t * t)
+            }
+        }
+    }
+
+    /// The derivative of [`Bezier::position`] at `t`, along the direction of travel.
+    pub fn tangent(&self, t: f32) -> P {
+        let s = 1.0 - t;
+        match *self {
+            Bezier::Quadratic([a, b, c]) => (b - a) * (2.0 * s) + (c - b) * (2.0 * t),
+            Bezier::Cubic([a, b, c, d]) => {
+                (b - a) * (3.0 * s * s) + (c - b) * (6.0 * s * t) + (d - c) * (3.0 * t * t)
+            }
+        }
+    }
+
+    /// Appends points along the curve to `points`, the start included if `points` is empty,
+    /// with lines between them straying at most `tolerance` from the curve.
+    pub fn flatten_into(&self, tolerance: f32, points: &mut Vec<P>) {
+        flatten(|t| self.position(t), tolerance, points);
+    }
+
+    /// Points along the curve, see [`Bezier::flatten_into`].
+    pub fn flatten(&self, tolerance: f32) -> Vec<P> {
+        let mut points = Vec::new();
+        self.flatten_into(tolerance, &mut points);
+        points
+    }
+}
+
+/// A curve through all of its points, whose tangent at each point is parallel to the line
+/// between its neighbours. Centripetal, so it neither overshoots nor loops between points that
+/// are unevenly spaced.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CatmullRom<P> {
+    pub points: Vec<P>,
+    /// Whether the curve goes on from the last point back to the first.
+    pub closed: bool,
+}
+
+impl<P: CurvePoint> CatmullRom<P> {
+    pub fn new(points: Vec<P>, closed: bool) -> Self {
+        Self { points, closed }
+    }
+
+    /// How many curves the spline is made of, one between each pair of consecutive points.
+    pub fn segment_count(&self) -> usize {
+        match self.points.len() {
+            0 | 1 => 0,
+            count if self.closed => count,
+            count => count - 1,
+        }
+    }
+
+    /// The point at `t` from 0 at the first point to 1 at the last, or back at the first if
+    /// `closed`. Each segment spans the same range of `t` however long it is, see
+    /// [`ArcLength`] for a parameter proportional to the distance travelled.
+    pub fn position(&self, t: f32) -> P {
+        let Some((segment, local)) = self.locate(t) else {
+            return self.points.first().copied().unwrap_or(P::ZERO);
+        };
+        self.segment(segment).position(local)
+    }
+
+    /// The derivative of [`CatmullRom::position`] at `t`, along the direction of travel.
+    pub fn tangent(&self, t: f32) -> P {
+        let Some((segment, local)) = self.locate(t) else {
+            return P::ZERO;
+        };
+        self.segment(segment).tangent(local) * self.segment_count() as f32
+    }
+
+    /// The segment `t` falls in and the parameter within it.
+    fn locate(&self, t: f32) -> Option<(usize, f32)> {
+        let count = self.segment_count();
+        if count == 0 {
+            return None;
+        }
+        let scaled = t.clamp(0.0, 1.0) * count as f32;
+        let segment = (scaled as usize).min(count - 1);
+        Some((segment, scaled - segment as f32))
+    }
+
+    /// The segment from point `index` to the next as a cubic Bezier curve, whose control points
+    /// follow from the neighbours of its ends, or from mirroring them at the ends of an open
+    /// spline.
+    pub fn segment(&self, index: usize) -> Bezier<P> {
+        let count = self.points.len();
+        let point = |i: isize| {
+            if self.closed {
+                self.points[i.rem_euclid(count as isize) as usize]
+            } else {
+                self.points[i.clamp(0, count as isize - 1) as usize]
+            }
+        };
+        let i = index as isize;
+        let (p1, p2) = (point(i), point(i + 1));
+        let p0 = if !self.closed && i == 0 {
+            p1 * 2.0 - p2
+        } else {
+            point(i - 1)
+        };
+        let p3 = if !self.closed && i + 2 >= count as isize {
+            p2 * 2.0 - p1
+        } else {
+            point(i + 2)
+        };
+
+        // Knots spaced by the square root of the distances between the points, see "On the
+        // Parameterization of Catmull-Rom Curves" by Yuksel et al.
+        let knot = |a: P, b: P| a.distance(b).sqrt().max(1e-4);
+        let (d0, d1, d2) = (knot(p0, p1), knot(p1, p2), knot(p2, p3));
+        let m1 =
+            ((p1 - p0) * (1.0 / d0) - (p2 - p0) * (1.0 / (d0 + d1)) + (p2 - p1) * (1.0 / d1)) * d1;
+        let m2 =
+            ((p2 - p1) * (1.0 / d1) - (p3 - p1) * (1.0 / (d1 + d2)) + (p3 - p2) * (1.0 / d2)) * d1;
+        Bezier::Cubic([p1, p1 + m1 * (1.0 / 3.0), p2 - m2 * (1.0 / 3.0), p2])
+    }
+
+    /// Appends points along the spline to `points`, see [`Bezier::flatten_into`].
+    pub fn flatten_into(&self, tolerance: f32, points: &mut Vec<P>) {
+        for segment in 0..self.segment_count() {
+            self.segment(segment).flatten_into(tolerance, points);
+        }
+    }
+
+    /// Points along the spline, see [`Bezier::flatten_into`].
+    pub fn flatten(&self, tolerance: f32) -> Vec<P> {
+        let mut points = Vec::new();
+        self.flatten_into(tolerance, &mut points);
+        points
+    }
+}
+
+/// Maps distances along a curve to its parameter, so that it can be travelled at a constant
+/// speed, as a camera path should be.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArcLength {
+    /// The distance from the start at evenly spaced parameters from 0 to 1.
+    distances: Vec<f32>,
+}
+
+impl ArcLength {
+    /// Measures the curve `position` maps parameters from 0 to 1 to with `samples` straight
+    /// lines.
+    pub fn new<P: CurvePoint>(position: impl Fn(f32) -> P, samples: usize) -> Self {
+        let samples = samples.max(1);
+        let mut distances = Vec::with_capacity(samples + 1);
+        let mut length = 0.0;
+        let mut previous = position(0.0);
+        distances.push(0.0);
+        for sample in 1..=samples {
+            let point = position(sample as f32 / samples as f32);
+            length += point.distance(previous);
+            distances.push(length);
+            previous = point;
+        }
+        Self { distances }
+    }
+
+    /// The length of the whole curve.
+    pub fn length(&self) -> f32 {
+        self.distances.last().copied().unwrap_or(0.0)
+    }
+
+    /// The parameter `distance` along the curve from its start.
+    pub fn parameter(&self, distance: f32) -> f32 {
+        let samples = self.distances.len() - 1;
+        if samples == 0 || self.length() <= 0.0 {
+            return 0.0;
+        }
+        let distance = distance.clamp(0.0, self.length());
+        let after = self
+            .distances
+            .partition_point(|&sample| sample < distance)
+            .clamp(1, samples);
+        let (start, end) = (self.distances[after - 1], self.distances[after]);
+        let fraction = if end > start {
+            (distance - start) / (end - start)
+        } else {
+            0.0
+        };
+        (after as f32 - 1.0 + fraction) / samples as f32
+    }
+
+    /// The parameter a `fraction` of the way along the curve, from 0 to 1.
+    pub fn parameter_at_fraction(&self, fraction: f32) -> f32 {
+        self.parameter(fraction * self.length())
+    }
+}
+
+/// Appends points along the curve `position` maps parameters from 0 to 1 to, halving spans of
+/// the parameter until the midpoint of each is within `tolerance` of the line between its ends.
+fn flatten<P: CurvePoint>(position: impl Fn(f32) -> P, tolerance: f32, points: &mut Vec<P>) {
+    let start = position(0.0);
+    if points.is_empty() {
+        points.push(start);
+    }
+    // The quarter points are checked too on the first level, so that an S-shaped curve whose
+    // midpoint happens to lie on its chord is still subdivided.
+    let mut stack = vec![(1.0, position(1.0), 0)];
+    let (mut t0, mut p0) = (0.0, start);
+    while let Some(&(t1, p1, depth)) = stack.last() {
+        let t = (t0 + t1) / 2.0;
+        let middle = position(t);
+        let flat = depth >= MAX_DEPTH
+            || (distance_to_line(middle, p0, p1) <= tolerance
+                && (depth > 0
+                    || (distance_to_line(position(0.25), p0, p1) <= tolerance
+                        && distance_to_line(position(0.75), p0, p1) <= tolerance)));
+        if flat {
+            points.push(p1);
+            stack.pop();
+            (t0, p0) = (t1, p1);
+        } else {
+            // Both halves are a level deeper.
+            if let Some(end) = stack.last_mut() {
+                end.2 = depth + 1;
+            }
+            stack.push((t, middle, depth + 1));
+        }
+    }
+}
+
+/// The distance from `point` to the line segment from `a` to `b`.
+fn distance_to_line<P: CurvePoint>(point: P, a: P, b: P) -> f32 {
+    let line = b - a;
+    let length_squared = line.dot(line);
+    let t = if length_squared > 0.0 {
+        ((point - a).dot(line) / length_squared).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    point.distance(a + line * t)
+}
@@ -0,0 +1,345 @@
+use std::sync::{Arc, OnceLock};
+
+use glam::{Mat4, Vec2, Vec3};
+
+use crate::curve::{Bezier, CatmullRom};
+use crate::math::{Aabb, Obb};
+use crate::pipeline_cache::PipelineBuilder;
+use crate::shader::{self, Preprocessor};
+use crate::taa::MOTION_FORMAT;
+use crate::texture::Texture;
+use crate::uniform::UniformBuffer;
+use crate::upload::Upload;
+use crate::vertex::{VertexLayout, VertexType};
+
+/// The segments circles and spheres are drawn with.
+const CIRCLE_SEGMENTS: usize = 32;
+
+/// How far, in world units, curves may stray from the true curve between their lines.
+const CURVE_TOLERANCE: f32 = 0.005;
+
+/// The edges of a box between its corners, whose index has a bit per axis: X, Y and Z.
+const CUBE_EDGES: [(usize, usize); 12] = [
+    (0, 1),
+    (2, 3),
+    (4, 5),
+    (6, 7),
+    (0, 2),
+    (1, 3),
+    (4, 6),
+    (5, 7),
+    (0, 4),
+    (1, 5),
+    (2, 6),
+    (3, 7),
+];
+
+/// The width of debug lines in logical pixels, before [`DebugDraw::pixels_per_point`].
+pub const DEFAULT_LINE_WIDTH: f32 = 1.5;
+
+/// A debug line, drawn as an instance of a quad the vertex shader expands on screen.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct DebugSegment {
+    pub start: [f32; 3],
+    pub end: [f32; 3],
+    /// Linear RGB.
+    pub color: [f32; 3],
+}
+
+impl VertexType for DebugSegment {
+    fn vertex_layout() -> &'static VertexLayout {
+        static LAYOUT: OnceLock<VertexLayout> = OnceLock::new();
+        LAYOUT.get_or_init(|| {
+            VertexLayout::new(wgpu::VertexStepMode::Instance, 0)
+                .with("start", wgpu::VertexFormat::Float32x3)
+                .with("end", wgpu::VertexFormat::Float32x3)
+                .with("color", wgpu::VertexFormat::Float32x3)
+        })
+    }
+}
+
+/// How the segments are expanded, matching `Lines` in `debug_draw.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct LinesUniform {
+    /// The size of the render target in pixels.
+    viewport: [f32; 2],
+    /// In pixels.
+    width: f32,
+    _padding: f32,
+}
+
+/// Everything that distinguishes one debug line pipeline from another.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DebugDrawPipelineKey {
+    pub format: wgpu::TextureFormat,
+    pub sample_count: u32,
+}
+
+/// Immediate-mode lines in world space: shapes added during a frame are batched into one
+/// instance buffer, drawn in the scene pass and forgotten, so they have to be added again every
+/// frame. Each line is expanded on screen to [`DebugDraw::line_width`] with round caps, which
+/// also round the joins of connected lines, and anti-aliased edges.
+pub struct DebugDraw {
+    /// The width of lines in logical pixels.
+    pub line_width: f32,
+    /// The physical pixels per logical pixel, the window's scale factor.
+    pub pixels_per_point: f32,
+    segments: Vec<DebugSegment>,
+    buffer: Option<wgpu::Buffer>,
+    /// The segments uploaded for the frame being rendered.
+    segment_count: u32,
+    uniform: UniformBuffer<LinesUniform>,
+    pipeline_layout: Arc<wgpu::PipelineLayout>,
+    shader_module: Arc<wgpu::ShaderModule>,
+}
+
+impl DebugDraw {
+    /// Compiles `debug_draw.wgsl` with `preprocessor`, reading the camera from a bind group with
+    /// `camera_layout`.
+    pub fn new(
+        device: &wgpu::Device,
+        preprocessor: &Preprocessor,
+        camera_layout: &wgpu::BindGroupLayout,
+    ) -> anyhow::Result<Self> {
+        let uniform = UniformBuffer::new(
+            device,
+            "debug draw lines",
+            LinesUniform::default(),
+            wgpu::ShaderStages::VERTEX_FRAGMENT,
+        );
+        let pipeline_layout = Arc::new(device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("debug draw"),
+                bind_group_layouts: &[camera_layout, uniform.bind_group_layout()],
+                push_constant_ranges: &[],
+            },
+        ));
+        let shader_module = Arc::new(shader::create_module(
+            device,
+            preprocessor,
+            "debug_draw.wgsl",
+        )?);
+
+        Ok(Self {
+            line_width: DEFAULT_LINE_WIDTH,
+            pixels_per_point: 1.0,
+            segments: Vec::new(),
+            buffer: None,
+            segment_count: 0,
+            uniform,
+            pipeline_layout,
+            shader_module,
+        })
+    }
+
+    /// Whether nothing has been added this frame.
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    /// Forgets the shapes added this frame.
+    pub fn clear(&mut self) {
+        self.segments.clear();
+    }
+
+    pub fn line(&mut self, start: Vec3, end: Vec3, color: Vec3) {
+        self.segments.push(DebugSegment {
+            start: start.to_array(),
+            end: end.to_array(),
+            color: color.to_array(),
+        });
+    }
+
+    /// Lines through `points`, e.g. a path or a plot.
+    pub fn polyline(&mut self, points: &[Vec3], color: Vec3) {
+        for pair in points.windows(2) {
+            self.line(pair[0], pair[1], color);
+        }
+    }
+
+    /// A quadratic or cubic Bezier curve.
+    pub fn bezier(&mut self, curve: &Bezier<Vec3>, color: Vec3) {
+        self.polyline(&curve.flatten(CURVE_TOLERANCE), color);
+    }
+
+    /// A curve through the points of `spline`, e.g. a camera path.
+    pub fn spline(&mut self, spline: &CatmullRom<Vec3>, color: Vec3) {
+        self.polyline(&spline.flatten(CURVE_TOLERANCE), color);
+    }
+
+    /// The edges of the box with the given corners, indexed like [`CUBE_EDGES`].
+    fn cube(&mut self, corners: [Vec3; 8], color: Vec3) {
+        for (a, b) in CUBE_EDGES {
+            self.line(corners[a], corners[b], color);
+        }
+    }
+
+    pub fn aabb(&mut self, aabb: &Aabb, color: Vec3) {
+        let corners = std::array::from_fn(|corner| {
+            Vec3::select(
+                glam::BVec3::new(corner & 1 != 0, corner & 2 != 0, corner & 4 != 0),
+                aabb.max,
+                aabb.min,
+            )
+        });
+        self.cube(corners, color);
+    }
+
+    pub fn obb(&mut self, obb: &Obb, color: Vec3) {
+        let corners = std::array::from_fn(|corner| {
+            let sign = |bit: usize| if corner & bit != 0 { 1.0 } else { -1.0 };
+            obb.center
+                + obb.axes[0] * obb.half_extents.x * sign(1)
+                + obb.axes[1] * obb.half_extents.y * sign(2)
+                + obb.axes[2] * obb.half_extents.z * sign(4)
+        });
+        self.cube(corners, color);
+    }
+
+    /// The edges of the volume `view_projection` maps to clip space, e.g. a camera's frustum.
+    pub fn frustum(&mut self, view_projection: Mat4, color: Vec3) {
+        let inverse = view_projection.inverse();
+        let corners = std::array::from_fn(|corner| {
+            let sign = |bit: usize| if corner & bit != 0 { 1.0 } else { -1.0 };
+            let depth = if corner & 4 != 0 { 1.0 } else { 0.0 };
+            inverse.project_point3(Vec3::new(sign(1), sign(2), depth))
+        });
+        self.cube(corners, color);
+    }
+
+    pub fn circle(&mut self, center: Vec3, normal: Vec3, radius: f32, color: Vec3) {
+        let (u, v) = normal.normalize_or_zero().any_orthonormal_pair();
+        let point = |segment: usize| {
+            let angle = segment as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU;
+            center + (u * angle.cos() + v * angle.sin()) * radius
+        };
+        for segment in 0..CIRCLE_SEGMENTS {
+            self.line(point(segment), point(segment + 1), color);
+        }
+    }
+
+    /// Three circles around the axes.
+    pub fn sphere(&mut self, center: Vec3, radius: f32, color: Vec3) {
+        for axis in Vec3::AXES {
+            self.circle(center, axis, radius, color);
+        }
+    }
+
+    /// The X, Y and Z axes of `matrix` from its origin, in red, green and blue, `size` long
+    /// before `matrix` scales them.
+    pub fn axes(&mut self, matrix: Mat4, size: f32) {
+        let origin = matrix.transform_point3(Vec3::ZERO);
+        for axis in Vec3::AXES {
+            let end = matrix.transform_point3(axis * size);
+            self.line(origin, end, axis);
+        }
+    }
+
+    /// Uploads the shapes added this frame for [`DebugDraw::draw`] to a render target `viewport`
+    /// pixels big and clears them, growing the instance buffer if it is too small.
+    pub fn upload(&mut self, upload: &mut Upload, viewport: Vec2) {
+        self.segment_count = self.segments.len() as u32;
+        if self.segments.is_empty() {
+            return;
+        }
+        self.uniform.value = LinesUniform {
+            viewport: viewport.to_array(),
+            width: self.line_width * self.pixels_per_point,
+            _padding: 0.0,
+        };
+        upload.write(
+            self.uniform.buffer(),
+            0,
+            bytemuck::bytes_of(&self.uniform.value),
+        );
+        let size = std::mem::size_of_val(self.segments.as_slice()) as wgpu::BufferAddress;
+        if self
+            .buffer
+            .as_ref()
+            .is_none_or(|buffer| buffer.size() < size)
+        {
+            self.buffer = Some(upload.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("debug draw"),
+                size: size.next_power_of_two(),
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }));
+        }
+        if let Some(buffer) = &self.buffer {
+            upload.write(buffer, 0, bytemuck::cast_slice(&self.segments));
+        }
+        self.segments.clear();
+    }
+
+    pub fn pipeline_builder(&self, key: DebugDrawPipelineKey) -> Arc<PipelineBuilder> {
+        let layout = self.pipeline_layout.clone();
+        let shader_module = self.shader_module.clone();
+        Arc::new(move |device, cache| create_pipeline(device, &layout, &shader_module, key, cache))
+    }
+
+    /// Draws the lines uploaded last, with the camera's bind group at group 0.
+    pub fn draw(
+        &self,
+        render_pass: &mut wgpu::RenderPass,
+        pipeline: &wgpu::RenderPipeline,
+        camera_bind_group: &wgpu::BindGroup,
+    ) {
+        let Some(buffer) = self.buffer.as_ref().filter(|_| self.segment_count > 0) else {
+            return;
+        };
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, self.uniform.bind_group(), &[]);
+        render_pass.set_vertex_buffer(0, buffer.slice(..));
+        // Two triangles per segment.
+        render_pass.draw(0..6, 0..self.segment_count);
+    }
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader_module: &wgpu::ShaderModule,
+    key: DebugDrawPipelineKey,
+    cache: Option<&wgpu::PipelineCache>,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("debug draw"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader_module,
+            entry_point: "vs_main",
+            buffers: &[DebugSegment::vertex_layout().buffer_layout()],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: Texture::DEPTH_FORMAT,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::LessEqual,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: key.sample_count,
+            ..Default::default()
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader_module,
+            entry_point: "fs_main",
+            targets: &[
+                Some(wgpu::ColorTargetState {
+                    format: key.format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }),
+                Some(MOTION_FORMAT.into()),
+            ],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        }),
+        multiview: None,
+        cache,
+    })
+}
@@ -0,0 +1,843 @@
+use std::time::Duration;
+
+use crate::camera::Camera;
+use crate::color_grading::ColorGrading;
+use crate::depth_of_field::DepthOfField;
+use crate::gizmo::{Gizmo, GizmoMode};
+use crate::lens_effects::LensEffects;
+use crate::light::{Light, LightKind};
+use crate::motion_blur::MotionBlur;
+use crate::particles::{ParticleBlend, ParticleEmitter};
+use crate::primitives;
+use crate::renderer::{OverlayContext, Renderer};
+use crate::scene::{Entity, MeshRenderer, Scene, Transform, World};
+use crate::stats::FrameStats;
+use crate::text::{FontFamily, TextStyle};
+use crate::tonemap::{Tonemap, Tonemapper};
+
+/// An egui overlay with panels for tweaking the renderer and the scene's entities at runtime,
+/// transform gizmos and frame statistics, drawn in its own pass after the scene.
+pub struct DebugUi {
+    context: egui::Context,
+    state: egui_winit::State,
+    renderer: egui_wgpu::Renderer,
+    visible: bool,
+    stats_visible: bool,
+    recording: bool,
+    /// Marks the positions of the point and spot lights on screen.
+    show_lights: bool,
+    /// Around the entity selected in the inspector, shown even while the panels are hidden.
+    gizmo: Gizmo,
+    /// The output of the last [`DebugUi::run`], waiting to be painted.
+    frame: Option<UiFrame>,
+}
+
+struct UiFrame {
+    paint_jobs: Vec<egui::ClippedPrimitive>,
+    textures_delta: egui::TexturesDelta,
+    screen_descriptor: egui_wgpu::ScreenDescriptor,
+}
+
+impl DebugUi {
+    pub fn new(window: &winit::window::Window, renderer: &Renderer) -> Self {
+        let context = egui::Context::default();
+        let state = egui_winit::State::new(
+            context.clone(),
+            egui::ViewportId::ROOT,
+            window,
+            Some(window.scale_factor() as f32),
+            window.theme(),
+            Some(renderer.device().limits().max_texture_dimension_2d as usize),
+        );
+        // The overlay is drawn straight into the resolved swapchain image.
+        let renderer = egui_wgpu::Renderer::new(
+            renderer.device(),
+            renderer.surface_config().format,
+            None,
+            1,
+            false,
+        );
+
+        Self {
+            context,
+            state,
+            renderer,
+            visible: false,
+            stats_visible: false,
+            recording: false,
+            show_lights: false,
+            gizmo: Gizmo::default(),
+            frame: None,
+        }
+    }
+
+    pub fn visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn set_visible(&mut self, visible: bool) {
+        self.visible = visible;
+    }
+
+    pub fn stats_visible(&self) -> bool {
+        self.stats_visible
+    }
+
+    /// Shows the frame statistics in a corner, independently of the settings panel.
+    pub fn set_stats_visible(&mut self, stats_visible: bool) {
+        self.stats_visible = stats_visible;
+    }
+
+    /// Shows a recording indicator, which doesn't end up in captured frames.
+    pub fn set_recording(&mut self, recording: bool) {
+        self.recording = recording;
+    }
+
+    pub fn gizmo(&self) -> &Gizmo {
+        &self.gizmo
+    }
+
+    pub fn gizmo_mut(&mut self) -> &mut Gizmo {
+        &mut self.gizmo
+    }
+
+    /// Passes `event` to egui, then to the gizmo, returning whether either consumed it and the
+    /// application should ignore it. Only the gizmo sees events while the overlay is hidden.
+    pub fn on_window_event(
+        &mut self,
+        window: &winit::window::Window,
+        event: &winit::event::WindowEvent,
+    ) -> bool {
+        if self.visible && self.state.on_window_event(window, event).consumed {
+            return true;
+        }
+        self.gizmo.on_window_event(event)
+    }
+
+    /// Lays out the panels for this frame, applying any changes made through them to `renderer`
+    /// and `scene`.
+    pub fn run(
+        &mut self,
+        window: &winit::window::Window,
+        renderer: &mut Renderer,
+        scene: &mut Scene,
+        stats: &FrameStats,
+    ) {
+        if self.stats_visible {
+            stats_overlay(window, stats, renderer);
+        }
+        let gizmo_visible = self.gizmo.selected().is_some();
+        if !self.visible && !self.recording && !self.show_lights && !gizmo_visible {
+            self.frame = None;
+            return;
+        }
+
+        let input = self.state.take_egui_input(window);
+        let output = self.context.run(input, |context| {
+            if self.visible {
+                settings_window(context, renderer, &mut self.show_lights);
+                inspector_window(context, &mut scene.world, &mut self.gizmo);
+            }
+            let config = renderer.surface_config();
+            self.gizmo.show(
+                context,
+                renderer.camera(),
+                (config.width, config.height),
+                &mut scene.world,
+            );
+            if self.show_lights {
+                light_markers(context, renderer);
+            }
+            if self.recording {
+                recording_indicator(context);
+            }
+        });
+        self.state
+            .handle_platform_output(window, output.platform_output);
+
+        let config = renderer.surface_config();
+        self.frame = Some(UiFrame {
+            paint_jobs: self
+                .context
+                .tessellate(output.shapes, output.pixels_per_point),
+            textures_delta: output.textures_delta,
+            screen_descriptor: egui_wgpu::ScreenDescriptor {
+                size_in_pixels: [config.width, config.height],
+                pixels_per_point: output.pixels_per_point,
+            },
+        });
+    }
+
+    /// Records the overlay pass laid out by the last [`DebugUi::run`] on top of the scene.
+    pub fn paint(&mut self, context: OverlayContext) {
+        let Some(frame) = self.frame.take() else {
+            return;
+        };
+        let OverlayContext {
+            device,
+            queue,
+            encoder,
+            view,
+            profiler,
+        } = context;
+
+        for (id, delta) in &frame.textures_delta.set {
+            self.renderer.update_texture(device, queue, *id, delta);
+        }
+        let command_buffers = self.renderer.update_buffers(
+            device,
+            queue,
+            encoder,
+            &frame.paint_jobs,
+            &frame.screen_descriptor,
+        );
+        // Only paint callbacks record their own command buffers, which have to run before the
+        // overlay pass.
+        queue.submit(command_buffers);
+
+        let mut render_pass = encoder
+            .begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("egui"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: profiler.and_then(|profiler| profiler.timestamp_writes("egui")),
+                occlusion_query_set: None,
+            })
+            .forget_lifetime();
+        self.renderer.render(
+            &mut render_pass,
+            &frame.paint_jobs,
+            &frame.screen_descriptor,
+        );
+        drop(render_pass);
+
+        for id in &frame.textures_delta.free {
+            self.renderer.free_texture(id);
+        }
+    }
+}
+
+fn settings_window(context: &egui::Context, renderer: &mut Renderer, show_lights: &mut bool) {
+    egui::Window::new("Renderer")
+        .default_pos([12.0, 12.0])
+        .resizable(false)
+        .show(context, |ui| {
+            ui.horizontal(|ui| {
+                let color = renderer.clear_color();
+                let mut rgb = [color.r as f32, color.g as f32, color.b as f32];
+                ui.label("Clear color");
+                if ui.color_edit_button_rgb(&mut rgb).changed() {
+                    renderer.set_clear_color(wgpu::Color {
+                        r: rgb[0].into(),
+                        g: rgb[1].into(),
+                        b: rgb[2].into(),
+                        a: 1.0,
+                    });
+                }
+            });
+
+            let mut present_mode = renderer.present_mode();
+            egui::ComboBox::from_label("Present mode")
+                .selected_text(format!("{present_mode:?}"))
+                .show_ui(ui, |ui| {
+                    for &mode in renderer.present_modes() {
+                        ui.selectable_value(&mut present_mode, mode, format!("{mode:?}"));
+                    }
+                });
+            if present_mode != renderer.present_mode() {
+                renderer.set_present_mode(present_mode);
+            }
+            let mut wireframe = renderer.wireframe();
+            if ui.checkbox(&mut wireframe, "Wireframe").changed() {
+                renderer.set_wireframe(wireframe);
+            }
+            let mut show_skybox = renderer.show_skybox();
+            if ui.checkbox(&mut show_skybox, "Skybox").changed() {
+                renderer.set_show_skybox(show_skybox);
+            }
+            let mut culling = renderer.culling().enabled();
+            if ui.checkbox(&mut culling, "Frustum culling").changed() {
+                renderer.culling_mut().set_enabled(culling);
+            }
+            if let Some(gpu_culling) = renderer.gpu_culling_mut() {
+                let mut enabled = gpu_culling.enabled();
+                let checkbox = egui::Checkbox::new(&mut enabled, "Cull on the GPU");
+                if ui.add_enabled(culling, checkbox).changed() {
+                    gpu_culling.set_enabled(enabled);
+                }
+                let mut multi_draw = gpu_culling.multi_draw();
+                let checkbox = egui::Checkbox::new(&mut multi_draw, "Multi-draw indirect");
+                let supported = gpu_culling.multi_draw_supported();
+                if ui
+                    .add_enabled(culling && enabled && supported, checkbox)
+                    .changed()
+                {
+                    gpu_culling.set_multi_draw(multi_draw);
+                }
+            }
+            let mut occlusion = renderer.occlusion().enabled();
+            let checkbox = egui::Checkbox::new(&mut occlusion, "Occlusion culling");
+            if ui
+                .add_enabled(culling && !renderer.culls_on_gpu(), checkbox)
+                .changed()
+            {
+                renderer.occlusion_mut().set_enabled(occlusion);
+            }
+            let mut bundles = renderer.static_bundles().enabled();
+            if ui.checkbox(&mut bundles, "Render bundles").changed() {
+                renderer.static_bundles_mut().set_enabled(bundles);
+            }
+            let mut show_bounds = renderer.show_bounds();
+            if ui.checkbox(&mut show_bounds, "Bounds").changed() {
+                renderer.set_show_bounds(show_bounds);
+            }
+            let mut shadows = renderer.shadows();
+            if ui.checkbox(&mut shadows, "Shadows").changed() {
+                renderer.set_shadows(shadows);
+            }
+            ui.add_enabled_ui(shadows, |ui| {
+                let shadow_map = renderer.shadow_map_mut();
+                let mut distance = shadow_map.distance();
+                if ui
+                    .add(egui::Slider::new(&mut distance, 1.0..=200.0).text("Shadow distance"))
+                    .changed()
+                {
+                    shadow_map.set_distance(distance);
+                }
+                let mut debug_cascades = shadow_map.debug_cascades();
+                if ui
+                    .checkbox(&mut debug_cascades, "Show shadow cascades")
+                    .changed()
+                {
+                    shadow_map.set_debug_cascades(debug_cascades);
+                }
+            });
+            let ssao = renderer.ssao_mut();
+            let mut enabled = ssao.enabled();
+            if ui.checkbox(&mut enabled, "SSAO").changed() {
+                ssao.set_enabled(enabled);
+            }
+            ui.add_enabled_ui(enabled, |ui| {
+                let mut radius = ssao.radius();
+                if ui
+                    .add(egui::Slider::new(&mut radius, 0.05..=4.0).text("SSAO radius"))
+                    .changed()
+                {
+                    ssao.set_radius(radius);
+                }
+                let mut intensity = ssao.intensity();
+                if ui
+                    .add(egui::Slider::new(&mut intensity, 0.0..=4.0).text("SSAO intensity"))
+                    .changed()
+                {
+                    ssao.set_intensity(intensity);
+                }
+            });
+            let mut sample_count = renderer.sample_count();
+            egui::ComboBox::from_label("MSAA")
+                .selected_text(format!("{sample_count}x"))
+                .show_ui(ui, |ui| {
+                    for &count in renderer.supported_sample_counts() {
+                        ui.selectable_value(&mut sample_count, count, format!("{count}x"));
+                    }
+                });
+            if sample_count != renderer.sample_count() {
+                renderer.set_sample_count(sample_count);
+            }
+            let mut fxaa = renderer.fxaa();
+            if ui.checkbox(&mut fxaa, "FXAA").changed() {
+                renderer.set_fxaa(fxaa);
+            }
+            let mut taa = renderer.taa();
+            if ui.checkbox(&mut taa, "TAA").changed() {
+                renderer.set_taa(taa);
+            }
+
+            ui.separator();
+            ui.heading("Post-processing");
+            let post_process = renderer.post_process_mut();
+            for index in 0..post_process.len() {
+                ui.horizontal(|ui| {
+                    let mut enabled = post_process.enabled(index);
+                    if ui
+                        .checkbox(&mut enabled, post_process.name(index))
+                        .changed()
+                    {
+                        post_process.set_enabled(index, enabled);
+                    }
+                    if ui.add_enabled(index > 0, egui::Button::new("Up")).clicked() {
+                        post_process.move_effect(index, index - 1);
+                    }
+                    let last = index + 1 == post_process.len();
+                    if ui.add_enabled(!last, egui::Button::new("Down")).clicked() {
+                        post_process.move_effect(index, index + 1);
+                    }
+                });
+            }
+            if let Some(tonemap) = post_process.effect_mut::<Tonemap>() {
+                egui::ComboBox::from_label("Tonemapping")
+                    .selected_text(tonemap.tonemapper.name())
+                    .show_ui(ui, |ui| {
+                        for tonemapper in Tonemapper::ALL {
+                            ui.selectable_value(
+                                &mut tonemap.tonemapper,
+                                tonemapper,
+                                tonemapper.name(),
+                            );
+                        }
+                    });
+                ui.add(egui::Slider::new(&mut tonemap.exposure, -8.0..=8.0).text("Exposure (EV)"));
+            }
+            if let Some(color_grading) = post_process.effect_mut::<ColorGrading>() {
+                ui.add(
+                    egui::Slider::new(&mut color_grading.strength, 0.0..=1.0)
+                        .text("Grading strength"),
+                );
+            }
+            if let Some(depth_of_field) = post_process.effect_mut::<DepthOfField>() {
+                ui.add(
+                    egui::Slider::new(&mut depth_of_field.focus_distance, 0.1..=100.0)
+                        .logarithmic(true)
+                        .text("Focus distance"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut depth_of_field.f_stop, 0.7..=22.0)
+                        .logarithmic(true)
+                        .text("f-stop"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut depth_of_field.max_radius, 1.0..=32.0)
+                        .text("Max blur radius (px)"),
+                );
+            }
+            if let Some(motion_blur) = post_process.effect_mut::<MotionBlur>() {
+                ui.add(egui::Slider::new(&mut motion_blur.shutter, 0.0..=1.0).text("Shutter"));
+                ui.add(
+                    egui::Slider::new(&mut motion_blur.max_length, 1.0..=64.0)
+                        .text("Max motion blur (px)"),
+                );
+            }
+            if let Some(lens_effects) = post_process.effect_mut::<LensEffects>() {
+                ui.checkbox(
+                    &mut lens_effects.chromatic_aberration,
+                    "Chromatic aberration",
+                );
+                ui.add_enabled(
+                    lens_effects.chromatic_aberration,
+                    egui::Slider::new(&mut lens_effects.chromatic_aberration_strength, 0.0..=16.0)
+                        .text("Aberration (px)"),
+                );
+                ui.checkbox(&mut lens_effects.vignette, "Vignette");
+                ui.add_enabled(
+                    lens_effects.vignette,
+                    egui::Slider::new(&mut lens_effects.vignette_intensity, 0.0..=1.0)
+                        .text("Vignette intensity"),
+                );
+                ui.add_enabled(
+                    lens_effects.vignette,
+                    egui::Slider::new(&mut lens_effects.vignette_radius, 0.0..=1.0)
+                        .text("Vignette radius"),
+                );
+                ui.checkbox(&mut lens_effects.grain, "Film grain");
+                ui.add_enabled(
+                    lens_effects.grain,
+                    egui::Slider::new(&mut lens_effects.grain_intensity, 0.0..=0.3)
+                        .text("Grain intensity"),
+                );
+            }
+
+            ui.separator();
+            ui.heading("Camera");
+            let camera = renderer.camera_mut();
+            ui.label(format!("Eye: {:.2}", camera.eye));
+            ui.label(format!("Target: {:.2}", camera.target));
+            let mut fovy = camera.fovy.to_degrees();
+            if ui
+                .add(egui::Slider::new(&mut fovy, 10.0..=120.0).text("FOV (degrees)"))
+                .changed()
+            {
+                camera.fovy = fovy.to_radians();
+            }
+            ui.add(
+                egui::Slider::new(&mut camera.znear, 0.001..=10.0)
+                    .logarithmic(true)
+                    .text("Near plane"),
+            );
+            let znear = camera.znear;
+            ui.add(
+                egui::Slider::new(&mut camera.zfar, znear * 2.0..=10_000.0)
+                    .logarithmic(true)
+                    .text("Far plane"),
+            );
+
+            ui.separator();
+            ui.heading("Light");
+            let light = renderer.light_mut();
+            ui.horizontal(|ui| {
+                ui.label("Direction");
+                for component in &mut light.direction {
+                    ui.add(egui::DragValue::new(component).speed(0.01));
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Color");
+                ui.color_edit_button_rgb(&mut light.color);
+            });
+            ui.add(egui::Slider::new(&mut light.ambient, 0.0..=2.0).text("Ambient"));
+            ui.add(egui::Slider::new(&mut light.intensity, 0.0..=10.0).text("Intensity"));
+            ui.label(format!(
+                "Point and spot lights: {}",
+                renderer.lights().len()
+            ));
+            ui.checkbox(show_lights, "Show lights");
+
+            ui.separator();
+            ui.heading("Level of detail");
+            if ui.button("Spheres with three levels").clicked() {
+                renderer.set_mesh(&primitives::sphere(0.6, 48, 24));
+                renderer.set_lods(&[
+                    (primitives::sphere(0.6, 16, 8), 0.1),
+                    (primitives::sphere(0.6, 6, 4), 0.04),
+                ]);
+            }
+            let lod = renderer.lod_mut();
+            ui.add(egui::Slider::new(&mut lod.hysteresis, 0.0..=0.5).text("Hysteresis"));
+            let mut crossfade = lod.crossfade.as_secs_f32();
+            if ui
+                .add(egui::Slider::new(&mut crossfade, 0.0..=1.0).text("Crossfade (s)"))
+                .changed()
+            {
+                lod.crossfade = Duration::from_secs_f32(crossfade);
+            }
+            if !lod.is_empty() {
+                ui.label(format!("Instances per level: {:?}", lod.counts()));
+            }
+
+            if let Some(particles) = renderer.particles_mut() {
+                ui.separator();
+                ui.heading("GPU particles");
+                let mut enabled = particles.enabled();
+                if ui.checkbox(&mut enabled, "Enabled").changed() {
+                    particles.set_enabled(enabled);
+                }
+                let mut blend = particles.blend();
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut blend, ParticleBlend::Additive, "Additive");
+                    ui.radio_value(&mut blend, ParticleBlend::Alpha, "Alpha (sorted)");
+                });
+                particles.set_blend(blend);
+                let capacity = particles.capacity() as f32;
+                emitter_settings(ui, particles.emitter_mut(), capacity, true);
+            }
+
+            ui.separator();
+            ui.heading("CPU particles");
+            let particles = renderer.cpu_particles_mut();
+            let mut enabled = particles.enabled();
+            if ui.checkbox(&mut enabled, "Enabled").changed() {
+                particles.set_enabled(enabled);
+            }
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut particles.blend, ParticleBlend::Additive, "Additive");
+                ui.radio_value(&mut particles.blend, ParticleBlend::Alpha, "Alpha (sorted)");
+            });
+            let capacity = particles.max_particles as f32;
+            emitter_settings(ui, &mut particles.emitter, capacity, false);
+            ui.horizontal(|ui| {
+                ui.label("Color over life");
+                for (_, color) in particles.color_over_life.keys_mut() {
+                    ui.color_edit_button_rgba_unmultiplied(color);
+                }
+            });
+            ui.label(format!("Alive: {}", particles.len()));
+
+            ui.separator();
+            ui.heading("Default material");
+            let mut factors = *renderer.default_material().factors();
+            ui.horizontal(|ui| {
+                ui.label("Base color");
+                ui.color_edit_button_rgba_unmultiplied(&mut factors.base_color);
+            });
+            ui.add(egui::Slider::new(&mut factors.metallic, 0.0..=1.0).text("Metallic"));
+            ui.add(egui::Slider::new(&mut factors.roughness, 0.0..=1.0).text("Roughness"));
+            if factors != *renderer.default_material().factors() {
+                renderer.set_default_material_factors(factors);
+            }
+            let cache = renderer.material_cache();
+            ui.label(format!(
+                "Mesh materials: {} ({} textures)",
+                cache.material_count(),
+                cache.texture_count()
+            ));
+        });
+}
+
+/// Lists the scene's entities to select one for the gizmo, and edits the selected entity's
+/// transform.
+fn inspector_window(context: &egui::Context, world: &mut World, gizmo: &mut Gizmo) {
+    egui::Window::new("Inspector")
+        .default_pos([340.0, 12.0])
+        .resizable(false)
+        .show(context, |ui| {
+            let mut selected = gizmo.selected();
+            egui::ComboBox::from_label("Entity")
+                .selected_text(
+                    selected
+                        .map_or_else(|| "None".to_owned(), |entity| entity_label(world, entity)),
+                )
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut selected, None, "None");
+                    let entities: Vec<Entity> = world
+                        .query::<&Transform>()
+                        .iter()
+                        .map(|(entity, _)| entity)
+                        .collect();
+                    for entity in entities {
+                        ui.selectable_value(
+                            &mut selected,
+                            Some(entity),
+                            entity_label(world, entity),
+                        );
+                    }
+                });
+            if selected != gizmo.selected() {
+                gizmo.select(selected);
+            }
+            ui.horizontal(|ui| {
+                ui.selectable_value(&mut gizmo.mode, GizmoMode::Translate, "Translate");
+                ui.selectable_value(&mut gizmo.mode, GizmoMode::Rotate, "Rotate");
+                ui.selectable_value(&mut gizmo.mode, GizmoMode::Scale, "Scale");
+            });
+
+            let Some(transform) =
+                selected.and_then(|entity| world.query_one_mut::<&mut Transform>(entity).ok())
+            else {
+                return;
+            };
+            ui.horizontal(|ui| {
+                ui.label("Translation");
+                for component in transform.translation.as_mut() {
+                    ui.add(egui::DragValue::new(component).speed(0.01));
+                }
+            });
+            let (y, x, z) = transform.rotation.to_euler(glam::EulerRot::YXZ);
+            let mut angles = [x, y, z].map(f32::to_degrees);
+            ui.horizontal(|ui| {
+                ui.label("Rotation");
+                let mut changed = false;
+                for angle in &mut angles {
+                    changed |= ui
+                        .add(egui::DragValue::new(angle).speed(0.5).suffix("°"))
+                        .changed();
+                }
+                if changed {
+                    let [x, y, z] = angles.map(f32::to_radians);
+                    transform.rotation = glam::Quat::from_euler(glam::EulerRot::YXZ, y, x, z);
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Scale");
+                for component in transform.scale.as_mut() {
+                    ui.add(
+                        egui::DragValue::new(component)
+                            .speed(0.01)
+                            .range(0.01..=f32::INFINITY),
+                    );
+                }
+            });
+        });
+}
+
+/// Names an entity after its id and what it is.
+fn entity_label(world: &World, entity: Entity) -> String {
+    let has = |satisfies: Result<bool, _>| satisfies.unwrap_or(false);
+    let kind = if has(world.satisfies::<&Camera>(entity)) {
+        "Camera"
+    } else if has(world.satisfies::<&Light>(entity)) {
+        "Light"
+    } else if has(world.satisfies::<&MeshRenderer>(entity)) {
+        "Mesh"
+    } else {
+        "Group"
+    };
+    format!("{kind} {}", entity.id())
+}
+
+/// Draws a dot in each light's color at its position, with a line along the axis of spot lights.
+/// Edits where and how particles are emitted, and their colors with `colors`.
+fn emitter_settings(ui: &mut egui::Ui, emitter: &mut ParticleEmitter, max_rate: f32, colors: bool) {
+    ui.horizontal(|ui| {
+        ui.label("Position");
+        for component in emitter.position.as_mut() {
+            ui.add(egui::DragValue::new(component).speed(0.01));
+        }
+    });
+    ui.horizontal(|ui| {
+        ui.label("Direction");
+        for component in emitter.direction.as_mut() {
+            ui.add(egui::DragValue::new(component).speed(0.01));
+        }
+    });
+    ui.horizontal(|ui| {
+        ui.label("Gravity");
+        for component in emitter.gravity.as_mut() {
+            ui.add(egui::DragValue::new(component).speed(0.05));
+        }
+    });
+    let mut spread = emitter.spread.to_degrees();
+    if ui
+        .add(egui::Slider::new(&mut spread, 0.0..=180.0).text("Spread (degrees)"))
+        .changed()
+    {
+        emitter.spread = spread.to_radians();
+    }
+    ui.add(egui::Slider::new(&mut emitter.speed, 0.0..=20.0).text("Speed"));
+    ui.add(
+        egui::Slider::new(&mut emitter.rate, 0.0..=max_rate)
+            .logarithmic(true)
+            .text("Rate (per second)"),
+    );
+    ui.add(egui::Slider::new(&mut emitter.lifetime, 0.1..=10.0).text("Lifetime (s)"));
+    ui.add(egui::Slider::new(&mut emitter.size, 0.005..=1.0).text("Size"));
+    if colors {
+        ui.horizontal(|ui| {
+            ui.label("Start color");
+            ui.color_edit_button_rgba_unmultiplied(&mut emitter.start_color);
+            ui.label("End color");
+            ui.color_edit_button_rgba_unmultiplied(&mut emitter.end_color);
+        });
+    }
+}
+
+fn light_markers(context: &egui::Context, renderer: &Renderer) {
+    let view_projection = renderer.camera().view_projection_matrix();
+    let screen = context.screen_rect();
+    let project = |position: glam::Vec3| {
+        let clip = view_projection * position.extend(1.0);
+        // Behind the camera.
+        if clip.w <= 0.0 {
+            return None;
+        }
+        let ndc = clip.truncate() / clip.w;
+        Some(egui::pos2(
+            screen.left() + (ndc.x + 1.0) * 0.5 * screen.width(),
+            screen.top() + (1.0 - ndc.y) * 0.5 * screen.height(),
+        ))
+    };
+
+    let painter = context.layer_painter(egui::LayerId::background());
+    for light in renderer.lights() {
+        let Some(position) = project(light.position) else {
+            continue;
+        };
+        let [r, g, b] = light.color;
+        let color = egui::Color32::from(egui::Rgba::from_rgb(r, g, b));
+        if let LightKind::Spot { direction, .. } = light.kind {
+            if let Some(end) = project(light.position + direction * light.range * 0.25) {
+                painter.line_segment([position, end], egui::Stroke::new(2.0, color));
+            }
+        }
+        painter.circle(
+            position,
+            5.0,
+            color,
+            egui::Stroke::new(1.0, egui::Color32::BLACK),
+        );
+    }
+}
+
+/// Formats a duration in milliseconds, or a dash while it is unknown.
+fn milliseconds(duration: Option<std::time::Duration>) -> String {
+    duration.map_or_else(
+        || "-".to_owned(),
+        |duration| format!("{:.2} ms", duration.as_secs_f64() * 1000.0),
+    )
+}
+
+/// Queues the frame statistics as text in the top right corner, where they're drawn over the
+/// scene like the overlay.
+fn stats_overlay(window: &winit::window::Window, stats: &FrameStats, renderer: &mut Renderer) {
+    let fps = stats
+        .fps()
+        .map_or_else(|| "-".to_owned(), |fps| format!("{fps:.1}"));
+    let low = stats
+        .one_percent_low()
+        .map_or_else(|| "-".to_owned(), |fps| format!("{fps:.1}"));
+    let mut lines = vec![
+        format!(
+            "FPS     {fps} ({})",
+            milliseconds(stats.average_frame_time())
+        ),
+        format!("1% low  {low}"),
+        format!("CPU     {}", milliseconds(stats.cpu_time())),
+        format!("GPU     {}", milliseconds(stats.gpu_time())),
+    ];
+    for (name, time) in stats.pass_times() {
+        lines.push(format!("  {name:<6}{}", milliseconds(Some(time))));
+    }
+    if renderer.culls_on_gpu() {
+        lines.push("Culled  on the GPU".to_owned());
+    } else {
+        let culling = renderer.culling().stats();
+        lines.push(format!("Drawn   {}", culling.drawn));
+        lines.push(format!("Culled  {}", culling.culled));
+        lines.push(format!("Hidden  {}", culling.occluded));
+    }
+    let (used, capacity) = renderer.mesh_allocator().usage();
+    let mebibytes = |bytes: u64| bytes as f64 / (1 << 20) as f64;
+    lines.push(format!(
+        "Meshes  {:.1} / {:.1} MiB",
+        mebibytes(used),
+        mebibytes(capacity)
+    ));
+    if renderer.static_bundles().enabled() {
+        let bundles = renderer.static_bundles();
+        lines.push(format!(
+            "Bundles {}, recorded {}x",
+            bundles.len(),
+            bundles.recordings()
+        ));
+    }
+    if !renderer.transparent_queue().is_empty() {
+        lines.push(format!(
+            "Sorted  {} blended draws",
+            renderer.transparent_queue().len()
+        ));
+    }
+    if let Some(bindless) = renderer.bindless() {
+        lines.push(format!("Bindless {} material slots", bindless.capacity()));
+    }
+    lines.push(format!("Present {:?}", renderer.present_mode()));
+
+    let scale = window.scale_factor() as f32;
+    let width = renderer.surface_config().width as f32;
+    renderer.text_mut().queue(
+        lines.join("\n"),
+        glam::Vec2::new(width - 12.0 * scale, 12.0 * scale),
+        TextStyle {
+            size: 13.0 * scale,
+            family: FontFamily::Monospace,
+            anchor: glam::Vec2::new(1.0, 0.0),
+            shadow: Some([0, 0, 0, 200]),
+            ..TextStyle::default()
+        },
+    );
+}
+
+fn recording_indicator(context: &egui::Context) {
+    egui::Area::new(egui::Id::new("recording"))
+        .anchor(egui::Align2::RIGHT_BOTTOM, [-12.0, -12.0])
+        .interactable(false)
+        .show(context, |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                ui.colored_label(egui::Color32::RED, "\u{25CF} REC");
+            });
+        });
+}
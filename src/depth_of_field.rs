@@ -0,0 +1,188 @@
+use std::sync::Arc;
+
+use wgpu::util::DeviceExt;
+
+use crate::camera::Camera;
+use crate::post_process::{self, Effect, EffectContext, EffectPipelineKey};
+use crate::shader::{self, Preprocessor};
+use crate::texture::Texture;
+
+/// The height of the simulated sensor in world units, a full-frame 35mm sensor if those are
+/// meters. Together with the field of view it gives the focal length.
+const SENSOR_HEIGHT: f32 = 0.024;
+
+/// `DepthOfField` in `depth_of_field.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct DepthOfFieldUniform {
+    near: f32,
+    far: f32,
+    focus_distance: f32,
+    coc_scale: f32,
+    max_radius: f32,
+    _padding: [f32; 3],
+}
+
+/// Depth of field, an [`Effect`] blurring the scene away from the focus distance like a thin
+/// lens would. Reads the depth of the prepass, and is meant to run on the HDR scene so that
+/// bright highlights blur into discs.
+pub struct DepthOfField {
+    /// The distance from the camera that is in focus, in world units.
+    pub focus_distance: f32,
+    /// The f-number of the lens, lower values blurring more.
+    pub f_stop: f32,
+    /// The largest blur radius in pixels, bounding the cost of the pass.
+    pub max_radius: f32,
+    /// The near plane, far plane and vertical field of view of the camera.
+    camera: (f32, f32, f32),
+    buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline_layout: Arc<wgpu::PipelineLayout>,
+    shader_module: Arc<wgpu::ShaderModule>,
+}
+
+impl DepthOfField {
+    /// Compiles `depth_of_field.wgsl` with `preprocessor`.
+    pub fn new(device: &wgpu::Device, preprocessor: &Preprocessor) -> anyhow::Result<Self> {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("depth of field"),
+            contents: bytemuck::bytes_of(&DepthOfFieldUniform {
+                near: 0.1,
+                far: 100.0,
+                focus_distance: 1.0,
+                coc_scale: 0.0,
+                max_radius: 0.0,
+                _padding: [0.0; 3],
+            }),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let [input, input_sampler] = post_process::input_layout_entries();
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("depth of field"),
+            entries: &[
+                input,
+                input_sampler,
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = Arc::new(device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("depth of field"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            },
+        ));
+        let shader_module = Arc::new(shader::create_module(
+            device,
+            preprocessor,
+            "depth_of_field.wgsl",
+        )?);
+
+        Ok(Self {
+            focus_distance: 5.0,
+            f_stop: 2.8,
+            max_radius: 12.0,
+            camera: (0.1, 100.0, 45f32.to_radians()),
+            buffer,
+            bind_group_layout,
+            pipeline_layout,
+            shader_module,
+        })
+    }
+
+    /// Follows the depth range and field of view of `camera`, once per frame before
+    /// [`Effect::update`].
+    pub fn set_camera(&mut self, camera: &Camera) {
+        self.camera = (camera.znear, camera.zfar, camera.fovy);
+    }
+}
+
+impl Effect for DepthOfField {
+    fn name(&self) -> &'static str {
+        "depth of field"
+    }
+
+    fn reads_depth(&self) -> bool {
+        true
+    }
+
+    fn update(&mut self, queue: &wgpu::Queue) {
+        let (near, far, fovy) = self.camera;
+        // Thin lens: a point at distance d, with the lens focused at s, blurs into a circle of
+        // diameter f² / (N (s - f)) · |d - s| / d on the sensor.
+        let focal_length = 0.5 * SENSOR_HEIGHT / (0.5 * fovy).tan();
+        let focus_distance = self.focus_distance.max(focal_length * 2.0);
+        let diameter =
+            focal_length * focal_length / (self.f_stop * (focus_distance - focal_length));
+        let uniform = DepthOfFieldUniform {
+            near,
+            far,
+            focus_distance,
+            coc_scale: 0.5 * diameter / SENSOR_HEIGHT,
+            max_radius: self.max_radius,
+            _padding: [0.0; 3],
+        };
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&uniform));
+    }
+
+    fn render(
+        &mut self,
+        context: &mut EffectContext,
+        input: &Texture,
+        output: &wgpu::TextureView,
+        format: wgpu::TextureFormat,
+    ) {
+        let key = EffectPipelineKey {
+            effect: self.name(),
+            format,
+        };
+        let pipeline = context.pipelines.get(
+            &key,
+            &*post_process::pipeline_builder(
+                "depth of field",
+                self.pipeline_layout.clone(),
+                self.shader_module.clone(),
+                format,
+            ),
+        );
+        let [input, input_sampler] = post_process::input_bind_group_entries(input);
+        let bind_group = context
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("depth of field"),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    input,
+                    input_sampler,
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(&context.depth.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: self.buffer.as_entire_binding(),
+                    },
+                ],
+            });
+        context.draw(self.name(), output, &pipeline, &bind_group);
+    }
+}
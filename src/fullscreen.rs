@@ -0,0 +1,138 @@
+use serde::Deserialize;
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+use winit::monitor::{MonitorHandle, VideoModeHandle};
+use winit::window::Window;
+
+/// How the window is displayed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WindowMode {
+    #[default]
+    Windowed,
+    /// A borderless window covering the monitor.
+    Borderless,
+    /// Exclusive fullscreen in the monitor's largest video mode.
+    Exclusive,
+}
+
+impl WindowMode {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Windowed => Self::Borderless,
+            Self::Borderless => Self::Exclusive,
+            Self::Exclusive => Self::Windowed,
+        }
+    }
+}
+
+/// Switches a window between [`WindowMode`]s on a chosen monitor, restoring the window's size and
+/// position when it goes back to windowed mode.
+///
+/// The surface follows through the usual resize path, since the window reports its new size like
+/// for any other resize.
+#[derive(Debug, Default)]
+pub struct Fullscreen {
+    mode: WindowMode,
+    /// An index into the available monitors, the window's current monitor without one.
+    monitor: Option<usize>,
+    /// The inner size and outer position of the window before it left windowed mode.
+    windowed: Option<(PhysicalSize<u32>, Option<PhysicalPosition<i32>>)>,
+}
+
+impl Fullscreen {
+    pub fn mode(&self) -> WindowMode {
+        self.mode
+    }
+
+    pub fn monitor(&self) -> Option<usize> {
+        self.monitor
+    }
+
+    pub fn set_mode(&mut self, window: &Window, mode: WindowMode) {
+        if self.mode == WindowMode::Windowed && mode != WindowMode::Windowed {
+            self.windowed = Some((window.inner_size(), window.outer_position().ok()));
+        }
+        self.mode = mode;
+
+        let monitor = self.target_monitor(window);
+        match mode {
+            WindowMode::Windowed => {
+                window.set_fullscreen(None);
+                if let Some((size, position)) = self.windowed.take() {
+                    let _ = window.request_inner_size(size);
+                    if let Some(position) = position {
+                        window.set_outer_position(position);
+                    }
+                }
+            }
+            WindowMode::Borderless => {
+                window.set_fullscreen(Some(winit::window::Fullscreen::Borderless(monitor)));
+            }
+            WindowMode::Exclusive => {
+                // Platforms without video modes, like the web, only have borderless fullscreen.
+                let fullscreen = match monitor.as_ref().and_then(largest_video_mode) {
+                    Some(video_mode) => winit::window::Fullscreen::Exclusive(video_mode),
+                    None => {
+                        eprintln!("exclusive fullscreen is unavailable, using borderless");
+                        winit::window::Fullscreen::Borderless(monitor)
+                    }
+                };
+                window.set_fullscreen(Some(fullscreen));
+            }
+        }
+    }
+
+    /// Switches to the next mode, from windowed to borderless to exclusive fullscreen.
+    pub fn cycle(&mut self, window: &Window) {
+        self.set_mode(window, self.mode.next());
+    }
+
+    /// Moves the window to the monitor at `index` among the available monitors, or leaves it on
+    /// its current one with `None`.
+    pub fn set_monitor(&mut self, window: &Window, index: Option<usize>) {
+        self.monitor = index;
+        match self.mode {
+            WindowMode::Windowed => {
+                if let Some(monitor) =
+                    index.and_then(|index| window.available_monitors().nth(index))
+                {
+                    window.set_outer_position(monitor.position());
+                }
+            }
+            mode => self.set_mode(window, mode),
+        }
+    }
+
+    /// Moves the window to the next available monitor.
+    pub fn next_monitor(&mut self, window: &Window) {
+        let count = window.available_monitors().count();
+        if count == 0 {
+            return;
+        }
+        let current = self.monitor.or_else(|| {
+            let current = window.current_monitor()?;
+            window
+                .available_monitors()
+                .position(|monitor| monitor == current)
+        });
+        let next = current.map_or(0, |index| (index + 1) % count);
+        self.set_monitor(window, Some(next));
+    }
+
+    fn target_monitor(&self, window: &Window) -> Option<MonitorHandle> {
+        self.monitor
+            .and_then(|index| window.available_monitors().nth(index))
+            .or_else(|| window.current_monitor())
+    }
+}
+
+/// The video mode with the highest resolution, and the highest refresh rate among those.
+fn largest_video_mode(monitor: &MonitorHandle) -> Option<VideoModeHandle> {
+    monitor.video_modes().max_by_key(|mode| {
+        let size = mode.size();
+        (
+            u64::from(size.width) * u64::from(size.height),
+            mode.refresh_rate_millihertz(),
+        )
+    })
+}
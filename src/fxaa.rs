@@ -0,0 +1,73 @@
+use std::sync::Arc;
+
+use crate::post_process::{self, Effect, EffectContext, EffectPipelineKey};
+use crate::shader::{self, Preprocessor};
+use crate::texture::Texture;
+
+/// FXAA 3.11, an [`Effect`] smoothing jagged edges found in the image itself. Much cheaper than
+/// MSAA, at the cost of some blur, and meant to run on the tonemapped image.
+pub struct Fxaa {
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline_layout: Arc<wgpu::PipelineLayout>,
+    shader_module: Arc<wgpu::ShaderModule>,
+}
+
+impl Fxaa {
+    /// Compiles `fxaa.wgsl` with `preprocessor`.
+    pub fn new(device: &wgpu::Device, preprocessor: &Preprocessor) -> anyhow::Result<Self> {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("fxaa"),
+            entries: &post_process::input_layout_entries(),
+        });
+        let pipeline_layout = Arc::new(device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("fxaa"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            },
+        ));
+        let shader_module = Arc::new(shader::create_module(device, preprocessor, "fxaa.wgsl")?);
+
+        Ok(Self {
+            bind_group_layout,
+            pipeline_layout,
+            shader_module,
+        })
+    }
+}
+
+impl Effect for Fxaa {
+    fn name(&self) -> &'static str {
+        "fxaa"
+    }
+
+    fn render(
+        &mut self,
+        context: &mut EffectContext,
+        input: &Texture,
+        output: &wgpu::TextureView,
+        format: wgpu::TextureFormat,
+    ) {
+        let key = EffectPipelineKey {
+            effect: self.name(),
+            format,
+        };
+        let pipeline = context.pipelines.get(
+            &key,
+            &*post_process::pipeline_builder(
+                "fxaa",
+                self.pipeline_layout.clone(),
+                self.shader_module.clone(),
+                format,
+            ),
+        );
+        let bind_group = context
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("fxaa"),
+                layout: &self.bind_group_layout,
+                entries: &post_process::input_bind_group_entries(input),
+            });
+        context.draw(self.name(), output, &pipeline, &bind_group);
+    }
+}
@@ -0,0 +1,52 @@
+use crate::input::Input;
+
+/// Polls the connected gamepads, feeding their buttons and sticks into [`Input`].
+///
+/// Gamepads can be connected and disconnected at any time.
+pub struct Gamepads {
+    gilrs: gilrs::Gilrs,
+    /// Axis values closer to the center than this read as centered, to hide stick drift.
+    pub deadzone: f32,
+}
+
+impl Gamepads {
+    pub fn new(deadzone: f32) -> anyhow::Result<Self> {
+        let gilrs = gilrs::Gilrs::new().map_err(|err| anyhow::anyhow!("{err}"))?;
+        for (_, gamepad) in gilrs.gamepads() {
+            println!("gamepad connected: {}", gamepad.name());
+        }
+        Ok(Self { gilrs, deadzone })
+    }
+
+    /// Applies the gamepad events received since the previous call to `input`.
+    pub fn poll(&mut self, input: &mut Input) {
+        while let Some(gilrs::Event { id, event, .. }) = self.gilrs.next_event() {
+            match event {
+                gilrs::EventType::Connected => {
+                    println!("gamepad connected: {}", self.gilrs.gamepad(id).name());
+                }
+                gilrs::EventType::Disconnected => {
+                    println!("gamepad disconnected: {}", self.gilrs.gamepad(id).name());
+                    input.release_gamepad();
+                }
+                gilrs::EventType::ButtonPressed(button, _) => input.set_button(button.into(), true),
+                gilrs::EventType::ButtonReleased(button, _) => {
+                    input.set_button(button.into(), false)
+                }
+                gilrs::EventType::AxisChanged(axis, value, _) => {
+                    input.set_axis(axis, apply_deadzone(value, self.deadzone));
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+/// Zeroes values within `deadzone` of the center and rescales the rest, so that the output
+/// still covers the whole range without jumping at the edge of the deadzone.
+fn apply_deadzone(value: f32, deadzone: f32) -> f32 {
+    if value.abs() <= deadzone {
+        return 0.0;
+    }
+    value.signum() * ((value.abs() - deadzone) / (1.0 - deadzone).max(f32::EPSILON)).min(1.0)
+}
@@ -0,0 +1,358 @@
+use glam::{Mat4, Quat, Vec2, Vec3};
+use winit::event::{ElementState, MouseButton, WindowEvent};
+
+use crate::camera::Camera;
+use crate::math::Ray;
+use crate::scene::{Entity, GlobalTransform, Transform, World};
+
+/// How far from a handle the cursor can be to grab it, in logical pixels.
+const GRAB_DISTANCE: f32 = 8.0;
+
+/// The length of the handles as a fraction of their distance to the camera, which keeps them
+/// the same size on screen.
+const SIZE: f32 = 0.15;
+
+/// The segments each rotation ring is drawn and grabbed with.
+const RING_SEGMENTS: usize = 48;
+
+const AXIS_COLORS: [egui::Color32; 3] = [
+    egui::Color32::from_rgb(230, 60, 60),
+    egui::Color32::from_rgb(60, 200, 60),
+    egui::Color32::from_rgb(60, 110, 240),
+];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GizmoMode {
+    /// Moves the entity along a world axis.
+    #[default]
+    Translate,
+    /// Turns the entity around a world axis.
+    Rotate,
+    /// Stretches the entity along one of its own axes.
+    Scale,
+}
+
+/// Where a drag started, which the transform is updated relative to.
+#[derive(Clone, Copy, Debug)]
+struct DragStart {
+    transform: Transform,
+    global: Mat4,
+    /// The position along the axis for translation and scale, the angle for rotation.
+    value: f32,
+    /// The direction from the origin to the cursor on the rotation plane.
+    direction: Vec3,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Drag {
+    axis: usize,
+    /// Filled in by the first [`Gizmo::show`] after the button was pressed.
+    start: Option<DragStart>,
+}
+
+/// Translate, rotate and scale handles around the selected entity, dragged with the left mouse
+/// button by intersecting the ray under the cursor with the grabbed axis.
+#[derive(Debug, Default)]
+pub struct Gizmo {
+    pub mode: GizmoMode,
+    selected: Option<Entity>,
+    /// In physical pixels.
+    cursor: Option<Vec2>,
+    /// The axis under the cursor as of the last [`Gizmo::show`].
+    hovered: Option<usize>,
+    drag: Option<Drag>,
+}
+
+impl Gizmo {
+    pub fn selected(&self) -> Option<Entity> {
+        self.selected
+    }
+
+    /// Shows the handles around `entity`, or hides them. Ends the drag in progress.
+    pub fn select(&mut self, entity: Option<Entity>) {
+        self.selected = entity;
+        self.hovered = None;
+        self.drag = None;
+    }
+
+    pub fn dragging(&self) -> bool {
+        self.drag.is_some()
+    }
+
+    /// Tracks the cursor and grabs the handle under it, returning whether the event was used to
+    /// drag a handle and shouldn't reach the camera.
+    pub fn on_window_event(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor = Some(Vec2::new(position.x as f32, position.y as f32));
+                self.drag.is_some()
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.cursor = None;
+                false
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
+            } => {
+                let Some(axis) = self.hovered.filter(|_| self.selected.is_some()) else {
+                    return false;
+                };
+                self.drag = Some(Drag { axis, start: None });
+                true
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Released,
+                button: MouseButton::Left,
+                ..
+            } => self.drag.take().is_some(),
+            _ => false,
+        }
+    }
+
+    /// Applies the drag in progress to the selected entity's [`Transform`] and draws the
+    /// handles, as seen through `camera` on a surface of `size` physical pixels.
+    pub fn show(
+        &mut self,
+        context: &egui::Context,
+        camera: &Camera,
+        size: (u32, u32),
+        world: &mut World,
+    ) {
+        self.hovered = None;
+        let Some(entity) = self.selected else {
+            return;
+        };
+        let Ok((transform, global)) =
+            world.query_one_mut::<(&mut Transform, &GlobalTransform)>(entity)
+        else {
+            self.select(None);
+            return;
+        };
+        let view_projection = camera.view_projection_matrix();
+        let ray = self
+            .cursor
+            .map(|cursor| Ray::from_cursor(view_projection, size, cursor));
+
+        if let (Some(drag), Some(ray)) = (&mut self.drag, ray) {
+            let axis = drag.axis;
+            let start = drag.start.get_or_insert_with(|| {
+                let handles = Handles::new(self.mode, global.0, camera);
+                let value = handles.value(axis, &ray).unwrap_or_default();
+                DragStart {
+                    transform: *transform,
+                    global: global.0,
+                    value: value.0,
+                    direction: value.1,
+                }
+            });
+            if let Some(dragged) = drag_transform(self.mode, axis, start, &ray) {
+                *transform = dragged;
+            }
+        }
+
+        // While dragging, the handles follow the transform being edited rather than the global
+        // transform, which is only propagated with the next frame.
+        let matrix = match self.drag.and_then(|drag| drag.start) {
+            Some(start) => start.global * start.transform.matrix().inverse() * transform.matrix(),
+            None => global.0,
+        };
+        let handles = Handles::new(self.mode, matrix, camera);
+        let pixels_per_point = context.pixels_per_point();
+        let screen = Vec2::new(size.0 as f32, size.1 as f32);
+        let project = |position: Vec3| {
+            let clip = view_projection * position.extend(1.0);
+            // Behind the camera.
+            if clip.w <= 0.0 {
+                return None;
+            }
+            let ndc = clip.truncate() / clip.w;
+            Some(Vec2::new(ndc.x + 1.0, 1.0 - ndc.y) * 0.5 * screen)
+        };
+        let polylines: Vec<Vec<Vec2>> = (0..3)
+            .map(|axis| {
+                handles
+                    .polyline(axis)
+                    .into_iter()
+                    .map(project)
+                    .collect::<Option<_>>()
+                    .unwrap_or_default()
+            })
+            .collect();
+
+        self.hovered = match self.drag {
+            Some(drag) => Some(drag.axis),
+            None => self.cursor.and_then(|cursor| {
+                polylines
+                    .iter()
+                    .enumerate()
+                    .map(|(axis, polyline)| (axis, polyline_distance(polyline, cursor)))
+                    .filter(|(_, distance)| *distance <= GRAB_DISTANCE * pixels_per_point)
+                    .min_by(|a, b| a.1.total_cmp(&b.1))
+                    .map(|(axis, _)| axis)
+            }),
+        };
+
+        let painter = context.layer_painter(egui::LayerId::background());
+        let to_points =
+            |point: Vec2| egui::pos2(point.x / pixels_per_point, point.y / pixels_per_point);
+        for (axis, polyline) in polylines.iter().enumerate() {
+            let color = if self.hovered == Some(axis) {
+                egui::Color32::YELLOW
+            } else {
+                AXIS_COLORS[axis]
+            };
+            let stroke = egui::Stroke::new(3.0, color);
+            let Some(&end) = polyline.last() else {
+                continue;
+            };
+            let end = to_points(end);
+            let points = polyline.iter().copied().map(to_points).collect();
+            painter.add(egui::Shape::line(points, stroke));
+            match self.mode {
+                GizmoMode::Translate => {
+                    painter.circle_filled(end, 5.0, color);
+                }
+                GizmoMode::Scale => {
+                    painter.rect_filled(
+                        egui::Rect::from_center_size(end, egui::vec2(9.0, 9.0)),
+                        0.0,
+                        color,
+                    );
+                }
+                GizmoMode::Rotate => (),
+            }
+        }
+    }
+}
+
+/// The handles of a gizmo at the origin of `matrix`.
+struct Handles {
+    mode: GizmoMode,
+    origin: Vec3,
+    /// Unit length.
+    axes: [Vec3; 3],
+    length: f32,
+}
+
+impl Handles {
+    fn new(mode: GizmoMode, matrix: Mat4, camera: &Camera) -> Self {
+        let origin = matrix.transform_point3(Vec3::ZERO);
+        let axes = match mode {
+            GizmoMode::Translate | GizmoMode::Rotate => [Vec3::X, Vec3::Y, Vec3::Z],
+            GizmoMode::Scale => [0, 1, 2].map(|axis| {
+                let column = matrix.col(axis).truncate().normalize_or_zero();
+                if column == Vec3::ZERO {
+                    Vec3::AXES[axis]
+                } else {
+                    column
+                }
+            }),
+        };
+        Self {
+            mode,
+            origin,
+            axes,
+            length: origin.distance(camera.eye) * SIZE,
+        }
+    }
+
+    /// The world-space points of the handle of `axis`.
+    fn polyline(&self, axis: usize) -> Vec<Vec3> {
+        let direction = self.axes[axis];
+        match self.mode {
+            GizmoMode::Translate | GizmoMode::Scale => {
+                vec![self.origin, self.origin + direction * self.length]
+            }
+            GizmoMode::Rotate => {
+                let (u, v) = direction.any_orthonormal_pair();
+                (0..=RING_SEGMENTS)
+                    .map(|segment| {
+                        let angle = segment as f32 / RING_SEGMENTS as f32 * std::f32::consts::TAU;
+                        self.origin + (u * angle.cos() + v * angle.sin()) * self.length
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Where the ray hits the handle of `axis`: the position along the axis, or the direction
+    /// from the origin on the rotation plane.
+    fn value(&self, axis: usize, ray: &Ray) -> Option<(f32, Vec3)> {
+        let axis_direction = self.axes[axis];
+        match self.mode {
+            GizmoMode::Translate | GizmoMode::Scale => {
+                closest_on_axis(self.origin, axis_direction, ray)
+                    .map(|position| (position, axis_direction))
+            }
+            GizmoMode::Rotate => {
+                let distance = ray.intersect_plane(self.origin, axis_direction)?;
+                Some((0.0, (ray.at(distance) - self.origin).normalize_or_zero()))
+            }
+        }
+    }
+}
+
+/// The transform of the entity dragged from `start` to `ray`, or `None` where the ray is
+/// parallel to the axis or misses its plane.
+fn drag_transform(mode: GizmoMode, axis: usize, start: &DragStart, ray: &Ray) -> Option<Transform> {
+    let center = start.global.transform_point3(Vec3::ZERO);
+    let parent = start.global * start.transform.matrix().inverse();
+    match mode {
+        GizmoMode::Translate => {
+            let position = closest_on_axis(center, Vec3::AXES[axis], ray)?;
+            let offset = Vec3::AXES[axis] * (position - start.value);
+            let global = Mat4::from_translation(offset) * start.global;
+            Some(Transform::from_matrix(parent.inverse() * global))
+        }
+        GizmoMode::Rotate => {
+            let normal = Vec3::AXES[axis];
+            let distance = ray.intersect_plane(center, normal)?;
+            let current = (ray.at(distance) - center).normalize_or_zero();
+            let angle = normal
+                .dot(start.direction.cross(current))
+                .atan2(start.direction.dot(current));
+            let rotation = Mat4::from_translation(center)
+                * Mat4::from_quat(Quat::from_axis_angle(normal, angle))
+                * Mat4::from_translation(-center);
+            Some(Transform::from_matrix(
+                parent.inverse() * rotation * start.global,
+            ))
+        }
+        GizmoMode::Scale => {
+            if start.value.abs() < 1e-4 {
+                return None;
+            }
+            let position = closest_on_axis(center, start.direction, ray)?;
+            let factor = (position / start.value).max(0.01);
+            let mut transform = start.transform;
+            transform.scale[axis] *= factor;
+            Some(transform)
+        }
+    }
+}
+
+/// The position along the axis through `axis_origin` closest to `ray`, the axis being unit
+/// length, or `None` if they are parallel.
+fn closest_on_axis(axis_origin: Vec3, axis: Vec3, ray: &Ray) -> Option<f32> {
+    let between = axis_origin - ray.origin;
+    let cos = axis.dot(ray.direction);
+    let denominator = 1.0 - cos * cos;
+    if denominator < 1e-4 {
+        return None;
+    }
+    Some((cos * ray.direction.dot(between) - axis.dot(between)) / denominator)
+}
+
+/// The distance from `point` to the closest segment of `polyline`.
+fn polyline_distance(polyline: &[Vec2], point: Vec2) -> f32 {
+    polyline
+        .windows(2)
+        .map(|segment| {
+            let (a, b) = (segment[0], segment[1]);
+            let t = ((point - a).dot(b - a) / (b - a).length_squared().max(1e-6)).clamp(0.0, 1.0);
+            point.distance(a + (b - a) * t)
+        })
+        .fold(f32::INFINITY, f32::min)
+}
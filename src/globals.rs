@@ -0,0 +1,14 @@
+/// Per-frame values shared by every shader, bound at `@group(0) @binding(0)`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Globals {
+    /// Surface size in physical pixels.
+    pub resolution: [f32; 2],
+    /// Cursor position in physical pixels, relative to the top-left corner of the window.
+    pub cursor: [f32; 2],
+    /// Seconds elapsed since the renderer was created.
+    pub time: f32,
+    /// Seconds elapsed since the previous frame.
+    pub delta_time: f32,
+    _padding: [f32; 2],
+}
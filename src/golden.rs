@@ -0,0 +1,148 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+
+/// The result of comparing a rendered frame against its reference image.
+pub struct Comparison {
+    pub differing_pixels: usize,
+    pub total_pixels: usize,
+    /// The reference faded to grey, with the differing pixels in red.
+    pub diff: image::RgbaImage,
+}
+
+impl Comparison {
+    pub fn differing_fraction(&self) -> f64 {
+        self.differing_pixels as f64 / self.total_pixels.max(1) as f64
+    }
+}
+
+/// Converts a color to the YIQ color space, whose distances are closer to perceived differences
+/// than RGB ones.
+fn yiq(pixel: image::Rgba<u8>) -> [f32; 3] {
+    let [r, g, b] = [pixel[0], pixel[1], pixel[2]].map(f32::from);
+    [
+        0.298_89 * r + 0.586_62 * g + 0.114_48 * b,
+        0.595_98 * r - 0.274_17 * g - 0.321_81 * b,
+        0.211_47 * r - 0.522_61 * g + 0.311_14 * b,
+    ]
+}
+
+/// The perceptual difference between two colors, from 0 for identical colors to 1 for black
+/// against white.
+fn color_delta(a: image::Rgba<u8>, b: image::Rgba<u8>) -> f32 {
+    // The largest possible weighted distance, between black and white.
+    const MAX_DELTA: f32 = 35_215.0;
+
+    let [y, i, q] = {
+        let (a, b) = (yiq(a), yiq(b));
+        [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+    };
+    (0.5053 * y * y + 0.299 * i * i + 0.1957 * q * q) / MAX_DELTA
+}
+
+/// Counts the pixels of `actual` that differ from `expected` by more than `threshold`, see
+/// [`color_delta`].
+pub fn compare(
+    expected: &image::RgbaImage,
+    actual: &image::RgbaImage,
+    threshold: f32,
+) -> anyhow::Result<Comparison> {
+    anyhow::ensure!(
+        expected.dimensions() == actual.dimensions(),
+        "expected a {}x{} image, got {}x{}",
+        expected.width(),
+        expected.height(),
+        actual.width(),
+        actual.height(),
+    );
+
+    let mut differing_pixels = 0;
+    let diff = image::RgbaImage::from_fn(expected.width(), expected.height(), |x, y| {
+        let (expected, actual) = (*expected.get_pixel(x, y), *actual.get_pixel(x, y));
+        if color_delta(expected, actual) > threshold {
+            differing_pixels += 1;
+            image::Rgba([255, 0, 0, 255])
+        } else {
+            let grey = (yiq(expected)[0] * 0.25 + 191.0) as u8;
+            image::Rgba([grey, grey, grey, 255])
+        }
+    });
+
+    Ok(Comparison {
+        differing_pixels,
+        total_pixels: (expected.width() * expected.height()) as usize,
+        diff,
+    })
+}
+
+/// Checks rendered frames against reference images stored as `NAME.png` in a directory.
+///
+/// References are only written when the `GOLDEN_UPDATE` environment variable is set, which
+/// rewrites all of them from the frames being checked; otherwise a missing reference fails the
+/// check. On failure the frame and a diff image are written to `output_dir` for inspection.
+#[derive(Clone, Debug)]
+pub struct Golden {
+    pub reference_dir: PathBuf,
+    pub output_dir: PathBuf,
+    /// How different a pixel may be before it counts as differing, see [`compare`].
+    pub threshold: f32,
+    /// The fraction of pixels that may differ, to allow for rasterization differences between
+    /// GPUs and drivers.
+    pub max_differing_fraction: f64,
+}
+
+impl Golden {
+    pub fn new(reference_dir: impl Into<PathBuf>, output_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            reference_dir: reference_dir.into(),
+            output_dir: output_dir.into(),
+            threshold: 0.01,
+            max_differing_fraction: 0.001,
+        }
+    }
+
+    pub fn check(&self, name: &str, actual: &image::RgbaImage) -> anyhow::Result<()> {
+        let reference = self.reference_dir.join(format!("{name}.png"));
+        if std::env::var_os("GOLDEN_UPDATE").is_some() {
+            std::fs::create_dir_all(&self.reference_dir)?;
+            actual
+                .save(&reference)
+                .with_context(|| format!("failed to write {}", reference.display()))?;
+            println!("wrote reference image {}", reference.display());
+            return Ok(());
+        }
+
+        if !reference.exists() {
+            std::fs::create_dir_all(&self.output_dir)?;
+            let actual_path = self.output_dir.join(format!("{name}.png"));
+            actual.save(&actual_path)?;
+            anyhow::bail!(
+                "{name}: missing reference image {}, see {} and set GOLDEN_UPDATE=1 to create it",
+                reference.display(),
+                actual_path.display(),
+            );
+        }
+        let expected = image::open(&reference)
+            .with_context(|| format!("failed to read {}", reference.display()))?
+            .into_rgba8();
+        let comparison = compare(&expected, actual, self.threshold)?;
+        if comparison.differing_fraction() <= self.max_differing_fraction {
+            return Ok(());
+        }
+
+        std::fs::create_dir_all(&self.output_dir)?;
+        let actual_path = self.output_dir.join(format!("{name}.png"));
+        let diff_path = self.output_dir.join(format!("{name}.diff.png"));
+        actual.save(&actual_path)?;
+        comparison.diff.save(&diff_path)?;
+        anyhow::bail!(
+            "{name}: {} of {} pixels ({:.3}%) differ from {}, see {} and {}",
+            comparison.differing_pixels,
+            comparison.total_pixels,
+            comparison.differing_fraction() * 100.0,
+            reference.display(),
+            actual_path.display(),
+            diff_path.display(),
+        )
+    }
+}
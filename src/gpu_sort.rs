@@ -0,0 +1,180 @@
+//! Sorting on the GPU, for data that never leaves it like the particles of
+//! [`GpuParticles`](crate::particles::GpuParticles).
+
+use wgpu::util::DeviceExt;
+
+use crate::shader::{self, Preprocessor};
+
+/// The invocations per workgroup of `gpu_sort.wgsl`.
+const WORKGROUP_SIZE: u32 = 256;
+
+/// `SortPass` in `gpu_sort.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct SortPass {
+    k: u32,
+    j: u32,
+}
+
+/// Sorts pairs of `u32` keys and values by ascending key with a bitonic sort, one compute
+/// dispatch per merge step. The pairs live in a storage buffer of `vec2<u32>`, keys first,
+/// whose length has to be a power of two: pad it with pairs keyed `u32::MAX` to sort them last.
+///
+/// Bitonic sorts aren't stable and take `log2(n) * (log2(n) + 1) / 2` passes over the whole
+/// buffer, which is fine for the tens of thousands of pairs of a particle system.
+pub struct GpuSort {
+    capacity: u32,
+    pairs_layout: wgpu::BindGroupLayout,
+    passes: Vec<SortPass>,
+    /// The size of each pass's slot in the uniform buffer, aligned for dynamic offsets.
+    slot_size: u32,
+    passes_bind_group: wgpu::BindGroup,
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl GpuSort {
+    /// Compiles `gpu_sort.wgsl` with `preprocessor`, for sorting up to `capacity` pairs rounded
+    /// up to a power of two. Needs compute shaders.
+    pub fn new(
+        device: &wgpu::Device,
+        preprocessor: &Preprocessor,
+        capacity: u32,
+    ) -> anyhow::Result<Self> {
+        let capacity = capacity.max(2).next_power_of_two();
+        let mut passes = Vec::new();
+        let mut k = 2;
+        while k <= capacity {
+            let mut j = k / 2;
+            while j > 0 {
+                passes.push(SortPass { k, j });
+                j /= 2;
+            }
+            k *= 2;
+        }
+        let alignment = device.limits().min_uniform_buffer_offset_alignment;
+        let slot_size = (std::mem::size_of::<SortPass>() as u32).next_multiple_of(alignment);
+        let mut data = vec![0; passes.len() * slot_size as usize];
+        for (index, pass) in passes.iter().enumerate() {
+            let offset = index * slot_size as usize;
+            data[offset..offset + std::mem::size_of::<SortPass>()]
+                .copy_from_slice(bytemuck::bytes_of(pass));
+        }
+        let passes_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("sort passes"),
+            contents: &data,
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let passes_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("sort passes"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<SortPass>() as u64),
+                },
+                count: None,
+            }],
+        });
+        let passes_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("sort passes"),
+            layout: &passes_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &passes_buffer,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(std::mem::size_of::<SortPass>() as u64),
+                }),
+            }],
+        });
+        let pairs_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("sort pairs"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("sort"),
+            bind_group_layouts: &[&passes_layout, &pairs_layout],
+            push_constant_ranges: &[],
+        });
+        let shader_module = shader::create_module(device, preprocessor, "gpu_sort.wgsl")?;
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("sort"),
+            layout: Some(&layout),
+            module: &shader_module,
+            entry_point: "sort",
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache: None,
+        });
+
+        Ok(Self {
+            capacity,
+            pairs_layout,
+            passes,
+            slot_size,
+            passes_bind_group,
+            pipeline,
+        })
+    }
+
+    /// The most pairs sorted at once, a power of two.
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    /// Binds the first `len` pairs of `pairs`, `len` being a power of two up to
+    /// [`GpuSort::capacity`], for [`GpuSort::sort`]. `pairs` needs
+    /// [`wgpu::BufferUsages::STORAGE`].
+    pub fn create_bind_group(
+        &self,
+        device: &wgpu::Device,
+        pairs: &wgpu::Buffer,
+        len: u32,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("sort pairs"),
+            layout: &self.pairs_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: pairs,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(len as u64 * 8),
+                }),
+            }],
+        })
+    }
+
+    /// Records the passes sorting the `len` pairs bound by `pairs`, created by
+    /// [`GpuSort::create_bind_group`] with the same `len`, into `compute_pass`.
+    pub fn sort(&self, compute_pass: &mut wgpu::ComputePass, pairs: &wgpu::BindGroup, len: u32) {
+        debug_assert!(len.is_power_of_two() && len <= self.capacity);
+        compute_pass.set_pipeline(&self.pipeline);
+        compute_pass.set_bind_group(1, pairs, &[]);
+        // The passes of smaller sorts come first.
+        for (index, _) in self
+            .passes
+            .iter()
+            .enumerate()
+            .take_while(|(_, pass)| pass.k <= len)
+        {
+            compute_pass.set_bind_group(
+                0,
+                &self.passes_bind_group,
+                &[index as u32 * self.slot_size],
+            );
+            compute_pass.dispatch_workgroups(len.div_ceil(WORKGROUP_SIZE), 1, 1);
+        }
+    }
+}
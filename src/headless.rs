@@ -0,0 +1,67 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use pollster::FutureExt;
+
+use crate::app;
+use crate::mesh::MeshData;
+use crate::renderer::{Renderer, RendererOptions};
+
+/// The frame size used when none is given.
+pub const DEFAULT_SIZE: (u32, u32) = (1280, 720);
+
+/// What to render when running without a window.
+#[derive(Clone, Debug)]
+pub struct HeadlessOptions {
+    pub width: u32,
+    pub height: u32,
+    pub frames: u32,
+    /// The directory frames are written to, as `frame-00000.png` and so on.
+    pub out: PathBuf,
+    pub renderer: RendererOptions,
+}
+
+/// Parses a size such as `1280x720`.
+pub fn parse_size(size: &str) -> anyhow::Result<(u32, u32)> {
+    let (width, height) = size
+        .split_once('x')
+        .with_context(|| format!("expected WIDTHxHEIGHT, got {size:?}"))?;
+    Ok((
+        width.parse().context("invalid width")?,
+        height.parse().context("invalid height")?,
+    ))
+}
+
+/// Renders a frame and reads it back.
+pub fn capture_frame(renderer: &mut Renderer) -> anyhow::Result<image::RgbaImage> {
+    renderer.request_capture();
+    renderer.render()?;
+    renderer
+        .take_capture()
+        .context("the frame wasn't captured")?
+}
+
+/// Renders the same scene as the windowed application into an offscreen texture, writing every
+/// frame to a PNG.
+pub fn run(options: &HeadlessOptions, mesh: Option<&MeshData>) -> anyhow::Result<()> {
+    let mut renderer =
+        Renderer::new_headless(options.width, options.height, &options.renderer).block_on()?;
+    app::setup_scene(&mut renderer, mesh);
+
+    std::fs::create_dir_all(&options.out)
+        .with_context(|| format!("failed to create {}", options.out.display()))?;
+    for frame in 0..options.frames {
+        let image = capture_frame(&mut renderer)?;
+        let path = options.out.join(format!("frame-{frame:05}.png"));
+        image
+            .save(&path)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+    }
+    println!(
+        "rendered {} frames to {}",
+        options.frames,
+        options.out.display()
+    );
+
+    Ok(())
+}
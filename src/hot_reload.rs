@@ -0,0 +1,97 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+
+use notify::Watcher;
+
+/// Watches a directory of shaders and reports the files that changed since the last poll.
+pub struct ShaderWatcher {
+    // Kept alive for as long as changes should be reported.
+    _watcher: notify::RecommendedWatcher,
+    receiver: mpsc::Receiver<PathBuf>,
+}
+
+impl ShaderWatcher {
+    pub fn new(directory: impl AsRef<Path>) -> notify::Result<Self> {
+        let (sender, receiver) = mpsc::channel();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                let Ok(event) = event else {
+                    return;
+                };
+                if event.kind.is_modify() || event.kind.is_create() {
+                    for path in event.paths {
+                        // The receiver only goes away together with the watcher.
+                        let _ = sender.send(path);
+                    }
+                }
+            })?;
+        watcher.watch(directory.as_ref(), notify::RecursiveMode::NonRecursive)?;
+
+        Ok(Self {
+            _watcher: watcher,
+            receiver,
+        })
+    }
+
+    /// Returns the WGSL files modified since the previous call, without duplicates.
+    pub fn changed_shaders(&self) -> Vec<PathBuf> {
+        let mut changed: Vec<_> = self
+            .receiver
+            .try_iter()
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension == "wgsl")
+            })
+            .collect();
+        changed.sort();
+        changed.dedup();
+        changed
+    }
+}
+
+/// Watches a single file, such as the configuration. Its directory is watched rather than the
+/// file itself, so that editors saving by replacing the file are noticed too.
+pub struct FileWatcher {
+    // Kept alive for as long as changes should be reported.
+    _watcher: notify::RecommendedWatcher,
+    receiver: mpsc::Receiver<()>,
+}
+
+impl FileWatcher {
+    pub fn new(path: impl AsRef<Path>) -> notify::Result<Self> {
+        let path = path.as_ref();
+        let directory = path
+            .parent()
+            .filter(|directory| !directory.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        let file_name = path.file_name().map(ToOwned::to_owned);
+
+        let (sender, receiver) = mpsc::channel();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                let Ok(event) = event else {
+                    return;
+                };
+                if (event.kind.is_modify() || event.kind.is_create())
+                    && event
+                        .paths
+                        .iter()
+                        .any(|path| path.file_name() == file_name.as_deref())
+                {
+                    // The receiver only goes away together with the watcher.
+                    let _ = sender.send(());
+                }
+            })?;
+        watcher.watch(directory, notify::RecursiveMode::NonRecursive)?;
+
+        Ok(Self {
+            _watcher: watcher,
+            receiver,
+        })
+    }
+
+    /// Whether the file changed since the previous call.
+    pub fn changed(&self) -> bool {
+        self.receiver.try_iter().count() > 0
+    }
+}
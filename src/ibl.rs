@@ -0,0 +1,366 @@
+use crate::shader::{self, Preprocessor};
+use crate::skybox::Skybox;
+
+/// The width and height of each face of the irradiance cube map in texels.
+pub const IRRADIANCE_SIZE: u32 = 32;
+/// The width and height of the sharpest level of the prefiltered cube map in texels.
+pub const PREFILTERED_SIZE: u32 = 128;
+/// The mip levels of the prefiltered cube map, going from smooth to fully rough,
+/// `PREFILTERED_MIPS` in `lighting.wgsl`.
+pub const PREFILTERED_MIPS: u32 = 5;
+/// The width and height of the BRDF lookup table in texels.
+pub const BRDF_LUT_SIZE: u32 = 256;
+
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+const BRDF_LUT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rg16Float;
+/// The spacing of the filter parameters of each pass in the uniform buffer, a multiple of every
+/// device's uniform offset alignment.
+const FILTER_STRIDE: u64 = 256;
+/// The irradiance faces followed by each face of each prefiltered level.
+const FILTER_PASSES: u32 = 6 + 6 * PREFILTERED_MIPS;
+
+/// The parameters of one filtering pass, `Filter` in `ibl.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct FilterUniform {
+    face: u32,
+    roughness: f32,
+    /// The width of the environment's sharpest level, picking the level to sample.
+    environment_size: f32,
+    _padding: u32,
+}
+
+/// Image-based lighting: an environment cube map convolved into an irradiance cube map for
+/// diffuse lighting and a prefiltered cube map for specular lighting, whose mip levels hold the
+/// environment blurred for increasing roughness. Together with a lookup table of the BRDF's
+/// response to a white environment, they light surfaces with the split-sum approximation.
+///
+/// The filtering runs in render passes rather than compute passes so it works on WebGL2.
+pub struct Ibl {
+    irradiance: wgpu::Texture,
+    prefiltered: wgpu::Texture,
+    irradiance_view: wgpu::TextureView,
+    prefiltered_view: wgpu::TextureView,
+    brdf_lut_view: wgpu::TextureView,
+    /// Samples the environment while filtering it, and the results in the scene pass.
+    sampler: wgpu::Sampler,
+    filter_buffer: wgpu::Buffer,
+    filter_bind_group_layout: wgpu::BindGroupLayout,
+    irradiance_pipeline: wgpu::RenderPipeline,
+    prefilter_pipeline: wgpu::RenderPipeline,
+}
+
+impl Ibl {
+    /// Compiles `ibl.wgsl` with `preprocessor` and renders the BRDF lookup table. The cube maps
+    /// are black until [`Ibl::prefilter`] is called.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        preprocessor: &Preprocessor,
+    ) -> anyhow::Result<Self> {
+        let cube_texture = |label, size, mip_level_count| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width: size,
+                    height: size,
+                    depth_or_array_layers: 6,
+                },
+                mip_level_count,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: FORMAT,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+            })
+        };
+        let cube_view = |texture: &wgpu::Texture| {
+            texture.create_view(&wgpu::TextureViewDescriptor {
+                dimension: Some(wgpu::TextureViewDimension::Cube),
+                ..Default::default()
+            })
+        };
+        let irradiance = cube_texture("irradiance", IRRADIANCE_SIZE, 1);
+        let prefiltered = cube_texture(
+            "prefiltered environment",
+            PREFILTERED_SIZE,
+            PREFILTERED_MIPS,
+        );
+        let irradiance_view = cube_view(&irradiance);
+        let prefiltered_view = cube_view(&prefiltered);
+        let brdf_lut = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("BRDF lookup table"),
+            size: wgpu::Extent3d {
+                width: BRDF_LUT_SIZE,
+                height: BRDF_LUT_SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: BRDF_LUT_FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let brdf_lut_view = brdf_lut.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("image-based lighting"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let filter_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("environment filter"),
+            size: FILTER_STRIDE * FILTER_PASSES as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let filter_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("environment filter"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: true,
+                            min_binding_size: wgpu::BufferSize::new(
+                                std::mem::size_of::<FilterUniform>() as u64,
+                            ),
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::Cube,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+            });
+        let filter_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("environment filter"),
+            bind_group_layouts: &[&filter_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let brdf_lut_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("BRDF lookup table"),
+            bind_group_layouts: &[],
+            push_constant_ranges: &[],
+        });
+        let shader_module = shader::create_module(device, preprocessor, "ibl.wgsl")?;
+        let pipeline = |label, layout, entry_point, format: wgpu::TextureFormat| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(layout),
+                vertex: wgpu::VertexState {
+                    module: &shader_module,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                },
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader_module,
+                    entry_point,
+                    targets: &[Some(format.into())],
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                }),
+                multiview: None,
+                cache: None,
+            })
+        };
+        let irradiance_pipeline = pipeline("irradiance", &filter_layout, "fs_irradiance", FORMAT);
+        let prefilter_pipeline = pipeline(
+            "prefiltered environment",
+            &filter_layout,
+            "fs_prefilter",
+            FORMAT,
+        );
+        let brdf_lut_pipeline = pipeline(
+            "BRDF lookup table",
+            &brdf_lut_layout,
+            "fs_brdf_lut",
+            BRDF_LUT_FORMAT,
+        );
+
+        // The lookup table doesn't depend on the environment, so it's only rendered once.
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("BRDF lookup table"),
+        });
+        {
+            let mut render_pass = begin_pass(&mut encoder, &brdf_lut_view, "BRDF lookup table");
+            render_pass.set_pipeline(&brdf_lut_pipeline);
+            render_pass.draw(0..3, 0..1);
+        }
+        queue.submit([encoder.finish()]);
+
+        Ok(Self {
+            irradiance,
+            prefiltered,
+            irradiance_view,
+            prefiltered_view,
+            brdf_lut_view,
+            sampler,
+            filter_buffer,
+            filter_bind_group_layout,
+            irradiance_pipeline,
+            prefilter_pipeline,
+        })
+    }
+
+    /// Convolves the environment of `skybox` into the irradiance and prefiltered cube maps.
+    pub fn prefilter(&self, device: &wgpu::Device, queue: &wgpu::Queue, skybox: &Skybox) {
+        // The irradiance faces first, then the prefiltered faces level by level.
+        let targets: Vec<_> = (0..6)
+            .map(|face| (&self.irradiance, &self.irradiance_pipeline, 0, face))
+            .chain((0..PREFILTERED_MIPS).flat_map(|mip| {
+                (0..6).map(move |face| (&self.prefiltered, &self.prefilter_pipeline, mip, face))
+            }))
+            .collect();
+        let mut filters = vec![0; FILTER_STRIDE as usize * targets.len()];
+        for (&(_, _, mip, face), filter) in targets
+            .iter()
+            .zip(filters.chunks_mut(FILTER_STRIDE as usize))
+        {
+            let uniform = FilterUniform {
+                face,
+                roughness: mip as f32 / (PREFILTERED_MIPS - 1) as f32,
+                environment_size: skybox.size() as f32,
+                _padding: 0,
+            };
+            filter[..std::mem::size_of::<FilterUniform>()]
+                .copy_from_slice(bytemuck::bytes_of(&uniform));
+        }
+        queue.write_buffer(&self.filter_buffer, 0, &filters);
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("environment filter"),
+            layout: &self.filter_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: &self.filter_buffer,
+                        offset: 0,
+                        size: wgpu::BufferSize::new(std::mem::size_of::<FilterUniform>() as u64),
+                    }),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(skybox.view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("environment filter"),
+        });
+        for (index, (texture, pipeline, mip, face)) in targets.into_iter().enumerate() {
+            let view = texture.create_view(&wgpu::TextureViewDescriptor {
+                dimension: Some(wgpu::TextureViewDimension::D2),
+                base_mip_level: mip,
+                mip_level_count: Some(1),
+                base_array_layer: face,
+                array_layer_count: Some(1),
+                ..Default::default()
+            });
+            let mut render_pass = begin_pass(&mut encoder, &view, "environment filter");
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[(FILTER_STRIDE * index as u64) as u32]);
+            render_pass.draw(0..3, 0..1);
+        }
+        queue.submit([encoder.finish()]);
+    }
+
+    /// The bind group entries sampling the lighting in the scene pass: the irradiance cube map,
+    /// the prefiltered cube map, the BRDF lookup table and their sampler, at `binding` and the
+    /// three following bindings.
+    pub fn layout_entries(binding: u32) -> [wgpu::BindGroupLayoutEntry; 4] {
+        let texture = |binding, view_dimension| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension,
+                multisampled: false,
+            },
+            count: None,
+        };
+        [
+            texture(binding, wgpu::TextureViewDimension::Cube),
+            texture(binding + 1, wgpu::TextureViewDimension::Cube),
+            texture(binding + 2, wgpu::TextureViewDimension::D2),
+            wgpu::BindGroupLayoutEntry {
+                binding: binding + 3,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+        ]
+    }
+
+    /// The resources for [`Ibl::layout_entries`].
+    pub fn bind_group_entries(&self, binding: u32) -> [wgpu::BindGroupEntry<'_>; 4] {
+        [
+            wgpu::BindGroupEntry {
+                binding,
+                resource: wgpu::BindingResource::TextureView(&self.irradiance_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: binding + 1,
+                resource: wgpu::BindingResource::TextureView(&self.prefiltered_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: binding + 2,
+                resource: wgpu::BindingResource::TextureView(&self.brdf_lut_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: binding + 3,
+                resource: wgpu::BindingResource::Sampler(&self.sampler),
+            },
+        ]
+    }
+}
+
+fn begin_pass<'a>(
+    encoder: &'a mut wgpu::CommandEncoder,
+    view: &wgpu::TextureView,
+    label: &str,
+) -> wgpu::RenderPass<'a> {
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some(label),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: None,
+        timestamp_writes: None,
+        occlusion_query_set: None,
+    })
+}
@@ -0,0 +1,381 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::de::{DeserializeOwned, Error as _};
+use serde::{Deserialize, Deserializer};
+use winit::event::{DeviceEvent, ElementState, MouseButton, MouseScrollDelta, WindowEvent};
+use winit::keyboard::{KeyCode, PhysicalKey};
+
+/// How far an axis has to be tilted for its [`Button::GamepadAxis`] to count as pressed.
+const AXIS_PRESS_THRESHOLD: f32 = 0.5;
+
+/// A keyboard key, a mouse button, or a gamepad button or half axis.
+///
+/// In the configuration keys are named after [`KeyCode`] variants, e.g. `"KeyW"`, mouse buttons
+/// after [`MouseButton`] ones, e.g. `"Left"`, and gamepad buttons after [`gilrs::Button`] ones
+/// with a `Gamepad` prefix, e.g. `"GamepadSouth"`. Half axes add the direction to a
+/// [`gilrs::Axis`], e.g. `"GamepadLeftStickY+"`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(untagged)]
+pub enum Button {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    Gamepad(GamepadButton),
+    GamepadAxis(GamepadAxis),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct GamepadButton(pub gilrs::Button);
+
+/// One direction of a gamepad axis.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct GamepadAxis {
+    pub axis: gilrs::Axis,
+    pub positive: bool,
+}
+
+impl GamepadAxis {
+    /// The tilt of `value` in this direction, from 0 to 1.
+    fn value(self, value: f32) -> f32 {
+        if self.positive {
+            value.max(0.0)
+        } else {
+            (-value).max(0.0)
+        }
+    }
+}
+
+/// Parses a gilrs enum variant named with a `Gamepad` prefix.
+fn parse_gamepad<T: DeserializeOwned, E: serde::de::Error>(name: &str) -> Result<T, E> {
+    let variant = name
+        .strip_prefix("Gamepad")
+        .ok_or_else(|| E::custom(format!("{name:?} isn't a gamepad input")))?;
+    T::deserialize(serde::de::value::StrDeserializer::<E>::new(variant))
+}
+
+impl<'de> Deserialize<'de> for GamepadButton {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        parse_gamepad(&name).map(Self)
+    }
+}
+
+impl<'de> Deserialize<'de> for GamepadAxis {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        let (axis, positive) = if let Some(axis) = name.strip_suffix('+') {
+            (axis, true)
+        } else if let Some(axis) = name.strip_suffix('-') {
+            (axis, false)
+        } else {
+            return Err(D::Error::custom(format!("{name:?} has no direction")));
+        };
+        Ok(Self {
+            axis: parse_gamepad(axis)?,
+            positive,
+        })
+    }
+}
+
+impl From<KeyCode> for Button {
+    fn from(key: KeyCode) -> Self {
+        Self::Key(key)
+    }
+}
+
+impl From<MouseButton> for Button {
+    fn from(button: MouseButton) -> Self {
+        Self::Mouse(button)
+    }
+}
+
+impl From<gilrs::Button> for Button {
+    fn from(button: gilrs::Button) -> Self {
+        Self::Gamepad(GamepadButton(button))
+    }
+}
+
+impl Button {
+    /// The half axis `axis` tilted towards positive or negative values.
+    pub fn axis(axis: gilrs::Axis, positive: bool) -> Self {
+        Self::GamepadAxis(GamepadAxis { axis, positive })
+    }
+
+    fn is_gamepad(self) -> bool {
+        matches!(self, Self::Gamepad(_) | Self::GamepadAxis(_))
+    }
+}
+
+/// Maps action names, such as `"move_forward"`, to the buttons triggering them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ActionMap {
+    bindings: HashMap<String, Vec<Button>>,
+}
+
+impl ActionMap {
+    /// An action map without any actions.
+    pub fn empty() -> Self {
+        Self {
+            bindings: HashMap::new(),
+        }
+    }
+
+    /// Binds `action` to `buttons`, replacing its previous bindings.
+    pub fn bind(&mut self, action: impl Into<String>, buttons: impl IntoIterator<Item = Button>) {
+        self.bindings
+            .insert(action.into(), buttons.into_iter().collect());
+    }
+
+    /// Binds `action` to `buttons` in addition to its current bindings.
+    pub fn add(&mut self, action: impl Into<String>, buttons: impl IntoIterator<Item = Button>) {
+        self.bindings
+            .entry(action.into())
+            .or_default()
+            .extend(buttons);
+    }
+
+    pub fn contains(&self, action: &str) -> bool {
+        self.bindings.contains_key(action)
+    }
+
+    /// The buttons bound to `action`, none if it isn't bound.
+    pub fn buttons(&self, action: &str) -> &[Button] {
+        self.bindings.get(action).map_or(&[], Vec::as_slice)
+    }
+}
+
+impl Default for ActionMap {
+    /// The demo's actions with their default bindings.
+    fn default() -> Self {
+        use KeyCode as K;
+
+        let mut actions = Self::empty();
+        for (action, key) in [
+            ("move_forward", K::KeyW),
+            ("move_backward", K::KeyS),
+            ("move_left", K::KeyA),
+            ("move_right", K::KeyD),
+            ("move_up", K::KeyE),
+            ("move_down", K::KeyQ),
+            ("toggle_camera", K::Tab),
+            ("toggle_ui", K::F1),
+            ("toggle_stats", K::F3),
+            ("cycle_present_mode", K::KeyV),
+            ("next_monitor", K::F8),
+            ("record", K::F9),
+            ("toggle_wireframe", K::F10),
+            ("cycle_fullscreen", K::F11),
+            ("screenshot", K::F12),
+            ("cycle_msaa", K::KeyM),
+            ("grab_cursor", K::Escape),
+        ] {
+            actions.bind(action, [key.into()]);
+        }
+        actions.bind("orbit", [MouseButton::Left.into()]);
+        actions.bind("pan", [MouseButton::Middle.into()]);
+        // A click rather than a drag, which orbits.
+        actions.bind("select", [MouseButton::Left.into()]);
+
+        use gilrs::{Axis, Button as G};
+        for (action, buttons) in [
+            ("move_forward", [Button::axis(Axis::LeftStickY, true)]),
+            ("move_backward", [Button::axis(Axis::LeftStickY, false)]),
+            ("move_left", [Button::axis(Axis::LeftStickX, false)]),
+            ("move_right", [Button::axis(Axis::LeftStickX, true)]),
+            ("move_up", [G::RightTrigger2.into()]),
+            ("move_down", [G::LeftTrigger2.into()]),
+            ("look_left", [Button::axis(Axis::RightStickX, false)]),
+            ("look_right", [Button::axis(Axis::RightStickX, true)]),
+            ("look_up", [Button::axis(Axis::RightStickY, true)]),
+            ("look_down", [Button::axis(Axis::RightStickY, false)]),
+            ("toggle_camera", [G::Select.into()]),
+            ("toggle_ui", [G::Start.into()]),
+        ] {
+            actions.add(action, buttons);
+        }
+        actions
+    }
+}
+
+/// The state of the keyboard and mouse, aggregated from winit events between two calls to
+/// [`Input::end_frame`], and queried by button or by action.
+#[derive(Debug, Default)]
+pub struct Input {
+    pub actions: ActionMap,
+    down: HashSet<Button>,
+    just_pressed: HashSet<Button>,
+    just_released: HashSet<Button>,
+    cursor_position: Option<(f64, f64)>,
+    cursor_delta: (f64, f64),
+    mouse_motion: (f64, f64),
+    scroll_lines: f32,
+    /// The latest value of each gamepad axis, after the deadzone.
+    axes: HashMap<gilrs::Axis, f32>,
+}
+
+impl Input {
+    pub fn process_window_event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::KeyboardInput { event, .. } => {
+                if let PhysicalKey::Code(key) = event.physical_key {
+                    self.set_button(key.into(), event.state == ElementState::Pressed);
+                }
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                self.set_button((*button).into(), *state == ElementState::Pressed)
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                if let Some((x, y)) = self.cursor_position {
+                    self.cursor_delta.0 += position.x - x;
+                    self.cursor_delta.1 += position.y - y;
+                }
+                self.cursor_position = Some((position.x, position.y));
+            }
+            WindowEvent::CursorLeft { .. } => self.cursor_position = None,
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.scroll_lines += match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 20.0,
+                }
+            }
+            // Releases aren't reported while the window is unfocused.
+            WindowEvent::Focused(false) => self.release_all(),
+            _ => (),
+        }
+    }
+
+    pub fn process_device_event(&mut self, event: &DeviceEvent) {
+        if let DeviceEvent::MouseMotion { delta } = event {
+            self.mouse_motion.0 += delta.0;
+            self.mouse_motion.1 += delta.1;
+        }
+    }
+
+    /// Presses or releases `button`, for input that doesn't come through winit.
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        // Key repeats don't count as new presses.
+        if pressed {
+            if self.down.insert(button) {
+                self.just_pressed.insert(button);
+            }
+        } else if self.down.remove(&button) {
+            self.just_released.insert(button);
+        }
+    }
+
+    /// Moves a gamepad axis to `value`, from -1 to 1, pressing its half axes when tilted far
+    /// enough.
+    pub fn set_axis(&mut self, axis: gilrs::Axis, value: f32) {
+        self.axes.insert(axis, value);
+        for positive in [true, false] {
+            let half = GamepadAxis { axis, positive };
+            self.set_button(
+                Button::GamepadAxis(half),
+                half.value(value) >= AXIS_PRESS_THRESHOLD,
+            );
+        }
+    }
+
+    /// Releases every button, e.g. when input stops reaching the window.
+    pub fn release_all(&mut self) {
+        self.just_released.extend(self.down.drain());
+        self.axes.clear();
+    }
+
+    /// Releases the gamepad buttons and centers the axes, e.g. when a gamepad is disconnected.
+    pub fn release_gamepad(&mut self) {
+        let released: Vec<_> = self
+            .down
+            .iter()
+            .copied()
+            .filter(|button| button.is_gamepad())
+            .collect();
+        for button in released {
+            self.set_button(button, false);
+        }
+        self.axes.clear();
+    }
+
+    /// Clears the presses, releases and motion of the frame, to be called once they have been
+    /// handled.
+    pub fn end_frame(&mut self) {
+        self.just_pressed.clear();
+        self.just_released.clear();
+        self.cursor_delta = (0.0, 0.0);
+        self.mouse_motion = (0.0, 0.0);
+        self.scroll_lines = 0.0;
+    }
+
+    /// Whether `button` is held down.
+    pub fn pressed(&self, button: impl Into<Button>) -> bool {
+        self.down.contains(&button.into())
+    }
+
+    /// Whether `button` went down this frame.
+    pub fn just_pressed(&self, button: impl Into<Button>) -> bool {
+        self.just_pressed.contains(&button.into())
+    }
+
+    /// Whether `button` went up this frame.
+    pub fn released(&self, button: impl Into<Button>) -> bool {
+        self.just_released.contains(&button.into())
+    }
+
+    /// Whether any button bound to `action` is held down.
+    pub fn action_pressed(&self, action: &str) -> bool {
+        self.actions
+            .buttons(action)
+            .iter()
+            .any(|button| self.down.contains(button))
+    }
+
+    /// How strongly `action` is triggered, from 0 to 1: 1 for a pressed button, the tilt of an
+    /// axis.
+    pub fn action_value(&self, action: &str) -> f32 {
+        self.actions
+            .buttons(action)
+            .iter()
+            .map(|&button| match button {
+                Button::GamepadAxis(half) => {
+                    half.value(self.axes.get(&half.axis).copied().unwrap_or(0.0))
+                }
+                button => f32::from(u8::from(self.down.contains(&button))),
+            })
+            .fold(0.0, f32::max)
+    }
+
+    /// Whether a button bound to `action` went down this frame.
+    pub fn action_just_pressed(&self, action: &str) -> bool {
+        self.actions
+            .buttons(action)
+            .iter()
+            .any(|button| self.just_pressed.contains(button))
+    }
+
+    /// Whether a button bound to `action` went up this frame.
+    pub fn action_released(&self, action: &str) -> bool {
+        self.actions
+            .buttons(action)
+            .iter()
+            .any(|button| self.just_released.contains(button))
+    }
+
+    /// The cursor position in physical pixels, if it is over the window.
+    pub fn cursor_position(&self) -> Option<(f64, f64)> {
+        self.cursor_position
+    }
+
+    /// How far the cursor moved this frame, in physical pixels.
+    pub fn cursor_delta(&self) -> (f64, f64) {
+        self.cursor_delta
+    }
+
+    /// Raw mouse motion this frame, which unlike the cursor isn't stopped by the window's edges.
+    pub fn mouse_motion(&self) -> (f64, f64) {
+        self.mouse_motion
+    }
+
+    /// Scroll wheel movement this frame in lines, positive when scrolling up.
+    pub fn scroll_lines(&self) -> f32 {
+        self.scroll_lines
+    }
+}
@@ -0,0 +1,92 @@
+use std::sync::OnceLock;
+
+use glam::{Mat4, Quat, Vec3};
+
+use crate::vertex::{Vertex, VertexLayout, VertexType};
+
+/// A placement of the mesh in the world, drawn in the same call as every other instance.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Instance {
+    pub transform: Mat4,
+    /// Multiplied with the vertex colors.
+    pub color: Vec3,
+}
+
+impl Default for Instance {
+    fn default() -> Self {
+        Self {
+            transform: Mat4::IDENTITY,
+            color: Vec3::ONE,
+        }
+    }
+}
+
+impl Instance {
+    /// A `columns` x `rows` grid in the XZ plane centred on the origin, tinted from one corner
+    /// to the other and rotated a little more in every cell.
+    pub fn grid(columns: u32, rows: u32, spacing: f32) -> Vec<Instance> {
+        let offset = Vec3::new(columns as f32 - 1.0, 0.0, rows as f32 - 1.0) * spacing * 0.5;
+        (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| (column, row)))
+            .map(|(column, row)| {
+                let u = column as f32 / columns.max(2).saturating_sub(1) as f32;
+                let v = row as f32 / rows.max(2).saturating_sub(1) as f32;
+                let position = Vec3::new(column as f32, 0.0, row as f32) * spacing - offset;
+                let rotation = Quat::from_rotation_y((u + v) * std::f32::consts::PI);
+                Instance {
+                    transform: Mat4::from_rotation_translation(rotation, position),
+                    color: Vec3::new(0.4 + 0.6 * u, 0.6, 0.4 + 0.6 * v),
+                }
+            })
+            .collect()
+    }
+
+    /// The instance as it is drawn, having moved from `previous_transform` since the previous
+    /// frame.
+    pub fn to_raw(&self, previous_transform: Mat4) -> InstanceRaw {
+        InstanceRaw {
+            model: self.transform.to_cols_array_2d(),
+            color: self.color.extend(1.0).to_array(),
+            previous_model: previous_transform.to_cols_array_2d(),
+        }
+    }
+}
+
+/// The per-instance vertex data, read with [`wgpu::VertexStepMode::Instance`].
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct InstanceRaw {
+    pub model: [[f32; 4]; 4],
+    pub color: [f32; 4],
+    /// The model matrix of the previous frame, for the motion vectors.
+    pub previous_model: [[f32; 4]; 4],
+}
+
+impl VertexType for InstanceRaw {
+    fn vertex_layout() -> &'static VertexLayout {
+        static LAYOUT: OnceLock<VertexLayout> = OnceLock::new();
+        LAYOUT.get_or_init(|| {
+            // Follows the attributes of `Vertex`.
+            let layout = VertexLayout::new(
+                wgpu::VertexStepMode::Instance,
+                Vertex::vertex_layout().next_location(),
+            )
+            .with_mat4(["model_0", "model_1", "model_2", "model_3"])
+            .with("color", wgpu::VertexFormat::Float32x4)
+            .with_mat4([
+                "previous_model_0",
+                "previous_model_1",
+                "previous_model_2",
+                "previous_model_3",
+            ]);
+            debug_assert_eq!(layout.stride(), std::mem::size_of::<Self>() as u64);
+            layout
+        })
+    }
+}
+
+impl InstanceRaw {
+    pub fn layout() -> wgpu::VertexBufferLayout<'static> {
+        Self::vertex_layout().buffer_layout()
+    }
+}
@@ -0,0 +1,162 @@
+use std::sync::Arc;
+
+use wgpu::util::DeviceExt;
+
+use crate::post_process::{self, Effect, EffectContext, EffectPipelineKey};
+use crate::shader::{self, Preprocessor};
+use crate::texture::Texture;
+
+/// `LensEffects` in `lens_effects.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct LensEffectsUniform {
+    chromatic_aberration: f32,
+    vignette: f32,
+    vignette_radius: f32,
+    grain: f32,
+    time: f32,
+    _padding: [f32; 3],
+}
+
+/// Stylized lens and film imperfections, an [`Effect`] meant to run last on the tonemapped
+/// image. Each of them can be turned off on its own.
+pub struct LensEffects {
+    pub chromatic_aberration: bool,
+    /// How far red and blue are pulled apart in the corners, in pixels.
+    pub chromatic_aberration_strength: f32,
+    pub vignette: bool,
+    /// How dark the corners get, from 0 to 1.
+    pub vignette_intensity: f32,
+    /// Where the vignette starts, from 0 at the center to 1 in the corners.
+    pub vignette_radius: f32,
+    pub grain: bool,
+    /// The amplitude of the grain, in sRGB.
+    pub grain_intensity: f32,
+    time: f32,
+    buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline_layout: Arc<wgpu::PipelineLayout>,
+    shader_module: Arc<wgpu::ShaderModule>,
+}
+
+impl LensEffects {
+    /// Compiles `lens_effects.wgsl` with `preprocessor`.
+    pub fn new(device: &wgpu::Device, preprocessor: &Preprocessor) -> anyhow::Result<Self> {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("lens effects"),
+            contents: bytemuck::bytes_of(&LensEffectsUniform::default()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let [input, input_sampler] = post_process::input_layout_entries();
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("lens effects"),
+            entries: &[
+                input,
+                input_sampler,
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = Arc::new(device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("lens effects"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            },
+        ));
+        let shader_module = Arc::new(shader::create_module(
+            device,
+            preprocessor,
+            "lens_effects.wgsl",
+        )?);
+
+        Ok(Self {
+            chromatic_aberration: true,
+            chromatic_aberration_strength: 3.0,
+            vignette: true,
+            vignette_intensity: 0.4,
+            vignette_radius: 0.5,
+            grain: true,
+            grain_intensity: 0.05,
+            time: 0.0,
+            buffer,
+            bind_group_layout,
+            pipeline_layout,
+            shader_module,
+        })
+    }
+
+    /// Animates the grain with the time since startup in seconds, once per frame before
+    /// [`Effect::update`].
+    pub fn set_time(&mut self, time: f32) {
+        self.time = time;
+    }
+}
+
+impl Effect for LensEffects {
+    fn name(&self) -> &'static str {
+        "lens effects"
+    }
+
+    fn update(&mut self, queue: &wgpu::Queue) {
+        let enabled = |enabled: bool, value: f32| if enabled { value } else { 0.0 };
+        let uniform = LensEffectsUniform {
+            chromatic_aberration: enabled(
+                self.chromatic_aberration,
+                self.chromatic_aberration_strength,
+            ),
+            vignette: enabled(self.vignette, self.vignette_intensity),
+            vignette_radius: self.vignette_radius.min(0.99),
+            grain: enabled(self.grain, self.grain_intensity),
+            time: self.time,
+            _padding: [0.0; 3],
+        };
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&uniform));
+    }
+
+    fn render(
+        &mut self,
+        context: &mut EffectContext,
+        input: &Texture,
+        output: &wgpu::TextureView,
+        format: wgpu::TextureFormat,
+    ) {
+        let key = EffectPipelineKey {
+            effect: self.name(),
+            format,
+        };
+        let pipeline = context.pipelines.get(
+            &key,
+            &*post_process::pipeline_builder(
+                "lens effects",
+                self.pipeline_layout.clone(),
+                self.shader_module.clone(),
+                format,
+            ),
+        );
+        let [input, input_sampler] = post_process::input_bind_group_entries(input);
+        let bind_group = context
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("lens effects"),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    input,
+                    input_sampler,
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: self.buffer.as_entire_binding(),
+                    },
+                ],
+            });
+        context.draw(self.name(), output, &pipeline, &bind_group);
+    }
+}
@@ -0,0 +1,163 @@
+pub mod animation;
+pub mod app;
+pub mod assets;
+pub mod atlas;
+pub mod bindless;
+pub mod camera;
+pub mod camera2d;
+pub mod capture;
+pub mod cli;
+pub mod color_grading;
+pub mod config;
+pub mod cpu_particles;
+pub mod culling;
+pub mod curve;
+pub mod debug_draw;
+pub mod debug_ui;
+pub mod depth_of_field;
+pub mod fullscreen;
+pub mod fxaa;
+pub mod gamepad;
+pub mod gizmo;
+pub mod globals;
+#[cfg(feature = "golden-tests")]
+pub mod golden;
+pub mod gpu_sort;
+pub mod headless;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod hot_reload;
+pub mod ibl;
+pub mod input;
+pub mod instance;
+pub mod lens_effects;
+pub mod light;
+pub mod lod;
+pub mod material;
+pub mod math;
+pub mod mesh;
+pub mod mesh_allocator;
+pub mod mipmap;
+pub mod morph;
+pub mod motion_blur;
+pub mod msdf;
+pub mod nine_patch;
+pub mod occlusion;
+pub mod oit;
+pub mod painter;
+pub mod particles;
+pub mod picking;
+pub mod pipeline_cache;
+pub mod post_process;
+pub mod prepass;
+pub mod primitives;
+pub mod profiler;
+pub mod recorder;
+pub mod render_bundle;
+pub mod render_graph;
+pub mod render_queue;
+pub mod renderer;
+pub mod scene;
+pub mod shader;
+pub mod shadow;
+pub mod skeleton;
+pub mod skinning;
+pub mod skybox;
+pub mod sprite;
+pub mod sprite_animation;
+pub mod ssao;
+pub mod stats;
+pub mod svg;
+pub mod taa;
+pub mod terrain;
+pub mod text;
+pub mod texture;
+pub mod tilemap;
+pub mod timestep;
+pub mod tonemap;
+pub mod uniform;
+pub mod uniform_arena;
+pub mod upload;
+pub mod vertex;
+
+pub use app::State;
+pub use camera::Camera;
+pub use globals::Globals;
+pub use instance::Instance;
+pub use mesh::MeshData;
+pub use renderer::{Renderer, RendererOptions};
+pub use scene::Scene;
+pub use texture::{SamplerDesc, Texture};
+pub use vertex::Vertex;
+
+/// Runs the demo application until the window is closed, configured from the command line, see
+/// [`cli::Args`].
+#[cfg(not(target_arch = "wasm32"))]
+pub fn run() -> anyhow::Result<()> {
+    let args = <cli::Args as clap::Parser>::parse();
+    if let Some(path) = &args.neutral_lut {
+        let lut = color_grading::LutData::neutral(color_grading::NEUTRAL_LUT_SIZE);
+        return assets::lut::save(&lut, path);
+    }
+    let mesh = args.mesh.as_ref().map(assets::load_mesh).transpose()?;
+    let options = args.renderer_options();
+
+    if args.headless {
+        let (width, height) = args.size.unwrap_or(headless::DEFAULT_SIZE);
+        let options = headless::HeadlessOptions {
+            width,
+            height,
+            frames: args.frames,
+            out: args.out,
+            renderer: options,
+        };
+        return headless::run(&options, mesh.as_ref());
+    }
+
+    let event_loop = winit::event_loop::EventLoop::with_user_event().build()?;
+    let config = config::Config::load(&args.config)?;
+    let mut state = State::new(&event_loop, mesh, options)
+        .with_config(config)
+        .watch_config(&args.config);
+    if let Some((width, height)) = args.size {
+        state = state.with_window_size(width, height);
+    }
+
+    event_loop.run_app(&mut state)?;
+
+    Ok(())
+}
+
+/// The entry point of the Android application, called by `android-activity` on its own thread.
+#[cfg(target_os = "android")]
+#[no_mangle]
+fn android_main(app: winit::platform::android::activity::AndroidApp) {
+    use winit::platform::android::EventLoopBuilderExtAndroid;
+
+    let result = winit::event_loop::EventLoop::with_user_event()
+        .with_android_app(app)
+        .build()
+        .map_err(anyhow::Error::from)
+        .and_then(|event_loop| {
+            let mut state = State::new(&event_loop, None, RendererOptions::default());
+            Ok(event_loop.run_app(&mut state)?)
+        });
+    if let Err(err) = result {
+        eprintln!("{err:#}");
+    }
+}
+
+/// Runs the demo application in a canvas appended to the page, called from JavaScript once the
+/// module has loaded.
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen::prelude::wasm_bindgen]
+pub fn run_web() -> Result<(), wasm_bindgen::JsError> {
+    use winit::platform::web::EventLoopExtWebSys;
+
+    console_error_panic_hook::set_once();
+    let event_loop = winit::event_loop::EventLoop::with_user_event().build()?;
+    let state = State::new(&event_loop, None, RendererOptions::default());
+    // Unlike `run_app`, this returns right away and leaves the event loop to the browser.
+    event_loop.spawn_app(state);
+
+    Ok(())
+}
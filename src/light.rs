@@ -0,0 +1,433 @@
+use glam::Vec3;
+use wgpu::util::DeviceExt;
+
+use crate::camera::Camera;
+use crate::ibl::Ibl;
+use crate::shader::Preprocessor;
+use crate::shadow::{PointShadows, ShadowMap};
+
+/// The most point and spot lights drawn at once, `MAX_LIGHTS` in `lighting.wgsl`.
+pub const MAX_LIGHTS: usize = 64;
+
+/// A directional light plus the image-based ambient lighting's strength, bound at
+/// `@group(3) @binding(0)`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct DirectionalLight {
+    /// The direction towards the light, normalized in the shader.
+    pub direction: [f32; 3],
+    /// Scales the light reflected from the environment map.
+    pub ambient: f32,
+    /// Linear RGB.
+    pub color: [f32; 3],
+    /// Scales `color`, with 1 giving white surfaces facing the light a brightness of 1/π.
+    pub intensity: f32,
+}
+
+impl Default for DirectionalLight {
+    fn default() -> Self {
+        Self {
+            direction: [0.4, 1.0, 0.6],
+            ambient: 1.0,
+            color: [1.0; 3],
+            intensity: 3.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightKind {
+    /// Shines equally in every direction.
+    Point,
+    /// Shines in a cone around `direction`, fading out between the inner and outer angles, in
+    /// radians from the axis.
+    Spot {
+        direction: Vec3,
+        inner_angle: f32,
+        outer_angle: f32,
+    },
+}
+
+/// A point or spot light, whose intensity falls off with the square of the distance and reaches
+/// zero at `range`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Light {
+    pub position: Vec3,
+    /// Linear RGB.
+    pub color: [f32; 3],
+    pub intensity: f32,
+    pub range: f32,
+    pub kind: LightKind,
+    /// Whether the light casts shadows, rendered into a shadow cube map.
+    pub shadows: bool,
+}
+
+impl Light {
+    pub fn point(position: Vec3, color: [f32; 3], intensity: f32, range: f32) -> Self {
+        Self {
+            position,
+            color,
+            intensity,
+            range,
+            kind: LightKind::Point,
+            shadows: false,
+        }
+    }
+
+    /// A spot light at `position` pointing towards `target`.
+    pub fn spot(
+        position: Vec3,
+        target: Vec3,
+        color: [f32; 3],
+        intensity: f32,
+        range: f32,
+        outer_angle: f32,
+    ) -> Self {
+        Self {
+            position,
+            color,
+            intensity,
+            range,
+            kind: LightKind::Spot {
+                direction: (target - position).normalize_or_zero(),
+                inner_angle: outer_angle * 0.8,
+                outer_angle,
+            },
+            shadows: false,
+        }
+    }
+
+    /// Makes the light cast shadows.
+    pub fn with_shadows(mut self) -> Self {
+        self.shadows = true;
+        self
+    }
+
+    pub fn to_raw(&self) -> LightRaw {
+        let (kind, direction, cone) = match self.kind {
+            LightKind::Point => (LIGHT_POINT, Vec3::ZERO, [-1.0, -1.0]),
+            LightKind::Spot {
+                direction,
+                inner_angle,
+                outer_angle,
+            } => (
+                LIGHT_SPOT,
+                direction,
+                [inner_angle.cos(), outer_angle.cos()],
+            ),
+        };
+        LightRaw {
+            position: self.position.into(),
+            kind,
+            direction: direction.into(),
+            range: self.range,
+            color: self.color,
+            intensity: self.intensity,
+            cone,
+            shadow: -1,
+            _padding: 0.0,
+        }
+    }
+}
+
+const LIGHT_POINT: u32 = 0;
+const LIGHT_SPOT: u32 = 1;
+
+/// The layout of a [`Light`] in the light list.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightRaw {
+    position: [f32; 3],
+    kind: u32,
+    direction: [f32; 3],
+    range: f32,
+    color: [f32; 3],
+    intensity: f32,
+    /// The cosines of the inner and outer cone angles of spot lights.
+    cone: [f32; 2],
+    /// The index of the light's shadow cube map, or -1 without shadows.
+    shadow: i32,
+    _padding: f32,
+}
+
+/// The header of the light list, followed by the lights.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightListHeader {
+    count: u32,
+    _padding: [u32; 3],
+}
+
+/// The directional light with its shadow map, the list of point and spot lights, the
+/// prefiltered environment and the screen-space ambient occlusion, bound at `@group(3)`.
+///
+/// The list lives in a storage buffer, or in a uniform buffer of [`MAX_LIGHTS`] lights where
+/// fragment shaders can't read storage buffers, e.g. on WebGL2.
+pub struct Lighting {
+    pub directional: DirectionalLight,
+    shadow_map: ShadowMap,
+    point_shadows: PointShadows,
+    ibl: Ibl,
+    lights: Vec<Light>,
+    lights_changed: bool,
+    uniform_list: bool,
+    directional_buffer: wgpu::Buffer,
+    list_buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+}
+
+impl Lighting {
+    /// Compiles the shadow and environment filtering passes with `preprocessor`. The scene is
+    /// lit with the ambient occlusion in `ambient_occlusion`, see
+    /// [`Lighting::set_ambient_occlusion`].
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        downlevel: &wgpu::DownlevelCapabilities,
+        preprocessor: &Preprocessor,
+        ambient_occlusion: &wgpu::TextureView,
+    ) -> anyhow::Result<Self> {
+        let uniform_list = !downlevel
+            .flags
+            .contains(wgpu::DownlevelFlags::FRAGMENT_STORAGE);
+        let directional = DirectionalLight::default();
+        let directional_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("directional light"),
+            contents: bytemuck::bytes_of(&directional),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let (list_usage, list_binding) = if uniform_list {
+            (
+                wgpu::BufferUsages::UNIFORM,
+                wgpu::BufferBindingType::Uniform,
+            )
+        } else {
+            (
+                wgpu::BufferUsages::STORAGE,
+                wgpu::BufferBindingType::Storage { read_only: true },
+            )
+        };
+        let list_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("lights"),
+            size: (std::mem::size_of::<LightListHeader>()
+                + MAX_LIGHTS * std::mem::size_of::<LightRaw>()) as u64,
+            usage: list_usage | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let shadow_map = ShadowMap::new(device, preprocessor)?;
+        let point_shadows = PointShadows::new(device, downlevel, preprocessor)?;
+        let ibl = Ibl::new(device, queue, preprocessor)?;
+
+        let entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let mut layout_entries = vec![
+            entry(0, wgpu::BufferBindingType::Uniform),
+            entry(1, list_binding),
+        ];
+        layout_entries.extend(ShadowMap::layout_entries(2));
+        layout_entries.push(point_shadows.layout_entry(5));
+        layout_entries.extend(Ibl::layout_entries(6));
+        layout_entries.push(wgpu::BindGroupLayoutEntry {
+            binding: 10,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("lighting"),
+            entries: &layout_entries,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("lighting"),
+            layout: &bind_group_layout,
+            entries: &bind_group_entries(
+                &directional_buffer,
+                &list_buffer,
+                &shadow_map,
+                &point_shadows,
+                &ibl,
+                ambient_occlusion,
+            ),
+        });
+
+        Ok(Self {
+            directional,
+            shadow_map,
+            point_shadows,
+            ibl,
+            lights: Vec::new(),
+            // The buffer starts out uninitialized.
+            lights_changed: true,
+            uniform_list,
+            directional_buffer,
+            list_buffer,
+            bind_group_layout,
+            bind_group,
+        })
+    }
+
+    /// Defines what `lighting.wgsl` needs to match the bind group layout.
+    pub fn configure_preprocessor(&self, preprocessor: Preprocessor) -> Preprocessor {
+        let preprocessor = self.point_shadows.configure_preprocessor(preprocessor);
+        if self.uniform_list {
+            preprocessor.define("LIGHTS_IN_UNIFORM", "")
+        } else {
+            preprocessor
+        }
+    }
+
+    pub fn lights(&self) -> &[Light] {
+        &self.lights
+    }
+
+    /// Replaces the point and spot lights. Only the first [`MAX_LIGHTS`] are drawn.
+    pub fn set_lights(&mut self, lights: Vec<Light>) {
+        if lights.len() > MAX_LIGHTS {
+            eprintln!("only drawing {MAX_LIGHTS} of {} lights", lights.len());
+        }
+        let shadowed = lights.iter().filter(|light| light.shadows).count();
+        if shadowed > self.point_shadows.capacity() {
+            eprintln!(
+                "only {} of {shadowed} lights cast shadows",
+                self.point_shadows.capacity()
+            );
+        }
+        self.lights = lights;
+        self.lights_changed = true;
+    }
+
+    pub fn shadow_map(&self) -> &ShadowMap {
+        &self.shadow_map
+    }
+
+    pub fn shadow_map_mut(&mut self) -> &mut ShadowMap {
+        &mut self.shadow_map
+    }
+
+    pub fn point_shadows(&self) -> &PointShadows {
+        &self.point_shadows
+    }
+
+    pub fn ibl(&self) -> &Ibl {
+        &self.ibl
+    }
+
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    /// Recreates the bind group with a new ambient occlusion texture, e.g. after it was resized.
+    pub fn set_ambient_occlusion(
+        &mut self,
+        device: &wgpu::Device,
+        ambient_occlusion: &wgpu::TextureView,
+    ) {
+        self.bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("lighting"),
+            layout: &self.bind_group_layout,
+            entries: &bind_group_entries(
+                &self.directional_buffer,
+                &self.list_buffer,
+                &self.shadow_map,
+                &self.point_shadows,
+                &self.ibl,
+                ambient_occlusion,
+            ),
+        });
+    }
+
+    /// Uploads the directional light with its shadow cascades fitted to `camera` and
+    /// `scene_bounds`, and the light list if it changed.
+    pub fn update(&mut self, queue: &wgpu::Queue, camera: &Camera, scene_bounds: (Vec3, Vec3)) {
+        queue.write_buffer(
+            &self.directional_buffer,
+            0,
+            bytemuck::bytes_of(&self.directional),
+        );
+        self.shadow_map.update(
+            queue,
+            camera,
+            Vec3::from(self.directional.direction),
+            scene_bounds,
+        );
+        if !std::mem::take(&mut self.lights_changed) {
+            return;
+        }
+
+        // Shadow cube maps go to the first lights asking for them.
+        let mut shadowed = Vec::new();
+        let lights: Vec<_> = self
+            .lights
+            .iter()
+            .take(MAX_LIGHTS)
+            .map(|light| {
+                let mut raw = light.to_raw();
+                if light.shadows && shadowed.len() < self.point_shadows.capacity() {
+                    raw.shadow = shadowed.len() as i32;
+                    shadowed.push((light.position, light.range));
+                }
+                raw
+            })
+            .collect();
+        self.point_shadows.update(queue, &shadowed);
+        let header = LightListHeader {
+            count: lights.len() as u32,
+            _padding: [0; 3],
+        };
+        queue.write_buffer(&self.list_buffer, 0, bytemuck::bytes_of(&header));
+        if !lights.is_empty() {
+            queue.write_buffer(
+                &self.list_buffer,
+                std::mem::size_of::<LightListHeader>() as u64,
+                bytemuck::cast_slice(&lights),
+            );
+        }
+    }
+}
+
+/// The entries of the lighting bind group, in the order of its layout.
+fn bind_group_entries<'a>(
+    directional_buffer: &'a wgpu::Buffer,
+    list_buffer: &'a wgpu::Buffer,
+    shadow_map: &'a ShadowMap,
+    point_shadows: &'a PointShadows,
+    ibl: &'a Ibl,
+    ambient_occlusion: &'a wgpu::TextureView,
+) -> Vec<wgpu::BindGroupEntry<'a>> {
+    let mut entries = vec![
+        wgpu::BindGroupEntry {
+            binding: 0,
+            resource: directional_buffer.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+            binding: 1,
+            resource: list_buffer.as_entire_binding(),
+        },
+    ];
+    entries.extend(shadow_map.bind_group_entries(2));
+    entries.push(point_shadows.bind_group_entry(5));
+    entries.extend(ibl.bind_group_entries(6));
+    entries.push(wgpu::BindGroupEntry {
+        binding: 10,
+        resource: wgpu::BindingResource::TextureView(ambient_occlusion),
+    });
+    entries
+}
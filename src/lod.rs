@@ -0,0 +1,227 @@
+use std::time::Duration;
+
+use glam::Mat4;
+
+use crate::camera::Camera;
+use crate::instance::InstanceRaw;
+use crate::math::BoundingSphere;
+use crate::mesh::{MaterialBindings, Mesh};
+use crate::upload::Upload;
+
+/// A coarser version of the renderer's mesh, drawn for instances covering little of the screen.
+pub struct LodLevel {
+    pub mesh: Mesh,
+    /// The screen coverage below which the level is drawn: the diameter of the instance's
+    /// bounding sphere as a fraction of the viewport's height.
+    pub coverage: f32,
+}
+
+/// The level an instance is drawn with, and the one it is fading out from.
+#[derive(Clone, Copy, Debug, Default)]
+struct LodState {
+    level: usize,
+    previous: Option<usize>,
+    /// From zero to one over the crossfade.
+    fade: f32,
+}
+
+/// The instances drawn with one level, uploaded every frame.
+#[derive(Default)]
+struct LodBatch {
+    instances: Vec<InstanceRaw>,
+    buffer: Option<wgpu::Buffer>,
+}
+
+/// Picks a level of detail for each instance by how much of the screen its bounding sphere
+/// covers, level zero being the renderer's mesh and the others [`LodLevel`]s. An instance only
+/// moves to another level once its coverage is past the threshold by `hysteresis`, so that it
+/// doesn't flicker between two levels at the threshold, and can crossfade between them with a
+/// dither pattern instead of popping.
+pub struct Lod {
+    /// Ordered from the finest to the coarsest.
+    levels: Vec<LodLevel>,
+    /// The fraction of the threshold the coverage has to be past it by before switching.
+    pub hysteresis: f32,
+    /// How long instances crossfade between levels, switching right away when zero.
+    pub crossfade: Duration,
+    /// By instance index.
+    states: Vec<LodState>,
+    /// By level.
+    batches: Vec<LodBatch>,
+}
+
+impl Default for Lod {
+    fn default() -> Self {
+        Self {
+            levels: Vec::new(),
+            hysteresis: 0.1,
+            crossfade: Duration::from_millis(250),
+            states: Vec::new(),
+            batches: Vec::new(),
+        }
+    }
+}
+
+impl Lod {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether there are no levels beyond the renderer's mesh, which is then drawn for every
+    /// instance.
+    pub fn is_empty(&self) -> bool {
+        self.levels.is_empty()
+    }
+
+    pub fn levels(&self) -> &[LodLevel] {
+        &self.levels
+    }
+
+    /// Replaces the levels, sorting them from the finest to the coarsest. Every instance starts
+    /// over at level zero.
+    pub fn set_levels(&mut self, mut levels: Vec<LodLevel>) {
+        levels.sort_by(|a, b| b.coverage.total_cmp(&a.coverage));
+        self.levels = levels;
+        self.states.clear();
+        self.batches.clear();
+        self.batches
+            .resize_with(self.levels.len() + 1, LodBatch::default);
+    }
+
+    /// The number of instances drawn with each level, counting those fading out too.
+    pub fn counts(&self) -> Vec<usize> {
+        self.batches
+            .iter()
+            .map(|batch| batch.instances.len())
+            .collect()
+    }
+
+    /// Picks the level of each of `instances` drawn this frame, by their index into `all`, as
+    /// seen from `camera`, `delta_time` after the last update, for [`Lod::upload`]. `sphere` is
+    /// the bounding sphere of the renderer's mesh.
+    pub fn update(
+        &mut self,
+        camera: &Camera,
+        sphere: &BoundingSphere,
+        all: &[InstanceRaw],
+        instances: &[u32],
+        delta_time: Duration,
+    ) {
+        if self.levels.is_empty() {
+            return;
+        }
+        self.states.resize(all.len(), LodState::default());
+        for batch in &mut self.batches {
+            batch.instances.clear();
+        }
+        let fade_step = if self.crossfade.is_zero() {
+            1.0
+        } else {
+            delta_time.as_secs_f32() / self.crossfade.as_secs_f32()
+        };
+        let half_height = (camera.fovy * 0.5).tan();
+
+        for &index in instances {
+            let raw = all[index as usize];
+            let world = sphere.transformed(Mat4::from_cols_array_2d(&raw.model));
+            let distance = world.center.distance(camera.eye).max(camera.znear);
+            let coverage = world.radius / (distance * half_height);
+
+            let level = self.select(coverage, self.states[index as usize].level);
+            let state = &mut self.states[index as usize];
+            if level != state.level {
+                *state = LodState {
+                    level,
+                    previous: Some(state.level),
+                    fade: 0.0,
+                };
+            }
+            state.fade = (state.fade + fade_step).min(1.0);
+            if state.fade >= 1.0 {
+                state.previous = None;
+            }
+
+            // The alpha of the instance's color is read as the fade by the scene shader:
+            // positive while fading in and negative while fading out.
+            let state = *state;
+            let mut faded = |level: usize, fade: f32| {
+                let mut raw = raw;
+                raw.color[3] = fade;
+                self.batches[level].instances.push(raw);
+            };
+            match state.previous {
+                Some(previous) => {
+                    faded(state.level, state.fade);
+                    faded(previous, -state.fade);
+                }
+                None => faded(state.level, 1.0),
+            }
+        }
+    }
+
+    /// Uploads the instances of each level picked by the last update, growing their buffers
+    /// where they're too small.
+    pub fn upload(&mut self, upload: &mut Upload) {
+        for batch in &mut self.batches {
+            if batch.instances.is_empty() {
+                continue;
+            }
+            let size = std::mem::size_of_val(batch.instances.as_slice()) as wgpu::BufferAddress;
+            if batch
+                .buffer
+                .as_ref()
+                .is_none_or(|buffer| buffer.size() < size)
+            {
+                batch.buffer = Some(upload.device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("lod instances"),
+                    size: size.next_power_of_two(),
+                    usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }));
+            }
+            if let Some(buffer) = &batch.buffer {
+                upload.write(buffer, 0, bytemuck::cast_slice(&batch.instances));
+            }
+        }
+    }
+
+    /// The level for `coverage`, staying at `current` unless the coverage is past the
+    /// thresholds by the hysteresis.
+    fn select(&self, coverage: f32, current: usize) -> usize {
+        let level = |coverage: f32| {
+            self.levels
+                .iter()
+                .take_while(|level| coverage < level.coverage)
+                .count()
+        };
+        let coarser = level(coverage * (1.0 + self.hysteresis));
+        if coarser > current {
+            return coarser;
+        }
+        let finer = level(coverage * (1.0 - self.hysteresis));
+        if finer < current {
+            return finer;
+        }
+        current
+    }
+
+    /// Draws the instances of the last update with their level, `mesh` being level zero.
+    pub fn draw(
+        &self,
+        render_pass: &mut wgpu::RenderPass,
+        mesh: &Mesh,
+        materials: Option<MaterialBindings>,
+    ) {
+        let meshes = std::iter::once(mesh).chain(self.levels.iter().map(|level| &level.mesh));
+        for (mesh, batch) in meshes.zip(&self.batches) {
+            if let (Some(buffer), false) = (&batch.buffer, batch.instances.is_empty()) {
+                mesh.draw(
+                    render_pass,
+                    buffer,
+                    0..batch.instances.len() as u32,
+                    materials,
+                );
+            }
+        }
+    }
+}
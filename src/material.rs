@@ -0,0 +1,487 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Weak};
+
+use crate::mipmap::MipmapGenerator;
+use crate::texture::{SamplerDesc, Texture};
+
+/// The constant factors of a metallic-roughness material, multiplied with its textures like in
+/// glTF.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MaterialFactors {
+    /// Linear RGBA.
+    pub base_color: [f32; 4],
+    pub metallic: f32,
+    pub roughness: f32,
+    /// Linear RGB.
+    pub emissive: [f32; 3],
+    /// Scales the X and Y of the tangent-space normals read from the normal texture.
+    pub normal_scale: f32,
+    /// How much of the occlusion texture applies, from 0 (none) to 1 (all of it).
+    pub occlusion_strength: f32,
+}
+
+/// The glTF defaults, a fully metallic and fully rough white surface.
+impl Default for MaterialFactors {
+    fn default() -> Self {
+        Self {
+            base_color: [1.0; 4],
+            metallic: 1.0,
+            roughness: 1.0,
+            emissive: [0.0; 3],
+            normal_scale: 1.0,
+            occlusion_strength: 1.0,
+        }
+    }
+}
+
+/// How a material covers what's behind it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum AlphaMode {
+    /// Hides everything behind it, ignoring the alpha of the base color.
+    #[default]
+    Opaque,
+    /// Blended over what's behind it by the alpha of the base color, drawn back to front after
+    /// the opaque materials.
+    Blend,
+    /// Blended over what's behind it by the alpha of the base color, with weighted blended
+    /// order-independent transparency so that overlapping surfaces don't need sorting, see
+    /// [`crate::oit`]. Cheaper than [`AlphaMode::Blend`] for many overlapping surfaces, but
+    /// only approximates their order.
+    WeightedBlended,
+}
+
+/// A material on the CPU, as loaded from an asset. Textures may be shared between materials.
+#[derive(Clone, Debug, Default)]
+pub struct MaterialData {
+    pub name: Option<String>,
+    pub factors: MaterialFactors,
+    /// sRGB color and linear alpha.
+    pub base_color_texture: Option<Arc<image::RgbaImage>>,
+    /// Linear roughness in green and metalness in blue.
+    pub metallic_roughness_texture: Option<Arc<image::RgbaImage>>,
+    /// Tangent-space normals.
+    pub normal_texture: Option<Arc<image::RgbaImage>>,
+    /// Linear ambient occlusion in red.
+    pub occlusion_texture: Option<Arc<image::RgbaImage>>,
+    /// sRGB emitted color.
+    pub emissive_texture: Option<Arc<image::RgbaImage>>,
+    /// Whether back faces are drawn too, rather than culled.
+    pub double_sided: bool,
+    pub alpha_mode: AlphaMode,
+}
+
+/// The textures of a [`Material`], missing ones are replaced by textures that leave the
+/// factors unchanged.
+#[derive(Default)]
+pub struct MaterialTextures {
+    pub base_color: Option<Texture>,
+    pub metallic_roughness: Option<Texture>,
+    pub normal: Option<Texture>,
+    pub occlusion: Option<Texture>,
+    pub emissive: Option<Texture>,
+}
+
+/// The colors of the 1×1 textures replacing missing ones, which leave the factors unchanged,
+/// and whether they're sRGB, in the order of [`Material::from_textures`].
+const FALLBACK_COLORS: [([u8; 4], bool); 5] = [
+    ([255; 4], true),
+    ([255; 4], false),
+    // A normal pointing straight out of the surface.
+    ([128, 128, 255, 255], false),
+    ([255; 4], false),
+    ([255; 4], true),
+];
+
+/// The layout of [`MaterialFactors`] in `MaterialParams` in `shader.wgsl`, which the renderer
+/// pushes into its uniform arena every frame rather than each material owning a buffer.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MaterialUniform {
+    base_color: [f32; 4],
+    emissive: [f32; 3],
+    metallic: f32,
+    roughness: f32,
+    normal_scale: f32,
+    occlusion_strength: f32,
+    /// The first of the material's textures in the bindless arrays, see [`crate::bindless`].
+    texture_index: u32,
+}
+
+impl From<&MaterialFactors> for MaterialUniform {
+    fn from(factors: &MaterialFactors) -> Self {
+        Self {
+            base_color: factors.base_color,
+            emissive: factors.emissive,
+            metallic: factors.metallic,
+            roughness: factors.roughness,
+            normal_scale: factors.normal_scale,
+            occlusion_strength: factors.occlusion_strength,
+            texture_index: 0,
+        }
+    }
+}
+
+impl MaterialUniform {
+    /// Points the shader at the material's textures in the bindless arrays.
+    pub fn with_texture_index(mut self, texture_index: u32) -> Self {
+        self.texture_index = texture_index;
+        self
+    }
+}
+
+/// A metallic-roughness material on the GPU, owning its textures and the bind group exposing
+/// them at `@group(2)`. Its factors are bound separately, see [`Material::uniform`].
+pub struct Material {
+    pub name: Option<String>,
+    factors: MaterialFactors,
+    double_sided: bool,
+    alpha_mode: AlphaMode,
+    /// Possibly shared with other materials.
+    textures: [Arc<Texture>; 5],
+    bind_group: wgpu::BindGroup,
+}
+
+impl Material {
+    /// The layout of a material's bind group: a texture and sampler pair each for the base
+    /// color, metallic-roughness, normal, occlusion and emissive textures, from binding 1.
+    pub fn bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        let mut entries = Vec::new();
+        for texture in 0..5 {
+            entries.push(wgpu::BindGroupLayoutEntry {
+                binding: 1 + texture * 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            });
+            entries.push(wgpu::BindGroupLayoutEntry {
+                binding: 2 + texture * 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            });
+        }
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("material"),
+            entries: &entries,
+        })
+    }
+
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        name: Option<String>,
+        factors: MaterialFactors,
+        textures: MaterialTextures,
+    ) -> Self {
+        let label = name.as_deref().unwrap_or("material");
+        let srgb_format = wgpu::TextureFormat::Rgba8UnormSrgb;
+        let linear = wgpu::TextureFormat::Rgba8Unorm;
+        let fallback = |color, format| Texture::from_color(device, queue, color, format, label);
+        let textures = [
+            (textures.base_color, FALLBACK_COLORS[0]),
+            (textures.metallic_roughness, FALLBACK_COLORS[1]),
+            (textures.normal, FALLBACK_COLORS[2]),
+            (textures.occlusion, FALLBACK_COLORS[3]),
+            (textures.emissive, FALLBACK_COLORS[4]),
+        ]
+        .map(|(texture, (color, srgb))| {
+            let format = if srgb { srgb_format } else { linear };
+            Arc::new(texture.unwrap_or_else(|| fallback(color, format)))
+        });
+        Self::from_textures(device, layout, name, factors, false, textures)
+    }
+
+    /// Creates a material around textures that may be shared with other materials, in the
+    /// order of the bindings: base color, metallic-roughness, normal, occlusion and emissive.
+    pub fn from_textures(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        name: Option<String>,
+        factors: MaterialFactors,
+        double_sided: bool,
+        textures: [Arc<Texture>; 5],
+    ) -> Self {
+        let label = name.as_deref().unwrap_or("material");
+        let mut entries = Vec::new();
+        for (index, texture) in textures.iter().enumerate() {
+            let binding = 1 + index as u32 * 2;
+            entries.push(wgpu::BindGroupEntry {
+                binding,
+                resource: wgpu::BindingResource::TextureView(&texture.view),
+            });
+            entries.push(wgpu::BindGroupEntry {
+                binding: binding + 1,
+                resource: wgpu::BindingResource::Sampler(&texture.sampler),
+            });
+        }
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(label),
+            layout,
+            entries: &entries,
+        });
+
+        Self {
+            name,
+            factors,
+            double_sided,
+            alpha_mode: AlphaMode::Opaque,
+            textures,
+            bind_group,
+        }
+    }
+
+    /// Uploads the textures of `data`, with full mip chains when given a [`MipmapGenerator`].
+    pub fn from_data(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        data: &MaterialData,
+        sampler: SamplerDesc,
+        mut mipmaps: Option<&mut MipmapGenerator>,
+    ) -> Self {
+        let label = data.name.as_deref().unwrap_or("material");
+        let mut upload = |image: &Option<Arc<image::RgbaImage>>, format| {
+            image.as_ref().map(|image| {
+                Texture::from_rgba(
+                    device,
+                    queue,
+                    image,
+                    format,
+                    label,
+                    sampler,
+                    mipmaps.as_deref_mut(),
+                )
+            })
+        };
+        let srgb = wgpu::TextureFormat::Rgba8UnormSrgb;
+        let linear = wgpu::TextureFormat::Rgba8Unorm;
+        let textures = MaterialTextures {
+            base_color: upload(&data.base_color_texture, srgb),
+            metallic_roughness: upload(&data.metallic_roughness_texture, linear),
+            normal: upload(&data.normal_texture, linear),
+            occlusion: upload(&data.occlusion_texture, linear),
+            emissive: upload(&data.emissive_texture, srgb),
+        };
+        let mut material = Self::new(
+            device,
+            queue,
+            layout,
+            data.name.clone(),
+            data.factors,
+            textures,
+        );
+        material.double_sided = data.double_sided;
+        material.alpha_mode = data.alpha_mode;
+        material
+    }
+
+    pub fn factors(&self) -> &MaterialFactors {
+        &self.factors
+    }
+
+    /// Whether back faces are drawn too, which needs a pipeline without culling.
+    pub fn double_sided(&self) -> bool {
+        self.double_sided
+    }
+
+    /// Which pass draws the material, see [`AlphaMode`].
+    pub fn alpha_mode(&self) -> AlphaMode {
+        self.alpha_mode
+    }
+
+    pub fn set_alpha_mode(&mut self, alpha_mode: AlphaMode) {
+        self.alpha_mode = alpha_mode;
+    }
+
+    /// Replaces the factors, uploaded with the next frame.
+    pub fn set_factors(&mut self, factors: MaterialFactors) {
+        self.factors = factors;
+    }
+
+    /// In the order of [`Material::from_textures`].
+    pub fn textures(&self) -> &[Arc<Texture>; 5] {
+        &self.textures
+    }
+
+    /// The factors as the shader reads them.
+    pub fn uniform(&self) -> MaterialUniform {
+        MaterialUniform::from(&self.factors)
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+}
+
+/// Identifies a texture of a [`MaterialCache`]: the address of the image it was uploaded from,
+/// or the color of a fallback texture, along with its format and sampler.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum TextureKey {
+    Image(usize, wgpu::TextureFormat, SamplerDesc),
+    Fallback([u8; 4], wgpu::TextureFormat),
+}
+
+/// Everything that distinguishes one material's bind group and pipeline from another's.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct MaterialKey {
+    /// The bits of the [`MaterialFactors`].
+    factors: [u32; 13],
+    /// The addresses of the textures.
+    textures: [usize; 5],
+    double_sided: bool,
+    alpha_mode: AlphaMode,
+}
+
+impl MaterialKey {
+    fn new(factors: &MaterialFactors, textures: &[Arc<Texture>; 5], data: &MaterialData) -> Self {
+        let mut bits = [0; 13];
+        let values = factors.base_color.iter().chain(&factors.emissive).chain([
+            &factors.metallic,
+            &factors.roughness,
+            &factors.normal_scale,
+            &factors.occlusion_strength,
+        ]);
+        for (bits, value) in bits.iter_mut().zip(values) {
+            *bits = value.to_bits();
+        }
+        Self {
+            factors: bits,
+            textures: textures
+                .each_ref()
+                .map(|texture| Arc::as_ptr(texture) as usize),
+            double_sided: data.double_sided,
+            alpha_mode: data.alpha_mode,
+        }
+    }
+}
+
+struct CachedTexture {
+    /// Keeps the address of the image in the key from being reused while the entry exists.
+    _image: Option<Arc<image::RgbaImage>>,
+    texture: Weak<Texture>,
+}
+
+/// Deduplicates the GPU objects behind [`MaterialData`]: images shared between materials are
+/// uploaded once, and materials with the same factors, textures and pipeline state share one
+/// [`Material`], with a single uniform buffer and bind group for every object using it.
+///
+/// Entries only live as long as something holds on to the materials and textures handed out,
+/// see [`MaterialCache::trim`].
+#[derive(Default)]
+pub struct MaterialCache {
+    textures: HashMap<TextureKey, CachedTexture>,
+    materials: HashMap<MaterialKey, Weak<Material>>,
+}
+
+impl MaterialCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The material for `data`, creating it and uploading its textures with `sampler` and
+    /// full mip chains, if no equivalent one is alive.
+    pub fn get(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        data: &MaterialData,
+        sampler: SamplerDesc,
+        mut mipmaps: Option<&mut MipmapGenerator>,
+    ) -> Arc<Material> {
+        let label = data.name.as_deref().unwrap_or("material");
+        let images = [
+            &data.base_color_texture,
+            &data.metallic_roughness_texture,
+            &data.normal_texture,
+            &data.occlusion_texture,
+            &data.emissive_texture,
+        ];
+        let textures = std::array::from_fn(|index| {
+            let (color, srgb) = FALLBACK_COLORS[index];
+            let format = if srgb {
+                wgpu::TextureFormat::Rgba8UnormSrgb
+            } else {
+                wgpu::TextureFormat::Rgba8Unorm
+            };
+            let key = match images[index] {
+                Some(image) => TextureKey::Image(Arc::as_ptr(image) as usize, format, sampler),
+                None => TextureKey::Fallback(color, format),
+            };
+            if let Some(texture) = self
+                .textures
+                .get(&key)
+                .and_then(|cached| cached.texture.upgrade())
+            {
+                return texture;
+            }
+            let texture = Arc::new(match images[index] {
+                Some(image) => Texture::from_rgba(
+                    device,
+                    queue,
+                    image,
+                    format,
+                    label,
+                    sampler,
+                    mipmaps.as_deref_mut(),
+                ),
+                None => Texture::from_color(device, queue, color, format, "fallback"),
+            });
+            self.textures.insert(
+                key,
+                CachedTexture {
+                    _image: images[index].clone(),
+                    texture: Arc::downgrade(&texture),
+                },
+            );
+            texture
+        });
+
+        let key = MaterialKey::new(&data.factors, &textures, data);
+        if let Some(material) = self.materials.get(&key).and_then(Weak::upgrade) {
+            return material;
+        }
+        let mut material = Material::from_textures(
+            device,
+            layout,
+            data.name.clone(),
+            data.factors,
+            data.double_sided,
+            textures,
+        );
+        material.alpha_mode = data.alpha_mode;
+        let material = Arc::new(material);
+        self.materials.insert(key, Arc::downgrade(&material));
+        material
+    }
+
+    /// Forgets the materials and textures that are no longer used, releasing the images they
+    /// were uploaded from.
+    pub fn trim(&mut self) {
+        self.materials
+            .retain(|_, material| material.strong_count() > 0);
+        self.textures
+            .retain(|_, cached| cached.texture.strong_count() > 0);
+    }
+
+    /// The number of distinct materials alive.
+    pub fn material_count(&self) -> usize {
+        self.materials
+            .values()
+            .filter(|material| material.strong_count() > 0)
+            .count()
+    }
+
+    /// The number of distinct textures alive.
+    pub fn texture_count(&self) -> usize {
+        self.textures
+            .values()
+            .filter(|cached| cached.texture.strong_count() > 0)
+            .count()
+    }
+}
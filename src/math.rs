@@ -0,0 +1,434 @@
+//! Rays and bounding volumes, for picking and other queries on the CPU.
+
+use glam::{Mat4, Vec2, Vec3, Vec4};
+
+/// A half-line from `origin` along the unit `direction`. Intersections are reported as the
+/// distance along it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ray {
+    pub origin: Vec3,
+    pub direction: Vec3,
+}
+
+impl Ray {
+    /// Normalizes `direction`.
+    pub fn new(origin: Vec3, direction: Vec3) -> Self {
+        Self {
+            origin,
+            direction: direction.normalize_or_zero(),
+        }
+    }
+
+    /// The ray through `cursor`, in physical pixels on a surface of `size`, from the near plane
+    /// of `view_projection`.
+    pub fn from_cursor(view_projection: Mat4, size: (u32, u32), cursor: Vec2) -> Self {
+        let ndc = Vec2::new(
+            cursor.x / size.0.max(1) as f32 * 2.0 - 1.0,
+            1.0 - cursor.y / size.1.max(1) as f32 * 2.0,
+        );
+        let inverse = view_projection.inverse();
+        let near = inverse.project_point3(ndc.extend(0.0));
+        let far = inverse.project_point3(ndc.extend(1.0));
+        Self::new(near, far - near)
+    }
+
+    pub fn at(&self, distance: f32) -> Vec3 {
+        self.origin + self.direction * distance
+    }
+
+    /// The ray in the space `matrix` transforms into. Distances along the transformed ray are
+    /// only comparable to the original ones if `matrix` doesn't scale.
+    pub fn transformed(&self, matrix: Mat4) -> Self {
+        Self::new(
+            matrix.transform_point3(self.origin),
+            matrix.transform_vector3(self.direction),
+        )
+    }
+
+    /// Where the ray hits the plane through `point` facing either way along `normal`.
+    pub fn intersect_plane(&self, point: Vec3, normal: Vec3) -> Option<f32> {
+        let denominator = self.direction.dot(normal);
+        if denominator.abs() < 1e-6 {
+            return None;
+        }
+        let distance = (point - self.origin).dot(normal) / denominator;
+        (distance >= 0.0).then_some(distance)
+    }
+
+    /// Where the ray enters `aabb`, or zero if it starts inside.
+    pub fn intersect_aabb(&self, aabb: &Aabb) -> Option<f32> {
+        let inverse = self.direction.recip();
+        let a = (aabb.min - self.origin) * inverse;
+        let b = (aabb.max - self.origin) * inverse;
+        let near = a.min(b).max_element().max(0.0);
+        let far = a.max(b).min_element();
+        (near <= far).then_some(near)
+    }
+
+    /// Where the ray enters `obb`, or zero if it starts inside.
+    pub fn intersect_obb(&self, obb: &Obb) -> Option<f32> {
+        let offset = self.origin - obb.center;
+        let mut near: f32 = 0.0;
+        let mut far = f32::INFINITY;
+        for (axis, half_extent) in obb.axes.iter().zip(obb.half_extents.to_array()) {
+            let start = offset.dot(*axis);
+            let speed = self.direction.dot(*axis);
+            if speed.abs() < 1e-6 {
+                if start.abs() > half_extent {
+                    return None;
+                }
+                continue;
+            }
+            let a = (-half_extent - start) / speed;
+            let b = (half_extent - start) / speed;
+            near = near.max(a.min(b));
+            far = far.min(a.max(b));
+        }
+        (near <= far).then_some(near)
+    }
+
+    /// Where the ray enters `sphere`, or zero if it starts inside.
+    pub fn intersect_sphere(&self, sphere: &BoundingSphere) -> Option<f32> {
+        let offset = self.origin - sphere.center;
+        let b = offset.dot(self.direction);
+        let c = offset.length_squared() - sphere.radius * sphere.radius;
+        let discriminant = b * b - c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let far = -b + discriminant.sqrt();
+        (far >= 0.0).then_some((-b - discriminant.sqrt()).max(0.0))
+    }
+
+    /// Where the ray hits the triangle, from either side (Möller–Trumbore).
+    pub fn intersect_triangle(&self, [a, b, c]: [Vec3; 3]) -> Option<f32> {
+        let (ab, ac) = (b - a, c - a);
+        let p = self.direction.cross(ac);
+        let determinant = ab.dot(p);
+        if determinant.abs() < 1e-8 {
+            return None;
+        }
+        let inverse = determinant.recip();
+        let offset = self.origin - a;
+        let u = offset.dot(p) * inverse;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q = offset.cross(ab);
+        let v = self.direction.dot(q) * inverse;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        let distance = ac.dot(q) * inverse;
+        (distance >= 0.0).then_some(distance)
+    }
+}
+
+/// An axis-aligned bounding box.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl From<(Vec3, Vec3)> for Aabb {
+    fn from((min, max): (Vec3, Vec3)) -> Self {
+        Self { min, max }
+    }
+}
+
+impl Aabb {
+    /// The box around `points`, empty without any.
+    pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Self {
+        points.into_iter().fold(
+            Self {
+                min: Vec3::splat(f32::INFINITY),
+                max: Vec3::splat(f32::NEG_INFINITY),
+            },
+            |aabb, point| Self {
+                min: aabb.min.min(point),
+                max: aabb.max.max(point),
+            },
+        )
+    }
+
+    /// Whether the box contains nothing, like the bounds of a mesh without vertices.
+    pub fn is_empty(&self) -> bool {
+        self.min.cmpgt(self.max).any()
+    }
+
+    pub fn contains(&self, point: Vec3) -> bool {
+        point.cmpge(self.min).all() && point.cmple(self.max).all()
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn half_extents(&self) -> Vec3 {
+        (self.max - self.min) * 0.5
+    }
+
+    /// The axis-aligned box around the box transformed by `matrix`.
+    pub fn transformed(&self, matrix: Mat4) -> Self {
+        let center = matrix.transform_point3(self.center());
+        let half_extents = self.half_extents();
+        let extents = matrix.x_axis.truncate().abs() * half_extents.x
+            + matrix.y_axis.truncate().abs() * half_extents.y
+            + matrix.z_axis.truncate().abs() * half_extents.z;
+        Self {
+            min: center - extents,
+            max: center + extents,
+        }
+    }
+}
+
+/// An oriented bounding box, a box turned by its `axes`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Obb {
+    pub center: Vec3,
+    /// Unit length and orthogonal.
+    pub axes: [Vec3; 3],
+    pub half_extents: Vec3,
+}
+
+impl Obb {
+    /// `aabb` transformed by `matrix`, which may scale but not skew.
+    pub fn from_aabb(aabb: &Aabb, matrix: Mat4) -> Self {
+        let half_extents = aabb.half_extents();
+        let columns = [matrix.x_axis, matrix.y_axis, matrix.z_axis].map(|axis| axis.truncate());
+        Self {
+            center: matrix.transform_point3(aabb.center()),
+            axes: columns.map(Vec3::normalize_or_zero),
+            half_extents: Vec3::from_array(
+                [0, 1, 2].map(|axis| half_extents[axis] * columns[axis].length()),
+            ),
+        }
+    }
+}
+
+/// A sphere around a shape, looser than a box but cheaper to test and unaffected by rotation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    /// The sphere centered on `aabb` reaching the farthest of `points`, which is close to the
+    /// smallest one for most meshes. Has a negative radius without any points.
+    pub fn from_points(aabb: &Aabb, points: impl IntoIterator<Item = Vec3>) -> Self {
+        let center = aabb.center();
+        let radius_squared = points
+            .into_iter()
+            .map(|point| point.distance_squared(center))
+            .fold(f32::NEG_INFINITY, f32::max);
+        Self {
+            center,
+            radius: if aabb.is_empty() {
+                -1.0
+            } else {
+                radius_squared.sqrt()
+            },
+        }
+    }
+
+    /// The sphere transformed by `matrix`, its radius scaled by the largest scale of `matrix`.
+    pub fn transformed(&self, matrix: Mat4) -> Self {
+        let scale = [matrix.x_axis, matrix.y_axis, matrix.z_axis]
+            .map(|axis| axis.truncate().length())
+            .into_iter()
+            .fold(0.0, f32::max);
+        Self {
+            center: matrix.transform_point3(self.center),
+            radius: self.radius * scale,
+        }
+    }
+}
+
+/// The bounding volumes of a mesh, computed once when it is created.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bounds {
+    pub aabb: Aabb,
+    pub sphere: BoundingSphere,
+}
+
+impl Bounds {
+    pub fn from_points(points: impl IntoIterator<Item = Vec3> + Clone) -> Self {
+        let aabb = Aabb::from_points(points.clone());
+        Self {
+            aabb,
+            sphere: BoundingSphere::from_points(&aabb, points),
+        }
+    }
+}
+
+/// The six planes bounding what a camera sees, facing inwards.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frustum {
+    /// The unit normal of each plane in `xyz` and its offset along it in `w`: left, right,
+    /// bottom, top, near and far.
+    pub planes: [Vec4; 6],
+}
+
+impl Frustum {
+    /// The frustum of `view_projection`, which maps depth to `0..1` like wgpu's clip space.
+    pub fn from_view_projection(view_projection: Mat4) -> Self {
+        let [x, y, z, w] = [0, 1, 2, 3].map(|row| view_projection.row(row));
+        Self {
+            planes: [w + x, w - x, w + y, w - y, z, w - z]
+                .map(|plane| plane / plane.truncate().length()),
+        }
+    }
+
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.truncate().dot(sphere.center) + plane.w >= -sphere.radius)
+    }
+
+    /// Whether `aabb` may be inside. Large boxes just outside a corner of the frustum pass too.
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        let (center, half_extents) = (aabb.center(), aabb.half_extents());
+        self.planes.iter().all(|plane| {
+            let normal = plane.truncate();
+            normal.dot(center) + plane.w >= -normal.abs().dot(half_extents)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_hit(hit: Option<f32>, expected: f32) {
+        let distance = hit.expect("the ray missed");
+        assert!(
+            (distance - expected).abs() < 1e-5,
+            "hit at {distance}, expected {expected}"
+        );
+    }
+
+    fn unit_box() -> Aabb {
+        Aabb::from((Vec3::splat(-1.0), Vec3::ONE))
+    }
+
+    /// The unit box turned about z so that its corners point along x and y.
+    fn turned_box() -> Obb {
+        let rotation = Mat4::from_rotation_z(std::f32::consts::FRAC_PI_4);
+        Obb::from_aabb(&unit_box(), rotation)
+    }
+
+    #[test]
+    fn rays_hit_and_miss_boxes() {
+        let aabb = unit_box();
+        let ray = Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::X);
+        assert_hit(ray.intersect_aabb(&aabb), 4.0);
+        let ray = Ray::new(Vec3::new(0.0, 5.0, 0.0), -Vec3::Y);
+        assert_hit(ray.intersect_aabb(&aabb), 4.0);
+        let beside = Ray::new(Vec3::new(-5.0, 2.0, 0.0), Vec3::X);
+        assert_eq!(beside.intersect_aabb(&aabb), None);
+        let away = Ray::new(Vec3::new(5.0, 0.0, 0.0), Vec3::X);
+        assert_eq!(away.intersect_aabb(&aabb), None);
+    }
+
+    #[test]
+    fn rays_hit_and_miss_oriented_boxes() {
+        let obb = turned_box();
+        let hit = Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::X).intersect_obb(&obb);
+        assert_hit(hit, 5.0 - std::f32::consts::SQRT_2);
+        // Through a corner the box turned away from, and one it turned into.
+        let corner = Ray::new(Vec3::new(0.9, 0.9, 5.0), -Vec3::Z);
+        assert!(corner.intersect_aabb(&unit_box()).is_some());
+        assert_eq!(corner.intersect_obb(&obb), None);
+        let turned_corner = Ray::new(Vec3::new(-5.0, 1.2, 0.0), Vec3::X);
+        assert_eq!(turned_corner.intersect_aabb(&unit_box()), None);
+        let entry = 5.0 - (std::f32::consts::SQRT_2 - 1.2);
+        assert_hit(turned_corner.intersect_obb(&obb), entry);
+    }
+
+    #[test]
+    fn rays_hit_and_miss_spheres() {
+        let sphere = BoundingSphere {
+            center: Vec3::new(0.0, 0.0, -10.0),
+            radius: 2.0,
+        };
+        let toward = Ray::new(Vec3::ZERO, -Vec3::Z);
+        assert_hit(toward.intersect_sphere(&sphere), 8.0);
+        let away = Ray::new(Vec3::ZERO, Vec3::Z);
+        assert_eq!(away.intersect_sphere(&sphere), None);
+        let beside = Ray::new(Vec3::new(3.0, 0.0, 0.0), -Vec3::Z);
+        assert_eq!(beside.intersect_sphere(&sphere), None);
+    }
+
+    #[test]
+    fn rays_hit_triangles_from_either_side() {
+        let triangle = [
+            Vec3::new(-1.0, -1.0, 0.0),
+            Vec3::new(1.0, -1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        ];
+        let front = Ray::new(Vec3::new(0.0, 0.0, 5.0), -Vec3::Z);
+        assert_hit(front.intersect_triangle(triangle), 5.0);
+        let back = Ray::new(Vec3::new(0.0, 0.0, -3.0), Vec3::Z);
+        assert_hit(back.intersect_triangle(triangle), 3.0);
+        let outside = Ray::new(Vec3::new(0.9, 0.9, 5.0), -Vec3::Z);
+        assert_eq!(outside.intersect_triangle(triangle), None);
+        let away = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::Z);
+        assert_eq!(away.intersect_triangle(triangle), None);
+        let parallel = Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::X);
+        assert_eq!(parallel.intersect_triangle(triangle), None);
+    }
+
+    #[test]
+    fn rays_starting_inside_hit_at_zero() {
+        let ray = Ray::new(Vec3::new(0.5, 0.0, 0.0), Vec3::new(1.0, 1.0, 0.0));
+        assert_eq!(ray.intersect_aabb(&unit_box()), Some(0.0));
+        assert_eq!(ray.intersect_obb(&turned_box()), Some(0.0));
+        let sphere = BoundingSphere {
+            center: Vec3::ZERO,
+            radius: 1.0,
+        };
+        assert_eq!(ray.intersect_sphere(&sphere), Some(0.0));
+    }
+
+    #[test]
+    fn rays_parallel_to_a_slab_hit_only_between_its_planes() {
+        let aabb = unit_box();
+        let obb = Obb::from_aabb(&aabb, Mat4::IDENTITY);
+        let inside = Ray::new(Vec3::new(-5.0, 0.5, 0.5), Vec3::X);
+        assert_hit(inside.intersect_aabb(&aabb), 4.0);
+        assert_hit(inside.intersect_obb(&obb), 4.0);
+        let outside = Ray::new(Vec3::new(-5.0, 1.5, 0.0), Vec3::X);
+        assert_eq!(outside.intersect_aabb(&aabb), None);
+        assert_eq!(outside.intersect_obb(&obb), None);
+    }
+
+    #[test]
+    fn frustum_accepts_what_the_camera_sees() {
+        // Looking down -z with a 90° field of view, so the sides are at |x| = -z.
+        let projection = Mat4::perspective_rh(std::f32::consts::FRAC_PI_2, 1.0, 0.1, 100.0);
+        let frustum = Frustum::from_view_projection(projection);
+        let sphere = |x, z| BoundingSphere {
+            center: Vec3::new(x, 0.0, z),
+            radius: 1.0,
+        };
+        let cube = |x, z| Aabb::from((Vec3::new(x, 0.0, z) - 1.0, Vec3::new(x, 0.0, z) + 1.0));
+
+        for (x, z) in [(0.0, -10.0), (10.5, -10.0), (0.0, -100.5)] {
+            assert!(frustum.intersects_sphere(&sphere(x, z)), "{x}, {z}");
+            assert!(frustum.intersects_aabb(&cube(x, z)), "{x}, {z}");
+        }
+        // Beside, behind, in front of the near plane and beyond the far plane.
+        for (x, z) in [
+            (13.0, -10.0),
+            (-13.0, -10.0),
+            (0.0, 5.0),
+            (0.0, 1.05),
+            (0.0, -102.0),
+        ] {
+            assert!(!frustum.intersects_sphere(&sphere(x, z)), "{x}, {z}");
+            assert!(!frustum.intersects_aabb(&cube(x, z)), "{x}, {z}");
+        }
+    }
+}
@@ -0,0 +1,778 @@
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use glam::Vec3;
+
+use crate::material::{AlphaMode, Material, MaterialData};
+use crate::math::{Aabb, Bounds, Ray};
+use crate::mesh_allocator::{Allocation, MeshAllocator};
+use crate::vertex::{Vertex, VertexLayout, VertexType};
+
+/// CPU-side geometry: a vertex list and a triangle list indexing into it.
+#[derive(Clone, Debug, Default)]
+pub struct MeshData {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    /// Index ranges drawn separately, one per material. Empty means the whole mesh is drawn
+    /// at once.
+    pub submeshes: Vec<SubMeshData>,
+    /// Materials referenced by [`SubMeshData::material`].
+    pub materials: Vec<MaterialData>,
+}
+
+/// A contiguous range of a mesh's indices sharing one material.
+#[derive(Clone, Debug)]
+pub struct SubMeshData {
+    pub name: String,
+    pub material: Option<usize>,
+    pub indices: Range<u32>,
+}
+
+impl MeshData {
+    /// Overrides the color of every vertex.
+    pub fn with_color(mut self, color: [f32; 3]) -> Self {
+        for vertex in &mut self.vertices {
+            vertex.color = color;
+        }
+        self
+    }
+
+    /// The bounding box and sphere of all vertices.
+    pub fn bounds(&self) -> Bounds {
+        vertex_bounds(&self.vertices)
+    }
+
+    /// The closest triangle hit by `ray`, in the mesh's space, as the distance along the ray.
+    pub fn intersect_ray(&self, ray: &Ray) -> Option<f32> {
+        self.indices
+            .chunks_exact(3)
+            .filter_map(|triangle| {
+                let [a, b, c] = [triangle[0], triangle[1], triangle[2]]
+                    .map(|index| Vec3::from(self.vertices[index as usize].position));
+                ray.intersect_triangle([a, b, c])
+            })
+            .min_by(f32::total_cmp)
+    }
+
+    /// Replaces the tangents of every vertex, see [`generate_tangents`].
+    pub fn generate_tangents(&mut self) {
+        generate_tangents(&mut self.vertices, &self.indices);
+    }
+}
+
+/// The bounding box and sphere of `vertices`, empty without any.
+pub fn vertex_bounds(vertices: &[Vertex]) -> Bounds {
+    Bounds::from_points(vertices.iter().map(|vertex| Vec3::from(vertex.position)))
+}
+
+/// The submeshes of `data`.
+fn submeshes(data: &MeshData) -> Vec<SubMesh> {
+    data.submeshes
+        .iter()
+        .map(|submesh| SubMesh::new(submesh, data))
+        .collect()
+}
+
+/// A range of a [`Mesh`]'s indices drawn with one material.
+#[derive(Clone, Debug)]
+pub struct SubMesh {
+    pub name: String,
+    pub indices: Range<u32>,
+    /// An index into the materials the mesh is drawn with, the fallback material without one.
+    pub material: Option<usize>,
+    /// The box around the vertices the submesh's indices reference, for sorting its draws.
+    pub bounds: Aabb,
+}
+
+impl SubMesh {
+    /// The submesh `data` of `mesh`.
+    pub fn new(data: &SubMeshData, mesh: &MeshData) -> Self {
+        let indices = mesh
+            .indices
+            .get(data.indices.start as usize..data.indices.end as usize)
+            .unwrap_or_default();
+        Self {
+            name: data.name.clone(),
+            indices: data.indices.clone(),
+            material: data.material,
+            bounds: Aabb::from_points(
+                indices
+                    .iter()
+                    .filter_map(|&index| mesh.vertices.get(index as usize))
+                    .map(|vertex| Vec3::from(vertex.position)),
+            ),
+        }
+    }
+}
+
+/// The materials of the submeshes in [`Mesh::draw`], and the pipelines drawing them.
+#[derive(Clone, Copy)]
+pub struct MaterialBindings<'r> {
+    pub materials: &'r [Arc<Material>],
+    /// The material of submeshes without one.
+    pub fallback: &'r Material,
+    /// The pipelines for single-sided and double-sided materials.
+    pub pipelines: [&'r wgpu::RenderPipeline; 2],
+    /// Bound at group 0 with the dynamic offset of each material's factors.
+    pub frame: &'r wgpu::BindGroup,
+    /// The offset of the factors of each of `materials`, followed by those of `fallback`.
+    pub offsets: &'r [u32],
+    /// Whether group 2 holds the textures of every material, see [`crate::bindless`], so that
+    /// materials only switch the offset of their factors.
+    pub bindless: bool,
+    /// Only the submeshes of materials with this alpha mode are drawn, as the pipelines are
+    /// made for it.
+    pub alpha_mode: AlphaMode,
+    /// Only the submeshes of the material at this index are drawn if set, see
+    /// [`MaterialBindings::index`], e.g. to record the draws of each material on their own.
+    pub only: Option<usize>,
+}
+
+impl<'r> MaterialBindings<'r> {
+    /// The index of `material` in `materials`, or one past the last one for the fallback.
+    pub fn index(&self, material: Option<usize>) -> usize {
+        material
+            .filter(|&material| material < self.materials.len())
+            .unwrap_or(self.materials.len())
+    }
+
+    /// The material at index `material` of `materials`, or the fallback, along with the offset
+    /// of its factors.
+    pub fn material(&self, material: Option<usize>) -> (&'r Material, u32) {
+        let fallback = self.materials.len();
+        match material.filter(|&material| material < fallback) {
+            Some(material) => (&*self.materials[material], self.offsets[material]),
+            None => (self.fallback, self.offsets[fallback]),
+        }
+    }
+}
+
+/// Geometry on the GPU: vertices laid out as its [`VertexLayout`] describes, which pipelines
+/// drawing the mesh take their vertex state from, optional indices and the submeshes drawn from
+/// them. Both are ranges of buffers shared with other meshes, see [`MeshAllocator`].
+pub struct Mesh {
+    /// Tells meshes apart, since their buffers are shared and their ranges reused.
+    id: u64,
+    vertices: Allocation,
+    vertex_count: u32,
+    /// The index of the first vertex in the shared buffer, added to every index.
+    base_vertex: u32,
+    /// The indices, their count and the index of the first one in the shared buffer.
+    indices: Option<(Allocation, u32, u32)>,
+    vertex_layout: &'static VertexLayout,
+    submeshes: Vec<SubMesh>,
+    bounds: Bounds,
+}
+
+impl Mesh {
+    /// Uploads `vertices`, and `indices` if any, without submeshes. `bounds` are the bounding
+    /// volumes of the vertices.
+    pub fn new<V: VertexType>(
+        allocator: &MeshAllocator,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        vertices: &[V],
+        indices: Option<&[u32]>,
+        bounds: Bounds,
+    ) -> Self {
+        let stride = std::mem::size_of::<V>() as wgpu::BufferAddress;
+        let vertex_allocation =
+            allocator.vertices(device, queue, bytemuck::cast_slice(vertices), stride);
+        Self::with_vertex_allocation::<V>(
+            allocator,
+            device,
+            queue,
+            vertex_allocation,
+            vertices.len() as u32,
+            indices,
+            bounds,
+        )
+    }
+
+    /// Like [`Mesh::new`], with the vertices already uploaded into `vertex_allocation`, along
+    /// with their count.
+    fn with_vertex_allocation<V: VertexType>(
+        allocator: &MeshAllocator,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        vertex_allocation: Allocation,
+        vertex_count: u32,
+        indices: Option<&[u32]>,
+        bounds: Bounds,
+    ) -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        let stride = std::mem::size_of::<V>() as wgpu::BufferAddress;
+        let base_vertex = (vertex_allocation.offset() / stride.max(1)) as u32;
+        let indices = indices.map(|indices| {
+            let allocation = allocator.indices(device, queue, indices);
+            let first_index = (allocation.offset() / 4) as u32;
+            (allocation, indices.len() as u32, first_index)
+        });
+
+        Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            vertices: vertex_allocation,
+            vertex_count,
+            base_vertex,
+            indices,
+            vertex_layout: V::vertex_layout(),
+            submeshes: Vec::new(),
+            bounds,
+        }
+    }
+
+    /// Uploads [`Vertex`]es, and `indices` if any, without submeshes.
+    pub fn from_vertices(
+        allocator: &MeshAllocator,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        vertices: &[Vertex],
+        indices: Option<&[u32]>,
+    ) -> Self {
+        Self::new(
+            allocator,
+            device,
+            queue,
+            vertices,
+            indices,
+            vertex_bounds(vertices),
+        )
+    }
+
+    /// Uploads `data` with its submeshes. Its materials are left to the caller.
+    pub fn from_data(
+        allocator: &MeshAllocator,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        data: &MeshData,
+    ) -> Self {
+        Self::from_data_with_vertices(allocator, device, queue, data, &data.vertices)
+    }
+
+    /// Like [`Mesh::from_data`], with `vertices` uploaded in place of those of `data`, one for
+    /// each, e.g. with more attributes.
+    pub fn from_data_with_vertices<V: VertexType>(
+        allocator: &MeshAllocator,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        data: &MeshData,
+        vertices: &[V],
+    ) -> Self {
+        let mut mesh = Self::new(
+            allocator,
+            device,
+            queue,
+            vertices,
+            Some(&data.indices),
+            vertex_bounds(&data.vertices),
+        );
+        mesh.submeshes = submeshes(data);
+        mesh
+    }
+
+    /// Like [`Mesh::from_data`], with the vertices in a buffer compute shaders can write, see
+    /// [`MeshAllocator::writable_vertices`].
+    pub fn from_data_writable(
+        allocator: &MeshAllocator,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        data: &MeshData,
+    ) -> Self {
+        let stride = std::mem::size_of::<Vertex>() as wgpu::BufferAddress;
+        let vertices = allocator.writable_vertices(
+            device,
+            queue,
+            bytemuck::cast_slice(&data.vertices),
+            stride,
+        );
+        let mut mesh = Self::with_vertex_allocation::<Vertex>(
+            allocator,
+            device,
+            queue,
+            vertices,
+            data.vertices.len() as u32,
+            Some(&data.indices),
+            data.bounds(),
+        );
+        mesh.submeshes = submeshes(data);
+        mesh
+    }
+
+    /// Unique among the meshes created, unlike the buffers holding them.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Describes the vertex buffer, for the vertex state of pipelines drawing the mesh.
+    pub fn vertex_layout(&self) -> &'static VertexLayout {
+        self.vertex_layout
+    }
+
+    /// The buffer holding the vertices, along with other meshes'.
+    pub fn vertex_buffer(&self) -> &wgpu::Buffer {
+        self.vertices.buffer()
+    }
+
+    /// The index of the first vertex in [`Mesh::vertex_buffer`], which vertex indices in
+    /// shaders count from.
+    pub fn base_vertex(&self) -> u32 {
+        self.base_vertex
+    }
+
+    /// The submeshes drawn one after the other, empty to draw every index at once.
+    pub fn submeshes(&self) -> &[SubMesh] {
+        &self.submeshes
+    }
+
+    /// The bounding box and sphere of the vertices, for culling and picking.
+    pub fn bounds(&self) -> Bounds {
+        self.bounds
+    }
+
+    /// Records the draws of `instances` read from `instance_buffer`, binding each submesh's
+    /// material at group 2 and its pipeline when given `materials`. Submeshes are drawn grouped
+    /// by pipeline and material, so that each is only bound once.
+    pub fn draw<'a>(
+        &'a self,
+        encoder: &mut impl DrawEncoder<'a>,
+        instance_buffer: &'a wgpu::Buffer,
+        instances: Range<u32>,
+        materials: Option<MaterialBindings<'a>>,
+    ) {
+        self.record(encoder, instance_buffer, materials, |encoder, draws| {
+            for draw in draws {
+                match self.draw_indices(draw) {
+                    Some(indices) => {
+                        encoder.draw_indexed(indices, self.base_vertex as i32, instances.clone());
+                    }
+                    None => encoder.draw(self.vertex_range(), instances.clone()),
+                }
+            }
+        });
+    }
+
+    /// Like [`Mesh::draw`], but with each draw's arguments read from `indirect_buffer`, laid out
+    /// like [`Mesh::indirect_args`]. With `multi_draw`, which needs
+    /// [`wgpu::Features::MULTI_DRAW_INDIRECT`], consecutive draws sharing a material are
+    /// submitted in a single call.
+    pub fn draw_indirect<'a>(
+        &'a self,
+        encoder: &mut impl DrawEncoder<'a>,
+        instance_buffer: &'a wgpu::Buffer,
+        indirect_buffer: &'a wgpu::Buffer,
+        multi_draw: bool,
+        materials: Option<MaterialBindings<'a>>,
+    ) {
+        let stride = self.indirect_stride() as wgpu::BufferAddress * 4;
+        let indexed = self.indices.is_some();
+        self.record(encoder, instance_buffer, materials, |encoder, draws| {
+            let offset = draws.start as wgpu::BufferAddress * stride;
+            let count = draws.len() as u32;
+            match (indexed, multi_draw && count > 1) {
+                (true, true) => encoder.multi_draw_indexed_indirect(indirect_buffer, offset, count),
+                (false, true) => encoder.multi_draw_indirect(indirect_buffer, offset, count),
+                (true, false) => {
+                    for draw in 0..count as wgpu::BufferAddress {
+                        encoder.draw_indexed_indirect(indirect_buffer, offset + draw * stride);
+                    }
+                }
+                (false, false) => {
+                    for draw in 0..count as wgpu::BufferAddress {
+                        encoder.draw_indirect(indirect_buffer, offset + draw * stride);
+                    }
+                }
+            }
+        });
+    }
+
+    /// Records draw call `draw` of [`Mesh::draw`] alone, binding its material and pipeline. For
+    /// draws that can't be grouped by material, like alpha-blended ones drawn back to front.
+    pub fn draw_submesh<'a>(
+        &'a self,
+        encoder: &mut impl DrawEncoder<'a>,
+        instance_buffer: &'a wgpu::Buffer,
+        draw: usize,
+        instances: Range<u32>,
+        materials: MaterialBindings<'a>,
+    ) {
+        let submesh = self.submeshes.get(draw);
+        let (material, offset) = materials.material(submesh.and_then(|submesh| submesh.material));
+        encoder.set_pipeline(materials.pipelines[material.double_sided() as usize]);
+        encoder.set_bind_group(0, materials.frame, &[offset]);
+        if !materials.bindless {
+            encoder.set_bind_group(2, material.bind_group(), &[]);
+        }
+        encoder.set_vertex_buffer(0, self.vertices.buffer().slice(..));
+        encoder.set_vertex_buffer(1, instance_buffer.slice(..));
+        match (&self.indices, self.draw_indices(draw)) {
+            (Some((index_allocation, _, _)), Some(indices)) => {
+                encoder.set_index_buffer(
+                    index_allocation.buffer().slice(..),
+                    wgpu::IndexFormat::Uint32,
+                );
+                encoder.draw_indexed(indices, self.base_vertex as i32, instances);
+            }
+            _ => encoder.draw(self.vertex_range(), instances),
+        }
+    }
+
+    /// The materials [`Mesh::draw`] draws submeshes with given `materials`, as indices like
+    /// [`MaterialBindings::index`], the single-sided ones first.
+    pub fn draw_materials(&self, materials: &MaterialBindings) -> Vec<usize> {
+        let mut indices: Vec<usize> = if self.indices.is_none() || self.submeshes.is_empty() {
+            vec![materials.index(None)]
+        } else {
+            self.submeshes
+                .iter()
+                .map(|submesh| materials.index(submesh.material))
+                .collect()
+        };
+        indices.retain(|&index| {
+            materials.material(Some(index)).0.alpha_mode() == materials.alpha_mode
+        });
+        indices.sort_by_key(|&index| (materials.material(Some(index)).0.double_sided(), index));
+        indices.dedup();
+        indices
+    }
+
+    /// The box around the vertices of draw call `draw`.
+    pub fn draw_bounds(&self, draw: usize) -> Aabb {
+        self.submeshes
+            .get(draw)
+            .map_or(self.bounds.aabb, |submesh| submesh.bounds)
+    }
+
+    /// The number of draw calls [`Mesh::draw`] records: one per submesh, or a single one.
+    pub fn draw_count(&self) -> usize {
+        self.submeshes.len().max(1)
+    }
+
+    /// The words in each draw's indirect arguments: five for indexed meshes and four otherwise.
+    pub fn indirect_stride(&self) -> u32 {
+        if self.indices.is_some() {
+            5
+        } else {
+            4
+        }
+    }
+
+    /// The arguments of every draw call drawing no instances, for [`Mesh::draw_indirect`] once
+    /// their instance counts have been filled in.
+    pub fn indirect_args(&self) -> Vec<u8> {
+        let Some(&(_, index_count, first_index)) = self.indices.as_ref() else {
+            return wgpu::util::DrawIndirectArgs {
+                vertex_count: self.vertex_count,
+                instance_count: 0,
+                first_vertex: self.base_vertex,
+                first_instance: 0,
+            }
+            .as_bytes()
+            .to_vec();
+        };
+        let ranges: Vec<_> = if self.submeshes.is_empty() {
+            std::iter::once(0..index_count).collect()
+        } else {
+            self.submeshes
+                .iter()
+                .map(|submesh| submesh.indices.clone())
+                .collect()
+        };
+        ranges
+            .into_iter()
+            .flat_map(|indices| {
+                wgpu::util::DrawIndexedIndirectArgs {
+                    index_count: indices.len() as u32,
+                    instance_count: 0,
+                    first_index: first_index + indices.start,
+                    base_vertex: self.base_vertex as i32,
+                    first_instance: 0,
+                }
+                .as_bytes()
+                .to_vec()
+            })
+            .collect()
+    }
+
+    /// The vertices in the shared vertex buffer, for draws without indices.
+    fn vertex_range(&self) -> Range<u32> {
+        self.base_vertex..self.base_vertex + self.vertex_count
+    }
+
+    /// The indices of draw call `draw` in the shared index buffer, `None` without indices.
+    fn draw_indices(&self, draw: usize) -> Option<Range<u32>> {
+        let &(_, index_count, first_index) = self.indices.as_ref()?;
+        let indices = self
+            .submeshes
+            .get(draw)
+            .map_or(0..index_count, |submesh| submesh.indices.clone());
+        Some(first_index + indices.start..first_index + indices.end)
+    }
+
+    /// Binds the buffers and calls `draw` with runs of consecutive draw calls, which share a
+    /// material when given `materials` and leave out the materials of other alpha modes.
+    fn record<'a, E: DrawEncoder<'a>>(
+        &'a self,
+        encoder: &mut E,
+        instance_buffer: &'a wgpu::Buffer,
+        materials: Option<MaterialBindings<'a>>,
+        mut draw: impl FnMut(&mut E, Range<usize>),
+    ) {
+        // Without submeshes the whole mesh has the fallback material.
+        let whole = self.indices.is_none() || self.submeshes.is_empty();
+        if materials.is_some_and(|bindings| {
+            whole
+                && (bindings.fallback.alpha_mode() != bindings.alpha_mode
+                    || bindings
+                        .only
+                        .is_some_and(|only| only != bindings.index(None)))
+        }) {
+            return;
+        }
+        encoder.set_vertex_buffer(0, self.vertices.buffer().slice(..));
+        encoder.set_vertex_buffer(1, instance_buffer.slice(..));
+        let Some((index_allocation, _, _)) = &self.indices else {
+            draw(encoder, 0..1);
+            return;
+        };
+        encoder.set_index_buffer(
+            index_allocation.buffer().slice(..),
+            wgpu::IndexFormat::Uint32,
+        );
+        let Some(bindings) = materials.filter(|_| !self.submeshes.is_empty()) else {
+            draw(encoder, 0..self.draw_count());
+            return;
+        };
+        let mut submeshes: Vec<(usize, &Material, u32)> = self
+            .submeshes
+            .iter()
+            .enumerate()
+            .filter(|(_, submesh)| {
+                bindings
+                    .only
+                    .is_none_or(|only| only == bindings.index(submesh.material))
+            })
+            .map(|(index, submesh)| {
+                let (material, offset) = bindings.material(submesh.material);
+                (index, material, offset)
+            })
+            .filter(|(_, material, _)| material.alpha_mode() == bindings.alpha_mode)
+            .collect();
+        // Opaque materials hide what's behind them and weighted blended ones don't depend on
+        // order, so the order only matters for how often state changes. The sort is stable, so
+        // the submeshes of a material stay in order and can be drawn in runs.
+        submeshes.sort_by_key(|(_, material, _)| {
+            (material.double_sided(), *material as *const Material)
+        });
+        let mut bound: Option<(*const Material, bool)> = None;
+        let mut run: Range<usize> = 0..0;
+        for (index, material, offset) in submeshes {
+            if bound.map(|(material, _)| material) == Some(material as *const _) && run.end == index
+            {
+                run.end += 1;
+                continue;
+            }
+            if !run.is_empty() {
+                draw(encoder, run);
+            }
+            run = index..index + 1;
+            let double_sided = material.double_sided();
+            if bound.map(|(_, double_sided)| double_sided) != Some(double_sided) {
+                encoder.set_pipeline(bindings.pipelines[double_sided as usize]);
+            }
+            if bound.map(|(material, _)| material) != Some(material as *const _) {
+                encoder.set_bind_group(0, bindings.frame, &[offset]);
+                if !bindings.bindless {
+                    encoder.set_bind_group(2, material.bind_group(), &[]);
+                }
+            }
+            bound = Some((material, double_sided));
+        }
+        if !run.is_empty() {
+            draw(encoder, run);
+        }
+    }
+}
+
+/// What [`Mesh`] records its draws into: a render pass, or a render bundle replayed in one.
+/// Bundles keep what they bind borrowed for as long as they are recorded, `'a`.
+pub trait DrawEncoder<'a> {
+    fn set_pipeline(&mut self, pipeline: &'a wgpu::RenderPipeline);
+    fn set_bind_group(&mut self, index: u32, bind_group: &'a wgpu::BindGroup, offsets: &[u32]);
+    fn set_vertex_buffer(&mut self, slot: u32, buffer_slice: wgpu::BufferSlice<'a>);
+    fn set_index_buffer(&mut self, buffer_slice: wgpu::BufferSlice<'a>, format: wgpu::IndexFormat);
+    fn draw(&mut self, vertices: Range<u32>, instances: Range<u32>);
+    fn draw_indexed(&mut self, indices: Range<u32>, base_vertex: i32, instances: Range<u32>);
+    fn draw_indirect(&mut self, indirect_buffer: &'a wgpu::Buffer, offset: wgpu::BufferAddress);
+    fn draw_indexed_indirect(
+        &mut self,
+        indirect_buffer: &'a wgpu::Buffer,
+        offset: wgpu::BufferAddress,
+    );
+
+    /// `count` tightly packed draws in one call where the encoder supports it, and one after
+    /// the other otherwise. Needs [`wgpu::Features::MULTI_DRAW_INDIRECT`].
+    fn multi_draw_indirect(
+        &mut self,
+        indirect_buffer: &'a wgpu::Buffer,
+        offset: wgpu::BufferAddress,
+        count: u32,
+    ) {
+        let stride = std::mem::size_of::<wgpu::util::DrawIndirectArgs>() as wgpu::BufferAddress;
+        for draw in 0..count as wgpu::BufferAddress {
+            self.draw_indirect(indirect_buffer, offset + draw * stride);
+        }
+    }
+
+    /// Like [`DrawEncoder::multi_draw_indirect`], for indexed draws.
+    fn multi_draw_indexed_indirect(
+        &mut self,
+        indirect_buffer: &'a wgpu::Buffer,
+        offset: wgpu::BufferAddress,
+        count: u32,
+    ) {
+        let stride =
+            std::mem::size_of::<wgpu::util::DrawIndexedIndirectArgs>() as wgpu::BufferAddress;
+        for draw in 0..count as wgpu::BufferAddress {
+            self.draw_indexed_indirect(indirect_buffer, offset + draw * stride);
+        }
+    }
+}
+
+macro_rules! forward_draw_encoder {
+    ($a:lifetime, $encoder:ty $(, { $($extra:tt)* })?) => {
+        impl<$a> DrawEncoder<$a> for $encoder {
+            fn set_pipeline(&mut self, pipeline: &$a wgpu::RenderPipeline) {
+                self.set_pipeline(pipeline);
+            }
+
+            fn set_bind_group(
+                &mut self,
+                index: u32,
+                bind_group: &$a wgpu::BindGroup,
+                offsets: &[u32],
+            ) {
+                self.set_bind_group(index, bind_group, offsets);
+            }
+
+            fn set_vertex_buffer(&mut self, slot: u32, buffer_slice: wgpu::BufferSlice<$a>) {
+                self.set_vertex_buffer(slot, buffer_slice);
+            }
+
+            fn set_index_buffer(
+                &mut self,
+                buffer_slice: wgpu::BufferSlice<$a>,
+                format: wgpu::IndexFormat,
+            ) {
+                self.set_index_buffer(buffer_slice, format);
+            }
+
+            fn draw(&mut self, vertices: Range<u32>, instances: Range<u32>) {
+                self.draw(vertices, instances);
+            }
+
+            fn draw_indexed(
+                &mut self,
+                indices: Range<u32>,
+                base_vertex: i32,
+                instances: Range<u32>,
+            ) {
+                self.draw_indexed(indices, base_vertex, instances);
+            }
+
+            fn draw_indirect(
+                &mut self,
+                indirect_buffer: &$a wgpu::Buffer,
+                offset: wgpu::BufferAddress,
+            ) {
+                self.draw_indirect(indirect_buffer, offset);
+            }
+
+            fn draw_indexed_indirect(
+                &mut self,
+                indirect_buffer: &$a wgpu::Buffer,
+                offset: wgpu::BufferAddress,
+            ) {
+                self.draw_indexed_indirect(indirect_buffer, offset);
+            }
+
+            $($($extra)*)?
+        }
+    };
+}
+
+// Render passes don't borrow what they bind, so they record the draws of any `'a`. Only they
+// have multi-draws, bundles fall back to one draw after the other.
+forward_draw_encoder!('a, wgpu::RenderPass<'_>, {
+    fn multi_draw_indirect(
+        &mut self,
+        indirect_buffer: &'a wgpu::Buffer,
+        offset: wgpu::BufferAddress,
+        count: u32,
+    ) {
+        self.multi_draw_indirect(indirect_buffer, offset, count);
+    }
+
+    fn multi_draw_indexed_indirect(
+        &mut self,
+        indirect_buffer: &'a wgpu::Buffer,
+        offset: wgpu::BufferAddress,
+        count: u32,
+    ) {
+        self.multi_draw_indexed_indirect(indirect_buffer, offset, count);
+    }
+});
+forward_draw_encoder!('a, wgpu::RenderBundleEncoder<'a>);
+
+/// Computes MikkTSpace tangents for the triangles `indices` of `vertices` from their positions,
+/// normals and UVs. MikkTSpace is what most tools bake normal maps in, and what glTF expects.
+///
+/// Vertices shared between triangles keep the tangent of one of them, which only differs where
+/// the UV mapping is discontinuous. Meshes without usable UVs end up with zero tangents, which
+/// the shader treats as having no normal map.
+pub fn generate_tangents(vertices: &mut [Vertex], indices: &[u32]) {
+    struct Geometry<'a> {
+        vertices: &'a mut [Vertex],
+        indices: &'a [u32],
+    }
+
+    impl Geometry<'_> {
+        fn vertex(&self, face: usize, vert: usize) -> &Vertex {
+            &self.vertices[self.indices[face * 3 + vert] as usize]
+        }
+    }
+
+    impl mikktspace::Geometry for Geometry<'_> {
+        fn num_faces(&self) -> usize {
+            self.indices.len() / 3
+        }
+
+        fn num_vertices_of_face(&self, _face: usize) -> usize {
+            3
+        }
+
+        fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+            self.vertex(face, vert).position
+        }
+
+        fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+            self.vertex(face, vert).normal
+        }
+
+        fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+            self.vertex(face, vert).uv
+        }
+
+        fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+            let index = self.indices[face * 3 + vert] as usize;
+            self.vertices[index].tangent = tangent;
+        }
+    }
+
+    for vertex in vertices.iter_mut() {
+        vertex.tangent = [0.0; 4];
+    }
+    if !mikktspace::generate_tangents(&mut Geometry { vertices, indices }) {
+        eprintln!("failed to generate tangents");
+    }
+}
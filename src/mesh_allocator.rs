@@ -0,0 +1,367 @@
+use std::ops::Range;
+use std::sync::{Arc, Mutex, PoisonError, Weak};
+
+/// The size of the buffers vertices are allocated from. Larger meshes get a buffer of their own.
+const VERTEX_BLOCK_SIZE: wgpu::BufferAddress = 16 << 20;
+const INDEX_BLOCK_SIZE: wgpu::BufferAddress = 8 << 20;
+
+/// Allocates the vertices and indices of every mesh from a few large buffers, rather than
+/// creating buffers per mesh. Meshes are drawn from their range with a base vertex and first
+/// index, so that meshes sharing a buffer can be drawn without rebinding it.
+pub struct MeshAllocator {
+    vertices: Arc<Mutex<Pool>>,
+    /// Vertices compute shaders write, like those of skinned meshes, kept apart so that other
+    /// vertex buffers don't need storage usage, which WebGL lacks.
+    writable_vertices: Arc<Mutex<Pool>>,
+    indices: Arc<Mutex<Pool>>,
+}
+
+impl Default for MeshAllocator {
+    fn default() -> Self {
+        let pool = |label, usage, block_size| {
+            Arc::new(Mutex::new(Pool {
+                label,
+                usage: usage | wgpu::BufferUsages::COPY_DST,
+                block_size,
+                blocks: Vec::new(),
+            }))
+        };
+        Self {
+            vertices: pool(
+                "mesh vertices",
+                wgpu::BufferUsages::VERTEX,
+                VERTEX_BLOCK_SIZE,
+            ),
+            writable_vertices: pool(
+                "writable mesh vertices",
+                wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE,
+                VERTEX_BLOCK_SIZE,
+            ),
+            indices: pool("mesh indices", wgpu::BufferUsages::INDEX, INDEX_BLOCK_SIZE),
+        }
+    }
+}
+
+impl MeshAllocator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Uploads vertices `stride` bytes each into a range starting at a whole vertex, so that it
+    /// can be drawn with a base vertex.
+    pub fn vertices(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        data: &[u8],
+        stride: wgpu::BufferAddress,
+    ) -> Allocation {
+        allocate(&self.vertices, device, queue, data, vertex_align(stride))
+    }
+
+    /// Like [`MeshAllocator::vertices`], from buffers that compute shaders can bind as storage
+    /// to write the vertices, which needs [`wgpu::DownlevelFlags::COMPUTE_SHADERS`].
+    pub fn writable_vertices(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        data: &[u8],
+        stride: wgpu::BufferAddress,
+    ) -> Allocation {
+        allocate(
+            &self.writable_vertices,
+            device,
+            queue,
+            data,
+            vertex_align(stride),
+        )
+    }
+
+    pub fn indices(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        indices: &[u32],
+    ) -> Allocation {
+        allocate(
+            &self.indices,
+            device,
+            queue,
+            bytemuck::cast_slice(indices),
+            std::mem::size_of::<u32>() as wgpu::BufferAddress,
+        )
+    }
+
+    /// The bytes allocated and the total size of the buffers they're allocated from, vertices
+    /// and indices together.
+    pub fn usage(&self) -> (wgpu::BufferAddress, wgpu::BufferAddress) {
+        [&self.vertices, &self.writable_vertices, &self.indices]
+            .into_iter()
+            .map(|pool| lock(pool).usage())
+            .fold((0, 0), |(used, capacity), usage| {
+                (used + usage.0, capacity + usage.1)
+            })
+    }
+}
+
+/// A range of one of the [`MeshAllocator`]'s buffers, freed when dropped.
+pub struct Allocation {
+    buffer: Arc<wgpu::Buffer>,
+    range: Range<wgpu::BufferAddress>,
+    block: usize,
+    pool: Weak<Mutex<Pool>>,
+}
+
+impl Allocation {
+    /// The buffer the range is part of, shared with other allocations.
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    /// In bytes from the start of the buffer.
+    pub fn offset(&self) -> wgpu::BufferAddress {
+        self.range.start
+    }
+
+    pub fn size(&self) -> wgpu::BufferAddress {
+        self.range.end - self.range.start
+    }
+}
+
+impl Drop for Allocation {
+    fn drop(&mut self) {
+        if let Some(pool) = self.pool.upgrade() {
+            lock(&pool).blocks[self.block]
+                .free
+                .release(self.range.clone());
+        }
+    }
+}
+
+fn allocate(
+    pool: &Arc<Mutex<Pool>>,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    data: &[u8],
+    align: wgpu::BufferAddress,
+) -> Allocation {
+    let size = (data.len() as wgpu::BufferAddress).next_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT);
+    let mut locked = lock(pool);
+    let (block, offset) = locked.allocate(device, size, align);
+    let buffer = locked.blocks[block].buffer.clone();
+    drop(locked);
+    if !data.is_empty() {
+        queue.write_buffer(&buffer, offset, data);
+    }
+    Allocation {
+        buffer,
+        range: offset..offset + size,
+        block,
+        pool: Arc::downgrade(pool),
+    }
+}
+
+/// The buffers of one kind of data, each with a free list.
+struct Pool {
+    label: &'static str,
+    usage: wgpu::BufferUsages,
+    block_size: wgpu::BufferAddress,
+    blocks: Vec<Block>,
+}
+
+impl Pool {
+    /// The first free range of `size` bytes aligned to `align`, in a new buffer if none fits.
+    fn allocate(
+        &mut self,
+        device: &wgpu::Device,
+        size: wgpu::BufferAddress,
+        align: wgpu::BufferAddress,
+    ) -> (usize, wgpu::BufferAddress) {
+        let free = self.blocks.iter_mut().map(|block| &mut block.free);
+        if let Some(found) = take_first(free, size, align) {
+            return found;
+        }
+        let capacity = self.block_size.max(size);
+        let mut free = FreeList::new(capacity);
+        let offset = free.take(size, align).unwrap_or(0);
+        self.blocks.push(Block {
+            buffer: Arc::new(device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(self.label),
+                size: capacity,
+                usage: self.usage,
+                mapped_at_creation: false,
+            })),
+            free,
+        });
+        (self.blocks.len() - 1, offset)
+    }
+
+    fn usage(&self) -> (wgpu::BufferAddress, wgpu::BufferAddress) {
+        self.blocks.iter().fold((0, 0), |(used, capacity), block| {
+            let size = block.buffer.size();
+            (used + size - block.free.available(), capacity + size)
+        })
+    }
+}
+
+struct Block {
+    buffer: Arc<wgpu::Buffer>,
+    free: FreeList,
+}
+
+/// The index of the first of `lists` with a free range of `size` bytes aligned to `align`, and
+/// the offset taken from it.
+fn take_first<'a>(
+    lists: impl IntoIterator<Item = &'a mut FreeList>,
+    size: wgpu::BufferAddress,
+    align: wgpu::BufferAddress,
+) -> Option<(usize, wgpu::BufferAddress)> {
+    lists
+        .into_iter()
+        .enumerate()
+        .find_map(|(index, list)| Some((index, list.take(size, align)?)))
+}
+
+/// The ranges of a buffer that aren't allocated.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct FreeList {
+    /// Sorted by offset, with no two ranges touching.
+    ranges: Vec<Range<wgpu::BufferAddress>>,
+}
+
+impl FreeList {
+    /// A buffer of `capacity` bytes with nothing allocated.
+    fn new(capacity: wgpu::BufferAddress) -> Self {
+        Self {
+            ranges: std::iter::once(0..capacity).collect(),
+        }
+    }
+
+    /// The free bytes, in total.
+    fn available(&self) -> wgpu::BufferAddress {
+        self.ranges
+            .iter()
+            .map(|range| range.end - range.start)
+            .sum()
+    }
+
+    /// Allocates the first free range of `size` bytes aligned to `align`, returning its offset.
+    fn take(
+        &mut self,
+        size: wgpu::BufferAddress,
+        align: wgpu::BufferAddress,
+    ) -> Option<wgpu::BufferAddress> {
+        let (index, start) = self.ranges.iter().enumerate().find_map(|(index, range)| {
+            let start = range.start.next_multiple_of(align);
+            (start + size <= range.end).then_some((index, start))
+        })?;
+        let range = self.ranges[index].clone();
+        let rest = [range.start..start, start + size..range.end];
+        self.ranges.splice(
+            index..=index,
+            rest.into_iter().filter(|range| !range.is_empty()),
+        );
+        Some(start)
+    }
+
+    /// Frees `range`, merging it with the free ranges it touches.
+    fn release(&mut self, range: Range<wgpu::BufferAddress>) {
+        if range.is_empty() {
+            return;
+        }
+        let index = self.ranges.partition_point(|free| free.start < range.start);
+        self.ranges.insert(index, range);
+        // Merge with the ranges after and before it.
+        if index + 1 < self.ranges.len() && self.ranges[index].end == self.ranges[index + 1].start {
+            self.ranges[index].end = self.ranges.remove(index + 1).end;
+        }
+        if index > 0 && self.ranges[index - 1].end == self.ranges[index].start {
+            self.ranges[index - 1].end = self.ranges.remove(index).end;
+        }
+    }
+}
+
+/// Only poisoned by a panic while allocating, which leaves the free lists intact.
+fn lock(pool: &Mutex<Pool>) -> std::sync::MutexGuard<'_, Pool> {
+    pool.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Aligns ranges to whole vertices of `stride` bytes, at offsets buffers can be written at.
+fn vertex_align(stride: wgpu::BufferAddress) -> wgpu::BufferAddress {
+    stride.max(1) * wgpu::COPY_BUFFER_ALIGNMENT / gcd(stride.max(1), wgpu::COPY_BUFFER_ALIGNMENT)
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn takes_aligned_ranges_in_order() {
+        let mut free = FreeList::new(64);
+        assert_eq!(free.take(10, 4), Some(0));
+        assert_eq!(free.take(8, 8), Some(16));
+        assert_eq!(free.take(4, 4), Some(12));
+        assert_eq!(free.ranges, [10..12, 24..64]);
+        assert_eq!(free.available(), 42);
+    }
+
+    #[test]
+    fn frees_and_coalesces() {
+        let mut free = FreeList::new(48);
+        let offsets: Vec<_> = (0..3).map(|_| free.take(16, 4).unwrap()).collect();
+        assert_eq!(offsets, [0, 16, 32]);
+        assert!(free.ranges.is_empty());
+
+        free.release(0..16);
+        free.release(32..48);
+        assert_eq!(free.ranges, [0..16, 32..48]);
+        assert_eq!(free.take(32, 4), None);
+
+        free.release(16..32);
+        assert_eq!(free.ranges.len(), 1);
+        assert_eq!(free.available(), 48);
+        assert_eq!(free.take(32, 4), Some(0));
+    }
+
+    #[test]
+    fn reuses_freed_ranges_first() {
+        let mut free = FreeList::new(64);
+        let first = free.take(16, 4).unwrap();
+        free.take(16, 4).unwrap();
+        free.release(first..first + 16);
+        assert_eq!(free.take(8, 4), Some(0));
+        assert_eq!(free.take(8, 4), Some(8));
+        assert_eq!(free.take(8, 4), Some(32));
+    }
+
+    #[test]
+    fn grows_into_new_blocks_when_full() {
+        let mut blocks = vec![FreeList::new(64)];
+        assert_eq!(take_first(&mut blocks, 48, 4), Some((0, 0)));
+        assert_eq!(take_first(&mut blocks, 32, 4), None);
+
+        // What `Pool::allocate` does when no block fits.
+        blocks.push(FreeList::new(64));
+        assert_eq!(take_first(&mut blocks, 32, 4), Some((1, 0)));
+        assert_eq!(take_first(&mut blocks, 16, 4), Some((0, 48)));
+        assert_eq!(take_first(&mut blocks, 32, 4), Some((1, 32)));
+        assert_eq!(take_first(&mut blocks, 4, 4), None);
+    }
+
+    #[test]
+    fn aligns_to_whole_vertices() {
+        assert_eq!(vertex_align(4), 4);
+        assert_eq!(vertex_align(12), 12);
+        assert_eq!(vertex_align(60), 60);
+        assert_eq!(vertex_align(6), 12);
+        assert_eq!(vertex_align(0), 4);
+    }
+}
@@ -0,0 +1,99 @@
+//! Morph targets, or blend shapes: displacements of every vertex of a mesh, added to it before
+//! skinning scaled by a weight per target, e.g. to animate facial expressions.
+
+use glam::Vec3;
+
+/// The most morph targets a mesh is drawn with, `MAX_MORPH_TARGETS` in `shader.wgsl` and
+/// `skinning.wgsl`.
+pub const MAX_MORPH_TARGETS: usize = 8;
+
+/// How far a morph target moves one vertex at full weight. Padded to the alignment of `vec3`
+/// in storage buffers.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MorphDelta {
+    pub position: [f32; 3],
+    _padding: f32,
+    pub normal: [f32; 3],
+    _padding2: f32,
+}
+
+impl MorphDelta {
+    pub fn new(position: [f32; 3], normal: [f32; 3]) -> Self {
+        Self {
+            position,
+            normal,
+            ..Default::default()
+        }
+    }
+}
+
+/// The morph targets of a mesh, as loaded from glTF.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MorphTargets {
+    /// The vertices each target displaces, every vertex of the mesh.
+    pub vertex_count: usize,
+    /// The displacement of every vertex by the first target, then by the second and so on.
+    pub deltas: Vec<MorphDelta>,
+    /// The weight of each target when no animation sets it.
+    pub weights: Vec<f32>,
+}
+
+impl MorphTargets {
+    /// The number of targets.
+    pub fn len(&self) -> usize {
+        self.deltas
+            .len()
+            .checked_div(self.vertex_count)
+            .unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// How far each target moves any vertex at most, at full weight.
+    pub fn extents(&self) -> Vec<f32> {
+        self.deltas
+            .chunks(self.vertex_count.max(1))
+            .take(self.len())
+            .map(|deltas| {
+                deltas
+                    .iter()
+                    .map(|delta| Vec3::from(delta.position).length())
+                    .fold(0.0, f32::max)
+            })
+            .collect()
+    }
+}
+
+/// Where a mesh's morph targets are and how much each counts, `Morph` in `shader.wgsl` and
+/// `skinning.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct MorphUniform {
+    pub weights: [f32; MAX_MORPH_TARGETS],
+    /// The index of the mesh's first vertex in the shared vertex buffer, which the shader's
+    /// vertex indices count from.
+    pub base_vertex: u32,
+    pub vertex_count: u32,
+    pub target_count: u32,
+    pub _padding: u32,
+}
+
+impl MorphUniform {
+    /// The first [`MAX_MORPH_TARGETS`] of `weights`, applied to `targets` of a mesh starting at
+    /// `base_vertex`.
+    pub fn new(targets: &MorphTargets, weights: &[f32], base_vertex: u32) -> Self {
+        let mut uniform = Self {
+            base_vertex,
+            vertex_count: targets.vertex_count as u32,
+            target_count: targets.len().min(MAX_MORPH_TARGETS) as u32,
+            ..Default::default()
+        };
+        for (weight, &value) in uniform.weights.iter_mut().zip(weights) {
+            *weight = value;
+        }
+        uniform
+    }
+}
@@ -0,0 +1,152 @@
+use std::sync::Arc;
+
+use wgpu::util::DeviceExt;
+
+use crate::post_process::{self, Effect, EffectContext, EffectPipelineKey};
+use crate::shader::{self, Preprocessor};
+use crate::texture::Texture;
+
+/// `MotionBlur` in `motion_blur.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct MotionBlurUniform {
+    shutter: f32,
+    max_length: f32,
+    _padding: [f32; 2],
+}
+
+/// Motion blur, an [`Effect`] smearing each pixel along the scene's motion vectors, from the
+/// camera as well as from moving instances. Meant to run on the HDR scene.
+pub struct MotionBlur {
+    /// The fraction of the frame the shutter is open, 0.5 matching a 180° film shutter.
+    pub shutter: f32,
+    /// The longest blur in pixels, bounding the smear of fast motion.
+    pub max_length: f32,
+    buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline_layout: Arc<wgpu::PipelineLayout>,
+    shader_module: Arc<wgpu::ShaderModule>,
+}
+
+impl MotionBlur {
+    /// Compiles `motion_blur.wgsl` with `preprocessor`.
+    pub fn new(device: &wgpu::Device, preprocessor: &Preprocessor) -> anyhow::Result<Self> {
+        let shutter = 0.5;
+        let max_length = 32.0;
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("motion blur"),
+            contents: bytemuck::bytes_of(&MotionBlurUniform {
+                shutter,
+                max_length,
+                _padding: [0.0; 2],
+            }),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let [input, input_sampler] = post_process::input_layout_entries();
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("motion blur"),
+            entries: &[
+                input,
+                input_sampler,
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = Arc::new(device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("motion blur"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            },
+        ));
+        let shader_module = Arc::new(shader::create_module(
+            device,
+            preprocessor,
+            "motion_blur.wgsl",
+        )?);
+
+        Ok(Self {
+            shutter,
+            max_length,
+            buffer,
+            bind_group_layout,
+            pipeline_layout,
+            shader_module,
+        })
+    }
+}
+
+impl Effect for MotionBlur {
+    fn name(&self) -> &'static str {
+        "motion blur"
+    }
+
+    fn update(&mut self, queue: &wgpu::Queue) {
+        let uniform = MotionBlurUniform {
+            shutter: self.shutter,
+            max_length: self.max_length,
+            _padding: [0.0; 2],
+        };
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&uniform));
+    }
+
+    fn render(
+        &mut self,
+        context: &mut EffectContext,
+        input: &Texture,
+        output: &wgpu::TextureView,
+        format: wgpu::TextureFormat,
+    ) {
+        let key = EffectPipelineKey {
+            effect: self.name(),
+            format,
+        };
+        let pipeline = context.pipelines.get(
+            &key,
+            &*post_process::pipeline_builder(
+                "motion blur",
+                self.pipeline_layout.clone(),
+                self.shader_module.clone(),
+                format,
+            ),
+        );
+        let [input, input_sampler] = post_process::input_bind_group_entries(input);
+        let bind_group = context
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("motion blur"),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    input,
+                    input_sampler,
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(&context.motion.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: self.buffer.as_entire_binding(),
+                    },
+                ],
+            });
+        context.draw(self.name(), output, &pipeline, &bind_group);
+    }
+}
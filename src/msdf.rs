@@ -0,0 +1,449 @@
+//! Text drawn from multi-channel signed distance fields, which stays sharp at any size and
+//! angle, unlike the glyphs [`TextRenderer`] rasterizes at one size for the screen. Meant for
+//! large text and labels placed in the world, drawn in the scene pass and tested against its
+//! depth.
+//!
+//! [`TextRenderer`]: crate::text::TextRenderer
+
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+
+use glam::{Mat4, Vec2, Vec3};
+use wgpu::util::DeviceExt;
+
+use crate::camera::Camera;
+use crate::pipeline_cache::PipelineBuilder;
+use crate::shader::{self, Preprocessor};
+use crate::taa::MOTION_FORMAT;
+use crate::texture::{SamplerDesc, Texture};
+use crate::upload::Upload;
+use crate::vertex::{VertexLayout, VertexType};
+
+/// Where a glyph is in the atlas and how it sits on the baseline, in ems.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MsdfGlyph {
+    /// How far the pen moves past the glyph.
+    pub advance: f32,
+    /// The left, bottom, right and top of the quad around the baseline, Y up, or `None` for
+    /// glyphs that draw nothing, like spaces.
+    pub plane: Option<[f32; 4]>,
+    /// The left, top, right and bottom of the glyph in the atlas, in UVs.
+    pub uv: [f32; 4],
+}
+
+/// A font's glyphs as distance fields in one atlas, see [`crate::assets::msdf::load`].
+#[derive(Clone, Debug)]
+pub struct MsdfFontData {
+    /// The range of distances the atlas encodes, in atlas pixels.
+    pub distance_range: f32,
+    /// The distance between baselines, in ems.
+    pub line_height: f32,
+    /// How far the top of the tallest glyphs is above the baseline, in ems.
+    pub ascender: f32,
+    pub glyphs: HashMap<char, MsdfGlyph>,
+    /// Added to the advance of the first character of each pair when followed by the second.
+    pub kerning: HashMap<(char, char), f32>,
+    /// Signed distances in the red, green and blue channels, which aren't colors.
+    pub image: image::RgbaImage,
+}
+
+impl MsdfFontData {
+    /// Lays out `text` in ems from its top left corner, X right and Y up, calling `quad` with
+    /// the plane and UV bounds of every visible glyph. Returns the width of the longest line and
+    /// the height of all of them.
+    fn layout(&self, text: &str, mut quad: impl FnMut([f32; 4], [f32; 4])) -> Vec2 {
+        let mut width = 0.0f32;
+        let mut lines = 0;
+        for line in text.lines() {
+            let baseline = -self.ascender - lines as f32 * self.line_height;
+            let mut pen = 0.0;
+            let mut characters = line.chars().peekable();
+            while let Some(character) = characters.next() {
+                let Some(glyph) = self
+                    .glyphs
+                    .get(&character)
+                    .or_else(|| self.glyphs.get(&'?'))
+                else {
+                    continue;
+                };
+                if let Some([left, bottom, right, top]) = glyph.plane {
+                    quad(
+                        [pen + left, baseline + bottom, pen + right, baseline + top],
+                        glyph.uv,
+                    );
+                }
+                pen += glyph.advance;
+                if let Some(&next) = characters.peek() {
+                    pen += self.kerning.get(&(character, next)).copied().unwrap_or(0.0);
+                }
+            }
+            width = width.max(pen);
+            lines += 1;
+        }
+        Vec2::new(width, lines as f32 * self.line_height)
+    }
+}
+
+/// How queued MSDF text looks.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MsdfStyle {
+    /// The size of an em, in world units before the transform.
+    pub size: f32,
+    /// Linear RGBA.
+    pub color: [f32; 4],
+    /// Where the origin lies in the text's bounds, from (0, 0) at their top left to (1, 1) at
+    /// their bottom right.
+    pub anchor: Vec2,
+    /// Turns the text to face the camera at the transform's origin, ignoring its rotation.
+    pub billboard: bool,
+}
+
+impl Default for MsdfStyle {
+    fn default() -> Self {
+        Self {
+            size: 1.0,
+            color: [1.0; 4],
+            anchor: Vec2::ZERO,
+            billboard: false,
+        }
+    }
+}
+
+/// A corner of a glyph's quad.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MsdfVertex {
+    pub position: [f32; 3],
+    pub uv: [f32; 2],
+    /// Linear RGBA.
+    pub color: [f32; 4],
+}
+
+impl VertexType for MsdfVertex {
+    fn vertex_layout() -> &'static VertexLayout {
+        static LAYOUT: OnceLock<VertexLayout> = OnceLock::new();
+        LAYOUT.get_or_init(|| {
+            VertexLayout::new(wgpu::VertexStepMode::Vertex, 0)
+                .with("position", wgpu::VertexFormat::Float32x3)
+                .with("uv", wgpu::VertexFormat::Float32x2)
+                .with("color", wgpu::VertexFormat::Float32x4)
+        })
+    }
+}
+
+/// Everything that distinguishes one MSDF text pipeline from another.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MsdfPipelineKey {
+    pub format: wgpu::TextureFormat,
+    pub sample_count: u32,
+}
+
+/// The distance range of the atlas, which the shader turns into screen pixels.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct MsdfUniform {
+    distance_range: f32,
+    _padding: [f32; 3],
+}
+
+/// A font uploaded for [`MsdfText`].
+struct MsdfFont {
+    data: MsdfFontData,
+    bind_group: wgpu::BindGroup,
+}
+
+struct QueuedText {
+    text: String,
+    transform: Mat4,
+    style: MsdfStyle,
+}
+
+/// Immediate-mode MSDF text in world space: text queued during a frame is laid out into one
+/// vertex buffer, drawn in the scene pass and forgotten. Nothing is drawn until a font is set
+/// with [`MsdfText::set_font`].
+pub struct MsdfText {
+    font: Option<MsdfFont>,
+    queued: Vec<QueuedText>,
+    vertices: Vec<MsdfVertex>,
+    buffer: Option<wgpu::Buffer>,
+    /// The vertices uploaded for the frame being rendered.
+    vertex_count: u32,
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline_layout: Arc<wgpu::PipelineLayout>,
+    shader_module: Arc<wgpu::ShaderModule>,
+}
+
+impl MsdfText {
+    /// Compiles `msdf.wgsl` with `preprocessor`, reading the camera from a bind group with
+    /// `camera_layout`.
+    pub fn new(
+        device: &wgpu::Device,
+        preprocessor: &Preprocessor,
+        camera_layout: &wgpu::BindGroupLayout,
+    ) -> anyhow::Result<Self> {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("msdf font"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = Arc::new(device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("msdf text"),
+                bind_group_layouts: &[camera_layout, &bind_group_layout],
+                push_constant_ranges: &[],
+            },
+        ));
+        let shader_module = Arc::new(shader::create_module(device, preprocessor, "msdf.wgsl")?);
+
+        Ok(Self {
+            font: None,
+            queued: Vec::new(),
+            vertices: Vec::new(),
+            buffer: None,
+            vertex_count: 0,
+            bind_group_layout,
+            pipeline_layout,
+            shader_module,
+        })
+    }
+
+    /// Uploads the atlas of `font`, replacing the previous font.
+    pub fn set_font(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, font: MsdfFontData) {
+        // Linear, since the channels hold distances, and without mips, which would blur them.
+        let texture = Texture::from_rgba(
+            device,
+            queue,
+            &font.image,
+            wgpu::TextureFormat::Rgba8Unorm,
+            "msdf atlas",
+            SamplerDesc::Linear(wgpu::AddressMode::ClampToEdge),
+            None,
+        );
+        let uniform = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("msdf font"),
+            contents: bytemuck::bytes_of(&MsdfUniform {
+                distance_range: font.distance_range,
+                _padding: [0.0; 3],
+            }),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("msdf font"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: uniform.as_entire_binding(),
+                },
+            ],
+        });
+        self.font = Some(MsdfFont {
+            data: font,
+            bind_group,
+        });
+    }
+
+    pub fn font(&self) -> Option<&MsdfFontData> {
+        self.font.as_ref().map(|font| &font.data)
+    }
+
+    /// Whether nothing is queued, or there's no font to draw it with.
+    pub fn is_empty(&self) -> bool {
+        self.font.is_none() || self.queued.is_empty()
+    }
+
+    /// Draws `text` in the XY plane of `transform`, facing +Z, in the next frame. Lines are
+    /// broken at newlines only, and characters missing from the font are drawn as `?`.
+    pub fn queue(&mut self, text: impl Into<String>, transform: Mat4, style: MsdfStyle) {
+        self.queued.push(QueuedText {
+            text: text.into(),
+            transform,
+            style,
+        });
+    }
+
+    /// Lays out the queued text into quads, billboards facing `camera`, uploads them for
+    /// [`MsdfText::draw`] and empties the queue, growing the vertex buffer if it is too small.
+    pub fn upload(&mut self, upload: &mut Upload, camera: &Camera) {
+        self.vertices.clear();
+        if let Some(font) = &self.font {
+            let view = camera.view_matrix();
+            let right = view.row(0).truncate();
+            let up = view.row(1).truncate();
+            for text in &self.queued {
+                let style = text.style;
+                let transform = if style.billboard {
+                    let origin = text.transform.transform_point3(Vec3::ZERO);
+                    Mat4::from_cols(
+                        right.extend(0.0),
+                        up.extend(0.0),
+                        right.cross(up).extend(0.0),
+                        origin.extend(1.0),
+                    )
+                } else {
+                    text.transform
+                };
+                let start = self.vertices.len();
+                let size = font.data.layout(&text.text, |plane, uv| {
+                    let [left, bottom, right, top] = plane;
+                    let corners = [
+                        ([left, bottom], [uv[0], uv[3]]),
+                        ([right, bottom], [uv[2], uv[3]]),
+                        ([right, top], [uv[2], uv[1]]),
+                        ([left, top], [uv[0], uv[1]]),
+                    ];
+                    self.vertices.extend([0, 1, 2, 0, 2, 3].map(|corner| {
+                        let (position, uv) = corners[corner];
+                        MsdfVertex {
+                            position: [position[0], position[1], 0.0],
+                            uv,
+                            color: style.color,
+                        }
+                    }));
+                });
+                // From ems around the top left corner to the world around the anchor.
+                let offset = Vec2::new(-style.anchor.x, style.anchor.y) * size;
+                for vertex in &mut self.vertices[start..] {
+                    let position =
+                        (Vec2::new(vertex.position[0], vertex.position[1]) + offset) * style.size;
+                    vertex.position = transform.transform_point3(position.extend(0.0)).to_array();
+                }
+            }
+        }
+        self.queued.clear();
+
+        self.vertex_count = self.vertices.len() as u32;
+        if self.vertices.is_empty() {
+            return;
+        }
+        let size = std::mem::size_of_val(self.vertices.as_slice()) as wgpu::BufferAddress;
+        if self
+            .buffer
+            .as_ref()
+            .is_none_or(|buffer| buffer.size() < size)
+        {
+            self.buffer = Some(upload.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("msdf text"),
+                size: size.next_power_of_two(),
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }));
+        }
+        if let Some(buffer) = &self.buffer {
+            upload.write(buffer, 0, bytemuck::cast_slice(&self.vertices));
+        }
+    }
+
+    pub fn pipeline_builder(&self, key: MsdfPipelineKey) -> Arc<PipelineBuilder> {
+        let layout = self.pipeline_layout.clone();
+        let shader_module = self.shader_module.clone();
+        Arc::new(move |device, cache| create_pipeline(device, &layout, &shader_module, key, cache))
+    }
+
+    /// Draws the quads uploaded last, with the camera's bind group at group 0.
+    pub fn draw(
+        &self,
+        render_pass: &mut wgpu::RenderPass,
+        pipeline: &wgpu::RenderPipeline,
+        camera_bind_group: &wgpu::BindGroup,
+    ) {
+        let Some((buffer, font)) = self
+            .buffer
+            .as_ref()
+            .zip(self.font.as_ref())
+            .filter(|_| self.vertex_count > 0)
+        else {
+            return;
+        };
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &font.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, buffer.slice(..));
+        render_pass.draw(0..self.vertex_count, 0..1);
+    }
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader_module: &wgpu::ShaderModule,
+    key: MsdfPipelineKey,
+    cache: Option<&wgpu::PipelineCache>,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("msdf text"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader_module,
+            entry_point: "vs_main",
+            buffers: &[MsdfVertex::vertex_layout().buffer_layout()],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        // Text seen from behind reads mirrored, but is still there.
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: Texture::DEPTH_FORMAT,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::LessEqual,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: key.sample_count,
+            ..Default::default()
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader_module,
+            entry_point: "fs_main",
+            targets: &[
+                Some(wgpu::ColorTargetState {
+                    format: key.format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }),
+                Some(wgpu::ColorTargetState {
+                    format: MOTION_FORMAT,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::empty(),
+                }),
+            ],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        }),
+        multiview: None,
+        cache,
+    })
+}
@@ -0,0 +1,111 @@
+//! Nine-slice quads for UI panels and buttons, whose corners keep their size while the edges
+//! and the middle stretch to fill the rest.
+
+use glam::Vec2;
+
+use crate::sprite::{self, Sprite, SpriteBatch, SpriteTexture};
+
+/// A texture region cut into nine by border insets: the corners are drawn unscaled, the edges
+/// stretched along their length and the center both ways.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NinePatch {
+    pub texture: SpriteTexture,
+    /// The left, top, right and bottom of the region in the texture, in UVs.
+    pub uv: [f32; 4],
+    /// The size of the region in texels.
+    pub size: Vec2,
+    /// The width of the left, top, right and bottom borders of the region in texels.
+    pub insets: [f32; 4],
+    /// Screen units per texel of the borders, to match the resolution of the rest of the UI.
+    pub border_scale: f32,
+    /// Whether the middle is drawn, which frames leave out.
+    pub draw_center: bool,
+}
+
+impl NinePatch {
+    /// The whole of `texture`, `size` texels big, cut by `insets`.
+    pub fn new(texture: SpriteTexture, size: Vec2, insets: [f32; 4]) -> Self {
+        Self {
+            texture,
+            uv: [0.0, 0.0, 1.0, 1.0],
+            size,
+            insets,
+            border_scale: 1.0,
+            draw_center: true,
+        }
+    }
+
+    /// Uses the part of the texture at `uv` instead, `size` texels big.
+    pub fn with_region(mut self, uv: [f32; 4], size: Vec2) -> Self {
+        self.uv = uv;
+        self.size = size;
+        self
+    }
+
+    pub fn with_border_scale(mut self, border_scale: f32) -> Self {
+        self.border_scale = border_scale;
+        self
+    }
+
+    pub fn with_center(mut self, draw_center: bool) -> Self {
+        self.draw_center = draw_center;
+        self
+    }
+
+    /// The slices filling `panel`, which gives the position, size, anchor, rotation, color and
+    /// layer of the whole; its UVs are ignored. Borders wider than the panel shrink to fit it.
+    pub fn sprites(&self, panel: Sprite) -> impl Iterator<Item = Sprite> {
+        let [left, top, right, bottom] = self.insets;
+        let fit = |start: f32, end: f32, length: f32| {
+            let border = (start + end) * self.border_scale;
+            let shrink = if border > length {
+                length / border
+            } else {
+                1.0
+            };
+            let scale = self.border_scale * shrink;
+            [0.0, start * scale, length - end * scale, length]
+        };
+        let xs = fit(left, right, panel.size.x);
+        let ys = fit(top, bottom, panel.size.y);
+        let split = |start: f32, end: f32, length: f32| {
+            let length = length.max(f32::EPSILON);
+            [0.0, start / length, 1.0 - end / length, 1.0]
+        };
+        let us = split(left, right, self.size.x);
+        let vs = split(top, bottom, self.size.y);
+
+        let (sin, cos) = panel.rotation.sin_cos();
+        let origin = panel.anchor * panel.size;
+        let (uv, draw_center) = (self.uv, self.draw_center);
+        (0..3)
+            .flat_map(|row| (0..3).map(move |column| (column, row)))
+            .filter(move |&slice| draw_center || slice != (1, 1))
+            .filter_map(move |(column, row)| {
+                let size = Vec2::new(xs[column + 1] - xs[column], ys[row + 1] - ys[row]);
+                if size.x <= 0.0 || size.y <= 0.0 {
+                    return None;
+                }
+                // Rotated around the panel's anchor rather than each slice's own.
+                let offset = Vec2::new(xs[column], ys[row]) - origin;
+                let rotated = Vec2::new(
+                    offset.x * cos - offset.y * sin,
+                    offset.x * sin + offset.y * cos,
+                );
+                Some(Sprite {
+                    position: panel.position + rotated,
+                    size,
+                    anchor: Vec2::ZERO,
+                    uv: sprite::map_uv(uv, [us[column], vs[row], us[column + 1], vs[row + 1]]),
+                    ..panel
+                })
+            })
+    }
+
+    /// Draws the slices filling `panel` with the next frame, see [`NinePatch::sprites`].
+    pub fn draw(&self, batch: &mut SpriteBatch, panel: Sprite) {
+        for sprite in self.sprites(panel) {
+            batch.draw(self.texture, sprite);
+        }
+    }
+}
@@ -0,0 +1,349 @@
+use std::sync::{mpsc, Arc, OnceLock};
+
+use glam::{Mat4, Vec3};
+use wgpu::util::DeviceExt;
+
+use crate::instance::InstanceRaw;
+use crate::math::Aabb;
+use crate::pipeline_cache::PipelineBuilder;
+use crate::shader::{self, Preprocessor};
+use crate::taa::MOTION_FORMAT;
+use crate::texture::Texture;
+use crate::tonemap::HDR_FORMAT;
+use crate::vertex::{VertexLayout, VertexType};
+
+/// The most instances queried in a frame. Any others are never considered occluded.
+const MAX_QUERIES: u32 = 4096;
+
+/// The faces of a box as two triangles each, between its corners, whose index has a bit per
+/// axis: X, Y and Z.
+const BOX_INDICES: [u16; 36] = [
+    0, 2, 6, 0, 6, 4, // -X
+    1, 3, 7, 1, 7, 5, // +X
+    0, 1, 5, 0, 5, 4, // -Y
+    2, 3, 7, 2, 7, 6, // +Y
+    0, 1, 3, 0, 3, 2, // -Z
+    4, 5, 7, 4, 7, 6, // +Z
+];
+
+/// A corner of the box drawn for an occlusion query.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ProxyVertex {
+    position: [f32; 3],
+}
+
+impl VertexType for ProxyVertex {
+    fn vertex_layout() -> &'static VertexLayout {
+        static LAYOUT: OnceLock<VertexLayout> = OnceLock::new();
+        LAYOUT.get_or_init(|| {
+            VertexLayout::new(wgpu::VertexStepMode::Vertex, 0)
+                .with("position", wgpu::VertexFormat::Float32x3)
+        })
+    }
+}
+
+/// Everything that distinguishes one proxy pipeline from another.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct OcclusionPipelineKey {
+    pub sample_count: u32,
+}
+
+/// Query results copied into `buffer`, waiting for the buffer to be mapped.
+struct PendingQueries {
+    /// The instance of each query.
+    instances: Vec<u32>,
+    buffer: wgpu::Buffer,
+    mapped: Option<mpsc::Receiver<Result<(), wgpu::BufferAsyncError>>>,
+}
+
+/// Skips instances hidden behind others: the bounding box of every instance inside the frustum
+/// is drawn in the scene pass after the geometry, each inside an occlusion query counting the
+/// samples passing the depth test. Instances none of whose samples passed are skipped in the
+/// following frames, until their box shows again. The results are read back without stalling,
+/// so instances appear a frame or two late when they come out from behind others.
+pub struct OcclusionCulling {
+    enabled: bool,
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    proxy_vertices: wgpu::Buffer,
+    proxy_indices: wgpu::Buffer,
+    pipeline_layout: Arc<wgpu::PipelineLayout>,
+    shader_module: Arc<wgpu::ShaderModule>,
+    /// The instance of each query issued this frame.
+    queried: Vec<u32>,
+    pending: Option<PendingQueries>,
+    /// Whether the box of each instance was hidden in the last results read back.
+    occluded: Vec<bool>,
+}
+
+impl OcclusionCulling {
+    /// Compiles `occlusion.wgsl` with `preprocessor`, reading the camera from a bind group with
+    /// `camera_layout`.
+    pub fn new(
+        device: &wgpu::Device,
+        preprocessor: &Preprocessor,
+        camera_layout: &wgpu::BindGroupLayout,
+    ) -> anyhow::Result<Self> {
+        let pipeline_layout = Arc::new(device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("occlusion"),
+                bind_group_layouts: &[camera_layout],
+                push_constant_ranges: &[],
+            },
+        ));
+        let shader_module = Arc::new(shader::create_module(
+            device,
+            preprocessor,
+            "occlusion.wgsl",
+        )?);
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("occlusion"),
+            ty: wgpu::QueryType::Occlusion,
+            count: MAX_QUERIES,
+        });
+        let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("occlusion resolve"),
+            size: MAX_QUERIES as wgpu::BufferAddress * 8,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let proxy_vertices = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("occlusion proxy"),
+            size: std::mem::size_of::<[ProxyVertex; 8]>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let proxy_indices = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("occlusion proxy"),
+            contents: bytemuck::cast_slice(&BOX_INDICES),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        Ok(Self {
+            enabled: true,
+            query_set,
+            resolve_buffer,
+            proxy_vertices,
+            proxy_indices,
+            pipeline_layout,
+            shader_module,
+            queried: Vec::new(),
+            pending: None,
+            occluded: Vec::new(),
+        })
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Forgets the instances found occluded when disabled.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.occluded.clear();
+        }
+    }
+
+    /// Whether each instance, by index, was hidden as of the last results. Shorter than the
+    /// instances, or empty, where they haven't been queried.
+    pub fn occluded(&self) -> &[bool] {
+        &self.occluded
+    }
+
+    /// Picks the queries of this frame: one for each of `in_frustum`, indices into `instances`,
+    /// unless `eye` is inside its box with `bounds`, which would be clipped by the near plane.
+    /// Nothing is queried while the results of an earlier frame are still being read back.
+    pub fn prepare(
+        &mut self,
+        queue: &wgpu::Queue,
+        bounds: &Aabb,
+        eye: Vec3,
+        instances: &[InstanceRaw],
+        in_frustum: &[u32],
+    ) {
+        self.queried.clear();
+        if !self.enabled || self.pending.is_some() || bounds.is_empty() {
+            return;
+        }
+        let corners: [ProxyVertex; 8] = std::array::from_fn(|corner| ProxyVertex {
+            position: Vec3::select(
+                glam::BVec3::new(corner & 1 != 0, corner & 2 != 0, corner & 4 != 0),
+                bounds.max,
+                bounds.min,
+            )
+            .to_array(),
+        });
+        queue.write_buffer(&self.proxy_vertices, 0, bytemuck::cast_slice(&corners));
+        self.queried.extend(
+            in_frustum
+                .iter()
+                .copied()
+                .filter(|&index| {
+                    let transform = Mat4::from_cols_array_2d(&instances[index as usize].model);
+                    !bounds.contains(transform.inverse().transform_point3(eye))
+                })
+                .take(MAX_QUERIES as usize),
+        );
+    }
+
+    /// The query set for the scene pass, if it has any queries to run this frame.
+    pub fn query_set(&self) -> Option<&wgpu::QuerySet> {
+        (!self.queried.is_empty()).then_some(&self.query_set)
+    }
+
+    /// Draws the box of each queried instance from `instance_buffer` in its own query, in a
+    /// render pass with [`OcclusionCulling::query_set`].
+    pub fn draw_proxies(
+        &self,
+        render_pass: &mut wgpu::RenderPass,
+        pipeline: &wgpu::RenderPipeline,
+        camera_bind_group: &wgpu::BindGroup,
+        instance_buffer: &wgpu::Buffer,
+    ) {
+        if self.queried.is_empty() {
+            return;
+        }
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.proxy_vertices.slice(..));
+        render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
+        render_pass.set_index_buffer(self.proxy_indices.slice(..), wgpu::IndexFormat::Uint16);
+        for (query, &instance) in self.queried.iter().enumerate() {
+            render_pass.begin_occlusion_query(query as u32);
+            render_pass.draw_indexed(0..BOX_INDICES.len() as u32, 0, instance..instance + 1);
+            render_pass.end_occlusion_query();
+        }
+    }
+
+    /// Records the copy of this frame's results into a readback buffer, after the scene pass.
+    pub fn resolve(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
+        if self.queried.is_empty() {
+            return;
+        }
+        let count = self.queried.len() as u32;
+        let size = count as wgpu::BufferAddress * 8;
+        encoder.resolve_query_set(&self.query_set, 0..count, &self.resolve_buffer, 0);
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("occlusion readback"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        encoder.copy_buffer_to_buffer(&self.resolve_buffer, 0, &buffer, 0, size);
+        self.pending = Some(PendingQueries {
+            instances: std::mem::take(&mut self.queried),
+            buffer,
+            mapped: None,
+        });
+    }
+
+    /// Starts mapping the readback buffer, once the frame copying into it has been submitted.
+    pub fn after_submit(&mut self) {
+        let Some(pending) = &mut self.pending else {
+            return;
+        };
+        if pending.mapped.is_some() {
+            return;
+        }
+        let (sender, receiver) = mpsc::channel();
+        pending
+            .buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+        pending.mapped = Some(receiver);
+    }
+
+    /// Takes in the results that have been read back since the previous call, if any.
+    pub fn poll(&mut self, device: &wgpu::Device) {
+        let Some(receiver) = self
+            .pending
+            .as_ref()
+            .and_then(|pending| pending.mapped.as_ref())
+        else {
+            return;
+        };
+        device.poll(wgpu::Maintain::Poll);
+        let result = match receiver.try_recv() {
+            Ok(result) => result,
+            Err(mpsc::TryRecvError::Empty) => return,
+            Err(mpsc::TryRecvError::Disconnected) => Err(wgpu::BufferAsyncError),
+        };
+        let Some(pending) = self.pending.take() else {
+            return;
+        };
+        if let Err(err) = result {
+            eprintln!("failed to read back the occlusion queries: {err}");
+            return;
+        }
+        self.occluded.clear();
+        if self.enabled {
+            let samples = pending.buffer.slice(..).get_mapped_range();
+            for (&instance, samples) in pending.instances.iter().zip(samples.chunks_exact(8)) {
+                let instance = instance as usize;
+                if self.occluded.len() <= instance {
+                    self.occluded.resize(instance + 1, false);
+                }
+                self.occluded[instance] = u64::from_ne_bytes(samples.try_into().unwrap()) == 0;
+            }
+        }
+        pending.buffer.unmap();
+    }
+
+    pub fn pipeline_builder(&self, key: OcclusionPipelineKey) -> Arc<PipelineBuilder> {
+        let layout = self.pipeline_layout.clone();
+        let shader_module = self.shader_module.clone();
+        Arc::new(move |device, cache| create_pipeline(device, &layout, &shader_module, key, cache))
+    }
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader_module: &wgpu::ShaderModule,
+    key: OcclusionPipelineKey,
+    cache: Option<&wgpu::PipelineCache>,
+) -> wgpu::RenderPipeline {
+    let hidden = |format: wgpu::TextureFormat| wgpu::ColorTargetState {
+        format,
+        blend: None,
+        write_mask: wgpu::ColorWrites::empty(),
+    };
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("occlusion"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader_module,
+            entry_point: "vs_main",
+            buffers: &[
+                ProxyVertex::vertex_layout().buffer_layout(),
+                InstanceRaw::layout(),
+            ],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        // Both sides, so that boxes the camera looks into still count.
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: Texture::DEPTH_FORMAT,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::LessEqual,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: key.sample_count,
+            ..Default::default()
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader_module,
+            entry_point: "fs_main",
+            targets: &[Some(hidden(HDR_FORMAT)), Some(hidden(MOTION_FORMAT))],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        }),
+        multiview: None,
+        cache,
+    })
+}
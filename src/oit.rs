@@ -0,0 +1,181 @@
+//! Weighted blended order-independent transparency, see "Weighted Blended Order-Independent
+//! Transparency" by McGuire and Bavoil. Transparent surfaces are summed into an accumulation
+//! target and multiplied into a revealage target in any order, and a fullscreen pass then blends
+//! their weighted average over the opaque scene.
+
+use std::sync::Arc;
+
+use crate::pipeline_cache::PipelineBuilder;
+use crate::profiler::GpuProfiler;
+use crate::shader::{self, Preprocessor};
+use crate::texture::Texture;
+
+/// The weighted sums of premultiplied colors and alphas, which need the range of half floats.
+pub const ACCUM_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+/// How much of the scene behind the transparent surfaces shows through, from 0 to 1.
+pub const REVEALAGE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+
+/// The color targets of pipelines drawing transparent surfaces, whose fragment shaders output
+/// the weighted color to the first and the alpha to the second. Accumulation is cleared to zero
+/// and revealage to one.
+pub fn targets() -> [Option<wgpu::ColorTargetState>; 2] {
+    let add = wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::One,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+    };
+    let multiply = wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::Zero,
+        dst_factor: wgpu::BlendFactor::OneMinusSrc,
+        operation: wgpu::BlendOperation::Add,
+    };
+    [
+        Some(wgpu::ColorTargetState {
+            format: ACCUM_FORMAT,
+            blend: Some(wgpu::BlendState {
+                color: add,
+                alpha: add,
+            }),
+            write_mask: wgpu::ColorWrites::ALL,
+        }),
+        Some(wgpu::ColorTargetState {
+            format: REVEALAGE_FORMAT,
+            blend: Some(wgpu::BlendState {
+                color: multiply,
+                alpha: multiply,
+            }),
+            write_mask: wgpu::ColorWrites::RED,
+        }),
+    ]
+}
+
+/// Composites the transparent surfaces accumulated in the [`targets`] over the scene.
+pub struct Oit {
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline_layout: Arc<wgpu::PipelineLayout>,
+    shader_module: Arc<wgpu::ShaderModule>,
+}
+
+impl Oit {
+    /// Compiles `oit_composite.wgsl` with `preprocessor`.
+    pub fn new(device: &wgpu::Device, preprocessor: &Preprocessor) -> anyhow::Result<Self> {
+        let texture = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("oit composite"),
+            entries: &[texture(0), texture(1)],
+        });
+        let pipeline_layout = Arc::new(device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("oit composite"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            },
+        ));
+        let shader_module = Arc::new(shader::create_module(
+            device,
+            preprocessor,
+            "oit_composite.wgsl",
+        )?);
+
+        Ok(Self {
+            bind_group_layout,
+            pipeline_layout,
+            shader_module,
+        })
+    }
+
+    /// Returns a builder for the pipeline blending the transparent surfaces over `format`
+    /// targets.
+    pub fn pipeline_builder(&self, format: wgpu::TextureFormat) -> Arc<PipelineBuilder> {
+        let layout = self.pipeline_layout.clone();
+        let shader_module = self.shader_module.clone();
+        Arc::new(move |device, cache| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("oit composite"),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader_module,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                },
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader_module,
+                    entry_point: "fs_main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::COLOR,
+                    })],
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                }),
+                multiview: None,
+                cache,
+            })
+        })
+    }
+
+    /// The bind group reading the resolved accumulation and revealage targets.
+    pub fn bind_group(
+        &self,
+        device: &wgpu::Device,
+        accum: &Texture,
+        revealage: &Texture,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("oit composite"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&accum.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&revealage.view),
+                },
+            ],
+        })
+    }
+
+    /// Blends the transparent surfaces over `output`, keeping what it holds.
+    pub fn composite(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        profiler: Option<&mut GpuProfiler>,
+        pipeline: &wgpu::RenderPipeline,
+        bind_group: &wgpu::BindGroup,
+        output: &wgpu::TextureView,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("oit composite"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: output,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: profiler
+                .and_then(|profiler| profiler.timestamp_writes("oit composite")),
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
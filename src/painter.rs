@@ -0,0 +1,428 @@
+//! Immediate-mode 2D vector shapes, tessellated on the CPU every frame and drawn with the sprite
+//! pipeline over the sprites.
+
+use std::f32::consts::{PI, TAU};
+
+use glam::{Mat4, Vec2};
+use wgpu::util::DeviceExt;
+
+use crate::curve::{Bezier, CatmullRom};
+use crate::sprite::{SpriteBatch, SpriteVertex};
+use crate::texture::Texture;
+use crate::upload::Upload;
+
+/// How far, in pixels, curves may stray from the true shape between their segments.
+const CURVE_TOLERANCE: f32 = 0.25;
+
+/// How much longer than half the stroke width a miter join may be before it is cut short.
+const MITER_LIMIT: f32 = 4.0;
+
+/// An outline `width` pixels wide.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stroke {
+    pub width: f32,
+    /// Linear RGBA.
+    pub color: [f32; 4],
+}
+
+impl Stroke {
+    pub fn new(width: f32, color: [f32; 4]) -> Self {
+        Self { width, color }
+    }
+}
+
+/// How a shape is painted: filled, outlined or both, the outline over the fill.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PaintStyle {
+    /// Linear RGBA.
+    pub fill: Option<[f32; 4]>,
+    pub stroke: Option<Stroke>,
+}
+
+impl PaintStyle {
+    pub fn fill(color: [f32; 4]) -> Self {
+        Self {
+            fill: Some(color),
+            stroke: None,
+        }
+    }
+
+    pub fn stroke(width: f32, color: [f32; 4]) -> Self {
+        Self {
+            fill: None,
+            stroke: Some(Stroke::new(width, color)),
+        }
+    }
+
+    pub fn with_fill(mut self, color: [f32; 4]) -> Self {
+        self.fill = Some(color);
+        self
+    }
+
+    pub fn with_stroke(mut self, width: f32, color: [f32; 4]) -> Self {
+        self.stroke = Some(Stroke::new(width, color));
+        self
+    }
+}
+
+/// Immediate-mode rectangles, circles, arcs, lines and polygons in screen pixels from the top
+/// left, Y down: shapes added during a frame are tessellated into one indexed vertex buffer,
+/// drawn in the order they were added and forgotten. Edges fade out over [`Painter::feather`]
+/// pixels instead of multisampling.
+pub struct Painter {
+    /// The width in pixels over which edges fade out, 0 for aliased edges.
+    pub feather: f32,
+    vertices: Vec<SpriteVertex>,
+    indices: Vec<u32>,
+    vertex_buffer: Option<wgpu::Buffer>,
+    index_buffer: Option<wgpu::Buffer>,
+    /// The indices uploaded for the frame being rendered.
+    index_count: u32,
+    projection: wgpu::Buffer,
+    projection_bind_group: wgpu::BindGroup,
+    /// A white texel, which the sprite pipeline samples and multiplies by the vertex colors.
+    white: wgpu::BindGroup,
+}
+
+impl Painter {
+    /// Creates the bind groups the shapes are drawn with through the pipeline of `batch`.
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, batch: &SpriteBatch) -> Self {
+        let projection = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("painter projection"),
+            contents: bytemuck::bytes_of(&Mat4::IDENTITY.to_cols_array_2d()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let projection_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("painter projection"),
+            layout: batch.projection_layout(),
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: projection.as_entire_binding(),
+            }],
+        });
+        let white = Texture::from_color(
+            device,
+            queue,
+            [255; 4],
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+            "painter white",
+        )
+        .create_bind_group(device, batch.texture_layout());
+
+        Self {
+            feather: 1.0,
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            vertex_buffer: None,
+            index_buffer: None,
+            index_count: 0,
+            projection,
+            projection_bind_group,
+            white,
+        }
+    }
+
+    /// Whether nothing has been added this frame.
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Forgets the shapes added this frame.
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+        self.indices.clear();
+    }
+
+    /// The rectangle from `min`, `size` pixels big.
+    pub fn rect(&mut self, min: Vec2, size: Vec2, style: PaintStyle) {
+        let max = min + size;
+        self.polygon(
+            &[min, Vec2::new(max.x, min.y), max, Vec2::new(min.x, max.y)],
+            style,
+        );
+    }
+
+    pub fn circle(&mut self, center: Vec2, radius: f32, style: PaintStyle) {
+        let segments = curve_segments(radius, TAU);
+        let points: Vec<_> = (0..segments)
+            .map(|segment| {
+                center + Vec2::from_angle(segment as f32 / segments as f32 * TAU) * radius
+            })
+            .collect();
+        self.polygon(&points, style);
+    }
+
+    /// The part of a circle from angle `start` to `end`, clockwise on screen in radians from
+    /// the right. Its fill is the pie slice between the arc and the center.
+    pub fn arc(&mut self, center: Vec2, radius: f32, start: f32, end: f32, style: PaintStyle) {
+        let sweep = end - start;
+        let segments = curve_segments(radius, sweep.abs());
+        let points: Vec<_> = (0..=segments)
+            .map(|segment| {
+                let angle = start + sweep * segment as f32 / segments as f32;
+                center + Vec2::from_angle(angle) * radius
+            })
+            .collect();
+        if let Some(color) = style.fill {
+            // A fan from the center covers the slice even when it is wider than half a circle.
+            let mut slice = Vec::with_capacity(points.len() + 1);
+            slice.push(center);
+            slice.extend_from_slice(&points);
+            self.fill_convex(&slice, color);
+        }
+        if let Some(stroke) = style.stroke {
+            self.polyline(&points, false, stroke);
+        }
+    }
+
+    pub fn line(&mut self, start: Vec2, end: Vec2, stroke: Stroke) {
+        self.polyline(&[start, end], false, stroke);
+    }
+
+    /// A quadratic or cubic Bezier curve.
+    pub fn bezier(&mut self, curve: &Bezier<Vec2>, stroke: Stroke) {
+        self.polyline(&curve.flatten(CURVE_TOLERANCE), false, stroke);
+    }
+
+    /// A curve through the points of `spline`, painted as a polygon if it is closed.
+    pub fn spline(&mut self, spline: &CatmullRom<Vec2>, style: PaintStyle) {
+        let points = spline.flatten(CURVE_TOLERANCE);
+        if spline.closed {
+            self.polygon(&points, style);
+        } else if let Some(stroke) = style.stroke {
+            self.polyline(&points, false, stroke);
+        }
+    }
+
+    /// A closed shape through `points`, which is filled correctly only when convex.
+    pub fn polygon(&mut self, points: &[Vec2], style: PaintStyle) {
+        if let Some(color) = style.fill {
+            self.fill_convex(points, color);
+        }
+        if let Some(stroke) = style.stroke {
+            self.polyline(points, true, stroke);
+        }
+    }
+
+    /// Lines through `points`, back to the first if `closed`, with mitered corners.
+    pub fn polyline(&mut self, points: &[Vec2], closed: bool, stroke: Stroke) {
+        let points = dedup(points, closed);
+        if points.len() < 2 || stroke.width <= 0.0 {
+            return;
+        }
+        let normals = vertex_normals(&points, closed);
+        let mut color = stroke.color;
+        let base = self.vertices.len() as u32;
+        // Rings of vertices along the line, from one edge to the other, between each of
+        // which a strip of quads is drawn.
+        let rings: Vec<(f32, f32)> = if self.feather > 0.0 {
+            // Thinner lines are drawn a feather wide and fainter instead.
+            if stroke.width < self.feather {
+                color[3] *= stroke.width / self.feather;
+            }
+            let width = stroke.width.max(self.feather);
+            let (inner, outer) = ((width - self.feather) / 2.0, (width + self.feather) / 2.0);
+            vec![(outer, 0.0), (inner, 1.0), (-inner, 1.0), (-outer, 0.0)]
+        } else {
+            let half = stroke.width / 2.0;
+            vec![(half, 1.0), (-half, 1.0)]
+        };
+        for (point, normal) in points.iter().zip(&normals) {
+            for &(offset, alpha) in &rings {
+                self.vertices
+                    .push(vertex(*point + *normal * offset, color, alpha));
+            }
+        }
+
+        let stride = rings.len() as u32;
+        let count = points.len() as u32;
+        let segments = if closed { count } else { count - 1 };
+        for segment in 0..segments {
+            let a = base + segment * stride;
+            let b = base + (segment + 1) % count * stride;
+            for ring in 0..stride - 1 {
+                self.quad(a + ring, b + ring, b + ring + 1, a + ring + 1);
+            }
+        }
+    }
+
+    /// Fills the convex polygon through `points`, fading its edges out over the feather.
+    fn fill_convex(&mut self, points: &[Vec2], color: [f32; 4]) {
+        let points = dedup(points, true);
+        if points.len() < 3 {
+            return;
+        }
+        let base = self.vertices.len() as u32;
+        let count = points.len() as u32;
+        if self.feather <= 0.0 {
+            self.vertices
+                .extend(points.iter().map(|point| vertex(*point, color, 1.0)));
+            for i in 1..count - 1 {
+                self.indices.extend([base, base + i, base + i + 1]);
+            }
+            return;
+        }
+
+        // Outward whichever way the points wind.
+        let normals = vertex_normals(&points, true);
+        let half = self.feather / 2.0;
+        for (point, normal) in points.iter().zip(&normals) {
+            self.vertices.extend([
+                vertex(*point - *normal * half, color, 1.0),
+                vertex(*point + *normal * half, color, 0.0),
+            ]);
+        }
+        for i in 1..count - 1 {
+            self.indices
+                .extend([base, base + i * 2, base + (i + 1) * 2]);
+        }
+        for i in 0..count {
+            let (a, b) = (base + i * 2, base + (i + 1) % count * 2);
+            self.quad(a, b, b + 1, a + 1);
+        }
+    }
+
+    fn quad(&mut self, a: u32, b: u32, c: u32, d: u32) {
+        self.indices.extend([a, b, c, a, c, d]);
+    }
+
+    /// Uploads the shapes added this frame for [`Painter::render`] along with `view_projection`
+    /// and clears them, growing the buffers if they are too small.
+    pub fn upload(&mut self, upload: &mut Upload, view_projection: Mat4) {
+        self.index_count = self.indices.len() as u32;
+        if self.indices.is_empty() {
+            self.vertices.clear();
+            return;
+        }
+        upload.write(
+            &self.projection,
+            0,
+            bytemuck::bytes_of(&view_projection.to_cols_array_2d()),
+        );
+        write_grown(
+            upload,
+            &mut self.vertex_buffer,
+            "painter vertices",
+            wgpu::BufferUsages::VERTEX,
+            bytemuck::cast_slice(&self.vertices),
+        );
+        write_grown(
+            upload,
+            &mut self.index_buffer,
+            "painter indices",
+            wgpu::BufferUsages::INDEX,
+            bytemuck::cast_slice(&self.indices),
+        );
+        self.clear();
+    }
+
+    /// Draws the shapes uploaded last with the sprite pipeline.
+    pub fn render(&self, render_pass: &mut wgpu::RenderPass, pipeline: &wgpu::RenderPipeline) {
+        let (Some(vertices), Some(indices)) = (&self.vertex_buffer, &self.index_buffer) else {
+            return;
+        };
+        if self.index_count == 0 {
+            return;
+        }
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, &self.projection_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.white, &[]);
+        render_pass.set_vertex_buffer(0, vertices.slice(..));
+        render_pass.set_index_buffer(indices.slice(..), wgpu::IndexFormat::Uint32);
+        render_pass.draw_indexed(0..self.index_count, 0, 0..1);
+    }
+}
+
+fn vertex(position: Vec2, [r, g, b, a]: [f32; 4], alpha: f32) -> SpriteVertex {
+    SpriteVertex {
+        position: position.to_array(),
+        uv: [0.5, 0.5],
+        color: [r, g, b, a * alpha],
+    }
+}
+
+/// Writes `data` to `buffer`, replacing it first with one a power of two big enough if needed.
+fn write_grown(
+    upload: &mut Upload,
+    buffer: &mut Option<wgpu::Buffer>,
+    label: &str,
+    usage: wgpu::BufferUsages,
+    data: &[u8],
+) {
+    let size = data.len() as wgpu::BufferAddress;
+    if buffer.as_ref().is_none_or(|buffer| buffer.size() < size) {
+        *buffer = Some(upload.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: size.next_power_of_two(),
+            usage: usage | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        }));
+    }
+    if let Some(buffer) = buffer {
+        upload.write(buffer, 0, data);
+    }
+}
+
+/// How many segments a circular curve of `radius` pixels spanning `angle` radians needs to
+/// stay within [`CURVE_TOLERANCE`] of the true curve.
+fn curve_segments(radius: f32, angle: f32) -> usize {
+    let step = if radius > CURVE_TOLERANCE {
+        2.0 * (1.0 - CURVE_TOLERANCE / radius).acos()
+    } else {
+        PI
+    };
+    ((angle / step).ceil() as usize).clamp(1, 512)
+}
+
+/// `points` without consecutive duplicates, which have no direction between them, counting
+/// the last and the first as consecutive if `closed`.
+fn dedup(points: &[Vec2], closed: bool) -> Vec<Vec2> {
+    let mut points = points.to_vec();
+    points.dedup_by(|a, b| a.distance_squared(*b) < 1e-6);
+    if closed && points.len() > 1 && points[0].distance_squared(points[points.len() - 1]) < 1e-6 {
+        points.pop();
+    }
+    points
+}
+
+/// Per point, the offset of a line one unit to each side: the miter of the normals of the
+/// segments meeting there, pointing out of the shape if `closed` and to the right of the
+/// direction of travel on screen otherwise.
+fn vertex_normals(points: &[Vec2], closed: bool) -> Vec<Vec2> {
+    let count = points.len();
+    // Positive when the points wind clockwise on screen, with Y down.
+    let area: f32 = (0..count)
+        .map(|i| points[i].perp_dot(points[(i + 1) % count]))
+        .sum();
+    let sign = if closed && area > 0.0 { -1.0 } else { 1.0 };
+    let segment_normal = |i: usize| {
+        let direction = (points[(i + 1) % count] - points[i]).normalize_or_zero();
+        direction.perp() * sign
+    };
+    (0..count)
+        .map(|i| {
+            let before = if i > 0 || closed {
+                segment_normal((i + count - 1) % count)
+            } else {
+                segment_normal(0)
+            };
+            let after = if i + 1 < count || closed {
+                segment_normal(i)
+            } else {
+                before
+            };
+            let miter = (before + after).normalize_or_zero();
+            let cos = miter.dot(after);
+            if cos <= 1.0 / MITER_LIMIT {
+                // Too sharp, or a full turn back: a bevel-like cut rather than a long spike.
+                if miter == Vec2::ZERO {
+                    after
+                } else {
+                    miter * MITER_LIMIT
+                }
+            } else {
+                miter / cos
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,443 @@
+use std::sync::Arc;
+
+use glam::Vec3;
+
+use crate::camera::Camera;
+use crate::gpu_sort::GpuSort;
+use crate::pipeline_cache::PipelineBuilder;
+use crate::profiler::GpuProfiler;
+use crate::shader::{self, Preprocessor};
+use crate::taa::MOTION_FORMAT;
+use crate::texture::Texture;
+use crate::tonemap::HDR_FORMAT;
+use crate::uniform::UniformBuffer;
+
+/// The invocations per workgroup of `particles.wgsl`.
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Where and how particles are emitted, and how they look over their lifetime.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ParticleEmitter {
+    pub position: Vec3,
+    pub direction: Vec3,
+    /// The half angle of the cone around `direction` particles are emitted in, in radians.
+    pub spread: f32,
+    /// In units per second.
+    pub speed: f32,
+    /// Particles emitted per second.
+    pub rate: f32,
+    /// How long particles live, in seconds. Each lives between three quarters of it and all
+    /// of it.
+    pub lifetime: f32,
+    /// The acceleration of every particle.
+    pub gravity: Vec3,
+    /// The width of the quads.
+    pub size: f32,
+    /// Linear RGBA when emitted, blended to `end_color` as particles age.
+    pub start_color: [f32; 4],
+    pub end_color: [f32; 4],
+}
+
+/// A fountain of sparks above the origin.
+impl Default for ParticleEmitter {
+    fn default() -> Self {
+        Self {
+            position: Vec3::new(0.0, 1.0, 0.0),
+            direction: Vec3::Y,
+            spread: 0.35,
+            speed: 4.0,
+            rate: 2000.0,
+            lifetime: 2.0,
+            gravity: Vec3::new(0.0, -9.81, 0.0),
+            size: 0.05,
+            start_color: [4.0, 2.0, 0.5, 1.0],
+            end_color: [1.0, 0.1, 0.0, 0.0],
+        }
+    }
+}
+
+/// How particles are blended with what's behind them. Additive blending doesn't depend on the
+/// order particles are drawn in, alpha blending needs them sorted back to front.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ParticleBlend {
+    #[default]
+    Additive,
+    Alpha,
+}
+
+impl ParticleBlend {
+    /// For the HDR target, with unpremultiplied colors.
+    pub fn state(self) -> wgpu::BlendState {
+        match self {
+            ParticleBlend::Additive => wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::SrcAlpha,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent::OVER,
+            },
+            ParticleBlend::Alpha => wgpu::BlendState::ALPHA_BLENDING,
+        }
+    }
+}
+
+/// Everything that distinguishes one particle pipeline from another.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ParticlePipelineKey {
+    pub sample_count: u32,
+    pub blend: ParticleBlend,
+}
+
+/// `Emitter` in `particles.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct EmitterUniform {
+    position: [f32; 3],
+    spread: f32,
+    direction: [f32; 3],
+    speed: f32,
+    gravity: [f32; 3],
+    lifetime: f32,
+    start_color: [f32; 4],
+    end_color: [f32; 4],
+    camera_right: [f32; 3],
+    size: f32,
+    camera_up: [f32; 3],
+    delta_time: f32,
+    emit_start: u32,
+    emit_count: u32,
+    capacity: u32,
+    seed: u32,
+    camera_position: [f32; 3],
+    order_len: u32,
+}
+
+/// `Particle` in `particles.wgsl`.
+const PARTICLE_SIZE: wgpu::BufferAddress = 32;
+
+/// Particles simulated entirely on the GPU: a compute pass emits new particles into a ring
+/// buffer, overwriting the oldest ones, and moves the living ones, which are then drawn as
+/// quads facing the camera in the scene pass. Alpha-blended particles are sorted back to front
+/// with a [`GpuSort`] first. Needs compute shaders and storage buffers in vertex shaders, which
+/// WebGL lacks.
+pub struct GpuParticles {
+    enabled: bool,
+    emitter: ParticleEmitter,
+    blend: ParticleBlend,
+    capacity: u32,
+    uniform: UniformBuffer<EmitterUniform>,
+    compute_bind_group: wgpu::BindGroup,
+    render_bind_group: wgpu::BindGroup,
+    emit_pipeline: wgpu::ComputePipeline,
+    update_pipeline: wgpu::ComputePipeline,
+    write_keys_pipeline: wgpu::ComputePipeline,
+    sort: GpuSort,
+    /// Binds the order the particles are drawn in for `sort`.
+    sort_bind_group: wgpu::BindGroup,
+    pipeline_layout: Arc<wgpu::PipelineLayout>,
+    shader_module: Arc<wgpu::ShaderModule>,
+    /// The fraction of a particle left to emit at the last update.
+    emit_remainder: f32,
+    /// The slot the next particle is emitted into.
+    emit_cursor: u32,
+    frame: u32,
+}
+
+impl GpuParticles {
+    /// Compiles `particles.wgsl` with `preprocessor` for room for `capacity` particles, reading
+    /// the camera from a bind group with `camera_layout`, or returns `None` where the downlevel
+    /// capabilities rule it out.
+    pub fn new(
+        device: &wgpu::Device,
+        preprocessor: &Preprocessor,
+        camera_layout: &wgpu::BindGroupLayout,
+        downlevel: &wgpu::DownlevelCapabilities,
+        capacity: u32,
+    ) -> anyhow::Result<Option<Self>> {
+        let required = wgpu::DownlevelFlags::COMPUTE_SHADERS | wgpu::DownlevelFlags::VERTEX_STORAGE;
+        if !downlevel.flags.contains(required) {
+            return Ok(None);
+        }
+
+        let capacity = capacity.max(1);
+        let uniform = UniformBuffer::new(
+            device,
+            "particles",
+            EmitterUniform::default(),
+            wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::VERTEX,
+        );
+        let particles = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("particles"),
+            size: capacity as wgpu::BufferAddress * PARTICLE_SIZE,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let sort = GpuSort::new(device, preprocessor, capacity)?;
+        let order = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("particle order"),
+            size: sort.capacity() as wgpu::BufferAddress * 8,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let sort_bind_group = sort.create_bind_group(device, &order, sort.capacity());
+        // Compute shaders write the particles and their order, vertex shaders can only read
+        // them.
+        let particles_layout = |visibility, read_only| {
+            let entry = |binding| wgpu::BindGroupLayoutEntry {
+                binding,
+                visibility,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            };
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("particles"),
+                entries: &[entry(0), entry(1)],
+            })
+        };
+        let compute_layout = particles_layout(wgpu::ShaderStages::COMPUTE, false);
+        let render_layout = particles_layout(wgpu::ShaderStages::VERTEX, true);
+        let bind_group = |layout| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("particles"),
+                layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: particles.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: order.as_entire_binding(),
+                    },
+                ],
+            })
+        };
+        let compute_bind_group = bind_group(&compute_layout);
+        let render_bind_group = bind_group(&render_layout);
+
+        let compute_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("particle simulation"),
+                bind_group_layouts: &[uniform.bind_group_layout(), &compute_layout],
+                push_constant_ranges: &[],
+            });
+        let compute_module = shader::create_module(
+            device,
+            &preprocessor.clone().define("COMPUTE", ""),
+            "particles.wgsl",
+        )?;
+        let compute_pipeline = |entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&compute_pipeline_layout),
+                module: &compute_module,
+                entry_point,
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            })
+        };
+
+        let pipeline_layout = Arc::new(device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("particles"),
+                bind_group_layouts: &[camera_layout, uniform.bind_group_layout(), &render_layout],
+                push_constant_ranges: &[],
+            },
+        ));
+        let shader_module = Arc::new(shader::create_module(
+            device,
+            preprocessor,
+            "particles.wgsl",
+        )?);
+
+        Ok(Some(Self {
+            enabled: false,
+            emitter: ParticleEmitter::default(),
+            blend: ParticleBlend::default(),
+            capacity,
+            emit_pipeline: compute_pipeline("emit"),
+            update_pipeline: compute_pipeline("update"),
+            write_keys_pipeline: compute_pipeline("write_keys"),
+            sort,
+            sort_bind_group,
+            uniform,
+            compute_bind_group,
+            render_bind_group,
+            pipeline_layout,
+            shader_module,
+            emit_remainder: 0.0,
+            emit_cursor: 0,
+            frame: 0,
+        }))
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn emitter(&self) -> &ParticleEmitter {
+        &self.emitter
+    }
+
+    pub fn emitter_mut(&mut self) -> &mut ParticleEmitter {
+        &mut self.emitter
+    }
+
+    pub fn blend(&self) -> ParticleBlend {
+        self.blend
+    }
+
+    pub fn set_blend(&mut self, blend: ParticleBlend) {
+        self.blend = blend;
+    }
+
+    /// How many particles can be alive at once. Emitting more overwrites the oldest.
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    /// Uploads the emitter and the particles to emit over `delta_time` seconds, facing the quads
+    /// towards `camera`, for [`GpuParticles::dispatch`] and [`GpuParticles::draw`].
+    pub fn update(&mut self, queue: &wgpu::Queue, delta_time: f32, camera: &Camera) {
+        let emitted = self.emitter.rate.max(0.0) * delta_time + self.emit_remainder;
+        let emit_count = (emitted.floor() as u32).min(self.capacity);
+        self.emit_remainder = emitted.fract();
+        let view = camera.view_matrix();
+        let emitter = &self.emitter;
+        self.uniform.value = EmitterUniform {
+            position: emitter.position.to_array(),
+            spread: emitter.spread,
+            direction: emitter
+                .direction
+                .try_normalize()
+                .unwrap_or(Vec3::Y)
+                .to_array(),
+            speed: emitter.speed,
+            gravity: emitter.gravity.to_array(),
+            lifetime: emitter.lifetime,
+            start_color: emitter.start_color,
+            end_color: emitter.end_color,
+            camera_right: view.row(0).truncate().to_array(),
+            size: emitter.size,
+            camera_up: view.row(1).truncate().to_array(),
+            delta_time,
+            emit_start: self.emit_cursor,
+            emit_count,
+            capacity: self.capacity,
+            seed: self.frame,
+            camera_position: camera.eye.to_array(),
+            order_len: self.sort.capacity(),
+        };
+        self.uniform.update(queue);
+        self.emit_cursor = (self.emit_cursor + emit_count) % self.capacity;
+        self.frame = self.frame.wrapping_add(1);
+    }
+
+    /// Records the compute pass emitting, moving and, when alpha-blended, sorting the
+    /// particles.
+    pub fn dispatch(&self, encoder: &mut wgpu::CommandEncoder, profiler: Option<&mut GpuProfiler>) {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("particles"),
+            timestamp_writes: profiler
+                .and_then(|profiler| profiler.compute_timestamp_writes("particles")),
+        });
+        compute_pass.set_bind_group(0, self.uniform.bind_group(), &[]);
+        compute_pass.set_bind_group(1, &self.compute_bind_group, &[]);
+        let emit_count = self.uniform.value.emit_count;
+        if emit_count > 0 {
+            compute_pass.set_pipeline(&self.emit_pipeline);
+            compute_pass.dispatch_workgroups(emit_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+        }
+        compute_pass.set_pipeline(&self.update_pipeline);
+        compute_pass.dispatch_workgroups(self.capacity.div_ceil(WORKGROUP_SIZE), 1, 1);
+        let order_len = self.sort.capacity();
+        compute_pass.set_pipeline(&self.write_keys_pipeline);
+        compute_pass.dispatch_workgroups(order_len.div_ceil(WORKGROUP_SIZE), 1, 1);
+        if self.blend == ParticleBlend::Alpha {
+            self.sort
+                .sort(&mut compute_pass, &self.sort_bind_group, order_len);
+        }
+    }
+
+    pub fn pipeline_builder(&self, key: ParticlePipelineKey) -> Arc<PipelineBuilder> {
+        let layout = self.pipeline_layout.clone();
+        let shader_module = self.shader_module.clone();
+        Arc::new(move |device, cache| create_pipeline(device, &layout, &shader_module, key, cache))
+    }
+
+    /// Draws every particle slot, dead ones collapsing to nothing, with the camera's bind group
+    /// at group 0.
+    pub fn draw(
+        &self,
+        render_pass: &mut wgpu::RenderPass,
+        pipeline: &wgpu::RenderPipeline,
+        camera_bind_group: &wgpu::BindGroup,
+    ) {
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, self.uniform.bind_group(), &[]);
+        render_pass.set_bind_group(2, &self.render_bind_group, &[]);
+        render_pass.draw(0..6, 0..self.capacity);
+    }
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader_module: &wgpu::ShaderModule,
+    key: ParticlePipelineKey,
+    cache: Option<&wgpu::PipelineCache>,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("particles"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader_module,
+            entry_point: "vs_main",
+            buffers: &[],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        primitive: wgpu::PrimitiveState::default(),
+        // Tested against the scene, but not hiding each other.
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: Texture::DEPTH_FORMAT,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::LessEqual,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: key.sample_count,
+            ..Default::default()
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader_module,
+            entry_point: "fs_main",
+            targets: &[
+                Some(wgpu::ColorTargetState {
+                    format: HDR_FORMAT,
+                    blend: Some(key.blend.state()),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }),
+                // Translucent particles leave the motion of what's behind them.
+                Some(wgpu::ColorTargetState {
+                    format: MOTION_FORMAT,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::empty(),
+                }),
+            ],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        }),
+        multiview: None,
+        cache,
+    })
+}
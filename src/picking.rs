@@ -0,0 +1,262 @@
+use std::sync::mpsc;
+
+use crate::instance::InstanceRaw;
+use crate::profiler::GpuProfiler;
+use crate::shader::{self, Preprocessor};
+use crate::texture::Texture;
+use crate::vertex::Vertex;
+
+/// The format of the ID buffer, holding the index of the instance drawn in each pixel plus one.
+const ID_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Uint;
+
+/// The instance under a pixel, read back from the ID buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Pick {
+    /// In physical pixels.
+    pub position: (u32, u32),
+    /// The index of the instance, `None` over the background.
+    pub instance: Option<u32>,
+}
+
+/// A pick whose pixel has been copied into `buffer`, waiting for the buffer to be mapped.
+struct PendingPick {
+    position: (u32, u32),
+    buffer: wgpu::Buffer,
+    mapped: Option<mpsc::Receiver<Result<(), wgpu::BufferAsyncError>>>,
+}
+
+/// Picks instances with the mouse by rendering instance IDs into an `R32Uint` buffer on request
+/// and reading back the pixel under the cursor, without stalling the frame: the result arrives
+/// through [`Picking::poll`] a frame or more later.
+pub struct Picking {
+    pipeline: wgpu::RenderPipeline,
+    ids: wgpu::Texture,
+    ids_view: wgpu::TextureView,
+    depth: Texture,
+    requested: Option<(u32, u32)>,
+    pending: Option<PendingPick>,
+}
+
+impl Picking {
+    /// Compiles `picking.wgsl` with `preprocessor`, reading the camera from a bind group with
+    /// `camera_layout`, and creates an ID buffer the size of `config`.
+    pub fn new(
+        device: &wgpu::Device,
+        preprocessor: &Preprocessor,
+        config: &wgpu::SurfaceConfiguration,
+        camera_layout: &wgpu::BindGroupLayout,
+    ) -> anyhow::Result<Self> {
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("picking"),
+            bind_group_layouts: &[camera_layout],
+            push_constant_ranges: &[],
+        });
+        let shader_module = shader::create_module(device, preprocessor, "picking.wgsl")?;
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("picking"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader_module,
+                entry_point: "vs_main",
+                buffers: &[Vertex::layout(), InstanceRaw::layout()],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            primitive: wgpu::PrimitiveState {
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader_module,
+                entry_point: "fs_main",
+                targets: &[Some(ID_FORMAT.into())],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            multiview: None,
+            cache: None,
+        });
+        let (ids, ids_view, depth) = create_targets(device, config);
+
+        Ok(Self {
+            pipeline,
+            ids,
+            ids_view,
+            depth,
+            requested: None,
+            pending: None,
+        })
+    }
+
+    /// Recreates the ID buffer for the new size of the surface.
+    pub fn resize(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) {
+        (self.ids, self.ids_view, self.depth) = create_targets(device, config);
+        self.requested = None;
+    }
+
+    /// Picks the instance under `position`, in physical pixels, with the next frame. Ignored
+    /// outside the surface.
+    pub fn request(&mut self, position: (u32, u32)) {
+        if position.0 < self.ids.width() && position.1 < self.ids.height() {
+            self.requested = Some(position);
+        }
+    }
+
+    /// Whether the next frame has to render the ID buffer. A new pick waits for the one in
+    /// flight to be read back.
+    pub fn wants_render(&self) -> bool {
+        self.requested.is_some() && self.pending.is_none()
+    }
+
+    /// Records the ID buffer around the requested pixel and its copy into a readback buffer,
+    /// calling `draw` to draw the scene's geometry with the picking pipeline and the camera's
+    /// bind group set.
+    pub fn render(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        profiler: Option<&mut GpuProfiler>,
+        camera_bind_group: &wgpu::BindGroup,
+        draw: impl FnOnce(&mut wgpu::RenderPass),
+    ) {
+        let Some(position) = self.requested.take() else {
+            return;
+        };
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("picking"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &self.ids_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.depth.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: wgpu::StoreOp::Discard,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: profiler.and_then(|profiler| profiler.timestamp_writes("picking")),
+            occlusion_query_set: None,
+        });
+        // Only the pixel under the cursor is read back, the rest isn't worth shading.
+        render_pass.set_scissor_rect(position.0, position.1, 1, 1);
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        draw(&mut render_pass);
+        drop(render_pass);
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("picking readback"),
+            size: 4,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &self.ids,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: position.0,
+                    y: position.1,
+                    z: 0,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: None,
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.pending = Some(PendingPick {
+            position,
+            buffer,
+            mapped: None,
+        });
+    }
+
+    /// Starts mapping the readback buffer, once the frame copying into it has been submitted.
+    pub fn after_submit(&mut self) {
+        let Some(pending) = &mut self.pending else {
+            return;
+        };
+        if pending.mapped.is_some() {
+            return;
+        }
+        let (sender, receiver) = mpsc::channel();
+        pending
+            .buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+        pending.mapped = Some(receiver);
+    }
+
+    /// Returns the pick that has been read back since the previous call, if any.
+    pub fn poll(&mut self, device: &wgpu::Device) -> Option<Pick> {
+        let receiver = self.pending.as_ref()?.mapped.as_ref()?;
+        device.poll(wgpu::Maintain::Poll);
+        let result = match receiver.try_recv() {
+            Ok(result) => result,
+            Err(mpsc::TryRecvError::Empty) => return None,
+            Err(mpsc::TryRecvError::Disconnected) => Err(wgpu::BufferAsyncError),
+        };
+        let pending = self.pending.take()?;
+        if let Err(err) = result {
+            eprintln!("failed to read back the picked pixel: {err}");
+            return None;
+        }
+        let id = {
+            let bytes = pending.buffer.slice(..).get_mapped_range();
+            u32::from_ne_bytes(bytes[..4].try_into().unwrap())
+        };
+        pending.buffer.unmap();
+        Some(Pick {
+            position: pending.position,
+            instance: id.checked_sub(1),
+        })
+    }
+}
+
+fn create_targets(
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+) -> (wgpu::Texture, wgpu::TextureView, Texture) {
+    let ids = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("picking ids"),
+        size: wgpu::Extent3d {
+            width: config.width.max(1),
+            height: config.height.max(1),
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: ID_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    let ids_view = ids.create_view(&wgpu::TextureViewDescriptor::default());
+    let depth = Texture::create_depth_texture(device, config, 1, "picking depth");
+    (ids, ids_view, depth)
+}
@@ -0,0 +1,138 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::sync::{mpsc, Arc};
+
+/// Builds a render pipeline, optionally seeding the driver with a [`wgpu::PipelineCache`].
+pub type PipelineBuilder =
+    dyn Fn(&wgpu::Device, Option<&wgpu::PipelineCache>) -> wgpu::RenderPipeline + Send + Sync;
+
+/// Compiles render pipelines on background threads and keeps them keyed by a hash of
+/// whatever describes them, so that asking for the same pipeline twice never compiles twice.
+///
+/// When the device supports [`wgpu::Features::PIPELINE_CACHE`], compiled driver state is also
+/// kept in a [`wgpu::PipelineCache`] that is loaded from and written back to `cache_dir`, so
+/// later runs skip most of the shader compilation.
+pub struct PipelineCompiler {
+    device: Arc<wgpu::Device>,
+    driver_cache: Option<Arc<wgpu::PipelineCache>>,
+    cache_path: Option<PathBuf>,
+    pipelines: HashMap<u64, Arc<wgpu::RenderPipeline>>,
+    pending: HashSet<u64>,
+    sender: mpsc::Sender<(u64, wgpu::RenderPipeline)>,
+    receiver: mpsc::Receiver<(u64, wgpu::RenderPipeline)>,
+}
+
+fn hash_key(key: &impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl PipelineCompiler {
+    pub fn new(
+        device: Arc<wgpu::Device>,
+        adapter_info: &wgpu::AdapterInfo,
+        cache_dir: Option<PathBuf>,
+    ) -> Self {
+        let cache_path = cache_dir
+            .filter(|_| device.features().contains(wgpu::Features::PIPELINE_CACHE))
+            .zip(wgpu::util::pipeline_cache_key(adapter_info))
+            .map(|(directory, key)| directory.join(key));
+        let driver_cache = cache_path.as_ref().map(|path| {
+            let data = std::fs::read(path).ok();
+            // SAFETY: the data was written by `PipelineCompiler::save` from
+            // `PipelineCache::get_data`, and `fallback` discards it if it doesn't match.
+            Arc::new(unsafe {
+                device.create_pipeline_cache(&wgpu::PipelineCacheDescriptor {
+                    label: Some("pipelines"),
+                    data: data.as_deref(),
+                    fallback: true,
+                })
+            })
+        });
+        let (sender, receiver) = mpsc::channel();
+
+        Self {
+            device,
+            driver_cache,
+            cache_path,
+            pipelines: HashMap::new(),
+            pending: HashSet::new(),
+            sender,
+            receiver,
+        }
+    }
+
+    /// Collects pipelines finished by background threads.
+    pub fn poll(&mut self) {
+        for (hash, pipeline) in self.receiver.try_iter() {
+            self.pending.remove(&hash);
+            self.pipelines.insert(hash, Arc::new(pipeline));
+        }
+    }
+
+    /// Returns the pipeline for `key` if it has been compiled, otherwise starts compiling it
+    /// in the background and returns `None`.
+    pub fn request(
+        &mut self,
+        key: &impl Hash,
+        build: Arc<PipelineBuilder>,
+    ) -> Option<Arc<wgpu::RenderPipeline>> {
+        let hash = hash_key(key);
+        if let Some(pipeline) = self.pipelines.get(&hash) {
+            return Some(pipeline.clone());
+        }
+        // There are no threads to compile on in the browser.
+        #[cfg(target_arch = "wasm32")]
+        return Some(self.get(key, &*build));
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.pending.insert(hash) {
+            let device = self.device.clone();
+            let driver_cache = self.driver_cache.clone();
+            let sender = self.sender.clone();
+            std::thread::spawn(move || {
+                let pipeline = build(&device, driver_cache.as_deref());
+                // The compiler may have been dropped in the meantime.
+                let _ = sender.send((hash, pipeline));
+            });
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        None
+    }
+
+    /// Returns the pipeline for `key`, compiling it on the current thread if needed.
+    pub fn get(&mut self, key: &impl Hash, build: &PipelineBuilder) -> Arc<wgpu::RenderPipeline> {
+        let hash = hash_key(key);
+        let driver_cache = self.driver_cache.as_deref();
+        self.pipelines
+            .entry(hash)
+            .or_insert_with(|| Arc::new(build(&self.device, driver_cache)))
+            .clone()
+    }
+
+    /// Writes the driver's pipeline cache to disk, if enabled.
+    pub fn save(&self) {
+        let (Some(cache), Some(path)) = (&self.driver_cache, &self.cache_path) else {
+            return;
+        };
+        let Some(data) = cache.get_data() else {
+            return;
+        };
+        let result = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|()| std::fs::write(path, data));
+        if let Err(err) = result {
+            eprintln!("failed to save pipeline cache to {}: {err}", path.display());
+        }
+    }
+}
+
+impl Drop for PipelineCompiler {
+    fn drop(&mut self) {
+        self.save();
+    }
+}
@@ -0,0 +1,389 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::pipeline_cache::{PipelineBuilder, PipelineCompiler};
+use crate::profiler::GpuProfiler;
+use crate::shader::{self, Preprocessor};
+use crate::texture::Texture;
+use crate::tonemap::HDR_FORMAT;
+
+/// A fullscreen effect in the [`PostProcess`] chain, reading the output of the previous effect
+/// and writing every pixel of its target.
+///
+/// Effects before the last one write into [`HDR_FORMAT`] textures, the last one into the
+/// swapchain.
+pub trait Effect: Any {
+    /// Identifies the effect in the debug UI, pass timings and pipeline keys.
+    fn name(&self) -> &'static str;
+
+    /// Whether the effect reads [`EffectContext::depth`], which is only rendered when an enabled
+    /// effect does.
+    fn reads_depth(&self) -> bool {
+        false
+    }
+
+    /// Uploads the effect's parameters, once per frame before it is recorded.
+    fn update(&mut self, _queue: &wgpu::Queue) {}
+
+    /// Called when the targets are resized to the size of `config`.
+    fn resize(&mut self, _device: &wgpu::Device, _config: &wgpu::SurfaceConfiguration) {}
+
+    /// Records the effect reading `input` and writing `output`, a `format` view.
+    fn render(
+        &mut self,
+        context: &mut EffectContext,
+        input: &Texture,
+        output: &wgpu::TextureView,
+        format: wgpu::TextureFormat,
+    );
+}
+
+/// What an [`Effect`] needs to record its passes.
+pub struct EffectContext<'f> {
+    pub device: &'f wgpu::Device,
+    /// The scene's motion vectors, each pixel's offset in UV space since the previous frame.
+    pub motion: &'f Texture,
+    /// The depth of the scene from the prepass, see [`Effect::reads_depth`].
+    pub depth: &'f Texture,
+    pub encoder: &'f mut wgpu::CommandEncoder,
+    pub pipelines: &'f mut PipelineCompiler,
+    pub profiler: Option<&'f mut GpuProfiler>,
+}
+
+impl EffectContext<'_> {
+    /// Records a pass called `name` drawing a fullscreen triangle into `output` with
+    /// `bind_group` at group 0.
+    pub fn draw(
+        &mut self,
+        name: &'static str,
+        output: &wgpu::TextureView,
+        pipeline: &wgpu::RenderPipeline,
+        bind_group: &wgpu::BindGroup,
+    ) {
+        let mut render_pass = self.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some(name),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: output,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    // The triangle covers every pixel.
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: self
+                .profiler
+                .as_mut()
+                .and_then(|profiler| profiler.timestamp_writes(name)),
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+/// Identifies the pipeline an effect draws into `format` targets with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct EffectPipelineKey {
+    pub effect: &'static str,
+    pub format: wgpu::TextureFormat,
+}
+
+/// The layout entries of an effect's input texture and its sampler, at bindings 0 and 1.
+pub fn input_layout_entries() -> [wgpu::BindGroupLayoutEntry; 2] {
+    [
+        wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+            binding: 1,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+        },
+    ]
+}
+
+/// The resources for [`input_layout_entries`].
+pub fn input_bind_group_entries(input: &Texture) -> [wgpu::BindGroupEntry<'_>; 2] {
+    [
+        wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::TextureView(&input.view),
+        },
+        wgpu::BindGroupEntry {
+            binding: 1,
+            resource: wgpu::BindingResource::Sampler(&input.sampler),
+        },
+    ]
+}
+
+/// Returns a builder for a fullscreen pipeline running `fs_main` of `shader_module`, whose
+/// vertex stage comes from `fullscreen.wgsl`, into `format` targets.
+pub fn pipeline_builder(
+    label: &'static str,
+    layout: Arc<wgpu::PipelineLayout>,
+    shader_module: Arc<wgpu::ShaderModule>,
+    format: wgpu::TextureFormat,
+) -> Arc<PipelineBuilder> {
+    Arc::new(move |device, cache| {
+        let constants = output_constants(format);
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader_module,
+                entry_point: "vs_main",
+                buffers: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader_module,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions {
+                    constants: &constants,
+                    ..Default::default()
+                },
+            }),
+            multiview: None,
+            cache,
+        })
+    })
+}
+
+/// The pipeline constants of `common.wgsl` for effects writing into `format` targets.
+pub fn output_constants(format: wgpu::TextureFormat) -> HashMap<String, f64> {
+    // The intermediate textures keep linear colors like sRGB swapchains, only other swapchains
+    // need the fragment shader to encode its output.
+    HashMap::from([(
+        "SRGB_SURFACE".to_owned(),
+        f64::from(u8::from(format.is_srgb() || format == HDR_FORMAT)),
+    )])
+}
+
+struct Slot {
+    effect: Box<dyn Effect>,
+    enabled: bool,
+}
+
+/// A chain of fullscreen [`Effect`]s turning the HDR scene into the final image, which can be
+/// toggled and reordered at runtime.
+///
+/// Each enabled effect reads the output of the previous one, going back and forth between two
+/// intermediate textures, and the last one writes into the swapchain. With every effect
+/// disabled the scene is copied as-is.
+pub struct PostProcess {
+    slots: Vec<Slot>,
+    targets: [Texture; 2],
+    passthrough: Passthrough,
+}
+
+impl PostProcess {
+    /// Creates an empty chain with intermediate textures the size of `config`.
+    pub fn new(
+        device: &wgpu::Device,
+        preprocessor: &Preprocessor,
+        config: &wgpu::SurfaceConfiguration,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            slots: Vec::new(),
+            targets: create_targets(device, config),
+            passthrough: Passthrough::new(device, preprocessor)?,
+        })
+    }
+
+    /// Appends `effect` to the end of the chain.
+    pub fn push(&mut self, effect: impl Effect, enabled: bool) {
+        self.slots.push(Slot {
+            effect: Box::new(effect),
+            enabled,
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    /// The name of the effect at `index`.
+    pub fn name(&self, index: usize) -> &'static str {
+        self.slots[index].effect.name()
+    }
+
+    pub fn enabled(&self, index: usize) -> bool {
+        self.slots[index].enabled
+    }
+
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) {
+        self.slots[index].enabled = enabled;
+    }
+
+    /// Moves the effect at `from` to `to`, shifting the effects in between.
+    pub fn move_effect(&mut self, from: usize, to: usize) {
+        let slot = self.slots.remove(from);
+        self.slots.insert(to, slot);
+    }
+
+    /// The index of the first effect of type `E` in the chain.
+    pub fn position<E: Effect>(&self) -> Option<usize> {
+        self.slots
+            .iter()
+            .position(|slot| (&*slot.effect as &dyn Any).is::<E>())
+    }
+
+    /// The first effect of type `E` in the chain.
+    pub fn effect<E: Effect>(&self) -> Option<&E> {
+        self.slots
+            .iter()
+            .find_map(|slot| (&*slot.effect as &dyn Any).downcast_ref())
+    }
+
+    /// The first effect of type `E` in the chain, e.g. to change its settings.
+    pub fn effect_mut<E: Effect>(&mut self) -> Option<&mut E> {
+        self.slots
+            .iter_mut()
+            .find_map(|slot| (&mut *slot.effect as &mut dyn Any).downcast_mut())
+    }
+
+    /// Recreates the intermediate textures for the size of `config`.
+    pub fn resize(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) {
+        self.targets = create_targets(device, config);
+        for slot in &mut self.slots {
+            slot.effect.resize(device, config);
+        }
+    }
+
+    /// Whether any enabled effect reads the depth of the scene.
+    pub fn reads_depth(&self) -> bool {
+        self.slots
+            .iter()
+            .any(|slot| slot.enabled && slot.effect.reads_depth())
+    }
+
+    /// Uploads the parameters of the enabled effects.
+    pub fn update(&mut self, queue: &wgpu::Queue) {
+        for slot in self.slots.iter_mut().filter(|slot| slot.enabled) {
+            slot.effect.update(queue);
+        }
+    }
+
+    /// Records the enabled effects, reading `input` and writing the `format` view `output`.
+    pub fn render(
+        &mut self,
+        context: &mut EffectContext,
+        input: &Texture,
+        output: &wgpu::TextureView,
+        format: wgpu::TextureFormat,
+    ) {
+        let mut effects: Vec<&mut dyn Effect> = self
+            .slots
+            .iter_mut()
+            .filter(|slot| slot.enabled)
+            .map(|slot| &mut *slot.effect)
+            .collect();
+        if effects.is_empty() {
+            effects.push(&mut self.passthrough);
+        }
+        let last = effects.len() - 1;
+        let mut source = input;
+        for (index, effect) in effects.into_iter().enumerate() {
+            if index == last {
+                effect.render(context, source, output, format);
+            } else {
+                let target = &self.targets[index % 2];
+                effect.render(context, source, &target.view, HDR_FORMAT);
+                source = target;
+            }
+        }
+    }
+}
+
+fn create_targets(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> [Texture; 2] {
+    [0, 1].map(|_| Texture::create_render_target(device, config, HDR_FORMAT, 1, "post-process"))
+}
+
+/// Copies its input, for when no effect is enabled.
+struct Passthrough {
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline_layout: Arc<wgpu::PipelineLayout>,
+    shader_module: Arc<wgpu::ShaderModule>,
+}
+
+impl Passthrough {
+    fn new(device: &wgpu::Device, preprocessor: &Preprocessor) -> anyhow::Result<Self> {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("copy"),
+            entries: &input_layout_entries(),
+        });
+        let pipeline_layout = Arc::new(device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("copy"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            },
+        ));
+        let shader_module = Arc::new(shader::create_module(device, preprocessor, "copy.wgsl")?);
+        Ok(Self {
+            bind_group_layout,
+            pipeline_layout,
+            shader_module,
+        })
+    }
+}
+
+impl Effect for Passthrough {
+    fn name(&self) -> &'static str {
+        "copy"
+    }
+
+    fn render(
+        &mut self,
+        context: &mut EffectContext,
+        input: &Texture,
+        output: &wgpu::TextureView,
+        format: wgpu::TextureFormat,
+    ) {
+        let key = EffectPipelineKey {
+            effect: self.name(),
+            format,
+        };
+        let pipeline = context.pipelines.get(
+            &key,
+            &*pipeline_builder(
+                "copy",
+                self.pipeline_layout.clone(),
+                self.shader_module.clone(),
+                format,
+            ),
+        );
+        let bind_group = context
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("copy"),
+                layout: &self.bind_group_layout,
+                entries: &input_bind_group_entries(input),
+            });
+        context.draw(self.name(), output, &pipeline, &bind_group);
+    }
+}
@@ -0,0 +1,131 @@
+use crate::instance::InstanceRaw;
+use crate::profiler::GpuProfiler;
+use crate::shader::{self, Preprocessor};
+use crate::texture::Texture;
+use crate::vertex::Vertex;
+
+/// The format of the world-space normals written by the prepass.
+const NORMAL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// Renders the depth and world-space normals of the scene without multisampling, ahead of the
+/// scene pass, for the screen-space passes that need them: SSAO and depth of field.
+pub struct Prepass {
+    pipeline: wgpu::RenderPipeline,
+    depth: Texture,
+    normal: Texture,
+}
+
+impl Prepass {
+    /// Compiles `prepass.wgsl` with `preprocessor`, reading the camera from a bind group with
+    /// `camera_layout`, and creates targets the size of `config`.
+    pub fn new(
+        device: &wgpu::Device,
+        preprocessor: &Preprocessor,
+        config: &wgpu::SurfaceConfiguration,
+        camera_layout: &wgpu::BindGroupLayout,
+    ) -> anyhow::Result<Self> {
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("prepass"),
+            bind_group_layouts: &[camera_layout],
+            push_constant_ranges: &[],
+        });
+        let shader_module = shader::create_module(device, preprocessor, "prepass.wgsl")?;
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("prepass"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader_module,
+                entry_point: "vs_main",
+                buffers: &[Vertex::layout(), InstanceRaw::layout()],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            primitive: wgpu::PrimitiveState {
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader_module,
+                entry_point: "fs_main",
+                targets: &[Some(NORMAL_FORMAT.into())],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            multiview: None,
+            cache: None,
+        });
+        let (depth, normal) = create_targets(device, config);
+
+        Ok(Self {
+            pipeline,
+            depth,
+            normal,
+        })
+    }
+
+    /// The depth of the scene, `1.0` where nothing was drawn.
+    pub fn depth(&self) -> &Texture {
+        &self.depth
+    }
+
+    /// The world-space normals of the scene.
+    pub fn normal(&self) -> &Texture {
+        &self.normal
+    }
+
+    /// Recreates the targets for the new size of the surface.
+    pub fn resize(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) {
+        (self.depth, self.normal) = create_targets(device, config);
+    }
+
+    /// Records the prepass, calling `draw` to draw the scene's geometry with the prepass'
+    /// pipeline and the camera's bind group set.
+    pub fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        profiler: Option<&mut GpuProfiler>,
+        camera_bind_group: &wgpu::BindGroup,
+        draw: impl FnOnce(&mut wgpu::RenderPass),
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("prepass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &self.normal.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.depth.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: profiler.and_then(|profiler| profiler.timestamp_writes("prepass")),
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        draw(&mut render_pass);
+    }
+}
+
+fn create_targets(
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+) -> (Texture, Texture) {
+    (
+        Texture::create_depth_texture(device, config, 1, "prepass depth"),
+        Texture::create_render_target(device, config, NORMAL_FORMAT, 1, "prepass normal"),
+    )
+}
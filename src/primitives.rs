@@ -0,0 +1,212 @@
+//! Procedural mesh generators. Every mesh is centred on the origin, winds its triangles
+//! counter-clockwise when seen from outside and is white unless stated otherwise.
+
+use std::f32::consts::{PI, TAU};
+
+use glam::{Vec2, Vec3};
+
+use crate::mesh::MeshData;
+use crate::vertex::Vertex;
+
+const WHITE: [f32; 3] = [1.0, 1.0, 1.0];
+
+fn vertex(position: Vec3, normal: Vec3, uv: Vec2) -> Vertex {
+    Vertex {
+        position: position.to_array(),
+        normal: normal.to_array(),
+        color: WHITE,
+        uv: uv.to_array(),
+        // Filled in by `MeshData::generate_tangents`.
+        tangent: [0.0; 4],
+    }
+}
+
+/// An axis-aligned unit cube. Every face has its own four vertices so that normals and colors
+/// stay flat per face, and each face maps the whole `0..1` UV range.
+pub fn cube() -> MeshData {
+    // (normal, u, v) with `u x v == normal`, so the corners below wind counter-clockwise when
+    // seen from outside the cube.
+    let faces = [
+        (Vec3::X, Vec3::NEG_Z, Vec3::Y, [0.9, 0.3, 0.3]),
+        (Vec3::NEG_X, Vec3::Z, Vec3::Y, [0.3, 0.9, 0.9]),
+        (Vec3::Y, Vec3::X, Vec3::NEG_Z, [0.3, 0.9, 0.3]),
+        (Vec3::NEG_Y, Vec3::X, Vec3::Z, [0.9, 0.3, 0.9]),
+        (Vec3::Z, Vec3::X, Vec3::Y, [0.3, 0.3, 0.9]),
+        (Vec3::NEG_Z, Vec3::NEG_X, Vec3::Y, [0.9, 0.9, 0.3]),
+    ];
+
+    let mut mesh = MeshData::default();
+    for (normal, u, v, color) in faces {
+        let base = mesh.vertices.len() as u32;
+        for (su, sv) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+            let position = (normal + u * su + v * sv) * 0.5;
+            let uv = Vec2::new(su + 1.0, 1.0 - sv) * 0.5;
+            mesh.vertices.push(Vertex {
+                color,
+                ..vertex(position, normal, uv)
+            });
+        }
+        mesh.indices
+            .extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+    }
+
+    mesh.generate_tangents();
+    mesh
+}
+
+/// A square in the XZ plane facing `+Y`, split into `subdivisions` quads along each side.
+pub fn plane(size: f32, subdivisions: u32) -> MeshData {
+    let subdivisions = subdivisions.max(1);
+    let row = subdivisions + 1;
+
+    let mut mesh = MeshData::default();
+    for i in 0..=subdivisions {
+        for j in 0..=subdivisions {
+            let uv = Vec2::new(i as f32, j as f32) / subdivisions as f32;
+            let position = Vec3::new(uv.x - 0.5, 0.0, uv.y - 0.5) * size;
+            mesh.vertices.push(vertex(position, Vec3::Y, uv));
+        }
+    }
+    for i in 0..subdivisions {
+        for j in 0..subdivisions {
+            let a = i * row + j;
+            let b = a + 1;
+            let c = b + row;
+            let d = a + row;
+            mesh.indices.extend_from_slice(&[a, b, c, a, c, d]);
+        }
+    }
+
+    mesh.generate_tangents();
+    mesh
+}
+
+/// A UV sphere with `sectors` slices around the Y axis and `stacks` rings from pole to pole.
+pub fn sphere(radius: f32, sectors: u32, stacks: u32) -> MeshData {
+    let sectors = sectors.max(3);
+    let stacks = stacks.max(2);
+    let row = sectors + 1;
+
+    let mut mesh = MeshData::default();
+    for i in 0..=stacks {
+        let (sin_phi, cos_phi) = (PI * i as f32 / stacks as f32).sin_cos();
+        for j in 0..=sectors {
+            let (sin_theta, cos_theta) = (TAU * j as f32 / sectors as f32).sin_cos();
+            let normal = Vec3::new(sin_phi * sin_theta, cos_phi, sin_phi * cos_theta);
+            let uv = Vec2::new(j as f32 / sectors as f32, i as f32 / stacks as f32);
+            mesh.vertices.push(vertex(normal * radius, normal, uv));
+        }
+    }
+    for i in 0..stacks {
+        for j in 0..sectors {
+            let k1 = i * row + j;
+            let k2 = k1 + row;
+            // The first and last stacks collapse into a single triangle at the poles.
+            if i != stacks - 1 {
+                mesh.indices.extend_from_slice(&[k1, k2, k2 + 1]);
+            }
+            if i != 0 {
+                mesh.indices.extend_from_slice(&[k1, k2 + 1, k1 + 1]);
+            }
+        }
+    }
+
+    mesh.generate_tangents();
+    mesh
+}
+
+/// A capped cylinder along the Y axis.
+pub fn cylinder(radius: f32, height: f32, segments: u32) -> MeshData {
+    let segments = segments.max(3);
+    let half_height = height * 0.5;
+    // Yields `(position, radial normal, fraction of the way around)` for every ring vertex.
+    let ring = |y: f32| {
+        (0..=segments).map(move |j| {
+            let u = j as f32 / segments as f32;
+            let (sin_theta, cos_theta) = (TAU * u).sin_cos();
+            (
+                Vec3::new(sin_theta * radius, y, cos_theta * radius),
+                Vec3::new(sin_theta, 0.0, cos_theta),
+                u,
+            )
+        })
+    };
+
+    let mut mesh = MeshData::default();
+
+    // Side: a top and a bottom ring sharing radial normals.
+    let top = mesh.vertices.len() as u32;
+    mesh.vertices.extend(
+        ring(half_height).map(|(position, normal, u)| vertex(position, normal, Vec2::new(u, 0.0))),
+    );
+    let bottom = mesh.vertices.len() as u32;
+    mesh.vertices.extend(
+        ring(-half_height).map(|(position, normal, u)| vertex(position, normal, Vec2::new(u, 1.0))),
+    );
+    for j in 0..segments {
+        let (t0, t1, b0, b1) = (top + j, top + j + 1, bottom + j, bottom + j + 1);
+        mesh.indices.extend_from_slice(&[t0, b0, b1, t0, b1, t1]);
+    }
+
+    // Caps: a fan around a centre vertex with flat normals.
+    for (y, normal) in [(half_height, Vec3::Y), (-half_height, Vec3::NEG_Y)] {
+        let centre = mesh.vertices.len() as u32;
+        mesh.vertices
+            .push(vertex(Vec3::new(0.0, y, 0.0), normal, Vec2::splat(0.5)));
+        mesh.vertices.extend(ring(y).map(|(position, radial, _)| {
+            let uv = Vec2::new(radial.x, radial.z) * 0.5 + 0.5;
+            vertex(position, normal, uv)
+        }));
+        for j in 0..segments {
+            let (r0, r1) = (centre + 1 + j, centre + 2 + j);
+            if normal.y > 0.0 {
+                mesh.indices.extend_from_slice(&[centre, r0, r1]);
+            } else {
+                mesh.indices.extend_from_slice(&[centre, r1, r0]);
+            }
+        }
+    }
+
+    mesh.generate_tangents();
+    mesh
+}
+
+/// A torus lying in the XZ plane, `major_radius` from the centre to the middle of the tube.
+pub fn torus(
+    major_radius: f32,
+    minor_radius: f32,
+    major_segments: u32,
+    minor_segments: u32,
+) -> MeshData {
+    let major_segments = major_segments.max(3);
+    let minor_segments = minor_segments.max(3);
+    let row = minor_segments + 1;
+
+    let mut mesh = MeshData::default();
+    for i in 0..=major_segments {
+        let (sin_u, cos_u) = (TAU * i as f32 / major_segments as f32).sin_cos();
+        for j in 0..=minor_segments {
+            let (sin_v, cos_v) = (TAU * j as f32 / minor_segments as f32).sin_cos();
+            let normal = Vec3::new(cos_v * sin_u, sin_v, cos_v * cos_u);
+            let centre = Vec3::new(sin_u, 0.0, cos_u) * major_radius;
+            let uv = Vec2::new(
+                i as f32 / major_segments as f32,
+                j as f32 / minor_segments as f32,
+            );
+            mesh.vertices
+                .push(vertex(centre + normal * minor_radius, normal, uv));
+        }
+    }
+    for i in 0..major_segments {
+        for j in 0..minor_segments {
+            let a = i * row + j;
+            let b = a + row;
+            let c = b + 1;
+            let d = a + 1;
+            mesh.indices.extend_from_slice(&[a, b, c, a, c, d]);
+        }
+    }
+
+    mesh.generate_tangents();
+    mesh
+}
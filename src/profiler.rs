@@ -0,0 +1,164 @@
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// The most passes that can be timed in a single frame.
+const MAX_PASSES: u32 = 16;
+
+/// Times render passes on the GPU with timestamp queries.
+///
+/// Each pass writes a timestamp at its beginning and end. At the end of the frame the queries are
+/// resolved into a buffer that is read back asynchronously, and frames recorded while a readback
+/// is still in flight aren't timed.
+pub struct GpuProfiler {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    readback_buffer: wgpu::Buffer,
+    /// Nanoseconds per timestamp tick.
+    period: f32,
+    /// The passes timed in the frame being recorded, or `None` while a readback is in flight.
+    passes: Option<Vec<&'static str>>,
+    /// The passes whose timestamps were resolved this frame, waiting for it to be submitted.
+    resolved: Option<Vec<&'static str>>,
+    /// The passes whose timestamps are being read back.
+    pending: Option<Vec<&'static str>>,
+    sender: mpsc::Sender<Result<(), wgpu::BufferAsyncError>>,
+    receiver: mpsc::Receiver<Result<(), wgpu::BufferAsyncError>>,
+    timings: Vec<(&'static str, Duration)>,
+}
+
+impl GpuProfiler {
+    /// Returns `None` when the device doesn't support [`wgpu::Features::TIMESTAMP_QUERY`].
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+
+        let count = MAX_PASSES * 2;
+        let size = u64::from(count) * wgpu::QUERY_SIZE as u64;
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("timestamps"),
+            ty: wgpu::QueryType::Timestamp,
+            count,
+        });
+        let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("timestamps resolve"),
+            size,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("timestamps readback"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let (sender, receiver) = mpsc::channel();
+
+        Some(Self {
+            query_set,
+            resolve_buffer,
+            readback_buffer,
+            period: queue.get_timestamp_period(),
+            passes: None,
+            resolved: None,
+            pending: None,
+            sender,
+            receiver,
+            timings: Vec::new(),
+        })
+    }
+
+    /// The GPU time of each pass in the most recently read back frame, in recording order.
+    pub fn timings(&self) -> &[(&'static str, Duration)] {
+        &self.timings
+    }
+
+    /// Collects finished readbacks and starts timing a new frame if none is in flight. Should be
+    /// called after polling the device.
+    pub fn begin_frame(&mut self) {
+        if let Some(result) = self.receiver.try_iter().last() {
+            let passes = self.pending.take().unwrap_or_default();
+            if result.is_ok() {
+                self.read_timings(&passes);
+            }
+            self.readback_buffer.unmap();
+        }
+        self.resolved = None;
+        self.passes = self.pending.is_none().then(Vec::new);
+    }
+
+    fn read_timings(&mut self, passes: &[&'static str]) {
+        let size = passes.len() as u64 * 2 * wgpu::QUERY_SIZE as u64;
+        let data = self.readback_buffer.slice(..size).get_mapped_range();
+        let timestamps: &[u64] = bytemuck::cast_slice(&data);
+        self.timings = passes
+            .iter()
+            .zip(timestamps.chunks_exact(2))
+            .map(|(&name, timestamps)| {
+                let ticks = timestamps[1].wrapping_sub(timestamps[0]);
+                let nanoseconds = ticks as f64 * f64::from(self.period);
+                (name, Duration::from_nanos(nanoseconds as u64))
+            })
+            .collect();
+    }
+
+    /// Returns the timestamp writes timing a pass called `name`, or `None` if this frame isn't
+    /// being timed.
+    pub fn timestamp_writes(
+        &mut self,
+        name: &'static str,
+    ) -> Option<wgpu::RenderPassTimestampWrites<'_>> {
+        let passes = self.passes.as_mut()?;
+        let index = u32::try_from(passes.len())
+            .ok()
+            .filter(|&index| index < MAX_PASSES)?;
+        passes.push(name);
+        Some(wgpu::RenderPassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(index * 2),
+            end_of_pass_write_index: Some(index * 2 + 1),
+        })
+    }
+
+    /// Like [`GpuProfiler::timestamp_writes`], for a compute pass.
+    pub fn compute_timestamp_writes(
+        &mut self,
+        name: &'static str,
+    ) -> Option<wgpu::ComputePassTimestampWrites<'_>> {
+        self.timestamp_writes(name)
+            .map(|writes| wgpu::ComputePassTimestampWrites {
+                query_set: writes.query_set,
+                beginning_of_pass_write_index: writes.beginning_of_pass_write_index,
+                end_of_pass_write_index: writes.end_of_pass_write_index,
+            })
+    }
+
+    /// Resolves the timestamps written this frame into the readback buffer.
+    pub fn end_frame(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        let Some(passes) = self.passes.take().filter(|passes| !passes.is_empty()) else {
+            return;
+        };
+        let count = passes.len() as u32 * 2;
+        let size = u64::from(count) * wgpu::QUERY_SIZE as u64;
+        encoder.resolve_query_set(&self.query_set, 0..count, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(&self.resolve_buffer, 0, &self.readback_buffer, 0, size);
+        self.resolved = Some(passes);
+    }
+
+    /// Starts reading back the timestamps resolved by [`GpuProfiler::end_frame`]. Must be called
+    /// after the frame has been submitted.
+    pub fn after_submit(&mut self) {
+        let Some(passes) = self.resolved.take() else {
+            return;
+        };
+        let size = passes.len() as u64 * 2 * wgpu::QUERY_SIZE as u64;
+        self.pending = Some(passes);
+        let sender = self.sender.clone();
+        self.readback_buffer
+            .slice(..size)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                // The profiler may have been dropped before the mapping completed.
+                let _ = sender.send(result);
+            });
+    }
+}
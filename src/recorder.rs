@@ -0,0 +1,140 @@
+use anyhow::Context;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+use web_time::Instant;
+
+/// Encodes frames into a video by piping raw RGBA pixels into an `ffmpeg` child process.
+///
+/// The video has a fixed framerate: frames are duplicated or dropped so that it plays back in
+/// real time however fast the application renders.
+pub struct Recorder {
+    child: Child,
+    frames: Option<mpsc::SyncSender<Vec<u8>>>,
+    writer: Option<JoinHandle<std::io::Result<()>>>,
+    width: u32,
+    height: u32,
+    fps: u32,
+    start: Instant,
+    frames_written: u64,
+    path: PathBuf,
+}
+
+impl Recorder {
+    /// Starts `ffmpeg` encoding `width`x`height` frames at `fps` into an H.264 video at `path`.
+    pub fn start(
+        path: impl AsRef<Path>,
+        width: u32,
+        height: u32,
+        fps: u32,
+    ) -> anyhow::Result<Self> {
+        let path = path.as_ref().to_owned();
+        let mut child = Command::new("ffmpeg")
+            .args(["-y", "-loglevel", "error"])
+            .args(["-f", "rawvideo", "-pix_fmt", "rgba"])
+            .args(["-s", &format!("{width}x{height}")])
+            .args(["-r", &fps.to_string()])
+            .args(["-i", "-"])
+            // H.264 in yuv420p needs even dimensions.
+            .args(["-vf", "pad=ceil(iw/2)*2:ceil(ih/2)*2"])
+            .args(["-c:v", "libx264", "-pix_fmt", "yuv420p"])
+            .arg(&path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+            .context("failed to start ffmpeg, is it installed?")?;
+
+        // Writing to the pipe blocks while ffmpeg encodes, so it happens on its own thread. The
+        // channel is bounded to limit how many frames can pile up in memory.
+        let mut stdin = child.stdin.take().context("ffmpeg has no stdin")?;
+        let (frames, receiver) = mpsc::sync_channel::<Vec<u8>>(8);
+        let writer = std::thread::spawn(move || {
+            for frame in receiver {
+                stdin.write_all(&frame)?;
+            }
+            Ok(())
+        });
+
+        Ok(Self {
+            child,
+            frames: Some(frames),
+            writer: Some(writer),
+            width,
+            height,
+            fps,
+            start: Instant::now(),
+            frames_written: 0,
+            path,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The number of frames the video should contain by now.
+    fn frames_due(&self) -> u64 {
+        (self.start.elapsed().as_secs_f64() * f64::from(self.fps)) as u64 + 1
+    }
+
+    /// Whether the next frame is needed to keep up with the framerate.
+    pub fn wants_frame(&self) -> bool {
+        self.frames_due() > self.frames_written
+    }
+
+    /// Adds `image` to the video as many times as needed to keep up with the framerate.
+    pub fn push_frame(&mut self, image: &image::RgbaImage) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            image.dimensions() == (self.width, self.height),
+            "the frame size changed from {}x{} to {}x{} while recording",
+            self.width,
+            self.height,
+            image.width(),
+            image.height(),
+        );
+        let frames = self.frames.as_ref().context("the recording was stopped")?;
+        for _ in self.frames_written..self.frames_due() {
+            frames
+                .send(image.as_raw().clone())
+                .ok()
+                .context("ffmpeg stopped accepting frames")?;
+            self.frames_written += 1;
+        }
+        Ok(())
+    }
+
+    /// Waits for `ffmpeg` to encode the remaining frames and returns the path of the video.
+    pub fn finish(mut self) -> anyhow::Result<PathBuf> {
+        self.close()?;
+        Ok(std::mem::take(&mut self.path))
+    }
+
+    fn close(&mut self) -> anyhow::Result<()> {
+        // Closing the channel ends the writer thread, which closes ffmpeg's stdin.
+        self.frames = None;
+        if let Some(writer) = self.writer.take() {
+            writer
+                .join()
+                .map_err(|_| anyhow::anyhow!("the frame writer panicked"))?
+                .context("failed to write frames to ffmpeg")?;
+        }
+        let status = self.child.wait().context("failed to wait for ffmpeg")?;
+        anyhow::ensure!(status.success(), "ffmpeg exited with {status}");
+        Ok(())
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        if self.writer.is_some() {
+            if let Err(err) = self.close() {
+                eprintln!(
+                    "failed to finish recording {}: {err:#}",
+                    self.path.display()
+                );
+            }
+        }
+    }
+}
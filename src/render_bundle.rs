@@ -0,0 +1,145 @@
+//! Draws recorded once into a [`wgpu::RenderBundle`] and replayed every frame, which saves
+//! encoding them again while nothing they use changes.
+
+use std::collections::HashMap;
+
+use crate::taa::MOTION_FORMAT;
+use crate::texture::Texture;
+use crate::tonemap::HDR_FORMAT;
+
+/// The GPU objects and values a bundle was recorded with. Bundles only store references to what
+/// they bind, so buffer contents may change between frames but the objects themselves may not.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BundleKey {
+    pipelines: Vec<wgpu::Id<wgpu::RenderPipeline>>,
+    bind_groups: Vec<wgpu::Id<wgpu::BindGroup>>,
+    buffers: Vec<wgpu::Id<wgpu::Buffer>>,
+    values: Vec<u64>,
+}
+
+impl BundleKey {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn pipeline(mut self, pipeline: &wgpu::RenderPipeline) -> Self {
+        self.pipelines.push(pipeline.global_id());
+        self
+    }
+
+    pub fn bind_group(mut self, bind_group: &wgpu::BindGroup) -> Self {
+        self.bind_groups.push(bind_group.global_id());
+        self
+    }
+
+    pub fn buffer(mut self, buffer: &wgpu::Buffer) -> Self {
+        self.buffers.push(buffer.global_id());
+        self
+    }
+
+    /// Anything else recorded into the bundle, like instance counts.
+    pub fn value(mut self, value: u64) -> Self {
+        self.values.push(value);
+        self
+    }
+}
+
+/// The scene's geometry recorded into bundles for the scene pass, one for each group of draws
+/// sharing a pipeline and material, each recorded again whenever its [`BundleKey`] changes.
+pub struct StaticBundles {
+    enabled: bool,
+    /// The bundle of each group and the key it was recorded with.
+    bundles: HashMap<usize, (BundleKey, wgpu::RenderBundle)>,
+    /// The groups in the order their bundles are executed.
+    order: Vec<usize>,
+    /// How many bundles have been recorded, to tell how often they are invalidated.
+    recordings: u64,
+}
+
+impl Default for StaticBundles {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            bundles: HashMap::new(),
+            order: Vec::new(),
+            recordings: 0,
+        }
+    }
+}
+
+impl StaticBundles {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Disabling drops the bundles, so that they don't keep what they bind alive.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.bundles.clear();
+            self.order.clear();
+        }
+    }
+
+    /// The bundles recorded by the last update, in the order of its groups.
+    pub fn bundles(&self) -> impl Iterator<Item = &wgpu::RenderBundle> {
+        self.order.iter().map(|group| &self.bundles[group].1)
+    }
+
+    /// The number of groups the last update recorded bundles for.
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    pub fn recordings(&self) -> u64 {
+        self.recordings
+    }
+
+    /// Keeps a bundle for each of `groups` and drops the others, calling `record` with a group
+    /// to record its bundle again if it was last recorded with another key. Bundles render into
+    /// the scene pass's targets, multisampled `sample_count` times.
+    pub fn update<'a>(
+        &mut self,
+        device: &'a wgpu::Device,
+        sample_count: u32,
+        groups: impl IntoIterator<Item = (usize, BundleKey)>,
+        mut record: impl FnMut(usize, &mut wgpu::RenderBundleEncoder<'a>),
+    ) {
+        let mut previous = std::mem::take(&mut self.bundles);
+        self.order.clear();
+        for (group, key) in groups {
+            let bundle = match previous.remove(&group) {
+                Some((recorded, bundle)) if recorded == key => bundle,
+                _ => {
+                    let mut encoder =
+                        device.create_render_bundle_encoder(&wgpu::RenderBundleEncoderDescriptor {
+                            label: Some("scene bundle"),
+                            color_formats: &[Some(HDR_FORMAT), Some(MOTION_FORMAT)],
+                            depth_stencil: Some(wgpu::RenderBundleDepthStencil {
+                                format: Texture::DEPTH_FORMAT,
+                                depth_read_only: false,
+                                stencil_read_only: true,
+                            }),
+                            sample_count,
+                            multiview: None,
+                        });
+                    record(group, &mut encoder);
+                    self.recordings += 1;
+                    encoder.finish(&wgpu::RenderBundleDescriptor {
+                        label: Some("scene bundle"),
+                    })
+                }
+            };
+            self.bundles.insert(group, (key, bundle));
+            self.order.push(group);
+        }
+    }
+}
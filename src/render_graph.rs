@@ -0,0 +1,343 @@
+//! A small render graph: passes declare which named resources they read and write, and the
+//! graph culls the passes nothing depends on, allocates the transient textures they share and
+//! records them.
+//!
+//! wgpu tracks how every texture is used and inserts the barriers between passes itself, so
+//! the graph only has to record the passes in an order that respects their dependencies, which
+//! is the order they were added in: a pass reads what the passes added before it wrote.
+
+use crate::texture::Texture;
+
+/// Identifies a resource of a [`RenderGraph`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ResourceId(usize);
+
+/// Describes a transient texture, which is always the size of the surface.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TextureDesc {
+    pub format: wgpu::TextureFormat,
+    pub sample_count: u32,
+}
+
+enum ResourceKind<'a> {
+    /// A texture allocated by the graph, only valid between its first and last use.
+    Transient(TextureDesc),
+    /// A view owned outside the graph, like the swapchain.
+    Imported(&'a wgpu::TextureView),
+    /// State owned outside the graph, like a shadow map, which only orders the passes using it.
+    External,
+}
+
+struct Resource<'a> {
+    name: &'static str,
+    kind: ResourceKind<'a>,
+    /// Whether the resource is needed after the frame, keeping the passes writing it.
+    retained: bool,
+}
+
+type Record<'a, C> = Box<dyn FnOnce(&mut C, &mut PassContext) + 'a>;
+
+struct Pass<'a, C> {
+    name: &'static str,
+    reads: Vec<ResourceId>,
+    writes: Vec<ResourceId>,
+    record: Record<'a, C>,
+}
+
+/// The passes of a frame and the resources they share, recorded with `&mut C` at hand so that
+/// they don't have to borrow it while the graph is being built.
+pub struct RenderGraph<'a, C> {
+    resources: Vec<Resource<'a>>,
+    passes: Vec<Pass<'a, C>>,
+}
+
+impl<'a, C> Default for RenderGraph<'a, C> {
+    fn default() -> Self {
+        Self {
+            resources: Vec::new(),
+            passes: Vec::new(),
+        }
+    }
+}
+
+impl<'a, C> RenderGraph<'a, C> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn add_resource(&mut self, name: &'static str, kind: ResourceKind<'a>) -> ResourceId {
+        let retained = matches!(kind, ResourceKind::Imported(_));
+        self.resources.push(Resource {
+            name,
+            kind,
+            retained,
+        });
+        ResourceId(self.resources.len() - 1)
+    }
+
+    /// Declares a texture the size of the surface which the graph allocates, and may share with
+    /// other transient textures whose passes don't overlap.
+    pub fn create_texture(&mut self, name: &'static str, desc: TextureDesc) -> ResourceId {
+        self.add_resource(name, ResourceKind::Transient(desc))
+    }
+
+    /// Imports a view owned outside the graph. Its contents outlive the frame, so the passes
+    /// writing it are never culled.
+    pub fn import_view(&mut self, name: &'static str, view: &'a wgpu::TextureView) -> ResourceId {
+        self.add_resource(name, ResourceKind::Imported(view))
+    }
+
+    /// Declares state owned outside the graph, like a shadow map, so that the passes writing it
+    /// run before the passes reading it and are culled along with them.
+    pub fn external(&mut self, name: &'static str) -> ResourceId {
+        self.add_resource(name, ResourceKind::External)
+    }
+
+    /// Keeps the passes writing `resource` even if no pass reads it, e.g. a copy to the CPU.
+    pub fn retain(&mut self, resource: ResourceId) {
+        self.resources[resource.0].retained = true;
+    }
+
+    /// Adds a pass after the ones already added, reading and writing the given resources.
+    pub fn add_pass(
+        &mut self,
+        name: &'static str,
+        reads: &[ResourceId],
+        writes: &[ResourceId],
+        record: impl FnOnce(&mut C, &mut PassContext) + 'a,
+    ) {
+        self.passes.push(Pass {
+            name,
+            reads: reads.to_vec(),
+            writes: writes.to_vec(),
+            record: Box::new(record),
+        });
+    }
+
+    /// The passes that contribute to a retained resource, in the order they were added.
+    fn live_passes(&self) -> Vec<bool> {
+        let mut needed: Vec<bool> = self
+            .resources
+            .iter()
+            .map(|resource| resource.retained)
+            .collect();
+        let mut live = vec![false; self.passes.len()];
+        for (index, pass) in self.passes.iter().enumerate().rev() {
+            if pass.writes.iter().any(|resource| needed[resource.0]) {
+                live[index] = true;
+                for resource in &pass.reads {
+                    needed[resource.0] = true;
+                }
+            }
+        }
+        live
+    }
+
+    /// Records the passes that aren't culled into `encoder`, allocating their transient
+    /// textures from `pool` with `device`, the size of `config`.
+    pub fn execute(
+        self,
+        context: &mut C,
+        encoder: &mut wgpu::CommandEncoder,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        pool: &mut TransientPool,
+    ) {
+        let live = self.live_passes();
+        let passes: Vec<_> = self
+            .passes
+            .into_iter()
+            .zip(live)
+            .filter_map(|(pass, live)| live.then_some(pass))
+            .collect();
+
+        // The first and last pass using each transient texture.
+        let mut lifetimes: Vec<Option<(usize, usize)>> = vec![None; self.resources.len()];
+        for (index, pass) in passes.iter().enumerate() {
+            for resource in pass.reads.iter().chain(&pass.writes) {
+                let lifetime = &mut lifetimes[resource.0];
+                *lifetime = Some(lifetime.map_or((index, index), |(first, _)| (first, index)));
+            }
+        }
+        for (index, pass) in passes.iter().enumerate() {
+            for resource in &pass.reads {
+                let written_before = passes[..index]
+                    .iter()
+                    .any(|earlier| earlier.writes.contains(resource));
+                assert!(
+                    written_before
+                        || !matches!(self.resources[resource.0].kind, ResourceKind::Transient(_)),
+                    "the {} pass reads {} before any pass writes it",
+                    pass.name,
+                    self.resources[resource.0].name,
+                );
+            }
+        }
+
+        pool.begin(config);
+        let mut slots = vec![None; self.resources.len()];
+        for (index, pass) in passes.iter().enumerate() {
+            for resource in pass.reads.iter().chain(&pass.writes) {
+                if let (ResourceKind::Transient(desc), None) =
+                    (&self.resources[resource.0].kind, slots[resource.0])
+                {
+                    let name = self.resources[resource.0].name;
+                    slots[resource.0] = Some(pool.acquire(device, config, name, *desc));
+                }
+            }
+            for resource in pass.reads.iter().chain(&pass.writes) {
+                if lifetimes[resource.0].is_some_and(|(_, last)| last == index) {
+                    if let Some(slot) = slots[resource.0] {
+                        pool.release(slot);
+                    }
+                }
+            }
+        }
+
+        let bindings: Vec<Binding> = self
+            .resources
+            .iter()
+            .zip(&slots)
+            .map(|(resource, slot)| match (&resource.kind, slot) {
+                (ResourceKind::Transient(_), Some(slot)) => Binding::Transient(*slot),
+                (ResourceKind::Imported(view), _) => Binding::Imported(view),
+                _ => Binding::None,
+            })
+            .collect();
+        let resources = Resources {
+            pool: &*pool,
+            bindings: &bindings,
+            names: &self.resources,
+        };
+        for pass in passes {
+            let mut pass_context = PassContext {
+                encoder: &mut *encoder,
+                resources,
+            };
+            (pass.record)(context, &mut pass_context);
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Binding<'a> {
+    Transient(usize),
+    Imported(&'a wgpu::TextureView),
+    None,
+}
+
+/// What a pass records with: the encoder and the textures of the resources it declared.
+pub struct PassContext<'p> {
+    pub encoder: &'p mut wgpu::CommandEncoder,
+    pub resources: Resources<'p>,
+}
+
+/// Looks up the textures of a [`RenderGraph`]'s resources while its passes are recorded.
+#[derive(Clone, Copy)]
+pub struct Resources<'p> {
+    pool: &'p TransientPool,
+    bindings: &'p [Binding<'p>],
+    names: &'p [Resource<'p>],
+}
+
+impl<'p> Resources<'p> {
+    /// The transient texture allocated for `resource`.
+    pub fn texture(&self, resource: ResourceId) -> &'p Texture {
+        match self.bindings[resource.0] {
+            Binding::Transient(slot) => &self.pool.textures[slot].texture,
+            _ => panic!("{} isn't a transient texture", self.names[resource.0].name),
+        }
+    }
+
+    /// The view of a transient or imported texture.
+    pub fn view(&self, resource: ResourceId) -> &'p wgpu::TextureView {
+        match self.bindings[resource.0] {
+            Binding::Transient(slot) => &self.pool.textures[slot].texture.view,
+            Binding::Imported(view) => view,
+            Binding::None => panic!("{} has no view", self.names[resource.0].name),
+        }
+    }
+}
+
+struct PooledTexture {
+    desc: TextureDesc,
+    texture: Texture,
+    /// Whether a transient texture is using it at this point of the frame.
+    in_use: bool,
+    /// Whether any transient texture used it this frame.
+    used: bool,
+}
+
+/// The textures behind the transient textures of [`RenderGraph`]s, kept from one frame to the
+/// next as long as they're used and the surface keeps its size.
+#[derive(Default)]
+pub struct TransientPool {
+    size: (u32, u32),
+    textures: Vec<PooledTexture>,
+}
+
+impl TransientPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of textures currently allocated.
+    pub fn len(&self) -> usize {
+        self.textures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.textures.is_empty()
+    }
+
+    /// Drops the textures the previous frame didn't use, or all of them if the surface was
+    /// resized.
+    fn begin(&mut self, config: &wgpu::SurfaceConfiguration) {
+        let size = (config.width, config.height);
+        if self.size != size {
+            self.textures.clear();
+            self.size = size;
+        }
+        self.textures.retain(|texture| texture.used);
+        for texture in &mut self.textures {
+            texture.in_use = false;
+            texture.used = false;
+        }
+    }
+
+    /// Finds a free texture matching `desc`, or creates one the size of `config`.
+    fn acquire(
+        &mut self,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        label: &str,
+        desc: TextureDesc,
+    ) -> usize {
+        let free = self
+            .textures
+            .iter()
+            .position(|texture| texture.desc == desc && !texture.in_use);
+        let slot = free.unwrap_or_else(|| {
+            let texture = if desc.format == Texture::DEPTH_FORMAT {
+                Texture::create_depth_texture(device, config, desc.sample_count, label)
+            } else {
+                Texture::create_render_target(device, config, desc.format, desc.sample_count, label)
+            };
+            self.textures.push(PooledTexture {
+                desc,
+                texture,
+                in_use: false,
+                used: false,
+            });
+            self.textures.len() - 1
+        });
+        let texture = &mut self.textures[slot];
+        texture.in_use = true;
+        texture.used = true;
+        slot
+    }
+
+    fn release(&mut self, slot: usize) {
+        self.textures[slot].in_use = false;
+    }
+}
@@ -0,0 +1,121 @@
+//! The draws of alpha-blended materials, which can't be grouped by material like the opaque ones
+//! since they have to be drawn back to front.
+
+use std::sync::Arc;
+
+use crate::camera::Camera;
+use crate::instance::Instance;
+use crate::material::{AlphaMode, Material};
+use crate::mesh::{DrawEncoder, MaterialBindings, Mesh};
+
+/// One draw call of a mesh for one instance, see [`Mesh::draw_submesh`].
+#[derive(Clone, Copy, Debug)]
+pub struct SortedDraw {
+    pub instance: u32,
+    /// The index of the submesh, 0 for meshes without submeshes.
+    pub draw: usize,
+    /// How far the center of the submesh is in front of the camera.
+    pub depth: f32,
+}
+
+/// The draws of the submeshes with [`AlphaMode::Blend`] materials, sorted from the furthest to
+/// the nearest so that each is blended over what's behind it. Opaque materials are drawn first,
+/// in as few draws as their materials allow, see [`Mesh::draw`].
+#[derive(Debug, Default)]
+pub struct TransparentQueue {
+    draws: Vec<SortedDraw>,
+}
+
+impl TransparentQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Collects the blended draws of the `visible` indices of `instances`, or of every instance
+    /// without them, and sorts them by their depth from `camera`. `materials` and `fallback`
+    /// are those the submeshes of `mesh` refer to, like in [`MaterialBindings`].
+    pub fn update(
+        &mut self,
+        camera: &Camera,
+        mesh: &Mesh,
+        materials: &[Arc<Material>],
+        fallback: &Material,
+        instances: &[Instance],
+        visible: Option<&[u32]>,
+    ) {
+        self.draws.clear();
+        let blended: Vec<usize> = (0..mesh.draw_count())
+            .filter(|&draw| {
+                let material = mesh
+                    .submeshes()
+                    .get(draw)
+                    .and_then(|submesh| submesh.material)
+                    .and_then(|material| materials.get(material))
+                    .map_or(fallback, |material| &**material);
+                material.alpha_mode() == AlphaMode::Blend
+            })
+            .collect();
+        if blended.is_empty() {
+            return;
+        }
+        let view = camera.view_matrix();
+        let mut push = |instance: u32| {
+            let Some(transform) = instances
+                .get(instance as usize)
+                .map(|instance| instance.transform)
+            else {
+                return;
+            };
+            for &draw in &blended {
+                let bounds = mesh.draw_bounds(draw);
+                if bounds.is_empty() {
+                    continue;
+                }
+                // The camera looks down negative Z in view space.
+                let center = view.transform_point3(transform.transform_point3(bounds.center()));
+                self.draws.push(SortedDraw {
+                    instance,
+                    draw,
+                    depth: -center.z,
+                });
+            }
+        };
+        match visible {
+            Some(visible) => visible.iter().copied().for_each(&mut push),
+            None => (0..instances.len() as u32).for_each(&mut push),
+        }
+        self.draws.sort_by(|a, b| b.depth.total_cmp(&a.depth));
+    }
+
+    /// The draws of the last update, furthest first.
+    pub fn draws(&self) -> &[SortedDraw] {
+        &self.draws
+    }
+
+    pub fn len(&self) -> usize {
+        self.draws.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.draws.is_empty()
+    }
+
+    /// Records the draws in order, each instance read from `instance_buffer` at its index.
+    pub fn draw<'a>(
+        &self,
+        encoder: &mut impl DrawEncoder<'a>,
+        mesh: &'a Mesh,
+        instance_buffer: &'a wgpu::Buffer,
+        materials: MaterialBindings<'a>,
+    ) {
+        for draw in &self.draws {
+            mesh.draw_submesh(
+                encoder,
+                instance_buffer,
+                draw.draw,
+                draw.instance..draw.instance + 1,
+                materials,
+            );
+        }
+    }
+}
@@ -0,0 +1,3287 @@
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+use anyhow::Context;
+use glam::{Mat4, Vec2, Vec3};
+#[cfg(not(target_arch = "wasm32"))]
+use pollster::FutureExt;
+use web_time::Instant;
+use wgpu::util::DeviceExt;
+
+use crate::bindless::BindlessTextures;
+use crate::camera::{Camera, CameraUniform};
+use crate::camera2d::Camera2D;
+use crate::capture::TextureReadback;
+use crate::color_grading::{ColorGrading, LutData};
+use crate::cpu_particles::{CpuParticlePipelineKey, CpuParticles};
+use crate::culling::{FrustumCulling, GpuCulling};
+use crate::debug_draw::{DebugDraw, DebugDrawPipelineKey};
+use crate::depth_of_field::DepthOfField;
+use crate::fxaa::Fxaa;
+use crate::globals::Globals;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::hot_reload::ShaderWatcher;
+use crate::instance::{Instance, InstanceRaw};
+use crate::lens_effects::LensEffects;
+use crate::light::{DirectionalLight, Light, Lighting};
+use crate::lod::{Lod, LodLevel};
+use crate::material::{
+    AlphaMode, Material, MaterialCache, MaterialData, MaterialFactors, MaterialTextures,
+    MaterialUniform,
+};
+use crate::math::{Aabb, Frustum, Obb};
+use crate::mesh::{MaterialBindings, Mesh, MeshData};
+use crate::mesh_allocator::MeshAllocator;
+use crate::mipmap::MipmapGenerator;
+use crate::motion_blur::MotionBlur;
+use crate::msdf::{MsdfPipelineKey, MsdfText};
+use crate::occlusion::{OcclusionCulling, OcclusionPipelineKey};
+use crate::oit::{self, Oit};
+use crate::painter::Painter;
+use crate::particles::{GpuParticles, ParticlePipelineKey};
+use crate::picking::{Pick, Picking};
+use crate::pipeline_cache::{PipelineBuilder, PipelineCompiler};
+use crate::post_process::{EffectContext, EffectPipelineKey, PostProcess};
+use crate::prepass::Prepass;
+use crate::primitives;
+use crate::profiler::GpuProfiler;
+use crate::render_bundle::{BundleKey, StaticBundles};
+use crate::render_graph::{RenderGraph, TextureDesc, TransientPool};
+use crate::render_queue::TransparentQueue;
+use crate::shader;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::shader::Preprocessor;
+use crate::shadow::ShadowMap;
+use crate::skinning::{SkinnedMesh, SkinnedMeshData, SkinnedVertex, Skinning};
+use crate::skybox::{CubeMapData, Skybox, SkyboxPipelineKey};
+use crate::sprite::{self, SpriteBatch, SpritePipelineKey};
+use crate::ssao::Ssao;
+use crate::svg::{Svg, SvgData};
+use crate::taa::{self, Taa, MOTION_FORMAT};
+use crate::terrain::{Terrain, TerrainData};
+use crate::text::TextRenderer;
+use crate::texture::{SamplerDesc, Texture};
+use crate::tilemap::{TileMap, TileMapData};
+use crate::tonemap::{Tonemap, HDR_FORMAT};
+use crate::uniform::UniformBuffer;
+use crate::uniform_arena::UniformArena;
+use crate::upload::Uploader;
+use crate::vertex::{Vertex, VertexLayout, VertexType};
+
+/// The directory watched for shader changes during development.
+#[cfg(not(target_arch = "wasm32"))]
+const SHADER_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/res");
+
+/// The MSAA sample count used when the surface format supports it.
+const DEFAULT_SAMPLE_COUNT: u32 = 4;
+
+/// The formats of the color targets that may be multisampled and resolved.
+const MSAA_FORMATS: [wgpu::TextureFormat; 3] = [HDR_FORMAT, MOTION_FORMAT, oit::REVEALAGE_FORMAT];
+
+/// How many GPU particles can be alive at once.
+const PARTICLE_CAPACITY: u32 = 1 << 16;
+
+/// A rough dielectric, so that the checker texture shows through.
+const DEFAULT_MATERIAL_FACTORS: MaterialFactors = MaterialFactors {
+    base_color: [1.0; 4],
+    metallic: 0.0,
+    roughness: 0.6,
+    emissive: [0.0; 3],
+    normal_scale: 1.0,
+    occlusion_strength: 1.0,
+};
+
+/// Overrides for how the renderer picks its backend, adapter and present mode.
+#[derive(Clone, Debug, Default)]
+pub struct RendererOptions {
+    /// The backends to choose an adapter from, instead of the platform's primary ones.
+    pub backends: Option<wgpu::Backends>,
+    /// The index of an adapter in the list of compatible adapters, or part of its name.
+    pub adapter: Option<String>,
+    /// Fails renderer creation if the surface doesn't support it.
+    pub present_mode: Option<wgpu::PresentMode>,
+}
+
+/// Owns the GPU device, the window surface and everything needed to draw a frame.
+pub struct Renderer<'a> {
+    /// Kept to create a new surface when the window's is destroyed, see [`Renderer::resume`].
+    instance: wgpu::Instance,
+    target: RenderTarget<'a>,
+    /// The configuration of the surface, or of the offscreen texture when rendering headless.
+    surface_config: wgpu::SurfaceConfiguration,
+    device: Arc<wgpu::Device>,
+    queue: wgpu::Queue,
+    downlevel: wgpu::DownlevelCapabilities,
+    supported_sample_counts: Vec<u32>,
+    /// The present modes the surface can be switched to, see [`Renderer::set_present_mode`].
+    present_modes: Vec<wgpu::PresentMode>,
+    mipmap_generator: MipmapGenerator,
+    sample_count: u32,
+    clear_color: wgpu::Color,
+    /// A sample count switched to once its pipeline has been compiled in the background.
+    pending_sample_count: Option<u32>,
+    /// The textures behind the transient targets of the frame's render graph.
+    transients: TransientPool,
+    /// Owns the buffers every mesh's vertices and indices are allocated from.
+    mesh_allocator: MeshAllocator,
+    mesh: Mesh,
+    instance_buffer: wgpu::Buffer,
+    /// The contents of `instance_buffer`, which culling copies the visible instances from.
+    instance_data: Vec<InstanceRaw>,
+    instance_count: u32,
+    culling: FrustumCulling,
+    occlusion: OcclusionCulling,
+    lod: Lod,
+    /// Takes over from `culling` where compute shaders are available.
+    gpu_culling: Option<GpuCulling>,
+    /// `None` where compute shaders or storage buffers in vertex shaders aren't supported.
+    particles: Option<GpuParticles>,
+    cpu_particles: CpuParticles,
+    /// World-space text that stays sharp at any scale.
+    msdf_text: MsdfText,
+    /// 2D sprites drawn over the post-processed frame.
+    sprites: SpriteBatch,
+    /// Vector shapes drawn over the sprites.
+    painter: Painter,
+    /// Drawn under the sprites.
+    tilemap: Option<TileMap>,
+    /// Drawn over the tile map and under the sprites.
+    svgs: Vec<Svg>,
+    skinning: Skinning,
+    /// Drawn once each with their opaque materials, after the mesh, and into the shadow maps
+    /// and the prepass where skinned by [`Skinning::dispatch`].
+    skinned_meshes: Vec<SkinnedMesh>,
+    /// The index of each skinned mesh in the camera's view this frame, all of them with culling
+    /// disabled.
+    visible_skinned_meshes: Vec<usize>,
+    /// Drawn with the meshes, and into the shadow maps and the prepass, in chunks picked by
+    /// [`Terrain::update`] every frame.
+    terrain: Option<Terrain>,
+    terrain_bind_group_layout: wgpu::BindGroupLayout,
+    /// The view of the sprites and the tile map.
+    camera_2d: Camera2D,
+    /// The scene pass's mesh draws by pipeline and material, replayed while culling doesn't
+    /// change them from frame to frame.
+    static_bundles: StaticBundles,
+    /// The bounds of every instance of the geometry, which the shadow map covers.
+    scene_bounds: (Vec3, Vec3),
+    instances: Vec<Instance>,
+    /// Whether the instances moved in the last [`Renderer::set_instances`], so that their
+    /// previous transforms have to catch up after the next frame.
+    instances_moved: bool,
+    /// Uploads the data rewritten every frame, like instances and debug lines.
+    uploader: Uploader,
+    globals: UniformBuffer<Globals>,
+    /// The factors of every material, followed by the default material's, pushed every frame.
+    material_uniforms: UniformArena<MaterialUniform>,
+    /// The dynamic offset of each material's factors in `material_uniforms`, in the same order.
+    material_offsets: Vec<u32>,
+    /// The index in `material_offsets` of the first material of each skinned mesh, whose
+    /// materials follow the default one, each followed by the default one again.
+    skinned_material_offsets: Vec<usize>,
+    frame_bind_group_layout: wgpu::BindGroupLayout,
+    /// The globals and the material uniforms at `@group(0)` of the mesh pipelines.
+    frame_bind_group: wgpu::BindGroup,
+    camera: Camera,
+    camera_uniform: UniformBuffer<CameraUniform>,
+    /// The camera of the previous frame, which motion vectors are measured from.
+    previous_view_proj: Option<Mat4>,
+    /// Counts frames to cycle through the TAA jitter offsets.
+    frame_index: u32,
+    lighting: Lighting,
+    prepass: Prepass,
+    ssao: Ssao,
+    picking: Picking,
+    debug_draw: DebugDraw,
+    /// Labels and the stats overlay, drawn over the post-processed frame.
+    text: TextRenderer,
+    material_bind_group_layout: wgpu::BindGroupLayout,
+    /// The textures of every material in one bind group, where binding arrays are supported.
+    bindless: Option<BindlessTextures>,
+    /// Used for draws without a material, like the cube grid.
+    default_material: Material,
+    materials: Vec<Arc<Material>>,
+    /// The draws of alpha-blended materials, sorted every frame.
+    transparent: TransparentQueue,
+    /// Shares textures and bind groups between equivalent materials.
+    material_cache: MaterialCache,
+    skybox: Skybox,
+    /// Composites transparent materials over the scene.
+    oit: Oit,
+    show_skybox: bool,
+    show_bounds: bool,
+    post_process: PostProcess,
+    pipeline_layout: Arc<wgpu::PipelineLayout>,
+    shader_module: Arc<wgpu::ShaderModule>,
+    /// Like `pipeline_layout`, with the joint palette next to the camera at `@group(1)`.
+    skinned_pipeline_layout: Arc<wgpu::PipelineLayout>,
+    /// `shader.wgsl` skinning vertices with the joint palette.
+    skinned_shader_module: Arc<wgpu::ShaderModule>,
+    /// Like `pipeline_layout`, with the terrain's layers in place of materials at `@group(2)`.
+    terrain_pipeline_layout: Arc<wgpu::PipelineLayout>,
+    /// `shader.wgsl` splatting the terrain's layers.
+    terrain_shader_module: Arc<wgpu::ShaderModule>,
+    /// Bumped on every shader reload so that pipelines built from older shaders aren't reused.
+    shader_generation: u64,
+    pipelines: PipelineCompiler,
+    pipeline: Arc<wgpu::RenderPipeline>,
+    wireframe: bool,
+    #[cfg(not(target_arch = "wasm32"))]
+    shader_watcher: Option<ShaderWatcher>,
+    /// Times passes with timestamp queries when the device supports them.
+    profiler: Option<GpuProfiler>,
+    /// Reports how long submitted frames took to complete, see [`Renderer::gpu_time`].
+    gpu_time_sender: mpsc::Sender<Duration>,
+    gpu_time_receiver: mpsc::Receiver<Duration>,
+    gpu_time: Option<Duration>,
+    acquire_time: Duration,
+    capture_requested: bool,
+    capture: Option<anyhow::Result<image::RgbaImage>>,
+    start_time: Instant,
+    last_frame: Instant,
+}
+
+/// Where frames end up: the swapchain of a window, or a texture when rendering headless.
+enum RenderTarget<'a> {
+    Surface(wgpu::Surface<'a>),
+    /// The window's surface was dropped while the application is suspended.
+    Suspended,
+    /// Shared with the frame being rendered, which outlives borrows of the renderer.
+    Offscreen(Arc<wgpu::Texture>),
+}
+
+impl RenderTarget<'_> {
+    fn acquire(&self) -> Result<Frame, wgpu::SurfaceError> {
+        match self {
+            RenderTarget::Surface(surface) => surface.get_current_texture().map(Frame::Surface),
+            RenderTarget::Suspended => Err(wgpu::SurfaceError::Lost),
+            RenderTarget::Offscreen(texture) => Ok(Frame::Offscreen(texture.clone())),
+        }
+    }
+}
+
+/// The texture a frame is rendered into.
+enum Frame {
+    Surface(wgpu::SurfaceTexture),
+    Offscreen(Arc<wgpu::Texture>),
+}
+
+impl Frame {
+    fn texture(&self) -> &wgpu::Texture {
+        match self {
+            Frame::Surface(output) => &output.texture,
+            Frame::Offscreen(texture) => texture,
+        }
+    }
+
+    fn present(self) {
+        if let Frame::Surface(output) = self {
+            output.present();
+        }
+    }
+}
+
+/// What an overlay drawn by [`Renderer::render_with`] needs to record its passes.
+pub struct OverlayContext<'f> {
+    pub device: &'f wgpu::Device,
+    pub queue: &'f wgpu::Queue,
+    pub encoder: &'f mut wgpu::CommandEncoder,
+    /// The swapchain view the scene has been post-processed into.
+    pub view: &'f wgpu::TextureView,
+    pub profiler: Option<&'f mut GpuProfiler>,
+}
+
+/// Everything that distinguishes one mesh pipeline from another.
+#[derive(Clone, Copy, Hash)]
+struct MeshPipelineKey {
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+    polygon_mode: wgpu::PolygonMode,
+    /// Whether back faces are drawn too, for double-sided materials.
+    double_sided: bool,
+    /// Transparent materials draw into the targets of [`oit`] rather than the scene's.
+    alpha_mode: AlphaMode,
+    vertex_layout: &'static VertexLayout,
+    /// Whether the shader splats the terrain's layers rather than sampling materials.
+    terrain: bool,
+    shader_generation: u64,
+}
+
+/// The bounding box around `bounds` transformed by each of `instances`.
+fn transformed_bounds(Aabb { min, max }: Aabb, instances: &[Instance]) -> (Vec3, Vec3) {
+    if min.cmpgt(max).any() || instances.is_empty() {
+        return (Vec3::ZERO, Vec3::ZERO);
+    }
+    let corners = (0..8).map(|corner| {
+        Vec3::select(
+            glam::BVec3::new(corner & 1 != 0, corner & 2 != 0, corner & 4 != 0),
+            max,
+            min,
+        )
+    });
+    instances
+        .iter()
+        .flat_map(|instance| {
+            corners
+                .clone()
+                .map(move |corner| instance.transform.transform_point3(corner))
+        })
+        .fold(
+            (Vec3::splat(f32::INFINITY), Vec3::splat(f32::NEG_INFINITY)),
+            |(min, max), corner| (min.min(corner), max.max(corner)),
+        )
+}
+
+impl<'a> Renderer<'a> {
+    pub async fn new(
+        window: Arc<winit::window::Window>,
+        options: &RendererOptions,
+    ) -> anyhow::Result<Self> {
+        let size = window.inner_size();
+
+        let instance = create_instance(options.backends).await;
+        let surface = instance.create_surface(window)?;
+        let adapter =
+            request_adapter(&instance, Some(&surface), options.adapter.as_deref()).await?;
+
+        let capabilities = surface.get_capabilities(&adapter);
+        let format = select_surface_format(&capabilities.formats)
+            .context("surface is incompatible with the adapter")?;
+        let present_mode = match options.present_mode {
+            Some(mode) => {
+                anyhow::ensure!(
+                    capabilities.present_modes.contains(&mode),
+                    "the surface doesn't support {mode:?}, it supports {:?}",
+                    capabilities.present_modes
+                );
+                mode
+            }
+            None => wgpu::PresentMode::AutoVsync,
+        };
+
+        // Copying from the swapchain is what makes frame captures possible.
+        let usage = wgpu::TextureUsages::RENDER_ATTACHMENT
+            | (capabilities.usages & wgpu::TextureUsages::COPY_SRC);
+        let surface_config = wgpu::SurfaceConfiguration {
+            usage,
+            format,
+            width: size.width.max(1),
+            height: size.height.max(1),
+            present_mode,
+            alpha_mode: wgpu::CompositeAlphaMode::Auto,
+            desired_maximum_frame_latency: 2,
+            view_formats: vec![],
+        };
+
+        Self::from_adapter(instance, &adapter, Some(surface), surface_config).await
+    }
+
+    /// Creates a renderer drawing into a `width`x`height` texture instead of a window, for
+    /// rendering without a display.
+    pub async fn new_headless(
+        width: u32,
+        height: u32,
+        options: &RendererOptions,
+    ) -> anyhow::Result<Self> {
+        let instance = create_instance(options.backends).await;
+        let adapter = request_adapter(&instance, None, options.adapter.as_deref()).await?;
+        let surface_config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            width: width.max(1),
+            height: height.max(1),
+            present_mode: wgpu::PresentMode::AutoVsync,
+            alpha_mode: wgpu::CompositeAlphaMode::Auto,
+            desired_maximum_frame_latency: 2,
+            view_formats: vec![],
+        };
+
+        Self::from_adapter(instance, &adapter, None, surface_config).await
+    }
+
+    /// Creates the device and everything else, drawing into `surface` or, without one, into a
+    /// texture described by `surface_config`.
+    async fn from_adapter(
+        instance: wgpu::Instance,
+        adapter: &wgpu::Adapter,
+        surface: Option<wgpu::Surface<'a>>,
+        surface_config: wgpu::SurfaceConfiguration,
+    ) -> anyhow::Result<Self> {
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: None,
+                    required_features: adapter.features(),
+                    required_limits: adapter.limits(),
+                    memory_hints: wgpu::MemoryHints::default(),
+                },
+                None,
+            )
+            .await?;
+        let device = Arc::new(device);
+
+        let present_modes = match &surface {
+            Some(surface) => {
+                available_present_modes(&surface.get_capabilities(adapter).present_modes)
+            }
+            None => Vec::new(),
+        };
+        let target = match surface {
+            Some(surface) => {
+                surface.configure(&device, &surface_config);
+                RenderTarget::Surface(surface)
+            }
+            None => RenderTarget::Offscreen(create_offscreen_texture(&device, &surface_config)),
+        };
+
+        let cube = primitives::cube();
+        let mesh_allocator = MeshAllocator::new();
+        let mesh = Mesh::from_vertices(
+            &mesh_allocator,
+            &device,
+            &queue,
+            &cube.vertices,
+            Some(&cube.indices),
+        );
+        let scene_bounds = transformed_bounds(mesh.bounds().aabb, &[Instance::default()]);
+        let downlevel = adapter.get_downlevel_capabilities();
+        let gpu_culling = GpuCulling::new(&device, &shader::embedded_preprocessor(), &downlevel)?;
+        let instance_data = instance_data(&[Instance::default()], &[]);
+        let instance_buffer =
+            create_instance_buffer(&device, &instance_data, gpu_culling.is_some());
+
+        let globals = UniformBuffer::new(
+            &device,
+            "globals",
+            Globals::default(),
+            wgpu::ShaderStages::VERTEX_FRAGMENT,
+        );
+        let material_uniforms = UniformArena::new(&device, "material uniforms", 16);
+        let frame_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("frame"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    UniformArena::<MaterialUniform>::layout_entry(1, wgpu::ShaderStages::FRAGMENT),
+                ],
+            });
+        let frame_bind_group = create_frame_bind_group(
+            &device,
+            &frame_bind_group_layout,
+            &globals,
+            &material_uniforms,
+        );
+        let camera = Camera::new(surface_config.width as f32 / surface_config.height as f32);
+        let camera_uniform = UniformBuffer::new(
+            &device,
+            "camera",
+            CameraUniform::from(&camera),
+            wgpu::ShaderStages::VERTEX_FRAGMENT,
+        );
+        let prepass = Prepass::new(
+            &device,
+            &shader::embedded_preprocessor(),
+            &surface_config,
+            camera_uniform.bind_group_layout(),
+        )?;
+        let ssao = Ssao::new(
+            &device,
+            &queue,
+            &shader::embedded_preprocessor(),
+            &surface_config,
+            &prepass,
+        )?;
+        let picking = Picking::new(
+            &device,
+            &shader::embedded_preprocessor(),
+            &surface_config,
+            camera_uniform.bind_group_layout(),
+        )?;
+        let debug_draw = DebugDraw::new(
+            &device,
+            &shader::embedded_preprocessor(),
+            camera_uniform.bind_group_layout(),
+        )?;
+        let particles = GpuParticles::new(
+            &device,
+            &shader::embedded_preprocessor(),
+            camera_uniform.bind_group_layout(),
+            &downlevel,
+            PARTICLE_CAPACITY,
+        )?;
+        let cpu_particles = CpuParticles::new(
+            &device,
+            &shader::embedded_preprocessor(),
+            camera_uniform.bind_group_layout(),
+        )?;
+        let msdf_text = MsdfText::new(
+            &device,
+            &shader::embedded_preprocessor(),
+            camera_uniform.bind_group_layout(),
+        )?;
+        let sprites = SpriteBatch::new(&device, &shader::embedded_preprocessor())?;
+        let painter = Painter::new(&device, &queue, &sprites);
+        let occlusion = OcclusionCulling::new(
+            &device,
+            &shader::embedded_preprocessor(),
+            camera_uniform.bind_group_layout(),
+        )?;
+        let lighting = Lighting::new(
+            &device,
+            &queue,
+            &downlevel,
+            &shader::embedded_preprocessor(),
+            ssao.view(),
+        )?;
+
+        let mut mipmap_generator = MipmapGenerator::new(&device);
+        let material_bind_group_layout = Material::bind_group_layout(&device);
+        let bindless = BindlessTextures::new(&device);
+        let texture = Texture::from_bytes(
+            &device,
+            &queue,
+            include_bytes!("res/checker.png"),
+            "checker.png",
+            SamplerDesc::default(),
+            Some(&mut mipmap_generator),
+        )?;
+        let default_material = Material::new(
+            &device,
+            &queue,
+            &material_bind_group_layout,
+            None,
+            DEFAULT_MATERIAL_FACTORS,
+            MaterialTextures {
+                base_color: Some(texture),
+                ..Default::default()
+            },
+        );
+
+        let skybox = Skybox::new(
+            &device,
+            &queue,
+            &shader::embedded_preprocessor(),
+            &CubeMapData::gradient(),
+            &mut mipmap_generator,
+        )?;
+        lighting.ibl().prefilter(&device, &queue, &skybox);
+
+        let pipeline_layout = Arc::new(device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[
+                    &frame_bind_group_layout,
+                    camera_uniform.bind_group_layout(),
+                    bindless.as_ref().map_or(
+                        &material_bind_group_layout,
+                        BindlessTextures::bind_group_layout,
+                    ),
+                    lighting.bind_group_layout(),
+                ],
+                push_constant_ranges: &[],
+            },
+        ));
+
+        let shader_module = Arc::new(shader::create_module(
+            &device,
+            &scene_preprocessor(
+                &lighting,
+                bindless.as_ref(),
+                shader::embedded_preprocessor(),
+            ),
+            "shader.wgsl",
+        )?);
+        let skinning = Skinning::new(&device, &shader::embedded_preprocessor(), &downlevel)?;
+        let skinned_pipeline_layout = Arc::new(device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("skinned"),
+                bind_group_layouts: &[
+                    &frame_bind_group_layout,
+                    skinning.bind_group_layout(),
+                    bindless.as_ref().map_or(
+                        &material_bind_group_layout,
+                        BindlessTextures::bind_group_layout,
+                    ),
+                    lighting.bind_group_layout(),
+                ],
+                push_constant_ranges: &[],
+            },
+        ));
+        let skinned_shader_module = Arc::new(shader::create_module(
+            &device,
+            &skinning.configure_preprocessor(scene_preprocessor(
+                &lighting,
+                bindless.as_ref(),
+                shader::embedded_preprocessor(),
+            )),
+            "shader.wgsl",
+        )?);
+        let terrain_bind_group_layout = Terrain::bind_group_layout(&device);
+        let terrain_pipeline_layout = Arc::new(device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("terrain"),
+                bind_group_layouts: &[
+                    &frame_bind_group_layout,
+                    camera_uniform.bind_group_layout(),
+                    &terrain_bind_group_layout,
+                    lighting.bind_group_layout(),
+                ],
+                push_constant_ranges: &[],
+            },
+        ));
+        let terrain_shader_module = Arc::new(shader::create_module(
+            &device,
+            &Terrain::configure_preprocessor(scene_preprocessor(
+                &lighting,
+                bindless.as_ref(),
+                shader::embedded_preprocessor(),
+            )),
+            "shader.wgsl",
+        )?);
+
+        let sample_count = supported_sample_counts(adapter, &MSAA_FORMATS)
+            .into_iter()
+            .filter(|&count| count <= DEFAULT_SAMPLE_COUNT)
+            .max()
+            .unwrap_or(1);
+        let mut post_process =
+            PostProcess::new(&device, &shader::embedded_preprocessor(), &surface_config)?;
+        post_process.push(
+            Taa::new(&device, &shader::embedded_preprocessor(), &surface_config)?,
+            false,
+        );
+        post_process.push(
+            DepthOfField::new(&device, &shader::embedded_preprocessor())?,
+            false,
+        );
+        post_process.push(
+            MotionBlur::new(&device, &shader::embedded_preprocessor())?,
+            false,
+        );
+        post_process.push(
+            Tonemap::new(&device, &shader::embedded_preprocessor())?,
+            true,
+        );
+        post_process.push(
+            ColorGrading::new(&device, &queue, &shader::embedded_preprocessor())?,
+            false,
+        );
+        post_process.push(Fxaa::new(&device, &shader::embedded_preprocessor())?, false);
+        post_process.push(
+            LensEffects::new(&device, &shader::embedded_preprocessor())?,
+            false,
+        );
+
+        let mut pipelines = PipelineCompiler::new(
+            device.clone(),
+            &adapter.get_info(),
+            // There is no file system to keep the cache on in the browser.
+            (!cfg!(target_arch = "wasm32"))
+                .then(|| std::env::temp_dir().join("hello-wgpu-pipelines")),
+        );
+        let key = MeshPipelineKey {
+            format: HDR_FORMAT,
+            sample_count,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            double_sided: false,
+            alpha_mode: AlphaMode::Opaque,
+            vertex_layout: mesh.vertex_layout(),
+            terrain: false,
+            shader_generation: 0,
+        };
+        let pipeline = pipelines.get(
+            &key,
+            &*mesh_pipeline_builder(pipeline_layout.clone(), shader_module.clone(), key),
+        );
+        let oit = Oit::new(&device, &shader::embedded_preprocessor())?;
+        let text = TextRenderer::new(&device, &queue, surface_config.format);
+
+        let profiler = GpuProfiler::new(&device, &queue);
+        let (gpu_time_sender, gpu_time_receiver) = mpsc::channel();
+
+        Ok(Self {
+            instance,
+            target,
+            surface_config,
+            device,
+            queue,
+            downlevel,
+            supported_sample_counts: supported_sample_counts(adapter, &MSAA_FORMATS),
+            present_modes,
+            mipmap_generator,
+            sample_count,
+            clear_color: wgpu::Color::BLACK,
+            pending_sample_count: None,
+            transients: TransientPool::new(),
+            mesh_allocator,
+            mesh,
+            instance_buffer,
+            instance_data,
+            instance_count: 1,
+            culling: FrustumCulling::new(),
+            occlusion,
+            lod: Lod::new(),
+            gpu_culling,
+            particles,
+            cpu_particles,
+            msdf_text,
+            sprites,
+            painter,
+            tilemap: None,
+            svgs: Vec::new(),
+            skinning,
+            skinned_meshes: Vec::new(),
+            visible_skinned_meshes: Vec::new(),
+            terrain: None,
+            terrain_bind_group_layout,
+            camera_2d: Camera2D::default(),
+            static_bundles: StaticBundles::new(),
+            scene_bounds,
+            instances: vec![Instance::default()],
+            instances_moved: false,
+            uploader: Uploader::new(),
+            globals,
+            material_uniforms,
+            material_offsets: Vec::new(),
+            skinned_material_offsets: Vec::new(),
+            frame_bind_group_layout,
+            frame_bind_group,
+            camera,
+            camera_uniform,
+            previous_view_proj: None,
+            frame_index: 0,
+            lighting,
+            prepass,
+            picking,
+            debug_draw,
+            text,
+            ssao,
+            material_bind_group_layout,
+            bindless,
+            default_material,
+            materials: Vec::new(),
+            transparent: TransparentQueue::new(),
+            material_cache: MaterialCache::new(),
+            skybox,
+            oit,
+            show_skybox: true,
+            show_bounds: false,
+            post_process,
+            pipeline_layout,
+            shader_module,
+            skinned_pipeline_layout,
+            skinned_shader_module,
+            terrain_pipeline_layout,
+            terrain_shader_module,
+            shader_generation: 0,
+            pipelines,
+            pipeline,
+            wireframe: false,
+            #[cfg(not(target_arch = "wasm32"))]
+            shader_watcher: ShaderWatcher::new(SHADER_DIR)
+                .inspect_err(|err| eprintln!("shader hot reload disabled: {err}"))
+                .ok(),
+            profiler,
+            gpu_time_sender,
+            gpu_time_receiver,
+            gpu_time: None,
+            acquire_time: Duration::ZERO,
+            capture_requested: false,
+            capture: None,
+            start_time: Instant::now(),
+            last_frame: Instant::now(),
+        })
+    }
+
+    pub fn device(&self) -> &wgpu::Device {
+        &self.device
+    }
+
+    pub fn queue(&self) -> &wgpu::Queue {
+        &self.queue
+    }
+
+    pub fn surface_config(&self) -> &wgpu::SurfaceConfiguration {
+        &self.surface_config
+    }
+
+    /// Whether the swapchain encodes to sRGB on write, as opposed to storing shader output
+    /// as-is.
+    pub fn surface_is_srgb(&self) -> bool {
+        self.surface_config.format.is_srgb()
+    }
+
+    /// The requested MSAA sample count, which takes effect once its pipeline has been compiled.
+    pub fn sample_count(&self) -> u32 {
+        self.pending_sample_count.unwrap_or(self.sample_count)
+    }
+
+    /// The MSAA sample counts supported by the HDR, motion vector, transparency and depth formats,
+    /// ascending.
+    pub fn supported_sample_counts(&self) -> &[u32] {
+        &self.supported_sample_counts
+    }
+
+    /// Switches the MSAA sample count. The pipeline for it is compiled in the background and the
+    /// render targets are rebuilt once it is ready. Unsupported counts are ignored.
+    pub fn set_sample_count(&mut self, sample_count: u32) {
+        if !self.supported_sample_counts.contains(&sample_count) {
+            return;
+        }
+        self.pending_sample_count = (sample_count != self.sample_count).then_some(sample_count);
+        self.update_pipeline();
+    }
+
+    /// How long the GPU took to render the most recently completed frame. This is the sum of
+    /// the pass timings when timestamp queries are supported, otherwise it's measured from the
+    /// frame's submission and includes any time it spent queued.
+    pub fn gpu_time(&self) -> Option<Duration> {
+        match self.pass_times() {
+            [] => self.gpu_time,
+            pass_times => Some(pass_times.iter().map(|&(_, time)| time).sum()),
+        }
+    }
+
+    /// The GPU time of each pass of a recent frame, empty when timestamp queries aren't
+    /// supported.
+    pub fn pass_times(&self) -> &[(&'static str, Duration)] {
+        self.profiler.as_ref().map_or(&[], GpuProfiler::timings)
+    }
+
+    /// How long the last frame waited for a swapchain image, e.g. because of vsync.
+    pub fn acquire_time(&self) -> Duration {
+        self.acquire_time
+    }
+
+    /// Reads back the next rendered frame, without overlays, to be retrieved with
+    /// [`Renderer::take_capture`].
+    pub fn request_capture(&mut self) {
+        self.capture_requested = true;
+    }
+
+    /// Takes the frame captured after [`Renderer::request_capture`], once it has been rendered.
+    pub fn take_capture(&mut self) -> Option<anyhow::Result<image::RgbaImage>> {
+        self.capture.take()
+    }
+
+    /// Picks the instance under `position`, in physical pixels, by rendering instance IDs with
+    /// the next frame. The result is retrieved with [`Renderer::take_pick`].
+    pub fn request_pick(&mut self, position: (u32, u32)) {
+        self.picking.request(position);
+    }
+
+    /// Takes the result of a [`Renderer::request_pick`] once it has been read back, which
+    /// doesn't wait for the GPU and usually takes a frame or two.
+    pub fn take_pick(&mut self) -> Option<Pick> {
+        self.picking.poll(&self.device)
+    }
+
+    pub fn clear_color(&self) -> wgpu::Color {
+        self.clear_color
+    }
+
+    /// Sets the linear color the frame is cleared to before the mesh is drawn, only visible
+    /// with the skybox hidden.
+    pub fn set_clear_color(&mut self, color: wgpu::Color) {
+        self.clear_color = color;
+    }
+
+    pub fn fxaa(&self) -> bool {
+        self.post_process
+            .position::<Fxaa>()
+            .is_some_and(|index| self.post_process.enabled(index))
+    }
+
+    /// Smooths edges with FXAA after tonemapping, instead of or on top of MSAA.
+    pub fn set_fxaa(&mut self, fxaa: bool) {
+        if let Some(index) = self.post_process.position::<Fxaa>() {
+            self.post_process.set_enabled(index, fxaa);
+        }
+    }
+
+    pub fn taa(&self) -> bool {
+        self.post_process
+            .position::<Taa>()
+            .is_some_and(|index| self.post_process.enabled(index))
+    }
+
+    /// Accumulates jittered frames with TAA before tonemapping, starting from a fresh history.
+    pub fn set_taa(&mut self, taa: bool) {
+        if let Some(index) = self.post_process.position::<Taa>() {
+            if taa && !self.post_process.enabled(index) {
+                if let Some(effect) = self.post_process.effect_mut::<Taa>() {
+                    effect.reset();
+                }
+            }
+            self.post_process.set_enabled(index, taa);
+        }
+    }
+
+    /// The effects turning the HDR scene into the final image, tonemapping by default.
+    pub fn post_process(&self) -> &PostProcess {
+        &self.post_process
+    }
+
+    pub fn post_process_mut(&mut self) -> &mut PostProcess {
+        &mut self.post_process
+    }
+
+    pub fn show_skybox(&self) -> bool {
+        self.show_skybox
+    }
+
+    /// Draws the skybox behind the scene, or leaves the clear color there.
+    pub fn set_show_skybox(&mut self, show_skybox: bool) {
+        self.show_skybox = show_skybox;
+    }
+
+    pub fn show_bounds(&self) -> bool {
+        self.show_bounds
+    }
+
+    /// Draws the bounding box and sphere of every instance of the mesh with the debug lines.
+    pub fn set_show_bounds(&mut self, show_bounds: bool) {
+        self.show_bounds = show_bounds;
+    }
+
+    /// Replaces the environment drawn behind the scene and lighting it.
+    pub fn set_skybox(&mut self, data: &CubeMapData) {
+        self.skybox
+            .set_cube_map(&self.device, &self.queue, data, &mut self.mipmap_generator);
+        self.lighting
+            .ibl()
+            .prefilter(&self.device, &self.queue, &self.skybox);
+    }
+
+    /// Grades the tonemapped image through `lut`, or stops grading it if `None`.
+    pub fn set_lut(&mut self, lut: Option<&LutData>) {
+        if let Some(lut) = lut {
+            if let Some(color_grading) = self.post_process.effect_mut::<ColorGrading>() {
+                color_grading.set_lut(&self.device, &self.queue, lut);
+            }
+        }
+        if let Some(index) = self.post_process.position::<ColorGrading>() {
+            self.post_process.set_enabled(index, lut.is_some());
+        }
+    }
+
+    pub fn vsync(&self) -> bool {
+        matches!(
+            self.surface_config.present_mode,
+            wgpu::PresentMode::AutoVsync | wgpu::PresentMode::Fifo | wgpu::PresentMode::FifoRelaxed
+        )
+    }
+
+    /// Switches between a vsynced and an uncapped present mode, reconfiguring the surface.
+    pub fn set_vsync(&mut self, vsync: bool) {
+        self.set_present_mode(if vsync {
+            wgpu::PresentMode::AutoVsync
+        } else {
+            wgpu::PresentMode::AutoNoVsync
+        });
+    }
+
+    pub fn present_mode(&self) -> wgpu::PresentMode {
+        self.surface_config.present_mode
+    }
+
+    /// The present modes supported by the surface, always including the automatic ones. Empty
+    /// when rendering headless.
+    pub fn present_modes(&self) -> &[wgpu::PresentMode] {
+        &self.present_modes
+    }
+
+    /// Switches the present mode, reconfiguring the surface. Unsupported modes are ignored.
+    pub fn set_present_mode(&mut self, present_mode: wgpu::PresentMode) {
+        if !self.present_modes.contains(&present_mode) {
+            return;
+        }
+        self.surface_config.present_mode = present_mode;
+        self.reconfigure();
+    }
+
+    pub fn wireframe(&self) -> bool {
+        self.wireframe
+    }
+
+    /// Draws meshes as lines instead of filled triangles. Has no effect when the device doesn't
+    /// support line polygon mode.
+    pub fn set_wireframe(&mut self, wireframe: bool) {
+        if wireframe
+            && !self
+                .device
+                .features()
+                .contains(wgpu::Features::POLYGON_MODE_LINE)
+        {
+            eprintln!("wireframe rendering is not supported by this device");
+            return;
+        }
+        self.wireframe = wireframe;
+    }
+
+    /// Returns the pipeline for `key`, starting to compile it in the background if it isn't
+    /// ready yet. Headless renderers compile it on the spot instead, as every frame they render
+    /// is kept and must draw everything.
+    fn request_mesh_pipeline(
+        &mut self,
+        key: MeshPipelineKey,
+        layout: Arc<wgpu::PipelineLayout>,
+        shader_module: Arc<wgpu::ShaderModule>,
+    ) -> Option<Arc<wgpu::RenderPipeline>> {
+        let build = mesh_pipeline_builder(layout, shader_module, key);
+        if matches!(self.target, RenderTarget::Offscreen(_)) {
+            return Some(self.pipelines.get(&key, &*build));
+        }
+        self.pipelines.request(&key, build)
+    }
+
+    /// Returns the mesh pipeline for the current shader, see [`Renderer::request_mesh_pipeline`].
+    fn request_pipeline(
+        &mut self,
+        sample_count: u32,
+        polygon_mode: wgpu::PolygonMode,
+        double_sided: bool,
+        alpha_mode: AlphaMode,
+    ) -> Option<Arc<wgpu::RenderPipeline>> {
+        let key = MeshPipelineKey {
+            format: HDR_FORMAT,
+            sample_count,
+            polygon_mode,
+            double_sided,
+            alpha_mode,
+            vertex_layout: self.mesh.vertex_layout(),
+            terrain: false,
+            shader_generation: self.shader_generation,
+        };
+        self.request_mesh_pipeline(
+            key,
+            self.pipeline_layout.clone(),
+            self.shader_module.clone(),
+        )
+    }
+
+    /// The pipelines for the single- and double-sided materials with `alpha_mode`, or `None` if
+    /// there are none or the single-sided pipeline isn't ready yet. Until then double-sided
+    /// materials are culled.
+    fn request_material_pipelines(
+        &mut self,
+        polygon_mode: wgpu::PolygonMode,
+        alpha_mode: AlphaMode,
+    ) -> Option<(Arc<wgpu::RenderPipeline>, Arc<wgpu::RenderPipeline>)> {
+        let mut materials = self
+            .materials
+            .iter()
+            .filter(|material| material.alpha_mode() == alpha_mode)
+            .peekable();
+        materials.peek()?;
+        let double_sided = materials.any(|material| material.double_sided());
+        let single_sided =
+            self.request_pipeline(self.sample_count, polygon_mode, false, alpha_mode);
+        let double_sided = double_sided
+            .then(|| self.request_pipeline(self.sample_count, polygon_mode, true, alpha_mode))
+            .flatten();
+        single_sided.map(|single_sided| {
+            let double_sided = double_sided.unwrap_or_else(|| single_sided.clone());
+            (single_sided, double_sided)
+        })
+    }
+
+    /// The pipelines for the single- and double-sided opaque materials of the skinned meshes,
+    /// or `None` if there are none or the single-sided pipeline isn't ready yet, see
+    /// [`Renderer::request_mesh_pipeline`]. Meshes skinned by the compute shader share the
+    /// pipelines of the mesh.
+    fn request_skinned_pipelines(
+        &mut self,
+        polygon_mode: wgpu::PolygonMode,
+    ) -> Option<(Arc<wgpu::RenderPipeline>, Arc<wgpu::RenderPipeline>)> {
+        if self.skinned_meshes.is_empty() {
+            return None;
+        }
+        let double_sided = self
+            .skinned_meshes
+            .iter()
+            .flat_map(SkinnedMesh::materials)
+            .any(|material| material.double_sided());
+        let (vertex_layout, pipeline_layout, shader_module) = if self.skinning.computes() {
+            (
+                Vertex::vertex_layout(),
+                self.pipeline_layout.clone(),
+                self.shader_module.clone(),
+            )
+        } else {
+            (
+                SkinnedVertex::vertex_layout(),
+                self.skinned_pipeline_layout.clone(),
+                self.skinned_shader_module.clone(),
+            )
+        };
+        let mut request = |double_sided| {
+            let key = MeshPipelineKey {
+                format: HDR_FORMAT,
+                sample_count: self.sample_count,
+                polygon_mode,
+                double_sided,
+                alpha_mode: AlphaMode::Opaque,
+                vertex_layout,
+                terrain: false,
+                shader_generation: self.shader_generation,
+            };
+            self.request_mesh_pipeline(key, pipeline_layout.clone(), shader_module.clone())
+        };
+        let single_sided = request(false)?;
+        let double_sided = double_sided
+            .then(|| request(true))
+            .flatten()
+            .unwrap_or_else(|| single_sided.clone());
+        Some((single_sided, double_sided))
+    }
+
+    /// The pipeline splatting the terrain's layers, or `None` if there is no terrain or the
+    /// pipeline isn't ready yet, see [`Renderer::request_mesh_pipeline`].
+    fn request_terrain_pipeline(
+        &mut self,
+        polygon_mode: wgpu::PolygonMode,
+    ) -> Option<Arc<wgpu::RenderPipeline>> {
+        self.terrain.as_ref()?;
+        let key = MeshPipelineKey {
+            format: HDR_FORMAT,
+            sample_count: self.sample_count,
+            polygon_mode,
+            double_sided: false,
+            alpha_mode: AlphaMode::Opaque,
+            vertex_layout: Vertex::vertex_layout(),
+            terrain: true,
+            shader_generation: self.shader_generation,
+        };
+        self.request_mesh_pipeline(
+            key,
+            self.terrain_pipeline_layout.clone(),
+            self.terrain_shader_module.clone(),
+        )
+    }
+
+    /// Switches to the pending sample count once its pipeline has been compiled.
+    fn update_pipeline(&mut self) {
+        self.pipelines.poll();
+        let Some(sample_count) = self.pending_sample_count else {
+            return;
+        };
+        if let Some(pipeline) = self.request_pipeline(
+            sample_count,
+            wgpu::PolygonMode::Fill,
+            false,
+            AlphaMode::Opaque,
+        ) {
+            self.pending_sample_count = None;
+            self.sample_count = sample_count;
+            self.pipeline = pipeline;
+            self.recreate_render_targets();
+        }
+    }
+
+    /// Recreates the targets that aren't transients of the render graph, which follows the size
+    /// of the surface and the sample count by itself.
+    fn recreate_render_targets(&mut self) {
+        self.post_process.resize(&self.device, &self.surface_config);
+        self.prepass.resize(&self.device, &self.surface_config);
+        self.picking.resize(&self.device, &self.surface_config);
+        self.ssao
+            .resize(&self.device, &self.surface_config, &self.prepass);
+        self.lighting
+            .set_ambient_occlusion(&self.device, self.ssao.view());
+    }
+
+    /// Replaces the drawn mesh. Without `indices` the vertices are drawn as a plain triangle list.
+    pub fn set_geometry(&mut self, vertices: &[Vertex], indices: Option<&[u32]>) {
+        self.mesh = Mesh::from_vertices(
+            &self.mesh_allocator,
+            &self.device,
+            &self.queue,
+            vertices,
+            indices,
+        );
+        self.lod.set_levels(Vec::new());
+        self.scene_bounds = transformed_bounds(self.mesh.bounds().aabb, &self.instances);
+    }
+
+    /// The drawn mesh.
+    pub fn mesh(&self) -> &Mesh {
+        &self.mesh
+    }
+
+    pub fn instances(&self) -> &[Instance] {
+        &self.instances
+    }
+
+    /// Skips the instances outside the camera's view in the passes drawing from it.
+    pub fn culling(&self) -> &FrustumCulling {
+        &self.culling
+    }
+
+    pub fn culling_mut(&mut self) -> &mut FrustumCulling {
+        &mut self.culling
+    }
+
+    /// Skips the instances hidden behind others, found with occlusion queries in earlier
+    /// frames. Only applies to culling on the CPU.
+    pub fn occlusion(&self) -> &OcclusionCulling {
+        &self.occlusion
+    }
+
+    pub fn occlusion_mut(&mut self) -> &mut OcclusionCulling {
+        &mut self.occlusion
+    }
+
+    /// Picks coarser versions of the mesh for instances covering less of the screen. Only
+    /// applies to culling on the CPU.
+    pub fn lod(&self) -> &Lod {
+        &self.lod
+    }
+
+    pub fn lod_mut(&mut self) -> &mut Lod {
+        &mut self.lod
+    }
+
+    /// The buffers meshes are allocated from.
+    pub fn mesh_allocator(&self) -> &MeshAllocator {
+        &self.mesh_allocator
+    }
+
+    /// Replays the scene's draws from a render bundle, recorded again whenever the mesh,
+    /// instances, materials or pipelines change. Only used while the draws don't change every
+    /// frame: with culling disabled or done on the GPU, and without levels of detail.
+    /// The material textures bound all at once, if binding arrays are supported.
+    pub fn bindless(&self) -> Option<&BindlessTextures> {
+        self.bindless.as_ref()
+    }
+
+    pub fn static_bundles(&self) -> &StaticBundles {
+        &self.static_bundles
+    }
+
+    pub fn static_bundles_mut(&mut self) -> &mut StaticBundles {
+        &mut self.static_bundles
+    }
+
+    /// Replaces the coarser versions of the mesh, each given with the screen coverage below
+    /// which it is drawn, see [`LodLevel::coverage`]. They share the mesh's materials.
+    pub fn set_lods(&mut self, levels: &[(MeshData, f32)]) {
+        self.lod.set_levels(
+            levels
+                .iter()
+                .map(|(mesh, coverage)| LodLevel {
+                    mesh: Mesh::from_data(&self.mesh_allocator, &self.device, &self.queue, mesh),
+                    coverage: *coverage,
+                })
+                .collect(),
+        );
+    }
+
+    /// Culls on the GPU instead of [`Renderer::culling`] while enabled, `None` where compute
+    /// shaders aren't supported.
+    pub fn gpu_culling(&self) -> Option<&GpuCulling> {
+        self.gpu_culling.as_ref()
+    }
+
+    pub fn gpu_culling_mut(&mut self) -> Option<&mut GpuCulling> {
+        self.gpu_culling.as_mut()
+    }
+
+    /// Particles emitted and moved by compute shaders, disabled by default, `None` where
+    /// they aren't supported.
+    pub fn particles(&self) -> Option<&GpuParticles> {
+        self.particles.as_ref()
+    }
+
+    pub fn particles_mut(&mut self) -> Option<&mut GpuParticles> {
+        self.particles.as_mut()
+    }
+
+    /// Particles simulated on the CPU, disabled by default.
+    pub fn cpu_particles(&self) -> &CpuParticles {
+        &self.cpu_particles
+    }
+
+    pub fn cpu_particles_mut(&mut self) -> &mut CpuParticles {
+        &mut self.cpu_particles
+    }
+
+    /// Text placed in the world, drawn with the next frame only once a font is set.
+    pub fn msdf_text(&self) -> &MsdfText {
+        &self.msdf_text
+    }
+
+    pub fn msdf_text_mut(&mut self) -> &mut MsdfText {
+        &mut self.msdf_text
+    }
+
+    /// Sprites drawn with the next frame only, over the 3D scene and under the overlay.
+    pub fn sprites(&self) -> &SpriteBatch {
+        &self.sprites
+    }
+
+    pub fn sprites_mut(&mut self) -> &mut SpriteBatch {
+        &mut self.sprites
+    }
+
+    /// Vector shapes in screen pixels drawn with the next frame only, over the sprites.
+    pub fn painter(&self) -> &Painter {
+        &self.painter
+    }
+
+    pub fn painter_mut(&mut self) -> &mut Painter {
+        &mut self.painter
+    }
+
+    /// The view sprites and the tile map are drawn through.
+    pub fn camera_2d(&self) -> &Camera2D {
+        &self.camera_2d
+    }
+
+    pub fn camera_2d_mut(&mut self) -> &mut Camera2D {
+        &mut self.camera_2d
+    }
+
+    pub fn tilemap(&self) -> Option<&TileMap> {
+        self.tilemap.as_ref()
+    }
+
+    /// Replaces the tile map drawn under the sprites, loading its tilesets.
+    pub fn set_tilemap(&mut self, data: Option<TileMapData>) -> anyhow::Result<()> {
+        self.tilemap = data
+            .map(|data| TileMap::new(&self.device, &self.queue, data, &self.sprites))
+            .transpose()?;
+        Ok(())
+    }
+
+    /// The vector graphics drawn through the 2D camera, in the order they were added.
+    pub fn svgs_mut(&mut self) -> &mut Vec<Svg> {
+        &mut self.svgs
+    }
+
+    /// Uploads an SVG to be drawn through the 2D camera, `transform` placing its units in the
+    /// camera's world.
+    pub fn add_svg(&mut self, data: &SvgData, transform: Mat4) {
+        let svg = Svg::new(&self.device, &self.queue, data, transform, &self.sprites);
+        self.svgs.push(svg);
+    }
+
+    /// The skinned meshes drawn along with the mesh, in the order they were added.
+    pub fn skinned_meshes_mut(&mut self) -> &mut Vec<SkinnedMesh> {
+        &mut self.skinned_meshes
+    }
+
+    /// Uploads a skinned mesh with its materials, placed at `transform` and playing its first
+    /// animation. Only its opaque materials are drawn.
+    pub fn add_skinned_mesh(&mut self, data: SkinnedMeshData, transform: Mat4) {
+        let materials = self.load_materials(&data.mesh.materials);
+        let mut skinned = SkinnedMesh::new(
+            &self.mesh_allocator,
+            &self.device,
+            &self.queue,
+            &self.skinning,
+            self.camera_uniform.buffer(),
+            data,
+            materials,
+        );
+        skinned.transform = transform;
+        self.skinned_meshes.push(skinned);
+    }
+
+    pub fn terrain(&self) -> Option<&Terrain> {
+        self.terrain.as_ref()
+    }
+
+    /// The terrain, e.g. to tune its level of detail.
+    pub fn terrain_mut(&mut self) -> Option<&mut Terrain> {
+        self.terrain.as_mut()
+    }
+
+    /// Replaces the terrain drawn along with the mesh, uploading its layers. Its chunks are
+    /// meshed as the camera comes near them.
+    pub fn set_terrain(&mut self, data: Option<TerrainData>) {
+        self.terrain = data.map(|data| {
+            Terrain::new(
+                &self.device,
+                &self.queue,
+                &self.terrain_bind_group_layout,
+                data,
+                &mut self.mipmap_generator,
+            )
+        });
+    }
+
+    /// Uploads the MSDF atlas world-space text is drawn with, see [`MsdfText::set_font`].
+    pub fn set_msdf_font(&mut self, font: crate::msdf::MsdfFontData) {
+        self.msdf_text.set_font(&self.device, &self.queue, font);
+    }
+
+    /// Whether frustum culling is enabled and done on the GPU, which doesn't report how many
+    /// instances it culled.
+    pub fn culls_on_gpu(&self) -> bool {
+        self.culling.enabled() && self.gpu_culling.as_ref().is_some_and(GpuCulling::enabled)
+    }
+
+    /// Replaces the instances of the mesh, all drawn with a single draw call. Instances keep
+    /// their index from one call to the next, the motion vectors of the next frame following
+    /// each from its previous transform.
+    pub fn set_instances(&mut self, instances: &[Instance]) {
+        if instances.len() == self.instances.len() {
+            self.instance_data = instance_data(instances, &self.instances);
+            self.queue.write_buffer(
+                &self.instance_buffer,
+                0,
+                bytemuck::cast_slice(&self.instance_data),
+            );
+        } else {
+            self.instance_data = instance_data(instances, &[]);
+            self.instance_buffer = create_instance_buffer(
+                &self.device,
+                &self.instance_data,
+                self.gpu_culling.is_some(),
+            );
+        }
+        self.instance_count = instances.len() as u32;
+        self.instances = instances.to_vec();
+        self.instances_moved = true;
+        self.scene_bounds = transformed_bounds(self.mesh.bounds().aabb, &self.instances);
+    }
+
+    pub fn shadows(&self) -> bool {
+        self.lighting.shadow_map().enabled()
+    }
+
+    /// Shadows the directional light with cascaded shadow maps, rendered in a pass per cascade
+    /// each frame.
+    pub fn set_shadows(&mut self, shadows: bool) {
+        self.lighting.shadow_map_mut().set_enabled(shadows);
+    }
+
+    pub fn ssao(&self) -> &Ssao {
+        &self.ssao
+    }
+
+    pub fn ssao_mut(&mut self) -> &mut Ssao {
+        &mut self.ssao
+    }
+
+    pub fn shadow_map(&self) -> &ShadowMap {
+        self.lighting.shadow_map()
+    }
+
+    pub fn shadow_map_mut(&mut self) -> &mut ShadowMap {
+        self.lighting.shadow_map_mut()
+    }
+
+    pub fn camera(&self) -> &Camera {
+        &self.camera
+    }
+
+    pub fn camera_mut(&mut self) -> &mut Camera {
+        &mut self.camera
+    }
+
+    pub fn light(&self) -> &DirectionalLight {
+        &self.lighting.directional
+    }
+
+    /// Lines drawn into the scene with the next frame only.
+    pub fn debug_draw_mut(&mut self) -> &mut DebugDraw {
+        &mut self.debug_draw
+    }
+
+    /// Text queued for the next frame only.
+    pub fn text(&self) -> &TextRenderer {
+        &self.text
+    }
+
+    /// Text drawn with the next frame only, see [`TextRenderer::queue`].
+    pub fn text_mut(&mut self) -> &mut TextRenderer {
+        &mut self.text
+    }
+
+    /// The light the mesh is shaded with, uploaded with the next frame.
+    pub fn light_mut(&mut self) -> &mut DirectionalLight {
+        &mut self.lighting.directional
+    }
+
+    /// The point and spot lights.
+    pub fn lights(&self) -> &[Light] {
+        self.lighting.lights()
+    }
+
+    /// Replaces the point and spot lights, uploaded with the next frame. Only the first
+    /// [`MAX_LIGHTS`](crate::light::MAX_LIGHTS) are drawn.
+    pub fn set_lights(&mut self, lights: Vec<Light>) {
+        self.lighting.set_lights(lights);
+    }
+
+    /// Records the cursor position, in physical pixels, exposed to shaders through the globals.
+    pub fn set_cursor_position(&mut self, position: winit::dpi::PhysicalPosition<f64>) {
+        self.globals.value.cursor = [position.x as f32, position.y as f32];
+    }
+
+    /// Replaces the drawn mesh and its materials, issuing one draw per submesh.
+    pub fn set_mesh(&mut self, mesh: &MeshData) {
+        self.mesh = Mesh::from_data(&self.mesh_allocator, &self.device, &self.queue, mesh);
+        self.lod.set_levels(Vec::new());
+        self.scene_bounds = transformed_bounds(self.mesh.bounds().aabb, &self.instances);
+        self.materials = self.load_materials(&mesh.materials);
+        self.material_cache.trim();
+    }
+
+    /// Uploads `materials` through the material cache, sampling their textures anisotropically
+    /// where supported.
+    fn load_materials(&mut self, materials: &[MaterialData]) -> Vec<Arc<Material>> {
+        let sampler = SamplerDesc::Anisotropic {
+            address_mode: wgpu::AddressMode::Repeat,
+            max_anisotropy: 16,
+        }
+        .supported(&self.downlevel);
+        materials
+            .iter()
+            .map(|material| {
+                self.material_cache.get(
+                    &self.device,
+                    &self.queue,
+                    &self.material_bind_group_layout,
+                    material,
+                    sampler,
+                    Some(&mut self.mipmap_generator),
+                )
+            })
+            .collect()
+    }
+
+    /// Loads a PNG, JPEG, KTX2 or DDS texture of colours with a full mip chain, degrading
+    /// `sampler` to what the adapter supports.
+    pub fn load_texture(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+        sampler: SamplerDesc,
+    ) -> anyhow::Result<Texture> {
+        let path = path.as_ref();
+        let sampler = sampler.supported(&self.downlevel);
+        let compressed = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| {
+                extension.eq_ignore_ascii_case("ktx2") || extension.eq_ignore_ascii_case("dds")
+            });
+        if compressed {
+            Texture::from_compressed_path(
+                &self.device,
+                &self.queue,
+                path,
+                true,
+                sampler,
+                Some(&mut self.mipmap_generator),
+            )
+        } else {
+            Texture::from_path(
+                &self.device,
+                &self.queue,
+                path,
+                sampler,
+                Some(&mut self.mipmap_generator),
+            )
+        }
+    }
+
+    /// Replaces the base color texture of the default material.
+    pub fn set_texture(&mut self, texture: Texture) {
+        self.default_material = Material::new(
+            &self.device,
+            &self.queue,
+            &self.material_bind_group_layout,
+            None,
+            *self.default_material.factors(),
+            MaterialTextures {
+                base_color: Some(texture),
+                ..Default::default()
+            },
+        );
+    }
+
+    /// The distinct materials and textures of the mesh.
+    pub fn material_cache(&self) -> &MaterialCache {
+        &self.material_cache
+    }
+
+    /// The draws of alpha-blended materials, as sorted for the last frame.
+    pub fn transparent_queue(&self) -> &TransparentQueue {
+        &self.transparent
+    }
+
+    /// The material of draws without one of their own.
+    pub fn default_material(&self) -> &Material {
+        &self.default_material
+    }
+
+    pub fn set_default_material_factors(&mut self, factors: MaterialFactors) {
+        self.default_material.set_factors(factors);
+    }
+
+    pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
+        if new_size.width > 0 && new_size.height > 0 {
+            self.surface_config.width = new_size.width;
+            self.surface_config.height = new_size.height;
+            match &mut self.target {
+                RenderTarget::Surface(surface) => {
+                    surface.configure(&self.device, &self.surface_config);
+                }
+                RenderTarget::Suspended => {}
+                RenderTarget::Offscreen(texture) => {
+                    *texture = create_offscreen_texture(&self.device, &self.surface_config);
+                }
+            }
+            self.recreate_render_targets();
+            self.camera.aspect = new_size.width as f32 / new_size.height as f32;
+        }
+    }
+
+    /// Reconfigures the surface with the current configuration, e.g. after it was lost.
+    pub fn reconfigure(&mut self) {
+        if let RenderTarget::Surface(surface) = &self.target {
+            surface.configure(&self.device, &self.surface_config);
+        }
+    }
+
+    /// Drops the window surface, which has to happen when an Android application is suspended
+    /// and its native window destroyed. Nothing can be rendered until [`Renderer::resume`].
+    pub fn suspend(&mut self) {
+        if let RenderTarget::Surface(_) = self.target {
+            self.target = RenderTarget::Suspended;
+        }
+    }
+
+    /// Creates a new surface for `window` after [`Renderer::suspend`].
+    pub fn resume(&mut self, window: Arc<winit::window::Window>) -> anyhow::Result<()> {
+        if !self.is_suspended() {
+            return Ok(());
+        }
+        let size = window.inner_size();
+        let surface = self.instance.create_surface(window)?;
+        self.target = RenderTarget::Surface(surface);
+        self.resize(size);
+        self.reconfigure();
+        Ok(())
+    }
+
+    pub fn is_suspended(&self) -> bool {
+        matches!(self.target, RenderTarget::Suspended)
+    }
+
+    /// Recompiles the mesh shader if it or one of its includes changed on disk, swapping in the
+    /// new pipelines only when compilation succeeds.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn reload_shaders(&mut self) {
+        let Some(watcher) = &self.shader_watcher else {
+            return;
+        };
+        if watcher.changed_shaders().is_empty() {
+            return;
+        }
+
+        // The shader is validated before anything is created, pipeline creation errors are
+        // caught by the error scope.
+        let preprocessor = scene_preprocessor(
+            &self.lighting,
+            self.bindless.as_ref(),
+            Preprocessor::new().with_directory(SHADER_DIR),
+        );
+        let skinned_preprocessor = self.skinning.configure_preprocessor(preprocessor.clone());
+        let terrain_preprocessor = Terrain::configure_preprocessor(preprocessor.clone());
+        let shader_modules = shader::create_module(&self.device, &preprocessor, "shader.wgsl")
+            .and_then(|shader_module| {
+                let skinned_shader_module =
+                    shader::create_module(&self.device, &skinned_preprocessor, "shader.wgsl")?;
+                let terrain_shader_module =
+                    shader::create_module(&self.device, &terrain_preprocessor, "shader.wgsl")?;
+                Ok((
+                    Arc::new(shader_module),
+                    Arc::new(skinned_shader_module),
+                    Arc::new(terrain_shader_module),
+                ))
+            });
+        let (shader_module, skinned_shader_module, terrain_shader_module) = match shader_modules {
+            Ok(shader_modules) => shader_modules,
+            Err(err) => {
+                eprintln!("failed to reload shader.wgsl, keeping the previous shader: {err:#}");
+                return;
+            }
+        };
+        // The new pipeline is compiled right away so that errors can be caught. Every attempt gets
+        // a new generation, so a pipeline that failed to compile is never handed out again.
+        self.shader_generation += 1;
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let key = MeshPipelineKey {
+            format: HDR_FORMAT,
+            sample_count: self.sample_count,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            double_sided: false,
+            alpha_mode: AlphaMode::Opaque,
+            vertex_layout: self.mesh.vertex_layout(),
+            terrain: false,
+            shader_generation: self.shader_generation,
+        };
+        let pipeline = self.pipelines.get(
+            &key,
+            &*mesh_pipeline_builder(self.pipeline_layout.clone(), shader_module.clone(), key),
+        );
+        if let Some(err) = self.device.pop_error_scope().block_on() {
+            eprintln!("failed to reload shader.wgsl, keeping the previous shader:\n{err}");
+            return;
+        }
+
+        self.shader_module = shader_module;
+        self.skinned_shader_module = skinned_shader_module;
+        self.terrain_shader_module = terrain_shader_module;
+        self.pipeline = pipeline;
+        println!("reloaded shader.wgsl");
+    }
+
+    /// Draws the mesh's submeshes of materials with `alpha_mode` with `pipelines`, for single-
+    /// and double-sided materials, as the frame culls them: with the draws culled on the GPU,
+    /// the levels of detail picked on the CPU or the instances culled on the CPU.
+    fn draw_meshes(
+        &self,
+        render_pass: &mut wgpu::RenderPass,
+        pipelines: [&wgpu::RenderPipeline; 2],
+        alpha_mode: AlphaMode,
+        culls_on_gpu: bool,
+        draws_lods: bool,
+        multi_draw: bool,
+    ) {
+        let materials = self.bind_materials(render_pass, pipelines, alpha_mode);
+        let gpu_culled = self
+            .gpu_culling
+            .as_ref()
+            .and_then(GpuCulling::buffers)
+            .filter(|_| culls_on_gpu);
+        match gpu_culled {
+            Some((instances, args)) => {
+                self.mesh
+                    .draw_indirect(render_pass, instances, args, multi_draw, Some(materials))
+            }
+            None if draws_lods => self.lod.draw(render_pass, &self.mesh, Some(materials)),
+            None => {
+                let (instances, count) = self
+                    .culling
+                    .instances(&self.instance_buffer, self.instance_count);
+                self.mesh
+                    .draw(render_pass, instances, 0..count, Some(materials));
+            }
+        }
+    }
+
+    /// Draws the opaque materials of every skinned mesh in view with `pipelines`, for single-
+    /// and double-sided materials, binding each mesh's joint palette if skinned in the vertex
+    /// shader.
+    fn draw_skinned_meshes(
+        &self,
+        render_pass: &mut wgpu::RenderPass,
+        pipelines: [&wgpu::RenderPipeline; 2],
+    ) {
+        render_pass.set_pipeline(pipelines[0]);
+        let bindless = self
+            .bindless
+            .as_ref()
+            .and_then(BindlessTextures::bind_group);
+        render_pass.set_bind_group(
+            2,
+            bindless.unwrap_or(self.default_material.bind_group()),
+            &[],
+        );
+        render_pass.set_bind_group(3, self.lighting.bind_group(), &[]);
+        for &index in &self.visible_skinned_meshes {
+            let skinned = &self.skinned_meshes[index];
+            let offsets = &self.material_offsets[self.skinned_material_offsets[index]..];
+            render_pass.set_bind_group(
+                0,
+                &self.frame_bind_group,
+                &[offsets[skinned.materials().len()]],
+            );
+            render_pass.set_bind_group(
+                1,
+                skinned
+                    .bind_group()
+                    .unwrap_or(self.camera_uniform.bind_group()),
+                &[],
+            );
+            let materials = MaterialBindings {
+                materials: skinned.materials(),
+                fallback: &self.default_material,
+                pipelines,
+                frame: &self.frame_bind_group,
+                offsets,
+                bindless: bindless.is_some(),
+                alpha_mode: AlphaMode::Opaque,
+                only: None,
+            };
+            skinned.mesh().draw(
+                render_pass,
+                skinned.instance_buffer(),
+                0..1,
+                Some(materials),
+            );
+        }
+    }
+
+    /// Draws the terrain with `pipeline`, its layers bound in place of a material.
+    fn draw_terrain(&self, render_pass: &mut wgpu::RenderPass, pipeline: &wgpu::RenderPipeline) {
+        let Some(terrain) = &self.terrain else {
+            return;
+        };
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(
+            0,
+            &self.frame_bind_group,
+            &[self.material_offsets[self.materials.len()]],
+        );
+        render_pass.set_bind_group(1, self.camera_uniform.bind_group(), &[]);
+        render_pass.set_bind_group(2, terrain.bind_group(), &[]);
+        render_pass.set_bind_group(3, self.lighting.bind_group(), &[]);
+        terrain.draw(render_pass);
+    }
+
+    /// Binds what the mesh pipelines share, with the default material, and returns the bindings
+    /// of the materials with `alpha_mode` drawn with `pipelines`.
+    fn bind_materials<'r>(
+        &'r self,
+        render_pass: &mut wgpu::RenderPass,
+        pipelines: [&'r wgpu::RenderPipeline; 2],
+        alpha_mode: AlphaMode,
+    ) -> MaterialBindings<'r> {
+        render_pass.set_pipeline(pipelines[0]);
+        render_pass.set_bind_group(
+            0,
+            &self.frame_bind_group,
+            &[self.material_offsets[self.materials.len()]],
+        );
+        render_pass.set_bind_group(1, self.camera_uniform.bind_group(), &[]);
+        let bindless = self
+            .bindless
+            .as_ref()
+            .and_then(BindlessTextures::bind_group);
+        render_pass.set_bind_group(
+            2,
+            bindless.unwrap_or(self.default_material.bind_group()),
+            &[],
+        );
+        render_pass.set_bind_group(3, self.lighting.bind_group(), &[]);
+        MaterialBindings {
+            materials: &self.materials,
+            fallback: &self.default_material,
+            pipelines,
+            frame: &self.frame_bind_group,
+            offsets: &self.material_offsets,
+            bindless: bindless.is_some(),
+            alpha_mode,
+            only: None,
+        }
+    }
+
+    pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+        self.render_with(|_| {})
+    }
+
+    /// Renders a frame, letting `overlay` record passes drawn on top of it into the swapchain
+    /// view before the frame is presented.
+    pub fn render_with(
+        &mut self,
+        overlay: impl FnOnce(OverlayContext),
+    ) -> Result<(), wgpu::SurfaceError> {
+        #[cfg(not(target_arch = "wasm32"))]
+        self.reload_shaders();
+        self.update_pipeline();
+        self.device.poll(wgpu::Maintain::Poll);
+        if let Some(gpu_time) = self.gpu_time_receiver.try_iter().last() {
+            self.gpu_time = Some(gpu_time);
+        }
+        if let Some(profiler) = &mut self.profiler {
+            profiler.begin_frame();
+        }
+        let polygon_mode = if self.wireframe {
+            wgpu::PolygonMode::Line
+        } else {
+            wgpu::PolygonMode::Fill
+        };
+        // The wireframe pipeline is compiled on first use, until then meshes are drawn filled.
+        let pipeline = if self.wireframe {
+            self.request_pipeline(self.sample_count, polygon_mode, false, AlphaMode::Opaque)
+                .unwrap_or_else(|| self.pipeline.clone())
+        } else {
+            self.pipeline.clone()
+        };
+        // Likewise double-sided materials are culled until their pipeline is ready.
+        let double_sided_pipeline = if self
+            .materials
+            .iter()
+            .any(|material| material.double_sided())
+        {
+            self.request_pipeline(self.sample_count, polygon_mode, true, AlphaMode::Opaque)
+                .unwrap_or_else(|| pipeline.clone())
+        } else {
+            pipeline.clone()
+        };
+        // Transparent materials are left out until their pipelines are ready, as there is no
+        // other pipeline to draw them with.
+        let blend_pipelines = self.request_material_pipelines(polygon_mode, AlphaMode::Blend);
+        let oit_pipelines =
+            self.request_material_pipelines(polygon_mode, AlphaMode::WeightedBlended);
+        // Skinned meshes wait for their pipelines too.
+        let skinned_pipelines = self.request_skinned_pipelines(polygon_mode);
+        let terrain_pipeline = self.request_terrain_pipeline(polygon_mode);
+
+        let acquire_start = Instant::now();
+        let output = self.target.acquire()?;
+        self.acquire_time = acquire_start.elapsed();
+        // Created first so that dynamic data is uploaded through it, see `Uploader`.
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+
+        let now = Instant::now();
+        let globals = &mut self.globals.value;
+        globals.resolution = [
+            self.surface_config.width as f32,
+            self.surface_config.height as f32,
+        ];
+        globals.time = (now - self.start_time).as_secs_f32();
+        globals.delta_time = (now - self.last_frame).as_secs_f32();
+        self.last_frame = now;
+        self.globals.update(&self.queue);
+        self.material_uniforms.clear();
+        self.material_offsets.clear();
+        if let Some(bindless) = &mut self.bindless {
+            let materials: Vec<&Material> = std::iter::once(&self.default_material)
+                .chain(self.materials.iter().map(|material| &**material))
+                .chain(
+                    self.skinned_meshes
+                        .iter()
+                        .flat_map(SkinnedMesh::materials)
+                        .map(|material| &**material),
+                )
+                .collect();
+            bindless.update(&self.device, &materials);
+        }
+        // In the bindless slots the default material comes first, but its factors come last.
+        let texture_index = |slot| {
+            self.bindless
+                .as_ref()
+                .map_or(0, |bindless| bindless.texture_index(slot))
+        };
+        for (index, material) in self.materials.iter().enumerate() {
+            let uniform = material
+                .uniform()
+                .with_texture_index(texture_index(index + 1));
+            self.material_offsets
+                .push(self.material_uniforms.push(&uniform));
+        }
+        let uniform = self
+            .default_material
+            .uniform()
+            .with_texture_index(texture_index(0));
+        self.material_offsets
+            .push(self.material_uniforms.push(&uniform));
+        // Followed by the materials of each skinned mesh, with the default one as their fallback.
+        self.skinned_material_offsets.clear();
+        let mut slot = self.materials.len() + 1;
+        for skinned in &self.skinned_meshes {
+            self.skinned_material_offsets
+                .push(self.material_offsets.len());
+            for material in skinned.materials() {
+                let uniform = material.uniform().with_texture_index(texture_index(slot));
+                self.material_offsets
+                    .push(self.material_uniforms.push(&uniform));
+                slot += 1;
+            }
+            self.material_offsets
+                .push(self.material_offsets[self.materials.len()]);
+        }
+        if self
+            .material_uniforms
+            .upload(&mut self.uploader.begin(&self.device, &mut encoder))
+        {
+            self.frame_bind_group = create_frame_bind_group(
+                &self.device,
+                &self.frame_bind_group_layout,
+                &self.globals,
+                &self.material_uniforms,
+            );
+        }
+        let view_proj = self.camera.view_projection_matrix();
+        let jitter = if self.taa() {
+            // From pixels to normalized device coordinates, which span two units.
+            taa::jitter(self.frame_index) * 2.0
+                / glam::Vec2::new(
+                    self.surface_config.width as f32,
+                    self.surface_config.height as f32,
+                )
+        } else {
+            glam::Vec2::ZERO
+        };
+        self.frame_index = self.frame_index.wrapping_add(1);
+        self.camera_uniform.value = CameraUniform {
+            previous_view_proj: self
+                .previous_view_proj
+                .replace(view_proj)
+                .unwrap_or(view_proj)
+                .to_cols_array_2d(),
+            jitter: jitter.to_array(),
+            ..CameraUniform::from(&self.camera)
+        };
+        self.camera_uniform.update(&self.queue);
+        for skinned in &mut self.skinned_meshes {
+            skinned.update(&self.queue, self.globals.value.delta_time);
+        }
+        // Culled by their bounds in the pose just updated.
+        let frustum = Frustum::from_view_projection(view_proj);
+        self.visible_skinned_meshes.clear();
+        self.visible_skinned_meshes.extend(
+            self.skinned_meshes
+                .iter()
+                .enumerate()
+                .filter(|(_, skinned)| {
+                    !self.culling.enabled()
+                        || frustum.intersects_aabb(&skinned.bounds().transformed(skinned.transform))
+                })
+                .map(|(index, _)| index),
+        );
+        if let Some(terrain) = &mut self.terrain {
+            terrain.update(
+                &self.mesh_allocator,
+                &self.device,
+                &self.queue,
+                self.camera.eye,
+                self.culling.enabled().then_some(&frustum),
+            );
+        }
+        let draws_text = !self.text.is_empty();
+        self.text.prepare(
+            &self.device,
+            &self.queue,
+            view_proj,
+            self.surface_config.width,
+            self.surface_config.height,
+        );
+        self.occlusion.poll(&self.device);
+        let culls_on_gpu = self.culls_on_gpu();
+        let multi_draw = self
+            .gpu_culling
+            .as_ref()
+            .is_some_and(GpuCulling::multi_draw);
+        match &mut self.gpu_culling {
+            Some(gpu_culling) if culls_on_gpu => gpu_culling.prepare(
+                &self.device,
+                &self.queue,
+                view_proj,
+                &self.mesh,
+                &self.instance_buffer,
+                self.instance_count,
+            ),
+            _ => self.culling.update(
+                &mut self.uploader.begin(&self.device, &mut encoder),
+                view_proj,
+                &self.mesh.bounds(),
+                &self.instance_data,
+                self.occlusion.occluded(),
+            ),
+        }
+        // The instances to query come from culling on the CPU.
+        let in_frustum = if culls_on_gpu {
+            &[]
+        } else {
+            self.culling.in_frustum()
+        };
+        self.occlusion.prepare(
+            &self.queue,
+            &self.mesh.bounds().aabb,
+            self.camera.eye,
+            &self.instance_data,
+            in_frustum,
+        );
+        let draws_lods = !culls_on_gpu && !self.lod.is_empty();
+        if draws_lods {
+            self.lod.update(
+                &self.camera,
+                &self.mesh.bounds().sphere,
+                &self.instance_data,
+                self.culling.drawn(),
+                Duration::from_secs_f32(self.globals.value.delta_time),
+            );
+            self.lod
+                .upload(&mut self.uploader.begin(&self.device, &mut encoder));
+        }
+        // The GPU culls the draws of opaque materials only.
+        let visible = (!culls_on_gpu).then(|| self.culling.drawn());
+        self.transparent.update(
+            &self.camera,
+            &self.mesh,
+            &self.materials,
+            &self.default_material,
+            &self.instances,
+            visible,
+        );
+        self.skybox.update(&self.queue, &self.camera);
+        let particles_pipeline = match &mut self.particles {
+            Some(particles) if particles.enabled() => {
+                particles.update(&self.queue, self.globals.value.delta_time, &self.camera);
+                let key = ParticlePipelineKey {
+                    sample_count: self.sample_count,
+                    blend: particles.blend(),
+                };
+                Some(self.pipelines.get(&key, &*particles.pipeline_builder(key)))
+            }
+            _ => None,
+        };
+        let cpu_particles_pipeline = self.cpu_particles.enabled().then(|| {
+            self.cpu_particles
+                .update(self.globals.value.delta_time, &self.camera);
+            self.cpu_particles
+                .upload(&mut self.uploader.begin(&self.device, &mut encoder));
+            let key = CpuParticlePipelineKey {
+                format: HDR_FORMAT,
+                sample_count: self.sample_count,
+                blend: self.cpu_particles.blend,
+            };
+            self.pipelines
+                .get(&key, &*self.cpu_particles.pipeline_builder(key))
+        });
+        let msdf_pipeline = (!self.msdf_text.is_empty()).then(|| {
+            let key = MsdfPipelineKey {
+                format: HDR_FORMAT,
+                sample_count: self.sample_count,
+            };
+            self.pipelines
+                .get(&key, &*self.msdf_text.pipeline_builder(key))
+        });
+        self.msdf_text.upload(
+            &mut self.uploader.begin(&self.device, &mut encoder),
+            &self.camera,
+        );
+        let sprite_pipeline = (!self.sprites.is_empty()
+            || !self.painter.is_empty()
+            || self.tilemap.is_some()
+            || !self.svgs.is_empty())
+        .then(|| {
+            let key = SpritePipelineKey {
+                format: self.surface_config.format,
+            };
+            self.pipelines
+                .get(&key, &*self.sprites.pipeline_builder(key))
+        });
+        self.camera_2d.update(self.globals.value.delta_time);
+        let sprite_projection = self.camera_2d.view_projection(
+            self.surface_config.width as f32,
+            self.surface_config.height as f32,
+        );
+        if let Some(tilemap) = &mut self.tilemap {
+            tilemap.update(
+                &self.queue,
+                self.globals.value.delta_time,
+                sprite_projection,
+            );
+        }
+        for svg in &self.svgs {
+            svg.update(&self.queue, sprite_projection);
+        }
+        self.sprites.upload(
+            &mut self.uploader.begin(&self.device, &mut encoder),
+            sprite_projection,
+        );
+        self.painter.upload(
+            &mut self.uploader.begin(&self.device, &mut encoder),
+            sprite::pixel_projection(
+                self.surface_config.width as f32,
+                self.surface_config.height as f32,
+            ),
+        );
+        // Skinned meshes cast shadows too where they're drawn into the shadow maps, and so does
+        // the terrain.
+        let scene_bounds = self
+            .skinned_meshes
+            .iter()
+            .filter(|_| self.skinning.computes())
+            .filter(|skinned| !skinned.bounds().is_empty())
+            .map(|skinned| skinned.bounds().transformed(skinned.transform))
+            .chain(self.terrain.as_ref().map(Terrain::bounds))
+            .fold(self.scene_bounds, |(min, max), bounds| {
+                (min.min(bounds.min), max.max(bounds.max))
+            });
+        self.lighting
+            .update(&self.queue, &self.camera, scene_bounds);
+        self.ssao.update(&self.queue, &self.camera);
+        if let Some(depth_of_field) = self.post_process.effect_mut::<DepthOfField>() {
+            depth_of_field.set_camera(&self.camera);
+        }
+        if let Some(lens_effects) = self.post_process.effect_mut::<LensEffects>() {
+            lens_effects.set_time(self.globals.value.time);
+        }
+        self.post_process.update(&self.queue);
+
+        let view = output
+            .texture()
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        let skybox_pipeline = self.show_skybox.then(|| {
+            let key = SkyboxPipelineKey {
+                format: HDR_FORMAT,
+                sample_count: self.sample_count,
+            };
+            self.pipelines
+                .get(&key, &*self.skybox.pipeline_builder(key))
+        });
+        if self.show_bounds {
+            let bounds = self.mesh.bounds();
+            if !bounds.aabb.is_empty() {
+                for instance in &self.instances {
+                    let sphere = bounds.sphere.transformed(instance.transform);
+                    self.debug_draw.obb(
+                        &Obb::from_aabb(&bounds.aabb, instance.transform),
+                        Vec3::new(1.0, 0.8, 0.0),
+                    );
+                    self.debug_draw
+                        .sphere(sphere.center, sphere.radius, Vec3::new(0.0, 0.8, 1.0));
+                }
+            }
+        }
+        let debug_draw_pipeline = (!self.debug_draw.is_empty()).then(|| {
+            let key = DebugDrawPipelineKey {
+                format: HDR_FORMAT,
+                sample_count: self.sample_count,
+            };
+            self.pipelines
+                .get(&key, &*self.debug_draw.pipeline_builder(key))
+        });
+        self.debug_draw.upload(
+            &mut self.uploader.begin(&self.device, &mut encoder),
+            Vec2::new(
+                self.surface_config.width as f32,
+                self.surface_config.height as f32,
+            ),
+        );
+        let oit_composite_pipeline = oit_pipelines.is_some().then(|| {
+            let key = EffectPipelineKey {
+                effect: "oit composite",
+                format: HDR_FORMAT,
+            };
+            self.pipelines
+                .get(&key, &*self.oit.pipeline_builder(HDR_FORMAT))
+        });
+        let occlusion_pipeline = self.occlusion.query_set().is_some().then(|| {
+            let key = OcclusionPipelineKey {
+                sample_count: self.sample_count,
+            };
+            self.pipelines
+                .get(&key, &*self.occlusion.pipeline_builder(key))
+        });
+        // The GPU culls into the same buffers every frame, and without culling every instance is
+        // drawn, so in both cases the draws only change along with what they read.
+        let bundles_scene = self.static_bundles.enabled()
+            && !draws_lods
+            && (culls_on_gpu || !self.culling.enabled());
+        let bindless = self
+            .bindless
+            .as_ref()
+            .and_then(BindlessTextures::bind_group);
+        if bundles_scene {
+            let material_textures = bindless.unwrap_or(self.default_material.bind_group());
+            let gpu_culled = self
+                .gpu_culling
+                .as_ref()
+                .and_then(GpuCulling::buffers)
+                .filter(|_| culls_on_gpu);
+            // What every group's draws use; each adds its pipeline and material.
+            let mut key = BundleKey::new()
+                .bind_group(&self.frame_bind_group)
+                .bind_group(self.camera_uniform.bind_group())
+                .bind_group(material_textures)
+                .bind_group(self.lighting.bind_group())
+                .buffer(self.mesh.vertex_buffer())
+                .value(self.mesh.id())
+                .value(self.instance_count.into())
+                .value(self.material_offsets[self.materials.len()].into());
+            key = match gpu_culled {
+                Some((instances, args)) => key.buffer(instances).buffer(args),
+                None => key.buffer(&self.instance_buffer),
+            };
+            let pipelines: [&wgpu::RenderPipeline; 2] = [&pipeline, &double_sided_pipeline];
+            let materials = MaterialBindings {
+                materials: &self.materials,
+                fallback: &self.default_material,
+                pipelines,
+                frame: &self.frame_bind_group,
+                offsets: &self.material_offsets,
+                bindless: bindless.is_some(),
+                alpha_mode: AlphaMode::Opaque,
+                only: None,
+            };
+            let groups = self
+                .mesh
+                .draw_materials(&materials)
+                .into_iter()
+                .map(|group| {
+                    let (material, offset) = materials.material(Some(group));
+                    let key = key
+                        .clone()
+                        .pipeline(pipelines[material.double_sided() as usize])
+                        .bind_group(material.bind_group())
+                        .value(offset.into());
+                    (group, key)
+                });
+            self.static_bundles.update(
+                &self.device,
+                self.sample_count,
+                groups,
+                |group, encoder| {
+                    encoder.set_pipeline(&pipeline);
+                    encoder.set_bind_group(
+                        0,
+                        &self.frame_bind_group,
+                        &[self.material_offsets[self.materials.len()]],
+                    );
+                    encoder.set_bind_group(1, self.camera_uniform.bind_group(), &[]);
+                    encoder.set_bind_group(2, material_textures, &[]);
+                    encoder.set_bind_group(3, self.lighting.bind_group(), &[]);
+                    let materials = MaterialBindings {
+                        only: Some(group),
+                        ..materials
+                    };
+                    match gpu_culled {
+                        Some((instances, args)) => {
+                            self.mesh.draw_indirect(
+                                encoder,
+                                instances,
+                                args,
+                                multi_draw,
+                                Some(materials),
+                            );
+                        }
+                        None => self.mesh.draw(
+                            encoder,
+                            &self.instance_buffer,
+                            0..self.instance_count,
+                            Some(materials),
+                        ),
+                    }
+                },
+            );
+        }
+        let mut readback = None;
+        let mut graph = RenderGraph::<Renderer>::new();
+        let swapchain = graph.import_view("swapchain", &view);
+        let shadows = graph.external("shadow maps");
+        let prepass = graph.external("prepass");
+        let ambient_occlusion = graph.external("ambient occlusion");
+        let hdr = graph.create_texture(
+            "hdr",
+            TextureDesc {
+                format: HDR_FORMAT,
+                sample_count: 1,
+            },
+        );
+        let motion = graph.create_texture(
+            "motion",
+            TextureDesc {
+                format: MOTION_FORMAT,
+                sample_count: 1,
+            },
+        );
+        let depth = graph.create_texture(
+            "depth",
+            TextureDesc {
+                format: Texture::DEPTH_FORMAT,
+                sample_count: self.sample_count,
+            },
+        );
+        // Multisampled targets resolved into `hdr` and `motion`.
+        let msaa = (self.sample_count > 1).then(|| {
+            let desc = |format| TextureDesc {
+                format,
+                sample_count: self.sample_count,
+            };
+            (
+                graph.create_texture("msaa", desc(HDR_FORMAT)),
+                graph.create_texture("msaa motion", desc(MOTION_FORMAT)),
+            )
+        });
+
+        let skinned = (self.skinning.computes() && !self.skinned_meshes.is_empty()).then(|| {
+            let skinned = graph.external("skinned vertices");
+            graph.add_pass("skinning", &[], &[skinned], |renderer, context| {
+                renderer.skinning.dispatch(
+                    context.encoder,
+                    renderer.profiler.as_mut(),
+                    &renderer.skinned_meshes,
+                );
+            });
+            skinned
+        });
+        if self.lighting.shadow_map().enabled() {
+            graph.add_pass(
+                "shadows",
+                skinned.as_slice(),
+                &[shadows],
+                |renderer, context| {
+                    // Instances outside the camera's view still cast shadows into it.
+                    let mut draw = |render_pass: &mut wgpu::RenderPass| {
+                        renderer.mesh.draw(
+                            render_pass,
+                            &renderer.instance_buffer,
+                            0..renderer.instance_count,
+                            None,
+                        );
+                        if renderer.skinning.computes() {
+                            for skinned in &renderer.skinned_meshes {
+                                skinned.mesh().draw(
+                                    render_pass,
+                                    skinned.instance_buffer(),
+                                    0..1,
+                                    None,
+                                );
+                            }
+                        }
+                        if let Some(terrain) = &renderer.terrain {
+                            terrain.draw_all(render_pass);
+                        }
+                    };
+                    renderer.lighting.shadow_map().render(
+                        context.encoder,
+                        renderer.profiler.as_mut(),
+                        &mut draw,
+                    );
+                    renderer.lighting.point_shadows().render(
+                        context.encoder,
+                        renderer.profiler.as_mut(),
+                        &mut draw,
+                    );
+                },
+            );
+        }
+        let culled = culls_on_gpu.then(|| {
+            let culled = graph.external("culled instances");
+            graph.add_pass("culling", &[], &[culled], |renderer, context| {
+                if let Some(gpu_culling) = &renderer.gpu_culling {
+                    gpu_culling.dispatch(context.encoder, renderer.profiler.as_mut());
+                }
+            });
+            culled
+        });
+        let simulated = particles_pipeline.is_some().then(|| {
+            let simulated = graph.external("particles");
+            graph.add_pass("particles", &[], &[simulated], |renderer, context| {
+                if let Some(particles) = &renderer.particles {
+                    particles.dispatch(context.encoder, renderer.profiler.as_mut());
+                }
+            });
+            simulated
+        });
+        // Culled unless SSAO or a post-processing effect reads it.
+        let prepass_reads: Vec<_> = culled.into_iter().chain(skinned).collect();
+        graph.add_pass(
+            "prepass",
+            &prepass_reads,
+            &[prepass],
+            |renderer, context| {
+                renderer.prepass.render(
+                    context.encoder,
+                    renderer.profiler.as_mut(),
+                    renderer.camera_uniform.bind_group(),
+                    |render_pass| {
+                        let gpu_culled = renderer
+                            .gpu_culling
+                            .as_ref()
+                            .and_then(GpuCulling::buffers)
+                            .filter(|_| culls_on_gpu);
+                        match gpu_culled {
+                            Some((instances, args)) => {
+                                renderer.mesh.draw_indirect(
+                                    render_pass,
+                                    instances,
+                                    args,
+                                    multi_draw,
+                                    None,
+                                );
+                            }
+                            None if draws_lods => {
+                                renderer.lod.draw(render_pass, &renderer.mesh, None);
+                            }
+                            None => {
+                                let (instances, count) = renderer
+                                    .culling
+                                    .instances(&renderer.instance_buffer, renderer.instance_count);
+                                renderer.mesh.draw(render_pass, instances, 0..count, None);
+                            }
+                        }
+                        if renderer.skinning.computes() {
+                            for &index in &renderer.visible_skinned_meshes {
+                                let skinned = &renderer.skinned_meshes[index];
+                                skinned.mesh().draw(
+                                    render_pass,
+                                    skinned.instance_buffer(),
+                                    0..1,
+                                    None,
+                                );
+                            }
+                        }
+                        if let Some(terrain) = &renderer.terrain {
+                            terrain.draw(render_pass);
+                        }
+                    },
+                );
+            },
+        );
+        if self.picking.wants_render() {
+            let picking = graph.external("picking");
+            graph.retain(picking);
+            graph.add_pass("picking", &[], &[picking], |renderer, context| {
+                renderer.picking.render(
+                    &renderer.device,
+                    context.encoder,
+                    renderer.profiler.as_mut(),
+                    renderer.camera_uniform.bind_group(),
+                    |render_pass| {
+                        // Every instance, as the IDs are their index among all of them.
+                        renderer.mesh.draw(
+                            render_pass,
+                            &renderer.instance_buffer,
+                            0..renderer.instance_count,
+                            None,
+                        );
+                    },
+                );
+            });
+        }
+        let ssao_reads = self.ssao.enabled().then_some(prepass);
+        graph.add_pass(
+            "ssao",
+            ssao_reads.as_slice(),
+            &[ambient_occlusion],
+            |renderer, context| {
+                renderer
+                    .ssao
+                    .render(context.encoder, renderer.profiler.as_mut());
+            },
+        );
+        let mut scene_writes = vec![hdr, motion, depth];
+        scene_writes.extend(msaa.into_iter().flat_map(|(color, motion)| [color, motion]));
+        let mut scene_reads = vec![shadows, ambient_occlusion];
+        scene_reads.extend(culled);
+        scene_reads.extend(skinned);
+        scene_reads.extend(simulated);
+        graph.add_pass(
+            "scene",
+            &scene_reads,
+            &scene_writes,
+            move |renderer, context| {
+                let resources = context.resources;
+                let msaa = msaa
+                    .map(|(color, motion)| (resources.texture(color), resources.texture(motion)));
+                let mut render_pass =
+                    context
+                        .encoder
+                        .begin_render_pass(&wgpu::RenderPassDescriptor {
+                            label: Some("scene"),
+                            color_attachments: &[
+                                Some(scene_attachment(
+                                    msaa.map(|(color, _)| color),
+                                    resources.texture(hdr),
+                                    renderer.clear_color,
+                                )),
+                                Some(scene_attachment(
+                                    msaa.map(|(_, motion)| motion),
+                                    resources.texture(motion),
+                                    wgpu::Color::TRANSPARENT,
+                                )),
+                            ],
+                            depth_stencil_attachment: Some(
+                                wgpu::RenderPassDepthStencilAttachment {
+                                    view: resources.view(depth),
+                                    depth_ops: Some(wgpu::Operations {
+                                        load: wgpu::LoadOp::Clear(1.0),
+                                        store: wgpu::StoreOp::Store,
+                                    }),
+                                    stencil_ops: None,
+                                },
+                            ),
+                            timestamp_writes: renderer
+                                .profiler
+                                .as_mut()
+                                .and_then(|profiler| profiler.timestamp_writes("scene")),
+                            occlusion_query_set: renderer.occlusion.query_set(),
+                        });
+                if bundles_scene {
+                    render_pass.execute_bundles(renderer.static_bundles.bundles());
+                } else {
+                    renderer.draw_meshes(
+                        &mut render_pass,
+                        [&pipeline, &double_sided_pipeline],
+                        AlphaMode::Opaque,
+                        culls_on_gpu,
+                        draws_lods,
+                        multi_draw,
+                    );
+                }
+                if let Some((pipeline, double_sided_pipeline)) = &skinned_pipelines {
+                    renderer
+                        .draw_skinned_meshes(&mut render_pass, [pipeline, double_sided_pipeline]);
+                }
+                if let Some(pipeline) = &terrain_pipeline {
+                    renderer.draw_terrain(&mut render_pass, pipeline);
+                }
+                if let Some(pipeline) = &occlusion_pipeline {
+                    renderer.occlusion.draw_proxies(
+                        &mut render_pass,
+                        pipeline,
+                        renderer.camera_uniform.bind_group(),
+                        &renderer.instance_buffer,
+                    );
+                }
+                if let Some(pipeline) = &skybox_pipeline {
+                    renderer.skybox.draw(&mut render_pass, pipeline);
+                }
+                // After the sky, since they don't write depth.
+                if let Some((pipeline, double_sided_pipeline)) = &blend_pipelines {
+                    let materials = renderer.bind_materials(
+                        &mut render_pass,
+                        [pipeline, double_sided_pipeline],
+                        AlphaMode::Blend,
+                    );
+                    renderer.transparent.draw(
+                        &mut render_pass,
+                        &renderer.mesh,
+                        &renderer.instance_buffer,
+                        materials,
+                    );
+                }
+                if let Some((particles, pipeline)) =
+                    renderer.particles.as_ref().zip(particles_pipeline.as_ref())
+                {
+                    particles.draw(
+                        &mut render_pass,
+                        pipeline,
+                        renderer.camera_uniform.bind_group(),
+                    );
+                }
+                if let Some(pipeline) = &cpu_particles_pipeline {
+                    renderer.cpu_particles.draw(
+                        &mut render_pass,
+                        pipeline,
+                        renderer.camera_uniform.bind_group(),
+                    );
+                }
+                if let Some(pipeline) = &msdf_pipeline {
+                    renderer.msdf_text.draw(
+                        &mut render_pass,
+                        pipeline,
+                        renderer.camera_uniform.bind_group(),
+                    );
+                }
+                if let Some(pipeline) = &debug_draw_pipeline {
+                    renderer.debug_draw.draw(
+                        &mut render_pass,
+                        pipeline,
+                        renderer.camera_uniform.bind_group(),
+                    );
+                }
+                drop(render_pass);
+                renderer
+                    .occlusion
+                    .resolve(&renderer.device, context.encoder);
+            },
+        );
+        if let Some(((pipeline, double_sided_pipeline), composite_pipeline)) =
+            oit_pipelines.zip(oit_composite_pipeline)
+        {
+            let desc = |format, sample_count| TextureDesc {
+                format,
+                sample_count,
+            };
+            let accum = graph.create_texture("oit accum", desc(oit::ACCUM_FORMAT, 1));
+            let revealage = graph.create_texture("oit revealage", desc(oit::REVEALAGE_FORMAT, 1));
+            // Multisampled like the depth buffer they're tested against, and resolved.
+            let msaa = (self.sample_count > 1).then(|| {
+                (
+                    graph.create_texture(
+                        "msaa oit accum",
+                        desc(oit::ACCUM_FORMAT, self.sample_count),
+                    ),
+                    graph.create_texture(
+                        "msaa oit revealage",
+                        desc(oit::REVEALAGE_FORMAT, self.sample_count),
+                    ),
+                )
+            });
+            let mut transparency_reads = vec![depth, shadows, ambient_occlusion];
+            transparency_reads.extend(culled);
+            let mut transparency_writes = vec![accum, revealage];
+            transparency_writes.extend(
+                msaa.into_iter()
+                    .flat_map(|(accum, revealage)| [accum, revealage]),
+            );
+            graph.add_pass(
+                "transparency",
+                &transparency_reads,
+                &transparency_writes,
+                move |renderer, context| {
+                    let resources = context.resources;
+                    let msaa = msaa.map(|(accum, revealage)| {
+                        (resources.texture(accum), resources.texture(revealage))
+                    });
+                    let mut render_pass =
+                        context
+                            .encoder
+                            .begin_render_pass(&wgpu::RenderPassDescriptor {
+                                label: Some("transparency"),
+                                color_attachments: &[
+                                    Some(scene_attachment(
+                                        msaa.map(|(accum, _)| accum),
+                                        resources.texture(accum),
+                                        wgpu::Color::TRANSPARENT,
+                                    )),
+                                    Some(scene_attachment(
+                                        msaa.map(|(_, revealage)| revealage),
+                                        resources.texture(revealage),
+                                        wgpu::Color::WHITE,
+                                    )),
+                                ],
+                                depth_stencil_attachment: Some(
+                                    wgpu::RenderPassDepthStencilAttachment {
+                                        view: resources.view(depth),
+                                        depth_ops: Some(wgpu::Operations {
+                                            load: wgpu::LoadOp::Load,
+                                            // Nothing reads the depth of the scene after this.
+                                            store: wgpu::StoreOp::Discard,
+                                        }),
+                                        stencil_ops: None,
+                                    },
+                                ),
+                                timestamp_writes: renderer
+                                    .profiler
+                                    .as_mut()
+                                    .and_then(|profiler| profiler.timestamp_writes("transparency")),
+                                occlusion_query_set: None,
+                            });
+                    renderer.draw_meshes(
+                        &mut render_pass,
+                        [&pipeline, &double_sided_pipeline],
+                        AlphaMode::WeightedBlended,
+                        culls_on_gpu,
+                        draws_lods,
+                        multi_draw,
+                    );
+                },
+            );
+            graph.add_pass(
+                "oit composite",
+                &[hdr, accum, revealage],
+                &[hdr],
+                move |renderer, context| {
+                    let resources = context.resources;
+                    let bind_group = renderer.oit.bind_group(
+                        &renderer.device,
+                        resources.texture(accum),
+                        resources.texture(revealage),
+                    );
+                    renderer.oit.composite(
+                        context.encoder,
+                        renderer.profiler.as_mut(),
+                        &composite_pipeline,
+                        &bind_group,
+                        resources.view(hdr),
+                    );
+                },
+            );
+        }
+        let mut post_process_reads = vec![hdr, motion];
+        post_process_reads.extend(self.post_process.reads_depth().then_some(prepass));
+        let format = self.surface_config.format;
+        graph.add_pass(
+            "post-process",
+            &post_process_reads,
+            &[swapchain],
+            move |renderer, context| {
+                renderer.post_process.render(
+                    &mut EffectContext {
+                        device: &renderer.device,
+                        motion: context.resources.texture(motion),
+                        depth: renderer.prepass.depth(),
+                        encoder: context.encoder,
+                        pipelines: &mut renderer.pipelines,
+                        profiler: renderer.profiler.as_mut(),
+                    },
+                    context.resources.texture(hdr),
+                    context.resources.view(swapchain),
+                    format,
+                );
+            },
+        );
+        if let Some(pipeline) = sprite_pipeline {
+            graph.add_pass(
+                "sprites",
+                &[swapchain],
+                &[swapchain],
+                move |renderer, context| {
+                    let mut render_pass =
+                        context
+                            .encoder
+                            .begin_render_pass(&wgpu::RenderPassDescriptor {
+                                label: Some("sprites"),
+                                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                                    view: context.resources.view(swapchain),
+                                    resolve_target: None,
+                                    ops: wgpu::Operations {
+                                        load: wgpu::LoadOp::Load,
+                                        store: wgpu::StoreOp::Store,
+                                    },
+                                })],
+                                depth_stencil_attachment: None,
+                                timestamp_writes: renderer
+                                    .profiler
+                                    .as_mut()
+                                    .and_then(|profiler| profiler.timestamp_writes("sprites")),
+                                occlusion_query_set: None,
+                            });
+                    if let Some(tilemap) = &renderer.tilemap {
+                        tilemap.render(&mut render_pass, &pipeline);
+                    }
+                    for svg in &renderer.svgs {
+                        svg.render(&mut render_pass, &pipeline);
+                    }
+                    renderer.sprites.render(&mut render_pass, &pipeline);
+                    renderer.painter.render(&mut render_pass, &pipeline);
+                },
+            );
+        }
+        if std::mem::take(&mut self.capture_requested) {
+            // Copied before the overlay, which screenshots leave out.
+            let capture = graph.external("capture");
+            graph.retain(capture);
+            graph.add_pass("capture", &[swapchain], &[capture], |renderer, context| {
+                readback = Some(TextureReadback::copy(
+                    &renderer.device,
+                    context.encoder,
+                    output.texture(),
+                ));
+            });
+        }
+        if draws_text {
+            // Drawn after the capture like the overlay, as part of the interface.
+            graph.add_pass("text", &[swapchain], &[swapchain], |renderer, context| {
+                let mut render_pass =
+                    context
+                        .encoder
+                        .begin_render_pass(&wgpu::RenderPassDescriptor {
+                            label: Some("text"),
+                            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                                view: context.resources.view(swapchain),
+                                resolve_target: None,
+                                ops: wgpu::Operations {
+                                    load: wgpu::LoadOp::Load,
+                                    store: wgpu::StoreOp::Store,
+                                },
+                            })],
+                            depth_stencil_attachment: None,
+                            timestamp_writes: renderer
+                                .profiler
+                                .as_mut()
+                                .and_then(|profiler| profiler.timestamp_writes("text")),
+                            occlusion_query_set: None,
+                        });
+                renderer.text.render(&mut render_pass);
+            });
+        }
+        graph.add_pass(
+            "overlay",
+            &[swapchain],
+            &[swapchain],
+            move |renderer, context| {
+                overlay(OverlayContext {
+                    device: &renderer.device,
+                    queue: &renderer.queue,
+                    encoder: context.encoder,
+                    view: context.resources.view(swapchain),
+                    profiler: renderer.profiler.as_mut(),
+                });
+            },
+        );
+        let device = self.device.clone();
+        let config = self.surface_config.clone();
+        let mut transients = std::mem::take(&mut self.transients);
+        graph.execute(self, &mut encoder, &device, &config, &mut transients);
+        self.transients = transients;
+        if let Some(profiler) = &mut self.profiler {
+            profiler.end_frame(&mut encoder);
+        }
+        self.uploader.finish();
+        self.queue.submit(std::iter::once(encoder.finish()));
+        self.uploader.recall();
+        if std::mem::take(&mut self.instances_moved) {
+            // The instances stand still from the next frame on, unless they're moved again.
+            self.instance_data = instance_data(&self.instances, &self.instances);
+            self.queue.write_buffer(
+                &self.instance_buffer,
+                0,
+                bytemuck::cast_slice(&self.instance_data),
+            );
+        }
+        if let Some(readback) = readback {
+            self.capture = Some(readback.and_then(|readback| readback.read(&self.device)));
+        }
+        self.picking.after_submit();
+        self.occlusion.after_submit();
+        if let Some(profiler) = &mut self.profiler {
+            profiler.after_submit();
+        }
+        let submitted = Instant::now();
+        let sender = self.gpu_time_sender.clone();
+        self.queue.on_submitted_work_done(move || {
+            // The renderer may have been dropped before the frame completed.
+            let _ = sender.send(submitted.elapsed());
+        });
+        output.present();
+
+        Ok(())
+    }
+}
+
+async fn create_instance(backends: Option<wgpu::Backends>) -> wgpu::Instance {
+    // Browsers without WebGPU and Android devices without Vulkan fall back to GL.
+    let backends = backends.unwrap_or(
+        if cfg!(any(target_arch = "wasm32", target_os = "android")) {
+            wgpu::Backends::PRIMARY | wgpu::Backends::GL
+        } else {
+            wgpu::Backends::PRIMARY
+        },
+    );
+    let descriptor = wgpu::InstanceDescriptor {
+        backends,
+        dx12_shader_compiler: wgpu::Dx12Compiler::Fxc,
+        flags: wgpu::InstanceFlags::default(),
+        gles_minor_version: wgpu::Gles3MinorVersion::Automatic,
+    };
+
+    // Browsers may expose WebGPU without being able to provide an adapter.
+    #[cfg(target_arch = "wasm32")]
+    return wgpu::util::new_instance_with_webgpu_detection(descriptor).await;
+
+    #[cfg(not(target_arch = "wasm32"))]
+    wgpu::Instance::new(descriptor)
+}
+
+/// Picks the adapter matching `selector`, see [`RendererOptions::adapter`], or lets wgpu choose
+/// one without it.
+async fn request_adapter(
+    instance: &wgpu::Instance,
+    compatible_surface: Option<&wgpu::Surface<'_>>,
+    selector: Option<&str>,
+) -> anyhow::Result<wgpu::Adapter> {
+    if let Some(selector) = selector {
+        #[cfg(not(target_arch = "wasm32"))]
+        return select_adapter(instance, compatible_surface, selector);
+        #[cfg(target_arch = "wasm32")]
+        anyhow::bail!("the browser doesn't allow choosing the adapter, got {selector:?}");
+    }
+
+    instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::LowPower,
+            compatible_surface,
+            force_fallback_adapter: false,
+        })
+        .await
+        .context("no compatible graphics adapter found")
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn select_adapter(
+    instance: &wgpu::Instance,
+    compatible_surface: Option<&wgpu::Surface<'_>>,
+    selector: &str,
+) -> anyhow::Result<wgpu::Adapter> {
+    let mut adapters: Vec<_> = instance
+        .enumerate_adapters(wgpu::Backends::all())
+        .into_iter()
+        .filter(|adapter| {
+            compatible_surface.is_none_or(|surface| adapter.is_surface_supported(surface))
+        })
+        .collect();
+
+    let index = match selector.parse::<usize>() {
+        Ok(index) => (index < adapters.len()).then_some(index),
+        Err(_) => {
+            let name = selector.to_lowercase();
+            adapters
+                .iter()
+                .position(|adapter| adapter.get_info().name.to_lowercase().contains(&name))
+        }
+    };
+    let index = index.with_context(|| {
+        let available: Vec<_> = adapters
+            .iter()
+            .enumerate()
+            .map(|(index, adapter)| {
+                let info = adapter.get_info();
+                format!("{index}: {} ({:?})", info.name, info.backend)
+            })
+            .collect();
+        format!(
+            "no adapter matches {selector:?}, the compatible adapters are: {}",
+            available.join(", ")
+        )
+    })?;
+    Ok(adapters.swap_remove(index))
+}
+
+/// The texture frames are rendered into when there is no window.
+fn create_offscreen_texture(
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+) -> Arc<wgpu::Texture> {
+    Arc::new(device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("offscreen"),
+        size: wgpu::Extent3d {
+            width: config.width,
+            height: config.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: config.format,
+        usage: config.usage,
+        view_formats: &[],
+    }))
+}
+
+/// Picks the first sRGB format the surface supports, so that shaders can output linear colors,
+/// falling back to the preferred format.
+fn select_surface_format(formats: &[wgpu::TextureFormat]) -> Option<wgpu::TextureFormat> {
+    formats
+        .iter()
+        .copied()
+        .find(wgpu::TextureFormat::is_srgb)
+        .or_else(|| formats.first().copied())
+}
+
+/// The present modes worth switching between out of the `supported` ones. The automatic modes
+/// fall back to FIFO, which every surface supports.
+fn available_present_modes(supported: &[wgpu::PresentMode]) -> Vec<wgpu::PresentMode> {
+    [
+        wgpu::PresentMode::AutoVsync,
+        wgpu::PresentMode::AutoNoVsync,
+        wgpu::PresentMode::Mailbox,
+        wgpu::PresentMode::Immediate,
+    ]
+    .into_iter()
+    .filter(|mode| {
+        matches!(
+            mode,
+            wgpu::PresentMode::AutoVsync | wgpu::PresentMode::AutoNoVsync
+        ) || supported.contains(mode)
+    })
+    .collect()
+}
+
+/// `instances` as drawn after the instances at the same index in `previous`, or standing still
+/// if there is none.
+fn instance_data(instances: &[Instance], previous: &[Instance]) -> Vec<InstanceRaw> {
+    instances
+        .iter()
+        .enumerate()
+        .map(|(index, instance)| {
+            let previous = previous.get(index).unwrap_or(instance);
+            instance.to_raw(previous.transform)
+        })
+        .collect()
+}
+
+/// Configures `shader.wgsl` for the lights and, if supported, bindless material textures.
+fn scene_preprocessor(
+    lighting: &Lighting,
+    bindless: Option<&BindlessTextures>,
+    preprocessor: shader::Preprocessor,
+) -> shader::Preprocessor {
+    let preprocessor = lighting.configure_preprocessor(preprocessor);
+    match bindless {
+        Some(bindless) => bindless.configure_preprocessor(preprocessor),
+        None => preprocessor,
+    }
+}
+
+fn create_frame_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    globals: &UniformBuffer<Globals>,
+    material_uniforms: &UniformArena<MaterialUniform>,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("frame"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: globals.buffer().as_entire_binding(),
+            },
+            material_uniforms.bind_group_entry(1),
+        ],
+    })
+}
+
+/// Also bound as a storage buffer with `storage`, for GPU culling.
+fn create_instance_buffer(
+    device: &wgpu::Device,
+    data: &[InstanceRaw],
+    storage: bool,
+) -> wgpu::Buffer {
+    let storage = if storage {
+        wgpu::BufferUsages::STORAGE
+    } else {
+        wgpu::BufferUsages::empty()
+    };
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("instances"),
+        contents: bytemuck::cast_slice(data),
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST | storage,
+    })
+}
+
+/// The sample counts usable for color targets of all `formats` resolved into textures and the
+/// depth buffer.
+fn supported_sample_counts(adapter: &wgpu::Adapter, formats: &[wgpu::TextureFormat]) -> Vec<u32> {
+    let depth = adapter.get_texture_format_features(Texture::DEPTH_FORMAT);
+    [1, 2, 4, 8, 16]
+        .into_iter()
+        .filter(|&count| {
+            count == 1
+                || (depth.flags.sample_count_supported(count)
+                    && formats.iter().all(|&format| {
+                        let color = adapter.get_texture_format_features(format);
+                        color
+                            .flags
+                            .contains(wgpu::TextureFormatFeatureFlags::MULTISAMPLE_RESOLVE)
+                            && color.flags.sample_count_supported(count)
+                    }))
+        })
+        .collect()
+}
+
+/// A color target of the scene or transparency pass, drawn into `msaa` and resolved into
+/// `resolved` if MSAA is enabled.
+fn scene_attachment<'a>(
+    msaa: Option<&'a Texture>,
+    resolved: &'a Texture,
+    clear: wgpu::Color,
+) -> wgpu::RenderPassColorAttachment<'a> {
+    match msaa {
+        Some(msaa) => wgpu::RenderPassColorAttachment {
+            view: &msaa.view,
+            resolve_target: Some(&resolved.view),
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(clear),
+                // Only the resolved image is needed after the pass.
+                store: wgpu::StoreOp::Discard,
+            },
+        },
+        None => wgpu::RenderPassColorAttachment {
+            view: &resolved.view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(clear),
+                store: wgpu::StoreOp::Store,
+            },
+        },
+    }
+}
+
+/// Returns a builder for the mesh pipeline that can be sent to a background thread.
+fn mesh_pipeline_builder(
+    layout: Arc<wgpu::PipelineLayout>,
+    shader_module: Arc<wgpu::ShaderModule>,
+    key: MeshPipelineKey,
+) -> Arc<PipelineBuilder> {
+    Arc::new(move |device, cache| create_mesh_pipeline(device, &layout, &shader_module, key, cache))
+}
+
+/// Opaque materials write depth and draw into the scene's color and motion targets. Blended
+/// materials only test depth and blend into the scene's color target, or with weighted blending
+/// into the [`oit::targets`].
+fn create_mesh_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader_module: &wgpu::ShaderModule,
+    key: MeshPipelineKey,
+    cache: Option<&wgpu::PipelineCache>,
+) -> wgpu::RenderPipeline {
+    let scene_targets = |entry_point, blend, motion_mask| {
+        (
+            entry_point,
+            [
+                Some(wgpu::ColorTargetState {
+                    format: key.format,
+                    blend,
+                    write_mask: wgpu::ColorWrites::ALL,
+                }),
+                Some(wgpu::ColorTargetState {
+                    format: MOTION_FORMAT,
+                    blend: None,
+                    write_mask: motion_mask,
+                }),
+            ],
+        )
+    };
+    let (entry_point, targets) = match key.alpha_mode {
+        AlphaMode::Opaque => scene_targets("fs_main", None, wgpu::ColorWrites::ALL),
+        // The motion of what's behind blended surfaces is kept, as that's what mostly shows.
+        AlphaMode::Blend => scene_targets(
+            "fs_blend",
+            Some(wgpu::BlendState::ALPHA_BLENDING),
+            wgpu::ColorWrites::empty(),
+        ),
+        AlphaMode::WeightedBlended => ("fs_oit", oit::targets()),
+    };
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: None,
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader_module,
+            entry_point: "vs_main",
+            buffers: &[key.vertex_layout.buffer_layout(), InstanceRaw::layout()],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        primitive: wgpu::PrimitiveState {
+            cull_mode: (!key.double_sided).then_some(wgpu::Face::Back),
+            polygon_mode: key.polygon_mode,
+            ..Default::default()
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: Texture::DEPTH_FORMAT,
+            depth_write_enabled: key.alpha_mode == AlphaMode::Opaque,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: key.sample_count,
+            ..Default::default()
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader_module,
+            entry_point,
+            targets: &targets,
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        }),
+        multiview: None,
+        cache,
+    })
+}
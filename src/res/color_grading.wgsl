@@ -0,0 +1,37 @@
+// Grades the tonemapped image through a 3D lookup table, which like most LUTs authored in
+// grading tools maps sRGB-encoded colors to sRGB-encoded colors.
+
+#include "common.wgsl"
+#include "fullscreen.wgsl"
+
+struct ColorGrading {
+    // The input colors mapped to the first and last texels of the LUT.
+    domain_min: vec3<f32>,
+    // How much of the graded color replaces the original one, from 0 to 1.
+    strength: f32,
+    domain_max: vec3<f32>,
+}
+
+@group(0) @binding(0)
+var t_input: texture_2d<f32>;
+@group(0) @binding(1)
+var s_input: sampler;
+@group(0) @binding(2)
+var t_lut: texture_3d<f32>;
+@group(0) @binding(3)
+var s_lut: sampler;
+@group(0) @binding(4)
+var<uniform> params: ColorGrading;
+
+@fragment
+fn fs_main(pin: VertexOut) -> @location(0) vec4<f32> {
+    let color = textureSampleLevel(t_input, s_input, pin.uv, 0.0);
+    let encoded = linear_to_srgb(clamp(color.rgb, vec3<f32>(0.0), vec3<f32>(1.0)));
+    let coord = saturate((encoded - params.domain_min) / (params.domain_max - params.domain_min));
+    // Sample between the centers of the first and last texels rather than the edges.
+    let size = f32(textureDimensions(t_lut).x);
+    let uvw = coord * ((size - 1.0) / size) + 0.5 / size;
+    let graded = textureSampleLevel(t_lut, s_lut, uvw, 0.0).rgb;
+    let result = mix(encoded, graded, params.strength);
+    return output_color(vec4<f32>(srgb_to_linear(result), color.a));
+}
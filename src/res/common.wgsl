@@ -0,0 +1,60 @@
+// Bindings and helpers shared by every shader.
+
+// Whether the swapchain converts linear output to sRGB itself.
+override SRGB_SURFACE: bool = true;
+
+struct Globals {
+    resolution: vec2<f32>,
+    cursor: vec2<f32>,
+    time: f32,
+    delta_time: f32,
+}
+
+struct Camera {
+    view_proj: mat4x4<f32>,
+    previous_view_proj: mat4x4<f32>,
+    position: vec4<f32>,
+    // Added to projected positions in normalized device coordinates.
+    jitter: vec2<f32>,
+}
+
+// The offset in UV space of a point from its position the previous frame, given both positions
+// in clip space.
+fn motion_vector(clip: vec4<f32>, previous_clip: vec4<f32>) -> vec2<f32> {
+    return (clip.xy / clip.w - previous_clip.xy / previous_clip.w) * vec2<f32>(0.5, -0.5);
+}
+
+fn linear_to_srgb(linear: vec3<f32>) -> vec3<f32> {
+    let cutoff = linear <= vec3<f32>(0.0031308);
+    let lower = linear * 12.92;
+    let higher = 1.055 * pow(linear, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(higher, lower, cutoff);
+}
+
+fn srgb_to_linear(srgb: vec3<f32>) -> vec3<f32> {
+    let cutoff = srgb <= vec3<f32>(0.04045);
+    let lower = srgb / 12.92;
+    let higher = pow((srgb + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(higher, lower, cutoff);
+}
+
+// Encodes a linear color for the swapchain format.
+fn output_color(color: vec4<f32>) -> vec4<f32> {
+    if SRGB_SURFACE {
+        return color;
+    }
+    return vec4<f32>(linear_to_srgb(max(color.rgb, vec3<f32>(0.0))), color.a);
+}
+
+// Whether the pixel at `pixel` of an instance crossfading between levels of detail is drawn,
+// `fade` being positive while fading in and negative while fading out. The pixels of the two
+// levels complement each other in a 4x4 ordered dither pattern.
+fn lod_fade_visible(fade: f32, pixel: vec2<f32>) -> bool {
+    var bayer = array<u32, 16>(0u, 8u, 2u, 10u, 12u, 4u, 14u, 6u, 3u, 11u, 1u, 9u, 15u, 7u, 13u, 5u);
+    let cell = vec2<u32>(pixel) % 4u;
+    let threshold = (f32(bayer[cell.y * 4u + cell.x]) + 0.5) / 16.0;
+    if fade >= 0.0 {
+        return threshold < fade;
+    }
+    return threshold >= -fade;
+}
@@ -0,0 +1,14 @@
+// Copies the input unchanged, used when every post-processing effect is disabled.
+
+#include "common.wgsl"
+#include "fullscreen.wgsl"
+
+@group(0) @binding(0)
+var t_input: texture_2d<f32>;
+@group(0) @binding(1)
+var s_input: sampler;
+
+@fragment
+fn fs_main(pin: VertexOut) -> @location(0) vec4<f32> {
+    return output_color(vec4<f32>(textureSample(t_input, s_input, pin.uv).rgb, 1.0));
+}
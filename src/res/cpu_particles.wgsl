@@ -0,0 +1,43 @@
+// Draws the camera-facing quads `CpuParticles` expands on the CPU every frame.
+
+#include "common.wgsl"
+
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+struct VertexIn {
+    @location(0) position: vec3<f32>,
+    // From -1 to 1 across the quad.
+    @location(1) corner: vec2<f32>,
+    @location(2) color: vec4<f32>,
+}
+
+struct VertexOut {
+    @builtin(position) position: vec4<f32>,
+    @location(0) corner: vec2<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+struct FragmentOut {
+    @location(0) color: vec4<f32>,
+    @location(1) motion: vec2<f32>,
+}
+
+@vertex
+fn vs_main(vin: VertexIn) -> VertexOut {
+    let clip = camera.view_proj * vec4<f32>(vin.position, 1.0);
+    var out: VertexOut;
+    // Jittered like the scene pass, so that TAA resolves the particles too.
+    out.position = clip + vec4<f32>(camera.jitter * clip.w, 0.0, 0.0);
+    out.corner = vin.corner;
+    out.color = vin.color;
+    return out;
+}
+
+@fragment
+fn fs_main(pin: VertexOut) -> FragmentOut {
+    // A soft disc rather than a square.
+    let alpha = pin.color.a * smoothstep(1.0, 0.5, length(pin.corner));
+    // The motion target isn't written, translucent particles leave the motion behind them.
+    return FragmentOut(vec4<f32>(pin.color.rgb, alpha), vec2<f32>(0.0));
+}
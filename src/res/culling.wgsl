@@ -0,0 +1,78 @@
+// Tests the bounds of every instance against the camera's frustum, compacting the visible
+// instances into `visible` and counting them into the indirect arguments of each draw.
+
+struct Instance {
+    model: mat4x4<f32>,
+    color: vec4<f32>,
+    previous_model: mat4x4<f32>,
+}
+
+struct Culling {
+    // Facing inwards, with the offset along the unit normal in `w`.
+    planes: array<vec4<f32>, 6>,
+    // The mesh's bounding sphere, with the radius in `w`.
+    sphere: vec4<f32>,
+    aabb_min: vec4<f32>,
+    aabb_max: vec4<f32>,
+    instance_count: u32,
+    draw_count: u32,
+    // The words in each draw's arguments, of which the instance count is the second.
+    args_stride: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> culling: Culling;
+
+@group(1) @binding(0)
+var<storage, read> instances: array<Instance>;
+@group(1) @binding(1)
+var<storage, read_write> visible: array<Instance>;
+// The instance count of the first draw counts the visible instances, `write_args` copies it to
+// the other draws.
+@group(1) @binding(2)
+var<storage, read_write> args: array<atomic<u32>>;
+
+fn is_visible(model: mat4x4<f32>) -> bool {
+    let scale = max(length(model[0].xyz), max(length(model[1].xyz), length(model[2].xyz)));
+    let sphere_center = (model * vec4<f32>(culling.sphere.xyz, 1.0)).xyz;
+    let radius = culling.sphere.w * scale;
+
+    let half_extents = (culling.aabb_max.xyz - culling.aabb_min.xyz) * 0.5;
+    let box_center = (model * vec4<f32>(culling.aabb_min.xyz + half_extents, 1.0)).xyz;
+    let extents = abs(model[0].xyz) * half_extents.x
+        + abs(model[1].xyz) * half_extents.y
+        + abs(model[2].xyz) * half_extents.z;
+
+    for (var i = 0u; i < 6u; i++) {
+        let plane = culling.planes[i];
+        if dot(plane.xyz, sphere_center) + plane.w < -radius {
+            return false;
+        }
+        if dot(plane.xyz, box_center) + plane.w < -dot(abs(plane.xyz), extents) {
+            return false;
+        }
+    }
+    return true;
+}
+
+@compute @workgroup_size(64)
+fn cull(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= culling.instance_count {
+        return;
+    }
+    let instance = instances[id.x];
+    if !is_visible(instance.model) {
+        return;
+    }
+    let index = atomicAdd(&args[1], 1u);
+    visible[index] = instance;
+}
+
+@compute @workgroup_size(64)
+fn write_args(@builtin(global_invocation_id) id: vec3<u32>) {
+    let draw = id.x + 1u;
+    if draw >= culling.draw_count {
+        return;
+    }
+    atomicStore(&args[draw * culling.args_stride + 1u], atomicLoad(&args[1]));
+}
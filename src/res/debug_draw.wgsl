@@ -0,0 +1,118 @@
+// Draws the lines batched by `DebugDraw` in the scene pass, depth tested against the scene. Each
+// line is an instance of a quad expanded on screen around it, shaded by the distance to the
+// line so that it gets round caps and anti-aliased edges.
+
+#include "common.wgsl"
+
+struct Lines {
+    // The size of the render target in pixels.
+    viewport: vec2<f32>,
+    // In pixels.
+    width: f32,
+}
+
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+@group(1) @binding(0)
+var<uniform> lines: Lines;
+
+struct SegmentIn {
+    @location(0) start: vec3<f32>,
+    @location(1) end: vec3<f32>,
+    @location(2) color: vec3<f32>,
+}
+
+struct VertexOut {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec3<f32>,
+    @location(1) clip: vec4<f32>,
+    @location(2) previous_clip: vec4<f32>,
+    // The pixel relative to the start of the line, along and across it on screen.
+    @location(3) @interpolate(linear) local: vec2<f32>,
+    @location(4) @interpolate(flat) length: f32,
+}
+
+struct FragmentOut {
+    @location(0) color: vec4<f32>,
+    @location(1) motion: vec2<f32>,
+}
+
+// How far the quads reach from the line, past its edges by the pixel they fade out over.
+fn half_extent() -> f32 {
+    return max(lines.width, 1.0) * 0.5 + 0.5;
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32, segment: SegmentIn) -> VertexOut {
+    var start = segment.start;
+    var end = segment.end;
+    // Clipped to the near plane, behind which the ends would not project onto the screen.
+    let start_z = (camera.view_proj * vec4<f32>(start, 1.0)).z;
+    let end_z = (camera.view_proj * vec4<f32>(end, 1.0)).z;
+    if start_z < 0.0 && end_z >= 0.0 {
+        start = mix(start, end, start_z / (start_z - end_z));
+    } else if end_z < 0.0 && start_z >= 0.0 {
+        end = mix(end, start, end_z / (end_z - start_z));
+    }
+
+    let clip_start = camera.view_proj * vec4<f32>(start, 1.0);
+    let clip_end = camera.view_proj * vec4<f32>(end, 1.0);
+    let half_viewport = lines.viewport * 0.5;
+    let screen_start = clip_start.xy / clip_start.w * half_viewport;
+    let screen_end = clip_end.xy / clip_end.w * half_viewport;
+    let offset = screen_end - screen_start;
+    let span = length(offset);
+    let direction = select(vec2<f32>(1.0, 0.0), offset / span, span > 1e-4);
+    let normal = vec2<f32>(-direction.y, direction.x);
+
+    // Corners of the two triangles as the end they belong to and the side of the line.
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, -1.0),
+        vec2<f32>(1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 1.0),
+    );
+    let corner = corners[vertex_index];
+    let at_end = corner.x > 0.5;
+    let radius = half_extent();
+    let extension = direction * select(-radius, radius, at_end) + normal * corner.y * radius;
+
+    let world = select(start, end, at_end);
+    let position = vec4<f32>(world, 1.0);
+    var clip = select(clip_start, clip_end, at_end);
+    clip = vec4<f32>(clip.xy + extension / half_viewport * clip.w, clip.zw);
+    var previous_clip = camera.previous_view_proj * position;
+    previous_clip = vec4<f32>(
+        previous_clip.xy + extension / half_viewport * previous_clip.w,
+        previous_clip.zw,
+    );
+
+    var out: VertexOut;
+    // Jittered like the scene pass, so that TAA resolves the lines too.
+    out.position = clip + vec4<f32>(camera.jitter * clip.w, 0.0, 0.0);
+    out.color = segment.color;
+    out.clip = clip;
+    out.previous_clip = previous_clip;
+    out.local = vec2<f32>(select(-radius, span + radius, at_end), corner.y * radius);
+    out.length = span;
+    return out;
+}
+
+@fragment
+fn fs_main(pin: VertexOut) -> FragmentOut {
+    // The distance to the line on screen, rounding its ends.
+    let along = max(max(-pin.local.x, pin.local.x - pin.length), 0.0);
+    let from_line = length(vec2<f32>(along, pin.local.y));
+    // Lines thinner than a pixel are drawn a pixel wide and fainter instead.
+    var coverage = clamp(max(lines.width, 1.0) * 0.5 + 0.5 - from_line, 0.0, 1.0);
+    coverage *= min(lines.width, 1.0);
+    if coverage <= 0.0 {
+        discard;
+    }
+    return FragmentOut(
+        vec4<f32>(pin.color, coverage),
+        motion_vector(pin.clip, pin.previous_clip),
+    );
+}
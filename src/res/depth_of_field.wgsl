@@ -0,0 +1,74 @@
+// Depth of field gathered in a single pass, after Dennis Gustafsson's "Bokeh depth of field in
+// a single pass". Samples along a golden-angle spiral around each pixel and keeps those whose
+// circle of confusion reaches back to it, so that blurry foreground bleeds over sharp
+// background but not the other way around.
+
+#include "common.wgsl"
+#include "fullscreen.wgsl"
+
+#define GOLDEN_ANGLE 2.39996323
+// How quickly the spiral grows, in pixels. Lower is smoother and slower.
+#define RADIUS_STEP 0.5
+
+struct DepthOfField {
+    near: f32,
+    far: f32,
+    // The view distance that is in focus.
+    focus_distance: f32,
+    // The circle of confusion radius, as a fraction of the image height, of a point infinitely
+    // far from the focus distance.
+    coc_scale: f32,
+    // The largest circle of confusion radius in pixels, bounding the cost of the gather.
+    max_radius: f32,
+}
+
+@group(0) @binding(0)
+var t_input: texture_2d<f32>;
+@group(0) @binding(1)
+var s_input: sampler;
+// Bound as a float texture, as GL can't load texels from depth textures.
+@group(0) @binding(2)
+var t_depth: texture_2d<f32>;
+@group(0) @binding(3)
+var<uniform> params: DepthOfField;
+
+// The distance from the camera of a depth buffer value.
+fn view_distance(depth: f32) -> f32 {
+    return params.near * params.far / (params.far - depth * (params.far - params.near));
+}
+
+// The radius in pixels of the circle a point at `distance` blurs into.
+fn circle_of_confusion(distance: f32, height: f32) -> f32 {
+    let coc = abs(distance - params.focus_distance) / distance * params.coc_scale * height;
+    return min(coc, params.max_radius);
+}
+
+@fragment
+fn fs_main(pin: VertexOut) -> @location(0) vec4<f32> {
+    let size = vec2<f32>(textureDimensions(t_input));
+    let depth_size = vec2<i32>(textureDimensions(t_depth));
+    let center_distance = view_distance(textureLoad(t_depth, vec2<i32>(pin.position.xy), 0).r);
+    let center_coc = circle_of_confusion(center_distance, size.y);
+
+    var color = textureSampleLevel(t_input, s_input, pin.uv, 0.0).rgb;
+    var total = 1.0;
+    var radius = RADIUS_STEP;
+    var angle = 0.0;
+    while radius < params.max_radius {
+        let uv = pin.uv + vec2<f32>(cos(angle), sin(angle)) * radius / size;
+        let sample_color = textureSampleLevel(t_input, s_input, uv, 0.0).rgb;
+        let sample_pixel = clamp(vec2<i32>(uv * size), vec2<i32>(0), depth_size - 1);
+        let sample_distance = view_distance(textureLoad(t_depth, sample_pixel, 0).r);
+        var sample_coc = circle_of_confusion(sample_distance, size.y);
+        // Background can't blur over sharper foreground.
+        if sample_distance > center_distance {
+            sample_coc = clamp(sample_coc, 0.0, center_coc * 2.0);
+        }
+        let contribution = smoothstep(radius - 0.5, radius + 0.5, sample_coc);
+        color += mix(color / total, sample_color, contribution);
+        total += 1.0;
+        radius += RADIUS_STEP / radius;
+        angle += GOLDEN_ANGLE;
+    }
+    return output_color(vec4<f32>(color / total, 1.0));
+}
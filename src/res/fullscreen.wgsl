@@ -0,0 +1,17 @@
+// The vertex stage of fullscreen passes.
+
+struct VertexOut {
+    @builtin(position) position: vec4<f32>,
+    // From 0 to 1, with y pointing down the texture.
+    @location(0) uv: vec2<f32>,
+}
+
+// A single triangle covering the whole viewport.
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOut {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOut;
+    out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
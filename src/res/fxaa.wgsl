@@ -0,0 +1,131 @@
+// FXAA 3.11 with the search steps of quality preset 12, after Timothy Lottes' reference
+// implementation. Finds edges from the luma contrast around each pixel, searches along them
+// for their ends and blends across them by how far the pixel is from the nearer end.
+
+#include "common.wgsl"
+#include "fullscreen.wgsl"
+
+// The local contrast needed to count as an edge, relative to the brightest neighbour.
+#define EDGE_THRESHOLD 0.166
+// Dark areas below this contrast are left alone.
+#define EDGE_THRESHOLD_MIN 0.0833
+// How much aliasing within a single pixel is smoothed, from 0 to 1.
+#define SUBPIXEL 0.75
+#define SEARCH_STEPS 5
+
+@group(0) @binding(0)
+var t_input: texture_2d<f32>;
+@group(0) @binding(1)
+var s_input: sampler;
+
+// Edges are found in roughly perceptual luma, the input being linear.
+fn luma(color: vec3<f32>) -> f32 {
+    return sqrt(dot(color, vec3<f32>(0.299, 0.587, 0.114)));
+}
+
+fn sample_luma(uv: vec2<f32>) -> f32 {
+    return luma(textureSampleLevel(t_input, s_input, uv, 0.0).rgb);
+}
+
+@fragment
+fn fs_main(pin: VertexOut) -> @location(0) vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_input));
+    let uv = pin.uv;
+    let center = textureSampleLevel(t_input, s_input, uv, 0.0).rgb;
+    let luma_m = luma(center);
+    var luma_n = sample_luma(uv + vec2<f32>(0.0, -texel.y));
+    var luma_s = sample_luma(uv + vec2<f32>(0.0, texel.y));
+    let luma_w = sample_luma(uv + vec2<f32>(-texel.x, 0.0));
+    let luma_e = sample_luma(uv + vec2<f32>(texel.x, 0.0));
+
+    let range_max = max(max(max(luma_n, luma_s), max(luma_w, luma_e)), luma_m);
+    let range_min = min(min(min(luma_n, luma_s), min(luma_w, luma_e)), luma_m);
+    let range = range_max - range_min;
+    if range < max(EDGE_THRESHOLD_MIN, range_max * EDGE_THRESHOLD) {
+        return output_color(vec4<f32>(center, 1.0));
+    }
+
+    let luma_nw = sample_luma(uv - texel);
+    let luma_se = sample_luma(uv + texel);
+    let luma_ne = sample_luma(uv + vec2<f32>(texel.x, -texel.y));
+    let luma_sw = sample_luma(uv + vec2<f32>(-texel.x, texel.y));
+
+    // How much the pixel differs from the average of its neighbourhood.
+    let average = (2.0 * (luma_n + luma_s + luma_w + luma_e) + luma_nw + luma_ne + luma_sw
+        + luma_se) / 12.0;
+    let subpixel_contrast = saturate(abs(average - luma_m) / range);
+    let subpixel_blend = smoothstep(0.0, 1.0, subpixel_contrast);
+    let subpixel_offset = subpixel_blend * subpixel_blend * SUBPIXEL;
+
+    // Whether the edge runs horizontally, from the second derivatives of luma across it.
+    let edge_horizontal = abs(luma_nw + luma_sw - 2.0 * luma_w)
+        + 2.0 * abs(luma_n + luma_s - 2.0 * luma_m) + abs(luma_ne + luma_se - 2.0 * luma_e);
+    let edge_vertical = abs(luma_nw + luma_ne - 2.0 * luma_n)
+        + 2.0 * abs(luma_w + luma_e - 2.0 * luma_m) + abs(luma_sw + luma_se - 2.0 * luma_s);
+    let horizontal = edge_horizontal >= edge_vertical;
+
+    // Which side of the pixel the edge lies on.
+    if !horizontal {
+        luma_n = luma_w;
+        luma_s = luma_e;
+    }
+    let gradient_n = luma_n - luma_m;
+    let gradient_s = luma_s - luma_m;
+    let pair_n = abs(gradient_n) >= abs(gradient_s);
+    let gradient = max(abs(gradient_n), abs(gradient_s));
+    var step_length = select(texel.x, texel.y, horizontal);
+    if pair_n {
+        step_length = -step_length;
+    }
+    let luma_pair = select(luma_s, luma_n, pair_n) + luma_m;
+    let luma_local = luma_pair * 0.5;
+
+    // Search both ways along the edge, halfway between the pixel and its neighbour across it,
+    // until the luma there stops matching the edge.
+    let along = select(vec2<f32>(0.0, texel.y), vec2<f32>(texel.x, 0.0), horizontal);
+    var edge_uv = uv;
+    if horizontal {
+        edge_uv.y += step_length * 0.5;
+    } else {
+        edge_uv.x += step_length * 0.5;
+    }
+    let gradient_scaled = gradient * 0.25;
+    var steps = array<f32, SEARCH_STEPS>(1.0, 1.5, 2.0, 4.0, 12.0);
+    var uv_n = edge_uv - along;
+    var uv_p = edge_uv + along;
+    var luma_end_n = sample_luma(uv_n) - luma_local;
+    var luma_end_p = sample_luma(uv_p) - luma_local;
+    var done_n = abs(luma_end_n) >= gradient_scaled;
+    var done_p = abs(luma_end_p) >= gradient_scaled;
+    for (var i = 1; i < SEARCH_STEPS && !(done_n && done_p); i++) {
+        if !done_n {
+            uv_n -= along * steps[i];
+            luma_end_n = sample_luma(uv_n) - luma_local;
+            done_n = abs(luma_end_n) >= gradient_scaled;
+        }
+        if !done_p {
+            uv_p += along * steps[i];
+            luma_end_p = sample_luma(uv_p) - luma_local;
+            done_p = abs(luma_end_p) >= gradient_scaled;
+        }
+    }
+
+    // Blend across the edge by how close the nearer end is, if the luma there changes the
+    // same way as at the pixel.
+    let distance_n = select(uv.y - uv_n.y, uv.x - uv_n.x, horizontal);
+    let distance_p = select(uv_p.y - uv.y, uv_p.x - uv.x, horizontal);
+    let nearer_n = distance_n < distance_p;
+    let luma_m_below = luma_m - luma_local < 0.0;
+    let luma_end = select(luma_end_p, luma_end_n, nearer_n);
+    let good_span = (luma_end < 0.0) != luma_m_below;
+    let edge_offset = select(0.0, 0.5 - min(distance_n, distance_p) / (distance_n + distance_p), good_span);
+    let offset = max(edge_offset, subpixel_offset);
+
+    var blended_uv = uv;
+    if horizontal {
+        blended_uv.y += offset * step_length;
+    } else {
+        blended_uv.x += offset * step_length;
+    }
+    return output_color(vec4<f32>(textureSampleLevel(t_input, s_input, blended_uv, 0.0).rgb, 1.0));
+}
@@ -0,0 +1,34 @@
+// One pass of a bitonic sort of key and value pairs by ascending key. A sort of `n` pairs, a
+// power of two, takes a pass for every `k` from 2 to `n` doubling and `j` from `k / 2` to 1
+// halving.
+
+struct SortPass {
+    // The size of the bitonic sequences being merged.
+    k: u32,
+    // The distance between the pairs compared.
+    j: u32,
+}
+
+// At a dynamic offset, one slot per pass.
+@group(0) @binding(0)
+var<uniform> sort_pass: SortPass;
+
+// Keys in `x` and values in `y`, bound with exactly as many pairs as are sorted.
+@group(1) @binding(0)
+var<storage, read_write> pairs: array<vec2<u32>>;
+
+@compute @workgroup_size(256)
+fn sort(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i = id.x;
+    let partner = i ^ sort_pass.j;
+    if i >= arrayLength(&pairs) || partner <= i {
+        return;
+    }
+    let ascending = (i & sort_pass.k) == 0u;
+    let a = pairs[i];
+    let b = pairs[partner];
+    if (a.x > b.x) == ascending {
+        pairs[i] = b;
+        pairs[partner] = a;
+    }
+}
@@ -0,0 +1,166 @@
+// Filters an environment cube map for image-based lighting, one cube face or lookup table at a
+// time.
+
+#include "lighting.wgsl"
+
+#define IRRADIANCE_SAMPLES 512u
+#define SPECULAR_SAMPLES 256u
+#define BRDF_SAMPLES 256u
+
+struct Filter {
+    face: u32,
+    roughness: f32,
+    environment_size: f32,
+}
+
+@group(0) @binding(0)
+var<uniform> params: Filter;
+@group(0) @binding(1)
+var t_environment: texture_cube<f32>;
+@group(0) @binding(2)
+var s_environment: sampler;
+
+struct VertexOut {
+    @builtin(position) position: vec4<f32>,
+    // From -1 to 1, with y pointing down the texture.
+    @location(0) uv: vec2<f32>,
+}
+
+// A single triangle covering the whole target.
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOut {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOut;
+    out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv * 2.0 - 1.0;
+    return out;
+}
+
+// The direction through `uv` on `face` of a cube map, matching `cube_direction` in `skybox.rs`.
+fn face_direction(face: u32, uv: vec2<f32>) -> vec3<f32> {
+    var direction: vec3<f32>;
+    switch face {
+        case 0u: {
+            direction = vec3<f32>(1.0, -uv.y, -uv.x);
+        }
+        case 1u: {
+            direction = vec3<f32>(-1.0, -uv.y, uv.x);
+        }
+        case 2u: {
+            direction = vec3<f32>(uv.x, 1.0, uv.y);
+        }
+        case 3u: {
+            direction = vec3<f32>(uv.x, -1.0, -uv.y);
+        }
+        case 4u: {
+            direction = vec3<f32>(uv.x, -uv.y, 1.0);
+        }
+        default: {
+            direction = vec3<f32>(-uv.x, -uv.y, -1.0);
+        }
+    }
+    return normalize(direction);
+}
+
+// Evenly spread points in the unit square.
+fn hammersley(i: u32, count: u32) -> vec2<f32> {
+    return vec2<f32>(f32(i) / f32(count), f32(reverseBits(i)) * 2.3283064365386963e-10);
+}
+
+// Rotates directions around +Z to directions around `n`.
+fn tangent_basis(n: vec3<f32>) -> mat3x3<f32> {
+    let up = select(vec3<f32>(0.0, 0.0, 1.0), vec3<f32>(1.0, 0.0, 0.0), abs(n.z) > 0.999);
+    let t = normalize(cross(up, n));
+    return mat3x3<f32>(t, cross(n, t), n);
+}
+
+// A half vector around +Z, distributed like the GGX microfacets of roughness `alpha`.
+fn importance_sample_ggx(xi: vec2<f32>, alpha: f32) -> vec3<f32> {
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (alpha * alpha - 1.0) * xi.y));
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    return vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+}
+
+// The environment level whose texels cover about the solid angle of one of `count` samples
+// drawn with probability density `pdf`, which keeps bright texels from turning into noise.
+fn sample_level(pdf: f32, count: u32) -> f32 {
+    let sample_solid_angle = 1.0 / (f32(count) * pdf + 0.0001);
+    let texel_solid_angle = 4.0 * PI / (6.0 * params.environment_size * params.environment_size);
+    return max(0.5 * log2(sample_solid_angle / texel_solid_angle) + 1.0, 0.0);
+}
+
+// The irradiance arriving at a surface facing each direction, divided by pi so that it only
+// needs multiplying with the diffuse color.
+@fragment
+fn fs_irradiance(pin: VertexOut) -> @location(0) vec4<f32> {
+    let n = face_direction(params.face, pin.uv);
+    let basis = tangent_basis(n);
+    var irradiance = vec3<f32>(0.0);
+    for (var i = 0u; i < IRRADIANCE_SAMPLES; i++) {
+        // Cosine-weighted directions, whose density cancels out the cosine term.
+        let xi = hammersley(i, IRRADIANCE_SAMPLES);
+        let phi = 2.0 * PI * xi.x;
+        let cos_theta = sqrt(1.0 - xi.y);
+        let sin_theta = sqrt(xi.y);
+        let l = basis * vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+        let level = sample_level(cos_theta / PI, IRRADIANCE_SAMPLES);
+        irradiance += textureSampleLevel(t_environment, s_environment, l, level).rgb;
+    }
+    return vec4<f32>(irradiance / f32(IRRADIANCE_SAMPLES), 1.0);
+}
+
+// The environment reflected by a GGX lobe of the pass' roughness around each direction,
+// assuming the view direction is the normal.
+@fragment
+fn fs_prefilter(pin: VertexOut) -> @location(0) vec4<f32> {
+    let n = face_direction(params.face, pin.uv);
+    if params.roughness == 0.0 {
+        return vec4<f32>(textureSampleLevel(t_environment, s_environment, n, 0.0).rgb, 1.0);
+    }
+    let alpha = params.roughness * params.roughness;
+    let basis = tangent_basis(n);
+    var color = vec3<f32>(0.0);
+    var weight = 0.0;
+    for (var i = 0u; i < SPECULAR_SAMPLES; i++) {
+        let h_tangent = importance_sample_ggx(hammersley(i, SPECULAR_SAMPLES), alpha);
+        let h = basis * h_tangent;
+        let l = reflect(-n, h);
+        let n_dot_l = dot(n, l);
+        if n_dot_l > 0.0 {
+            // With the view along the normal, the density of l is D(h) / 4.
+            let pdf = distribution_ggx(h_tangent.z, alpha) / 4.0;
+            let level = sample_level(pdf, SPECULAR_SAMPLES);
+            color += textureSampleLevel(t_environment, s_environment, l, level).rgb * n_dot_l;
+            weight += n_dot_l;
+        }
+    }
+    return vec4<f32>(color / max(weight, 0.0001), 1.0);
+}
+
+// The scale and bias applied to F0 by the specular BRDF lit by a white environment, for n.v
+// along x and roughness along y.
+@fragment
+fn fs_brdf_lut(pin: VertexOut) -> @location(0) vec4<f32> {
+    let n_dot_v = max(pin.uv.x * 0.5 + 0.5, 0.001);
+    let roughness = pin.uv.y * 0.5 + 0.5;
+    let alpha = max(roughness * roughness, 0.002);
+    let v = vec3<f32>(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+    var scale = 0.0;
+    var bias = 0.0;
+    for (var i = 0u; i < BRDF_SAMPLES; i++) {
+        let h = importance_sample_ggx(hammersley(i, BRDF_SAMPLES), alpha);
+        let l = reflect(-v, h);
+        let n_dot_l = l.z;
+        if n_dot_l > 0.0 {
+            let v_dot_h = max(dot(v, h), 0.0);
+            // The BRDF times the cosine over the sampling density, without the Fresnel term.
+            let g_vis = visibility_smith_ggx(n_dot_l, n_dot_v, alpha) * 4.0 * n_dot_l * v_dot_h
+                / max(h.z, 0.0001);
+            let fc = pow(1.0 - v_dot_h, 5.0);
+            scale += (1.0 - fc) * g_vis;
+            bias += fc * g_vis;
+        }
+    }
+    return vec4<f32>(scale, bias, 0.0, 1.0) / vec4<f32>(f32(BRDF_SAMPLES), f32(BRDF_SAMPLES), 1.0, 1.0);
+}
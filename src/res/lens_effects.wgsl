@@ -0,0 +1,56 @@
+// Imperfections of real lenses and film, applied to the finished image: chromatic aberration
+// splitting colors towards the edges, a vignette darkening the corners and animated grain. Each
+// is disabled by a zero strength.
+
+#include "common.wgsl"
+#include "fullscreen.wgsl"
+
+struct LensEffects {
+    // How far red and blue are pulled apart in the corners, in pixels.
+    chromatic_aberration: f32,
+    // How dark the corners get, from 0 to 1.
+    vignette: f32,
+    // Where the vignette starts, as a distance from the center relative to the corners.
+    vignette_radius: f32,
+    // The amplitude of the grain.
+    grain: f32,
+    // Seconds since startup, animating the grain.
+    time: f32,
+}
+
+@group(0) @binding(0)
+var t_input: texture_2d<f32>;
+@group(0) @binding(1)
+var s_input: sampler;
+@group(0) @binding(2)
+var<uniform> params: LensEffects;
+
+// A cheap hash of a pixel and a frame to a value in [0, 1).
+fn hash(pixel: vec2<f32>, seed: f32) -> f32 {
+    let p = fract(vec3<f32>(pixel, seed) * 0.1031);
+    let q = p + dot(p, p.yzx + 33.33);
+    return fract((q.x + q.y) * q.z);
+}
+
+@fragment
+fn fs_main(pin: VertexOut) -> @location(0) vec4<f32> {
+    let size = vec2<f32>(textureDimensions(t_input));
+    // From the center, 1 in the corners.
+    let offset = (pin.uv - 0.5) * 2.0;
+    let distance = length(offset) / sqrt(2.0);
+
+    let shift = offset * distance * params.chromatic_aberration / size;
+    let color = textureSampleLevel(t_input, s_input, pin.uv, 0.0);
+    let red = textureSampleLevel(t_input, s_input, pin.uv - shift, 0.0).r;
+    let blue = textureSampleLevel(t_input, s_input, pin.uv + shift, 0.0).b;
+    var rgb = vec3<f32>(red, color.g, blue);
+
+    let falloff = smoothstep(params.vignette_radius, 1.0, distance);
+    rgb *= 1.0 - params.vignette * falloff;
+
+    // Grain is added in sRGB, where it's perceptually even across brightness.
+    let noise = hash(pin.position.xy, fract(params.time) * 1000.0) - 0.5;
+    var encoded = linear_to_srgb(clamp(rgb, vec3<f32>(0.0), vec3<f32>(1.0)));
+    encoded = saturate(encoded + noise * params.grain);
+    return output_color(vec4<f32>(srgb_to_linear(encoded), color.a));
+}
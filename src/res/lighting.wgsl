@@ -0,0 +1,132 @@
+#define MAX_LIGHTS 64
+#define SHADOW_CASCADES 4
+#define POINT_SHADOW_MAP_SIZE 512
+#define PREFILTERED_MIPS 5
+#define LIGHT_POINT 0u
+#define LIGHT_SPOT 1u
+#define PI 3.14159265358979
+
+struct DirectionalLight {
+    direction: vec3<f32>,
+    // Scales the light reflected from the environment.
+    ambient: f32,
+    color: vec3<f32>,
+    intensity: f32,
+}
+
+// One slice of the camera's view, matching `CascadeUniform`.
+struct ShadowCascade {
+    view_proj: mat4x4<f32>,
+    split: f32,
+    normal_offset: f32,
+}
+
+// The directional light's view of the scene, matching `ShadowUniform`.
+struct ShadowParams {
+    cascades: array<ShadowCascade, SHADOW_CASCADES>,
+    camera_forward: vec4<f32>,
+    bias: f32,
+    texel_size: f32,
+    enabled: u32,
+    debug_cascades: u32,
+}
+
+// A point or spot light, matching `LightRaw`.
+struct Light {
+    position: vec3<f32>,
+    kind: u32,
+    direction: vec3<f32>,
+    range: f32,
+    color: vec3<f32>,
+    intensity: f32,
+    // The cosines of the inner and outer cone angles of spot lights.
+    cone: vec2<f32>,
+    // The index of the light's shadow cube map, or -1 without shadows.
+    shadow: i32,
+}
+
+struct LightList {
+    count: u32,
+#ifdef LIGHTS_IN_UNIFORM
+    lights: array<Light, MAX_LIGHTS>,
+#else
+    lights: array<Light>,
+#endif
+}
+
+// The inputs of the glTF metallic-roughness BRDF at a point on a surface.
+struct Surface {
+    base_color: vec3<f32>,
+    metallic: f32,
+    // Perceptual roughness, squared for the microfacet distribution.
+    roughness: f32,
+    // Normalized.
+    normal: vec3<f32>,
+    // Normalized, from the surface towards the eye.
+    view: vec3<f32>,
+}
+
+fn fresnel_schlick(f0: vec3<f32>, v_dot_h: f32) -> vec3<f32> {
+    return f0 + (1.0 - f0) * pow(1.0 - v_dot_h, 5.0);
+}
+
+// The GGX (Trowbridge-Reitz) microfacet distribution.
+fn distribution_ggx(n_dot_h: f32, alpha: f32) -> f32 {
+    let alpha2 = alpha * alpha;
+    let d = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+    return alpha2 / (PI * d * d);
+}
+
+// The height-correlated Smith masking-shadowing term, divided by `4 n.l n.v`.
+fn visibility_smith_ggx(n_dot_l: f32, n_dot_v: f32, alpha: f32) -> f32 {
+    let alpha2 = alpha * alpha;
+    let ggx_v = n_dot_l * sqrt(n_dot_v * n_dot_v * (1.0 - alpha2) + alpha2);
+    let ggx_l = n_dot_v * sqrt(n_dot_l * n_dot_l * (1.0 - alpha2) + alpha2);
+    return 0.5 / max(ggx_v + ggx_l, 0.0001);
+}
+
+// The light reflected towards the eye for unit light arriving from the normalized direction
+// `l`, including the cosine term: a Lambertian diffuse lobe plus a GGX specular lobe, blended by
+// metalness like in glTF.
+fn brdf(surface: Surface, l: vec3<f32>) -> vec3<f32> {
+    let n_dot_l = dot(surface.normal, l);
+    if n_dot_l <= 0.0 {
+        return vec3<f32>(0.0);
+    }
+    let h = normalize(l + surface.view);
+    let n_dot_v = max(dot(surface.normal, surface.view), 0.0001);
+    let n_dot_h = max(dot(surface.normal, h), 0.0);
+    let v_dot_h = max(dot(surface.view, h), 0.0);
+    // Perfectly smooth surfaces would have infinitely small highlights.
+    let alpha = max(surface.roughness * surface.roughness, 0.002);
+
+    let f0 = mix(vec3<f32>(0.04), surface.base_color, surface.metallic);
+    let f = fresnel_schlick(f0, v_dot_h);
+    let specular = f * distribution_ggx(n_dot_h, alpha) * visibility_smith_ggx(n_dot_l, n_dot_v, alpha);
+    let diffuse = (1.0 - f) * (1.0 - surface.metallic) * surface.base_color / PI;
+    return (diffuse + specular) * n_dot_l;
+}
+
+// The light reflected towards the eye from `light`, without ambient lighting.
+fn directional_light(light: DirectionalLight, surface: Surface) -> vec3<f32> {
+    return light.color * light.intensity * brdf(surface, normalize(light.direction));
+}
+
+// Inverse-square falloff, windowed to reach zero at `range`.
+fn distance_attenuation(distance: f32, range: f32) -> f32 {
+    let window = saturate(1.0 - pow(distance / range, 4.0));
+    return window * window / max(distance * distance, 0.01);
+}
+
+// The light reflected towards the eye from a point or spot light, for a surface at `position`.
+fn punctual_light(light: Light, surface: Surface, position: vec3<f32>) -> vec3<f32> {
+    let to_light = light.position - position;
+    let distance = length(to_light);
+    let l = to_light / max(distance, 0.0001);
+
+    var attenuation = distance_attenuation(distance, light.range);
+    if light.kind == LIGHT_SPOT {
+        attenuation *= smoothstep(light.cone.y, light.cone.x, dot(-l, normalize(light.direction)));
+    }
+    return light.color * light.intensity * attenuation * brdf(surface, l);
+}
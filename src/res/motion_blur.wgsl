@@ -0,0 +1,44 @@
+// Blurs each pixel along its motion vector, as if the shutter stayed open for part of the
+// frame.
+
+#include "common.wgsl"
+#include "fullscreen.wgsl"
+
+#define SAMPLES 12
+
+struct MotionBlur {
+    // The fraction of the frame the shutter is open, scaling the motion vectors.
+    shutter: f32,
+    // The longest blur in pixels.
+    max_length: f32,
+}
+
+@group(0) @binding(0)
+var t_input: texture_2d<f32>;
+@group(0) @binding(1)
+var s_input: sampler;
+@group(0) @binding(2)
+var t_motion: texture_2d<f32>;
+@group(0) @binding(3)
+var<uniform> params: MotionBlur;
+
+@fragment
+fn fs_main(pin: VertexOut) -> @location(0) vec4<f32> {
+    let size = vec2<f32>(textureDimensions(t_input));
+    var velocity = textureLoad(t_motion, vec2<i32>(pin.position.xy), 0).xy * params.shutter;
+    let length_pixels = length(velocity * size);
+    if length_pixels < 0.5 {
+        return output_color(textureSampleLevel(t_input, s_input, pin.uv, 0.0));
+    }
+    if length_pixels > params.max_length {
+        velocity *= params.max_length / length_pixels;
+    }
+
+    // Centered on the pixel, so that the blur spans where it was and where it is going.
+    var color = vec3<f32>(0.0);
+    for (var i = 0; i < SAMPLES; i++) {
+        let t = f32(i) / f32(SAMPLES - 1) - 0.5;
+        color += textureSampleLevel(t_input, s_input, pin.uv + velocity * t, 0.0).rgb;
+    }
+    return output_color(vec4<f32>(color / f32(SAMPLES), 1.0));
+}
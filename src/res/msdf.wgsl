@@ -0,0 +1,67 @@
+// Draws the glyph quads `MsdfText` lays out, from a multi-channel signed distance field atlas.
+
+#include "common.wgsl"
+
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+struct Font {
+    // The range of distances the atlas encodes, in atlas pixels.
+    distance_range: f32,
+}
+
+@group(1) @binding(0)
+var atlas: texture_2d<f32>;
+@group(1) @binding(1)
+var atlas_sampler: sampler;
+@group(1) @binding(2)
+var<uniform> font: Font;
+
+struct VertexIn {
+    @location(0) position: vec3<f32>,
+    @location(1) uv: vec2<f32>,
+    @location(2) color: vec4<f32>,
+}
+
+struct VertexOut {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+struct FragmentOut {
+    @location(0) color: vec4<f32>,
+    @location(1) motion: vec2<f32>,
+}
+
+@vertex
+fn vs_main(vin: VertexIn) -> VertexOut {
+    let clip = camera.view_proj * vec4<f32>(vin.position, 1.0);
+    var out: VertexOut;
+    // Jittered like the scene pass, so that TAA resolves the text too.
+    out.position = clip + vec4<f32>(camera.jitter * clip.w, 0.0, 0.0);
+    out.uv = vin.uv;
+    out.color = vin.color;
+    return out;
+}
+
+fn median(a: f32, b: f32, c: f32) -> f32 {
+    return max(min(a, b), min(max(a, b), c));
+}
+
+@fragment
+fn fs_main(pin: VertexOut) -> FragmentOut {
+    let distances = textureSample(atlas, atlas_sampler, pin.uv).rgb;
+    // How many screen pixels the distance range covers, so that edges stay a pixel wide
+    // however large the text is drawn.
+    let unit_range = vec2<f32>(font.distance_range) / vec2<f32>(textureDimensions(atlas));
+    let screen_size = vec2<f32>(1.0) / fwidth(pin.uv);
+    let screen_range = max(0.5 * dot(unit_range, screen_size), 1.0);
+    let distance = median(distances.r, distances.g, distances.b) - 0.5;
+    let alpha = pin.color.a * clamp(distance * screen_range + 0.5, 0.0, 1.0);
+    if alpha <= 0.0 {
+        discard;
+    }
+    // The motion target isn't written, like for other translucent surfaces.
+    return FragmentOut(vec4<f32>(pin.color.rgb, alpha), vec2<f32>(0.0));
+}
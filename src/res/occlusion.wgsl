@@ -0,0 +1,32 @@
+// Draws the bounding box of an instance without writing to any target, for an occlusion query
+// to count the samples of it in front of the scene's depth.
+
+#include "common.wgsl"
+
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+struct InstanceIn {
+    @location(5) model_0: vec4<f32>,
+    @location(6) model_1: vec4<f32>,
+    @location(7) model_2: vec4<f32>,
+    @location(8) model_3: vec4<f32>,
+}
+
+struct FragmentOut {
+    @location(0) color: vec4<f32>,
+    @location(1) motion: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@location(0) position: vec3<f32>, instance: InstanceIn) -> @builtin(position) vec4<f32> {
+    let model = mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+    let clip = camera.view_proj * model * vec4<f32>(position, 1.0);
+    // Jittered like the scene pass, whose depth buffer it is tested against.
+    return clip + vec4<f32>(camera.jitter * clip.w, 0.0, 0.0);
+}
+
+@fragment
+fn fs_main() -> FragmentOut {
+    return FragmentOut(vec4<f32>(0.0), vec2<f32>(0.0));
+}
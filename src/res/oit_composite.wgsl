@@ -0,0 +1,23 @@
+// Resolves the weighted sums of the transparent surfaces into their average color and blends it
+// over the opaque scene by how much of it they cover.
+
+#include "fullscreen.wgsl"
+
+@group(0) @binding(0)
+var t_accum: texture_2d<f32>;
+@group(0) @binding(1)
+var t_revealage: texture_2d<f32>;
+
+@fragment
+fn fs_main(pin: VertexOut) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(pin.position.xy);
+    let revealage = textureLoad(t_revealage, pixel, 0).r;
+    // Nothing transparent covers the pixel.
+    if revealage >= 1.0 {
+        discard;
+    }
+    let accum = textureLoad(t_accum, pixel, 0);
+    // Large weights overflow half floats into infinity.
+    let color = accum.rgb / clamp(accum.a, 1e-4, 5e4);
+    return vec4<f32>(color, 1.0 - revealage);
+}
@@ -0,0 +1,193 @@
+// Emits particles into a ring buffer and moves them in compute shaders, then draws each as a
+// quad facing the camera in the scene pass.
+
+#include "common.wgsl"
+
+struct Emitter {
+    position: vec3<f32>,
+    // The half angle of the cone particles are emitted in, in radians.
+    spread: f32,
+    direction: vec3<f32>,
+    speed: f32,
+    gravity: vec3<f32>,
+    lifetime: f32,
+    start_color: vec4<f32>,
+    end_color: vec4<f32>,
+    camera_right: vec3<f32>,
+    size: f32,
+    camera_up: vec3<f32>,
+    delta_time: f32,
+    // The slot of the first particle emitted this frame, the others following it around the
+    // ring.
+    emit_start: u32,
+    emit_count: u32,
+    capacity: u32,
+    seed: u32,
+    camera_position: vec3<f32>,
+    // The length of `order`, the capacity rounded up to a power of two.
+    order_len: u32,
+}
+
+// Dead once `age` reaches `lifetime`, which zeroed particles already are.
+struct Particle {
+    position: vec3<f32>,
+    age: f32,
+    velocity: vec3<f32>,
+    lifetime: f32,
+}
+
+#ifdef COMPUTE
+@group(0) @binding(0)
+var<uniform> emitter: Emitter;
+@group(1) @binding(0)
+var<storage, read_write> particles: array<Particle>;
+// Sort keys in `x` and particle indices in `y`, sorted by `GpuSort` for alpha blending.
+@group(1) @binding(1)
+var<storage, read_write> order: array<vec2<u32>>;
+
+// PCG hash, see "Hash Functions for GPU Rendering" by Jarzynski and Olano.
+fn hash(value: u32) -> u32 {
+    let state = value * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+// A uniform random number in [0, 1), advancing `state`.
+fn random(state: ptr<function, u32>) -> f32 {
+    *state = hash(*state);
+    return f32(*state >> 8u) / 16777216.0;
+}
+
+@compute @workgroup_size(64)
+fn emit(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= emitter.emit_count {
+        return;
+    }
+    let slot = (emitter.emit_start + id.x) % emitter.capacity;
+    var state = hash(emitter.seed ^ hash(slot));
+
+    // A direction in the cone around the emitter's, uniform over the cap of the unit sphere.
+    let cos_theta = mix(1.0, cos(emitter.spread), random(&state));
+    let sin_theta = sqrt(max(1.0 - cos_theta * cos_theta, 0.0));
+    let phi = random(&state) * 6.2831853;
+    let axis = normalize(emitter.direction);
+    let helper = select(vec3<f32>(1.0, 0.0, 0.0), vec3<f32>(0.0, 1.0, 0.0), abs(axis.x) > 0.9);
+    let tangent = normalize(cross(helper, axis));
+    let bitangent = cross(axis, tangent);
+    let direction = axis * cos_theta
+        + (tangent * cos(phi) + bitangent * sin(phi)) * sin_theta;
+
+    var particle: Particle;
+    particle.position = emitter.position;
+    particle.age = 0.0;
+    particle.velocity = direction * emitter.speed * mix(0.8, 1.0, random(&state));
+    particle.lifetime = emitter.lifetime * mix(0.75, 1.0, random(&state));
+    particles[slot] = particle;
+}
+
+@compute @workgroup_size(64)
+fn update(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= emitter.capacity {
+        return;
+    }
+    var particle = particles[id.x];
+    if particle.age >= particle.lifetime {
+        return;
+    }
+    particle.velocity += emitter.gravity * emitter.delta_time;
+    particle.position += particle.velocity * emitter.delta_time;
+    particle.age += emitter.delta_time;
+    particles[id.x] = particle;
+}
+
+// Keys each particle by its view depth, furthest first so that ascending keys draw back to
+// front, and dead particles and the padding last.
+@compute @workgroup_size(64)
+fn write_keys(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= emitter.order_len {
+        return;
+    }
+    var key = 0xffffffffu;
+    if id.x < emitter.capacity {
+        let particle = particles[id.x];
+        if particle.age < particle.lifetime {
+            let forward = cross(emitter.camera_up, emitter.camera_right);
+            let depth = max(dot(particle.position - emitter.camera_position, forward), 0.0);
+            // The bits of positive floats are ordered like them.
+            key = 0xfffffffeu - bitcast<u32>(depth);
+        }
+    }
+    order[id.x] = vec2<u32>(key, id.x);
+}
+#else
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+@group(1) @binding(0)
+var<uniform> emitter: Emitter;
+@group(2) @binding(0)
+var<storage, read> particles: array<Particle>;
+@group(2) @binding(1)
+var<storage, read> order: array<vec2<u32>>;
+
+struct VertexOut {
+    @builtin(position) position: vec4<f32>,
+    // From -1 to 1 across the quad.
+    @location(0) corner: vec2<f32>,
+    @location(1) color: vec4<f32>,
+    @location(2) clip: vec4<f32>,
+    @location(3) previous_clip: vec4<f32>,
+}
+
+struct FragmentOut {
+    @location(0) color: vec4<f32>,
+    @location(1) motion: vec2<f32>,
+}
+
+// The corners of the two triangles of a quad.
+const CORNERS = array<vec2<f32>, 6>(
+    vec2<f32>(-1.0, -1.0),
+    vec2<f32>(1.0, -1.0),
+    vec2<f32>(1.0, 1.0),
+    vec2<f32>(-1.0, -1.0),
+    vec2<f32>(1.0, 1.0),
+    vec2<f32>(-1.0, 1.0),
+);
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) vertex_index: u32,
+    @builtin(instance_index) instance_index: u32,
+) -> VertexOut {
+    let particle = particles[order[instance_index].y];
+    var out: VertexOut;
+    if particle.age >= particle.lifetime {
+        // Every corner at the same point, which draws nothing.
+        out.position = vec4<f32>(0.0, 0.0, 2.0, 1.0);
+        return out;
+    }
+    // Only arrays in variables can be indexed with a value known at run time.
+    var corners = CORNERS;
+    let corner = corners[vertex_index];
+    let offset = (emitter.camera_right * corner.x + emitter.camera_up * corner.y)
+        * emitter.size * 0.5;
+    let position = vec4<f32>(particle.position + offset, 1.0);
+    let clip = camera.view_proj * position;
+    // Jittered like the scene pass, so that TAA resolves the particles too.
+    out.position = clip + vec4<f32>(camera.jitter * clip.w, 0.0, 0.0);
+    out.corner = corner;
+    out.color = mix(emitter.start_color, emitter.end_color, particle.age / particle.lifetime);
+    out.clip = clip;
+    out.previous_clip = camera.previous_view_proj * position;
+    return out;
+}
+
+@fragment
+fn fs_main(pin: VertexOut) -> FragmentOut {
+    // A soft disc rather than a square.
+    let alpha = pin.color.a * smoothstep(1.0, 0.5, length(pin.corner));
+    return FragmentOut(
+        vec4<f32>(pin.color.rgb, alpha),
+        motion_vector(pin.clip, pin.previous_clip),
+    );
+}
+#endif
@@ -0,0 +1,42 @@
+// Writes the index of each instance plus one into an ID buffer, zero being the background, so
+// that the instance under the cursor can be read back.
+
+#include "common.wgsl"
+
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+struct VertexIn {
+    @location(0) position: vec3<f32>,
+}
+
+struct InstanceIn {
+    @location(5) model_0: vec4<f32>,
+    @location(6) model_1: vec4<f32>,
+    @location(7) model_2: vec4<f32>,
+    @location(8) model_3: vec4<f32>,
+}
+
+struct VertexOut {
+    @builtin(position) position: vec4<f32>,
+    @location(0) @interpolate(flat) id: u32,
+}
+
+@vertex
+fn vs_main(
+    vin: VertexIn,
+    instance: InstanceIn,
+    @builtin(instance_index) instance_index: u32,
+) -> VertexOut {
+    let model = mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+    var out: VertexOut;
+    // Unjittered, the cursor points at the image the jitter converges to.
+    out.position = camera.view_proj * model * vec4<f32>(vin.position, 1.0);
+    out.id = instance_index + 1u;
+    return out;
+}
+
+@fragment
+fn fs_main(pin: VertexOut) -> @location(0) u32 {
+    return pin.id;
+}
@@ -0,0 +1,43 @@
+// Renders the distance of the scene from a point light into one face of its shadow cube map.
+
+struct PointShadowFace {
+    view_proj: mat4x4<f32>,
+    light_position: vec3<f32>,
+    range: f32,
+}
+
+@group(0) @binding(0)
+var<uniform> face: PointShadowFace;
+
+struct VertexIn {
+    @location(0) position: vec3<f32>,
+}
+
+struct InstanceIn {
+    @location(5) model_0: vec4<f32>,
+    @location(6) model_1: vec4<f32>,
+    @location(7) model_2: vec4<f32>,
+    @location(8) model_3: vec4<f32>,
+}
+
+struct VertexOut {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+}
+
+@vertex
+fn vs_main(vin: VertexIn, instance: InstanceIn) -> VertexOut {
+    let model = mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+    let world_position = model * vec4<f32>(vin.position, 1.0);
+    var vout: VertexOut;
+    vout.clip_position = face.view_proj * world_position;
+    vout.world_position = world_position.xyz;
+    return vout;
+}
+
+// The distance to the light rather than the depth along the face's axis, so the same comparison
+// works whichever face a direction falls on.
+@fragment
+fn fs_main(vout: VertexOut) -> @builtin(frag_depth) f32 {
+    return saturate(distance(vout.world_position, face.light_position) / face.range);
+}
@@ -0,0 +1,40 @@
+// Renders the depth and world-space normals of the scene ahead of the scene pass, for the
+// screen-space passes that feed into its lighting.
+
+#include "common.wgsl"
+
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+struct VertexIn {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+}
+
+struct InstanceIn {
+    @location(5) model_0: vec4<f32>,
+    @location(6) model_1: vec4<f32>,
+    @location(7) model_2: vec4<f32>,
+    @location(8) model_3: vec4<f32>,
+}
+
+struct VertexOut {
+    @builtin(position) position: vec4<f32>,
+    @location(0) normal: vec3<f32>,
+}
+
+@vertex
+fn vs_main(vin: VertexIn, instance: InstanceIn) -> VertexOut {
+    let model = mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+    let clip = camera.view_proj * model * vec4<f32>(vin.position, 1.0);
+    var out: VertexOut;
+    // Jittered like the scene pass, so that both see the same pixels.
+    out.position = clip + vec4<f32>(camera.jitter * clip.w, 0.0, 0.0);
+    out.normal = (model * vec4<f32>(vin.normal, 0.0)).xyz;
+    return out;
+}
+
+@fragment
+fn fs_main(pin: VertexOut) -> @location(0) vec4<f32> {
+    return vec4<f32>(normalize(pin.normal), 1.0);
+}
@@ -1,18 +1,533 @@
+#include "common.wgsl"
+#include "lighting.wgsl"
+
+// The factors of a glTF metallic-roughness material, multiplied with its textures.
+struct MaterialParams {
+    base_color: vec4<f32>,
+    emissive: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+    normal_scale: f32,
+    occlusion_strength: f32,
+    // The first of the material's five textures in the bindless arrays.
+    texture_index: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> globals: Globals;
+// The slot of the material being drawn in the frame's uniform arena, at a dynamic offset.
+@group(0) @binding(1)
+var<uniform> material: MaterialParams;
+
+@group(1) @binding(0)
+var<uniform> camera: Camera;
+
+#ifdef SKINNED
+#define MAX_JOINTS 128
+// The matrices moving vertices from the pose they were modelled in into the current one, one
+// per joint.
+#ifdef JOINTS_IN_UNIFORM
+@group(1) @binding(1)
+var<uniform> joint_palette: array<mat4x4<f32>, MAX_JOINTS>;
+#else
+@group(1) @binding(1)
+var<storage, read> joint_palette: array<mat4x4<f32>>;
+#endif
+
+#ifdef MORPH_TARGETS
+#define MAX_MORPH_TARGETS 8
+// How far a morph target moves a vertex at full weight.
+struct MorphDelta {
+    position: vec3<f32>,
+    normal: vec3<f32>,
+}
+
+struct Morph {
+    // `MAX_MORPH_TARGETS` weights, four to a vector.
+    weights: array<vec4<f32>, 2>,
+    // The index of the mesh's first vertex, which vertex indices count from.
+    base_vertex: u32,
+    vertex_count: u32,
+    target_count: u32,
+}
+
+// The deltas of every vertex for the first target, then the second and so on.
+@group(1) @binding(2)
+var<storage, read> morph_deltas: array<MorphDelta>;
+@group(1) @binding(3)
+var<uniform> morph: Morph;
+#endif
+#endif
+
+#ifdef TERRAIN
+#define MAX_TERRAIN_LAYERS 4
+// A texture splatted where the terrain's height and slope fall within ranges.
+struct TerrainLayer {
+    // The world heights the layer covers in `x` and `y`, and the slopes in `z` and `w`.
+    ranges: vec4<f32>,
+    // World units one repetition of the texture spans.
+    scale: f32,
+    roughness: f32,
+}
+
+struct Terrain {
+    layers: array<TerrainLayer, MAX_TERRAIN_LAYERS>,
+    layer_count: u32,
+    // How far past the ends of their ranges layers fade out.
+    height_blend: f32,
+    slope_blend: f32,
+}
+
+// The terrain's layers, in place of a material's textures.
+@group(2) @binding(0)
+var<uniform> terrain: Terrain;
+@group(2) @binding(1)
+var t_terrain: texture_2d_array<f32>;
+@group(2) @binding(2)
+var s_terrain: sampler;
+#else
+#ifdef BINDLESS
+// The textures of every material, five per material in the order of the classic bindings.
+@group(2) @binding(0)
+var textures: binding_array<texture_2d<f32>>;
+@group(2) @binding(1)
+var samplers: binding_array<sampler>;
+#else
+@group(2) @binding(1)
+var t_base_color: texture_2d<f32>;
+@group(2) @binding(2)
+var s_base_color: sampler;
+@group(2) @binding(3)
+var t_metallic_roughness: texture_2d<f32>;
+@group(2) @binding(4)
+var s_metallic_roughness: sampler;
+@group(2) @binding(5)
+var t_normal: texture_2d<f32>;
+@group(2) @binding(6)
+var s_normal: sampler;
+@group(2) @binding(7)
+var t_occlusion: texture_2d<f32>;
+@group(2) @binding(8)
+var s_occlusion: sampler;
+@group(2) @binding(9)
+var t_emissive: texture_2d<f32>;
+@group(2) @binding(10)
+var s_emissive: sampler;
+#endif
+#endif
+
+@group(3) @binding(0)
+var<uniform> light: DirectionalLight;
+#ifdef LIGHTS_IN_UNIFORM
+@group(3) @binding(1)
+var<uniform> light_list: LightList;
+#else
+@group(3) @binding(1)
+var<storage, read> light_list: LightList;
+#endif
+@group(3) @binding(2)
+var<uniform> shadow: ShadowParams;
+@group(3) @binding(3)
+var t_shadow: texture_depth_2d_array;
+@group(3) @binding(4)
+var s_shadow: sampler_comparison;
+#ifdef POINT_SHADOWS_IN_CUBE
+@group(3) @binding(5)
+var t_point_shadow: texture_depth_cube;
+#else
+@group(3) @binding(5)
+var t_point_shadow: texture_depth_cube_array;
+#endif
+@group(3) @binding(6)
+var t_irradiance: texture_cube<f32>;
+@group(3) @binding(7)
+var t_prefiltered: texture_cube<f32>;
+@group(3) @binding(8)
+var t_brdf_lut: texture_2d<f32>;
+@group(3) @binding(9)
+var s_ibl: sampler;
+// Screen-space ambient occlusion, white when disabled.
+@group(3) @binding(10)
+var t_ambient_occlusion: texture_2d<f32>;
+
+struct VertexIn {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) color: vec3<f32>,
+    @location(3) uv: vec2<f32>,
+    @location(4) tangent: vec4<f32>,
+}
+
+struct InstanceIn {
+    @location(5) model_0: vec4<f32>,
+    @location(6) model_1: vec4<f32>,
+    @location(7) model_2: vec4<f32>,
+    @location(8) model_3: vec4<f32>,
+    @location(9) color: vec4<f32>,
+    @location(10) previous_model_0: vec4<f32>,
+    @location(11) previous_model_1: vec4<f32>,
+    @location(12) previous_model_2: vec4<f32>,
+    @location(13) previous_model_3: vec4<f32>,
+}
+
+#ifdef SKINNED
+struct SkinIn {
+    @location(14) joints: vec4<u32>,
+    @location(15) weights: vec4<f32>,
+}
+
+// The vertex moved along with the joints it follows, or as it is without weights.
+fn skin_vertex(vin: VertexIn, skin: SkinIn) -> VertexIn {
+    let total = dot(skin.weights, vec4<f32>(1.0));
+    if total <= 0.0 {
+        return vin;
+    }
+    let last = u32(MAX_JOINTS) - 1u;
+    let joints = min(skin.joints, vec4<u32>(last));
+    let weights = skin.weights / total;
+    let joint_matrix = joint_palette[joints.x] * weights.x
+        + joint_palette[joints.y] * weights.y
+        + joint_palette[joints.z] * weights.z
+        + joint_palette[joints.w] * weights.w;
+    var out = vin;
+    out.position = (joint_matrix * vec4<f32>(vin.position, 1.0)).xyz;
+    // Joints are only rotated, translated and uniformly scaled, like instances.
+    out.normal = (joint_matrix * vec4<f32>(vin.normal, 0.0)).xyz;
+    out.tangent = vec4<f32>((joint_matrix * vec4<f32>(vin.tangent.xyz, 0.0)).xyz, vin.tangent.w);
+    return out;
+}
+
+#ifdef MORPH_TARGETS
+// The vertex moved by every morph target, scaled by its weight.
+fn morph_vertex(vin: VertexIn, vertex_index: u32) -> VertexIn {
+    let vertex = vertex_index - morph.base_vertex;
+    var out = vin;
+    for (var i = 0u; i < min(morph.target_count, u32(MAX_MORPH_TARGETS)); i++) {
+        let weight = morph.weights[i / 4u][i % 4u];
+        if weight == 0.0 {
+            continue;
+        }
+        let delta = morph_deltas[i * morph.vertex_count + vertex];
+        out.position += delta.position * weight;
+        out.normal += delta.normal * weight;
+    }
+    return out;
+}
+#endif
+#endif
+
 struct VertexOut {
     @builtin(position) position: vec4<f32>,
-    @location(0) color: vec3<f32>,
+    @location(0) normal: vec3<f32>,
+    @location(1) color: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(3) world_position: vec3<f32>,
+    @location(4) tangent: vec4<f32>,
+    // Unjittered, for the motion vectors.
+    @location(5) clip: vec4<f32>,
+    @location(6) previous_clip: vec4<f32>,
+    // The alpha of the instance's color, below one while crossfading between levels of detail.
+    @location(7) @interpolate(flat) fade: f32,
 }
 
+struct FragmentOut {
+    @location(0) color: vec4<f32>,
+    @location(1) motion: vec2<f32>,
+}
 
 @vertex
-fn vs_main(@location(0) position: vec2<f32>, @location(1) color: vec3<f32>) -> VertexOut {
+#ifdef SKINNED
+#ifdef MORPH_TARGETS
+fn vs_main(
+    bind_pose: VertexIn,
+    instance: InstanceIn,
+    skin: SkinIn,
+    @builtin(vertex_index) vertex_index: u32,
+) -> VertexOut {
+    // Morphed first, in the pose the joints' inverse bind matrices start from.
+    let vin = skin_vertex(morph_vertex(bind_pose, vertex_index), skin);
+#else
+fn vs_main(bind_pose: VertexIn, instance: InstanceIn, skin: SkinIn) -> VertexOut {
+    let vin = skin_vertex(bind_pose, skin);
+#endif
+#else
+fn vs_main(vin: VertexIn, instance: InstanceIn) -> VertexOut {
+#endif
+    let model = mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+    let previous_model = mat4x4<f32>(
+        instance.previous_model_0,
+        instance.previous_model_1,
+        instance.previous_model_2,
+        instance.previous_model_3,
+    );
+    let world_position = model * vec4<f32>(vin.position, 1.0);
     var out: VertexOut;
-    out.position = vec4<f32>(position, 0.0, 1.0);
-    out.color = color;
+    out.clip = camera.view_proj * world_position;
+    out.previous_clip = camera.previous_view_proj * previous_model * vec4<f32>(vin.position, 1.0);
+    out.position = out.clip + vec4<f32>(camera.jitter * out.clip.w, 0.0, 0.0);
+    out.world_position = world_position.xyz;
+    // Instances are only rotated, translated and uniformly scaled.
+    out.normal = (model * vec4<f32>(vin.normal, 0.0)).xyz;
+    out.tangent = vec4<f32>((model * vec4<f32>(vin.tangent.xyz, 0.0)).xyz, vin.tangent.w);
+    out.color = vin.color * instance.color.rgb;
+    out.fade = instance.color.a;
+    out.uv = vin.uv;
     return out;
 }
 
+// Transforms a normal read from the normal texture from tangent space to world space.
+fn perturb_normal(normal: vec3<f32>, tangent: vec4<f32>, tangent_normal: vec3<f32>) -> vec3<f32> {
+    let n = normalize(normal);
+    // Vertices without tangents have no tangent space to map into.
+    if dot(tangent.xyz, tangent.xyz) < 1e-8 {
+        return n;
+    }
+    // Re-orthogonalized, since interpolation skews the basis.
+    let t = normalize(tangent.xyz - n * dot(n, tangent.xyz));
+    let b = cross(n, t) * tangent.w;
+    let scaled = tangent_normal * vec3<f32>(material.normal_scale, material.normal_scale, 1.0);
+    return normalize(t * scaled.x + b * scaled.y + n * scaled.z);
+}
+
+// The index of the cascade covering `depth` along the camera's view, `SHADOW_CASCADES` past
+// the last one.
+fn shadow_cascade(depth: f32) -> u32 {
+    for (var i = 0u; i < u32(SHADOW_CASCADES); i++) {
+        if depth < shadow.cascades[i].split {
+            return i;
+        }
+    }
+    return u32(SHADOW_CASCADES);
+}
+
+// How much of the directional light reaches `position` according to `cascade`, filtered with
+// 3x3 comparison taps.
+fn cascade_visibility(cascade: u32, position: vec3<f32>, normal: vec3<f32>) -> f32 {
+    // Moving along the normal keeps surfaces from shadowing themselves at grazing angles.
+    let offset_position = position + normal * shadow.cascades[cascade].normal_offset;
+    let clip = shadow.cascades[cascade].view_proj * vec4<f32>(offset_position, 1.0);
+    let ndc = clip.xyz / clip.w;
+    let uv = ndc.xy * vec2<f32>(0.5, -0.5) + 0.5;
+    // Everything outside the light's view is lit.
+    if any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) || ndc.z > 1.0 {
+        return 1.0;
+    }
+
+    var visibility = 0.0;
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let offset = vec2<f32>(f32(x), f32(y)) * shadow.texel_size;
+            visibility += textureSampleCompareLevel(t_shadow, s_shadow, uv + offset, cascade, ndc.z - shadow.bias);
+        }
+    }
+    return visibility / 9.0;
+}
+
+// How much of the directional light reaches `position`, from 0 in full shadow to 1.
+fn shadow_visibility(position: vec3<f32>, normal: vec3<f32>) -> f32 {
+    if shadow.enabled == 0u {
+        return 1.0;
+    }
+    let depth = dot(position - camera.position.xyz, shadow.camera_forward.xyz);
+    let cascade = shadow_cascade(depth);
+    if cascade >= u32(SHADOW_CASCADES) {
+        return 1.0;
+    }
+    let visibility = cascade_visibility(cascade, position, normal);
+
+    // Fades into the next cascade over the last tenth of this one, hiding the seam between
+    // their resolutions. The last cascade fades out to unshadowed.
+    var start = 0.0;
+    if cascade > 0u {
+        start = shadow.cascades[cascade - 1u].split;
+    }
+    let end = shadow.cascades[cascade].split;
+    let blend = smoothstep(end - (end - start) * 0.1, end, depth);
+    if blend <= 0.0 {
+        return visibility;
+    }
+    var next = 1.0;
+    if cascade + 1u < u32(SHADOW_CASCADES) {
+        next = cascade_visibility(cascade + 1u, position, normal);
+    }
+    return mix(visibility, next, blend);
+}
+
+// How much of a point or spot light reaches `position`, comparing its distance to the light
+// with the one stored in the light's shadow cube map.
+fn point_shadow_visibility(light: Light, position: vec3<f32>, normal: vec3<f32>) -> f32 {
+    if shadow.enabled == 0u || light.shadow < 0 {
+        return 1.0;
+    }
+    // Texels grow with the distance from the light, and so does the normal offset.
+    let texel = 2.0 * distance(position, light.position) / f32(POINT_SHADOW_MAP_SIZE);
+    let to_position = position + normal * texel * 1.5 - light.position;
+    let reference = length(to_position) / light.range - shadow.bias;
+#ifdef POINT_SHADOWS_IN_CUBE
+    return textureSampleCompareLevel(t_point_shadow, s_shadow, to_position, reference);
+#else
+    return textureSampleCompareLevel(t_point_shadow, s_shadow, to_position, light.shadow, reference);
+#endif
+}
+
+// Red, green, blue and yellow for each cascade in turn, white past the last one.
+fn cascade_tint(position: vec3<f32>) -> vec3<f32> {
+    var tints = array<vec3<f32>, 5>(
+        vec3<f32>(1.0, 0.3, 0.3),
+        vec3<f32>(0.3, 1.0, 0.3),
+        vec3<f32>(0.3, 0.3, 1.0),
+        vec3<f32>(1.0, 1.0, 0.3),
+        vec3<f32>(1.0),
+    );
+    let depth = dot(position - camera.position.xyz, shadow.camera_forward.xyz);
+    return tints[min(shadow_cascade(depth), 4u)];
+}
+
+// The environment reflected towards the eye: the irradiance cube map lights the diffuse lobe,
+// and the prefiltered mip matching the roughness the specular lobe, scaled by the BRDF lookup
+// table.
+fn ambient_light(surface: Surface) -> vec3<f32> {
+    let n_dot_v = max(dot(surface.normal, surface.view), 0.0001);
+    let f0 = mix(vec3<f32>(0.04), surface.base_color, surface.metallic);
+    // Rough surfaces reflect less at grazing angles than the plain Schlick term says.
+    let f = f0 + (max(vec3<f32>(1.0 - surface.roughness), f0) - f0) * pow(1.0 - n_dot_v, 5.0);
+    let kd = (1.0 - f) * (1.0 - surface.metallic);
+    let diffuse = textureSampleLevel(t_irradiance, s_ibl, surface.normal, 0.0).rgb * surface.base_color;
+
+    let r = reflect(-surface.view, surface.normal);
+    let level = surface.roughness * f32(PREFILTERED_MIPS - 1);
+    let prefiltered = textureSampleLevel(t_prefiltered, s_ibl, r, level).rgb;
+    // The table's rows run from smooth at the top to rough at the bottom.
+    let lut = textureSampleLevel(t_brdf_lut, s_ibl, vec2<f32>(n_dot_v, surface.roughness), 0.0).rg;
+    let specular = prefiltered * (f0 * lut.x + lut.y);
+    return kd * diffuse + specular;
+}
+
+#ifdef TERRAIN
+// One within `range`, fading out to zero over `blend` past either end.
+fn coverage(value: f32, range: vec2<f32>, blend: f32) -> f32 {
+    let fade = max(blend, 1e-4);
+    return saturate(1.0 + min(value - range.x, range.y - value) / fade);
+}
+
+// The colors of the layers covering the terrain at `pin` blended by how much each covers it,
+// with their roughness in alpha.
+fn splat(pin: VertexOut) -> vec4<f32> {
+    let height = pin.world_position.y;
+    let slope = 1.0 - normalize(pin.normal).y;
+    var total = 0.0;
+    var splatted = vec4<f32>(0.0);
+    // Every layer is sampled, as sampling needs uniform control flow.
+    for (var i = 0u; i < min(terrain.layer_count, u32(MAX_TERRAIN_LAYERS)); i++) {
+        let layer = terrain.layers[i];
+        let uv = pin.world_position.xz / layer.scale;
+        let color = textureSample(t_terrain, s_terrain, uv, i).rgb;
+        // The first layer shows wherever none covers the terrain.
+        let weight = coverage(height, layer.ranges.xy, terrain.height_blend)
+            * coverage(slope, layer.ranges.zw, terrain.slope_blend)
+            + select(0.0, 1e-4, i == 0u);
+        splatted += vec4<f32>(color, layer.roughness) * weight;
+        total += weight;
+    }
+    return splatted / max(total, 1e-4);
+}
+#endif
+
+// The lit color of the surface, with the alpha of its base color.
+fn shade(pin: VertexOut) -> vec4<f32> {
+#ifdef TERRAIN
+    let splatted = splat(pin);
+    let base_color = vec4<f32>(splatted.rgb * pin.color, 1.0);
+    let occlusion = 1.0;
+    let emissive = vec3<f32>(0.0);
+
+    var surface: Surface;
+    surface.base_color = base_color.rgb;
+    surface.metallic = 0.0;
+    surface.roughness = saturate(splatted.a);
+    surface.normal = normalize(pin.normal);
+#else
+    // Roughness is stored in green and metalness in blue.
+#ifdef BINDLESS
+    // The index is the same for the whole draw, so it doesn't need non-uniform indexing.
+    let first = material.texture_index;
+    let base_texel = textureSample(textures[first], samplers[first], pin.uv);
+    let metallic_roughness = textureSample(textures[first + 1u], samplers[first + 1u], pin.uv);
+    let normal_texel = textureSample(textures[first + 2u], samplers[first + 2u], pin.uv);
+    let occlusion_texel = textureSample(textures[first + 3u], samplers[first + 3u], pin.uv);
+    let emissive_texel = textureSample(textures[first + 4u], samplers[first + 4u], pin.uv);
+#else
+    let base_texel = textureSample(t_base_color, s_base_color, pin.uv);
+    let metallic_roughness = textureSample(t_metallic_roughness, s_metallic_roughness, pin.uv);
+    let normal_texel = textureSample(t_normal, s_normal, pin.uv);
+    let occlusion_texel = textureSample(t_occlusion, s_occlusion, pin.uv);
+    let emissive_texel = textureSample(t_emissive, s_emissive, pin.uv);
+#endif
+    let base_color = base_texel * material.base_color * vec4<f32>(pin.color, 1.0);
+    let occlusion = occlusion_texel.r;
+    let emissive = emissive_texel.rgb * material.emissive;
+    let tangent_normal = normal_texel.xyz * 2.0 - 1.0;
+
+    var surface: Surface;
+    surface.base_color = base_color.rgb;
+    surface.metallic = saturate(material.metallic * metallic_roughness.b);
+    surface.roughness = saturate(material.roughness * metallic_roughness.g);
+    surface.normal = perturb_normal(pin.normal, pin.tangent, tangent_normal);
+#endif
+    surface.view = normalize(camera.position.xyz - pin.world_position);
+
+    let ambient_occlusion = mix(1.0, occlusion, material.occlusion_strength)
+        * textureLoad(t_ambient_occlusion, vec2<i32>(pin.position.xy), 0).r;
+    var color = ambient_light(surface) * light.ambient * ambient_occlusion;
+    color += directional_light(light, surface) * shadow_visibility(pin.world_position, normalize(pin.normal));
+    for (var i = 0u; i < min(light_list.count, u32(MAX_LIGHTS)); i++) {
+        let point_light = light_list.lights[i];
+        color += punctual_light(point_light, surface, pin.world_position)
+            * point_shadow_visibility(point_light, pin.world_position, normalize(pin.normal));
+    }
+    color += emissive;
+    if shadow.debug_cascades != 0u {
+        color *= cascade_tint(pin.world_position);
+    }
+    return vec4<f32>(color, base_color.a);
+}
+
+@fragment
+fn fs_main(pin: VertexOut) -> FragmentOut {
+    if !lod_fade_visible(pin.fade, pin.position.xy) {
+        discard;
+    }
+    let color = shade(pin).rgb;
+    return FragmentOut(vec4<f32>(color, 1.0), motion_vector(pin.clip, pin.previous_clip));
+}
+
+// Like `fs_main`, keeping the alpha of the base color for alpha blending.
+@fragment
+fn fs_blend(pin: VertexOut) -> FragmentOut {
+    if !lod_fade_visible(pin.fade, pin.position.xy) {
+        discard;
+    }
+    return FragmentOut(shade(pin), motion_vector(pin.clip, pin.previous_clip));
+}
+
+struct OitOut {
+    // Premultiplied color and alpha, weighted and summed over every surface.
+    @location(0) accum: vec4<f32>,
+    // The product of one minus each surface's alpha, through the blend state.
+    @location(1) revealage: f32,
+}
+
+// Weighted blended order-independent transparency, see "Weighted Blended Order-Independent
+// Transparency" by McGuire and Bavoil. Surfaces nearer the camera weigh more, which stands in
+// for sorting them.
 @fragment
-fn fs_main(pin: VertexOut) -> @location(0) vec4<f32> {
-    return vec4<f32>(pin.color, 1.0);
-}
\ No newline at end of file
+fn fs_oit(pin: VertexOut) -> OitOut {
+    if !lod_fade_visible(pin.fade, pin.position.xy) {
+        discard;
+    }
+    let color = shade(pin);
+    let alpha = color.a;
+    let depth = 1.0 - pin.position.z;
+    let weight = clamp(alpha * max(1e-2, 3e3 * depth * depth * depth), 1e-2, 3e3);
+    return OitOut(vec4<f32>(color.rgb * alpha, alpha) * weight, alpha);
+}
@@ -0,0 +1,25 @@
+// Renders the depth of the scene as seen from the directional light.
+
+struct ShadowPass {
+    view_proj: mat4x4<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> shadow: ShadowPass;
+
+struct VertexIn {
+    @location(0) position: vec3<f32>,
+}
+
+struct InstanceIn {
+    @location(5) model_0: vec4<f32>,
+    @location(6) model_1: vec4<f32>,
+    @location(7) model_2: vec4<f32>,
+    @location(8) model_3: vec4<f32>,
+}
+
+@vertex
+fn vs_main(vin: VertexIn, instance: InstanceIn) -> @builtin(position) vec4<f32> {
+    let model = mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+    return shadow.view_proj * model * vec4<f32>(vin.position, 1.0);
+}
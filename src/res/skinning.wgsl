@@ -0,0 +1,100 @@
+// Skins the vertices of a mesh with its joint palette, after moving them by its morph targets,
+// into the vertices it's drawn with, so that it's drawn like any other mesh afterwards.
+
+#define MAX_MORPH_TARGETS 8
+// The words of a `Vertex`, and of a `SkinnedVertex`: a `Vertex` followed by the four joints it
+// follows and their four weights.
+#define VERTEX_WORDS 15u
+#define SKINNED_VERTEX_WORDS 23u
+
+// How far a morph target moves a vertex at full weight.
+struct MorphDelta {
+    position: vec3<f32>,
+    normal: vec3<f32>,
+}
+
+struct Morph {
+    // `MAX_MORPH_TARGETS` weights, four to a vector.
+    weights: array<vec4<f32>, 2>,
+    // The index of the mesh's first vertex in `vertices`.
+    base_vertex: u32,
+    vertex_count: u32,
+    target_count: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> morph: Morph;
+// The mesh's vertices in the pose they were modelled in, with their skins.
+@group(0) @binding(1)
+var<storage, read> bind_pose: array<f32>;
+// The matrices moving vertices from the pose they were modelled in into the current one, one
+// per joint.
+@group(0) @binding(2)
+var<storage, read> joint_palette: array<mat4x4<f32>>;
+// The deltas of every vertex for the first target, then the second and so on.
+@group(0) @binding(3)
+var<storage, read> morph_deltas: array<MorphDelta>;
+// The vertex buffer the mesh is allocated from, shared with other meshes.
+@group(0) @binding(4)
+var<storage, read_write> vertices: array<f32>;
+
+fn read_vec3(offset: u32) -> vec3<f32> {
+    return vec3<f32>(bind_pose[offset], bind_pose[offset + 1u], bind_pose[offset + 2u]);
+}
+
+fn read_vec4(offset: u32) -> vec4<f32> {
+    return vec4<f32>(read_vec3(offset), bind_pose[offset + 3u]);
+}
+
+fn write_vec3(offset: u32, value: vec3<f32>) {
+    vertices[offset] = value.x;
+    vertices[offset + 1u] = value.y;
+    vertices[offset + 2u] = value.z;
+}
+
+@compute @workgroup_size(64)
+fn skin(@builtin(global_invocation_id) id: vec3<u32>) {
+    let vertex = id.x;
+    if vertex >= morph.vertex_count {
+        return;
+    }
+    let source = vertex * SKINNED_VERTEX_WORDS;
+    var position = read_vec3(source);
+    var normal = read_vec3(source + 3u);
+    var tangent = read_vec4(source + 11u);
+
+    for (var i = 0u; i < min(morph.target_count, u32(MAX_MORPH_TARGETS)); i++) {
+        let weight = morph.weights[i / 4u][i % 4u];
+        if weight == 0.0 {
+            continue;
+        }
+        let delta = morph_deltas[i * morph.vertex_count + vertex];
+        position += delta.position * weight;
+        normal += delta.normal * weight;
+    }
+
+    let joints = bitcast<vec4<u32>>(read_vec4(source + VERTEX_WORDS));
+    let weights = read_vec4(source + VERTEX_WORDS + 4u);
+    // Vertices without weights don't move with the skeleton.
+    let total = dot(weights, vec4<f32>(1.0));
+    if total > 0.0 {
+        let last = arrayLength(&joint_palette) - 1u;
+        let clamped = min(joints, vec4<u32>(last));
+        let normalized = weights / total;
+        let joint_matrix = joint_palette[clamped.x] * normalized.x
+            + joint_palette[clamped.y] * normalized.y
+            + joint_palette[clamped.z] * normalized.z
+            + joint_palette[clamped.w] * normalized.w;
+        position = (joint_matrix * vec4<f32>(position, 1.0)).xyz;
+        // Joints are only rotated, translated and uniformly scaled, like instances.
+        normal = (joint_matrix * vec4<f32>(normal, 0.0)).xyz;
+        tangent = vec4<f32>((joint_matrix * vec4<f32>(tangent.xyz, 0.0)).xyz, tangent.w);
+    }
+
+    // The color and UV are left as uploaded.
+    let output = (morph.base_vertex + vertex) * VERTEX_WORDS;
+    write_vec3(output, position);
+    write_vec3(output + 3u, normal);
+    write_vec3(output + 11u, tangent.xyz);
+    vertices[output + 14u] = tangent.w;
+}
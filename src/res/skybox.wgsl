@@ -0,0 +1,44 @@
+#include "common.wgsl"
+
+struct Sky {
+    inverse_view_proj: mat4x4<f32>,
+    // Without the camera's translation, like `inverse_view_proj`.
+    previous_view_proj: mat4x4<f32>,
+}
+
+struct FragmentOut {
+    @location(0) color: vec4<f32>,
+    @location(1) motion: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> sky: Sky;
+@group(0) @binding(1)
+var t_sky: texture_cube<f32>;
+@group(0) @binding(2)
+var s_sky: sampler;
+
+struct VertexOut {
+    @builtin(position) position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+}
+
+// A single triangle covering the whole viewport, on the far plane.
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOut {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOut;
+    out.ndc = vec2<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+    out.position = vec4<f32>(out.ndc, 1.0, 1.0);
+    return out;
+}
+
+@fragment
+fn fs_main(pin: VertexOut) -> FragmentOut {
+    // The camera sits at the origin, so the point on the far plane is the view direction.
+    let far = sky.inverse_view_proj * vec4<f32>(pin.ndc, 1.0, 1.0);
+    let direction = far.xyz / far.w;
+    let color = vec4<f32>(textureSampleLevel(t_sky, s_sky, direction, 0.0).rgb, 1.0);
+    let previous_clip = sky.previous_view_proj * vec4<f32>(direction, 1.0);
+    return FragmentOut(color, motion_vector(vec4<f32>(pin.ndc, 1.0, 1.0), previous_clip));
+}
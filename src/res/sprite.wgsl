@@ -0,0 +1,37 @@
+// Draws the quads `SpriteBatch` builds, over the post-processed frame.
+
+#include "common.wgsl"
+
+@group(0) @binding(0)
+var<uniform> view_proj: mat4x4<f32>;
+
+@group(1) @binding(0)
+var sprite_texture: texture_2d<f32>;
+@group(1) @binding(1)
+var sprite_sampler: sampler;
+
+struct VertexIn {
+    @location(0) position: vec2<f32>,
+    @location(1) uv: vec2<f32>,
+    @location(2) color: vec4<f32>,
+}
+
+struct VertexOut {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+@vertex
+fn vs_main(vin: VertexIn) -> VertexOut {
+    var out: VertexOut;
+    out.position = view_proj * vec4<f32>(vin.position, 0.0, 1.0);
+    out.uv = vin.uv;
+    out.color = vin.color;
+    return out;
+}
+
+@fragment
+fn fs_main(pin: VertexOut) -> @location(0) vec4<f32> {
+    return output_color(textureSample(sprite_texture, sprite_sampler, pin.uv) * pin.color);
+}
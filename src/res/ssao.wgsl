@@ -0,0 +1,75 @@
+// Screen-space ambient occlusion: counts how many points of a hemisphere around each pixel's
+// surface are hidden behind the depth buffer. The noisy result is smoothed by `ssao_blur.wgsl`.
+
+#include "fullscreen.wgsl"
+
+#define KERNEL_SIZE 16
+#define NOISE_SIZE 4
+
+struct Ssao {
+    projection: mat4x4<f32>,
+    inverse_projection: mat4x4<f32>,
+    view: mat4x4<f32>,
+    // Points in a unit hemisphere around +z, denser towards the center.
+    kernel: array<vec4<f32>, KERNEL_SIZE>,
+    // The radius of the hemisphere in world units.
+    radius: f32,
+    // How much closer than a sample the depth buffer must be to occlude it, against acne.
+    bias: f32,
+    // The power the unoccluded fraction is raised to, darkening it when above 1.
+    intensity: f32,
+}
+
+@group(0) @binding(0)
+var<uniform> params: Ssao;
+// Bound as a float texture, as GL can't load texels from depth textures.
+@group(0) @binding(1)
+var t_depth: texture_2d<f32>;
+@group(0) @binding(2)
+var t_normal: texture_2d<f32>;
+// Random rotations of the kernel around the normal, tiled over the screen.
+@group(0) @binding(3)
+var t_noise: texture_2d<f32>;
+
+fn view_position(uv: vec2<f32>, depth: f32) -> vec3<f32> {
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    let position = params.inverse_projection * ndc;
+    return position.xyz / position.w;
+}
+
+@fragment
+fn fs_main(pin: VertexOut) -> @location(0) vec4<f32> {
+    let size = vec2<i32>(textureDimensions(t_depth));
+    let pixel = vec2<i32>(pin.position.xy);
+    let depth = textureLoad(t_depth, pixel, 0).r;
+    // Nothing was drawn here.
+    if depth >= 1.0 {
+        return vec4<f32>(1.0);
+    }
+    let position = view_position(pin.uv, depth);
+    let normal = normalize((params.view * vec4<f32>(textureLoad(t_normal, pixel, 0).xyz, 0.0)).xyz);
+
+    let random = textureLoad(t_noise, pixel % NOISE_SIZE, 0).xyz;
+    let tangent = normalize(random - normal * dot(random, normal));
+    let tbn = mat3x3<f32>(tangent, cross(normal, tangent), normal);
+
+    var occlusion = 0.0;
+    for (var i = 0; i < KERNEL_SIZE; i++) {
+        let offset = position + tbn * params.kernel[i].xyz * params.radius;
+        let clip = params.projection * vec4<f32>(offset, 1.0);
+        let uv = clip.xy / clip.w * vec2<f32>(0.5, -0.5) + 0.5;
+        if any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) {
+            continue;
+        }
+        let sample_pixel = clamp(vec2<i32>(uv * vec2<f32>(size)), vec2<i32>(0), size - 1);
+        let scene = view_position(uv, textureLoad(t_depth, sample_pixel, 0).r);
+        // Geometry far in front of the surface doesn't occlude it.
+        let range = smoothstep(0.0, 1.0, params.radius / abs(position.z - scene.z));
+        // The view looks down -z.
+        if scene.z >= offset.z + params.bias {
+            occlusion += range;
+        }
+    }
+    let ambient = pow(1.0 - occlusion / f32(KERNEL_SIZE), params.intensity);
+    return vec4<f32>(ambient, ambient, ambient, 1.0);
+}
@@ -0,0 +1,25 @@
+// One half of a separable 9-tap Gaussian blur of the ambient occlusion.
+
+#include "fullscreen.wgsl"
+
+// Whether the blur runs along rows rather than columns.
+override BLUR_HORIZONTAL: bool = true;
+
+@group(0) @binding(0)
+var t_input: texture_2d<f32>;
+
+@fragment
+fn fs_main(pin: VertexOut) -> @location(0) vec4<f32> {
+    var weights = array<f32, 5>(0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+    let size = vec2<i32>(textureDimensions(t_input));
+    let pixel = vec2<i32>(pin.position.xy);
+    let direction = select(vec2<i32>(0, 1), vec2<i32>(1, 0), BLUR_HORIZONTAL);
+    var ambient = textureLoad(t_input, pixel, 0).r * weights[0];
+    for (var i = 1; i < 5; i++) {
+        let before = clamp(pixel - direction * i, vec2<i32>(0), size - 1);
+        let after = clamp(pixel + direction * i, vec2<i32>(0), size - 1);
+        ambient += (textureLoad(t_input, before, 0).r
+            + textureLoad(t_input, after, 0).r) * weights[i];
+    }
+    return vec4<f32>(ambient, ambient, ambient, 1.0);
+}
@@ -0,0 +1,62 @@
+// Temporal anti-aliasing: blends each jittered frame into a history of the previous ones,
+// reprojected along the motion vectors and clamped to the current neighbourhood so that
+// disoccluded and changed pixels don't ghost.
+
+#include "common.wgsl"
+#include "fullscreen.wgsl"
+
+struct Taa {
+    // The weight of the current frame, 1 discarding the history.
+    blend: f32,
+}
+
+@group(0) @binding(0)
+var t_input: texture_2d<f32>;
+@group(0) @binding(1)
+var s_input: sampler;
+@group(0) @binding(2)
+var t_history: texture_2d<f32>;
+@group(0) @binding(3)
+var t_motion: texture_2d<f32>;
+@group(0) @binding(4)
+var<uniform> params: Taa;
+
+struct FragmentOut {
+    @location(0) color: vec4<f32>,
+    // The same color, kept for the next frame.
+    @location(1) history: vec4<f32>,
+}
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+@fragment
+fn fs_main(pin: VertexOut) -> FragmentOut {
+    let size = vec2<i32>(textureDimensions(t_input));
+    let pixel = vec2<i32>(pin.position.xy);
+    let current = textureLoad(t_input, pixel, 0).rgb;
+
+    // The history is only trusted within the range of the 3x3 neighbourhood.
+    var minimum = current;
+    var maximum = current;
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let neighbour = textureLoad(t_input, clamp(pixel + vec2<i32>(x, y), vec2<i32>(0), size - 1), 0).rgb;
+            minimum = min(minimum, neighbour);
+            maximum = max(maximum, neighbour);
+        }
+    }
+
+    let history_uv = pin.uv - textureLoad(t_motion, pixel, 0).xy;
+    var color = current;
+    let on_screen = all(history_uv >= vec2<f32>(0.0)) && all(history_uv <= vec2<f32>(1.0));
+    if on_screen && params.blend < 1.0 {
+        let history = clamp(textureSampleLevel(t_history, s_input, history_uv, 0.0).rgb, minimum, maximum);
+        // Weighting by inverse luminance keeps single bright samples from flickering.
+        let current_weight = params.blend / (1.0 + luminance(current));
+        let history_weight = (1.0 - params.blend) / (1.0 + luminance(history));
+        color = (current * current_weight + history * history_weight) / (current_weight + history_weight);
+    }
+    return FragmentOut(output_color(vec4<f32>(color, 1.0)), vec4<f32>(color, 1.0));
+}
@@ -0,0 +1,90 @@
+// Maps HDR colors into the displayable range.
+
+#include "common.wgsl"
+#include "fullscreen.wgsl"
+
+#define TONEMAP_REINHARD 1u
+#define TONEMAP_AGX 2u
+
+struct Tonemap {
+    // A linear scale.
+    exposure: f32,
+    tonemapper: u32,
+}
+
+@group(0) @binding(0)
+var t_input: texture_2d<f32>;
+@group(0) @binding(1)
+var s_input: sampler;
+@group(0) @binding(2)
+var<uniform> params: Tonemap;
+
+// Stephen Hill's fit of the ACES reference rendering and output transforms.
+fn aces(color: vec3<f32>) -> vec3<f32> {
+    let input = mat3x3<f32>(
+        vec3<f32>(0.59719, 0.07600, 0.02840),
+        vec3<f32>(0.35458, 0.90834, 0.13383),
+        vec3<f32>(0.04823, 0.01566, 0.83777),
+    );
+    let output = mat3x3<f32>(
+        vec3<f32>(1.60475, -0.10208, -0.00327),
+        vec3<f32>(-0.53108, 1.10813, -0.07276),
+        vec3<f32>(-0.07367, -0.00605, 1.07602),
+    );
+    let v = input * color;
+    let a = v * (v + 0.0245786) - 0.000090537;
+    let b = v * (0.983729 * v + 0.4329510) + 0.238081;
+    return saturate(output * (a / b));
+}
+
+fn reinhard(color: vec3<f32>) -> vec3<f32> {
+    let luminance = dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+    return color / (1.0 + luminance);
+}
+
+// The polynomial fit of the AgX contrast curve, for log-encoded values from 0 to 1.
+fn agx_contrast(x: vec3<f32>) -> vec3<f32> {
+    let x2 = x * x;
+    let x4 = x2 * x2;
+    return 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2
+        + 0.1191 * x - 0.00232;
+}
+
+// Troy Sobotka's AgX with the default look, after Benjamin Wrensch's minimal version.
+fn agx(color: vec3<f32>) -> vec3<f32> {
+    let inset = mat3x3<f32>(
+        vec3<f32>(0.842479062253094, 0.0423282422610123, 0.0423756549057051),
+        vec3<f32>(0.0784335999999992, 0.878468636469772, 0.0784336),
+        vec3<f32>(0.0792237451477643, 0.0791661274605434, 0.879142973793104),
+    );
+    let outset = mat3x3<f32>(
+        vec3<f32>(1.19687900512017, -0.0528968517574562, -0.0529716355144438),
+        vec3<f32>(-0.0980208811401368, 1.15190312990417, -0.0980434501171241),
+        vec3<f32>(-0.0990297440797205, -0.0989611768448433, 1.15107367264116),
+    );
+    let min_ev = -12.47393;
+    let max_ev = 4.026069;
+    let encoded = clamp(log2(max(inset * color, vec3<f32>(1e-10))), vec3<f32>(min_ev), vec3<f32>(max_ev));
+    let curve = agx_contrast((encoded - min_ev) / (max_ev - min_ev));
+    // The curve outputs display-encoded values, decoded back to linear for `output_color`.
+    return pow(max(outset * curve, vec3<f32>(0.0)), vec3<f32>(2.2));
+}
+
+@fragment
+fn fs_main(pin: VertexOut) -> @location(0) vec4<f32> {
+    let color = max(textureSample(t_input, s_input, pin.uv).rgb, vec3<f32>(0.0)) * params.exposure;
+    var mapped: vec3<f32>;
+    switch params.tonemapper {
+        case TONEMAP_REINHARD: {
+            mapped = reinhard(color);
+        }
+        case TONEMAP_AGX: {
+            mapped = agx(color);
+        }
+        // ACES.
+        default: {
+            mapped = aces(color);
+        }
+    }
+    return output_color(vec4<f32>(mapped, 1.0));
+}
@@ -0,0 +1,292 @@
+//! The scene as entities with components, and the systems turning them into what the renderer
+//! draws.
+//!
+//! Entities are placed by a [`Transform`] relative to their [`Parent`], which
+//! [`propagate_transforms`] resolves into a [`GlobalTransform`]. [`extract`] then collects
+//! every [`MeshRenderer`], [`Light`] and the first [`Camera`] into a [`RenderQueue`] for the
+//! [`Renderer`].
+
+use std::collections::HashMap;
+
+use glam::{Mat4, Quat, Vec3};
+pub use hecs::{Entity, World};
+
+use crate::camera::Camera;
+use crate::instance::Instance;
+use crate::light::{Light, LightKind};
+use crate::math::{Aabb, Obb, Ray};
+use crate::mesh::MeshData;
+use crate::renderer::Renderer;
+
+/// Parent chains deeper than this are cut off, which also stops cycles.
+const MAX_DEPTH: usize = 64;
+
+/// The placement of an entity relative to its [`Parent`], or to the world without one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            translation: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            scale: Vec3::ONE,
+        }
+    }
+}
+
+impl Transform {
+    pub fn from_translation(translation: Vec3) -> Self {
+        Self {
+            translation,
+            ..Self::default()
+        }
+    }
+
+    /// Decomposes `matrix`, which must not be skewed.
+    pub fn from_matrix(matrix: Mat4) -> Self {
+        let (scale, rotation, translation) = matrix.to_scale_rotation_translation();
+        Self {
+            translation,
+            rotation,
+            scale,
+        }
+    }
+
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+}
+
+/// The placement of an entity in the world, written by [`propagate_transforms`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GlobalTransform(pub Mat4);
+
+impl Default for GlobalTransform {
+    fn default() -> Self {
+        Self(Mat4::IDENTITY)
+    }
+}
+
+/// The entity whose [`GlobalTransform`] an entity's [`Transform`] is relative to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Parent(pub Entity);
+
+/// Draws the renderer's mesh at the entity, as one of its instances.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MeshRenderer {
+    /// Multiplied with the vertex colors.
+    pub color: Vec3,
+}
+
+impl Default for MeshRenderer {
+    fn default() -> Self {
+        Self { color: Vec3::ONE }
+    }
+}
+
+/// Resolves the [`GlobalTransform`] of every entity with a [`Transform`], adding it where it is
+/// missing. A parent without a transform counts as the origin.
+pub fn propagate_transforms(world: &mut World) {
+    let missing: Vec<Entity> = world
+        .query::<&Transform>()
+        .without::<&GlobalTransform>()
+        .iter()
+        .map(|(entity, _)| entity)
+        .collect();
+    for entity in missing {
+        // The entity was just returned by the query.
+        let _ = world.insert_one(entity, GlobalTransform::default());
+    }
+
+    let locals: HashMap<Entity, (Mat4, Option<Entity>)> = world
+        .query_mut::<(&Transform, Option<&Parent>)>()
+        .into_iter()
+        .map(|(entity, (transform, parent))| {
+            (entity, (transform.matrix(), parent.map(|parent| parent.0)))
+        })
+        .collect();
+    let mut globals = HashMap::with_capacity(locals.len());
+    for &entity in locals.keys() {
+        global_matrix(entity, &locals, &mut globals, 0);
+    }
+    for (entity, global) in world.query_mut::<&mut GlobalTransform>() {
+        if let Some(&matrix) = globals.get(&entity) {
+            global.0 = matrix;
+        }
+    }
+}
+
+fn global_matrix(
+    entity: Entity,
+    locals: &HashMap<Entity, (Mat4, Option<Entity>)>,
+    globals: &mut HashMap<Entity, Mat4>,
+    depth: usize,
+) -> Mat4 {
+    if let Some(&global) = globals.get(&entity) {
+        return global;
+    }
+    let Some(&(local, parent)) = locals.get(&entity) else {
+        return Mat4::IDENTITY;
+    };
+    let parent = match parent {
+        Some(parent) if depth < MAX_DEPTH => global_matrix(parent, locals, globals, depth + 1),
+        _ => Mat4::IDENTITY,
+    };
+    let global = parent * local;
+    globals.insert(entity, global);
+    global
+}
+
+/// The closest entity with a [`MeshRenderer`] whose mesh, with the bounding box `bounds`, is hit
+/// by `ray`, tested against the box turned with the entity. Returns the distance along the ray.
+pub fn raycast(world: &World, ray: &Ray, bounds: Aabb) -> Option<(Entity, f32)> {
+    if bounds.is_empty() {
+        return None;
+    }
+    world
+        .query::<(&MeshRenderer, &GlobalTransform)>()
+        .iter()
+        .filter_map(|(entity, (_, global))| {
+            let distance = ray.intersect_obb(&Obb::from_aabb(&bounds, global.0))?;
+            Some((entity, distance))
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+}
+
+/// Like [`raycast`], but tests the triangles of `mesh` wherever the ray hits its bounding box.
+pub fn raycast_mesh(world: &World, ray: &Ray, mesh: &MeshData) -> Option<(Entity, f32)> {
+    let bounds = mesh.bounds().aabb;
+    if bounds.is_empty() {
+        return None;
+    }
+    world
+        .query::<(&MeshRenderer, &GlobalTransform)>()
+        .iter()
+        .filter(|(_, (_, global))| {
+            ray.intersect_obb(&Obb::from_aabb(&bounds, global.0))
+                .is_some()
+        })
+        .filter_map(|(entity, (_, global))| {
+            let local = ray.transformed(global.0.inverse());
+            let hit = local.at(mesh.intersect_ray(&local)?);
+            Some((entity, global.0.transform_point3(hit).distance(ray.origin)))
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+}
+
+/// What the renderer draws of a [`World`], see [`extract`].
+#[derive(Clone, Debug, Default)]
+pub struct RenderQueue {
+    pub instances: Vec<Instance>,
+    /// The entity of each of `instances`.
+    pub entities: Vec<Entity>,
+    /// Moved and turned by their entity.
+    pub lights: Vec<Light>,
+    /// The first camera, looking from its entity.
+    pub camera: Option<Camera>,
+}
+
+/// Collects what the renderer draws from the entities with a [`GlobalTransform`]. Instances are
+/// in a stable order as long as no components are added or removed, so that their motion
+/// vectors follow them.
+pub fn extract(world: &World) -> RenderQueue {
+    let mut queue = RenderQueue::default();
+    for (entity, (mesh_renderer, global)) in
+        world.query::<(&MeshRenderer, &GlobalTransform)>().iter()
+    {
+        queue.instances.push(Instance {
+            transform: global.0,
+            color: mesh_renderer.color,
+        });
+        queue.entities.push(entity);
+    }
+
+    queue.lights = world
+        .query::<(&Light, &GlobalTransform)>()
+        .iter()
+        .map(|(_, (light, global))| {
+            let kind = match light.kind {
+                LightKind::Point => LightKind::Point,
+                LightKind::Spot {
+                    direction,
+                    inner_angle,
+                    outer_angle,
+                } => LightKind::Spot {
+                    direction: global.0.transform_vector3(direction).normalize_or_zero(),
+                    inner_angle,
+                    outer_angle,
+                },
+            };
+            Light {
+                position: global.0.transform_point3(light.position),
+                kind,
+                ..*light
+            }
+        })
+        .collect();
+
+    queue.camera = world
+        .query::<(&Camera, &GlobalTransform)>()
+        .iter()
+        .next()
+        .map(|(_, (camera, global))| Camera {
+            eye: global.0.transform_point3(camera.eye),
+            target: global.0.transform_point3(camera.target),
+            up: global.0.transform_vector3(camera.up),
+            ..camera.clone()
+        });
+    queue
+}
+
+impl RenderQueue {
+    /// Hands the queue to `renderer`, only replacing the instances and lights that changed. Only
+    /// the view of the camera is taken, its projection belongs to the renderer.
+    pub fn submit(&self, renderer: &mut Renderer) {
+        if renderer.instances() != self.instances {
+            renderer.set_instances(&self.instances);
+        }
+        if renderer.lights() != self.lights {
+            renderer.set_lights(self.lights.clone());
+        }
+        if let Some(camera) = &self.camera {
+            let view = renderer.camera_mut();
+            view.eye = camera.eye;
+            view.target = camera.target;
+            view.up = camera.up;
+        }
+    }
+}
+
+/// The [`World`] the demo draws, with its systems run once per frame by [`Scene::update`].
+#[derive(Default)]
+pub struct Scene {
+    pub world: World,
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The first camera, which the scene is viewed through.
+    pub fn camera_mut(&mut self) -> Option<&mut Camera> {
+        self.world
+            .query_mut::<&mut Camera>()
+            .into_iter()
+            .next()
+            .map(|(_, camera)| camera)
+    }
+
+    /// Propagates the transforms and submits what changed to `renderer`.
+    pub fn update(&mut self, renderer: &mut Renderer) -> RenderQueue {
+        propagate_transforms(&mut self.world);
+        let queue = extract(&self.world);
+        queue.submit(renderer);
+        queue
+    }
+}
@@ -0,0 +1,168 @@
+//! Loading of the crate's WGSL shaders through the [`Preprocessor`], and of external WGSL,
+//! SPIR-V and GLSL shaders from disk.
+
+use std::path::Path;
+
+use anyhow::Context;
+use pollster::FutureExt;
+
+mod preprocessor;
+
+pub use preprocessor::{Preprocessor, ProcessedShader};
+
+/// The built-in shaders, embedded so that the binary doesn't depend on the source tree.
+pub const EMBEDDED_SHADERS: &[(&str, &str)] = &[
+    ("common.wgsl", include_str!("res/common.wgsl")),
+    ("lighting.wgsl", include_str!("res/lighting.wgsl")),
+    ("shader.wgsl", include_str!("res/shader.wgsl")),
+    ("shadow.wgsl", include_str!("res/shadow.wgsl")),
+    ("skybox.wgsl", include_str!("res/skybox.wgsl")),
+    ("point_shadow.wgsl", include_str!("res/point_shadow.wgsl")),
+    ("ibl.wgsl", include_str!("res/ibl.wgsl")),
+    ("fullscreen.wgsl", include_str!("res/fullscreen.wgsl")),
+    ("copy.wgsl", include_str!("res/copy.wgsl")),
+    ("tonemap.wgsl", include_str!("res/tonemap.wgsl")),
+    ("fxaa.wgsl", include_str!("res/fxaa.wgsl")),
+    ("taa.wgsl", include_str!("res/taa.wgsl")),
+    ("prepass.wgsl", include_str!("res/prepass.wgsl")),
+    ("ssao.wgsl", include_str!("res/ssao.wgsl")),
+    ("ssao_blur.wgsl", include_str!("res/ssao_blur.wgsl")),
+    (
+        "depth_of_field.wgsl",
+        include_str!("res/depth_of_field.wgsl"),
+    ),
+    ("motion_blur.wgsl", include_str!("res/motion_blur.wgsl")),
+    ("color_grading.wgsl", include_str!("res/color_grading.wgsl")),
+    ("lens_effects.wgsl", include_str!("res/lens_effects.wgsl")),
+    ("picking.wgsl", include_str!("res/picking.wgsl")),
+    ("debug_draw.wgsl", include_str!("res/debug_draw.wgsl")),
+    ("culling.wgsl", include_str!("res/culling.wgsl")),
+    ("occlusion.wgsl", include_str!("res/occlusion.wgsl")),
+    ("particles.wgsl", include_str!("res/particles.wgsl")),
+    ("gpu_sort.wgsl", include_str!("res/gpu_sort.wgsl")),
+    ("skinning.wgsl", include_str!("res/skinning.wgsl")),
+    ("cpu_particles.wgsl", include_str!("res/cpu_particles.wgsl")),
+    ("oit_composite.wgsl", include_str!("res/oit_composite.wgsl")),
+    ("msdf.wgsl", include_str!("res/msdf.wgsl")),
+    ("sprite.wgsl", include_str!("res/sprite.wgsl")),
+];
+
+/// A preprocessor resolving includes among [`EMBEDDED_SHADERS`].
+pub fn embedded_preprocessor() -> Preprocessor {
+    EMBEDDED_SHADERS
+        .iter()
+        .fold(Preprocessor::new(), |preprocessor, (name, code)| {
+            preprocessor.add_source(*name, *code)
+        })
+}
+
+/// Parses and validates the expanded code with naga, reporting errors at their location in
+/// the original files rather than in the expanded code.
+pub fn validate(shader: &ProcessedShader) -> anyhow::Result<()> {
+    let module = naga::front::wgsl::parse_str(&shader.code).map_err(|err| {
+        let location = err
+            .location(&shader.code)
+            .map(|location| shader.describe_location(location.line_number, location.line_position))
+            .unwrap_or_default();
+        anyhow::anyhow!("{location}: {}", err.message())
+    })?;
+
+    naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::all(),
+    )
+    .validate(&module)
+    .map_err(|err| {
+        let location = err
+            .location(&shader.code)
+            .map(|location| shader.describe_location(location.line_number, location.line_position))
+            .unwrap_or_default();
+        anyhow::anyhow!("{location}: {}", err.as_inner())
+    })?;
+
+    Ok(())
+}
+
+/// Expands, validates and compiles the shader called `name`.
+pub fn create_module(
+    device: &wgpu::Device,
+    preprocessor: &Preprocessor,
+    name: &str,
+) -> anyhow::Result<wgpu::ShaderModule> {
+    let shader = preprocessor.process(name)?;
+    validate(&shader)?;
+
+    Ok(device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some(name),
+        source: wgpu::ShaderSource::Wgsl(shader.code.into()),
+    }))
+}
+
+/// Loads a shader module from disk, picking the front-end by file extension:
+///
+/// - `.wgsl` is preprocessed with includes resolved next to the file,
+/// - `.spv` is a SPIR-V binary,
+/// - `.vert`, `.frag` and `.comp` are GLSL vertex, fragment and compute shaders. GLSL entry
+///   points are always called `main`.
+pub fn load_module(
+    device: &wgpu::Device,
+    path: impl AsRef<Path>,
+) -> anyhow::Result<wgpu::ShaderModule> {
+    let path = path.as_ref();
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .with_context(|| format!("invalid shader path {}", path.display()))?;
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+
+    let source = match extension.as_deref() {
+        Some("wgsl") => {
+            let directory = path.parent().unwrap_or(Path::new("."));
+            let preprocessor = Preprocessor::new().with_directory(directory);
+            return create_module(device, &preprocessor, name);
+        }
+        Some("spv") => {
+            let bytes = std::fs::read(path)
+                .with_context(|| format!("failed to read {}", path.display()))?;
+            if bytes.len() % 4 != 0 {
+                anyhow::bail!("{} is not a valid SPIR-V binary", path.display());
+            }
+            let words: Vec<u32> = bytes
+                .chunks_exact(4)
+                .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+                .collect();
+            wgpu::ShaderSource::SpirV(words.into())
+        }
+        Some(extension @ ("vert" | "frag" | "comp")) => {
+            let stage = match extension {
+                "vert" => naga::ShaderStage::Vertex,
+                "frag" => naga::ShaderStage::Fragment,
+                _ => naga::ShaderStage::Compute,
+            };
+            let shader = std::fs::read_to_string(path)
+                .with_context(|| format!("failed to read {}", path.display()))?;
+            wgpu::ShaderSource::Glsl {
+                shader: shader.into(),
+                stage,
+                defines: Default::default(),
+            }
+        }
+        _ => anyhow::bail!("unsupported shader type: {}", path.display()),
+    };
+
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some(name),
+        source,
+    });
+    match device.pop_error_scope().block_on() {
+        Some(err) => Err(anyhow::anyhow!(
+            "failed to compile {}: {err}",
+            path.display()
+        )),
+        None => Ok(module),
+    }
+}
@@ -0,0 +1,292 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+use anyhow::Context;
+
+/// Expands `#include "file.wgsl"`, `#define NAME value` and `#ifdef`/`#ifndef`/`#else`/`#endif`
+/// directives in WGSL sources.
+///
+/// Included files are looked up in a directory on disk when one is set, and otherwise among
+/// the sources registered with [`Preprocessor::add_source`]. Each file is included at most
+/// once. Defined names are replaced wherever they appear as a whole identifier.
+#[derive(Clone, Debug, Default)]
+pub struct Preprocessor {
+    directory: Option<PathBuf>,
+    sources: HashMap<String, String>,
+    defines: HashMap<String, String>,
+}
+
+/// The expanded source of a shader along with where each of its lines came from.
+#[derive(Clone, Debug)]
+pub struct ProcessedShader {
+    pub code: String,
+    source_map: Vec<(String, u32)>,
+}
+
+impl ProcessedShader {
+    /// The file name and 1-based line number a 1-based line of [`ProcessedShader::code`] was
+    /// expanded from.
+    pub fn original_location(&self, line: u32) -> Option<(&str, u32)> {
+        let (file, line) = self.source_map.get(line.checked_sub(1)? as usize)?;
+        Some((file, *line))
+    }
+
+    /// Formats a location in the expanded code as `file:line:column`.
+    pub fn describe_location(&self, line: u32, column: u32) -> String {
+        match self.original_location(line) {
+            Some((file, line)) => format!("{file}:{line}:{column}"),
+            None => format!("<expanded>:{line}:{column}"),
+        }
+    }
+}
+
+struct Conditional {
+    /// Whether lines are currently emitted at this nesting level.
+    active: bool,
+    /// Whether the enclosing level was active.
+    parent_active: bool,
+    seen_else: bool,
+}
+
+impl Preprocessor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Resolves included files by reading them from `directory`.
+    pub fn with_directory(mut self, directory: impl Into<PathBuf>) -> Self {
+        self.directory = Some(directory.into());
+        self
+    }
+
+    /// Registers an in-memory source, used when no directory is set.
+    pub fn add_source(mut self, name: impl Into<String>, code: impl Into<String>) -> Self {
+        self.sources.insert(name.into(), code.into());
+        self
+    }
+
+    /// Defines `name` for `#ifdef` checks and replaces it with `value` in the code.
+    pub fn define(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.defines.insert(name.into(), value.into());
+        self
+    }
+
+    fn read(&self, name: &str) -> anyhow::Result<String> {
+        match &self.directory {
+            Some(directory) => {
+                let path = directory.join(name);
+                std::fs::read_to_string(&path)
+                    .with_context(|| format!("failed to read {}", path.display()))
+            }
+            None => self
+                .sources
+                .get(name)
+                .cloned()
+                .with_context(|| format!("unknown shader source {name:?}")),
+        }
+    }
+
+    /// Expands the shader called `name` and everything it includes.
+    pub fn process(&self, name: &str) -> anyhow::Result<ProcessedShader> {
+        let mut shader = ProcessedShader {
+            code: String::new(),
+            source_map: Vec::new(),
+        };
+        let mut defines = self.defines.clone();
+        let mut included = HashSet::new();
+        self.expand(name, &mut shader, &mut defines, &mut included)?;
+        Ok(shader)
+    }
+
+    fn expand(
+        &self,
+        name: &str,
+        shader: &mut ProcessedShader,
+        defines: &mut HashMap<String, String>,
+        included: &mut HashSet<String>,
+    ) -> anyhow::Result<()> {
+        if !included.insert(name.to_owned()) {
+            return Ok(());
+        }
+        let source = self.read(name)?;
+
+        let mut conditionals: Vec<Conditional> = Vec::new();
+        for (index, line) in source.lines().enumerate() {
+            let line_number = index as u32 + 1;
+            let location = || format!("{name}:{line_number}");
+            let active = conditionals.last().is_none_or(|c| c.active);
+            let trimmed = line.trim();
+
+            let Some(directive) = trimmed.strip_prefix('#') else {
+                if active {
+                    shader.code.push_str(&substitute(line, defines));
+                    shader.code.push('\n');
+                    shader.source_map.push((name.to_owned(), line_number));
+                }
+                continue;
+            };
+
+            let (keyword, argument) = directive
+                .split_once(char::is_whitespace)
+                .map_or((directive, ""), |(keyword, argument)| {
+                    (keyword, argument.trim())
+                });
+            match keyword {
+                "ifdef" | "ifndef" => {
+                    let defined = defines.contains_key(argument);
+                    conditionals.push(Conditional {
+                        active: active && (defined == (keyword == "ifdef")),
+                        parent_active: active,
+                        seen_else: false,
+                    });
+                }
+                "else" => {
+                    let conditional = conditionals
+                        .last_mut()
+                        .filter(|c| !c.seen_else)
+                        .with_context(|| format!("{}: unexpected #else", location()))?;
+                    conditional.active = conditional.parent_active && !conditional.active;
+                    conditional.seen_else = true;
+                }
+                "endif" => {
+                    conditionals
+                        .pop()
+                        .with_context(|| format!("{}: unexpected #endif", location()))?;
+                }
+                _ if !active => {}
+                "define" => {
+                    let (define, value) = argument
+                        .split_once(char::is_whitespace)
+                        .map_or((argument, ""), |(define, value)| (define, value.trim()));
+                    if define.is_empty() {
+                        anyhow::bail!("{}: #define without a name", location());
+                    }
+                    defines.insert(define.to_owned(), value.to_owned());
+                }
+                "include" => {
+                    let file = argument
+                        .strip_prefix('"')
+                        .and_then(|argument| argument.strip_suffix('"'))
+                        .with_context(|| format!("{}: expected #include \"file\"", location()))?;
+                    self.expand(file, shader, defines, included)
+                        .with_context(|| format!("included from {}", location()))?;
+                }
+                _ => anyhow::bail!("{}: unknown directive #{keyword}", location()),
+            }
+        }
+
+        if !conditionals.is_empty() {
+            anyhow::bail!("{name}: missing #endif");
+        }
+        Ok(())
+    }
+}
+
+/// Replaces every identifier in `line` that is a key of `defines` with its value.
+fn substitute(line: &str, defines: &HashMap<String, String>) -> String {
+    if defines.is_empty() {
+        return line.to_owned();
+    }
+
+    let mut output = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(start) = rest.find(|c: char| c.is_alphabetic() || c == '_') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        let identifier = &rest[..end];
+        output.push_str(defines.get(identifier).map_or(identifier, String::as_str));
+        rest = &rest[end..];
+    }
+    output.push_str(rest);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(shader: &ProcessedShader) -> Vec<&str> {
+        shader.code.lines().collect()
+    }
+
+    #[test]
+    fn expands_nested_includes() {
+        let preprocessor = Preprocessor::new()
+            .add_source("main.wgsl", "#include \"a.wgsl\"\nmain")
+            .add_source("a.wgsl", "a\n#include \"b.wgsl\"")
+            .add_source("b.wgsl", "b");
+        let shader = preprocessor.process("main.wgsl").unwrap();
+        assert_eq!(lines(&shader), ["a", "b", "main"]);
+        assert_eq!(shader.original_location(2), Some(("b.wgsl", 1)));
+        assert_eq!(shader.original_location(3), Some(("main.wgsl", 2)));
+        assert_eq!(shader.describe_location(1, 4), "a.wgsl:1:4");
+    }
+
+    #[test]
+    fn includes_each_file_once() {
+        let preprocessor = Preprocessor::new()
+            .add_source("a.wgsl", "#include \"b.wgsl\"\na")
+            .add_source("b.wgsl", "#include \"a.wgsl\"\n#include \"b.wgsl\"\nb");
+        let shader = preprocessor.process("a.wgsl").unwrap();
+        assert_eq!(lines(&shader), ["b", "a"]);
+    }
+
+    #[test]
+    fn missing_include_is_an_error() {
+        let preprocessor =
+            Preprocessor::new().add_source("main.wgsl", "main\n#include \"missing.wgsl\"");
+        let error = preprocessor.process("main.wgsl").unwrap_err();
+        assert_eq!(error.to_string(), "included from main.wgsl:2");
+        assert!(format!("{error:#}").contains("unknown shader source \"missing.wgsl\""));
+    }
+
+    #[test]
+    fn branches_on_defines() {
+        let source = "\
+#define LOCAL 2
+#ifdef GLOBAL
+global GLOBAL
+#ifndef LOCAL
+no local
+#else
+local LOCAL
+#endif
+#else
+no global
+#endif";
+        let preprocessor = Preprocessor::new().add_source("main.wgsl", source);
+        let shader = preprocessor.process("main.wgsl").unwrap();
+        assert_eq!(lines(&shader), ["no global"]);
+
+        let shader = preprocessor
+            .define("GLOBAL", "1")
+            .process("main.wgsl")
+            .unwrap();
+        assert_eq!(lines(&shader), ["global 1", "local 2"]);
+    }
+
+    #[test]
+    fn defines_replace_whole_identifiers() {
+        let preprocessor = Preprocessor::new()
+            .add_source("main.wgsl", "N N_2 M_N N+1")
+            .define("N", "4");
+        let shader = preprocessor.process("main.wgsl").unwrap();
+        assert_eq!(lines(&shader), ["4 N_2 M_N 4+1"]);
+    }
+
+    #[test]
+    fn unbalanced_conditionals_are_errors() {
+        for source in [
+            "#ifdef A",
+            "#endif",
+            "#else",
+            "#ifdef A\n#else\n#else\n#endif",
+        ] {
+            let preprocessor = Preprocessor::new().add_source("main.wgsl", source);
+            assert!(preprocessor.process("main.wgsl").is_err(), "{source:?}");
+        }
+    }
+}
@@ -0,0 +1,707 @@
+use glam::{Mat4, Vec3};
+use wgpu::util::DeviceExt;
+
+use crate::camera::Camera;
+use crate::instance::InstanceRaw;
+use crate::profiler::GpuProfiler;
+use crate::shader::{self, Preprocessor};
+use crate::texture::Texture;
+use crate::vertex::Vertex;
+
+/// The width and height of each cascade's shadow map in texels.
+pub const SHADOW_MAP_SIZE: u32 = 2048;
+/// The number of cascades splitting the camera's view, `SHADOW_CASCADES` in `lighting.wgsl`.
+pub const SHADOW_CASCADES: usize = 4;
+
+const PASS_NAMES: [&str; SHADOW_CASCADES] = ["shadow 0", "shadow 1", "shadow 2", "shadow 3"];
+
+/// The width and height of each face of a point shadow cube map in texels,
+/// `POINT_SHADOW_MAP_SIZE` in `lighting.wgsl`.
+pub const POINT_SHADOW_MAP_SIZE: u32 = 512;
+/// The most point and spot lights casting shadows at once, where cube map arrays are supported.
+pub const MAX_POINT_SHADOWS: usize = 4;
+
+const POINT_PASS_NAMES: [&str; MAX_POINT_SHADOWS] = [
+    "point shadow 0",
+    "point shadow 1",
+    "point shadow 2",
+    "point shadow 3",
+];
+/// The near plane of the cube faces, closer geometry casts no shadow.
+const POINT_SHADOW_NEAR: f32 = 0.05;
+/// The forward and up direction of each cube face, in the order of the layers of a cube map.
+/// Cube maps are left-handed, so the faces are rendered with left-handed views.
+const CUBE_FACES: [(Vec3, Vec3); 6] = [
+    (Vec3::X, Vec3::Y),
+    (Vec3::NEG_X, Vec3::Y),
+    (Vec3::Y, Vec3::NEG_Z),
+    (Vec3::NEG_Y, Vec3::Z),
+    (Vec3::Z, Vec3::Y),
+    (Vec3::NEG_Z, Vec3::Y),
+];
+
+/// One cascade of the light's view, `ShadowCascade` in `lighting.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct CascadeUniform {
+    view_proj: [[f32; 4]; 4],
+    /// The view depth where the cascade ends and the next one starts.
+    split: f32,
+    /// How far fragments are moved along their normal before looking them up, in world units.
+    normal_offset: f32,
+    _padding: [f32; 2],
+}
+
+/// The light's view of the scene, `ShadowParams` in `lighting.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ShadowUniform {
+    cascades: [CascadeUniform; SHADOW_CASCADES],
+    /// The camera's view direction, measuring the view depth that selects the cascade. `w` is
+    /// unused.
+    camera_forward: [f32; 4],
+    /// Subtracted from the fragment's depth in light space before comparing.
+    bias: f32,
+    /// The size of a texel in shadow map UVs, the spacing of the PCF taps.
+    texel_size: f32,
+    /// Zero when shadows are disabled and every fragment is lit.
+    enabled: u32,
+    /// Non-zero to tint the scene by the cascade each fragment is shadowed from.
+    debug_cascades: u32,
+}
+
+/// Renders one layer of a shadow map.
+struct ShadowPass {
+    view: wgpu::TextureView,
+    /// The layer's view of the scene, read by the shadow pass' vertex shader.
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+/// Cascaded shadow maps for the directional light, rendered in depth-only passes from the
+/// light's view and sampled with comparison sampling in the scene pass.
+///
+/// The camera's view is split into [`SHADOW_CASCADES`] slices up to the shadow distance, each
+/// covered by its own layer of the shadow map. Slices close to the camera are short, so nearby
+/// shadows get most of the resolution.
+pub struct ShadowMap {
+    uniform: ShadowUniform,
+    buffer: wgpu::Buffer,
+    /// All cascades, as a texture array.
+    view: wgpu::TextureView,
+    sampler: wgpu::Sampler,
+    pipeline: wgpu::RenderPipeline,
+    passes: Vec<ShadowPass>,
+    distance: f32,
+}
+
+impl ShadowMap {
+    pub fn new(device: &wgpu::Device, preprocessor: &Preprocessor) -> anyhow::Result<Self> {
+        let uniform = ShadowUniform {
+            cascades: [CascadeUniform {
+                view_proj: Mat4::IDENTITY.to_cols_array_2d(),
+                split: 0.0,
+                normal_offset: 0.0,
+                _padding: [0.0; 2],
+            }; SHADOW_CASCADES],
+            camera_forward: [0.0, 0.0, -1.0, 0.0],
+            bias: 0.0005,
+            texel_size: 1.0 / SHADOW_MAP_SIZE as f32,
+            enabled: 1,
+            debug_cascades: 0,
+        };
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("shadow"),
+            contents: bytemuck::bytes_of(&uniform),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("shadow map"),
+            size: wgpu::Extent3d {
+                width: SHADOW_MAP_SIZE,
+                height: SHADOW_MAP_SIZE,
+                depth_or_array_layers: SHADOW_CASCADES as u32,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Texture::DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        // Linear filtering blends the results of the four nearest comparisons, smoothing each
+        // PCF tap.
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("shadow map"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+
+        let pass_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("shadow pass"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            });
+        let passes = (0..SHADOW_CASCADES as u32)
+            .map(|layer| {
+                let view = texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some(PASS_NAMES[layer as usize]),
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    base_array_layer: layer,
+                    array_layer_count: Some(1),
+                    ..Default::default()
+                });
+                let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some(PASS_NAMES[layer as usize]),
+                    contents: bytemuck::bytes_of(&Mat4::IDENTITY.to_cols_array_2d()),
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                });
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some(PASS_NAMES[layer as usize]),
+                    layout: &pass_bind_group_layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: buffer.as_entire_binding(),
+                    }],
+                });
+                ShadowPass {
+                    view,
+                    buffer,
+                    bind_group,
+                }
+            })
+            .collect();
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("shadow pass"),
+            bind_group_layouts: &[&pass_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let shader_module = shader::create_module(device, preprocessor, "shadow.wgsl")?;
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("shadow pass"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader_module,
+                entry_point: "vs_main",
+                buffers: &[Vertex::layout(), InstanceRaw::layout()],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            primitive: wgpu::PrimitiveState {
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                // Pushes the stored depth back, more so on surfaces at grazing angles to the
+                // light, to keep them from shadowing themselves.
+                bias: wgpu::DepthBiasState {
+                    constant: 2,
+                    slope_scale: 2.0,
+                    clamp: 0.0,
+                },
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            fragment: None,
+            multiview: None,
+            cache: None,
+        });
+
+        Ok(Self {
+            uniform,
+            buffer,
+            view,
+            sampler,
+            pipeline,
+            passes,
+            distance: 50.0,
+        })
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.uniform.enabled != 0
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.uniform.enabled = enabled.into();
+    }
+
+    /// How far from the camera shadows reach, in world units.
+    pub fn distance(&self) -> f32 {
+        self.distance
+    }
+
+    pub fn set_distance(&mut self, distance: f32) {
+        self.distance = distance.max(0.01);
+    }
+
+    pub fn debug_cascades(&self) -> bool {
+        self.uniform.debug_cascades != 0
+    }
+
+    /// Tints the scene red, green, blue and yellow by the cascade each fragment is shadowed
+    /// from.
+    pub fn set_debug_cascades(&mut self, debug_cascades: bool) {
+        self.uniform.debug_cascades = debug_cascades.into();
+    }
+
+    /// The bind group entries sampling the shadow map in the scene pass: the parameters, the
+    /// depth texture array and the comparison sampler, at `binding` and the two following
+    /// bindings.
+    pub fn layout_entries(binding: u32) -> [wgpu::BindGroupLayoutEntry; 3] {
+        [
+            wgpu::BindGroupLayoutEntry {
+                binding,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: binding + 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Depth,
+                    view_dimension: wgpu::TextureViewDimension::D2Array,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: binding + 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                count: None,
+            },
+        ]
+    }
+
+    /// The resources for [`ShadowMap::layout_entries`].
+    pub fn bind_group_entries(&self, binding: u32) -> [wgpu::BindGroupEntry<'_>; 3] {
+        [
+            wgpu::BindGroupEntry {
+                binding,
+                resource: self.buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: binding + 1,
+                resource: wgpu::BindingResource::TextureView(&self.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: binding + 2,
+                resource: wgpu::BindingResource::Sampler(&self.sampler),
+            },
+        ]
+    }
+
+    /// Fits a cascade around each slice of `camera`'s view, with `direction` pointing towards
+    /// the light, and uploads them. Every cascade reaches back far enough towards the light to
+    /// catch the casters anywhere in the scene bounds `(min, max)`.
+    pub fn update(
+        &mut self,
+        queue: &wgpu::Queue,
+        camera: &Camera,
+        direction: Vec3,
+        (scene_min, scene_max): (Vec3, Vec3),
+    ) {
+        let direction = direction.try_normalize().unwrap_or(Vec3::Y);
+        let scene_center = (scene_min + scene_max) * 0.5;
+        let scene_radius = (scene_max - scene_min).length() * 0.5;
+        // Any up vector works as long as it isn't parallel to the view direction.
+        let up = if direction.y.abs() > 0.99 {
+            Vec3::Z
+        } else {
+            Vec3::Y
+        };
+        // Only the light's orientation, to snap cascades to its texel grid.
+        let light_rotation = Mat4::look_at_rh(Vec3::ZERO, -direction, up);
+        let inverse_camera_view = camera.view_matrix().inverse();
+        let tan_y = (camera.fovy * 0.5).tan();
+        let tan_x = tan_y * camera.aspect;
+
+        let near = camera.znear;
+        let far = self.distance.min(camera.zfar).max(near);
+        let mut split_near = near;
+        for (index, (cascade, pass)) in self
+            .uniform
+            .cascades
+            .iter_mut()
+            .zip(&self.passes)
+            .enumerate()
+        {
+            let split_far = split_distance(near, far, (index + 1) as f32 / SHADOW_CASCADES as f32);
+            let corners = [split_near, split_far].map(|depth| {
+                [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)].map(|(x, y)| {
+                    inverse_camera_view.transform_point3(Vec3::new(
+                        x * depth * tan_x,
+                        y * depth * tan_y,
+                        -depth,
+                    ))
+                })
+            });
+            let corners = corners.as_flattened();
+            let center = corners.iter().sum::<Vec3>() / corners.len() as f32;
+            // A sphere rather than a tight box keeps the cascade's size, and so the size of its
+            // texels, the same however the camera turns.
+            let radius = corners
+                .iter()
+                .map(|corner| corner.distance(center))
+                .fold(0.0, f32::max);
+            let radius = (radius * 16.0).ceil() / 16.0;
+            let texel = 2.0 * radius / SHADOW_MAP_SIZE as f32;
+
+            // Moving the cascade by whole texels keeps shadow edges from shimmering as the
+            // camera moves.
+            let light_center = light_rotation.transform_point3(center);
+            let snapped = (light_center.truncate() / texel).floor() * texel;
+            let center = light_rotation
+                .inverse()
+                .transform_point3(snapped.extend(light_center.z));
+
+            let depth = radius.max((scene_center - center).dot(direction) + scene_radius);
+            let view = Mat4::look_at_rh(center + direction * depth, center, up);
+            let projection =
+                Mat4::orthographic_rh(-radius, radius, -radius, radius, 0.0, depth + radius);
+            let view_proj = (projection * view).to_cols_array_2d();
+
+            cascade.view_proj = view_proj;
+            cascade.split = split_far;
+            // About a texel and a half, which covers the depth error of a texel-sized PCF tap.
+            cascade.normal_offset = 1.5 * texel;
+            queue.write_buffer(&pass.buffer, 0, bytemuck::bytes_of(&view_proj));
+            split_near = split_far;
+        }
+
+        let forward = (camera.target - camera.eye)
+            .try_normalize()
+            .unwrap_or(Vec3::NEG_Z);
+        self.uniform.camera_forward = forward.extend(0.0).to_array();
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&self.uniform));
+    }
+
+    /// Records a shadow pass per cascade, calling `draw` to draw the scene's geometry with the
+    /// pass' pipeline and bind group set.
+    pub fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        mut profiler: Option<&mut GpuProfiler>,
+        mut draw: impl FnMut(&mut wgpu::RenderPass),
+    ) {
+        for (pass, name) in self.passes.iter().zip(PASS_NAMES) {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some(name),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &pass.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes: profiler
+                    .as_deref_mut()
+                    .and_then(|profiler| profiler.timestamp_writes(name)),
+                occlusion_query_set: None,
+            });
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_bind_group(0, &pass.bind_group, &[]);
+            draw(&mut render_pass);
+        }
+    }
+}
+
+/// The view depth `fraction` of the way from `near` to `far`, between a logarithmic split,
+/// which gives every cascade the same resolution relative to depth, and an even one, which
+/// keeps the closest cascade from getting too short.
+fn split_distance(near: f32, far: f32, fraction: f32) -> f32 {
+    const LAMBDA: f32 = 0.75;
+    let logarithmic = near * (far / near).powf(fraction);
+    let uniform = near + (far - near) * fraction;
+    LAMBDA * logarithmic + (1.0 - LAMBDA) * uniform
+}
+
+/// One face of a point light's view, `PointShadowFace` in `point_shadow.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PointShadowFaceUniform {
+    view_proj: [[f32; 4]; 4],
+    light_position: [f32; 3],
+    range: f32,
+}
+
+/// Shadow cube maps for point and spot lights, one per shadowed light, each rendered in six
+/// depth passes. The faces store the distance to the light divided by its range rather than
+/// depth, so the scene pass compares distances whichever face a direction falls on.
+///
+/// The cube maps live in a cube map array, or in a single cube map where cube map arrays aren't
+/// supported, e.g. on WebGL2, which limits shadows to one light.
+pub struct PointShadows {
+    cube_array: bool,
+    /// All cube maps, as a cube or cube array texture.
+    view: wgpu::TextureView,
+    pipeline: wgpu::RenderPipeline,
+    /// Six passes per cube map, in cube face order.
+    faces: Vec<ShadowPass>,
+    /// The number of cube maps in use.
+    count: usize,
+}
+
+impl PointShadows {
+    pub fn new(
+        device: &wgpu::Device,
+        downlevel: &wgpu::DownlevelCapabilities,
+        preprocessor: &Preprocessor,
+    ) -> anyhow::Result<Self> {
+        let cube_array = downlevel
+            .flags
+            .contains(wgpu::DownlevelFlags::CUBE_ARRAY_TEXTURES);
+        let capacity = if cube_array { MAX_POINT_SHADOWS } else { 1 };
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("point shadow maps"),
+            size: wgpu::Extent3d {
+                width: POINT_SHADOW_MAP_SIZE,
+                height: POINT_SHADOW_MAP_SIZE,
+                depth_or_array_layers: capacity as u32 * 6,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Texture::DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(if cube_array {
+                wgpu::TextureViewDimension::CubeArray
+            } else {
+                wgpu::TextureViewDimension::Cube
+            }),
+            ..Default::default()
+        });
+
+        let pass_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("point shadow pass"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            });
+        let faces = (0..capacity as u32 * 6)
+            .map(|layer| {
+                let label = POINT_PASS_NAMES[layer as usize / 6];
+                let view = texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some(label),
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    base_array_layer: layer,
+                    array_layer_count: Some(1),
+                    ..Default::default()
+                });
+                let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some(label),
+                    size: std::mem::size_of::<PointShadowFaceUniform>() as u64,
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                });
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some(label),
+                    layout: &pass_bind_group_layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: buffer.as_entire_binding(),
+                    }],
+                });
+                ShadowPass {
+                    view,
+                    buffer,
+                    bind_group,
+                }
+            })
+            .collect();
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("point shadow pass"),
+            bind_group_layouts: &[&pass_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let shader_module = shader::create_module(device, preprocessor, "point_shadow.wgsl")?;
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("point shadow pass"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader_module,
+                entry_point: "vs_main",
+                buffers: &[Vertex::layout(), InstanceRaw::layout()],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            primitive: wgpu::PrimitiveState {
+                // The left-handed views mirror the faces, which flips the winding order.
+                front_face: wgpu::FrontFace::Cw,
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            // The fragment shader writes the depth, so a depth bias wouldn't apply; the scene
+            // pass biases its comparisons instead.
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader_module,
+                entry_point: "fs_main",
+                targets: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            multiview: None,
+            cache: None,
+        });
+
+        Ok(Self {
+            cube_array,
+            view,
+            pipeline,
+            faces,
+            count: 0,
+        })
+    }
+
+    /// How many lights can cast shadows at once.
+    pub fn capacity(&self) -> usize {
+        self.faces.len() / 6
+    }
+
+    /// Defines what `shader.wgsl` needs to match the texture's view dimension.
+    pub fn configure_preprocessor(&self, preprocessor: Preprocessor) -> Preprocessor {
+        if self.cube_array {
+            preprocessor
+        } else {
+            preprocessor.define("POINT_SHADOWS_IN_CUBE", "")
+        }
+    }
+
+    /// The bind group entry sampling the cube maps in the scene pass, with the directional
+    /// shadow map's comparison sampler.
+    pub fn layout_entry(&self, binding: u32) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Depth,
+                view_dimension: if self.cube_array {
+                    wgpu::TextureViewDimension::CubeArray
+                } else {
+                    wgpu::TextureViewDimension::Cube
+                },
+                multisampled: false,
+            },
+            count: None,
+        }
+    }
+
+    /// The resource for [`PointShadows::layout_entry`].
+    pub fn bind_group_entry(&self, binding: u32) -> wgpu::BindGroupEntry<'_> {
+        wgpu::BindGroupEntry {
+            binding,
+            resource: wgpu::BindingResource::TextureView(&self.view),
+        }
+    }
+
+    /// Places a cube map at each light's `(position, range)`, the `n`th light rendering into
+    /// cube map `n`. Lights past [`PointShadows::capacity`] are ignored.
+    pub fn update(&mut self, queue: &wgpu::Queue, lights: &[(Vec3, f32)]) {
+        self.count = lights.len().min(self.capacity());
+        for (&(position, range), faces) in lights.iter().zip(self.faces.chunks(6)) {
+            let range = range.max(POINT_SHADOW_NEAR * 2.0);
+            let projection =
+                Mat4::perspective_lh(std::f32::consts::FRAC_PI_2, 1.0, POINT_SHADOW_NEAR, range);
+            for (face, (forward, up)) in faces.iter().zip(CUBE_FACES) {
+                let view = Mat4::look_at_lh(position, position + forward, up);
+                let uniform = PointShadowFaceUniform {
+                    view_proj: (projection * view).to_cols_array_2d(),
+                    light_position: position.to_array(),
+                    range,
+                };
+                queue.write_buffer(&face.buffer, 0, bytemuck::bytes_of(&uniform));
+            }
+        }
+    }
+
+    /// Records the six passes of every cube map in use, calling `draw` to draw the scene's
+    /// geometry with the pass' pipeline and bind group set. Each cube map is timed as a whole.
+    pub fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        mut profiler: Option<&mut GpuProfiler>,
+        mut draw: impl FnMut(&mut wgpu::RenderPass),
+    ) {
+        for (faces, name) in self.faces.chunks(6).take(self.count).zip(POINT_PASS_NAMES) {
+            let timestamp_writes = profiler
+                .as_deref_mut()
+                .and_then(|profiler| profiler.timestamp_writes(name));
+            for (index, face) in faces.iter().enumerate() {
+                let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some(name),
+                    color_attachments: &[],
+                    depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                        view: &face.view,
+                        depth_ops: Some(wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(1.0),
+                            store: wgpu::StoreOp::Store,
+                        }),
+                        stencil_ops: None,
+                    }),
+                    // The first face starts the timer and the last one stops it.
+                    timestamp_writes: timestamp_writes.as_ref().map(|writes| {
+                        wgpu::RenderPassTimestampWrites {
+                            query_set: writes.query_set,
+                            beginning_of_pass_write_index: writes
+                                .beginning_of_pass_write_index
+                                .filter(|_| index == 0),
+                            end_of_pass_write_index: writes
+                                .end_of_pass_write_index
+                                .filter(|_| index == faces.len() - 1),
+                        }
+                    }),
+                    occlusion_query_set: None,
+                });
+                render_pass.set_pipeline(&self.pipeline);
+                render_pass.set_bind_group(0, &face.bind_group, &[]);
+                draw(&mut render_pass);
+            }
+        }
+    }
+}
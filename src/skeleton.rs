@@ -0,0 +1,140 @@
+//! Joint hierarchies, posed by animations, and the matrices skinned vertices are moved with.
+
+use glam::{Mat4, Quat, Vec3};
+
+/// The most joints a skeleton is drawn with, `MAX_JOINTS` in `shader.wgsl`.
+pub const MAX_JOINTS: usize = 128;
+
+/// The placement of a joint relative to its parent.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct JointTransform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Default for JointTransform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl JointTransform {
+    pub const IDENTITY: Self = Self {
+        translation: Vec3::ZERO,
+        rotation: Quat::IDENTITY,
+        scale: Vec3::ONE,
+    };
+
+    /// Decomposes `matrix`, which mustn't be skewed.
+    pub fn from_matrix(matrix: Mat4) -> Self {
+        let (scale, rotation, translation) = matrix.to_scale_rotation_translation();
+        Self {
+            translation,
+            rotation,
+            scale,
+        }
+    }
+
+    pub fn to_matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+
+    /// The transform `t` of the way from `self` to `other`, rotating along the shortest arc.
+    pub fn lerp(&self, other: &Self, t: f32) -> Self {
+        Self {
+            translation: self.translation.lerp(other.translation, t),
+            rotation: self.rotation.slerp(other.rotation, t),
+            scale: self.scale.lerp(other.scale, t),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Joint {
+    pub name: String,
+    /// An index into [`Skeleton::joints`], before this joint's.
+    pub parent: Option<usize>,
+    /// The placement of the joint when no animation moves it.
+    pub rest: JointTransform,
+    /// Moves vertices from the mesh's space into the joint's space in the pose the mesh was
+    /// modelled in.
+    pub inverse_bind: Mat4,
+    /// For joints without a parent joint, the transform of the nodes above them, identity
+    /// otherwise.
+    pub base: Mat4,
+}
+
+/// The joints of a skinned mesh, ordered so that parents come before their children.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Skeleton {
+    pub joints: Vec<Joint>,
+}
+
+/// The transform of every joint of a skeleton relative to its parent, in the same order, and
+/// the weights of the mesh's morph targets.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Pose {
+    pub joints: Vec<JointTransform>,
+    pub weights: Vec<f32>,
+}
+
+impl Pose {
+    /// Moves every joint and morph target weight `t` of the way towards those of `other`.
+    pub fn blend(&mut self, other: &Pose, t: f32) {
+        for (joint, other) in self.joints.iter_mut().zip(&other.joints) {
+            *joint = joint.lerp(other, t);
+        }
+        for (weight, other) in self.weights.iter_mut().zip(&other.weights) {
+            *weight += (other - *weight) * t;
+        }
+    }
+}
+
+impl Skeleton {
+    pub fn len(&self) -> usize {
+        self.joints.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.joints.is_empty()
+    }
+
+    /// The index of the joint called `name`.
+    pub fn joint(&self, name: &str) -> Option<usize> {
+        self.joints.iter().position(|joint| joint.name == name)
+    }
+
+    /// Every joint at its rest transform, without morph target weights.
+    pub fn rest_pose(&self) -> Pose {
+        Pose {
+            joints: self.joints.iter().map(|joint| joint.rest).collect(),
+            weights: Vec::new(),
+        }
+    }
+
+    /// The transform of every joint in `pose` from its space to the mesh's.
+    pub fn model_transforms(&self, pose: &Pose) -> Vec<Mat4> {
+        let mut transforms: Vec<Mat4> = Vec::with_capacity(self.joints.len());
+        for (joint, local) in self.joints.iter().zip(&pose.joints) {
+            let parent = joint
+                .parent
+                .and_then(|parent| transforms.get(parent).copied())
+                .unwrap_or(joint.base);
+            transforms.push(parent * local.to_matrix());
+        }
+        transforms
+    }
+
+    /// Replaces `palette` with the matrices moving vertices from the modelled pose into `pose`,
+    /// one per joint.
+    pub fn skinning_matrices(&self, pose: &Pose, palette: &mut Vec<Mat4>) {
+        palette.clear();
+        palette.extend(
+            self.model_transforms(pose)
+                .into_iter()
+                .zip(&self.joints)
+                .map(|(transform, joint)| transform * joint.inverse_bind),
+        );
+    }
+}
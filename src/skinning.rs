@@ -0,0 +1,576 @@
+//! Meshes deformed by the joints of a skeleton: each vertex follows up to four joints,
+//! weighted, through a palette of matrices uploaded every frame, after being moved by the mesh's
+//! morph targets.
+//!
+//! Where compute shaders are available, `skinning.wgsl` skins the vertices into a mesh of plain
+//! [`Vertex`]es ahead of the frame's passes, which then draw it like any other mesh, into the
+//! shadow maps and the prepass too. Elsewhere `shader.wgsl` skins them in the vertex shader, and
+//! skinned meshes are only drawn in the scene pass.
+
+use std::sync::{Arc, OnceLock};
+
+use glam::Mat4;
+use wgpu::util::DeviceExt;
+
+use crate::animation::{AnimationClip, AnimationPlayer};
+use crate::instance::{Instance, InstanceRaw};
+use crate::material::Material;
+use crate::math::Aabb;
+use crate::mesh::{Mesh, MeshData};
+use crate::mesh_allocator::MeshAllocator;
+use crate::morph::{MorphDelta, MorphTargets, MorphUniform, MAX_MORPH_TARGETS};
+use crate::profiler::GpuProfiler;
+use crate::shader::{self, Preprocessor};
+use crate::skeleton::{Pose, Skeleton, MAX_JOINTS};
+use crate::vertex::{Vertex, VertexLayout, VertexType};
+
+/// The joints a vertex follows and how much.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SkinVertex {
+    /// Indices into the joints of the skeleton.
+    pub joints: [u32; 4],
+    /// Summing to one, or all zero for vertices that don't move with the skeleton.
+    pub weights: [f32; 4],
+}
+
+/// The vertices of skinned meshes: a [`Vertex`] with its skin read at the shader locations
+/// after the instance's.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SkinnedVertex {
+    pub vertex: Vertex,
+    pub skin: SkinVertex,
+}
+
+impl VertexType for SkinnedVertex {
+    fn vertex_layout() -> &'static VertexLayout {
+        static LAYOUT: OnceLock<VertexLayout> = OnceLock::new();
+        LAYOUT.get_or_init(|| {
+            let layout = Vertex::vertex_layout()
+                .clone()
+                .skip_to(InstanceRaw::vertex_layout().next_location())
+                .with("joints", wgpu::VertexFormat::Uint32x4)
+                .with("weights", wgpu::VertexFormat::Float32x4);
+            debug_assert_eq!(layout.stride(), std::mem::size_of::<Self>() as u64);
+            layout
+        })
+    }
+}
+
+/// A rigged mesh as loaded from a glTF skin, with the animations moving its skeleton.
+#[derive(Clone, Debug, Default)]
+pub struct SkinnedMeshData {
+    /// The vertices in the space the skeleton's inverse bind matrices start from.
+    pub mesh: MeshData,
+    /// The skin of each vertex of `mesh`, in the same order.
+    pub skin: Vec<SkinVertex>,
+    pub skeleton: Skeleton,
+    pub animations: Vec<AnimationClip>,
+    pub morph_targets: MorphTargets,
+}
+
+/// The invocations per workgroup of `skinning.wgsl`.
+const WORKGROUP_SIZE: u32 = 64;
+
+/// What skinned meshes share: the compute pipeline skinning their vertices, or the layout of
+/// `@group(1)` of the mesh pipelines skinning them in the vertex shader, holding the camera,
+/// the joint palette and the morph targets.
+///
+/// In the vertex shader the palette lives in a storage buffer, or in a uniform buffer of
+/// [`MAX_JOINTS`] matrices where vertex shaders can't read storage buffers, e.g. on WebGL2.
+/// Morph targets are only supported with storage buffers, and left out otherwise.
+pub struct Skinning {
+    uniform_palette: bool,
+    bind_group_layout: wgpu::BindGroupLayout,
+    /// `None` where compute shaders aren't supported.
+    compute: Option<ComputeSkinning>,
+}
+
+/// The pipeline of `skinning.wgsl` and the layout of what it reads and writes.
+struct ComputeSkinning {
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl Skinning {
+    /// Compiles `skinning.wgsl` with `preprocessor` where the downlevel capabilities allow it.
+    pub fn new(
+        device: &wgpu::Device,
+        preprocessor: &Preprocessor,
+        downlevel: &wgpu::DownlevelCapabilities,
+    ) -> anyhow::Result<Self> {
+        let uniform_palette = !downlevel
+            .flags
+            .contains(wgpu::DownlevelFlags::VERTEX_STORAGE);
+        let palette_binding = if uniform_palette {
+            wgpu::BufferBindingType::Uniform
+        } else {
+            wgpu::BufferBindingType::Storage { read_only: true }
+        };
+        let entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let mut entries = vec![
+            entry(0, wgpu::BufferBindingType::Uniform),
+            entry(1, palette_binding),
+        ];
+        if !uniform_palette {
+            entries.extend([
+                entry(2, wgpu::BufferBindingType::Storage { read_only: true }),
+                entry(3, wgpu::BufferBindingType::Uniform),
+            ]);
+        }
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("skinning"),
+            entries: &entries,
+        });
+        let compute = downlevel
+            .flags
+            .contains(wgpu::DownlevelFlags::COMPUTE_SHADERS)
+            .then(|| ComputeSkinning::new(device, preprocessor))
+            .transpose()?;
+        Ok(Self {
+            uniform_palette,
+            bind_group_layout,
+            compute,
+        })
+    }
+
+    /// Replaces the camera's layout at `@group(1)` of the mesh pipelines drawing skinned
+    /// meshes.
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    /// Whether meshes are drawn with their morph targets.
+    pub fn morph_targets(&self) -> bool {
+        self.compute.is_some() || !self.uniform_palette
+    }
+
+    /// Whether vertices are skinned by [`Skinning::dispatch`] into meshes of [`Vertex`]es,
+    /// drawn with the same pipelines as other meshes, rather than in the vertex shader.
+    pub fn computes(&self) -> bool {
+        self.compute.is_some()
+    }
+
+    /// Records the compute pass skinning the vertices of `meshes` in their last updated pose,
+    /// which the frame's passes draw them in afterwards.
+    pub fn dispatch(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        profiler: Option<&mut GpuProfiler>,
+        meshes: &[SkinnedMesh],
+    ) {
+        let Some(compute) = &self.compute else {
+            return;
+        };
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("skinning"),
+            timestamp_writes: profiler
+                .and_then(|profiler| profiler.compute_timestamp_writes("skinning")),
+        });
+        compute_pass.set_pipeline(&compute.pipeline);
+        for skinned in meshes {
+            let Deformation::Compute(bind_group) = &skinned.deformation else {
+                continue;
+            };
+            compute_pass.set_bind_group(0, bind_group, &[]);
+            compute_pass.dispatch_workgroups(
+                (skinned.morph_targets.vertex_count as u32).div_ceil(WORKGROUP_SIZE),
+                1,
+                1,
+            );
+        }
+    }
+
+    /// Defines what `shader.wgsl` needs to skin vertices with the palette and move them by the
+    /// morph targets.
+    pub fn configure_preprocessor(&self, preprocessor: Preprocessor) -> Preprocessor {
+        let preprocessor = preprocessor.define("SKINNED", "");
+        if self.uniform_palette {
+            preprocessor.define("JOINTS_IN_UNIFORM", "")
+        } else {
+            preprocessor.define("MORPH_TARGETS", "")
+        }
+    }
+
+    fn palette_usage(&self) -> wgpu::BufferUsages {
+        if self.uniform_palette && self.compute.is_none() {
+            wgpu::BufferUsages::UNIFORM
+        } else {
+            wgpu::BufferUsages::STORAGE
+        }
+    }
+}
+
+impl ComputeSkinning {
+    fn new(device: &wgpu::Device, preprocessor: &Preprocessor) -> anyhow::Result<Self> {
+        let entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let read_only = wgpu::BufferBindingType::Storage { read_only: true };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("compute skinning"),
+            entries: &[
+                entry(0, wgpu::BufferBindingType::Uniform),
+                entry(1, read_only),
+                entry(2, read_only),
+                entry(3, read_only),
+                entry(4, wgpu::BufferBindingType::Storage { read_only: false }),
+            ],
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("compute skinning"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let shader_module = shader::create_module(device, preprocessor, "skinning.wgsl")?;
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("skinning"),
+            layout: Some(&layout),
+            module: &shader_module,
+            entry_point: "skin",
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache: None,
+        });
+        Ok(Self {
+            bind_group_layout,
+            pipeline,
+        })
+    }
+}
+
+/// Where the vertices of a [`SkinnedMesh`] are skinned.
+enum Deformation {
+    /// In the vertex shader, which reads the camera, the palette and the morph targets from
+    /// this group.
+    VertexShader(wgpu::BindGroup),
+    /// By `skinning.wgsl`, from the vertices as modelled, with their skins, into the mesh's,
+    /// reading and writing the buffers of this group.
+    Compute(wgpu::BindGroup),
+}
+
+/// A [`SkinnedMeshData`] uploaded to the GPU, drawn with its materials like the scene's mesh,
+/// once, and posed by its animations.
+pub struct SkinnedMesh {
+    pub transform: Mat4,
+    /// Plays and blends the animations, indexing into [`SkinnedMesh::animations`].
+    pub player: AnimationPlayer,
+    mesh: Mesh,
+    materials: Vec<Arc<Material>>,
+    palette_buffer: wgpu::Buffer,
+    /// The weights of the morph targets, along with the buffer of their deltas bound next to
+    /// it, if supported.
+    morph: Option<(wgpu::Buffer, wgpu::Buffer)>,
+    morph_targets: MorphTargets,
+    /// How far each morph target moves any vertex at most, at full weight.
+    morph_extents: Vec<f32>,
+    instance_buffer: wgpu::Buffer,
+    deformation: Deformation,
+    /// The box around the vertices in the last updated pose.
+    bounds: Aabb,
+    skeleton: Skeleton,
+    animations: Vec<AnimationClip>,
+    rest_pose: Pose,
+    pose: Pose,
+    palette: Vec<Mat4>,
+    /// The transform the last frame was drawn with, for the motion vectors.
+    previous_transform: Option<Mat4>,
+}
+
+impl SkinnedMesh {
+    /// Uploads `data` at the origin, drawn with its `materials` and reading the camera from
+    /// `camera_buffer`. Only the first [`MAX_JOINTS`] joints move vertices.
+    pub fn new(
+        allocator: &MeshAllocator,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        skinning: &Skinning,
+        camera_buffer: &wgpu::Buffer,
+        mut data: SkinnedMeshData,
+        materials: Vec<Arc<Material>>,
+    ) -> Self {
+        // `skinning.wgsl` skins as many vertices as the targets displace.
+        if data.morph_targets.is_empty() {
+            data.morph_targets.vertex_count = data.mesh.vertices.len();
+        }
+        if data.skeleton.len() > MAX_JOINTS {
+            eprintln!(
+                "only skinning {MAX_JOINTS} of {} joints",
+                data.skeleton.len()
+            );
+        }
+        let vertices: Vec<SkinnedVertex> = data
+            .mesh
+            .vertices
+            .iter()
+            .enumerate()
+            .map(|(index, &vertex)| SkinnedVertex {
+                vertex,
+                skin: data.skin.get(index).copied().unwrap_or_default(),
+            })
+            .collect();
+        // Skinned by the compute shader into plain vertices, which start out in the bind pose.
+        let mesh = if skinning.computes() {
+            Mesh::from_data_writable(allocator, device, queue, &data.mesh)
+        } else {
+            Mesh::from_data_with_vertices(allocator, device, queue, &data.mesh, &vertices)
+        };
+        let palette_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("joint palette"),
+            size: (MAX_JOINTS * std::mem::size_of::<Mat4>()) as wgpu::BufferAddress,
+            usage: skinning.palette_usage() | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("skinned instance"),
+            contents: bytemuck::bytes_of(&instance(Mat4::IDENTITY).to_raw(Mat4::IDENTITY)),
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        });
+        if data.morph_targets.len() > MAX_MORPH_TARGETS {
+            eprintln!(
+                "only morphing {MAX_MORPH_TARGETS} of {} targets",
+                data.morph_targets.len()
+            );
+        }
+        let morph = skinning.morph_targets().then(|| {
+            let uniform = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("morph weights"),
+                contents: bytemuck::bytes_of(&MorphUniform::default()),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+            // Bindings can't be empty, so meshes without targets get a zero delta.
+            let deltas = if data.morph_targets.is_empty() {
+                &[MorphDelta::default()][..]
+            } else {
+                &data.morph_targets.deltas
+            };
+            let deltas = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("morph deltas"),
+                contents: bytemuck::cast_slice(deltas),
+                usage: wgpu::BufferUsages::STORAGE,
+            });
+            (uniform, deltas)
+        });
+        let deformation = match (&skinning.compute, &morph) {
+            (Some(compute), Some((uniform, deltas))) => {
+                let bind_pose = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("skinned bind pose"),
+                    contents: bytemuck::cast_slice(&vertices),
+                    usage: wgpu::BufferUsages::STORAGE,
+                });
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("compute skinning"),
+                    layout: &compute.bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: uniform.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: bind_pose.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: palette_buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 3,
+                            resource: deltas.as_entire_binding(),
+                        },
+                        // The shader writes the mesh's range of the shared buffer.
+                        wgpu::BindGroupEntry {
+                            binding: 4,
+                            resource: mesh.vertex_buffer().as_entire_binding(),
+                        },
+                    ],
+                });
+                Deformation::Compute(bind_group)
+            }
+            _ => {
+                let mut entries = vec![
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: camera_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: palette_buffer.as_entire_binding(),
+                    },
+                ];
+                if let Some((uniform, deltas)) = &morph {
+                    entries.extend([
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: deltas.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 3,
+                            resource: uniform.as_entire_binding(),
+                        },
+                    ]);
+                }
+                Deformation::VertexShader(device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("skinning"),
+                    layout: skinning.bind_group_layout(),
+                    entries: &entries,
+                }))
+            }
+        };
+        let mut rest_pose = data.skeleton.rest_pose();
+        rest_pose.weights = data.morph_targets.weights.clone();
+        rest_pose.weights.resize(data.morph_targets.len(), 0.0);
+        let mut player = AnimationPlayer::new();
+        if !data.animations.is_empty() {
+            player.play(0);
+        }
+
+        Self {
+            transform: Mat4::IDENTITY,
+            player,
+            bounds: mesh.bounds().aabb,
+            mesh,
+            materials,
+            palette_buffer,
+            morph,
+            morph_extents: data.morph_targets.extents(),
+            morph_targets: data.morph_targets,
+            instance_buffer,
+            deformation,
+            skeleton: data.skeleton,
+            animations: data.animations,
+            pose: rest_pose.clone(),
+            rest_pose,
+            palette: Vec::new(),
+            previous_transform: None,
+        }
+    }
+
+    pub fn mesh(&self) -> &Mesh {
+        &self.mesh
+    }
+
+    pub fn materials(&self) -> &[Arc<Material>] {
+        &self.materials
+    }
+
+    pub fn skeleton(&self) -> &Skeleton {
+        &self.skeleton
+    }
+
+    pub fn animations(&self) -> &[AnimationClip] {
+        &self.animations
+    }
+
+    /// The pose of the last update.
+    pub fn pose(&self) -> &Pose {
+        &self.pose
+    }
+
+    /// The index of the animation called `name`, to play with [`SkinnedMesh::player`].
+    pub fn animation(&self, name: &str) -> Option<usize> {
+        self.animations.iter().position(|clip| clip.name == name)
+    }
+
+    /// Advances the animations by `delta_time` seconds, and uploads the joint palette and the
+    /// placement of the mesh.
+    pub fn update(&mut self, queue: &wgpu::Queue, delta_time: f32) {
+        self.player.update(delta_time, &self.animations);
+        self.player
+            .sample(&self.animations, &self.rest_pose, &mut self.pose);
+        self.skeleton
+            .skinning_matrices(&self.pose, &mut self.palette);
+        self.palette.truncate(MAX_JOINTS);
+        queue.write_buffer(&self.palette_buffer, 0, bytemuck::cast_slice(&self.palette));
+        self.bounds = self.pose_bounds();
+        if let Some((uniform, _)) = &self.morph {
+            let morph = MorphUniform::new(
+                &self.morph_targets,
+                &self.pose.weights,
+                self.mesh.base_vertex(),
+            );
+            queue.write_buffer(uniform, 0, bytemuck::bytes_of(&morph));
+        }
+        queue.write_buffer(
+            &self.instance_buffer,
+            0,
+            bytemuck::bytes_of(
+                &instance(self.transform).to_raw(
+                    self.previous_transform
+                        .replace(self.transform)
+                        .unwrap_or(self.transform),
+                ),
+            ),
+        );
+    }
+
+    /// The camera, the joint palette and the morph targets, bound at group 1 to draw the mesh
+    /// skinned with [`SkinnedMesh::instance_buffer`], or `None` if the mesh's vertices are
+    /// skinned already, see [`Skinning::computes`].
+    pub fn bind_group(&self) -> Option<&wgpu::BindGroup> {
+        match &self.deformation {
+            Deformation::VertexShader(bind_group) => Some(bind_group),
+            Deformation::Compute(_) => None,
+        }
+    }
+
+    /// The box around the vertices in the pose of the last update, in the mesh's space.
+    pub fn bounds(&self) -> Aabb {
+        self.bounds
+    }
+
+    /// The single instance of the mesh, at [`SkinnedMesh::transform`].
+    pub fn instance_buffer(&self) -> &wgpu::Buffer {
+        &self.instance_buffer
+    }
+
+    /// A box around the vertices in the current pose: vertices are moved by a weighted average
+    /// of joints, so they stay within the boxes of the bind pose moved by each joint, or by
+    /// none for those without weights. The box is grown by how far the morph targets may move
+    /// them beforehand.
+    fn pose_bounds(&self) -> Aabb {
+        let aabb = self.mesh.bounds().aabb;
+        if aabb.is_empty() {
+            return aabb;
+        }
+        let morph_extent: f32 = self
+            .morph_extents
+            .iter()
+            .zip(&self.pose.weights)
+            .map(|(extent, weight)| extent * weight.abs())
+            .sum();
+        let aabb = Aabb {
+            min: aabb.min - morph_extent,
+            max: aabb.max + morph_extent,
+        };
+        let boxes = std::iter::once(aabb).chain(
+            self.palette
+                .iter()
+                .map(|&skinning_matrix| aabb.transformed(skinning_matrix)),
+        );
+        Aabb::from_points(boxes.flat_map(|aabb| [aabb.min, aabb.max]))
+    }
+}
+
+fn instance(transform: Mat4) -> Instance {
+    Instance {
+        transform,
+        ..Instance::default()
+    }
+}
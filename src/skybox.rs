@@ -0,0 +1,389 @@
+use std::sync::Arc;
+
+use glam::{Mat4, Vec3};
+use wgpu::util::DeviceExt;
+
+use crate::camera::Camera;
+use crate::mipmap::{mip_level_count, MipmapGenerator};
+use crate::pipeline_cache::PipelineBuilder;
+use crate::shader::{self, Preprocessor};
+use crate::taa::MOTION_FORMAT;
+use crate::texture::Texture;
+
+/// The format of skybox cube maps, filterable everywhere and wide enough for HDR environments.
+pub const SKYBOX_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// A cube map on the CPU: six square faces of linear RGBA, in the order of the layers of a cube
+/// texture, +X, -X, +Y, -Y, +Z and -Z.
+#[derive(Clone, Debug)]
+pub struct CubeMapData {
+    pub size: u32,
+    pub faces: [image::Rgba32FImage; 6],
+}
+
+impl CubeMapData {
+    /// Fills each texel with `color` of the direction through its center.
+    pub fn from_fn(size: u32, color: impl Fn(Vec3) -> [f32; 4]) -> Self {
+        let faces = std::array::from_fn(|face| {
+            image::Rgba32FImage::from_fn(size, size, |x, y| {
+                image::Rgba(color(cube_direction(face, size, x, y)))
+            })
+        });
+        Self { size, faces }
+    }
+
+    /// Projects an equirectangular panorama, with the top row looking up and the center
+    /// looking down -Z, onto faces of `size` texels.
+    pub fn from_equirectangular(image: &image::Rgba32FImage, size: u32) -> Self {
+        Self::from_fn(size, |direction| {
+            let u = direction.x.atan2(-direction.z) / std::f32::consts::TAU + 0.5;
+            let v = direction.y.clamp(-1.0, 1.0).acos() / std::f32::consts::PI;
+            sample_bilinear(image, u, v)
+        })
+    }
+
+    /// A sky fading from blue overhead to a pale horizon, over a dark ground.
+    pub fn gradient() -> Self {
+        const ZENITH: Vec3 = Vec3::new(0.18, 0.35, 0.75);
+        const HORIZON: Vec3 = Vec3::new(0.7, 0.75, 0.8);
+        const GROUND: Vec3 = Vec3::new(0.1, 0.09, 0.08);
+        Self::from_fn(64, |direction| {
+            let color = if direction.y >= 0.0 {
+                HORIZON.lerp(ZENITH, direction.y.sqrt())
+            } else {
+                HORIZON.lerp(GROUND, (-direction.y).sqrt())
+            };
+            color.extend(1.0).to_array()
+        })
+    }
+}
+
+/// The direction through the center of texel `(x, y)` of `face`, following the cube map
+/// conventions of WebGPU.
+fn cube_direction(face: usize, size: u32, x: u32, y: u32) -> Vec3 {
+    let u = (x as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+    let v = (y as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+    let direction = match face {
+        0 => Vec3::new(1.0, -v, -u),
+        1 => Vec3::new(-1.0, -v, u),
+        2 => Vec3::new(u, 1.0, v),
+        3 => Vec3::new(u, -1.0, -v),
+        4 => Vec3::new(u, -v, 1.0),
+        _ => Vec3::new(-u, -v, -1.0),
+    };
+    direction.normalize()
+}
+
+/// Samples `image` at UV `(u, v)` with bilinear filtering, wrapping horizontally and clamping
+/// vertically.
+fn sample_bilinear(image: &image::Rgba32FImage, u: f32, v: f32) -> [f32; 4] {
+    let (width, height) = image.dimensions();
+    let x = u * width as f32 - 0.5;
+    let y = (v * height as f32 - 0.5).clamp(0.0, height as f32 - 1.0);
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let texel = |x: f32, y: f32| {
+        let x = (x as i64).rem_euclid(width as i64) as u32;
+        let y = (y as u32).min(height - 1);
+        glam::Vec4::from(image.get_pixel(x, y).0)
+    };
+    let top = texel(x0, y0).lerp(texel(x0 + 1.0, y0), fx);
+    let bottom = texel(x0, y0 + 1.0).lerp(texel(x0 + 1.0, y0 + 1.0), fx);
+    top.lerp(bottom, fy).to_array()
+}
+
+/// The inverse of the camera's rotation and projection, `Sky` in `skybox.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct SkyUniform {
+    inverse_view_proj: [[f32; 4]; 4],
+    /// The rotation and projection of the previous frame, for the motion vectors.
+    previous_view_proj: [[f32; 4]; 4],
+}
+
+/// Identifies a compiled skybox pipeline.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SkyboxPipelineKey {
+    pub format: wgpu::TextureFormat,
+    pub sample_count: u32,
+}
+
+/// An environment cube map drawn behind the scene with a fullscreen triangle on the far plane.
+pub struct Skybox {
+    /// The width and height of the faces of the sharpest level.
+    size: u32,
+    view: wgpu::TextureView,
+    sampler: wgpu::Sampler,
+    buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    pipeline_layout: Arc<wgpu::PipelineLayout>,
+    shader_module: Arc<wgpu::ShaderModule>,
+    /// The rotation and projection uploaded last, `None` before the first frame.
+    previous_view_proj: Option<Mat4>,
+}
+
+impl Skybox {
+    /// Compiles `skybox.wgsl` with `preprocessor` and uploads `data`.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        preprocessor: &Preprocessor,
+        data: &CubeMapData,
+        mipmaps: &mut MipmapGenerator,
+    ) -> anyhow::Result<Self> {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("skybox"),
+            contents: bytemuck::bytes_of(&SkyUniform {
+                inverse_view_proj: Mat4::IDENTITY.to_cols_array_2d(),
+                previous_view_proj: Mat4::IDENTITY.to_cols_array_2d(),
+            }),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("skybox"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("skybox"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let view = upload_cube_map(device, queue, data, mipmaps);
+        let bind_group = create_bind_group(device, &bind_group_layout, &buffer, &view, &sampler);
+        let pipeline_layout = Arc::new(device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("skybox"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            },
+        ));
+        let shader_module = Arc::new(shader::create_module(device, preprocessor, "skybox.wgsl")?);
+
+        Ok(Self {
+            size: data.size,
+            view,
+            sampler,
+            buffer,
+            bind_group_layout,
+            bind_group,
+            pipeline_layout,
+            shader_module,
+            previous_view_proj: None,
+        })
+    }
+
+    /// Replaces the environment with `data`.
+    pub fn set_cube_map(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        data: &CubeMapData,
+        mipmaps: &mut MipmapGenerator,
+    ) {
+        self.size = data.size;
+        self.view = upload_cube_map(device, queue, data, mipmaps);
+        self.bind_group = create_bind_group(
+            device,
+            &self.bind_group_layout,
+            &self.buffer,
+            &self.view,
+            &self.sampler,
+        );
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// The environment as a cube texture view, with a full mip chain.
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    /// Uploads the view direction of `camera`. Only its rotation matters, the sky is infinitely
+    /// far away.
+    pub fn update(&mut self, queue: &wgpu::Queue, camera: &Camera) {
+        let mut view = camera.view_matrix();
+        view.w_axis = glam::Vec4::W;
+        let view_proj = camera.projection_matrix() * view;
+        let previous_view_proj = self.previous_view_proj.replace(view_proj);
+        let uniform = SkyUniform {
+            inverse_view_proj: view_proj.inverse().to_cols_array_2d(),
+            previous_view_proj: previous_view_proj.unwrap_or(view_proj).to_cols_array_2d(),
+        };
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&uniform));
+    }
+
+    /// Returns a builder for the skybox pipeline drawing into `format` targets with
+    /// `sample_count` samples.
+    pub fn pipeline_builder(&self, key: SkyboxPipelineKey) -> Arc<PipelineBuilder> {
+        let layout = self.pipeline_layout.clone();
+        let shader_module = self.shader_module.clone();
+        Arc::new(move |device, cache| create_pipeline(device, &layout, &shader_module, key, cache))
+    }
+
+    /// Draws the sky wherever the depth buffer is still clear. Should come after the opaque
+    /// geometry, which then hides most of it before it's shaded.
+    pub fn draw(&self, render_pass: &mut wgpu::RenderPass, pipeline: &wgpu::RenderPipeline) {
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+/// Uploads `data` as a half-float cube texture with a full mip chain, which image-based
+/// lighting samples to filter the environment.
+fn upload_cube_map(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    data: &CubeMapData,
+    mipmaps: &mut MipmapGenerator,
+) -> wgpu::TextureView {
+    let texels: Vec<u16> = data
+        .faces
+        .iter()
+        .flat_map(|face| face.as_raw())
+        .map(|&channel| half::f16::from_f32(channel).to_bits())
+        .collect();
+    let size = wgpu::Extent3d {
+        width: data.size,
+        height: data.size,
+        depth_or_array_layers: 6,
+    };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("skybox"),
+        size,
+        mip_level_count: mip_level_count(size),
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: SKYBOX_FORMAT,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::COPY_DST
+            | wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    });
+    queue.write_texture(
+        texture.as_image_copy(),
+        bytemuck::cast_slice(&texels),
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(data.size * 8),
+            rows_per_image: Some(data.size),
+        },
+        size,
+    );
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("skybox mipmaps"),
+    });
+    mipmaps.generate(device, &mut encoder, &texture);
+    queue.submit([encoder.finish()]);
+
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(wgpu::TextureViewDimension::Cube),
+        ..Default::default()
+    })
+}
+
+fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    buffer: &wgpu::Buffer,
+    view: &wgpu::TextureView,
+    sampler: &wgpu::Sampler,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("skybox"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ],
+    })
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader_module: &wgpu::ShaderModule,
+    key: SkyboxPipelineKey,
+    cache: Option<&wgpu::PipelineCache>,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("skybox"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader_module,
+            entry_point: "vs_main",
+            buffers: &[],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        primitive: wgpu::PrimitiveState::default(),
+        // The triangle lies on the far plane, where only the cleared depth lets it through.
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: Texture::DEPTH_FORMAT,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::LessEqual,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: key.sample_count,
+            ..Default::default()
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader_module,
+            entry_point: "fs_main",
+            targets: &[
+                Some(wgpu::ColorTargetState {
+                    format: key.format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                }),
+                Some(MOTION_FORMAT.into()),
+            ],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        }),
+        multiview: None,
+        cache,
+    })
+}
@@ -0,0 +1,346 @@
+//! Textured 2D quads batched into as few draws as their textures and layers allow, drawn over
+//! the post-processed frame with an orthographic projection.
+
+use std::ops::Range;
+use std::sync::{Arc, OnceLock};
+
+use glam::{Mat4, Vec2};
+
+use crate::pipeline_cache::PipelineBuilder;
+use crate::post_process;
+use crate::shader::{self, Preprocessor};
+use crate::texture::Texture;
+use crate::uniform::UniformBuffer;
+use crate::upload::Upload;
+use crate::vertex::{VertexLayout, VertexType};
+
+/// The corners of the two triangles of a quad, from its top left to its bottom right.
+const CORNERS: [[f32; 2]; 6] = [
+    [0.0, 0.0],
+    [1.0, 0.0],
+    [1.0, 1.0],
+    [0.0, 0.0],
+    [1.0, 1.0],
+    [0.0, 1.0],
+];
+
+/// A texture added to a [`SpriteBatch`] with [`SpriteBatch::add_texture`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SpriteTexture(usize);
+
+/// A textured quad, in the units of the projection the batch is drawn with, pixels by default,
+/// with Y down.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sprite {
+    pub position: Vec2,
+    pub size: Vec2,
+    /// The point of the quad at `position` and rotated around, from (0, 0) at its top left to
+    /// (1, 1) at its bottom right.
+    pub anchor: Vec2,
+    /// Clockwise on screen, in radians.
+    pub rotation: f32,
+    /// The left, top, right and bottom of the quad in the texture, in UVs. Swapping left and
+    /// right or top and bottom flips the sprite.
+    pub uv: [f32; 4],
+    /// Linear RGBA, multiplying the texture.
+    pub color: [f32; 4],
+    /// Sprites on higher layers are drawn over those on lower ones.
+    pub layer: i32,
+}
+
+impl Sprite {
+    /// The whole texture, untinted, centered on `position`.
+    pub fn new(position: Vec2, size: Vec2) -> Self {
+        Self {
+            position,
+            size,
+            anchor: Vec2::splat(0.5),
+            rotation: 0.0,
+            uv: [0.0, 0.0, 1.0, 1.0],
+            color: [1.0; 4],
+            layer: 0,
+        }
+    }
+
+    pub fn with_uv(mut self, uv: [f32; 4]) -> Self {
+        self.uv = uv;
+        self
+    }
+
+    pub fn with_color(mut self, color: [f32; 4]) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_layer(mut self, layer: i32) -> Self {
+        self.layer = layer;
+        self
+    }
+
+    pub fn with_rotation(mut self, rotation: f32) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_anchor(mut self, anchor: Vec2) -> Self {
+        self.anchor = anchor;
+        self
+    }
+}
+
+/// A corner of a sprite's quad.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SpriteVertex {
+    pub position: [f32; 2],
+    pub uv: [f32; 2],
+    /// Linear RGBA.
+    pub color: [f32; 4],
+}
+
+impl VertexType for SpriteVertex {
+    fn vertex_layout() -> &'static VertexLayout {
+        static LAYOUT: OnceLock<VertexLayout> = OnceLock::new();
+        LAYOUT.get_or_init(|| {
+            VertexLayout::new(wgpu::VertexStepMode::Vertex, 0)
+                .with("position", wgpu::VertexFormat::Float32x2)
+                .with("uv", wgpu::VertexFormat::Float32x2)
+                .with("color", wgpu::VertexFormat::Float32x4)
+        })
+    }
+}
+
+/// Everything that distinguishes one sprite pipeline from another.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SpritePipelineKey {
+    pub format: wgpu::TextureFormat,
+}
+
+/// The sprites of one layer sharing a texture, drawn together.
+#[derive(Clone, Debug)]
+struct SpriteDraw {
+    texture: SpriteTexture,
+    vertices: Range<u32>,
+}
+
+/// Immediate-mode sprites: those added during a frame are sorted by layer, then by texture,
+/// built into quads in one vertex buffer, drawn in a draw per run of the same texture and
+/// forgotten. Sprites of the same layer and texture keep the order they were added in, but
+/// sprites of the same layer with different textures shouldn't overlap.
+pub struct SpriteBatch {
+    textures: Vec<wgpu::BindGroup>,
+    texture_layout: wgpu::BindGroupLayout,
+    projection: UniformBuffer<[[f32; 4]; 4]>,
+    sprites: Vec<(SpriteTexture, Sprite)>,
+    vertices: Vec<SpriteVertex>,
+    buffer: Option<wgpu::Buffer>,
+    /// The draws uploaded for the frame being rendered.
+    draws: Vec<SpriteDraw>,
+    pipeline_layout: Arc<wgpu::PipelineLayout>,
+    shader_module: Arc<wgpu::ShaderModule>,
+}
+
+impl SpriteBatch {
+    /// Compiles `sprite.wgsl` with `preprocessor`.
+    pub fn new(device: &wgpu::Device, preprocessor: &Preprocessor) -> anyhow::Result<Self> {
+        let texture_layout = Texture::bind_group_layout(device);
+        let projection = UniformBuffer::new(
+            device,
+            "sprite projection",
+            Mat4::IDENTITY.to_cols_array_2d(),
+            wgpu::ShaderStages::VERTEX,
+        );
+        let pipeline_layout = Arc::new(device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("sprites"),
+                bind_group_layouts: &[projection.bind_group_layout(), &texture_layout],
+                push_constant_ranges: &[],
+            },
+        ));
+        let shader_module = Arc::new(shader::create_module(device, preprocessor, "sprite.wgsl")?);
+
+        Ok(Self {
+            textures: Vec::new(),
+            texture_layout,
+            projection,
+            sprites: Vec::new(),
+            vertices: Vec::new(),
+            buffer: None,
+            draws: Vec::new(),
+            pipeline_layout,
+            shader_module,
+        })
+    }
+
+    /// Makes `texture` available to sprites, sampled with its own sampler.
+    pub fn add_texture(&mut self, device: &wgpu::Device, texture: &Texture) -> SpriteTexture {
+        self.textures
+            .push(texture.create_bind_group(device, &self.texture_layout));
+        SpriteTexture(self.textures.len() - 1)
+    }
+
+    /// The layout of group 0 of the sprite pipeline: the view projection matrix in a uniform
+    /// buffer at binding 0.
+    pub fn projection_layout(&self) -> &wgpu::BindGroupLayout {
+        self.projection.bind_group_layout()
+    }
+
+    /// The layout of group 1 of the sprite pipeline, see [`Texture::bind_group_layout`].
+    pub fn texture_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.texture_layout
+    }
+
+    /// Draws `sprite` with `texture` in the next frame.
+    pub fn draw(&mut self, texture: SpriteTexture, sprite: Sprite) {
+        self.sprites.push((texture, sprite));
+    }
+
+    /// Whether no sprites have been added this frame.
+    pub fn is_empty(&self) -> bool {
+        self.sprites.is_empty()
+    }
+
+    /// The sprites added this frame.
+    pub fn len(&self) -> usize {
+        self.sprites.len()
+    }
+
+    /// The draw calls of the frame being rendered.
+    pub fn draw_count(&self) -> usize {
+        self.draws.len()
+    }
+
+    /// Sorts and builds the sprites added this frame into quads, uploads them for
+    /// [`SpriteBatch::render`] along with `view_projection` and forgets them, growing the vertex
+    /// buffer if it is too small.
+    pub fn upload(&mut self, upload: &mut Upload, view_projection: Mat4) {
+        // Stable, keeping the order sprites were added in within a layer and texture.
+        self.sprites
+            .sort_by_key(|(texture, sprite)| (sprite.layer, *texture));
+        self.vertices.clear();
+        self.draws.clear();
+        for (texture, sprite) in self.sprites.drain(..) {
+            let start = self.vertices.len() as u32;
+            let (sin, cos) = sprite.rotation.sin_cos();
+            let [left, top, right, bottom] = sprite.uv;
+            self.vertices.extend(CORNERS.map(|[x, y]| {
+                let offset = (Vec2::new(x, y) - sprite.anchor) * sprite.size;
+                let rotated = Vec2::new(
+                    offset.x * cos - offset.y * sin,
+                    offset.x * sin + offset.y * cos,
+                );
+                SpriteVertex {
+                    position: (sprite.position + rotated).to_array(),
+                    uv: [left + (right - left) * x, top + (bottom - top) * y],
+                    color: sprite.color,
+                }
+            }));
+            let end = self.vertices.len() as u32;
+            match self.draws.last_mut() {
+                Some(draw) if draw.texture == texture => draw.vertices.end = end,
+                _ => self.draws.push(SpriteDraw {
+                    texture,
+                    vertices: start..end,
+                }),
+            }
+        }
+
+        if self.vertices.is_empty() {
+            return;
+        }
+        self.projection.value = view_projection.to_cols_array_2d();
+        upload.write(
+            self.projection.buffer(),
+            0,
+            bytemuck::bytes_of(&self.projection.value),
+        );
+        let size = std::mem::size_of_val(self.vertices.as_slice()) as wgpu::BufferAddress;
+        if self
+            .buffer
+            .as_ref()
+            .is_none_or(|buffer| buffer.size() < size)
+        {
+            self.buffer = Some(upload.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("sprites"),
+                size: size.next_power_of_two(),
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }));
+        }
+        if let Some(buffer) = &self.buffer {
+            upload.write(buffer, 0, bytemuck::cast_slice(&self.vertices));
+        }
+    }
+
+    pub fn pipeline_builder(&self, key: SpritePipelineKey) -> Arc<PipelineBuilder> {
+        let layout = self.pipeline_layout.clone();
+        let shader_module = self.shader_module.clone();
+        Arc::new(move |device, cache| create_pipeline(device, &layout, &shader_module, key, cache))
+    }
+
+    /// Draws the sprites uploaded last.
+    pub fn render(&self, render_pass: &mut wgpu::RenderPass, pipeline: &wgpu::RenderPipeline) {
+        let Some(buffer) = self.buffer.as_ref().filter(|_| !self.draws.is_empty()) else {
+            return;
+        };
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, self.projection.bind_group(), &[]);
+        render_pass.set_vertex_buffer(0, buffer.slice(..));
+        for draw in &self.draws {
+            render_pass.set_bind_group(1, &self.textures[draw.texture.0], &[]);
+            render_pass.draw(draw.vertices.clone(), 0..1);
+        }
+    }
+}
+
+/// Maps `uv`, relative to the `region` of a texture, to the whole texture, both as left, top,
+/// right and bottom.
+pub fn map_uv(region: [f32; 4], [left, top, right, bottom]: [f32; 4]) -> [f32; 4] {
+    let [region_left, region_top, region_right, region_bottom] = region;
+    let x = |u: f32| region_left + (region_right - region_left) * u;
+    let y = |v: f32| region_top + (region_bottom - region_top) * v;
+    [x(left), y(top), x(right), y(bottom)]
+}
+
+/// Maps pixels from the top left of a `width` by `height` target to clip space, Y down.
+pub fn pixel_projection(width: f32, height: f32) -> Mat4 {
+    Mat4::orthographic_rh(0.0, width, height, 0.0, -1.0, 1.0)
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader_module: &wgpu::ShaderModule,
+    key: SpritePipelineKey,
+    cache: Option<&wgpu::PipelineCache>,
+) -> wgpu::RenderPipeline {
+    let constants = post_process::output_constants(key.format);
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("sprites"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader_module,
+            entry_point: "vs_main",
+            buffers: &[SpriteVertex::vertex_layout().buffer_layout()],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        fragment: Some(wgpu::FragmentState {
+            module: shader_module,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format: key.format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: wgpu::PipelineCompilationOptions {
+                constants: &constants,
+                ..Default::default()
+            },
+        }),
+        multiview: None,
+        cache,
+    })
+}
@@ -0,0 +1,258 @@
+//! Frame-by-frame animations from sprite sheets, selecting which part of a texture a
+//! [`Sprite`] shows over time.
+
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+
+use crate::sprite::{self, Sprite};
+
+/// One frame of a sprite sheet.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpriteFrame {
+    /// The left, top, right and bottom of the frame in the sheet, in UVs.
+    pub uv: [f32; 4],
+    /// How long the frame is shown, in seconds, at a speed of 1.
+    pub duration: f32,
+}
+
+/// How an animation plays once it reaches its last frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum LoopMode {
+    /// Stops on the last frame.
+    Once,
+    /// Starts over from the first frame.
+    #[default]
+    Loop,
+    /// Plays backwards to the first frame, then forwards again.
+    PingPong,
+}
+
+/// A named run of frames in a sheet, like an Aseprite tag.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpriteClip {
+    /// The first and last frame, in sheet order.
+    pub frames: RangeInclusive<usize>,
+    pub mode: LoopMode,
+    /// Starts from the last frame and plays backwards.
+    pub reverse: bool,
+}
+
+/// The frames of a texture and the animations made of them, see [`SpriteSheet::grid`] and
+/// [`crate::assets::aseprite::load`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SpriteSheet {
+    pub frames: Vec<SpriteFrame>,
+    pub clips: HashMap<String, SpriteClip>,
+}
+
+impl SpriteSheet {
+    /// A texture cut into `columns` by `rows` equal frames, numbered row by row from the top
+    /// left, each shown for `duration` seconds. Has no clips.
+    pub fn grid(columns: u32, rows: u32, duration: f32) -> Self {
+        let (width, height) = (1.0 / columns as f32, 1.0 / rows as f32);
+        let frames = (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| (column, row)))
+            .map(|(column, row)| {
+                let (left, top) = (column as f32 * width, row as f32 * height);
+                SpriteFrame {
+                    uv: [left, top, left + width, top + height],
+                    duration,
+                }
+            })
+            .collect();
+
+        Self {
+            frames,
+            clips: HashMap::new(),
+        }
+    }
+
+    /// Adds or replaces the clip called `name`.
+    pub fn with_clip(mut self, name: impl Into<String>, clip: SpriteClip) -> Self {
+        self.clips.insert(name.into(), clip);
+        self
+    }
+
+    /// Every frame in order, looping.
+    pub fn all_frames(&self) -> SpriteClip {
+        SpriteClip {
+            frames: 0..=self.frames.len().saturating_sub(1),
+            mode: LoopMode::Loop,
+            reverse: false,
+        }
+    }
+}
+
+/// What happened during a [`SpriteAnimator::update`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AnimationEvent {
+    /// Another frame, by its index in the sheet, is shown.
+    FrameChanged(usize),
+    /// A looping animation went back to its first frame, or a ping-pong one turned around.
+    Looped,
+    /// An animation playing once reached its last frame, where it stays.
+    Finished,
+}
+
+/// Plays a [`SpriteClip`] of a sheet, keeping track of the frame to show.
+#[derive(Clone, Debug)]
+pub struct SpriteAnimator {
+    clip: SpriteClip,
+    /// The frame shown, an index into the sheet.
+    frame: usize,
+    /// The time spent on the current frame, in seconds.
+    time: f32,
+    /// Whether playback currently goes from the last frame towards the first.
+    backwards: bool,
+    finished: bool,
+    /// Scales how fast frames advance, 1 playing them at their durations.
+    pub speed: f32,
+    pub paused: bool,
+    events: Vec<AnimationEvent>,
+}
+
+impl SpriteAnimator {
+    pub fn new(clip: SpriteClip) -> Self {
+        let mut animator = Self {
+            frame: 0,
+            time: 0.0,
+            backwards: false,
+            finished: false,
+            speed: 1.0,
+            paused: false,
+            events: Vec::new(),
+            clip,
+        };
+        animator.restart();
+        animator
+    }
+
+    /// Plays `clip` from its start, unless it's the clip already playing.
+    pub fn play(&mut self, clip: &SpriteClip) {
+        if self.clip != *clip {
+            self.clip = clip.clone();
+            self.restart();
+        }
+    }
+
+    /// Goes back to the first frame of the clip.
+    pub fn restart(&mut self) {
+        self.backwards = self.clip.reverse;
+        self.frame = if self.backwards {
+            *self.clip.frames.end()
+        } else {
+            *self.clip.frames.start()
+        };
+        self.time = 0.0;
+        self.finished = false;
+    }
+
+    pub fn clip(&self) -> &SpriteClip {
+        &self.clip
+    }
+
+    /// The index in the sheet of the frame shown.
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    /// Whether an animation playing once has reached its end.
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    /// What happened during the last update.
+    pub fn events(&self) -> &[AnimationEvent] {
+        &self.events
+    }
+
+    /// Advances by `delta_time` seconds through the frames of `sheet`, as many as that covers,
+    /// recording the [`SpriteAnimator::events`] of the way.
+    pub fn update(&mut self, sheet: &SpriteSheet, delta_time: f32) {
+        self.events.clear();
+        if self.paused || self.finished || self.speed <= 0.0 || sheet.frames.is_empty() {
+            return;
+        }
+        let (first, last) = (*self.clip.frames.start(), *self.clip.frames.end());
+        let last = last.min(sheet.frames.len() - 1);
+        if first > last {
+            return;
+        }
+        self.time += delta_time * self.speed;
+        loop {
+            // With a floor, so that frames without a duration can't stall the loop.
+            let duration = sheet.frames[self.frame.clamp(first, last)]
+                .duration
+                .max(1e-3);
+            if self.time < duration {
+                break;
+            }
+            self.time -= duration;
+            if !self.advance(first, last) {
+                self.time = 0.0;
+                break;
+            }
+        }
+    }
+
+    /// Moves to the next frame, returning whether there is one.
+    fn advance(&mut self, first: usize, last: usize) -> bool {
+        let at_end = if self.backwards {
+            self.frame <= first
+        } else {
+            self.frame >= last
+        };
+        if !at_end {
+            self.step();
+            return true;
+        }
+        match self.clip.mode {
+            LoopMode::Once => {
+                self.finished = true;
+                self.events.push(AnimationEvent::Finished);
+                return false;
+            }
+            LoopMode::Loop => {
+                self.events.push(AnimationEvent::Looped);
+                if first != last {
+                    self.frame = if self.backwards { last } else { first };
+                    self.events.push(AnimationEvent::FrameChanged(self.frame));
+                }
+            }
+            LoopMode::PingPong => {
+                self.backwards = !self.backwards;
+                self.events.push(AnimationEvent::Looped);
+                if first != last {
+                    self.step();
+                }
+            }
+        }
+        true
+    }
+
+    fn step(&mut self) {
+        if self.backwards {
+            self.frame -= 1;
+        } else {
+            self.frame += 1;
+        }
+        self.events.push(AnimationEvent::FrameChanged(self.frame));
+    }
+
+    /// The UVs of the frame shown in `sheet`.
+    pub fn uv(&self, sheet: &SpriteSheet) -> [f32; 4] {
+        sheet
+            .frames
+            .get(self.frame)
+            .map_or([0.0, 0.0, 1.0, 1.0], |frame| frame.uv)
+    }
+
+    /// `sprite` showing the current frame, its UVs taken as relative to the frame so that
+    /// flipped sprites stay flipped.
+    pub fn sprite(&self, sheet: &SpriteSheet, sprite: Sprite) -> Sprite {
+        Sprite {
+            uv: sprite::map_uv(self.uv(sheet), sprite.uv),
+            ..sprite
+        }
+    }
+}
@@ -0,0 +1,462 @@
+use std::collections::HashMap;
+
+use glam::{Mat4, Vec3};
+use wgpu::util::DeviceExt;
+
+use crate::camera::Camera;
+use crate::prepass::Prepass;
+use crate::profiler::GpuProfiler;
+use crate::shader::{self, Preprocessor};
+use crate::texture::Texture;
+
+/// The number of samples per pixel, `KERNEL_SIZE` in `ssao.wgsl`.
+const KERNEL_SIZE: usize = 16;
+/// The width and height of the tiled noise texture, `NOISE_SIZE` in `ssao.wgsl`.
+const NOISE_SIZE: u32 = 4;
+
+/// The format of the ambient occlusion, bound in the lighting group.
+const OCCLUSION_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+
+/// `Ssao` in `ssao.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct SsaoUniform {
+    projection: [[f32; 4]; 4],
+    inverse_projection: [[f32; 4]; 4],
+    view: [[f32; 4]; 4],
+    kernel: [[f32; 4]; KERNEL_SIZE],
+    radius: f32,
+    bias: f32,
+    intensity: f32,
+    _padding: f32,
+}
+
+/// The textures the occlusion is computed and blurred in, the size of the surface, with the
+/// bind groups reading the prepass and them.
+struct Targets {
+    /// The occlusion, blurred back into it from `blurred`.
+    occlusion: Texture,
+    /// The occlusion blurred horizontally.
+    blurred: Texture,
+    bind_group: wgpu::BindGroup,
+    horizontal_bind_group: wgpu::BindGroup,
+    vertical_bind_group: wgpu::BindGroup,
+}
+
+/// Screen-space ambient occlusion, darkening the ambient light in creases and corners.
+///
+/// The depth and normals from the [`Prepass`] are sampled in a hemisphere around each pixel to
+/// estimate how much of it is hidden by nearby geometry. The noisy result is blurred in two
+/// passes and read by the scene pass from the lighting group.
+pub struct Ssao {
+    enabled: bool,
+    /// Whether the occlusion has been cleared to white since SSAO was disabled or resized.
+    cleared: bool,
+    uniform: SsaoUniform,
+    buffer: wgpu::Buffer,
+    noise: wgpu::TextureView,
+    bind_group_layout: wgpu::BindGroupLayout,
+    blur_bind_group_layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::RenderPipeline,
+    horizontal_pipeline: wgpu::RenderPipeline,
+    vertical_pipeline: wgpu::RenderPipeline,
+    targets: Targets,
+}
+
+impl Ssao {
+    /// Compiles the occlusion and blur passes with `preprocessor` and creates targets the size
+    /// of `config`, reading from `prepass`.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        preprocessor: &Preprocessor,
+        config: &wgpu::SurfaceConfiguration,
+        prepass: &Prepass,
+    ) -> anyhow::Result<Self> {
+        let uniform = SsaoUniform {
+            projection: Mat4::IDENTITY.to_cols_array_2d(),
+            inverse_projection: Mat4::IDENTITY.to_cols_array_2d(),
+            view: Mat4::IDENTITY.to_cols_array_2d(),
+            kernel: kernel(),
+            radius: 0.5,
+            bias: 0.025,
+            intensity: 1.5,
+            _padding: 0.0,
+        };
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("ssao"),
+            contents: bytemuck::bytes_of(&uniform),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let noise = create_noise_texture(device, queue);
+
+        let texture = |binding, sample_type| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let float = wgpu::TextureSampleType::Float { filterable: false };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("ssao"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                texture(1, float),
+                texture(2, float),
+                texture(3, float),
+            ],
+        });
+        let blur_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("ssao blur"),
+                entries: &[texture(0, float)],
+            });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("ssao"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let module = shader::create_module(device, preprocessor, "ssao.wgsl")?;
+        let pipeline = create_fullscreen_pipeline(device, "ssao", &layout, &module, HashMap::new());
+        let blur_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("ssao blur"),
+            bind_group_layouts: &[&blur_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let blur_module = shader::create_module(device, preprocessor, "ssao_blur.wgsl")?;
+        let blur_pipeline = |horizontal: bool| {
+            let constants = [(
+                "BLUR_HORIZONTAL".to_owned(),
+                f64::from(u8::from(horizontal)),
+            )];
+            create_fullscreen_pipeline(
+                device,
+                "ssao blur",
+                &blur_layout,
+                &blur_module,
+                constants.into(),
+            )
+        };
+        let horizontal_pipeline = blur_pipeline(true);
+        let vertical_pipeline = blur_pipeline(false);
+
+        let targets = create_targets(
+            device,
+            config,
+            &bind_group_layout,
+            &blur_bind_group_layout,
+            &buffer,
+            &noise,
+            prepass,
+        );
+
+        Ok(Self {
+            enabled: false,
+            cleared: false,
+            uniform,
+            buffer,
+            noise,
+            bind_group_layout,
+            blur_bind_group_layout,
+            pipeline,
+            horizontal_pipeline,
+            vertical_pipeline,
+            targets,
+        })
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.cleared = false;
+    }
+
+    /// The radius around each surface searched for occluders, in world units.
+    pub fn radius(&self) -> f32 {
+        self.uniform.radius
+    }
+
+    pub fn set_radius(&mut self, radius: f32) {
+        self.uniform.radius = radius;
+    }
+
+    /// How strongly occlusion darkens the ambient light, 1 being physically plausible.
+    pub fn intensity(&self) -> f32 {
+        self.uniform.intensity
+    }
+
+    pub fn set_intensity(&mut self, intensity: f32) {
+        self.uniform.intensity = intensity;
+    }
+
+    /// The ambient occlusion for the scene pass, white while SSAO is disabled.
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.targets.occlusion.view
+    }
+
+    /// Recreates the targets for the new size of the surface, after `prepass` was resized. The
+    /// lighting bind group has to be recreated with the new [`Ssao::view`].
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        prepass: &Prepass,
+    ) {
+        self.targets = create_targets(
+            device,
+            config,
+            &self.bind_group_layout,
+            &self.blur_bind_group_layout,
+            &self.buffer,
+            &self.noise,
+            prepass,
+        );
+        self.cleared = false;
+    }
+
+    /// Uploads the view and projection of `camera`.
+    pub fn update(&mut self, queue: &wgpu::Queue, camera: &Camera) {
+        let projection = camera.projection_matrix();
+        self.uniform.projection = projection.to_cols_array_2d();
+        self.uniform.inverse_projection = projection.inverse().to_cols_array_2d();
+        self.uniform.view = camera.view_matrix().to_cols_array_2d();
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&self.uniform));
+    }
+
+    /// Records the occlusion and blur passes, reading the prepass rendered earlier in the frame.
+    /// Only clears the occlusion while SSAO is disabled.
+    pub fn render(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        mut profiler: Option<&mut GpuProfiler>,
+    ) {
+        if !self.enabled {
+            if !std::mem::replace(&mut self.cleared, true) {
+                // The pass only needs to begin to clear the target.
+                encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("ssao clear"),
+                    color_attachments: &[Some(attachment(&self.targets.occlusion.view))],
+                    ..Default::default()
+                });
+            }
+            return;
+        }
+
+        let passes = [
+            (
+                "ssao",
+                &self.pipeline,
+                &self.targets.bind_group,
+                &self.targets.occlusion,
+            ),
+            (
+                "ssao blur x",
+                &self.horizontal_pipeline,
+                &self.targets.horizontal_bind_group,
+                &self.targets.blurred,
+            ),
+            (
+                "ssao blur y",
+                &self.vertical_pipeline,
+                &self.targets.vertical_bind_group,
+                &self.targets.occlusion,
+            ),
+        ];
+        for (name, pipeline, bind_group, output) in passes {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some(name),
+                color_attachments: &[Some(attachment(&output.view))],
+                depth_stencil_attachment: None,
+                timestamp_writes: profiler
+                    .as_deref_mut()
+                    .and_then(|profiler| profiler.timestamp_writes(name)),
+                occlusion_query_set: None,
+            });
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(0, bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+    }
+}
+
+/// Clears `view` to white, unoccluded where nothing gets drawn.
+fn attachment(view: &wgpu::TextureView) -> wgpu::RenderPassColorAttachment<'_> {
+    wgpu::RenderPassColorAttachment {
+        view,
+        resolve_target: None,
+        ops: wgpu::Operations {
+            load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+            store: wgpu::StoreOp::Store,
+        },
+    }
+}
+
+/// Points in the unit hemisphere around +z, scaled so that more of them lie close to the
+/// center, where occluders matter most.
+fn kernel() -> [[f32; 4]; KERNEL_SIZE] {
+    std::array::from_fn(|i| {
+        let direction = Vec3::new(
+            random(i as u32 * 3) * 2.0 - 1.0,
+            random(i as u32 * 3 + 1) * 2.0 - 1.0,
+            random(i as u32 * 3 + 2),
+        )
+        .normalize_or_zero();
+        let scale = (i as f32 + 1.0) / KERNEL_SIZE as f32;
+        (direction * (0.1 + 0.9 * scale * scale))
+            .extend(0.0)
+            .to_array()
+    })
+}
+
+/// A hash of `seed` mapped to `0..1`, so that the kernel and noise are the same every run.
+fn random(seed: u32) -> f32 {
+    let mut x = seed.wrapping_mul(0x9e37_79b9) ^ 0x85eb_ca6b;
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846c_a68b);
+    x ^= x >> 16;
+    x as f32 / u32::MAX as f32
+}
+
+/// Random directions in the xy plane, which rotate the kernel around each pixel's normal.
+fn create_noise_texture(device: &wgpu::Device, queue: &wgpu::Queue) -> wgpu::TextureView {
+    let texels: Vec<i8> = (0..NOISE_SIZE * NOISE_SIZE)
+        .flat_map(|i| {
+            let angle = random(i + 1000) * std::f32::consts::TAU;
+            [
+                (angle.cos() * 127.0) as i8,
+                (angle.sin() * 127.0) as i8,
+                0,
+                0,
+            ]
+        })
+        .collect();
+    let texture = device.create_texture_with_data(
+        queue,
+        &wgpu::TextureDescriptor {
+            label: Some("ssao noise"),
+            size: wgpu::Extent3d {
+                width: NOISE_SIZE,
+                height: NOISE_SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Snorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        },
+        wgpu::util::TextureDataOrder::LayerMajor,
+        bytemuck::cast_slice(&texels),
+    );
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+fn create_targets(
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+    layout: &wgpu::BindGroupLayout,
+    blur_layout: &wgpu::BindGroupLayout,
+    buffer: &wgpu::Buffer,
+    noise: &wgpu::TextureView,
+    prepass: &Prepass,
+) -> Targets {
+    let occlusion =
+        Texture::create_render_target(device, config, OCCLUSION_FORMAT, 1, "ssao occlusion");
+    let blurred = Texture::create_render_target(device, config, OCCLUSION_FORMAT, 1, "ssao blur");
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("ssao"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&prepass.depth().view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(&prepass.normal().view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::TextureView(noise),
+            },
+        ],
+    });
+    let blur_bind_group = |input: &Texture| {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("ssao blur"),
+            layout: blur_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&input.view),
+            }],
+        })
+    };
+    let horizontal_bind_group = blur_bind_group(&occlusion);
+    let vertical_bind_group = blur_bind_group(&blurred);
+
+    Targets {
+        occlusion,
+        blurred,
+        bind_group,
+        horizontal_bind_group,
+        vertical_bind_group,
+    }
+}
+
+/// A pass drawing a fullscreen triangle into an [`OCCLUSION_FORMAT`] target.
+fn create_fullscreen_pipeline(
+    device: &wgpu::Device,
+    label: &str,
+    layout: &wgpu::PipelineLayout,
+    module: &wgpu::ShaderModule,
+    constants: HashMap<String, f64>,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module,
+            entry_point: "vs_main",
+            buffers: &[],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        fragment: Some(wgpu::FragmentState {
+            module,
+            entry_point: "fs_main",
+            targets: &[Some(OCCLUSION_FORMAT.into())],
+            compilation_options: wgpu::PipelineCompilationOptions {
+                constants: &constants,
+                ..Default::default()
+            },
+        }),
+        multiview: None,
+        cache: None,
+    })
+}
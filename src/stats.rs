@@ -0,0 +1,108 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use web_time::Instant;
+
+/// The number of frames the statistics are computed over.
+const WINDOW: usize = 600;
+
+/// Timings of the most recent frames, for showing performance statistics.
+#[derive(Debug)]
+pub struct FrameStats {
+    last_frame: Option<Instant>,
+    frame_times: VecDeque<Duration>,
+    cpu_times: VecDeque<Duration>,
+    gpu_times: VecDeque<Duration>,
+    pass_times: Vec<(&'static str, VecDeque<Duration>)>,
+}
+
+impl Default for FrameStats {
+    fn default() -> Self {
+        Self {
+            last_frame: None,
+            frame_times: VecDeque::with_capacity(WINDOW),
+            cpu_times: VecDeque::with_capacity(WINDOW),
+            gpu_times: VecDeque::with_capacity(WINDOW),
+            pass_times: Vec::new(),
+        }
+    }
+}
+
+fn push(times: &mut VecDeque<Duration>, time: Duration) {
+    if times.len() == WINDOW {
+        times.pop_front();
+    }
+    times.push_back(time);
+}
+
+fn average(times: &VecDeque<Duration>) -> Option<Duration> {
+    let count = u32::try_from(times.len()).ok().filter(|&count| count > 0)?;
+    Some(times.iter().sum::<Duration>() / count)
+}
+
+impl FrameStats {
+    /// Records a frame that took `cpu_time` to update and record, and `gpu_time` to execute if
+    /// it is known, along with the GPU time of each of its passes. The frame time is measured
+    /// from the previous call.
+    pub fn record(
+        &mut self,
+        cpu_time: Duration,
+        gpu_time: Option<Duration>,
+        pass_times: &[(&'static str, Duration)],
+    ) {
+        let now = Instant::now();
+        if let Some(last_frame) = self.last_frame.replace(now) {
+            push(&mut self.frame_times, now - last_frame);
+        }
+        push(&mut self.cpu_times, cpu_time);
+        if let Some(gpu_time) = gpu_time {
+            push(&mut self.gpu_times, gpu_time);
+        }
+        for &(name, time) in pass_times {
+            let index = match self.pass_times.iter().position(|(pass, _)| *pass == name) {
+                Some(index) => index,
+                None => {
+                    self.pass_times
+                        .push((name, VecDeque::with_capacity(WINDOW)));
+                    self.pass_times.len() - 1
+                }
+            };
+            push(&mut self.pass_times[index].1, time);
+        }
+    }
+
+    pub fn average_frame_time(&self) -> Option<Duration> {
+        average(&self.frame_times)
+    }
+
+    pub fn fps(&self) -> Option<f32> {
+        self.average_frame_time()
+            .map(|frame_time| frame_time.as_secs_f32().recip())
+    }
+
+    /// The frame rate averaged over the slowest 1% of frames.
+    pub fn one_percent_low(&self) -> Option<f32> {
+        let mut frame_times: Vec<_> = self.frame_times.iter().copied().collect();
+        frame_times.sort_unstable_by(|a, b| b.cmp(a));
+        let slowest = frame_times.len().div_ceil(100);
+        average(&frame_times.into_iter().take(slowest).collect())
+            .map(|frame_time| frame_time.as_secs_f32().recip())
+    }
+
+    /// The average time spent on the CPU updating and recording a frame.
+    pub fn cpu_time(&self) -> Option<Duration> {
+        average(&self.cpu_times)
+    }
+
+    /// The average time the GPU took to execute a frame.
+    pub fn gpu_time(&self) -> Option<Duration> {
+        average(&self.gpu_times)
+    }
+
+    /// The average GPU time of each pass, in the order the passes were first recorded.
+    pub fn pass_times(&self) -> impl Iterator<Item = (&'static str, Duration)> + '_ {
+        self.pass_times
+            .iter()
+            .filter_map(|(name, times)| Some((*name, average(times)?)))
+    }
+}
@@ -0,0 +1,119 @@
+//! Vector graphics tessellated from SVG files into static meshes, drawn with the sprite
+//! pipeline so they stay sharp at any resolution they were tessellated for.
+
+use glam::{Mat4, Vec2};
+use wgpu::util::DeviceExt;
+
+use crate::sprite::{SpriteBatch, SpriteVertex};
+use crate::texture::Texture;
+
+/// How far, in pixels, the tessellated edges of an SVG may stray from its true curves at the
+/// scale it was tessellated for.
+pub const CURVE_TOLERANCE: f32 = 0.1;
+
+/// The triangles of an SVG in its own units, from the top left, Y down, as loaded by
+/// [`assets::svg`](crate::assets::svg).
+#[derive(Clone, Debug, Default)]
+pub struct SvgData {
+    /// The size of the document.
+    pub size: Vec2,
+    pub vertices: Vec<SpriteVertex>,
+    pub indices: Vec<u32>,
+}
+
+/// An [`SvgData`] uploaded to the GPU, drawn with the sprite pipeline of a [`SpriteBatch`]
+/// through the 2D camera.
+pub struct Svg {
+    /// Places the SVG's units in the 2D camera's world.
+    pub transform: Mat4,
+    size: Vec2,
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    index_count: u32,
+    /// The view projection times `transform`.
+    uniform: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    /// A white texel, which the sprite pipeline samples and multiplies by the vertex colors.
+    white: wgpu::BindGroup,
+}
+
+impl Svg {
+    /// Uploads the triangles of `data`, with bind groups for the layouts of `batch`.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        data: &SvgData,
+        transform: Mat4,
+        batch: &SpriteBatch,
+    ) -> Self {
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("svg vertices"),
+            contents: bytemuck::cast_slice(&data.vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("svg indices"),
+            contents: bytemuck::cast_slice(&data.indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+        let uniform = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("svg projection"),
+            contents: bytemuck::bytes_of(&Mat4::IDENTITY.to_cols_array_2d()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("svg projection"),
+            layout: batch.projection_layout(),
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform.as_entire_binding(),
+            }],
+        });
+        let white = Texture::from_color(
+            device,
+            queue,
+            [255; 4],
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+            "svg white",
+        )
+        .create_bind_group(device, batch.texture_layout());
+
+        Self {
+            transform,
+            size: data.size,
+            vertex_buffer,
+            index_buffer,
+            index_count: data.indices.len() as u32,
+            uniform,
+            bind_group,
+            white,
+        }
+    }
+
+    /// The size of the document in its own units.
+    pub fn size(&self) -> Vec2 {
+        self.size
+    }
+
+    /// Places the SVG for the 2D camera's `view_projection`.
+    pub fn update(&self, queue: &wgpu::Queue, view_projection: Mat4) {
+        queue.write_buffer(
+            &self.uniform,
+            0,
+            bytemuck::bytes_of(&(view_projection * self.transform).to_cols_array_2d()),
+        );
+    }
+
+    /// Draws the SVG with `pipeline` from [`SpriteBatch::pipeline_builder`].
+    pub fn render(&self, render_pass: &mut wgpu::RenderPass, pipeline: &wgpu::RenderPipeline) {
+        if self.index_count == 0 {
+            return;
+        }
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_bind_group(1, &self.white, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        render_pass.draw_indexed(0..self.index_count, 0, 0..1);
+    }
+}
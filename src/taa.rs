@@ -0,0 +1,270 @@
+use std::sync::Arc;
+
+use glam::Vec2;
+use wgpu::util::DeviceExt;
+
+use crate::pipeline_cache::PipelineBuilder;
+use crate::post_process::{self, Effect, EffectContext, EffectPipelineKey};
+use crate::shader::{self, Preprocessor};
+use crate::texture::Texture;
+use crate::tonemap::HDR_FORMAT;
+
+/// The format of the motion vectors written by the scene pass.
+pub const MOTION_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rg16Float;
+
+/// The number of jitter offsets cycled through.
+const JITTER_PHASES: u32 = 8;
+
+/// The subpixel offset of the camera on frame `index`, in pixels from the pixel center, from
+/// the (2, 3) Halton sequence.
+pub fn jitter(index: u32) -> Vec2 {
+    let index = index % JITTER_PHASES + 1;
+    Vec2::new(halton(index, 2), halton(index, 3)) - 0.5
+}
+
+fn halton(mut index: u32, base: u32) -> f32 {
+    let mut fraction = 1.0;
+    let mut result = 0.0;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
+
+/// `Taa` in `taa.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct TaaUniform {
+    blend: f32,
+    _padding: [f32; 3],
+}
+
+/// Temporal anti-aliasing, an [`Effect`] accumulating frames rendered with the camera jittered
+/// by [`jitter`] into a history texture. The history is reprojected with the scene's motion
+/// vectors and clamped to each pixel's neighbourhood in the current frame.
+///
+/// Meant to run first, on the HDR scene.
+pub struct Taa {
+    /// The weight of the current frame in the history, lower values smoothing more but
+    /// ghosting longer.
+    pub blend: f32,
+    /// Drops the history on the next frame.
+    reset: bool,
+    /// Read and written in turns, `history[current]` holding the last frame.
+    history: [Texture; 2],
+    current: usize,
+    buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline_layout: Arc<wgpu::PipelineLayout>,
+    shader_module: Arc<wgpu::ShaderModule>,
+}
+
+impl Taa {
+    /// Compiles `taa.wgsl` with `preprocessor` and creates the history for targets the size of
+    /// `config`.
+    pub fn new(
+        device: &wgpu::Device,
+        preprocessor: &Preprocessor,
+        config: &wgpu::SurfaceConfiguration,
+    ) -> anyhow::Result<Self> {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("taa"),
+            contents: bytemuck::bytes_of(&TaaUniform {
+                blend: 1.0,
+                _padding: [0.0; 3],
+            }),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let texture = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let [input, input_sampler] = post_process::input_layout_entries();
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("taa"),
+            entries: &[
+                input,
+                input_sampler,
+                texture(2),
+                texture(3),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = Arc::new(device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("taa"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            },
+        ));
+        let shader_module = Arc::new(shader::create_module(device, preprocessor, "taa.wgsl")?);
+
+        Ok(Self {
+            blend: 0.1,
+            reset: true,
+            history: create_history(device, config),
+            current: 0,
+            buffer,
+            bind_group_layout,
+            pipeline_layout,
+            shader_module,
+        })
+    }
+
+    /// Discards the history, e.g. after the camera cut to a different view.
+    pub fn reset(&mut self) {
+        self.reset = true;
+    }
+}
+
+impl Effect for Taa {
+    fn name(&self) -> &'static str {
+        "taa"
+    }
+
+    fn update(&mut self, queue: &wgpu::Queue) {
+        let uniform = TaaUniform {
+            blend: if self.reset { 1.0 } else { self.blend },
+            _padding: [0.0; 3],
+        };
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&uniform));
+    }
+
+    fn resize(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) {
+        self.history = create_history(device, config);
+        self.reset = true;
+    }
+
+    fn render(
+        &mut self,
+        context: &mut EffectContext,
+        input: &Texture,
+        output: &wgpu::TextureView,
+        format: wgpu::TextureFormat,
+    ) {
+        let key = EffectPipelineKey {
+            effect: self.name(),
+            format,
+        };
+        let pipeline = context.pipelines.get(
+            &key,
+            &*pipeline_builder(
+                self.pipeline_layout.clone(),
+                self.shader_module.clone(),
+                format,
+            ),
+        );
+        let [input, input_sampler] = post_process::input_bind_group_entries(input);
+        let bind_group = context
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("taa"),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    input,
+                    input_sampler,
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(
+                            &self.history[self.current].view,
+                        ),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: wgpu::BindingResource::TextureView(&context.motion.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: self.buffer.as_entire_binding(),
+                    },
+                ],
+            });
+
+        let next = 1 - self.current;
+        let attachment = |view| {
+            Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    // The triangle covers every pixel.
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })
+        };
+        let mut render_pass = context
+            .encoder
+            .begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("taa"),
+                color_attachments: &[attachment(output), attachment(&self.history[next].view)],
+                depth_stencil_attachment: None,
+                timestamp_writes: context
+                    .profiler
+                    .as_mut()
+                    .and_then(|profiler| profiler.timestamp_writes("taa")),
+                occlusion_query_set: None,
+            });
+        render_pass.set_pipeline(&pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+
+        self.current = next;
+        self.reset = false;
+    }
+}
+
+fn create_history(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> [Texture; 2] {
+    [0, 1].map(|_| Texture::create_render_target(device, config, HDR_FORMAT, 1, "taa history"))
+}
+
+/// Like [`post_process::pipeline_builder`], with the history as a second target.
+fn pipeline_builder(
+    layout: Arc<wgpu::PipelineLayout>,
+    shader_module: Arc<wgpu::ShaderModule>,
+    format: wgpu::TextureFormat,
+) -> Arc<PipelineBuilder> {
+    Arc::new(move |device, cache| {
+        let constants = post_process::output_constants(format);
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("taa"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader_module,
+                entry_point: "vs_main",
+                buffers: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader_module,
+                entry_point: "fs_main",
+                targets: &[Some(format.into()), Some(HDR_FORMAT.into())],
+                compilation_options: wgpu::PipelineCompilationOptions {
+                    constants: &constants,
+                    ..Default::default()
+                },
+            }),
+            multiview: None,
+            cache,
+        })
+    })
+}
@@ -0,0 +1,738 @@
+//! Terrain from a heightmap: a grid of vertices raised by the brightness of its pixels, lit and
+//! shadowed like the meshes, with textures splatted over it by height and slope.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::Path;
+
+use anyhow::Context;
+use glam::{Mat4, Vec2, Vec3};
+use wgpu::util::DeviceExt;
+
+use crate::instance::Instance;
+use crate::math::{Aabb, Frustum};
+use crate::mesh::{DrawEncoder, Mesh, MeshData};
+use crate::mesh_allocator::MeshAllocator;
+use crate::mipmap::{mip_level_count, MipmapGenerator};
+use crate::shader::Preprocessor;
+use crate::texture::SamplerDesc;
+use crate::vertex::Vertex;
+
+/// The most layers splatted over a terrain, `MAX_TERRAIN_LAYERS` in `shader.wgsl`.
+pub const MAX_TERRAIN_LAYERS: usize = 4;
+
+/// A texture splatted over the terrain where its height and slope fall within ranges, blended
+/// with the other layers where their ranges overlap.
+#[derive(Clone, Debug)]
+pub struct TerrainLayer {
+    pub image: image::RgbaImage,
+    /// World units one repetition of the image spans.
+    pub scale: f32,
+    /// The world heights the layer covers.
+    pub heights: Range<f32>,
+    /// The slopes the layer covers, from 0 on flat ground to 1 on vertical cliffs.
+    pub slopes: Range<f32>,
+    pub roughness: f32,
+}
+
+impl TerrainLayer {
+    /// `image` repeated every `scale` units over every height and slope.
+    pub fn new(image: image::RgbaImage, scale: f32) -> Self {
+        Self {
+            image,
+            scale,
+            heights: f32::MIN..f32::MAX,
+            slopes: 0.0..1.0,
+            roughness: 0.9,
+        }
+    }
+
+    pub fn with_heights(mut self, heights: Range<f32>) -> Self {
+        self.heights = heights;
+        self
+    }
+
+    pub fn with_slopes(mut self, slopes: Range<f32>) -> Self {
+        self.slopes = slopes;
+        self
+    }
+}
+
+/// A heightmap on the CPU and the layers splatted over it. The terrain is centred on the origin
+/// in the XZ plane, its samples `spacing` apart.
+#[derive(Clone, Debug)]
+pub struct TerrainData {
+    /// From 0 to 1, a row of `width` samples along X for each of the `depth` along Z.
+    pub heights: Vec<f32>,
+    pub width: u32,
+    pub depth: u32,
+    /// World units between neighbouring samples.
+    pub spacing: f32,
+    /// The world height of a sample of 1.
+    pub height_scale: f32,
+    /// Up to [`MAX_TERRAIN_LAYERS`], the first showing wherever none covers the terrain. Plain
+    /// white without any.
+    pub layers: Vec<TerrainLayer>,
+    /// How far past the ends of their ranges layers fade out, in world units.
+    pub height_blend: f32,
+    /// Likewise in slope.
+    pub slope_blend: f32,
+}
+
+impl TerrainData {
+    /// The brightness of each pixel of `image` as the height of a sample, without layers.
+    pub fn from_image(image: &image::DynamicImage, spacing: f32, height_scale: f32) -> Self {
+        let luma = image.to_luma16();
+        Self {
+            heights: luma
+                .pixels()
+                .map(|pixel| pixel.0[0] as f32 / u16::MAX as f32)
+                .collect(),
+            width: luma.width(),
+            depth: luma.height(),
+            spacing,
+            height_scale,
+            layers: Vec::new(),
+            height_blend: 1.0,
+            slope_blend: 0.05,
+        }
+    }
+
+    /// Decodes a heightmap image file, see [`TerrainData::from_image`]. 16-bit grayscale PNGs
+    /// keep their precision.
+    pub fn load(path: impl AsRef<Path>, spacing: f32, height_scale: f32) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let image =
+            image::open(path).with_context(|| format!("failed to load {}", path.display()))?;
+        Ok(Self::from_image(&image, spacing, height_scale))
+    }
+
+    pub fn with_layer(mut self, layer: TerrainLayer) -> Self {
+        self.layers.push(layer);
+        self
+    }
+
+    /// The extent of the terrain along X and Z.
+    pub fn size(&self) -> Vec2 {
+        Vec2::new(
+            self.width.saturating_sub(1) as f32,
+            self.depth.saturating_sub(1) as f32,
+        ) * self.spacing
+    }
+
+    /// The world height of sample `(x, z)`, clamped to the edges.
+    pub fn height(&self, x: i32, z: i32) -> f32 {
+        let x = x.min(self.width as i32 - 1).max(0) as usize;
+        let z = z.min(self.depth as i32 - 1).max(0) as usize;
+        self.heights
+            .get(z * self.width as usize + x)
+            .map_or(0.0, |height| height * self.height_scale)
+    }
+
+    /// The world height under `position` on the XZ plane, interpolated between the samples
+    /// around it, e.g. to place things on the ground.
+    pub fn height_at(&self, position: Vec2) -> f32 {
+        let sample = (position + self.size() * 0.5) / self.spacing.max(f32::EPSILON);
+        let corner = sample.floor();
+        let t = sample - corner;
+        let (x, z) = (corner.x as i32, corner.y as i32);
+        let near = self.height(x, z) + (self.height(x + 1, z) - self.height(x, z)) * t.x;
+        let far = self.height(x, z + 1) + (self.height(x + 1, z + 1) - self.height(x, z + 1)) * t.x;
+        near + (far - near) * t.y
+    }
+
+    /// The world position of sample `(x, z)`.
+    pub fn position(&self, x: u32, z: u32) -> Vec3 {
+        let offset = self.size() * 0.5;
+        Vec3::new(
+            x as f32 * self.spacing - offset.x,
+            self.height(x as i32, z as i32),
+            z as f32 * self.spacing - offset.y,
+        )
+    }
+
+    /// The normal at sample `(x, z)`, from the differences between the heights of its
+    /// neighbours.
+    pub fn normal(&self, x: u32, z: u32) -> Vec3 {
+        let (x, z) = (x as i32, z as i32);
+        let dx = self.height(x + 1, z) - self.height(x - 1, z);
+        let dz = self.height(x, z + 1) - self.height(x, z - 1);
+        Vec3::new(-dx, 2.0 * self.spacing, -dz).normalize_or_zero()
+    }
+
+    /// A grid with a vertex at every sample, its UVs spanning `0..1` over the whole terrain.
+    pub fn mesh(&self) -> MeshData {
+        self.grid(0, 0, self.width.max(self.depth), 1, 0.0)
+    }
+
+    /// A grid through every `step`th sample from sample `(x, z)`, `quads` steps along X and Z
+    /// and cut short by the edges of the heightmap, with a skirt hanging `skirt` below its edges
+    /// if positive. UVs span `0..1` over the whole terrain, like [`TerrainData::mesh`].
+    pub fn grid(&self, x: u32, z: u32, quads: u32, step: u32, skirt: f32) -> MeshData {
+        let mut mesh = MeshData::default();
+        if self.width == 0 || self.depth == 0 {
+            return mesh;
+        }
+        let samples = |start: u32, count: u32| {
+            let mut samples: Vec<u32> = (0..=quads)
+                .map(|quad| (start + quad * step.max(1)).min(count - 1))
+                .collect();
+            samples.dedup();
+            samples
+        };
+        let (xs, zs) = (samples(x, self.width), samples(z, self.depth));
+        let last = Vec2::new(
+            self.width.saturating_sub(1).max(1) as f32,
+            self.depth.saturating_sub(1).max(1) as f32,
+        );
+        for &z in &zs {
+            for &x in &xs {
+                mesh.vertices.push(Vertex {
+                    position: self.position(x, z).to_array(),
+                    normal: self.normal(x, z).to_array(),
+                    color: [1.0; 3],
+                    uv: (Vec2::new(x as f32, z as f32) / last).to_array(),
+                    tangent: [0.0; 4],
+                });
+            }
+        }
+        // Counter-clockwise seen from above, like `primitives::plane`.
+        let row = xs.len() as u32;
+        for z in 0..zs.len() as u32 - 1 {
+            for x in 0..row - 1 {
+                let a = z * row + x;
+                let b = a + row;
+                mesh.indices
+                    .extend_from_slice(&[a, b, b + 1, a, b + 1, a + 1]);
+            }
+        }
+        mesh.generate_tangents();
+        if skirt <= 0.0 {
+            return mesh;
+        }
+
+        let rows = zs.len() as u32;
+        let edges: [Vec<u32>; 4] = [
+            (0..row).collect(),
+            (0..row).map(|x| (rows - 1) * row + x).collect(),
+            (0..rows).map(|z| z * row).collect(),
+            (0..rows).map(|z| z * row + row - 1).collect(),
+        ];
+        for edge in edges {
+            let base = mesh.vertices.len() as u32;
+            for &top in &edge {
+                let mut vertex = mesh.vertices[top as usize];
+                vertex.position[1] -= skirt;
+                mesh.vertices.push(vertex);
+            }
+            for (index, pair) in edge.windows(2).enumerate() {
+                let (a, b) = (pair[0], pair[1]);
+                let (c, d) = (base + index as u32 + 1, base + index as u32);
+                // Wound both ways, as the crack it fills may be seen from either side.
+                mesh.indices
+                    .extend_from_slice(&[a, b, c, a, c, d, a, c, b, a, d, c]);
+            }
+        }
+        mesh
+    }
+}
+
+/// A layer as `shader.wgsl` reads it.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct LayerUniform {
+    /// The heights the layer covers in `x` and `y`, and the slopes in `z` and `w`.
+    ranges: [f32; 4],
+    scale: f32,
+    roughness: f32,
+    _padding: [f32; 2],
+}
+
+/// `Terrain` in `shader.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct TerrainUniform {
+    layers: [LayerUniform; MAX_TERRAIN_LAYERS],
+    layer_count: u32,
+    height_blend: f32,
+    slope_blend: f32,
+    _padding: u32,
+}
+
+/// Quads along each side of a chunk at every level of detail, a chunk of each coarser level
+/// covering four of the level below.
+pub const CHUNK_QUADS: u32 = 64;
+
+/// How many frames the mesh of a chunk is kept after it was last drawn, so that moving back and
+/// forth doesn't rebuild it.
+const KEEP_FRAMES: u64 = 120;
+
+/// A node of the quadtree: a chunk of `level`, which steps over `1 << level` samples at once,
+/// by its column along X and row along Z among the chunks of its level.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct ChunkKey {
+    level: u32,
+    column: u32,
+    row: u32,
+}
+
+impl ChunkKey {
+    /// The samples along each side of the chunk.
+    fn span(self) -> u32 {
+        CHUNK_QUADS << self.level
+    }
+
+    /// The four chunks of the level below covering this one.
+    fn children(self) -> [ChunkKey; 4] {
+        [(0, 0), (1, 0), (0, 1), (1, 1)].map(|(x, z)| ChunkKey {
+            level: self.level - 1,
+            column: self.column * 2 + x,
+            row: self.row * 2 + z,
+        })
+    }
+}
+
+/// The chunks of one level of the quadtree, by the lowest and highest heights under each.
+struct Level {
+    columns: u32,
+    rows: u32,
+    /// Row by row.
+    heights: Vec<(f32, f32)>,
+}
+
+impl Level {
+    fn heights(&self, column: u32, row: u32) -> Option<(f32, f32)> {
+        (column < self.columns && row < self.rows)
+            .then(|| self.heights[(row * self.columns + column) as usize])
+    }
+}
+
+/// The mesh of a chunk and the frame it was last drawn in.
+struct Chunk {
+    mesh: Mesh,
+    last_used: u64,
+}
+
+/// A terrain uploaded for drawing, as a quadtree of chunks: every frame the chunks near the
+/// camera are drawn from the finest level and those further away from coarser ones, so that
+/// distant terrain costs as many triangles as nearby terrain. Chunks are meshed when first
+/// drawn, with skirts hanging from their edges hiding the cracks between neighbours of
+/// different levels.
+///
+/// The chunks are drawn with an instance placing them at the origin, and with the layers bound
+/// at `@group(2)` of `shader.wgsl` with `TERRAIN` defined, in place of a material.
+pub struct Terrain {
+    /// Without its layers, which are uploaded.
+    data: TerrainData,
+    /// From the finest to the coarsest, a single chunk covering the whole terrain.
+    levels: Vec<Level>,
+    chunks: HashMap<ChunkKey, Chunk>,
+    /// The chunks picked for the camera by the last update, in view or not.
+    selected: Vec<ChunkKey>,
+    /// The picked chunks in the camera's view.
+    visible: Vec<ChunkKey>,
+    frame: u64,
+    instance_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    /// How far from the camera, in multiples of their size, chunks are drawn rather than their
+    /// four finer children. Larger values draw finer chunks further away.
+    pub lod_distance: f32,
+}
+
+impl Terrain {
+    /// The layout of the layers' uniform, texture array and sampler.
+    pub fn bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("terrain"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        })
+    }
+
+    /// Defines what `shader.wgsl` needs to splat the layers in place of sampling materials.
+    pub fn configure_preprocessor(preprocessor: Preprocessor) -> Preprocessor {
+        preprocessor.define("TERRAIN", "")
+    }
+
+    /// Uploads the layers of `data`, each resized to the size of the first, and prepares its
+    /// quadtree. Chunks are meshed by [`Terrain::update`].
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        mut data: TerrainData,
+        mipmaps: &mut MipmapGenerator,
+    ) -> Self {
+        let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("terrain instance"),
+            contents: bytemuck::bytes_of(&Instance::default().to_raw(Mat4::IDENTITY)),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        if data.layers.len() > MAX_TERRAIN_LAYERS {
+            eprintln!(
+                "only splatting {MAX_TERRAIN_LAYERS} of {} terrain layers",
+                data.layers.len()
+            );
+        }
+        let white = TerrainLayer::new(
+            image::RgbaImage::from_pixel(1, 1, image::Rgba([255; 4])),
+            1.0,
+        );
+        let layers: Vec<_> = if data.layers.is_empty() {
+            vec![&white]
+        } else {
+            data.layers.iter().take(MAX_TERRAIN_LAYERS).collect()
+        };
+        let mut uniform = TerrainUniform {
+            layer_count: layers.len() as u32,
+            height_blend: data.height_blend,
+            slope_blend: data.slope_blend,
+            ..Default::default()
+        };
+        for (raw, layer) in uniform.layers.iter_mut().zip(&layers) {
+            *raw = LayerUniform {
+                // Kept finite so that the shader's fades don't overflow.
+                ranges: [
+                    layer.heights.start.max(-1e9),
+                    layer.heights.end.min(1e9),
+                    layer.slopes.start,
+                    layer.slopes.end,
+                ],
+                scale: layer.scale,
+                roughness: layer.roughness,
+                ..Default::default()
+            };
+        }
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("terrain"),
+            contents: bytemuck::bytes_of(&uniform),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let view = upload_layers(device, queue, &layers, mipmaps);
+        let sampler = device.create_sampler(&SamplerDesc::default().descriptor(Some("terrain")));
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("terrain"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+        // The images are only needed until they're uploaded.
+        data.layers = Vec::new();
+
+        Self {
+            levels: levels(&data),
+            data,
+            chunks: HashMap::new(),
+            selected: Vec::new(),
+            visible: Vec::new(),
+            frame: 0,
+            instance_buffer,
+            bind_group,
+            lod_distance: 2.0,
+        }
+    }
+
+    /// Picks the chunks to draw around `camera`, those in `frustum` for the camera's passes if
+    /// given and every one for the shadow maps, and meshes those that aren't yet.
+    pub fn update(
+        &mut self,
+        allocator: &MeshAllocator,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        camera: Vec3,
+        frustum: Option<&Frustum>,
+    ) {
+        self.frame += 1;
+        self.selected.clear();
+        if let Some(root) = self.levels.len().checked_sub(1) {
+            let root = ChunkKey {
+                level: root as u32,
+                column: 0,
+                row: 0,
+            };
+            self.select(root, camera);
+        }
+        self.visible.clear();
+        for &key in &self.selected {
+            if frustum.is_none_or(|frustum| frustum.intersects_aabb(&self.chunk_bounds(key))) {
+                self.visible.push(key);
+            }
+        }
+
+        for &key in &self.selected {
+            let chunk = self.chunks.entry(key).or_insert_with(|| {
+                let data = chunk_mesh(&self.data, &self.levels, key);
+                Chunk {
+                    mesh: Mesh::from_data(allocator, device, queue, &data),
+                    last_used: 0,
+                }
+            });
+            chunk.last_used = self.frame;
+        }
+        let frame = self.frame;
+        self.chunks
+            .retain(|_, chunk| frame - chunk.last_used < KEEP_FRAMES);
+    }
+
+    /// Picks `key` if it's far enough from `camera` or of the finest level, and otherwise its
+    /// children.
+    fn select(&mut self, key: ChunkKey, camera: Vec3) {
+        let bounds = self.chunk_bounds(key);
+        let distance = camera.clamp(bounds.min, bounds.max).distance(camera);
+        let size = key.span() as f32 * self.data.spacing;
+        if key.level == 0 || distance >= size * self.lod_distance {
+            self.selected.push(key);
+            return;
+        }
+        for child in key.children() {
+            if self.levels[child.level as usize]
+                .heights(child.column, child.row)
+                .is_some()
+            {
+                self.select(child, camera);
+            }
+        }
+    }
+
+    /// The box around the samples under `key`.
+    fn chunk_bounds(&self, key: ChunkKey) -> Aabb {
+        let (low, high) = self.levels[key.level as usize]
+            .heights(key.column, key.row)
+            .unwrap_or_default();
+        let span = key.span();
+        let end = |start: u32, count: u32| (start + span).min(count.saturating_sub(1));
+        let min = self.data.position(key.column * span, key.row * span);
+        let max = self.data.position(
+            end(key.column * span, self.data.width),
+            end(key.row * span, self.data.depth),
+        );
+        Aabb {
+            min: Vec3::new(min.x, low, min.z),
+            max: Vec3::new(max.x, high, max.z),
+        }
+    }
+
+    /// Records the draws of the chunks in view as of the last update. The pipeline and bind
+    /// groups are left to the caller.
+    pub fn draw<'a>(&'a self, encoder: &mut impl DrawEncoder<'a>) {
+        self.draw_chunks(encoder, &self.visible);
+    }
+
+    /// Like [`Terrain::draw`], with every chunk picked by the last update, for the shadow maps
+    /// that chunks outside the camera's view still cast shadows into.
+    pub fn draw_all<'a>(&'a self, encoder: &mut impl DrawEncoder<'a>) {
+        self.draw_chunks(encoder, &self.selected);
+    }
+
+    fn draw_chunks<'a>(&'a self, encoder: &mut impl DrawEncoder<'a>, keys: &[ChunkKey]) {
+        for key in keys {
+            if let Some(chunk) = self.chunks.get(key) {
+                chunk.mesh.draw(encoder, &self.instance_buffer, 0..1, None);
+            }
+        }
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    /// The chunks drawn in the camera's view as of the last update.
+    pub fn visible_chunks(&self) -> usize {
+        self.visible.len()
+    }
+
+    /// The box around the terrain in world space.
+    pub fn bounds(&self) -> Aabb {
+        match self.levels.len().checked_sub(1) {
+            Some(root) => self.chunk_bounds(ChunkKey {
+                level: root as u32,
+                column: 0,
+                row: 0,
+            }),
+            None => Aabb::from_points([]),
+        }
+    }
+}
+
+/// The levels of the quadtree over the samples of `data`, none without any quads.
+fn levels(data: &TerrainData) -> Vec<Level> {
+    let quads = (data.width.saturating_sub(1), data.depth.saturating_sub(1));
+    if quads.0 == 0 || quads.1 == 0 {
+        return Vec::new();
+    }
+    let (columns, rows) = (quads.0.div_ceil(CHUNK_QUADS), quads.1.div_ceil(CHUNK_QUADS));
+    let mut heights = Vec::with_capacity((columns * rows) as usize);
+    for row in 0..rows {
+        for column in 0..columns {
+            // Edges included, as they're shared with the neighbours.
+            let mut range = (f32::INFINITY, f32::NEG_INFINITY);
+            for z in row * CHUNK_QUADS..=((row + 1) * CHUNK_QUADS).min(quads.1) {
+                for x in column * CHUNK_QUADS..=((column + 1) * CHUNK_QUADS).min(quads.0) {
+                    let height = data.height(x as i32, z as i32);
+                    range = (range.0.min(height), range.1.max(height));
+                }
+            }
+            heights.push(range);
+        }
+    }
+    let mut levels = vec![Level {
+        columns,
+        rows,
+        heights,
+    }];
+    while let Some(below) = levels
+        .last()
+        .filter(|level| level.columns > 1 || level.rows > 1)
+    {
+        let (columns, rows) = (below.columns.div_ceil(2), below.rows.div_ceil(2));
+        let mut heights = Vec::with_capacity((columns * rows) as usize);
+        for row in 0..rows {
+            for column in 0..columns {
+                let range = [(0, 0), (1, 0), (0, 1), (1, 1)]
+                    .into_iter()
+                    .filter_map(|(x, z)| below.heights(column * 2 + x, row * 2 + z))
+                    .fold((f32::INFINITY, f32::NEG_INFINITY), |range, heights| {
+                        (range.0.min(heights.0), range.1.max(heights.1))
+                    });
+                heights.push(range);
+            }
+        }
+        levels.push(Level {
+            columns,
+            rows,
+            heights,
+        });
+    }
+    levels
+}
+
+/// The mesh of chunk `key`. Its skirt hangs as far as the heights under it span: the cracks
+/// along its edges are between samples of one of the two chunks meeting there, so neither is
+/// deeper than that for the coarser of them.
+fn chunk_mesh(data: &TerrainData, levels: &[Level], key: ChunkKey) -> MeshData {
+    let (low, high) = levels[key.level as usize]
+        .heights(key.column, key.row)
+        .unwrap_or_default();
+    let step = 1 << key.level;
+    let skirt = (high - low).max(data.spacing * step as f32);
+    data.grid(
+        key.column * key.span(),
+        key.row * key.span(),
+        CHUNK_QUADS,
+        step,
+        skirt,
+    )
+}
+
+/// Uploads the images of `layers` into the layers of an sRGB texture array, each resized to
+/// the size of the first, and fills their mip chains.
+fn upload_layers(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    layers: &[&TerrainLayer],
+    mipmaps: &mut MipmapGenerator,
+) -> wgpu::TextureView {
+    let (width, height) = layers[0].image.dimensions();
+    let size = wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: layers.len() as u32,
+    };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("terrain layers"),
+        size,
+        mip_level_count: mip_level_count(size),
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::COPY_DST
+            | wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    });
+    for (index, layer) in layers.iter().enumerate() {
+        let image = if layer.image.dimensions() == (width, height) {
+            Cow::Borrowed(&layer.image)
+        } else {
+            Cow::Owned(image::imageops::resize(
+                &layer.image,
+                width,
+                height,
+                image::imageops::FilterType::Triangle,
+            ))
+        };
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: 0,
+                    y: 0,
+                    z: index as u32,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            &image,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * width),
+                rows_per_image: Some(height),
+            },
+            wgpu::Extent3d {
+                depth_or_array_layers: 1,
+                ..size
+            },
+        );
+    }
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("mipmap"),
+    });
+    mipmaps.generate(device, &mut encoder, &texture);
+    queue.submit(std::iter::once(encoder.finish()));
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        label: Some("terrain layers"),
+        dimension: Some(wgpu::TextureViewDimension::D2Array),
+        ..Default::default()
+    })
+}
@@ -0,0 +1,313 @@
+//! Text drawn with TTF and OTF fonts through glyphon, which shapes it with cosmic-text and
+//! rasterizes the glyphs into an atlas on demand.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::Context;
+use glam::{Mat4, Vec2, Vec3};
+use glyphon::{
+    Attrs, Buffer, Cache, Color, ColorMode, Family, FontSystem, Metrics, Resolution, Shaping,
+    SwashCache, TextArea, TextAtlas, TextBounds, Viewport,
+};
+
+/// The font family text is set in, among the system fonts and those loaded with
+/// [`TextRenderer::load_font`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum FontFamily {
+    #[default]
+    SansSerif,
+    Serif,
+    Monospace,
+    Named(String),
+}
+
+impl FontFamily {
+    fn family(&self) -> Family<'_> {
+        match self {
+            FontFamily::SansSerif => Family::SansSerif,
+            FontFamily::Serif => Family::Serif,
+            FontFamily::Monospace => Family::Monospace,
+            FontFamily::Named(name) => Family::Name(name),
+        }
+    }
+}
+
+/// How queued text looks.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextStyle {
+    /// The size of the font, in physical pixels.
+    pub size: f32,
+    /// The distance between lines, relative to `size`.
+    pub line_height: f32,
+    /// sRGB color and linear alpha.
+    pub color: [u8; 4],
+    pub family: FontFamily,
+    /// Where the position lies in the text's bounds, from (0, 0) at their top left to (1, 1) at
+    /// their bottom right.
+    pub anchor: Vec2,
+    /// The color of a copy drawn a pixel down and right behind the text, keeping it readable
+    /// over any background.
+    pub shadow: Option<[u8; 4]>,
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        Self {
+            size: 16.0,
+            line_height: 1.25,
+            color: [255; 4],
+            family: FontFamily::SansSerif,
+            anchor: Vec2::ZERO,
+            shadow: None,
+        }
+    }
+}
+
+/// Where queued text is drawn.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextPosition {
+    /// In physical pixels from the top left of the surface.
+    Screen(Vec2),
+    /// A point in the world, projected with the camera, and hidden while behind it.
+    World(Vec3),
+}
+
+impl From<Vec2> for TextPosition {
+    fn from(position: Vec2) -> Self {
+        TextPosition::Screen(position)
+    }
+}
+
+impl From<Vec3> for TextPosition {
+    fn from(position: Vec3) -> Self {
+        TextPosition::World(position)
+    }
+}
+
+struct QueuedText {
+    text: String,
+    position: TextPosition,
+    style: TextStyle,
+}
+
+/// What a shaped [`Buffer`] depends on.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct BufferKey {
+    text: String,
+    size: u32,
+    line_height: u32,
+    family: FontFamily,
+}
+
+impl BufferKey {
+    fn new(text: &QueuedText) -> Self {
+        Self {
+            text: text.text.clone(),
+            size: text.style.size.to_bits(),
+            line_height: text.style.line_height.to_bits(),
+            family: text.style.family.clone(),
+        }
+    }
+}
+
+struct ShapedText {
+    buffer: Buffer,
+    /// The width of the longest line and the height of all of them.
+    size: Vec2,
+    used: bool,
+}
+
+/// Draws UTF-8 strings at points on screen or in the world. Text is queued anew every frame
+/// with [`TextRenderer::queue`], and text that stays the same from one frame to the next is
+/// only shaped once.
+///
+/// The system fonts are available where there are any. In the browser fonts have to be loaded
+/// with [`TextRenderer::load_font`] first.
+pub struct TextRenderer {
+    font_system: FontSystem,
+    swash_cache: SwashCache,
+    viewport: Viewport,
+    atlas: TextAtlas,
+    renderer: glyphon::TextRenderer,
+    queued: Vec<QueuedText>,
+    shaped: HashMap<BufferKey, ShapedText>,
+}
+
+impl TextRenderer {
+    /// Draws into `format` targets.
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, format: wgpu::TextureFormat) -> Self {
+        let cache = Cache::new(device);
+        let viewport = Viewport::new(device, &cache);
+        // Colors are given in sRGB, which targets without an sRGB view store as they are.
+        let color_mode = if format.is_srgb() {
+            ColorMode::Accurate
+        } else {
+            ColorMode::Web
+        };
+        let mut atlas = TextAtlas::with_color_mode(device, queue, &cache, format, color_mode);
+        let renderer =
+            glyphon::TextRenderer::new(&mut atlas, device, wgpu::MultisampleState::default(), None);
+
+        Self {
+            font_system: FontSystem::new(),
+            swash_cache: SwashCache::new(),
+            viewport,
+            atlas,
+            renderer,
+            queued: Vec::new(),
+            shaped: HashMap::new(),
+        }
+    }
+
+    /// Adds the fonts in a TTF, OTF or font collection file to the families text can use.
+    pub fn load_font(&mut self, data: Vec<u8>) {
+        self.font_system.db_mut().load_font_data(data);
+    }
+
+    /// Like [`TextRenderer::load_font`], reading the font from `path`.
+    pub fn load_font_file(&mut self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let data =
+            std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        self.load_font(data);
+        Ok(())
+    }
+
+    /// Draws `text` at `position` in the next frame. Lines are broken at newlines only.
+    pub fn queue(
+        &mut self,
+        text: impl Into<String>,
+        position: impl Into<TextPosition>,
+        style: TextStyle,
+    ) {
+        self.queued.push(QueuedText {
+            text: text.into(),
+            position: position.into(),
+            style,
+        });
+    }
+
+    /// Whether nothing is queued.
+    pub fn is_empty(&self) -> bool {
+        self.queued.is_empty()
+    }
+
+    /// Shapes the queued text, uploads the glyphs it's missing and empties the queue, for a
+    /// surface of `width` by `height` seen through `view_projection`.
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        view_projection: Mat4,
+        width: u32,
+        height: u32,
+    ) {
+        // The glyphs of the previous frame have been drawn by now.
+        self.atlas.trim();
+        self.viewport.update(queue, Resolution { width, height });
+        let keys: Vec<BufferKey> = self.queued.iter().map(BufferKey::new).collect();
+        for (text, key) in self.queued.iter().zip(&keys) {
+            if let Some(shaped) = self.shaped.get_mut(key) {
+                shaped.used = true;
+                continue;
+            }
+            let shaped = shape(&mut self.font_system, text);
+            self.shaped.insert(key.clone(), shaped);
+        }
+
+        let screen = Vec2::new(width as f32, height as f32);
+        let bounds = TextBounds {
+            left: 0,
+            top: 0,
+            right: width as i32,
+            bottom: height as i32,
+        };
+        let mut areas = Vec::new();
+        for (text, key) in self.queued.iter().zip(&keys) {
+            let Some(position) = project(text.position, view_projection, screen) else {
+                continue;
+            };
+            let shaped = &self.shaped[key];
+            let origin = (position - shaped.size * text.style.anchor).round();
+            let area = |offset: f32, [r, g, b, a]: [u8; 4]| TextArea {
+                buffer: &shaped.buffer,
+                left: origin.x + offset,
+                top: origin.y + offset,
+                scale: 1.0,
+                bounds,
+                default_color: Color::rgba(r, g, b, a),
+            };
+            if let Some(shadow) = text.style.shadow {
+                areas.push(area(1.0, shadow));
+            }
+            areas.push(area(0.0, text.style.color));
+        }
+        if let Err(err) = self.renderer.prepare(
+            device,
+            queue,
+            &mut self.font_system,
+            &mut self.atlas,
+            &self.viewport,
+            areas,
+            &mut self.swash_cache,
+        ) {
+            eprintln!("failed to prepare text: {err}");
+        }
+
+        self.queued.clear();
+        self.shaped
+            .retain(|_, shaped| std::mem::take(&mut shaped.used));
+    }
+
+    /// Draws the text of the last [`TextRenderer::prepare`].
+    pub fn render<'pass>(&'pass self, render_pass: &mut wgpu::RenderPass<'pass>) {
+        if let Err(err) = self
+            .renderer
+            .render(&self.atlas, &self.viewport, render_pass)
+        {
+            eprintln!("failed to draw text: {err}");
+        }
+    }
+}
+
+/// Lays out `text` without wrapping.
+fn shape(font_system: &mut FontSystem, text: &QueuedText) -> ShapedText {
+    let line_height = text.style.size * text.style.line_height;
+    let mut buffer = Buffer::new(font_system, Metrics::new(text.style.size, line_height));
+    buffer.set_size(font_system, None, None);
+    buffer.set_text(
+        font_system,
+        &text.text,
+        Attrs::new().family(text.style.family.family()),
+        Shaping::Advanced,
+    );
+    buffer.shape_until_scroll(font_system, false);
+    let (width, lines) = buffer
+        .layout_runs()
+        .fold((0.0f32, 0), |(width, lines), run| {
+            (width.max(run.line_w), lines + 1)
+        });
+
+    ShapedText {
+        buffer,
+        size: Vec2::new(width, lines as f32 * line_height),
+        used: true,
+    }
+}
+
+/// `position` in pixels from the top left of a `screen` sized surface, `None` behind the
+/// camera.
+fn project(position: TextPosition, view_projection: Mat4, screen: Vec2) -> Option<Vec2> {
+    match position {
+        TextPosition::Screen(position) => Some(position),
+        TextPosition::World(position) => {
+            let clip = view_projection * position.extend(1.0);
+            if clip.w <= 0.0 {
+                return None;
+            }
+            let ndc = clip.truncate() / clip.w;
+            Some(Vec2::new(ndc.x + 1.0, 1.0 - ndc.y) * 0.5 * screen)
+        }
+    }
+}
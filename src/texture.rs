@@ -0,0 +1,416 @@
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::mipmap::{self, MipmapGenerator};
+
+mod compressed;
+
+/// Filtering and addressing presets for the sampler of a [`Texture`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SamplerDesc {
+    /// Point sampling, for pixel art and lookup tables.
+    Nearest(wgpu::AddressMode),
+    /// Bilinear filtering with linear blending between mip levels.
+    Linear(wgpu::AddressMode),
+    /// Linear filtering with anisotropy clamped to `max_anisotropy`, which must be in `1..=16`.
+    Anisotropic {
+        address_mode: wgpu::AddressMode,
+        max_anisotropy: u16,
+    },
+}
+
+impl Default for SamplerDesc {
+    fn default() -> Self {
+        SamplerDesc::Linear(wgpu::AddressMode::Repeat)
+    }
+}
+
+impl SamplerDesc {
+    /// Falls back to plain linear filtering when the adapter can't filter anisotropically.
+    pub fn supported(self, downlevel: &wgpu::DownlevelCapabilities) -> Self {
+        match self {
+            SamplerDesc::Anisotropic { address_mode, .. }
+                if !downlevel
+                    .flags
+                    .contains(wgpu::DownlevelFlags::ANISOTROPIC_FILTERING) =>
+            {
+                SamplerDesc::Linear(address_mode)
+            }
+            desc => desc,
+        }
+    }
+
+    pub fn descriptor<'a>(&self, label: Option<&'a str>) -> wgpu::SamplerDescriptor<'a> {
+        let (address_mode, filter, anisotropy_clamp) = match *self {
+            SamplerDesc::Nearest(address_mode) => (address_mode, wgpu::FilterMode::Nearest, 1),
+            SamplerDesc::Linear(address_mode) => (address_mode, wgpu::FilterMode::Linear, 1),
+            SamplerDesc::Anisotropic {
+                address_mode,
+                max_anisotropy,
+            } => (
+                address_mode,
+                wgpu::FilterMode::Linear,
+                max_anisotropy.clamp(1, 16),
+            ),
+        };
+
+        wgpu::SamplerDescriptor {
+            label,
+            address_mode_u: address_mode,
+            address_mode_v: address_mode,
+            address_mode_w: address_mode,
+            mag_filter: filter,
+            min_filter: filter,
+            mipmap_filter: filter,
+            anisotropy_clamp,
+            ..Default::default()
+        }
+    }
+}
+
+/// A GPU texture together with its default view and sampler.
+pub struct Texture {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
+}
+
+impl Texture {
+    pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+    /// Creates a color attachment matching the size of the surface.
+    pub fn create_render_target(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        label: &str,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: config.width.max(1),
+                height: config.height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: if sample_count > 1 {
+                wgpu::TextureUsages::RENDER_ATTACHMENT
+            } else {
+                wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING
+            },
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some(label),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            texture,
+            view,
+            sampler,
+        }
+    }
+
+    /// Creates a depth attachment matching the size of the surface.
+    pub fn create_depth_texture(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
+        label: &str,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: config.width.max(1),
+                height: config.height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::DEPTH_FORMAT,
+            // Multisampled depth is only ever attached, which lets GL back it with a renderbuffer.
+            usage: if sample_count > 1 {
+                wgpu::TextureUsages::RENDER_ATTACHMENT
+            } else {
+                wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING
+            },
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some(label),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+
+        Self {
+            texture,
+            view,
+            sampler,
+        }
+    }
+
+    /// Decodes a PNG or JPEG image file into an sRGB texture, see [`Texture::from_image`].
+    pub fn from_path(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: impl AsRef<Path>,
+        sampler: SamplerDesc,
+        mipmaps: Option<&mut MipmapGenerator>,
+    ) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let image =
+            image::open(path).with_context(|| format!("failed to load {}", path.display()))?;
+        Ok(Self::from_image(
+            device,
+            queue,
+            &image,
+            &path.display().to_string(),
+            sampler,
+            mipmaps,
+        ))
+    }
+
+    /// Decodes an in-memory PNG or JPEG image into an sRGB texture.
+    pub fn from_bytes(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bytes: &[u8],
+        label: &str,
+        sampler: SamplerDesc,
+        mipmaps: Option<&mut MipmapGenerator>,
+    ) -> anyhow::Result<Self> {
+        let image =
+            image::load_from_memory(bytes).with_context(|| format!("failed to decode {label}"))?;
+        Ok(Self::from_image(
+            device, queue, &image, label, sampler, mipmaps,
+        ))
+    }
+
+    /// Uploads `image` as an sRGB texture. With a [`MipmapGenerator`] the full mip chain is
+    /// allocated and filled on the GPU, otherwise the texture has a single level.
+    pub fn from_image(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: &image::DynamicImage,
+        label: &str,
+        sampler: SamplerDesc,
+        mipmaps: Option<&mut MipmapGenerator>,
+    ) -> Self {
+        Self::from_rgba(
+            device,
+            queue,
+            &image.to_rgba8(),
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+            label,
+            sampler,
+            mipmaps,
+        )
+    }
+
+    /// A 1x1 texture of a single color, standing in for missing textures.
+    pub fn from_color(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        color: [u8; 4],
+        format: wgpu::TextureFormat,
+        label: &str,
+    ) -> Self {
+        let rgba = image::RgbaImage::from_pixel(1, 1, image::Rgba(color));
+        Self::from_rgba(
+            device,
+            queue,
+            &rgba,
+            format,
+            label,
+            SamplerDesc::default(),
+            None,
+        )
+    }
+
+    /// Uploads 8-bit RGBA pixels as a texture of `format`, which must be one of the
+    /// `Rgba8Unorm` variants. Data textures like normal maps use `Rgba8Unorm`, colors
+    /// `Rgba8UnormSrgb`.
+    pub fn from_rgba(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        rgba: &image::RgbaImage,
+        format: wgpu::TextureFormat,
+        label: &str,
+        sampler: SamplerDesc,
+        mipmaps: Option<&mut MipmapGenerator>,
+    ) -> Self {
+        let size = wgpu::Extent3d {
+            width: rgba.width(),
+            height: rgba.height(),
+            depth_or_array_layers: 1,
+        };
+
+        let (mip_level_count, usage) = match mipmaps {
+            Some(_) => (
+                mipmap::mip_level_count(size),
+                wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_DST
+                    | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            ),
+            None => (
+                1,
+                wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            ),
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage,
+            view_formats: &[],
+        });
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            rgba,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * size.width),
+                rows_per_image: Some(size.height),
+            },
+            size,
+        );
+
+        if let Some(generator) = mipmaps {
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("mipmap"),
+            });
+            generator.generate(device, &mut encoder, &texture);
+            queue.submit(std::iter::once(encoder.finish()));
+        }
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&sampler.descriptor(Some(label)));
+
+        Self {
+            texture,
+            view,
+            sampler,
+        }
+    }
+
+    /// Loads a block-compressed texture from a `.ktx2` or `.dds` file with all of its mip
+    /// levels. When the device lacks the feature for the file's format, the base level is
+    /// decompressed on the CPU and uploaded like [`Texture::from_image`] instead.
+    ///
+    /// Legacy DDS files don't tell whether they hold colours or data like normals, so they're
+    /// read as sRGB if `srgb` is set and as linear otherwise. Other files keep their own format.
+    pub fn from_compressed_path(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: impl AsRef<Path>,
+        srgb: bool,
+        sampler: SamplerDesc,
+        mipmaps: Option<&mut MipmapGenerator>,
+    ) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let label = path.display().to_string();
+        let bytes =
+            std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        let image = match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("ktx2") => {
+                compressed::parse_ktx2(&bytes)
+            }
+            Some(extension) if extension.eq_ignore_ascii_case("dds") => {
+                compressed::parse_dds(&bytes, srgb)
+            }
+            _ => anyhow::bail!("unsupported compressed texture: {}", path.display()),
+        }
+        .with_context(|| format!("failed to load {}", path.display()))?;
+
+        if !device.features().contains(image.format.required_features()) {
+            let rgba = compressed::decompress(&image)
+                .with_context(|| format!("failed to decompress {}", path.display()))?;
+            let format = if image.format.is_srgb() {
+                wgpu::TextureFormat::Rgba8UnormSrgb
+            } else {
+                wgpu::TextureFormat::Rgba8Unorm
+            };
+            return Ok(Self::from_rgba(
+                device, queue, &rgba, format, &label, sampler, mipmaps,
+            ));
+        }
+
+        let texture = compressed::upload(device, queue, &image, &label)?;
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&sampler.descriptor(Some(&label)));
+
+        Ok(Self {
+            texture,
+            view,
+            sampler,
+        })
+    }
+
+    /// The layout of [`Texture::create_bind_group`]: a filterable 2D texture at binding 0 and
+    /// its sampler at binding 1.
+    pub fn bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("texture"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        })
+    }
+
+    pub fn create_bind_group(
+        &self,
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("texture"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&self.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        })
+    }
+}
@@ -0,0 +1,391 @@
+//! Parsing of KTX2 and DDS containers holding block-compressed textures.
+
+use anyhow::Context;
+
+/// A block-compressed 2D image with its mip chain, largest level first.
+pub(super) struct CompressedImage {
+    pub format: wgpu::TextureFormat,
+    pub width: u32,
+    pub height: u32,
+    pub levels: Vec<Vec<u8>>,
+}
+
+const ASTC_BLOCKS: [wgpu::AstcBlock; 14] = [
+    wgpu::AstcBlock::B4x4,
+    wgpu::AstcBlock::B5x4,
+    wgpu::AstcBlock::B5x5,
+    wgpu::AstcBlock::B6x5,
+    wgpu::AstcBlock::B6x6,
+    wgpu::AstcBlock::B8x5,
+    wgpu::AstcBlock::B8x6,
+    wgpu::AstcBlock::B8x8,
+    wgpu::AstcBlock::B10x5,
+    wgpu::AstcBlock::B10x6,
+    wgpu::AstcBlock::B10x8,
+    wgpu::AstcBlock::B10x10,
+    wgpu::AstcBlock::B12x10,
+    wgpu::AstcBlock::B12x12,
+];
+
+fn ktx2_format(format: ktx2::Format) -> Option<wgpu::TextureFormat> {
+    use wgpu::TextureFormat as F;
+
+    Some(match format {
+        ktx2::Format::BC1_RGB_UNORM_BLOCK | ktx2::Format::BC1_RGBA_UNORM_BLOCK => F::Bc1RgbaUnorm,
+        ktx2::Format::BC1_RGB_SRGB_BLOCK | ktx2::Format::BC1_RGBA_SRGB_BLOCK => F::Bc1RgbaUnormSrgb,
+        ktx2::Format::BC2_UNORM_BLOCK => F::Bc2RgbaUnorm,
+        ktx2::Format::BC2_SRGB_BLOCK => F::Bc2RgbaUnormSrgb,
+        ktx2::Format::BC3_UNORM_BLOCK => F::Bc3RgbaUnorm,
+        ktx2::Format::BC3_SRGB_BLOCK => F::Bc3RgbaUnormSrgb,
+        ktx2::Format::BC4_UNORM_BLOCK => F::Bc4RUnorm,
+        ktx2::Format::BC4_SNORM_BLOCK => F::Bc4RSnorm,
+        ktx2::Format::BC5_UNORM_BLOCK => F::Bc5RgUnorm,
+        ktx2::Format::BC5_SNORM_BLOCK => F::Bc5RgSnorm,
+        ktx2::Format::BC6H_UFLOAT_BLOCK => F::Bc6hRgbUfloat,
+        ktx2::Format::BC6H_SFLOAT_BLOCK => F::Bc6hRgbFloat,
+        ktx2::Format::BC7_UNORM_BLOCK => F::Bc7RgbaUnorm,
+        ktx2::Format::BC7_SRGB_BLOCK => F::Bc7RgbaUnormSrgb,
+        _ => {
+            // The Vulkan ASTC formats are laid out as (UNORM, SRGB) pairs in block size order.
+            let offset = format
+                .0
+                .get()
+                .checked_sub(ktx2::Format::ASTC_4x4_UNORM_BLOCK.0.get())?;
+            let block = *ASTC_BLOCKS.get(offset as usize / 2)?;
+            let channel = if offset % 2 == 0 {
+                wgpu::AstcChannel::Unorm
+            } else {
+                wgpu::AstcChannel::UnormSrgb
+            };
+            F::Astc { block, channel }
+        }
+    })
+}
+
+/// The format of `dds`. Legacy headers don't tell the colour space, so their formats are sRGB
+/// if `srgb` is set and linear otherwise.
+fn dds_format(dds: &ddsfile::Dds, srgb: bool) -> Option<wgpu::TextureFormat> {
+    use ddsfile::{D3DFormat, DxgiFormat};
+    use wgpu::TextureFormat as F;
+
+    if let Some(format) = dds.get_dxgi_format() {
+        return Some(match format {
+            DxgiFormat::BC1_UNorm => F::Bc1RgbaUnorm,
+            DxgiFormat::BC1_UNorm_sRGB => F::Bc1RgbaUnormSrgb,
+            DxgiFormat::BC2_UNorm => F::Bc2RgbaUnorm,
+            DxgiFormat::BC2_UNorm_sRGB => F::Bc2RgbaUnormSrgb,
+            DxgiFormat::BC3_UNorm => F::Bc3RgbaUnorm,
+            DxgiFormat::BC3_UNorm_sRGB => F::Bc3RgbaUnormSrgb,
+            DxgiFormat::BC4_UNorm => F::Bc4RUnorm,
+            DxgiFormat::BC4_SNorm => F::Bc4RSnorm,
+            DxgiFormat::BC5_UNorm => F::Bc5RgUnorm,
+            DxgiFormat::BC5_SNorm => F::Bc5RgSnorm,
+            DxgiFormat::BC6H_UF16 => F::Bc6hRgbUfloat,
+            DxgiFormat::BC6H_SF16 => F::Bc6hRgbFloat,
+            DxgiFormat::BC7_UNorm => F::Bc7RgbaUnorm,
+            DxgiFormat::BC7_UNorm_sRGB => F::Bc7RgbaUnormSrgb,
+            _ => return None,
+        });
+    }
+
+    let (linear, srgb_format) = match dds.get_d3d_format()? {
+        D3DFormat::DXT1 => (F::Bc1RgbaUnorm, F::Bc1RgbaUnormSrgb),
+        D3DFormat::DXT3 => (F::Bc2RgbaUnorm, F::Bc2RgbaUnormSrgb),
+        D3DFormat::DXT5 => (F::Bc3RgbaUnorm, F::Bc3RgbaUnormSrgb),
+        _ => return None,
+    };
+    Some(if srgb { srgb_format } else { linear })
+}
+
+/// Rejects the formats [`decompress`] can't decode, so that files load on every adapter rather
+/// than only on those supporting their format.
+fn ensure_decodable(format: wgpu::TextureFormat) -> anyhow::Result<()> {
+    use wgpu::TextureFormat as F;
+
+    match format {
+        F::Bc4RSnorm | F::Bc5RgSnorm => {
+            anyhow::bail!("signed {format:?} textures are not supported")
+        }
+        F::Bc6hRgbUfloat | F::Bc6hRgbFloat => {
+            anyhow::bail!("HDR {format:?} textures are not supported")
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The size in bytes of mip `level` of a `width`x`height` image in `format`.
+fn level_size(format: wgpu::TextureFormat, width: u32, height: u32, level: u32) -> usize {
+    let size = wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    }
+    .mip_level_size(level, wgpu::TextureDimension::D2)
+    .physical_size(format);
+    let (block_width, block_height) = format.block_dimensions();
+    let block_size = format.block_copy_size(None).unwrap_or(0);
+    ((size.width / block_width) * (size.height / block_height) * block_size) as usize
+}
+
+pub(super) fn parse_ktx2(bytes: &[u8]) -> anyhow::Result<CompressedImage> {
+    let reader = ktx2::Reader::new(bytes).context("invalid KTX2 file")?;
+    let header = reader.header();
+    if header.supercompression_scheme.is_some() {
+        anyhow::bail!("supercompressed KTX2 files are not supported");
+    }
+    if header.face_count != 1 || header.layer_count > 1 || header.pixel_depth > 1 {
+        anyhow::bail!("only single 2D KTX2 images are supported");
+    }
+    let format = header
+        .format
+        .and_then(ktx2_format)
+        .with_context(|| format!("unsupported KTX2 format {:?}", header.format))?;
+    ensure_decodable(format)?;
+
+    Ok(CompressedImage {
+        format,
+        width: header.pixel_width,
+        height: header.pixel_height,
+        levels: reader.levels().map(<[u8]>::to_vec).collect(),
+    })
+}
+
+/// Parses a DDS file, reading legacy formats as sRGB if `srgb` is set, see [`dds_format`].
+pub(super) fn parse_dds(bytes: &[u8], srgb: bool) -> anyhow::Result<CompressedImage> {
+    let dds = ddsfile::Dds::read(bytes).context("invalid DDS file")?;
+    let format = dds_format(&dds, srgb).context("unsupported DDS format")?;
+    ensure_decodable(format)?;
+    let (width, height) = (dds.get_width(), dds.get_height());
+    let mut data = dds.get_data(0).context("DDS file has no image data")?;
+
+    let mut levels = Vec::new();
+    for level in 0..dds.get_num_mipmap_levels().max(1) {
+        let size = level_size(format, width, height, level);
+        if data.len() < size {
+            anyhow::bail!("DDS file is truncated at mip level {level}");
+        }
+        let (level_data, rest) = data.split_at(size);
+        levels.push(level_data.to_vec());
+        data = rest;
+    }
+
+    Ok(CompressedImage {
+        format,
+        width,
+        height,
+        levels,
+    })
+}
+
+/// Creates a texture in the image's own format and uploads every mip level. The device needs
+/// the features required by the format.
+pub(super) fn upload(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    image: &CompressedImage,
+    label: &str,
+) -> anyhow::Result<wgpu::Texture> {
+    let (block_width, block_height) = image.format.block_dimensions();
+    if !image.width.is_multiple_of(block_width) || !image.height.is_multiple_of(block_height) {
+        anyhow::bail!(
+            "{}x{} is not a multiple of the {block_width}x{block_height} block size",
+            image.width,
+            image.height
+        );
+    }
+    let block_size = image.format.block_copy_size(None).unwrap_or(0);
+    let size = wgpu::Extent3d {
+        width: image.width,
+        height: image.height,
+        depth_or_array_layers: 1,
+    };
+
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size,
+        mip_level_count: image.levels.len() as u32,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: image.format,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+
+    for (level, data) in image.levels.iter().enumerate() {
+        let level_size = size
+            .mip_level_size(level as u32, wgpu::TextureDimension::D2)
+            .physical_size(image.format);
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: level as u32,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            data,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(level_size.width / block_width * block_size),
+                rows_per_image: Some(level_size.height / block_height),
+            },
+            level_size,
+        );
+    }
+
+    Ok(texture)
+}
+
+/// Decodes the base level into 8-bit RGBA for devices without support for the format.
+pub(super) fn decompress(image: &CompressedImage) -> anyhow::Result<image::RgbaImage> {
+    use wgpu::TextureFormat as F;
+
+    let (width, height) = (image.width as usize, image.height as usize);
+    let data = image.levels.first().context("image has no mip levels")?;
+    let mut pixels = vec![0u32; width * height];
+    match image.format {
+        F::Bc1RgbaUnorm | F::Bc1RgbaUnormSrgb => {
+            texture2ddecoder::decode_bc1(data, width, height, &mut pixels)
+        }
+        F::Bc2RgbaUnorm | F::Bc2RgbaUnormSrgb => decode_bc2(data, width, height, &mut pixels),
+        F::Bc3RgbaUnorm | F::Bc3RgbaUnormSrgb => {
+            texture2ddecoder::decode_bc3(data, width, height, &mut pixels)
+        }
+        F::Bc4RUnorm => texture2ddecoder::decode_bc4(data, width, height, &mut pixels),
+        F::Bc5RgUnorm => texture2ddecoder::decode_bc5(data, width, height, &mut pixels),
+        F::Bc7RgbaUnorm | F::Bc7RgbaUnormSrgb => {
+            texture2ddecoder::decode_bc7(data, width, height, &mut pixels)
+        }
+        F::Astc { .. } => {
+            let (block_width, block_height) = image.format.block_dimensions();
+            texture2ddecoder::decode_astc(
+                data,
+                width,
+                height,
+                block_width as usize,
+                block_height as usize,
+                &mut pixels,
+            )
+        }
+        format => anyhow::bail!("no CPU decoder for {format:?}"),
+    }
+    .map_err(|err| anyhow::anyhow!(err))?;
+
+    // The decoder packs pixels as BGRA.
+    let rgba = pixels
+        .into_iter()
+        .flat_map(|pixel| {
+            let [b, g, r, a] = pixel.to_le_bytes();
+            [r, g, b, a]
+        })
+        .collect();
+    image::RgbaImage::from_raw(image.width, image.height, rgba)
+        .context("decoded image has the wrong size")
+}
+
+/// Decodes BC2, which `texture2ddecoder` lacks, into pixels packed as BGRA like its decoders':
+/// each block is 4-bit alphas followed by a BC1 colour block, always in four-colour mode.
+fn decode_bc2(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    pixels: &mut [u32],
+) -> Result<(), &'static str> {
+    let blocks_wide = width.div_ceil(4);
+    let blocks = blocks_wide * height.div_ceil(4);
+    if data.len() < blocks * 16 {
+        return Err("not enough data to decode image");
+    }
+    if pixels.len() < width * height {
+        return Err("image buffer is too small");
+    }
+
+    for (index, block) in data.chunks_exact(16).take(blocks).enumerate() {
+        let alphas = u64::from_le_bytes(block[..8].try_into().unwrap());
+        let start = rgb565(u16::from_le_bytes([block[8], block[9]]));
+        let end = rgb565(u16::from_le_bytes([block[10], block[11]]));
+        let third = |a: [u8; 3], b: [u8; 3]| {
+            [0, 1, 2].map(|channel| ((2 * a[channel] as u16 + b[channel] as u16) / 3) as u8)
+        };
+        let colors = [start, end, third(start, end), third(end, start)];
+        let indices = u32::from_le_bytes(block[12..].try_into().unwrap());
+
+        let (left, top) = (index % blocks_wide * 4, index / blocks_wide * 4);
+        for texel in 0..16 {
+            let (x, y) = (left + texel % 4, top + texel / 4);
+            if x >= width || y >= height {
+                continue;
+            }
+            let [r, g, b] = colors[(indices >> (2 * texel) & 3) as usize];
+            let a = (alphas >> (4 * texel) & 0xf) as u8 * 17;
+            pixels[y * width + x] = u32::from_le_bytes([b, g, r, a]);
+        }
+    }
+    Ok(())
+}
+
+/// Expands a 5:6:5 colour to 8 bits per channel.
+fn rgb565(color: u16) -> [u8; 3] {
+    let (r, g, b) = (
+        (color >> 11) as u8,
+        (color >> 5 & 0x3f) as u8,
+        (color & 0x1f) as u8,
+    );
+    [r << 3 | r >> 2, g << 2 | g >> 4, b << 3 | b >> 2]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_bc2() {
+        // Alphas counting up, pure red and blue endpoints, and texels stepping through the four
+        // colours along each row.
+        let mut block = [0u8; 16];
+        for (texel, byte) in block[..8].iter_mut().enumerate() {
+            *byte = ((2 * texel as u8 + 1) << 4) | (2 * texel as u8);
+        }
+        block[8..10].copy_from_slice(&0xf800u16.to_le_bytes());
+        block[10..12].copy_from_slice(&0x001fu16.to_le_bytes());
+        block[12..].copy_from_slice(&[0b11_10_01_00; 4]);
+
+        let mut pixels = vec![0; 16];
+        decode_bc2(&block, 4, 4, &mut pixels).unwrap();
+        let bgra = |pixel: u32| pixel.to_le_bytes();
+        assert_eq!(bgra(pixels[0]), [0, 0, 255, 0]);
+        assert_eq!(bgra(pixels[1]), [255, 0, 0, 17]);
+        assert_eq!(bgra(pixels[2]), [85, 0, 170, 34]);
+        assert_eq!(bgra(pixels[3]), [170, 0, 85, 51]);
+        assert_eq!(bgra(pixels[15]), [170, 0, 85, 255]);
+    }
+
+    #[test]
+    fn decodes_bc2_cut_short_by_the_edges() {
+        let mut pixels = vec![0; 6 * 5];
+        decode_bc2(&[0xff; 16 * 4], 6, 5, &mut pixels).unwrap();
+        assert!(pixels.iter().all(|&pixel| pixel == u32::MAX));
+        assert!(decode_bc2(&[0; 16 * 3], 6, 5, &mut pixels).is_err());
+    }
+
+    #[test]
+    fn rejects_formats_without_decoders() {
+        use wgpu::TextureFormat as F;
+
+        for format in [
+            F::Bc4RSnorm,
+            F::Bc5RgSnorm,
+            F::Bc6hRgbUfloat,
+            F::Bc6hRgbFloat,
+        ] {
+            assert!(ensure_decodable(format).is_err(), "{format:?}");
+        }
+        for format in [
+            F::Bc1RgbaUnormSrgb,
+            F::Bc2RgbaUnorm,
+            F::Bc5RgUnorm,
+            F::Bc7RgbaUnorm,
+        ] {
+            assert!(ensure_decodable(format).is_ok(), "{format:?}");
+        }
+    }
+}
@@ -0,0 +1,463 @@
+//! Tile maps made with Tiled, see [`crate::assets::tiled`], drawn with the sprite pipeline. Each
+//! layer is built once into static meshes of square chunks of tiles, and only the chunks in
+//! view are drawn.
+
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use glam::{Mat4, Vec2, Vec3};
+use wgpu::util::DeviceExt;
+
+use crate::sprite::{SpriteBatch, SpriteVertex};
+use crate::texture::{SamplerDesc, Texture};
+
+/// Set in a tile's global ID when it's mirrored horizontally.
+pub const FLIP_HORIZONTAL: u32 = 0x8000_0000;
+/// Set in a tile's global ID when it's mirrored vertically.
+pub const FLIP_VERTICAL: u32 = 0x4000_0000;
+/// Set in a tile's global ID when its X and Y are swapped, before the other flips.
+pub const FLIP_DIAGONAL: u32 = 0x2000_0000;
+/// The bits of a tile's global ID that aren't flags, including those of hexagonal rotations.
+const GID_MASK: u32 = 0x0fff_ffff;
+
+/// The width and height of the chunks a layer is split into, in tiles.
+pub const CHUNK_SIZE: u32 = 16;
+
+/// The corners of the two triangles of a quad, from its top left to its bottom right, like
+/// those of sprites.
+const CORNERS: [[f32; 2]; 6] = [
+    [0.0, 0.0],
+    [1.0, 0.0],
+    [1.0, 1.0],
+    [0.0, 0.0],
+    [1.0, 1.0],
+    [0.0, 1.0],
+];
+
+/// A frame of an animated tile.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TileAnimationFrame {
+    /// The tile shown, by its ID in the tileset.
+    pub tile: u32,
+    /// In seconds.
+    pub duration: f32,
+}
+
+/// The tiles of an image, cut into a grid.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TilesetData {
+    /// The global ID of the first tile, those of the others following it.
+    pub first_gid: u32,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub columns: u32,
+    pub tile_count: u32,
+    /// Pixels around the tiles at the edges of the image.
+    pub margin: u32,
+    /// Pixels between neighbouring tiles.
+    pub spacing: u32,
+    pub image: PathBuf,
+    pub image_width: u32,
+    pub image_height: u32,
+    /// The frames of animated tiles, by tile ID.
+    pub animations: HashMap<u32, Vec<TileAnimationFrame>>,
+}
+
+impl TilesetData {
+    /// The left, top, right and bottom of `tile` in the image, in UVs.
+    pub fn uv(&self, tile: u32) -> [f32; 4] {
+        let columns = self.columns.max(1);
+        let (column, row) = (tile % columns, tile / columns);
+        let left = self.margin + column * (self.tile_width + self.spacing);
+        let top = self.margin + row * (self.tile_height + self.spacing);
+        let (width, height) = (self.image_width as f32, self.image_height as f32);
+        [
+            left as f32 / width,
+            top as f32 / height,
+            (left + self.tile_width) as f32 / width,
+            (top + self.tile_height) as f32 / height,
+        ]
+    }
+
+    /// The tile an animated `tile` shows `time` seconds into its animation, or `tile` itself
+    /// if it isn't animated.
+    pub fn animated_tile(&self, tile: u32, time: f32) -> u32 {
+        let Some(frames) = self.animations.get(&tile) else {
+            return tile;
+        };
+        let total: f32 = frames.iter().map(|frame| frame.duration).sum();
+        if total <= 0.0 {
+            return tile;
+        }
+        let mut time = time.rem_euclid(total);
+        for frame in frames {
+            if time < frame.duration {
+                return frame.tile;
+            }
+            time -= frame.duration;
+        }
+        frames.last().map_or(tile, |frame| frame.tile)
+    }
+}
+
+/// A grid of tiles the size of the map's.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TileLayerData {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    /// Global tile IDs with their flip flags, row by row from the top left, 0 for no tile.
+    pub tiles: Vec<u32>,
+    /// In pixels.
+    pub offset: Vec2,
+    /// How fast the layer moves with the view, 1 moving along with the map and 0 staying put.
+    pub parallax: Vec2,
+    pub opacity: f32,
+    pub visible: bool,
+}
+
+/// An orthogonal tile map with its layers drawn bottom to top, in the coordinates sprites are
+/// drawn in: pixels from the top left, Y down.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TileMapData {
+    /// In tiles.
+    pub width: u32,
+    pub height: u32,
+    /// The size of a cell, in pixels. Larger tiles stick out of the top right of their cells.
+    pub tile_width: u32,
+    pub tile_height: u32,
+    /// The point of the map where parallax layers line up, when it's at the center of the
+    /// view.
+    pub parallax_origin: Vec2,
+    /// Sorted by their first global ID.
+    pub tilesets: Vec<TilesetData>,
+    pub layers: Vec<TileLayerData>,
+}
+
+impl TileMapData {
+    /// The tileset of a global tile ID, by its index, and the tile's ID in it.
+    pub fn tileset(&self, gid: u32) -> Option<(usize, u32)> {
+        let gid = gid & GID_MASK;
+        if gid == 0 {
+            return None;
+        }
+        let index = self
+            .tilesets
+            .partition_point(|tileset| tileset.first_gid <= gid)
+            .checked_sub(1)?;
+        let tile = gid - self.tilesets[index].first_gid;
+        (tile < self.tilesets[index].tile_count).then_some((index, tile))
+    }
+}
+
+/// The quad of a tile, its texture coordinates flipped by the flags of its global ID.
+fn tile_quad(position: [f32; 4], uv: [f32; 4], gid: u32, color: [f32; 4]) -> [SpriteVertex; 6] {
+    let [left, top, right, bottom] = position;
+    let [u0, v0, u1, v1] = uv;
+    CORNERS.map(|[x, y]| {
+        let (mut s, mut t) = if gid & FLIP_DIAGONAL != 0 {
+            (y, x)
+        } else {
+            (x, y)
+        };
+        if gid & FLIP_HORIZONTAL != 0 {
+            s = 1.0 - s;
+        }
+        if gid & FLIP_VERTICAL != 0 {
+            t = 1.0 - t;
+        }
+        SpriteVertex {
+            position: [left + (right - left) * x, top + (bottom - top) * y],
+            uv: [u0 + (u1 - u0) * s, v0 + (v1 - v0) * t],
+            color,
+        }
+    })
+}
+
+/// A square of tiles, drawn in a draw per tileset.
+struct Chunk {
+    /// The corners of the tiles' quads, before the layer's offset.
+    min: Vec2,
+    max: Vec2,
+    draws: Vec<(usize, Range<u32>)>,
+}
+
+/// A tile whose vertices are rewritten when its animation moves to another frame.
+struct AnimatedTile {
+    first_vertex: u32,
+    tileset: usize,
+    tile: u32,
+    gid: u32,
+    position: [f32; 4],
+    /// The tile shown.
+    shown: u32,
+}
+
+struct Layer {
+    vertex_buffer: wgpu::Buffer,
+    /// The view projection with the layer's offset and parallax.
+    uniform: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    chunks: Vec<Chunk>,
+    animated: Vec<AnimatedTile>,
+    offset: Vec2,
+    parallax: Vec2,
+    color: [f32; 4],
+    /// The chunks in view, by index.
+    visible: Vec<usize>,
+}
+
+/// A [`TileMapData`] uploaded to the GPU, drawn with the sprite pipeline of a [`SpriteBatch`].
+pub struct TileMap {
+    data: TileMapData,
+    textures: Vec<wgpu::BindGroup>,
+    layers: Vec<Layer>,
+    /// The seconds animations have played for.
+    time: f32,
+}
+
+impl TileMap {
+    /// Loads the tileset images of `data` and builds the chunks of its visible layers, with
+    /// bind groups for the layouts of `batch`.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        data: TileMapData,
+        batch: &SpriteBatch,
+    ) -> anyhow::Result<Self> {
+        let textures = data
+            .tilesets
+            .iter()
+            .map(|tileset| {
+                // Nearest, since tiles are usually pixel art and filtering would bleed their
+                // neighbours in.
+                let texture = Texture::from_path(
+                    device,
+                    queue,
+                    &tileset.image,
+                    SamplerDesc::Nearest(wgpu::AddressMode::ClampToEdge),
+                    None,
+                )?;
+                Ok(texture.create_bind_group(device, batch.texture_layout()))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()
+            .context("failed to load a tileset")?;
+
+        let layers = data
+            .layers
+            .iter()
+            .filter(|layer| layer.visible && layer.opacity > 0.0)
+            .filter_map(|layer| build_layer(device, &data, layer, batch))
+            .collect();
+
+        Ok(Self {
+            data,
+            textures,
+            layers,
+            time: 0.0,
+        })
+    }
+
+    pub fn data(&self) -> &TileMapData {
+        &self.data
+    }
+
+    /// The size of the map in pixels.
+    pub fn size(&self) -> Vec2 {
+        Vec2::new(
+            (self.data.width * self.data.tile_width) as f32,
+            (self.data.height * self.data.tile_height) as f32,
+        )
+    }
+
+    /// The chunks drawn and those culled for the last update.
+    pub fn chunk_stats(&self) -> (usize, usize) {
+        self.layers.iter().fold((0, 0), |(drawn, culled), layer| {
+            (
+                drawn + layer.visible.len(),
+                culled + layer.chunks.len() - layer.visible.len(),
+            )
+        })
+    }
+
+    /// Advances the tile animations by `delta_time` seconds, and finds the chunks in view of
+    /// `view_projection`, placing the parallax layers for its center.
+    pub fn update(&mut self, queue: &wgpu::Queue, delta_time: f32, view_projection: Mat4) {
+        self.time += delta_time;
+        for layer in &mut self.layers {
+            for animated in &mut layer.animated {
+                let tileset = &self.data.tilesets[animated.tileset];
+                let shown = tileset.animated_tile(animated.tile, self.time);
+                if shown == animated.shown {
+                    continue;
+                }
+                animated.shown = shown;
+                let vertices = tile_quad(
+                    animated.position,
+                    tileset.uv(shown),
+                    animated.gid,
+                    layer.color,
+                );
+                queue.write_buffer(
+                    &layer.vertex_buffer,
+                    animated.first_vertex as wgpu::BufferAddress
+                        * std::mem::size_of::<SpriteVertex>() as wgpu::BufferAddress,
+                    bytemuck::cast_slice(&vertices),
+                );
+            }
+        }
+
+        // The view in map pixels, as the bounds of the corners of clip space.
+        let inverse = view_projection.inverse();
+        let corners = [[-1.0, -1.0], [1.0, -1.0], [1.0, 1.0], [-1.0, 1.0]]
+            .map(|[x, y]| inverse.project_point3(Vec3::new(x, y, 0.0)).truncate());
+        let view_min = corners.into_iter().reduce(Vec2::min).unwrap_or_default();
+        let view_max = corners.into_iter().reduce(Vec2::max).unwrap_or_default();
+        let center = inverse.project_point3(Vec3::ZERO).truncate();
+        for layer in &mut self.layers {
+            // Layers moving slower than the map are shifted along with the view.
+            let shift =
+                layer.offset + (center - self.data.parallax_origin) * (Vec2::ONE - layer.parallax);
+            let projection = view_projection * Mat4::from_translation(shift.extend(0.0));
+            queue.write_buffer(
+                &layer.uniform,
+                0,
+                bytemuck::bytes_of(&projection.to_cols_array_2d()),
+            );
+            layer.visible.clear();
+            for (index, chunk) in layer.chunks.iter().enumerate() {
+                let (min, max) = (chunk.min + shift, chunk.max + shift);
+                if min.cmplt(view_max).all() && max.cmpgt(view_min).all() {
+                    layer.visible.push(index);
+                }
+            }
+        }
+    }
+
+    /// Draws the chunks in view, with `pipeline` from [`SpriteBatch::pipeline_builder`].
+    pub fn render(&self, render_pass: &mut wgpu::RenderPass, pipeline: &wgpu::RenderPipeline) {
+        render_pass.set_pipeline(pipeline);
+        for layer in &self.layers {
+            if layer.visible.is_empty() {
+                continue;
+            }
+            render_pass.set_bind_group(0, &layer.bind_group, &[]);
+            render_pass.set_vertex_buffer(0, layer.vertex_buffer.slice(..));
+            for &chunk in &layer.visible {
+                for (tileset, vertices) in &layer.chunks[chunk].draws {
+                    render_pass.set_bind_group(1, &self.textures[*tileset], &[]);
+                    render_pass.draw(vertices.clone(), 0..1);
+                }
+            }
+        }
+    }
+}
+
+/// Builds the chunks of `layer` into one vertex buffer, or `None` if it has no tiles.
+fn build_layer(
+    device: &wgpu::Device,
+    map: &TileMapData,
+    layer: &TileLayerData,
+    batch: &SpriteBatch,
+) -> Option<Layer> {
+    let color = [1.0, 1.0, 1.0, layer.opacity];
+    let mut vertices = Vec::new();
+    let mut chunks = Vec::new();
+    let mut animated = Vec::new();
+    let (cell_width, cell_height) = (map.tile_width as f32, map.tile_height as f32);
+    for chunk_y in (0..layer.height).step_by(CHUNK_SIZE as usize) {
+        for chunk_x in (0..layer.width).step_by(CHUNK_SIZE as usize) {
+            let mut tiles = Vec::new();
+            for y in chunk_y..(chunk_y + CHUNK_SIZE).min(layer.height) {
+                for x in chunk_x..(chunk_x + CHUNK_SIZE).min(layer.width) {
+                    let gid = layer.tiles[(y * layer.width + x) as usize];
+                    if let Some((tileset, tile)) = map.tileset(gid) {
+                        tiles.push((tileset, tile, gid, x, y));
+                    }
+                }
+            }
+            if tiles.is_empty() {
+                continue;
+            }
+            // Stable, so that tiles stay in rows within a tileset.
+            tiles.sort_by_key(|&(tileset, ..)| tileset);
+
+            let mut chunk = Chunk {
+                min: Vec2::splat(f32::INFINITY),
+                max: Vec2::splat(f32::NEG_INFINITY),
+                draws: Vec::new(),
+            };
+            for (tileset_index, tile, gid, x, y) in tiles {
+                let tileset = &map.tilesets[tileset_index];
+                // Anchored at the bottom left of the cell, like Tiled draws them.
+                let left = x as f32 * cell_width;
+                let bottom = (y + 1) as f32 * cell_height;
+                let position = [
+                    left,
+                    bottom - tileset.tile_height as f32,
+                    left + tileset.tile_width as f32,
+                    bottom,
+                ];
+                chunk.min = chunk.min.min(Vec2::new(position[0], position[1]));
+                chunk.max = chunk.max.max(Vec2::new(position[2], position[3]));
+
+                let first_vertex = vertices.len() as u32;
+                let shown = tileset.animated_tile(tile, 0.0);
+                if tileset.animations.contains_key(&tile) {
+                    animated.push(AnimatedTile {
+                        first_vertex,
+                        tileset: tileset_index,
+                        tile,
+                        gid,
+                        position,
+                        shown,
+                    });
+                }
+                vertices.extend(tile_quad(position, tileset.uv(shown), gid, color));
+                let end = vertices.len() as u32;
+                match chunk.draws.last_mut() {
+                    Some((last, range)) if *last == tileset_index => range.end = end,
+                    _ => chunk.draws.push((tileset_index, first_vertex..end)),
+                }
+            }
+            chunks.push(chunk);
+        }
+    }
+    if vertices.is_empty() {
+        return None;
+    }
+
+    let label = format!("tile layer {}", layer.name);
+    let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some(&label),
+        contents: bytemuck::cast_slice(&vertices),
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+    });
+    let uniform = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some(&label),
+        contents: bytemuck::bytes_of(&Mat4::IDENTITY.to_cols_array_2d()),
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some(&label),
+        layout: batch.projection_layout(),
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: uniform.as_entire_binding(),
+        }],
+    });
+
+    Some(Layer {
+        vertex_buffer,
+        uniform,
+        bind_group,
+        chunks,
+        animated,
+        offset: layer.offset,
+        parallax: layer.parallax,
+        color,
+        visible: Vec::new(),
+    })
+}
@@ -0,0 +1,54 @@
+use std::time::Duration;
+
+use web_time::Instant;
+
+/// Frames taking longer than this slow the simulation down rather than running ever more steps
+/// to catch up, e.g. after the application was suspended.
+const MAX_FRAME_TIME: Duration = Duration::from_millis(250);
+
+/// Splits real time into fixed steps, so that simulations advance the same way however fast
+/// frames are rendered.
+///
+/// The time left over after the last whole step carries over to the next frame, and is exposed as
+/// [`FixedTimestep::alpha`] to interpolate between the two most recent steps when rendering.
+#[derive(Debug)]
+pub struct FixedTimestep {
+    step: Duration,
+    accumulator: Duration,
+    last: Instant,
+}
+
+impl FixedTimestep {
+    /// Runs `rate` steps per second.
+    ///
+    /// Panics if `rate` is zero.
+    pub fn new(rate: u32) -> Self {
+        assert!(rate > 0, "a fixed timestep needs a positive rate");
+        Self {
+            step: Duration::from_secs(1) / rate,
+            accumulator: Duration::ZERO,
+            last: Instant::now(),
+        }
+    }
+
+    /// The duration of a step.
+    pub fn step(&self) -> Duration {
+        self.step
+    }
+
+    /// Adds the time since the previous call and returns the number of steps that are due.
+    pub fn advance(&mut self) -> u32 {
+        let now = Instant::now();
+        self.accumulator += (now - self.last).min(MAX_FRAME_TIME);
+        self.last = now;
+
+        let steps = (self.accumulator.as_nanos() / self.step.as_nanos()) as u32;
+        self.accumulator -= self.step * steps;
+        steps
+    }
+
+    /// How far the time is between the most recent step and the next one, from 0 to 1.
+    pub fn alpha(&self) -> f32 {
+        self.accumulator.as_secs_f32() / self.step.as_secs_f32()
+    }
+}
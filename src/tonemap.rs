@@ -0,0 +1,166 @@
+use std::sync::Arc;
+
+use serde::Deserialize;
+use wgpu::util::DeviceExt;
+
+use crate::post_process::{self, Effect, EffectContext, EffectPipelineKey};
+use crate::shader::{self, Preprocessor};
+use crate::texture::Texture;
+
+/// The format the scene is rendered in before tonemapping, which keeps values above 1.
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// The curve mapping HDR colors into the displayable range.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Tonemapper {
+    /// A fit of the ACES filmic curve, contrasty and saturated.
+    #[default]
+    Aces,
+    /// Reinhard on luminance, which keeps hues but washes out highlights.
+    Reinhard,
+    /// AgX, which desaturates bright colors towards white like film.
+    Agx,
+}
+
+impl Tonemapper {
+    pub const ALL: [Self; 3] = [Self::Aces, Self::Reinhard, Self::Agx];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Aces => "ACES",
+            Self::Reinhard => "Reinhard",
+            Self::Agx => "AgX",
+        }
+    }
+}
+
+/// `Tonemap` in `tonemap.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct TonemapUniform {
+    /// The linear scale applied before the curve.
+    exposure: f32,
+    /// The [`Tonemapper`] discriminant, `TONEMAP_*` in `tonemap.wgsl`.
+    tonemapper: u32,
+    _padding: [u32; 2],
+}
+
+/// The [`Effect`] that scales the HDR scene by the exposure and maps it with a [`Tonemapper`].
+pub struct Tonemap {
+    pub tonemapper: Tonemapper,
+    /// In stops, 0 leaving the scene as rendered.
+    pub exposure: f32,
+    buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline_layout: Arc<wgpu::PipelineLayout>,
+    shader_module: Arc<wgpu::ShaderModule>,
+}
+
+impl Tonemap {
+    /// Compiles `tonemap.wgsl` with `preprocessor`.
+    pub fn new(device: &wgpu::Device, preprocessor: &Preprocessor) -> anyhow::Result<Self> {
+        let tonemapper = Tonemapper::default();
+        let exposure = 0.0;
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("tonemap"),
+            contents: bytemuck::bytes_of(&uniform(tonemapper, exposure)),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let [input, input_sampler] = post_process::input_layout_entries();
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("tonemap"),
+            entries: &[
+                input,
+                input_sampler,
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = Arc::new(device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("tonemap"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            },
+        ));
+        let shader_module = Arc::new(shader::create_module(device, preprocessor, "tonemap.wgsl")?);
+
+        Ok(Self {
+            tonemapper,
+            exposure,
+            buffer,
+            bind_group_layout,
+            pipeline_layout,
+            shader_module,
+        })
+    }
+}
+
+impl Effect for Tonemap {
+    fn name(&self) -> &'static str {
+        "tonemap"
+    }
+
+    fn update(&mut self, queue: &wgpu::Queue) {
+        queue.write_buffer(
+            &self.buffer,
+            0,
+            bytemuck::bytes_of(&uniform(self.tonemapper, self.exposure)),
+        );
+    }
+
+    fn render(
+        &mut self,
+        context: &mut EffectContext,
+        input: &Texture,
+        output: &wgpu::TextureView,
+        format: wgpu::TextureFormat,
+    ) {
+        let key = EffectPipelineKey {
+            effect: self.name(),
+            format,
+        };
+        let pipeline = context.pipelines.get(
+            &key,
+            &*post_process::pipeline_builder(
+                "tonemap",
+                self.pipeline_layout.clone(),
+                self.shader_module.clone(),
+                format,
+            ),
+        );
+        let [input, input_sampler] = post_process::input_bind_group_entries(input);
+        let bind_group = context
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("tonemap"),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    input,
+                    input_sampler,
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: self.buffer.as_entire_binding(),
+                    },
+                ],
+            });
+        context.draw(self.name(), output, &pipeline, &bind_group);
+    }
+}
+
+fn uniform(tonemapper: Tonemapper, exposure: f32) -> TonemapUniform {
+    TonemapUniform {
+        exposure: exposure.exp2(),
+        tonemapper: tonemapper as u32,
+        _padding: [0; 2],
+    }
+}
@@ -0,0 +1,96 @@
+use std::marker::PhantomData;
+
+use crate::upload::Upload;
+
+/// Per-frame uniform data of many draws in a single buffer. Values are pushed into slots
+/// aligned for dynamic offsets and uploaded at once, then each draw binds the same bind group
+/// with the offset of its slot instead of a bind group of its own.
+pub struct UniformArena<T> {
+    label: String,
+    /// The slots pushed this frame, each `slot_size` bytes.
+    data: Vec<u8>,
+    /// The size of `T` rounded up to the device's uniform offset alignment, usually 256 bytes.
+    slot_size: usize,
+    buffer: wgpu::Buffer,
+    _value: PhantomData<T>,
+}
+
+impl<T: bytemuck::Pod> UniformArena<T> {
+    /// Creates a buffer with room for `capacity` slots, grown as needed by
+    /// [`UniformArena::upload`].
+    pub fn new(device: &wgpu::Device, label: &str, capacity: usize) -> Self {
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as usize;
+        let slot_size = std::mem::size_of::<T>().next_multiple_of(alignment);
+        Self {
+            label: label.to_owned(),
+            data: Vec::new(),
+            slot_size,
+            buffer: create_buffer(device, label, (capacity.max(1) * slot_size) as u64),
+            _value: PhantomData,
+        }
+    }
+
+    /// The layout of a binding reading one `T` from the arena at a dynamic offset.
+    pub fn layout_entry(
+        binding: u32,
+        visibility: wgpu::ShaderStages,
+    ) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: true,
+                min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<T>() as u64),
+            },
+            count: None,
+        }
+    }
+
+    /// Binds the arena's buffer at `binding`, in a bind group created with
+    /// [`UniformArena::layout_entry`]. Has to be created again when the buffer grows.
+    pub fn bind_group_entry(&self, binding: u32) -> wgpu::BindGroupEntry<'_> {
+        wgpu::BindGroupEntry {
+            binding,
+            resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                buffer: &self.buffer,
+                offset: 0,
+                size: wgpu::BufferSize::new(std::mem::size_of::<T>() as u64),
+            }),
+        }
+    }
+
+    /// Forgets the slots pushed last frame.
+    pub fn clear(&mut self) {
+        self.data.clear();
+    }
+
+    /// Adds `value` in a new slot, returning the dynamic offset binding it.
+    pub fn push(&mut self, value: &T) -> u32 {
+        let offset = self.data.len();
+        self.data.extend_from_slice(bytemuck::bytes_of(value));
+        self.data.resize(offset + self.slot_size, 0);
+        offset as u32
+    }
+
+    /// Uploads the slots pushed since [`UniformArena::clear`], growing the buffer if they don't
+    /// fit. Returns whether it grew, which bind groups using it have to be created again for.
+    pub fn upload(&mut self, upload: &mut Upload) -> bool {
+        let size = self.data.len() as u64;
+        let grew = size > self.buffer.size();
+        if grew {
+            self.buffer = create_buffer(upload.device, &self.label, size.next_power_of_two());
+        }
+        upload.write(&self.buffer, 0, &self.data);
+        grew
+    }
+}
+
+fn create_buffer(device: &wgpu::Device, label: &str, size: u64) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        size,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
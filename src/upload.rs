@@ -0,0 +1,74 @@
+//! Per-frame uploads of dynamic data through a [`wgpu::util::StagingBelt`], which copies from
+//! staging chunks recorded into the frame's encoder instead of allocating for every write.
+
+use std::num::NonZeroU64;
+
+/// The size of the staging chunks, large enough that a frame's debug lines and instances
+/// rarely need a second one.
+const CHUNK_SIZE: wgpu::BufferAddress = 1 << 20;
+
+/// Reuses mapped staging chunks from frame to frame: [`Uploader::begin`] records copies out of
+/// them into the frame's encoder, [`Uploader::finish`] unmaps them before the frame is
+/// submitted and [`Uploader::recall`] maps them again once it has been.
+pub struct Uploader {
+    belt: wgpu::util::StagingBelt,
+}
+
+impl Default for Uploader {
+    fn default() -> Self {
+        Self {
+            belt: wgpu::util::StagingBelt::new(CHUNK_SIZE),
+        }
+    }
+}
+
+impl Uploader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts recording uploads into `encoder`, whose commands run before anything recorded
+    /// after them reads the written buffers.
+    pub fn begin<'f>(
+        &'f mut self,
+        device: &'f wgpu::Device,
+        encoder: &'f mut wgpu::CommandEncoder,
+    ) -> Upload<'f> {
+        Upload {
+            device,
+            encoder,
+            belt: &mut self.belt,
+        }
+    }
+
+    /// Closes the chunks written this frame, which has to happen before its encoder is
+    /// submitted.
+    pub fn finish(&mut self) {
+        self.belt.finish();
+    }
+
+    /// Maps the chunks of submitted frames again, as soon as the GPU is done copying from them.
+    pub fn recall(&mut self) {
+        self.belt.recall();
+    }
+}
+
+/// The uploads of one frame, see [`Uploader::begin`].
+pub struct Upload<'f> {
+    pub device: &'f wgpu::Device,
+    encoder: &'f mut wgpu::CommandEncoder,
+    belt: &'f mut wgpu::util::StagingBelt,
+}
+
+impl Upload<'_> {
+    /// Writes `data` into `buffer` at `offset`, both multiples of four bytes. `buffer` needs
+    /// [`wgpu::BufferUsages::COPY_DST`].
+    pub fn write(&mut self, buffer: &wgpu::Buffer, offset: wgpu::BufferAddress, data: &[u8]) {
+        let Some(size) = NonZeroU64::new(data.len() as u64) else {
+            return;
+        };
+        self.belt
+            .write_buffer(self.encoder, buffer, offset, size, self.device)
+            .copy_from_slice(data);
+    }
+}
@@ -0,0 +1,128 @@
+use std::sync::OnceLock;
+
+/// A named attribute of a [`VertexLayout`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct VertexAttribute {
+    /// Matches the shader's input, for error messages and debugging.
+    pub name: &'static str,
+    pub format: wgpu::VertexFormat,
+}
+
+/// Describes a vertex buffer declaratively: its attributes in the order they're laid out, read
+/// at consecutive shader locations from `first_location` unless skipped ahead. Offsets, the
+/// stride and the [`wgpu::VertexBufferLayout`] of pipelines are derived from it.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct VertexLayout {
+    pub step_mode: wgpu::VertexStepMode,
+    pub first_location: u32,
+    /// The shader location of the next attribute appended.
+    next_location: u32,
+    attributes: Vec<VertexAttribute>,
+    /// `attributes` with their offsets and locations, which the buffer layout borrows.
+    wgpu_attributes: Vec<wgpu::VertexAttribute>,
+}
+
+impl VertexLayout {
+    pub fn new(step_mode: wgpu::VertexStepMode, first_location: u32) -> Self {
+        Self {
+            step_mode,
+            first_location,
+            next_location: first_location,
+            attributes: Vec::new(),
+            wgpu_attributes: Vec::new(),
+        }
+    }
+
+    /// Appends an attribute right after the previous one, at the next shader location.
+    pub fn with(mut self, name: &'static str, format: wgpu::VertexFormat) -> Self {
+        self.wgpu_attributes.push(wgpu::VertexAttribute {
+            format,
+            offset: self.stride(),
+            shader_location: self.next_location,
+        });
+        self.attributes.push(VertexAttribute { name, format });
+        self.next_location += 1;
+        self
+    }
+
+    /// Reads the attributes appended next from shader `location` on, leaving the locations in
+    /// between to other buffers.
+    pub fn skip_to(mut self, location: u32) -> Self {
+        debug_assert!(location >= self.next_location);
+        self.next_location = location;
+        self
+    }
+
+    /// Appends a column-major 4x4 matrix as four `Float32x4` attributes, one per column.
+    pub fn with_mat4(self, names: [&'static str; 4]) -> Self {
+        names.into_iter().fold(self, |layout, name| {
+            layout.with(name, wgpu::VertexFormat::Float32x4)
+        })
+    }
+
+    pub fn attributes(&self) -> &[VertexAttribute] {
+        &self.attributes
+    }
+
+    /// The shader location after the last attribute, where a following buffer can start.
+    pub fn next_location(&self) -> u32 {
+        self.next_location
+    }
+
+    /// The size of a vertex in bytes.
+    pub fn stride(&self) -> wgpu::BufferAddress {
+        self.attributes
+            .iter()
+            .map(|attribute| attribute.format.size())
+            .sum()
+    }
+
+    /// The layout of the buffer in a pipeline's vertex state.
+    pub fn buffer_layout(&self) -> wgpu::VertexBufferLayout<'_> {
+        wgpu::VertexBufferLayout {
+            array_stride: self.stride(),
+            step_mode: self.step_mode,
+            attributes: &self.wgpu_attributes,
+        }
+    }
+}
+
+/// A type whose values are laid out in a vertex buffer as described by its [`VertexLayout`].
+pub trait VertexType: bytemuck::Pod {
+    fn vertex_layout() -> &'static VertexLayout;
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Vertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub color: [f32; 3],
+    pub uv: [f32; 2],
+    /// The direction of increasing U, with the sign of the bitangent in `w`, see
+    /// [`generate_tangents`](crate::mesh::generate_tangents).
+    pub tangent: [f32; 4],
+}
+
+impl VertexType for Vertex {
+    fn vertex_layout() -> &'static VertexLayout {
+        static LAYOUT: OnceLock<VertexLayout> = OnceLock::new();
+        LAYOUT.get_or_init(|| {
+            use wgpu::VertexFormat::*;
+            let layout = VertexLayout::new(wgpu::VertexStepMode::Vertex, 0)
+                .with("position", Float32x3)
+                .with("normal", Float32x3)
+                .with("color", Float32x3)
+                .with("uv", Float32x2)
+                .with("tangent", Float32x4);
+            debug_assert_eq!(layout.stride(), std::mem::size_of::<Self>() as u64);
+            layout
+        })
+    }
+}
+
+impl Vertex {
+    pub fn layout() -> wgpu::VertexBufferLayout<'static> {
+        Self::vertex_layout().buffer_layout()
+    }
+}
@@ -0,0 +1,49 @@
+//! Renders known scenes headlessly and compares them against the reference images in
+//! `tests/golden`. Set `GOLDEN_UPDATE=1` to regenerate the references after an intended change.
+
+use hello_wgpu::golden::Golden;
+use hello_wgpu::{app, headless, primitives, Renderer, RendererOptions};
+use pollster::FutureExt;
+
+const SIZE: u32 = 256;
+
+fn golden() -> Golden {
+    Golden::new(
+        concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden"),
+        concat!(env!("CARGO_TARGET_TMPDIR"), "/golden"),
+    )
+}
+
+fn render(scene: impl FnOnce(&mut Renderer)) -> image::RgbaImage {
+    let mut renderer = Renderer::new_headless(SIZE, SIZE, &RendererOptions::default())
+        .block_on()
+        .expect("failed to create renderer");
+    scene(&mut renderer);
+    headless::capture_frame(&mut renderer).expect("failed to render frame")
+}
+
+#[test]
+fn grid() {
+    let frame = render(|renderer| {
+        app::setup_scene(renderer, None);
+    });
+    golden().check("grid", &frame).unwrap();
+}
+
+#[test]
+fn sphere() {
+    let mesh = primitives::sphere(1.0, 32, 16);
+    let frame = render(|renderer| {
+        app::setup_scene(renderer, Some(&mesh));
+    });
+    golden().check("sphere", &frame).unwrap();
+}
+
+#[test]
+fn torus() {
+    let mesh = primitives::torus(1.0, 0.35, 48, 24);
+    let frame = render(|renderer| {
+        app::setup_scene(renderer, Some(&mesh));
+    });
+    golden().check("torus", &frame).unwrap();
+}